@@ -1,20 +1,602 @@
+use crate::models::capabilities_dto::Capabilities;
+use crate::models::service_probe_dao::VersionStanding;
+use crate::models::webhook_dao::Channel;
+use crate::utils::chaos::ChaosConfig;
+use crate::utils::compression::Encoding;
+use crate::utils::sandbox::{PinnedBinary, SandboxProfile};
+use crate::utils::window::{ExecutionWindows, weekday_time};
+use ipnet::IpNet;
+use reqwest::header::{HeaderName, HeaderValue};
+use semver::Version;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
-use std::time::Duration;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
 use std::{env, time};
 use tracing::{info, warn};
 
+/// Tenant every job belongs to unless another one is requested
+pub const DEFAULT_TENANT: &str = "default";
+
+/// Queued jobs the sender picks per round unless `SENDER_BATCH_SIZE` is set
+pub const DEFAULT_SENDER_BATCH: usize = 100;
+
+/// Bytes of each log of a run the client keeps unless `RUN_LOG_MAX_SIZE` is set
+pub const DEFAULT_RUN_LOG_MAX_SIZE: u64 = 1024 * 1024;
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Config {
     pub services: HashMap<String, Service>,
+    pub tenants: HashMap<String, Tenant>,
+    pub tiers: HashMap<String, Tier>,
     pub db_path: String,
     pub data_path: String,
     pub max_age: Duration,
+    /// Levels of directories between a data namespace, `data_path` or a tenant
+    /// directory, and the job directories, 1 when the jobs sit right in it
+    pub job_dir_depth: usize,
+    /// How far back an upload with `dedupe=true` looks for an identical job
+    pub dedupe_window: Duration,
+    /// Server only: refuse uploads of a `user_id` not created with
+    /// `POST /users`, instead of registering it on its first upload
+    pub require_registered_users: bool,
+    /// Server only: how long before the cleaner removes the results of a job
+    /// its callback is told so, unset disables the notice
+    pub expiry_notice: Option<Duration>,
     pub port: u16,
+    /// One structured log line per request, on by default
+    pub access_log: bool,
+    /// Bearer token for the admin endpoints, they are disabled when unset
+    pub admin_token: Option<Secret>,
+    /// Networks the admin and user management routes are reachable from,
+    /// unset leaves them reachable from anywhere
+    pub management_allowlist: Option<IpAllowlist>,
+    /// Server only: signed, expiring links to job results, disabled when unset
+    pub download_links: Option<DownloadLinkConfig>,
+    /// Single sign-on giving operators a session for the admin endpoints
+    pub oidc: Option<OidcConfig>,
+    pub http: HttpConfig,
+    pub body_limits: BodyLimits,
+    /// Cross-origin requests allowed from browser frontends, unset allows none
+    pub cors: Option<CorsConfig>,
+    /// Server only: inputs fetched from a URL, refused when unset
+    pub remote_inputs: Option<RemoteInputConfig>,
+    /// Remote tier old result archives are moved to, unset keeps them local. A
+    /// client configured with the same store pushes the results of services
+    /// with `shared_results` there directly.
+    pub object_store: Option<ObjectStoreConfig>,
+    /// Server only: idle time after which a job directory is packed into one archive
+    pub pack_after: Option<Duration>,
+    /// Server only: have the clients delete the payloads no job refers to
+    /// anymore, on by default
+    pub reconcile_payloads: bool,
+    /// Server only: periodic online snapshots of the database, unset disables them
+    pub backup: Option<BackupConfig>,
+    /// Server only: delivery of the callbacks of finished jobs
+    pub webhook: WebhookConfig,
+    /// Server only: programs run at points of the life of a job
+    pub hooks: HooksConfig,
+    /// Server only: how often a failed download of results is retried
+    pub download_retry: DownloadRetryConfig,
+    /// Server only: how often a failed upload of a job to its client is retried
+    pub submit_retry: SubmitRetryConfig,
+    /// Server only: queue watermarks reported to the operators, unset disables them
+    pub alerts: Option<AlertConfig>,
+    /// Server only: operators told about job events and alerts, by name
+    pub subscriptions: HashMap<String, Subscription>,
+    /// Server only: most queued jobs the sender picks per round
+    pub sender_batch: usize,
+    /// Server only: how the clients are checked for availability
+    pub probes: ProbeConfig,
+    /// Server only: client versions the sender dispatches to
+    pub client_versions: ClientVersionPolicy,
+    /// Client only: the binaries payloads may invoke, verified before each run
+    pub pinned_binaries: Vec<PinnedBinary>,
+    /// Client only: digest of the image the client runs in, recorded with results
+    pub image_digest: Option<String>,
+    /// Client only: commands whose output records the tool versions, e.g. `gmx --version`
+    pub environment_probes: Vec<String>,
+    /// Client only: zip the results as soon as a payload completes, not on the first retrieve
+    pub prebuild_archive: bool,
+    /// Client only: run time of the payloads submitted without a timeout,
+    /// unbounded when unset
+    pub run_default_timeout: Option<Duration>,
+    /// Client only: longest a payload may run whatever the submission asks for
+    pub run_max_timeout: Option<Duration>,
+    /// Client only: bytes a payload directory may hold once run, whatever
+    /// the submission asks for
+    pub max_output_size: Option<u64>,
+    /// Client only: bytes of `stdout.log` and `stderr.log` kept once a payload
+    /// ran, the start of a longer log is dropped
+    pub run_log_max_size: u64,
+    /// Client only: checks the submitted files pass in quarantine before the
+    /// payload is prepared
+    pub upload_checks: UploadChecks,
+    /// Client only: reference data fetched once and linked into every
+    /// payload, by name
+    pub datasets: HashMap<String, Dataset>,
+    /// Client only: where the payloads get their scratch directory, e.g. a
+    /// fast local volume, `<DATA_PATH>/.scratch` when unset
+    pub scratch_path: Option<String>,
+    /// Client only: command run at startup, `/health` fails until it succeeded
+    pub warmup: Option<WarmupConfig>,
+    /// Server only: fault injection for tests and staging, unset in production
+    pub chaos: Option<ChaosConfig>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+/// Settings of the HTTP client used for the outbound requests to the services
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct HttpConfig {
+    pub connect_timeout: Duration,
+    /// Maximum time without receiving data, so long transfers are not cut short
+    pub read_timeout: Duration,
+    /// Extra attempts for idempotent requests that failed or got a 5xx
+    pub retries: u32,
+    /// PEM bundle with additional root certificates, e.g. an internal CA
+    pub ca_bundle: Option<PathBuf>,
+    /// Compression of the uploads, the receiving client must support it
+    pub upload_encoding: Option<Encoding>,
+}
+
+/// Largest request bodies accepted, in bytes after decompression
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct BodyLimits {
+    /// Routes receiving job inputs, `/upload` and `/submit`
+    pub upload: usize,
+    /// Every other route
+    pub default: usize,
+    /// Parts of a `/submit` form, files and fields together
+    pub submit_fields: usize,
+}
+
+impl Default for BodyLimits {
+    fn default() -> Self {
+        BodyLimits {
+            upload: 400 * 1024 * 1024,
+            default: 1024 * 1024,
+            submit_fields: 1000,
+        }
+    }
+}
+
+/// Origins, methods and request headers browsers may use across origins.
+/// A `*` entry allows any.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct CorsConfig {
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    pub allowed_headers: Vec<String>,
+    /// How long browsers may cache the answer to a preflight request
+    pub max_age: Duration,
+}
+
+/// Server only: where the files a `POST /jobs` references by URL may be
+/// fetched from, and how large they may be
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RemoteInputConfig {
+    /// Hosts the files may come from, `*.example.org` also matches its subdomains
+    pub hosts: Vec<String>,
+    pub schemes: Vec<String>,
+    /// Largest file, in bytes
+    pub max_size: u64,
+    /// Largest sum of the files of one submission, in bytes
+    pub max_total: u64,
+}
+
+impl RemoteInputConfig {
+    /// `url` points to an allowed scheme and host
+    pub fn allows(&self, url: &reqwest::Url) -> bool {
+        let Some(host) = url.host_str().map(|h| h.to_ascii_lowercase()) else {
+            return false;
+        };
+        self.schemes.iter().any(|s| s == url.scheme())
+            && self
+                .hosts
+                .iter()
+                .any(|allowed| match allowed.strip_prefix("*.") {
+                    Some(domain) => host == domain || host.ends_with(&format!(".{domain}")),
+                    None => host == *allowed,
+                })
+    }
+}
+
+/// An object store reached over plain HTTP, objects live at `<url>/<key>` and
+/// are written with `PUT`, read with `GET` and removed with `DELETE`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ObjectStoreConfig {
+    pub url: String,
+    pub auth: ServiceAuth,
+    /// Result archives older than this are moved off the local disk
+    pub tier_after: Duration,
+    /// Keys of an S3 compatible store, needed to hand out pre-signed URLs
+    pub s3: Option<S3Credentials>,
+}
+
+/// Access key pair used to sign S3 requests (AWS Signature Version 4)
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct S3Credentials {
+    pub access_key: String,
+    pub secret_key: Secret,
+    pub region: String,
+    /// How long a pre-signed URL stays valid
+    pub url_ttl: Duration,
+}
+
+/// Where and how often the database is snapshotted, each snapshot is also
+/// copied to the object store when one is configured
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct BackupConfig {
+    pub dir: PathBuf,
+    pub interval: Duration,
+    /// Number of snapshots kept, older ones are removed
+    pub keep: usize,
+}
+
+/// Client networks allowed on the management routes
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct IpAllowlist {
+    pub allowed: Vec<IpNet>,
+    /// Reverse proxies whose `X-Forwarded-For` is believed, the address they
+    /// forward for is checked instead of theirs
+    pub trusted_proxies: Vec<IpNet>,
+}
+
+/// How links to job results are signed
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct DownloadLinkConfig {
+    pub secret: Secret,
+    /// Longest validity a link can be given
+    pub max_ttl: Duration,
+    /// Public address of the server the links start with, relative links when unset
+    pub base_url: Option<String>,
+}
+
+/// OpenID Connect provider operators log in with, see `controllers::auth`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct OidcConfig {
+    /// Its discovery document lives under `/.well-known/openid-configuration`
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: Secret,
+    /// Public URL of `/auth/callback`, as registered with the provider
+    pub redirect_url: String,
+    /// Addresses, or `@domain` suffixes, allowed in. Empty lets in anyone the
+    /// provider authenticates.
+    pub allowed_emails: Vec<String>,
+    pub session_ttl: Duration,
+}
+
+/// How job callbacks are signed and retried
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct WebhookConfig {
+    /// Key of the HMAC-SHA256 signature, deliveries are unsigned without it
+    pub secret: Option<Secret>,
+    /// Attempts before a delivery is given up
+    pub max_attempts: u32,
+    /// Wait before the first retry, doubled for every further one
+    pub backoff: Duration,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        WebhookConfig {
+            secret: None,
+            max_attempts: 8,
+            backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Site-specific programs run at points of the life of a job, see
+/// `services::hooks`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct HooksConfig {
+    /// Checks a submission before it is queued, a failure rejects it
+    pub pre_submit: Option<PathBuf>,
+    /// Told about a job once its results are in
+    pub post_complete: Option<PathBuf>,
+    /// Checks an aged-out job before its files go, a failure keeps them
+    pub pre_clean: Option<PathBuf>,
+    /// Longest a program may run before it is killed and counted as failed
+    pub timeout: Duration,
+    /// Wasmtime executable the `wasm_validator` of the services run with. Its
+    /// flags, the sandbox of the module, are set by `services::hooks`.
+    pub wasm_runtime: PathBuf,
+    /// Largest linear memory, in bytes, a `wasm_validator` may grow to
+    pub wasm_max_memory: u64,
+    /// Fuel a `wasm_validator` runs out of, about one unit per instruction
+    pub wasm_fuel: u64,
+}
+
+impl Default for HooksConfig {
+    fn default() -> Self {
+        HooksConfig {
+            pre_submit: None,
+            post_complete: None,
+            pre_clean: None,
+            timeout: Duration::from_secs(30),
+            wasm_runtime: PathBuf::from("wasmtime"),
+            wasm_max_memory: 256 * 1024 * 1024,
+            wasm_fuel: 10_000_000_000,
+        }
+    }
+}
+
+/// Queue watermarks posted to the operator webhook when crossed, see
+/// `services::alert`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AlertConfig {
+    /// Reached besides the subscriptions to `alert.*` events
+    pub webhook_url: Option<String>,
+    /// Queued jobs above which the queue counts as backed up
+    pub queue_depth: Option<u32>,
+    /// How long the queue stays above `queue_depth` before it is reported
+    pub queue_depth_for: Duration,
+    /// Age of the oldest queued job that is reported
+    pub oldest_queued: Option<Duration>,
+}
+
+/// An operator target told about the events it subscribed to, see
+/// `services::notify`
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct Subscription {
+    pub name: String,
+    pub channel: Channel,
+    /// Where the channel sends to, e.g. the URL of a Slack incoming webhook
+    pub target: String,
+    /// Events sent, exact or ending in `*` to match a prefix, all when empty
+    pub events: Vec<String>,
+}
+
+impl Subscription {
+    pub fn wants(&self, event: &str) -> bool {
+        self.events.is_empty()
+            || self
+                .events
+                .iter()
+                .any(|pattern| match pattern.strip_suffix('*') {
+                    Some(prefix) => event.starts_with(prefix),
+                    None => event == pattern,
+                })
+    }
+}
+
+/// Reference data shared by the payloads of a client, see `services::datasets`
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct Dataset {
+    pub name: String,
+    /// Fetched with a plain `GET`, a `.zip` is unpacked
+    pub url: String,
+    /// Hex SHA-256 the download must have, unchecked when unset
+    pub sha256: Option<String>,
+}
+
+/// What the client does before it reports itself healthy, e.g. pulling an image
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct WarmupConfig {
+    /// Run with `sh -c`, must exit with `0`
+    pub command: String,
+    /// Longest the command may take before the warm-up counts as failed
+    pub timeout: Duration,
+}
+
+/// How often the prober asks each service's client for its `/health`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ProbeConfig {
+    pub interval: Duration,
+    /// Wait for an answer before the probe counts as failed
+    pub timeout: Duration,
+    /// Failed probes in a row after which the sender skips the service
+    pub down_after: u32,
+}
+
+impl Default for ProbeConfig {
+    fn default() -> Self {
+        ProbeConfig {
+            interval: Duration::from_secs(30),
+            timeout: Duration::from_secs(5),
+            down_after: 3,
+        }
+    }
+}
+
+/// Client versions the sender dispatches to, as reported to the prober. Every
+/// version gets jobs by default.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct ClientVersionPolicy {
+    /// Clients below get no jobs, nor do the ones reporting no version
+    pub min: Option<Version>,
+    /// Still sent jobs, flagged in the stats
+    pub deprecated: Vec<Version>,
+    /// Sent no jobs, whatever `min`
+    pub blocked: Vec<Version>,
+}
+
+impl ClientVersionPolicy {
+    /// How a client reporting `version` is treated
+    pub fn standing(&self, version: Option<&str>) -> VersionStanding {
+        let Some(version) = version.and_then(parse_version) else {
+            // A client older than the version report is below any minimum
+            return match self.min {
+                Some(_) => VersionStanding::Outdated,
+                None => VersionStanding::Unknown,
+            };
+        };
+        if self.blocked.contains(&version) {
+            VersionStanding::Blocked
+        } else if self.min.as_ref().is_some_and(|min| &version < min) {
+            VersionStanding::Outdated
+        } else if self.deprecated.contains(&version) {
+            VersionStanding::Deprecated
+        } else {
+            VersionStanding::Current
+        }
+    }
+}
+
+/// A version as `2.2.3` or `v2.2.3`
+fn parse_version(v: &str) -> Option<Version> {
+    Version::parse(v.trim().trim_start_matches('v')).ok()
+}
+
+/// Comma separated versions, all of them valid
+fn parse_versions(v: &str) -> Result<Vec<Version>, String> {
+    v.split(',')
+        .filter(|e| !e.trim().is_empty())
+        .map(|e| parse_version(e).ok_or_else(|| format!("Invalid client version {}", e.trim())))
+        .collect()
+}
+
+/// What the submitted files are checked for while in quarantine, nothing by
+/// default
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct UploadChecks {
+    /// Largest file accepted, in bytes
+    pub max_file_size: Option<u64>,
+    /// Lowercase extensions accepted, without the dot, any when empty
+    pub allowed_extensions: Vec<String>,
+    /// Text refused anywhere in a script, a `.sh` or `.py` file or one
+    /// starting with `#!`
+    pub script_denylist: Vec<String>,
+    /// Scanner run with the quarantine directory as last argument, it exits
+    /// with 0 when the files are clean and 1 when it found something
+    pub av_command: Option<String>,
+}
+
+/// How the getter retries the downloads of results that failed
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct DownloadRetryConfig {
+    /// Failed attempts before the job is given up
+    pub max_attempts: u32,
+    /// Wait before the first retry, doubled for every further one
+    pub backoff: Duration,
+}
+
+impl Default for DownloadRetryConfig {
+    fn default() -> Self {
+        DownloadRetryConfig {
+            max_attempts: 5,
+            backoff: Duration::from_secs(10),
+        }
+    }
+}
+
+/// How the sender retries the uploads of jobs that failed on the way to
+/// their client
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SubmitRetryConfig {
+    /// Failed uploads before the job is given up
+    pub max_attempts: u32,
+    /// Wait before the first retry, doubled for every further one, of which
+    /// a random part is left out so the jobs of a client that was down do
+    /// not all come back at once
+    pub backoff: Duration,
+}
+
+impl Default for SubmitRetryConfig {
+    fn default() -> Self {
+        SubmitRetryConfig {
+            max_attempts: 5,
+            backoff: Duration::from_secs(10),
+        }
+    }
+}
+
+impl ObjectStoreConfig {
+    pub fn object_url(&self, key: &str) -> String {
+        format!("{}/{key}", self.url.trim_end_matches('/'))
+    }
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig {
+            connect_timeout: Duration::from_secs(10),
+            read_timeout: Duration::from_secs(60),
+            retries: 2,
+            ca_bundle: None,
+            upload_encoding: None,
+        }
+    }
+}
+
+/// A credential read from the environment, never printed in logs
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: &str) -> Secret {
+        Secret(value.to_string())
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Secret(***)")
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            services: HashMap::new(),
+            tenants: HashMap::new(),
+            tiers: HashMap::new(),
+            db_path: String::new(),
+            data_path: String::new(),
+            max_age: Duration::from_secs(864000),
+            job_dir_depth: 1,
+            dedupe_window: Duration::from_secs(600),
+            require_registered_users: false,
+            expiry_notice: Some(Duration::from_secs(86400)),
+            port: 5000,
+            access_log: true,
+            admin_token: None,
+            management_allowlist: None,
+            download_links: None,
+            oidc: None,
+            http: HttpConfig::default(),
+            body_limits: BodyLimits::default(),
+            cors: None,
+            remote_inputs: None,
+            object_store: None,
+            pack_after: None,
+            reconcile_payloads: true,
+            backup: None,
+            webhook: WebhookConfig::default(),
+            hooks: HooksConfig::default(),
+            download_retry: DownloadRetryConfig::default(),
+            submit_retry: SubmitRetryConfig::default(),
+            alerts: None,
+            subscriptions: HashMap::new(),
+            sender_batch: DEFAULT_SENDER_BATCH,
+            probes: ProbeConfig::default(),
+            client_versions: ClientVersionPolicy::default(),
+            pinned_binaries: Vec::new(),
+            image_digest: None,
+            environment_probes: Vec::new(),
+            prebuild_archive: false,
+            run_default_timeout: None,
+            run_max_timeout: None,
+            max_output_size: None,
+            run_log_max_size: DEFAULT_RUN_LOG_MAX_SIZE,
+            upload_checks: UploadChecks::default(),
+            datasets: HashMap::new(),
+            scratch_path: None,
+            warmup: None,
+            chaos: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct Service {
     pub name: String,
     pub upload_url: String,
@@ -22,11 +604,211 @@ pub struct Service {
     pub terminate_url: String,
     pub runs_per_user: u16,
     pub max_runs: u16,
+    pub auth: ServiceAuth,
+    /// Unpack `output.zip` into the job directory once downloaded
+    pub extract_results: bool,
+    /// The client puts `output.zip` in the object store shared with the server
+    /// instead of sending it, both sides need the same `OBJECT_STORE_URL`
+    pub shared_results: bool,
+    /// Isolation the client applies when running the payloads
+    pub sandbox: SandboxProfile,
+    /// Wait for a connection to the client, `HTTP_CONNECT_TIMEOUT` when unset
+    pub connect_timeout: Option<Duration>,
+    /// Longest a whole upload or download may take before it is cancelled and
+    /// retried on a later round, unbounded when unset
+    pub transfer_timeout: Option<Duration>,
+    /// Hold the submissions whose `run.sh` fails the dangerous pattern check
+    /// for review, instead of leaving the client to reject them
+    pub hold_suspicious: bool,
+    /// Hold for review the submissions of a user already having this many
+    /// jobs waiting on the service
+    pub hold_queued_over: Option<u32>,
+    /// Longest a job may stay submitted without progress before the watchdog
+    /// reports it, never when unset
+    pub stale_after: Option<Duration>,
+    /// What the watchdog does with the jobs past `stale_after`
+    pub on_stale: StaleAction,
+    /// What the client reported under `GET /capabilities` at startup, `None`
+    /// when it could not tell
+    pub capabilities: Option<Capabilities>,
+    /// Storage root of the jobs of this service, `DATA_PATH` when unset
+    pub data_path: Option<String>,
+    /// Run time of the jobs submitted without a timeout, unbounded when unset
+    pub default_timeout: Option<Duration>,
+    /// Longest run time a submission may ask for, larger ones are refused
+    pub max_timeout: Option<Duration>,
+    /// Bytes a payload directory may hold once run, the client fails the
+    /// payloads over it instead of archiving them. Unbounded when unset.
+    pub max_output_size: Option<u64>,
+    /// What the cleaner does with the files of the jobs past their `max_age`
+    pub cleanup: CleanupAction,
+    /// `run.sh` rendered from the parameters of the jobs submitted to
+    /// `POST /jobs`, which is refused for the service when unset
+    pub template: Option<String>,
+    /// When the sender dispatches the jobs, any time when unset
+    pub windows: Option<ExecutionWindows>,
+    /// `User-Agent` of the requests to the client, none when unset
+    pub user_agent: Option<String>,
+    /// Extra headers sent with every request to the client, e.g. routing
+    /// hints for a gateway in front of it, by lowercase name
+    pub headers: BTreeMap<String, String>,
+    /// WebAssembly module checking, and possibly rewriting, the files of each
+    /// submission, none when unset
+    pub wasm_validator: Option<PathBuf>,
+}
+
+impl Service {
+    /// Why the jobs of this service wait in the queue at `now`, `None` when
+    /// they may be sent
+    pub fn closed_reason(&self, now: SystemTime) -> Option<String> {
+        let opens = self.windows.as_ref()?.next_open(now)?;
+        Some(format!(
+            "Outside the execution windows of service {}, the next one opens {}",
+            self.name,
+            weekday_time(opens)
+        ))
+    }
+
+    /// How long a job asking for `requested` may run on this service
+    pub fn effective_timeout(&self, requested: Option<Duration>) -> Option<Duration> {
+        effective_timeout(requested, self.default_timeout, self.max_timeout)
+    }
+}
+
+/// `requested`, or `default` when unset, brought down to `max`
+pub fn effective_timeout(
+    requested: Option<Duration>,
+    default: Option<Duration>,
+    max: Option<Duration>,
+) -> Option<Duration> {
+    match (requested.or(default), max) {
+        (Some(timeout), Some(max)) => Some(timeout.min(max)),
+        (timeout, max) => timeout.or(max),
+    }
+}
+
+/// What the watchdog does with a job stuck in `submitted`
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+pub enum StaleAction {
+    /// Only report it, once
+    #[default]
+    Alert,
+    /// Stop it on the client and queue it again
+    Requeue,
+    /// Stop it on the client and fail it
+    Cancel,
+}
+
+impl std::str::FromStr for StaleAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "alert" => Ok(StaleAction::Alert),
+            "requeue" => Ok(StaleAction::Requeue),
+            "cancel" => Ok(StaleAction::Cancel),
+            other => Err(format!("Unknown stale action: {other}")),
+        }
+    }
+}
+
+/// What the cleaner does with the files of a job it retires
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+pub enum CleanupAction {
+    /// Remove the job directory and any archive in the object store
+    #[default]
+    Delete,
+    /// Keep the result archive in the object store, remove the directory
+    Archive,
+    /// Pack the job directory into a single archive and leave it in place
+    Compress,
+}
+
+impl std::str::FromStr for CleanupAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "delete" => Ok(CleanupAction::Delete),
+            "archive" => Ok(CleanupAction::Archive),
+            "compress" => Ok(CleanupAction::Compress),
+            other => Err(format!("Unknown cleanup action: {other}")),
+        }
+    }
+}
+
+/// Credentials attached to every outbound request made to a service.
+/// All of them are optional, the custom header can be combined with either
+/// the bearer token or the basic auth credentials.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct ServiceAuth {
+    pub bearer_token: Option<Secret>,
+    /// Custom header, e.g. `X-API-Key`, sent with `header_value`
+    pub header_name: Option<String>,
+    pub header_value: Option<Secret>,
+    pub username: Option<String>,
+    pub password: Option<Secret>,
+}
+
+impl ServiceAuth {
+    /// Bearer and basic auth both go in the `Authorization` header, so only
+    /// one of them can be set under the `prefix` variables
+    fn check(&self, prefix: &str) -> Result<(), String> {
+        if self.bearer_token.is_some() && self.username.is_some() {
+            return Err(format!(
+                "{prefix}_AUTH_BEARER and {prefix}_AUTH_USERNAME cannot be set together"
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// A group of users sharing a data namespace, retention and quotas.
+/// Unset fields fall back to the global/service settings.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Tenant {
+    pub name: String,
+    /// Services this tenant may submit to, empty means all of them
+    pub services: Vec<String>,
+    pub max_age: Option<Duration>,
+    pub runs_per_user: Option<u16>,
+    /// Maximum active jobs for the whole tenant, across services
+    pub max_runs: Option<u16>,
+    /// Storage root of the jobs of this tenant, `DATA_PATH` when unset
+    pub data_path: Option<String>,
+}
+
+/// Scheduling priority of the users assigned to it, unknown tiers get
+/// a weight of 1 and no cap
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Tier {
+    pub name: String,
+    /// Jobs a user of this tier is dispatched per round-robin turn
+    pub weight: u16,
+    /// Maximum active jobs for all users of this tier, across services
+    pub max_runs: Option<u16>,
+    /// Services the users of this tier may submit to, empty means all of them
+    pub services: Vec<String>,
+}
+
+impl Default for Tier {
+    fn default() -> Self {
+        Tier {
+            name: String::new(),
+            weight: 1,
+            max_runs: None,
+            services: Vec::new(),
+        }
+    }
 }
 
 impl Config {
     pub fn new() -> Result<Config, Box<dyn Error>> {
         let mut services = HashMap::new();
+        let mut tenants: HashMap<String, Tenant> = HashMap::new();
+        let mut tiers: HashMap<String, Tier> = HashMap::new();
+        let mut subscriptions: HashMap<String, Subscription> = HashMap::new();
+        let mut datasets: HashMap<String, Dataset> = HashMap::new();
 
         // Iterate over all environment variables
         for (key, value) in env::vars() {
@@ -36,6 +818,30 @@ impl Config {
             // - SERVICE_<NAME>_RUNS_PER_USER
             // - SERVICE_<NAME>_TERMINATE_URL
             // - SERVICE_<NAME>_MAX_RUNS
+            // - SERVICE_<NAME>_AUTH_BEARER
+            // - SERVICE_<NAME>_AUTH_HEADER_NAME
+            // - SERVICE_<NAME>_AUTH_HEADER_VALUE
+            // - SERVICE_<NAME>_AUTH_USERNAME
+            // - SERVICE_<NAME>_AUTH_PASSWORD
+            // - SERVICE_<NAME>_EXTRACT_RESULTS
+            // - SERVICE_<NAME>_SHARED_RESULTS
+            // - SERVICE_<NAME>_SANDBOX
+            // - SERVICE_<NAME>_CONNECT_TIMEOUT
+            // - SERVICE_<NAME>_TRANSFER_TIMEOUT
+            // - SERVICE_<NAME>_HOLD_SUSPICIOUS
+            // - SERVICE_<NAME>_HOLD_QUEUED_OVER
+            // - SERVICE_<NAME>_STALE_AFTER
+            // - SERVICE_<NAME>_ON_STALE
+            // - SERVICE_<NAME>_DATA_PATH
+            // - SERVICE_<NAME>_DEFAULT_TIMEOUT
+            // - SERVICE_<NAME>_MAX_TIMEOUT
+            // - SERVICE_<NAME>_MAX_OUTPUT_SIZE
+            // - SERVICE_<NAME>_CLEANUP
+            // - SERVICE_<NAME>_TEMPLATE
+            // - SERVICE_<NAME>_WINDOWS
+            // - SERVICE_<NAME>_USER_AGENT
+            // - SERVICE_<NAME>_HEADER_<HEADER> (`_` in <HEADER> becomes `-`)
+            // - SERVICE_<NAME>_WASM_VALIDATOR
             if key.starts_with("SERVICE_") {
                 let parts: Vec<&str> = key.split('_').collect();
                 if parts.len() >= 3 {
@@ -52,6 +858,7 @@ impl Config {
                             terminate_url: String::new(),
                             runs_per_user: 5,  // by default consider 5 runs per user per service
                             max_runs: 10,      // by default allow 10 concurrent payloads per service
+                            ..Default::default()
                         });
 
                     // Assign the corresponding vars to the config
@@ -61,11 +868,186 @@ impl Config {
                         "RUNS_PER_USER" => service.runs_per_user = value.parse::<u16>().unwrap(),
                         "MAX_RUNS" => service.max_runs = value.parse::<u16>().unwrap(),
                         "TERMINATE_URL" => service.terminate_url = value,
+                        "AUTH_BEARER" => service.auth.bearer_token = Some(Secret::new(&value)),
+                        "AUTH_HEADER_NAME" => service.auth.header_name = Some(value),
+                        "AUTH_HEADER_VALUE" => {
+                            service.auth.header_value = Some(Secret::new(&value))
+                        }
+                        "AUTH_USERNAME" => service.auth.username = Some(value),
+                        "AUTH_PASSWORD" => service.auth.password = Some(Secret::new(&value)),
+                        "EXTRACT_RESULTS" => {
+                            service.extract_results = value.parse::<bool>().unwrap()
+                        }
+                        "SHARED_RESULTS" => service.shared_results = value.parse::<bool>()?,
+                        "SANDBOX" => service.sandbox = value.parse::<SandboxProfile>()?,
+                        "CONNECT_TIMEOUT" => {
+                            service.connect_timeout = Some(Duration::from_secs(value.parse()?))
+                        }
+                        "TRANSFER_TIMEOUT" => {
+                            service.transfer_timeout = Some(Duration::from_secs(value.parse()?))
+                        }
+                        "HOLD_SUSPICIOUS" => service.hold_suspicious = value.parse::<bool>()?,
+                        "HOLD_QUEUED_OVER" => service.hold_queued_over = Some(value.parse()?),
+                        "STALE_AFTER" => {
+                            service.stale_after = Some(Duration::from_secs(value.parse()?))
+                        }
+                        "ON_STALE" => service.on_stale = value.parse::<StaleAction>()?,
+                        "DATA_PATH" => service.data_path = Some(value),
+                        "DEFAULT_TIMEOUT" => {
+                            service.default_timeout = Some(Duration::from_secs(value.parse()?))
+                        }
+                        "MAX_TIMEOUT" => {
+                            service.max_timeout = Some(Duration::from_secs(value.parse()?))
+                        }
+                        "MAX_OUTPUT_SIZE" => service.max_output_size = Some(value.parse()?),
+                        "CLEANUP" => service.cleanup = value.parse::<CleanupAction>()?,
+                        "TEMPLATE" => service.template = Some(value),
+                        "WINDOWS" => service.windows = Some(value.parse::<ExecutionWindows>()?),
+                        "USER_AGENT" => {
+                            HeaderValue::from_str(&value)
+                                .map_err(|_| format!("Invalid {key}: {value}"))?;
+                            service.user_agent = Some(value)
+                        }
+                        v if v.starts_with("HEADER_") => {
+                            let name = v["HEADER_".len()..].to_ascii_lowercase().replace('_', "-");
+                            if HeaderName::from_bytes(name.as_bytes()).is_err()
+                                || HeaderValue::from_str(&value).is_err()
+                            {
+                                return Err(format!("Invalid {key}: {value}").into());
+                            }
+                            service.headers.insert(name, value);
+                        }
+                        "WASM_VALIDATOR" => service.wasm_validator = Some(PathBuf::from(value)),
+                        _ => continue,
+                    };
+                }
+            } else if key.starts_with("TENANT_") {
+                // Look for tenant environment variables with the pattern:
+                // - TENANT_<NAME>_SERVICES (comma separated)
+                // - TENANT_<NAME>_MAX_AGE
+                // - TENANT_<NAME>_RUNS_PER_USER
+                // - TENANT_<NAME>_MAX_RUNS
+                // - TENANT_<NAME>_DATA_PATH
+                let parts: Vec<&str> = key.split('_').collect();
+                if parts.len() >= 3 {
+                    let tenant_name = parts[1].to_ascii_lowercase();
+                    let tenant_vars = parts[2..].join("_");
+
+                    let tenant = tenants.entry(tenant_name.clone()).or_insert(Tenant {
+                        name: tenant_name,
+                        ..Default::default()
+                    });
+
+                    match tenant_vars.as_str() {
+                        "SERVICES" => {
+                            tenant.services = value
+                                .split(',')
+                                .map(|s| s.trim().to_ascii_lowercase())
+                                .filter(|s| !s.is_empty())
+                                .collect()
+                        }
+                        "MAX_AGE" => {
+                            tenant.max_age = Some(Duration::from_secs(value.parse().unwrap()))
+                        }
+                        "RUNS_PER_USER" => {
+                            tenant.runs_per_user = Some(value.parse::<u16>().unwrap())
+                        }
+                        "MAX_RUNS" => tenant.max_runs = Some(value.parse::<u16>().unwrap()),
+                        "DATA_PATH" => tenant.data_path = Some(value),
+                        _ => continue,
+                    };
+                }
+            } else if key.starts_with("TIER_") {
+                // Look for tier environment variables with the pattern:
+                // - TIER_<NAME>_WEIGHT
+                // - TIER_<NAME>_MAX_RUNS
+                // - TIER_<NAME>_SERVICES (comma separated)
+                let parts: Vec<&str> = key.split('_').collect();
+                if parts.len() >= 3 {
+                    let tier_name = parts[1].to_ascii_lowercase();
+                    let tier_vars = parts[2..].join("_");
+
+                    let tier = tiers.entry(tier_name.clone()).or_insert(Tier {
+                        name: tier_name,
+                        ..Default::default()
+                    });
+
+                    match tier_vars.as_str() {
+                        "WEIGHT" => tier.weight = value.parse::<u16>().unwrap().max(1),
+                        "MAX_RUNS" => tier.max_runs = Some(value.parse::<u16>().unwrap()),
+                        "SERVICES" => {
+                            tier.services = value
+                                .split(',')
+                                .map(|s| s.trim().to_ascii_lowercase())
+                                .filter(|s| !s.is_empty())
+                                .collect()
+                        }
+                        _ => continue,
+                    };
+                }
+            } else if key.starts_with("NOTIFY_") {
+                // Look for subscription environment variables with the pattern:
+                // - NOTIFY_<NAME>_CHANNEL
+                // - NOTIFY_<NAME>_TARGET
+                // - NOTIFY_<NAME>_EVENTS (comma separated)
+                let parts: Vec<&str> = key.split('_').collect();
+                if parts.len() >= 3 {
+                    let name = parts[1].to_ascii_lowercase();
+                    let vars = parts[2..].join("_");
+
+                    let subscription = subscriptions.entry(name.clone()).or_insert(Subscription {
+                        name,
+                        ..Default::default()
+                    });
+
+                    match vars.as_str() {
+                        "CHANNEL" => subscription.channel = value.parse::<Channel>()?,
+                        "TARGET" => subscription.target = value,
+                        "EVENTS" => {
+                            subscription.events = value
+                                .split(',')
+                                .map(|e| e.trim().to_string())
+                                .filter(|e| !e.is_empty())
+                                .collect()
+                        }
+                        _ => continue,
+                    };
+                }
+            } else if key.starts_with("DATASET_") {
+                // Look for dataset environment variables with the pattern:
+                // - DATASET_<NAME>_URL
+                // - DATASET_<NAME>_SHA256
+                let parts: Vec<&str> = key.split('_').collect();
+                if parts.len() >= 3 {
+                    let name = parts[1].to_ascii_lowercase();
+                    let vars = parts[2..].join("_");
+
+                    let dataset = datasets.entry(name.clone()).or_insert(Dataset {
+                        name,
+                        ..Default::default()
+                    });
+
+                    match vars.as_str() {
+                        "URL" => dataset.url = value,
+                        "SHA256" => dataset.sha256 = Some(value.trim().to_ascii_lowercase()),
                         _ => continue,
                     };
                 }
             }
         }
+        if let Some(s) = subscriptions.values().find(|s| s.target.is_empty()) {
+            return Err(
+                format!("NOTIFY_{}_TARGET is required", s.name.to_ascii_uppercase()).into(),
+            );
+        }
+        if let Some(d) = datasets.values().find(|d| d.url.is_empty()) {
+            return Err(format!("DATASET_{}_URL is required", d.name.to_ascii_uppercase()).into());
+        }
+        for (name, service) in &services {
+            service
+                .auth
+                .check(&format!("SERVICE_{}", name.to_ascii_uppercase()))?;
+        }
 
         let wd = env::current_dir().unwrap().display().to_string();
 
@@ -99,6 +1081,27 @@ impl Config {
             }
         };
 
+        let mut job_dir_depth = 1;
+        if let Ok(v) = env::var("JOB_DIR_DEPTH") {
+            job_dir_depth = v.parse::<usize>()?.max(1);
+        }
+
+        let mut dedupe_window = time::Duration::from_secs(600);
+        if let Ok(v) = env::var("DEDUPE_WINDOW") {
+            dedupe_window = time::Duration::from_secs(v.parse()?);
+        }
+
+        let require_registered_users = match env::var("REQUIRE_REGISTERED_USERS") {
+            Ok(v) => v.parse::<bool>()?,
+            Err(_) => false,
+        };
+
+        // A day by default, 0 turns it off
+        let mut expiry_notice = Some(time::Duration::from_secs(86400));
+        if let Ok(v) = env::var("EXPIRY_NOTICE") {
+            expiry_notice = Some(time::Duration::from_secs(v.parse()?)).filter(|d| !d.is_zero());
+        }
+
         let port = match env::var("PORT") {
             Ok(v) => v.parse::<u16>().unwrap(),
             Err(_) => {
@@ -108,64 +1111,701 @@ impl Config {
             }
         };
 
-        let config = Config {
-            services,
-            db_path,
-            data_path,
-            max_age,
-            port,
+        let access_log = match env::var("ACCESS_LOG") {
+            Ok(v) => v.parse::<bool>()?,
+            Err(_) => true,
         };
 
-        info!("{:?}", config);
-        Ok(config)
-    }
-
-    pub fn get_download_url(&self, service_name: &str) -> Option<&str> {
-        self.services
-            .get(service_name)
-            .map(|service| service.download_url.as_str())
-    }
+        let admin_token = match env::var("ADMIN_TOKEN") {
+            Ok(v) if !v.is_empty() => Some(Secret::new(&v)),
+            _ => {
+                warn!("ADMIN_TOKEN not defined, admin endpoints are disabled");
+                None
+            }
+        };
 
-    pub fn get_upload_url(&self, service_name: &str) -> Option<&str> {
-        self.services
-            .get(service_name)
-            .map(|service| service.upload_url.as_str())
-    }
+        // Comma separated networks, a bare address stands for itself
+        let networks = |key: &str| -> Result<Vec<IpNet>, Box<dyn Error>> {
+            let mut networks = Vec::new();
+            for entry in env::var(key).unwrap_or_default().split(',') {
+                let entry = entry.trim();
+                if entry.is_empty() {
+                    continue;
+                }
+                match entry.parse::<IpNet>() {
+                    Ok(net) => networks.push(net),
+                    Err(_) => networks.push(IpNet::from(
+                        entry
+                            .parse::<IpAddr>()
+                            .map_err(|_| format!("{key}: invalid network {entry}"))?,
+                    )),
+                }
+            }
+            Ok(networks)
+        };
+        let allowed = networks("MANAGEMENT_ALLOWED_IPS")?;
+        let management_allowlist = if allowed.is_empty() {
+            None
+        } else {
+            Some(IpAllowlist {
+                allowed,
+                trusted_proxies: networks("TRUSTED_PROXIES")?,
+            })
+        };
 
-    pub fn get_terminate_url(&self, service_name: &str) -> Option<&str> {
-        self.services
-            .get(service_name)
-            .map(|service| service.terminate_url.as_str())
-    }
-}
+        let download_links = match env::var("DOWNLOAD_LINK_SECRET") {
+            Ok(v) if !v.is_empty() => {
+                let mut max_ttl = time::Duration::from_secs(7 * 86400);
+                if let Ok(v) = env::var("DOWNLOAD_LINK_MAX_TTL") {
+                    max_ttl = time::Duration::from_secs(v.parse()?);
+                }
+                Some(DownloadLinkConfig {
+                    secret: Secret::new(&v),
+                    max_ttl,
+                    base_url: env::var("DOWNLOAD_LINK_BASE_URL")
+                        .ok()
+                        .map(|u| u.trim_end_matches('/').to_string())
+                        .filter(|u| !u.is_empty()),
+                })
+            }
+            _ => None,
+        };
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::collections::HashMap;
+        let oidc = match env::var("OIDC_ISSUER") {
+            Ok(issuer) if !issuer.is_empty() => {
+                let required = |key: &str| match env::var(key) {
+                    Ok(v) if !v.is_empty() => Ok(v),
+                    _ => Err(format!("{key} is required with OIDC_ISSUER")),
+                };
+                let mut session_ttl = time::Duration::from_secs(8 * 3600);
+                if let Ok(v) = env::var("OIDC_SESSION_TTL") {
+                    session_ttl = time::Duration::from_secs(v.parse()?);
+                }
+                let allowed_emails: Vec<String> = env::var("OIDC_ALLOWED_EMAILS")
+                    .map(|v| {
+                        v.split(',')
+                            .map(|e| e.trim().to_ascii_lowercase())
+                            .filter(|e| !e.is_empty())
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                // Otherwise anyone the provider authenticates would be an operator
+                if allowed_emails.is_empty() {
+                    return Err("OIDC_ALLOWED_EMAILS is required with OIDC_ISSUER".into());
+                }
+                Some(OidcConfig {
+                    issuer,
+                    client_id: required("OIDC_CLIENT_ID")?,
+                    client_secret: Secret::new(&required("OIDC_CLIENT_SECRET")?),
+                    redirect_url: required("OIDC_REDIRECT_URL")?,
+                    allowed_emails,
+                    session_ttl,
+                })
+            }
+            _ => None,
+        };
 
-    // Helper to create a test config manually
-    fn create_test_config() -> Config {
-        let mut services = HashMap::new();
+        // Outbound HTTP client, HTTP(S)_PROXY and NO_PROXY are honored as well
+        let mut http = HttpConfig::default();
+        if let Ok(v) = env::var("HTTP_CONNECT_TIMEOUT") {
+            http.connect_timeout = time::Duration::from_secs(v.parse()?);
+        }
+        if let Ok(v) = env::var("HTTP_READ_TIMEOUT") {
+            http.read_timeout = time::Duration::from_secs(v.parse()?);
+        }
+        if let Ok(v) = env::var("HTTP_RETRIES") {
+            http.retries = v.parse()?;
+        }
+        if let Ok(v) = env::var("HTTP_CA_BUNDLE") {
+            http.ca_bundle = Some(PathBuf::from(v));
+        }
+        match env::var("HTTP_UPLOAD_ENCODING").as_deref() {
+            Err(_) | Ok("") | Ok("none") => {}
+            Ok(v) => http.upload_encoding = Some(v.parse::<Encoding>()?),
+        }
 
-        services.insert(
-            "test".to_string(),
-            Service {
-                name: "test".to_string(),
-                upload_url: "http://test.com/upload".to_string(),
-                download_url: "http://test.com/download".to_string(),
-                terminate_url: "http://example.com/terminate".to_string(),
-                runs_per_user: 10,
-                max_runs: 1,
-            },
-        );
+        let mut body_limits = BodyLimits::default();
+        if let Ok(v) = env::var("MAX_UPLOAD_SIZE") {
+            body_limits.upload = v.parse()?;
+        }
+        if let Ok(v) = env::var("MAX_BODY_SIZE") {
+            body_limits.default = v.parse()?;
+        }
+        if let Ok(v) = env::var("MAX_SUBMIT_FIELDS") {
+            body_limits.submit_fields = v.parse()?;
+        }
 
-        Config {
+        let cors = match env::var("CORS_ALLOWED_ORIGINS") {
+            Ok(v) if !v.trim().is_empty() => {
+                let list = |key: &str, default: &str| -> Vec<String> {
+                    env::var(key)
+                        .unwrap_or_else(|_| default.to_string())
+                        .split(',')
+                        .map(|e| e.trim().to_string())
+                        .filter(|e| !e.is_empty())
+                        .collect()
+                };
+                let allowed_origins = list("CORS_ALLOWED_ORIGINS", "");
+                let allowed_methods = list("CORS_ALLOWED_METHODS", "GET,POST,PATCH,PUT,DELETE");
+                let allowed_headers = list("CORS_ALLOWED_HEADERS", "authorization,content-type");
+                // Reject what the middleware could not use rather than ignoring it
+                for origin in allowed_origins.iter().filter(|o| *o != "*") {
+                    http::HeaderValue::from_str(origin)?;
+                }
+                for method in allowed_methods.iter().filter(|m| *m != "*") {
+                    method.parse::<http::Method>()?;
+                }
+                for header in allowed_headers.iter().filter(|h| *h != "*") {
+                    header.parse::<http::HeaderName>()?;
+                }
+                let mut max_age = time::Duration::from_secs(3600);
+                if let Ok(v) = env::var("CORS_MAX_AGE") {
+                    max_age = time::Duration::from_secs(v.parse()?);
+                }
+                Some(CorsConfig {
+                    allowed_origins,
+                    allowed_methods,
+                    allowed_headers,
+                    max_age,
+                })
+            }
+            _ => None,
+        };
+
+        let remote_inputs = match env::var("REMOTE_INPUT_HOSTS") {
+            Ok(v) if !v.trim().is_empty() => {
+                let list = |v: &str| -> Vec<String> {
+                    v.split(',')
+                        .map(|e| e.trim().to_ascii_lowercase())
+                        .filter(|e| !e.is_empty())
+                        .collect()
+                };
+                let mut config = RemoteInputConfig {
+                    hosts: list(&v),
+                    schemes: list(&env::var("REMOTE_INPUT_SCHEMES").unwrap_or("https".into())),
+                    max_size: 1024 * 1024 * 1024,
+                    max_total: 4 * 1024 * 1024 * 1024,
+                };
+                if let Ok(v) = env::var("REMOTE_INPUT_MAX_SIZE") {
+                    config.max_size = v.parse()?;
+                }
+                if let Ok(v) = env::var("REMOTE_INPUT_MAX_TOTAL") {
+                    config.max_total = v.parse()?;
+                }
+                if let Some(scheme) = config
+                    .schemes
+                    .iter()
+                    .find(|s| !matches!(s.as_str(), "http" | "https"))
+                {
+                    return Err(format!("Unsupported REMOTE_INPUT_SCHEMES entry: {scheme}").into());
+                }
+                Some(config)
+            }
+            _ => None,
+        };
+
+        let object_store = match env::var("OBJECT_STORE_URL") {
+            Ok(url) if !url.is_empty() => {
+                let secret = |key: &str| env::var(key).ok().map(|v| Secret::new(&v));
+                let mut tier_after = time::Duration::from_secs(7 * 86400);
+                if let Ok(v) = env::var("STORAGE_TIER_AFTER_DAYS") {
+                    tier_after = time::Duration::from_secs(v.parse::<u64>()? * 86400);
+                }
+                let s3 = match (
+                    env::var("OBJECT_STORE_S3_ACCESS_KEY"),
+                    secret("OBJECT_STORE_S3_SECRET_KEY"),
+                ) {
+                    (Ok(access_key), Some(secret_key)) if !access_key.is_empty() => {
+                        let mut url_ttl = time::Duration::from_secs(3600);
+                        if let Ok(v) = env::var("OBJECT_STORE_S3_URL_TTL") {
+                            url_ttl = time::Duration::from_secs(v.parse()?);
+                        }
+                        Some(S3Credentials {
+                            access_key,
+                            secret_key,
+                            region: env::var("OBJECT_STORE_S3_REGION")
+                                .unwrap_or_else(|_| "us-east-1".to_string()),
+                            url_ttl,
+                        })
+                    }
+                    _ => None,
+                };
+                let auth = ServiceAuth {
+                    bearer_token: secret("OBJECT_STORE_AUTH_BEARER"),
+                    header_name: env::var("OBJECT_STORE_AUTH_HEADER_NAME").ok(),
+                    header_value: secret("OBJECT_STORE_AUTH_HEADER_VALUE"),
+                    username: env::var("OBJECT_STORE_AUTH_USERNAME").ok(),
+                    password: secret("OBJECT_STORE_AUTH_PASSWORD"),
+                };
+                auth.check("OBJECT_STORE")?;
+                Some(ObjectStoreConfig {
+                    url,
+                    auth,
+                    tier_after,
+                    s3,
+                })
+            }
+            _ => None,
+        };
+
+        let mut pack_after = None;
+        if let Ok(v) = env::var("PACK_AFTER") {
+            pack_after = Some(time::Duration::from_secs(v.parse()?));
+        }
+
+        let reconcile_payloads = match env::var("RECONCILE_PAYLOADS") {
+            Ok(v) => v.parse::<bool>()?,
+            Err(_) => true,
+        };
+
+        let backup = match env::var("DB_BACKUP_DIR") {
+            Ok(dir) if !dir.is_empty() => {
+                let mut interval = time::Duration::from_secs(3600);
+                if let Ok(v) = env::var("DB_BACKUP_INTERVAL") {
+                    interval = time::Duration::from_secs(v.parse()?);
+                }
+                let mut keep = 24;
+                if let Ok(v) = env::var("DB_BACKUP_KEEP") {
+                    keep = v.parse()?;
+                }
+                Some(BackupConfig {
+                    dir: PathBuf::from(dir),
+                    interval,
+                    keep,
+                })
+            }
+            _ => None,
+        };
+
+        let mut webhook = WebhookConfig::default();
+        match env::var("WEBHOOK_SECRET") {
+            Ok(v) if !v.is_empty() => webhook.secret = Some(Secret::new(&v)),
+            _ => warn!("WEBHOOK_SECRET not defined, job callbacks are not signed"),
+        }
+        if let Ok(v) = env::var("WEBHOOK_MAX_ATTEMPTS") {
+            webhook.max_attempts = v.parse()?;
+        }
+        if let Ok(v) = env::var("WEBHOOK_BACKOFF") {
+            webhook.backoff = time::Duration::from_secs(v.parse()?);
+        }
+
+        let hook = |key: &str| {
+            env::var(key)
+                .ok()
+                .filter(|v| !v.is_empty())
+                .map(PathBuf::from)
+        };
+        let mut hooks = HooksConfig {
+            pre_submit: hook("HOOK_PRE_SUBMIT"),
+            post_complete: hook("HOOK_POST_COMPLETE"),
+            pre_clean: hook("HOOK_PRE_CLEAN"),
+            ..Default::default()
+        };
+        if let Ok(v) = env::var("HOOK_TIMEOUT") {
+            hooks.timeout = time::Duration::from_secs(v.parse()?);
+        }
+        if let Ok(v) = env::var("WASM_RUNTIME")
+            && !v.is_empty()
+        {
+            // The sandbox flags are the server's, none can be added or overridden
+            if v.split_whitespace().count() > 1 {
+                return Err(
+                    "WASM_RUNTIME takes the path of wasmtime alone, without arguments".into(),
+                );
+            }
+            hooks.wasm_runtime = PathBuf::from(v);
+        }
+        if let Ok(v) = env::var("WASM_MAX_MEMORY") {
+            hooks.wasm_max_memory = v.parse()?;
+        }
+        if let Ok(v) = env::var("WASM_FUEL") {
+            hooks.wasm_fuel = v.parse()?;
+        }
+
+        let mut download_retry = DownloadRetryConfig::default();
+        if let Ok(v) = env::var("DOWNLOAD_MAX_ATTEMPTS") {
+            download_retry.max_attempts = v.parse::<u32>()?.max(1);
+        }
+        if let Ok(v) = env::var("DOWNLOAD_BACKOFF") {
+            download_retry.backoff = time::Duration::from_secs(v.parse()?);
+        }
+
+        let mut submit_retry = SubmitRetryConfig::default();
+        if let Ok(v) = env::var("SUBMIT_MAX_ATTEMPTS") {
+            submit_retry.max_attempts = v.parse::<u32>()?.max(1);
+        }
+        if let Ok(v) = env::var("SUBMIT_BACKOFF") {
+            submit_retry.backoff = time::Duration::from_secs(v.parse()?);
+        }
+
+        // Alerts go to the operator webhook and the subscriptions to them
+        let alert_url = env::var("ALERT_WEBHOOK_URL").ok().filter(|u| !u.is_empty());
+        let alert_subscribed = subscriptions
+            .values()
+            .any(|s| s.wants("alert.queue_depth") || s.wants("alert.oldest_queued"));
+        let alerts = match alert_url {
+            url if url.is_some() || alert_subscribed => {
+                let mut queue_depth = None;
+                if let Ok(v) = env::var("ALERT_QUEUE_DEPTH") {
+                    queue_depth = Some(v.parse()?);
+                }
+                let mut queue_depth_for = time::Duration::from_secs(300);
+                if let Ok(v) = env::var("ALERT_QUEUE_DEPTH_FOR") {
+                    queue_depth_for = time::Duration::from_secs(v.parse()?);
+                }
+                let mut oldest_queued = None;
+                if let Ok(v) = env::var("ALERT_OLDEST_QUEUED") {
+                    oldest_queued = Some(time::Duration::from_secs(v.parse()?));
+                }
+                let unset = queue_depth.is_none() && oldest_queued.is_none();
+                if unset && url.is_some() {
+                    warn!(
+                        "ALERT_WEBHOOK_URL set without ALERT_QUEUE_DEPTH or ALERT_OLDEST_QUEUED, nothing is reported"
+                    );
+                }
+                // A catch-all subscription alone does not turn the alerts on
+                (url.is_some() || !unset).then_some(AlertConfig {
+                    webhook_url: url,
+                    queue_depth,
+                    queue_depth_for,
+                    oldest_queued,
+                })
+            }
+            _ => None,
+        };
+
+        let sender_batch = match env::var("SENDER_BATCH_SIZE") {
+            Ok(v) => v.parse::<usize>()?.max(1),
+            Err(_) => DEFAULT_SENDER_BATCH,
+        };
+
+        let mut probes = ProbeConfig::default();
+        if let Ok(v) = env::var("PROBE_INTERVAL") {
+            probes.interval = time::Duration::from_secs(v.parse::<u64>()?.max(1));
+        }
+        if let Ok(v) = env::var("PROBE_TIMEOUT") {
+            probes.timeout = time::Duration::from_secs(v.parse()?);
+        }
+        if let Ok(v) = env::var("PROBE_DOWN_AFTER") {
+            probes.down_after = v.parse::<u32>()?.max(1);
+        }
+
+        let mut client_versions = ClientVersionPolicy {
+            min: match env::var("CLIENT_MIN_VERSION") {
+                Ok(v) if !v.trim().is_empty() => Some(
+                    parse_version(&v)
+                        .ok_or_else(|| format!("Invalid client version {}", v.trim()))?,
+                ),
+                _ => None,
+            },
+            ..Default::default()
+        };
+        if let Ok(v) = env::var("CLIENT_DEPRECATED_VERSIONS") {
+            client_versions.deprecated = parse_versions(&v)?;
+        }
+        if let Ok(v) = env::var("CLIENT_BLOCKED_VERSIONS") {
+            client_versions.blocked = parse_versions(&v)?;
+        }
+
+        // Comma separated `<path>=<sha256>` entries
+        let mut pinned_binaries = Vec::new();
+        if let Ok(v) = env::var("PINNED_BINARIES") {
+            for entry in v.split(',').filter(|e| !e.trim().is_empty()) {
+                pinned_binaries.push(entry.trim().parse::<PinnedBinary>()?);
+            }
+        }
+
+        let image_digest = env::var("CONTAINER_IMAGE_DIGEST")
+            .ok()
+            .filter(|v| !v.is_empty());
+        let environment_probes = env::var("ENVIRONMENT_PROBES")
+            .map(|v| {
+                v.split(',')
+                    .map(|p| p.trim().to_string())
+                    .filter(|p| !p.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let scratch_path = env::var("SCRATCH_PATH").ok().filter(|v| !v.is_empty());
+        let warmup = match env::var("WARMUP_COMMAND") {
+            Ok(command) if !command.trim().is_empty() => Some(WarmupConfig {
+                command,
+                timeout: match env::var("WARMUP_TIMEOUT") {
+                    Ok(v) => time::Duration::from_secs(v.parse()?),
+                    Err(_) => time::Duration::from_secs(1800),
+                },
+            }),
+            _ => None,
+        };
+        let prebuild_archive = match env::var("PREBUILD_ARCHIVE") {
+            Ok(v) => v.parse::<bool>()?,
+            Err(_) => false,
+        };
+        let mut run_default_timeout = None;
+        if let Ok(v) = env::var("RUN_DEFAULT_TIMEOUT") {
+            run_default_timeout = Some(time::Duration::from_secs(v.parse()?));
+        }
+        let mut run_max_timeout = None;
+        if let Ok(v) = env::var("RUN_MAX_TIMEOUT") {
+            run_max_timeout = Some(time::Duration::from_secs(v.parse()?));
+        }
+        let mut max_output_size = None;
+        if let Ok(v) = env::var("MAX_OUTPUT_SIZE") {
+            max_output_size = Some(v.parse()?);
+        }
+        let mut run_log_max_size = DEFAULT_RUN_LOG_MAX_SIZE;
+        if let Ok(v) = env::var("RUN_LOG_MAX_SIZE") {
+            run_log_max_size = v.parse()?;
+        }
+
+        // Comma separated, blanks dropped
+        let list = |key: &str| -> Vec<String> {
+            env::var(key)
+                .map(|v| {
+                    v.split(',')
+                        .map(|e| e.trim().to_string())
+                        .filter(|e| !e.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        let mut upload_checks = UploadChecks {
+            allowed_extensions: list("UPLOAD_ALLOWED_EXTENSIONS")
+                .iter()
+                .map(|e| e.trim_start_matches('.').to_lowercase())
+                .collect(),
+            script_denylist: list("UPLOAD_SCRIPT_DENYLIST"),
+            av_command: env::var("UPLOAD_AV_COMMAND")
+                .ok()
+                .filter(|v| !v.trim().is_empty()),
+            ..Default::default()
+        };
+        if let Ok(v) = env::var("UPLOAD_MAX_FILE_SIZE") {
+            upload_checks.max_file_size = Some(v.parse()?);
+        }
+
+        // Fault injection, off unless one of the rates is set
+        let rate = |key: &str| -> Result<f64, String> {
+            match env::var(key) {
+                Ok(v) => match v.parse::<f64>() {
+                    Ok(r) if (0.0..=1.0).contains(&r) => Ok(r),
+                    _ => Err(format!("{key}: expected a rate between 0 and 1, got {v}")),
+                },
+                Err(_) => Ok(0.0),
+            }
+        };
+        let mut chaos = ChaosConfig {
+            upload_failure_rate: rate("CHAOS_UPLOAD_FAILURE_RATE")?,
+            slow_download_rate: rate("CHAOS_SLOW_DOWNLOAD_RATE")?,
+            db_failure_rate: rate("CHAOS_DB_FAILURE_RATE")?,
+            ..Default::default()
+        };
+        if let Ok(v) = env::var("CHAOS_SLOW_DOWNLOAD_DELAY") {
+            chaos.slow_download_delay = time::Duration::from_secs(v.parse()?);
+        }
+        let chaos = if chaos.is_active() {
+            warn!("Fault injection is enabled, do not run this in production: {chaos:?}");
+            Some(chaos)
+        } else {
+            None
+        };
+
+        let config = Config {
+            services,
+            tenants,
+            tiers,
+            db_path,
+            data_path,
+            max_age,
+            job_dir_depth,
+            dedupe_window,
+            require_registered_users,
+            expiry_notice,
+            port,
+            access_log,
+            admin_token,
+            management_allowlist,
+            download_links,
+            oidc,
+            http,
+            body_limits,
+            cors,
+            remote_inputs,
+            object_store,
+            pack_after,
+            reconcile_payloads,
+            backup,
+            webhook,
+            hooks,
+            download_retry,
+            submit_retry,
+            alerts,
+            subscriptions,
+            sender_batch,
+            probes,
+            client_versions,
+            pinned_binaries,
+            image_digest,
+            environment_probes,
+            prebuild_archive,
+            run_default_timeout,
+            run_max_timeout,
+            max_output_size,
+            run_log_max_size,
+            upload_checks,
+            datasets,
+            scratch_path,
+            warmup,
+            chaos,
+        };
+
+        info!("{:?}", config);
+        Ok(config)
+    }
+
+    pub fn get_download_url(&self, service_name: &str) -> Option<&str> {
+        self.services
+            .get(service_name)
+            .map(|service| service.download_url.as_str())
+    }
+
+    pub fn get_upload_url(&self, service_name: &str) -> Option<&str> {
+        self.services
+            .get(service_name)
+            .map(|service| service.upload_url.as_str())
+    }
+
+    pub fn get_terminate_url(&self, service_name: &str) -> Option<&str> {
+        self.services
+            .get(service_name)
+            .map(|service| service.terminate_url.as_str())
+    }
+
+    /// The default tenant always exists, others must be configured
+    pub fn is_known_tenant(&self, tenant: &str) -> bool {
+        tenant == DEFAULT_TENANT || self.tenants.contains_key(tenant)
+    }
+
+    pub fn tenant_allows_service(&self, tenant: &str, service: &str) -> bool {
+        match self.tenants.get(tenant) {
+            Some(t) if !t.services.is_empty() => t.services.iter().any(|s| s == service),
+            _ => true,
+        }
+    }
+
+    pub fn tier_allows_service(&self, tier: &str, service: &str) -> bool {
+        match self.tiers.get(tier) {
+            Some(t) if !t.services.is_empty() => t.services.iter().any(|s| s == service),
+            _ => true,
+        }
+    }
+
+    /// A user may submit to a service open to both its tenant and its tier
+    pub fn user_allows_service(&self, tenant: &str, tier: &str, service: &str) -> bool {
+        self.tenant_allows_service(tenant, service) && self.tier_allows_service(tier, service)
+    }
+
+    /// Storage root of the jobs of a tenant on a service: the root of the
+    /// service, else the one of the tenant, else `data_path`. The tenant
+    /// namespace is laid out below it as in `data_path`, see
+    /// [`tenant_data_path`]. The client does not know the services, it passes `None`.
+    pub fn data_path_for(&self, service: Option<&str>, tenant: &str) -> &str {
+        service
+            .and_then(|s| self.services.get(s))
+            .and_then(|s| s.data_path.as_deref())
+            .or_else(|| {
+                self.tenants
+                    .get(tenant)
+                    .and_then(|t| t.data_path.as_deref())
+            })
+            .unwrap_or(&self.data_path)
+    }
+
+    /// Every storage root jobs may live under, `data_path` first
+    pub fn data_paths(&self) -> Vec<&str> {
+        let mut paths = vec![self.data_path.as_str()];
+        let mut services: Vec<_> = self.services.values().collect();
+        services.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut tenants: Vec<_> = self.tenants.values().collect();
+        tenants.sort_by(|a, b| a.name.cmp(&b.name));
+        let roots = services
+            .into_iter()
+            .filter_map(|s| s.data_path.as_deref())
+            .chain(tenants.into_iter().filter_map(|t| t.data_path.as_deref()));
+        for root in roots {
+            if !paths.contains(&root) {
+                paths.push(root);
+            }
+        }
+        paths
+    }
+
+    /// Retention for the jobs of a tenant
+    pub fn max_age_for(&self, tenant: &str) -> Duration {
+        self.tenants
+            .get(tenant)
+            .and_then(|t| t.max_age)
+            .unwrap_or(self.max_age)
+    }
+
+    /// Per-user quota for a service, a tenant override takes precedence
+    pub fn runs_per_user_for(&self, tenant: &str, service: &Service) -> u16 {
+        self.tenants
+            .get(tenant)
+            .and_then(|t| t.runs_per_user)
+            .unwrap_or(service.runs_per_user)
+    }
+
+    /// Round-robin weight of a tier, 1 unless configured otherwise
+    pub fn tier_weight(&self, tier: &str) -> usize {
+        self.tiers.get(tier).map_or(1, |t| t.weight.max(1) as usize)
+    }
+
+    /// Active job cap shared by all users of a tier, if any
+    pub fn tier_max_runs(&self, tier: &str) -> Option<u16> {
+        self.tiers.get(tier).and_then(|t| t.max_runs)
+    }
+}
+
+/// Data directory of a tenant; the default tenant uses `data_path` itself
+/// so layouts from before tenants existed keep working
+pub fn tenant_data_path(data_path: &str, tenant: &str) -> PathBuf {
+    if tenant == DEFAULT_TENANT {
+        PathBuf::from(data_path)
+    } else {
+        Path::new(data_path).join(tenant)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    // Helper to create a test config manually
+    fn create_test_config() -> Config {
+        let mut services = HashMap::new();
+
+        services.insert(
+            "test".to_string(),
+            Service {
+                name: "test".to_string(),
+                upload_url: "http://test.com/upload".to_string(),
+                download_url: "http://test.com/download".to_string(),
+                terminate_url: "http://example.com/terminate".to_string(),
+                runs_per_user: 10,
+                max_runs: 1,
+                ..Default::default()
+            },
+        );
+
+        Config {
             services,
             db_path: "/test/db.sqlite".to_string(),
             data_path: "/test/data".to_string(),
             max_age: Duration::from_secs(3600),
             port: 1111,
+            ..Default::default()
         }
     }
 
@@ -180,6 +1820,7 @@ mod tests {
             terminate_url: "http://example.com/terminate".to_string(),
             runs_per_user: 5,
             max_runs: 1,
+            ..Default::default()
         };
 
         assert_eq!(service.name, "test");
@@ -265,6 +1906,7 @@ mod tests {
                 terminate_url: "http://s1.com/terminate".to_string(),
                 runs_per_user: 5,
                 max_runs: 1,
+                ..Default::default()
             },
         );
 
@@ -277,6 +1919,7 @@ mod tests {
                 terminate_url: "http://s2.com/terminate".to_string(),
                 runs_per_user: 10,
                 max_runs: 1,
+                ..Default::default()
             },
         );
 
@@ -286,6 +1929,7 @@ mod tests {
             data_path: "/test/data".to_string(),
             max_age: Duration::from_secs(7200),
             port: 1111,
+            ..Default::default()
         };
 
         assert_eq!(config.services.len(), 2);
@@ -337,6 +1981,25 @@ mod tests {
             env::set_var("SERVICE_FOO_TERMINATE_URL", "http://foo.com/terminate");
             env::set_var("SERVICE_FOO_RUNS_PER_USER", "3");
             env::set_var("SERVICE_FOO_MAX_RUNS", "2");
+            env::set_var("SERVICE_FOO_EXTRACT_RESULTS", "true");
+            env::set_var("SERVICE_FOO_SHARED_RESULTS", "true");
+            env::set_var("SERVICE_FOO_SANDBOX", "untrusted");
+            env::set_var("SERVICE_FOO_CONNECT_TIMEOUT", "2");
+            env::set_var("SERVICE_FOO_TRANSFER_TIMEOUT", "600");
+            env::set_var("SERVICE_FOO_HOLD_SUSPICIOUS", "true");
+            env::set_var("SERVICE_FOO_HOLD_QUEUED_OVER", "20");
+            env::set_var("SERVICE_FOO_STALE_AFTER", "3600");
+            env::set_var("SERVICE_FOO_ON_STALE", "Requeue");
+            env::set_var("SERVICE_FOO_DATA_PATH", "/nvme/foo");
+            env::set_var("SERVICE_FOO_DEFAULT_TIMEOUT", "3600");
+            env::set_var("SERVICE_FOO_MAX_TIMEOUT", "86400");
+            env::set_var("SERVICE_FOO_MAX_OUTPUT_SIZE", "1073741824");
+            env::set_var("SERVICE_FOO_CLEANUP", "archive");
+            env::set_var("SERVICE_FOO_TEMPLATE", "/etc/orchestrator/foo.sh");
+            env::set_var("SERVICE_FOO_WINDOWS", "mon-fri 22:00-06:00; sat,sun");
+            env::set_var("SERVICE_FOO_USER_AGENT", "orchestrator-foo/1.0");
+            env::set_var("SERVICE_FOO_HEADER_X_ROUTE_HINT", "gpu");
+            env::set_var("SERVICE_FOO_WASM_VALIDATOR", "/etc/orchestrator/foo.wasm");
         }
         let config = Config::new().unwrap();
         cleanup_env(&[
@@ -345,6 +2008,25 @@ mod tests {
             "SERVICE_FOO_TERMINATE_URL",
             "SERVICE_FOO_RUNS_PER_USER",
             "SERVICE_FOO_MAX_RUNS",
+            "SERVICE_FOO_EXTRACT_RESULTS",
+            "SERVICE_FOO_SHARED_RESULTS",
+            "SERVICE_FOO_SANDBOX",
+            "SERVICE_FOO_CONNECT_TIMEOUT",
+            "SERVICE_FOO_TRANSFER_TIMEOUT",
+            "SERVICE_FOO_HOLD_SUSPICIOUS",
+            "SERVICE_FOO_HOLD_QUEUED_OVER",
+            "SERVICE_FOO_STALE_AFTER",
+            "SERVICE_FOO_ON_STALE",
+            "SERVICE_FOO_DATA_PATH",
+            "SERVICE_FOO_DEFAULT_TIMEOUT",
+            "SERVICE_FOO_MAX_TIMEOUT",
+            "SERVICE_FOO_MAX_OUTPUT_SIZE",
+            "SERVICE_FOO_CLEANUP",
+            "SERVICE_FOO_TEMPLATE",
+            "SERVICE_FOO_WINDOWS",
+            "SERVICE_FOO_USER_AGENT",
+            "SERVICE_FOO_HEADER_X_ROUTE_HINT",
+            "SERVICE_FOO_WASM_VALIDATOR",
         ]);
 
         let service = config
@@ -356,6 +2038,118 @@ mod tests {
         assert_eq!(service.terminate_url, "http://foo.com/terminate");
         assert_eq!(service.runs_per_user, 3);
         assert_eq!(service.max_runs, 2);
+        assert!(service.extract_results);
+        assert!(service.shared_results);
+        assert_eq!(service.sandbox, SandboxProfile::Untrusted);
+        assert_eq!(service.connect_timeout, Some(Duration::from_secs(2)));
+        assert_eq!(service.transfer_timeout, Some(Duration::from_secs(600)));
+        assert!(service.hold_suspicious);
+        assert_eq!(service.hold_queued_over, Some(20));
+        assert_eq!(service.stale_after, Some(Duration::from_secs(3600)));
+        assert_eq!(service.on_stale, StaleAction::Requeue);
+        assert_eq!(service.data_path.as_deref(), Some("/nvme/foo"));
+        assert_eq!(service.default_timeout, Some(Duration::from_secs(3600)));
+        assert_eq!(service.max_timeout, Some(Duration::from_secs(86400)));
+        assert_eq!(service.max_output_size, Some(1073741824));
+        assert_eq!(service.cleanup, CleanupAction::Archive);
+        assert_eq!(
+            service.template.as_deref(),
+            Some("/etc/orchestrator/foo.sh")
+        );
+        assert_eq!(service.user_agent.as_deref(), Some("orchestrator-foo/1.0"));
+        assert_eq!(
+            service.headers,
+            BTreeMap::from([("x-route-hint".to_string(), "gpu".to_string())])
+        );
+        assert_eq!(
+            service.wasm_validator,
+            Some(PathBuf::from("/etc/orchestrator/foo.wasm"))
+        );
+        // Monday 1970-01-05 at noon, then on Saturday
+        let monday = SystemTime::UNIX_EPOCH + Duration::from_secs(4 * 86400 + 12 * 3600);
+        assert_eq!(
+            service.closed_reason(monday).as_deref(),
+            Some("Outside the execution windows of service foo, the next one opens Mon 22:00 UTC")
+        );
+        assert_eq!(
+            service.closed_reason(monday + Duration::from_secs(5 * 86400)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_effective_timeout() {
+        let s = Duration::from_secs;
+        assert_eq!(effective_timeout(None, None, None), None);
+        assert_eq!(effective_timeout(Some(s(60)), None, None), Some(s(60)));
+        assert_eq!(effective_timeout(None, Some(s(60)), None), Some(s(60)));
+        assert_eq!(
+            effective_timeout(Some(s(30)), Some(s(60)), Some(s(90))),
+            Some(s(30))
+        );
+        assert_eq!(
+            effective_timeout(Some(s(120)), Some(s(60)), Some(s(90))),
+            Some(s(90))
+        );
+        // Without a default the jobs still may not run longer than the max
+        assert_eq!(effective_timeout(None, None, Some(s(90))), Some(s(90)));
+        assert_eq!(
+            effective_timeout(None, Some(s(120)), Some(s(90))),
+            Some(s(90))
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_new_with_run_timeouts() {
+        let keys = ["RUN_DEFAULT_TIMEOUT", "RUN_MAX_TIMEOUT"];
+        cleanup_env(&keys);
+        let config = Config::new().unwrap();
+        assert_eq!(config.run_default_timeout, None);
+        assert_eq!(config.run_max_timeout, None);
+
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var(keys[0], "600");
+            env::set_var(keys[1], "7200");
+        }
+        let config = Config::new().unwrap();
+        cleanup_env(&keys);
+        assert_eq!(config.run_default_timeout, Some(Duration::from_secs(600)));
+        assert_eq!(config.run_max_timeout, Some(Duration::from_secs(7200)));
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_new_with_max_output_size() {
+        cleanup_env(&["MAX_OUTPUT_SIZE"]);
+        assert_eq!(Config::new().unwrap().max_output_size, None);
+
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var("MAX_OUTPUT_SIZE", "10737418240");
+        }
+        let config = Config::new().unwrap();
+        cleanup_env(&["MAX_OUTPUT_SIZE"]);
+        assert_eq!(config.max_output_size, Some(10737418240));
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_new_with_run_log_max_size() {
+        cleanup_env(&["RUN_LOG_MAX_SIZE"]);
+        assert_eq!(
+            Config::new().unwrap().run_log_max_size,
+            DEFAULT_RUN_LOG_MAX_SIZE
+        );
+
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var("RUN_LOG_MAX_SIZE", "4096");
+        }
+        let config = Config::new().unwrap();
+        cleanup_env(&["RUN_LOG_MAX_SIZE"]);
+        assert_eq!(config.run_log_max_size, 4096);
     }
 
     #[test]
@@ -408,6 +2202,52 @@ mod tests {
         assert_eq!(config.max_age, Duration::from_secs(3600));
     }
 
+    #[test]
+    #[serial]
+    fn test_config_new_dedupe_window() {
+        let config = Config::new().unwrap();
+        assert_eq!(config.dedupe_window, Duration::from_secs(600));
+
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe { env::set_var("DEDUPE_WINDOW", "30") };
+        let config = Config::new().unwrap();
+        cleanup_env(&["DEDUPE_WINDOW"]);
+
+        assert_eq!(config.dedupe_window, Duration::from_secs(30));
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_new_require_registered_users() {
+        let config = Config::new().unwrap();
+        assert!(!config.require_registered_users);
+
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe { env::set_var("REQUIRE_REGISTERED_USERS", "true") };
+        let config = Config::new().unwrap();
+        cleanup_env(&["REQUIRE_REGISTERED_USERS"]);
+
+        assert!(config.require_registered_users);
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_new_expiry_notice() {
+        let config = Config::new().unwrap();
+        assert_eq!(config.expiry_notice, Some(Duration::from_secs(86400)));
+
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe { env::set_var("EXPIRY_NOTICE", "3600") };
+        let config = Config::new().unwrap();
+        assert_eq!(config.expiry_notice, Some(Duration::from_secs(3600)));
+
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe { env::set_var("EXPIRY_NOTICE", "0") };
+        let config = Config::new().unwrap();
+        cleanup_env(&["EXPIRY_NOTICE"]);
+        assert_eq!(config.expiry_notice, None);
+    }
+
     #[test]
     #[serial]
     fn test_config_new_service_name_lowercase() {
@@ -431,4 +2271,1239 @@ mod tests {
             config.services.keys().collect::<Vec<_>>()
         );
     }
+
+    // ===== Tenant tests =====
+
+    #[test]
+    fn test_default_tenant_is_known() {
+        let config = create_test_config();
+        assert!(config.is_known_tenant(DEFAULT_TENANT));
+        assert!(!config.is_known_tenant("unknown"));
+    }
+
+    #[test]
+    fn test_tenant_allows_service() {
+        let mut config = create_test_config();
+        config.tenants.insert(
+            "lab".to_string(),
+            Tenant {
+                name: "lab".to_string(),
+                services: vec!["test".to_string()],
+                ..Default::default()
+            },
+        );
+        config.tenants.insert(
+            "open".to_string(),
+            Tenant {
+                name: "open".to_string(),
+                ..Default::default()
+            },
+        );
+
+        assert!(config.tenant_allows_service("lab", "test"));
+        assert!(!config.tenant_allows_service("lab", "other"));
+        // No restriction configured
+        assert!(config.tenant_allows_service("open", "other"));
+        assert!(config.tenant_allows_service(DEFAULT_TENANT, "other"));
+    }
+
+    #[test]
+    fn test_user_allows_service() {
+        let mut config = create_test_config();
+        config.tenants.insert(
+            "lab".to_string(),
+            Tenant {
+                name: "lab".to_string(),
+                services: vec!["test".to_string(), "other".to_string()],
+                ..Default::default()
+            },
+        );
+        config.tiers.insert(
+            "trial".to_string(),
+            Tier {
+                name: "trial".to_string(),
+                services: vec!["test".to_string()],
+                ..Default::default()
+            },
+        );
+
+        assert!(config.user_allows_service("lab", "trial", "test"));
+        assert!(!config.user_allows_service("lab", "trial", "other"));
+        assert!(config.user_allows_service("lab", "standard", "other"));
+        // Both must allow it
+        assert!(!config.user_allows_service("lab", "standard", "third"));
+        assert!(!config.user_allows_service(DEFAULT_TENANT, "trial", "third"));
+        assert!(config.user_allows_service(DEFAULT_TENANT, "standard", "third"));
+    }
+
+    #[test]
+    fn test_tenant_overrides() {
+        let mut config = create_test_config();
+        config.tenants.insert(
+            "lab".to_string(),
+            Tenant {
+                name: "lab".to_string(),
+                max_age: Some(Duration::from_secs(60)),
+                runs_per_user: Some(1),
+                ..Default::default()
+            },
+        );
+        let service = config.services.get("test").unwrap().clone();
+
+        assert_eq!(config.max_age_for("lab"), Duration::from_secs(60));
+        assert_eq!(
+            config.max_age_for(DEFAULT_TENANT),
+            Duration::from_secs(3600)
+        );
+        assert_eq!(config.runs_per_user_for("lab", &service), 1);
+        assert_eq!(config.runs_per_user_for(DEFAULT_TENANT, &service), 10);
+    }
+
+    #[test]
+    fn test_tenant_data_path() {
+        assert_eq!(
+            tenant_data_path("/test/data", DEFAULT_TENANT),
+            PathBuf::from("/test/data")
+        );
+        assert_eq!(
+            tenant_data_path("/test/data", "lab"),
+            PathBuf::from("/test/data/lab")
+        );
+    }
+
+    #[test]
+    fn test_data_path_for() {
+        let mut config = create_test_config();
+        config.services.get_mut("test").unwrap().data_path = Some("/nvme".to_string());
+        config.services.insert(
+            "archive".to_string(),
+            Service {
+                name: "archive".to_string(),
+                ..Default::default()
+            },
+        );
+        config.tenants.insert(
+            "lab".to_string(),
+            Tenant {
+                name: "lab".to_string(),
+                data_path: Some("/array".to_string()),
+                ..Default::default()
+            },
+        );
+
+        // The service root wins over the tenant root
+        assert_eq!(config.data_path_for(Some("test"), "lab"), "/nvme");
+        assert_eq!(config.data_path_for(Some("archive"), "lab"), "/array");
+        assert_eq!(config.data_path_for(None, "lab"), "/array");
+        assert_eq!(
+            config.data_path_for(Some("archive"), DEFAULT_TENANT),
+            "/test/data"
+        );
+        assert_eq!(config.data_path_for(Some("unknown"), "other"), "/test/data");
+
+        assert_eq!(config.data_paths(), ["/test/data", "/nvme", "/array"]);
+        config.tenants.get_mut("lab").unwrap().data_path = Some("/nvme".to_string());
+        assert_eq!(config.data_paths(), ["/test/data", "/nvme"]);
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_new_with_tenant_env() {
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var("TENANT_LAB_SERVICES", "foo, Bar");
+            env::set_var("TENANT_LAB_MAX_AGE", "120");
+            env::set_var("TENANT_LAB_RUNS_PER_USER", "2");
+            env::set_var("TENANT_LAB_MAX_RUNS", "4");
+            env::set_var("TENANT_LAB_DATA_PATH", "/array/lab");
+        }
+        let config = Config::new().unwrap();
+        cleanup_env(&[
+            "TENANT_LAB_SERVICES",
+            "TENANT_LAB_MAX_AGE",
+            "TENANT_LAB_RUNS_PER_USER",
+            "TENANT_LAB_MAX_RUNS",
+            "TENANT_LAB_DATA_PATH",
+        ]);
+
+        let tenant = config
+            .tenants
+            .get("lab")
+            .expect("Tenant 'lab' should be present");
+        assert_eq!(tenant.services, vec!["foo".to_string(), "bar".to_string()]);
+        assert_eq!(tenant.max_age, Some(Duration::from_secs(120)));
+        assert_eq!(tenant.runs_per_user, Some(2));
+        assert_eq!(tenant.max_runs, Some(4));
+        assert_eq!(tenant.data_path.as_deref(), Some("/array/lab"));
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_new_with_tier_env() {
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var("TIER_PRIORITY_WEIGHT", "3");
+            env::set_var("TIER_PRIORITY_MAX_RUNS", "6");
+            env::set_var("TIER_PRIORITY_SERVICES", "Test, other,");
+        }
+        let config = Config::new().unwrap();
+        cleanup_env(&[
+            "TIER_PRIORITY_WEIGHT",
+            "TIER_PRIORITY_MAX_RUNS",
+            "TIER_PRIORITY_SERVICES",
+        ]);
+
+        let tier = config
+            .tiers
+            .get("priority")
+            .expect("Tier 'priority' should be present");
+        assert_eq!(tier.weight, 3);
+        assert_eq!(tier.max_runs, Some(6));
+        assert_eq!(tier.services, vec!["test", "other"]);
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_new_with_pinned_binaries() {
+        let hash = "a".repeat(64);
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var(
+                "PINNED_BINARIES",
+                format!("/usr/bin/gmx={hash}, /opt/haddock/bin/haddock={hash}"),
+            );
+        }
+        let config = Config::new().unwrap();
+        cleanup_env(&["PINNED_BINARIES"]);
+
+        assert_eq!(config.pinned_binaries.len(), 2);
+        assert_eq!(
+            config.pinned_binaries[1].path,
+            std::path::PathBuf::from("/opt/haddock/bin/haddock")
+        );
+
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var("PINNED_BINARIES", "/usr/bin/gmx=nothex");
+        }
+        let result = Config::new();
+        cleanup_env(&["PINNED_BINARIES"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_new_with_environment_probes() {
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var("CONTAINER_IMAGE_DIGEST", "sha256:abc");
+            env::set_var("ENVIRONMENT_PROBES", "gmx --version, python3 --version,");
+        }
+        let config = Config::new().unwrap();
+        cleanup_env(&["CONTAINER_IMAGE_DIGEST", "ENVIRONMENT_PROBES"]);
+
+        assert_eq!(config.image_digest.as_deref(), Some("sha256:abc"));
+        assert_eq!(
+            config.environment_probes,
+            vec!["gmx --version", "python3 --version"]
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_new_with_scratch_path() {
+        cleanup_env(&["SCRATCH_PATH"]);
+        assert_eq!(Config::new().unwrap().scratch_path, None);
+
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var("SCRATCH_PATH", "/nvme/scratch");
+        }
+        let config = Config::new().unwrap();
+        cleanup_env(&["SCRATCH_PATH"]);
+        assert_eq!(config.scratch_path.as_deref(), Some("/nvme/scratch"));
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_new_with_warmup() {
+        let keys = ["WARMUP_COMMAND", "WARMUP_TIMEOUT"];
+        cleanup_env(&keys);
+        assert_eq!(Config::new().unwrap().warmup, None);
+
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var(keys[0], "docker pull haddock:2.5");
+        }
+        let config = Config::new().unwrap();
+        assert_eq!(
+            config.warmup,
+            Some(WarmupConfig {
+                command: "docker pull haddock:2.5".to_string(),
+                timeout: Duration::from_secs(1800),
+            })
+        );
+
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var(keys[1], "60");
+        }
+        let config = Config::new().unwrap();
+        cleanup_env(&keys);
+        assert_eq!(config.warmup.unwrap().timeout, Duration::from_secs(60));
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_new_with_prebuild_archive() {
+        assert!(!Config::new().unwrap().prebuild_archive);
+
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var("PREBUILD_ARCHIVE", "true");
+        }
+        let config = Config::new().unwrap();
+        assert!(config.prebuild_archive);
+
+        unsafe {
+            env::set_var("PREBUILD_ARCHIVE", "sometimes");
+        }
+        let result = Config::new();
+        cleanup_env(&["PREBUILD_ARCHIVE"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_new_with_object_store() {
+        let keys = [
+            "OBJECT_STORE_URL",
+            "OBJECT_STORE_AUTH_BEARER",
+            "STORAGE_TIER_AFTER_DAYS",
+        ];
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var(keys[0], "https://store.example.com/results/");
+            env::set_var(keys[1], "tok");
+            env::set_var(keys[2], "30");
+        }
+        let config = Config::new().unwrap();
+        cleanup_env(&keys);
+
+        let store = config
+            .object_store
+            .expect("object store should be configured");
+        assert_eq!(store.auth.bearer_token, Some(Secret::new("tok")));
+        assert_eq!(store.tier_after, Duration::from_secs(30 * 86400));
+        assert_eq!(store.s3, None);
+
+        let keys = [
+            "OBJECT_STORE_URL",
+            "OBJECT_STORE_S3_ACCESS_KEY",
+            "OBJECT_STORE_S3_SECRET_KEY",
+            "OBJECT_STORE_S3_URL_TTL",
+        ];
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var(keys[0], "https://s3.example.com/bucket");
+            env::set_var(keys[1], "AKID");
+            env::set_var(keys[2], "secret");
+            env::set_var(keys[3], "900");
+        }
+        let config = Config::new().unwrap();
+        cleanup_env(&keys);
+
+        let s3 = config
+            .object_store
+            .unwrap()
+            .s3
+            .expect("S3 keys should be set");
+        assert_eq!(s3.access_key, "AKID");
+        assert_eq!(s3.secret_key, Secret::new("secret"));
+        assert_eq!(s3.region, "us-east-1");
+        assert_eq!(s3.url_ttl, Duration::from_secs(900));
+        assert_eq!(
+            store.object_url("default/abc.zip"),
+            "https://store.example.com/results/default/abc.zip"
+        );
+
+        let config = Config::new().unwrap();
+        assert!(config.object_store.is_none());
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_new_with_pack_after() {
+        cleanup_env(&["PACK_AFTER"]);
+        assert_eq!(Config::new().unwrap().pack_after, None);
+
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var("PACK_AFTER", "3600");
+        }
+        let config = Config::new().unwrap();
+        cleanup_env(&["PACK_AFTER"]);
+        assert_eq!(config.pack_after, Some(Duration::from_secs(3600)));
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_new_with_chaos() {
+        let keys = [
+            "CHAOS_UPLOAD_FAILURE_RATE",
+            "CHAOS_SLOW_DOWNLOAD_RATE",
+            "CHAOS_SLOW_DOWNLOAD_DELAY",
+            "CHAOS_DB_FAILURE_RATE",
+        ];
+        cleanup_env(&keys);
+        assert_eq!(Config::new().unwrap().chaos, None);
+
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var(keys[1], "0.25");
+            env::set_var(keys[2], "30");
+        }
+        let config = Config::new().unwrap();
+        assert_eq!(
+            config.chaos,
+            Some(ChaosConfig {
+                upload_failure_rate: 0.0,
+                slow_download_rate: 0.25,
+                slow_download_delay: Duration::from_secs(30),
+                db_failure_rate: 0.0,
+            })
+        );
+
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var(keys[0], "1.5");
+        }
+        let result = Config::new();
+        cleanup_env(&keys);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_new_with_backup() {
+        let keys = ["DB_BACKUP_DIR", "DB_BACKUP_INTERVAL", "DB_BACKUP_KEEP"];
+        cleanup_env(&keys);
+        assert_eq!(Config::new().unwrap().backup, None);
+
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var(keys[0], "/backups");
+            env::set_var(keys[1], "600");
+        }
+        let config = Config::new().unwrap();
+        cleanup_env(&keys);
+        assert_eq!(
+            config.backup,
+            Some(BackupConfig {
+                dir: PathBuf::from("/backups"),
+                interval: Duration::from_secs(600),
+                keep: 24,
+            })
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_new_with_webhook() {
+        let keys = ["WEBHOOK_SECRET", "WEBHOOK_MAX_ATTEMPTS", "WEBHOOK_BACKOFF"];
+        cleanup_env(&keys);
+        assert_eq!(Config::new().unwrap().webhook, WebhookConfig::default());
+
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var(keys[0], "hmac-key");
+            env::set_var(keys[2], "5");
+        }
+        let config = Config::new().unwrap();
+        cleanup_env(&keys);
+        assert_eq!(
+            config.webhook,
+            WebhookConfig {
+                secret: Some(Secret::new("hmac-key")),
+                max_attempts: 8,
+                backoff: Duration::from_secs(5),
+            }
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_new_with_hooks() {
+        let keys = [
+            "HOOK_PRE_SUBMIT",
+            "HOOK_POST_COMPLETE",
+            "HOOK_PRE_CLEAN",
+            "HOOK_TIMEOUT",
+            "WASM_RUNTIME",
+            "WASM_MAX_MEMORY",
+            "WASM_FUEL",
+        ];
+        cleanup_env(&keys);
+        assert_eq!(Config::new().unwrap().hooks, HooksConfig::default());
+
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var(keys[0], "/opt/hooks/check-submission");
+            env::set_var(keys[2], "");
+            env::set_var(keys[3], "5");
+            env::set_var(keys[4], "/usr/local/bin/wasmtime");
+            env::set_var(keys[5], "1048576");
+            env::set_var(keys[6], "1000");
+        }
+        let config = Config::new().unwrap();
+        assert_eq!(
+            config.hooks,
+            HooksConfig {
+                pre_submit: Some(PathBuf::from("/opt/hooks/check-submission")),
+                post_complete: None,
+                pre_clean: None,
+                timeout: Duration::from_secs(5),
+                wasm_runtime: PathBuf::from("/usr/local/bin/wasmtime"),
+                wasm_max_memory: 1048576,
+                wasm_fuel: 1000,
+            }
+        );
+
+        // Flags could lift the sandbox of the modules
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe { env::set_var(keys[4], "wasmtime run -S inherit-network") };
+        let result = Config::new();
+        cleanup_env(&keys);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_new_with_download_retry() {
+        let keys = ["DOWNLOAD_MAX_ATTEMPTS", "DOWNLOAD_BACKOFF"];
+        cleanup_env(&keys);
+        assert_eq!(
+            Config::new().unwrap().download_retry,
+            DownloadRetryConfig::default()
+        );
+
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var(keys[0], "0");
+            env::set_var(keys[1], "3");
+        }
+        let config = Config::new().unwrap();
+        cleanup_env(&keys);
+        assert_eq!(
+            config.download_retry,
+            DownloadRetryConfig {
+                max_attempts: 1,
+                backoff: Duration::from_secs(3),
+            }
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_new_with_submit_retry() {
+        let keys = ["SUBMIT_MAX_ATTEMPTS", "SUBMIT_BACKOFF"];
+        cleanup_env(&keys);
+        assert_eq!(
+            Config::new().unwrap().submit_retry,
+            SubmitRetryConfig::default()
+        );
+
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var(keys[0], "3");
+            env::set_var(keys[1], "60");
+        }
+        let config = Config::new().unwrap();
+        cleanup_env(&keys);
+        assert_eq!(
+            config.submit_retry,
+            SubmitRetryConfig {
+                max_attempts: 3,
+                backoff: Duration::from_secs(60),
+            }
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_new_with_alerts() {
+        let keys = [
+            "ALERT_WEBHOOK_URL",
+            "ALERT_QUEUE_DEPTH",
+            "ALERT_QUEUE_DEPTH_FOR",
+            "ALERT_OLDEST_QUEUED",
+        ];
+        cleanup_env(&keys);
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var(keys[1], "100");
+        }
+        assert_eq!(Config::new().unwrap().alerts, None);
+
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var(keys[0], "https://ops.example.org/hook");
+            env::set_var(keys[3], "1800");
+        }
+        let config = Config::new().unwrap();
+        cleanup_env(&keys);
+        assert_eq!(
+            config.alerts,
+            Some(AlertConfig {
+                webhook_url: Some("https://ops.example.org/hook".to_string()),
+                queue_depth: Some(100),
+                queue_depth_for: Duration::from_secs(300),
+                oldest_queued: Some(Duration::from_secs(1800)),
+            })
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_new_with_subscriptions() {
+        let keys = [
+            "NOTIFY_OPS_CHANNEL",
+            "NOTIFY_OPS_TARGET",
+            "NOTIFY_OPS_EVENTS",
+            "ALERT_QUEUE_DEPTH",
+        ];
+        cleanup_env(&keys);
+        assert!(Config::new().unwrap().subscriptions.is_empty());
+
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var(keys[0], "slack");
+            env::set_var(keys[2], "job.failed, alert.*");
+        }
+        assert!(Config::new().is_err());
+
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var(keys[1], "https://hooks.slack.com/services/T0/B0/x");
+            env::set_var(keys[3], "100");
+        }
+        let config = Config::new().unwrap();
+        cleanup_env(&keys);
+        assert_eq!(
+            config.subscriptions["ops"],
+            Subscription {
+                name: "ops".to_string(),
+                channel: Channel::Slack,
+                target: "https://hooks.slack.com/services/T0/B0/x".to_string(),
+                events: vec!["job.failed".to_string(), "alert.*".to_string()],
+            }
+        );
+        // The subscription is enough for the alerts to be raised
+        let alerts = config.alerts.unwrap();
+        assert_eq!(alerts.webhook_url, None);
+        assert_eq!(alerts.queue_depth, Some(100));
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_new_with_datasets() {
+        let keys = ["DATASET_GENOME_URL", "DATASET_GENOME_SHA256"];
+        cleanup_env(&keys);
+        assert!(Config::new().unwrap().datasets.is_empty());
+
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var(keys[1], "ABC123");
+        }
+        assert!(Config::new().is_err());
+
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var(keys[0], "https://data.example.org/hg38.zip");
+        }
+        let config = Config::new().unwrap();
+        cleanup_env(&keys);
+        assert_eq!(
+            config.datasets["genome"],
+            Dataset {
+                name: "genome".to_string(),
+                url: "https://data.example.org/hg38.zip".to_string(),
+                sha256: Some("abc123".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn test_subscription_wants() {
+        let mut subscription = Subscription {
+            events: vec!["job.failed".to_string(), "alert.*".to_string()],
+            ..Default::default()
+        };
+        assert!(subscription.wants("job.failed"));
+        assert!(subscription.wants("alert.queue_depth.resolved"));
+        assert!(!subscription.wants("job.completed"));
+        assert!(!subscription.wants("job.failed.twice"));
+
+        subscription.events.clear();
+        assert!(subscription.wants("job.completed"));
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_new_with_sender_batch() {
+        cleanup_env(&["SENDER_BATCH_SIZE"]);
+        assert_eq!(Config::new().unwrap().sender_batch, DEFAULT_SENDER_BATCH);
+
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe { env::set_var("SENDER_BATCH_SIZE", "0") };
+        let config = Config::new();
+        cleanup_env(&["SENDER_BATCH_SIZE"]);
+        assert_eq!(config.unwrap().sender_batch, 1);
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_new_with_probes() {
+        let keys = ["PROBE_INTERVAL", "PROBE_TIMEOUT", "PROBE_DOWN_AFTER"];
+        cleanup_env(&keys);
+        assert_eq!(Config::new().unwrap().probes, ProbeConfig::default());
+
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var(keys[0], "10");
+            env::set_var(keys[1], "2");
+            env::set_var(keys[2], "0");
+        }
+        let config = Config::new().unwrap();
+        cleanup_env(&keys);
+        assert_eq!(
+            config.probes,
+            ProbeConfig {
+                interval: Duration::from_secs(10),
+                timeout: Duration::from_secs(2),
+                down_after: 1,
+            }
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_new_with_client_versions() {
+        let keys = [
+            "CLIENT_MIN_VERSION",
+            "CLIENT_DEPRECATED_VERSIONS",
+            "CLIENT_BLOCKED_VERSIONS",
+        ];
+        cleanup_env(&keys);
+        let policy = Config::new().unwrap().client_versions;
+        assert_eq!(policy, ClientVersionPolicy::default());
+        assert_eq!(policy.standing(None), VersionStanding::Unknown);
+        assert_eq!(policy.standing(Some("0.1.0")), VersionStanding::Current);
+
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var(keys[0], "v2.1.0");
+            env::set_var(keys[1], "2.1.0, 2.1.1");
+            env::set_var(keys[2], "2.2.0,");
+        }
+        let config = Config::new();
+        cleanup_env(&keys);
+        let policy = config.unwrap().client_versions;
+        assert_eq!(policy.min, Some(Version::new(2, 1, 0)));
+        assert_eq!(policy.standing(Some("2.0.9")), VersionStanding::Outdated);
+        assert_eq!(policy.standing(Some("2.1.1")), VersionStanding::Deprecated);
+        assert_eq!(policy.standing(Some("2.2.0")), VersionStanding::Blocked);
+        assert_eq!(policy.standing(Some("v2.2.3")), VersionStanding::Current);
+        assert_eq!(
+            policy.standing(Some("3.0.0-rc.1")),
+            VersionStanding::Current
+        );
+        // Clients reporting no version predate any minimum
+        assert_eq!(policy.standing(None), VersionStanding::Outdated);
+        assert_eq!(policy.standing(Some("latest")), VersionStanding::Outdated);
+
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe { env::set_var(keys[2], "2.2") };
+        let result = Config::new();
+        cleanup_env(&keys);
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Invalid client version 2.2"
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_new_with_upload_checks() {
+        let keys = [
+            "UPLOAD_MAX_FILE_SIZE",
+            "UPLOAD_ALLOWED_EXTENSIONS",
+            "UPLOAD_SCRIPT_DENYLIST",
+            "UPLOAD_AV_COMMAND",
+        ];
+        cleanup_env(&keys);
+        assert_eq!(
+            Config::new().unwrap().upload_checks,
+            UploadChecks::default()
+        );
+
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var(keys[0], "1048576");
+            env::set_var(keys[1], "sh, .PDB,,top");
+            env::set_var(keys[2], "curl ,wget");
+            env::set_var(keys[3], "clamscan -r --no-summary");
+        }
+        let config = Config::new().unwrap();
+        cleanup_env(&keys);
+        assert_eq!(
+            config.upload_checks,
+            UploadChecks {
+                max_file_size: Some(1048576),
+                allowed_extensions: vec!["sh".to_string(), "pdb".to_string(), "top".to_string()],
+                script_denylist: vec!["curl".to_string(), "wget".to_string()],
+                av_command: Some("clamscan -r --no-summary".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_new_with_body_limits() {
+        let keys = ["MAX_UPLOAD_SIZE", "MAX_BODY_SIZE", "MAX_SUBMIT_FIELDS"];
+        cleanup_env(&keys);
+        assert_eq!(Config::new().unwrap().body_limits, BodyLimits::default());
+
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var(keys[0], "1073741824");
+            env::set_var(keys[1], "65536");
+            env::set_var(keys[2], "50");
+        }
+        let config = Config::new().unwrap();
+        cleanup_env(&keys);
+        assert_eq!(
+            config.body_limits,
+            BodyLimits {
+                upload: 1073741824,
+                default: 65536,
+                submit_fields: 50,
+            }
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_new_with_cors() {
+        let keys = [
+            "CORS_ALLOWED_ORIGINS",
+            "CORS_ALLOWED_METHODS",
+            "CORS_ALLOWED_HEADERS",
+            "CORS_MAX_AGE",
+        ];
+        cleanup_env(&keys);
+        assert_eq!(Config::new().unwrap().cors, None);
+
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var(keys[0], "https://app.example.org, https://lab.example.org");
+            env::set_var(keys[2], "*");
+        }
+        let config = Config::new().unwrap();
+        assert_eq!(
+            config.cors,
+            Some(CorsConfig {
+                allowed_origins: vec![
+                    "https://app.example.org".to_string(),
+                    "https://lab.example.org".to_string()
+                ],
+                allowed_methods: ["GET", "POST", "PATCH", "PUT", "DELETE"]
+                    .map(String::from)
+                    .to_vec(),
+                allowed_headers: vec!["*".to_string()],
+                max_age: Duration::from_secs(3600),
+            })
+        );
+
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var(keys[1], "GET,NOT A METHOD");
+        }
+        let result = Config::new();
+        cleanup_env(&keys);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_new_with_remote_inputs() {
+        let keys = [
+            "REMOTE_INPUT_HOSTS",
+            "REMOTE_INPUT_SCHEMES",
+            "REMOTE_INPUT_MAX_SIZE",
+            "REMOTE_INPUT_MAX_TOTAL",
+        ];
+        cleanup_env(&keys);
+        assert_eq!(Config::new().unwrap().remote_inputs, None);
+
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var(keys[0], "files.rcsb.org, *.ebi.ac.uk");
+            env::set_var(keys[2], "1048576");
+        }
+        let remote = Config::new().unwrap().remote_inputs.unwrap();
+        assert_eq!(remote.hosts, vec!["files.rcsb.org", "*.ebi.ac.uk"]);
+        assert_eq!(remote.schemes, vec!["https"]);
+        assert_eq!(remote.max_size, 1048576);
+        assert_eq!(remote.max_total, 4 * 1024 * 1024 * 1024);
+
+        let url = |u: &str| reqwest::Url::parse(u).unwrap();
+        assert!(remote.allows(&url("https://files.rcsb.org/download/1abc.pdb")));
+        assert!(remote.allows(&url("https://ftp.EBI.ac.uk/pub/x.fa")));
+        assert!(remote.allows(&url("https://ebi.ac.uk/x.fa")));
+        assert!(!remote.allows(&url("http://files.rcsb.org/download/1abc.pdb")));
+        assert!(!remote.allows(&url("https://files.rcsb.org.evil.com/1abc.pdb")));
+        assert!(!remote.allows(&url("https://notebi.ac.uk/x.fa")));
+        assert!(!remote.allows(&url("file:///etc/passwd")));
+
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var(keys[1], "https,ftp");
+        }
+        let result = Config::new();
+        cleanup_env(&keys);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_tier_defaults() {
+        let mut config = Config::default();
+        config.tiers.insert(
+            "priority".to_string(),
+            Tier {
+                name: "priority".to_string(),
+                weight: 4,
+                max_runs: Some(2),
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(config.tier_weight("priority"), 4);
+        assert_eq!(config.tier_max_runs("priority"), Some(2));
+        assert_eq!(config.tier_weight("standard"), 1);
+        assert_eq!(config.tier_max_runs("standard"), None);
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_new_with_admin_token() {
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var("ADMIN_TOKEN", "s3cret");
+        }
+        let config = Config::new().unwrap();
+        cleanup_env(&["ADMIN_TOKEN"]);
+
+        let token = config.admin_token.expect("admin token should be set");
+        assert_eq!(token.expose(), "s3cret");
+        assert!(!format!("{token:?}").contains("s3cret"));
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_new_with_management_allowlist() {
+        let keys = ["MANAGEMENT_ALLOWED_IPS", "TRUSTED_PROXIES"];
+        cleanup_env(&keys);
+        assert_eq!(Config::new().unwrap().management_allowlist, None);
+
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var(keys[0], "10.0.0.0/8, 192.168.1.7,fd00::/8");
+            env::set_var(keys[1], "10.0.0.2");
+        }
+        let config = Config::new().unwrap();
+        assert_eq!(
+            config.management_allowlist,
+            Some(IpAllowlist {
+                allowed: vec![
+                    "10.0.0.0/8".parse().unwrap(),
+                    "192.168.1.7/32".parse().unwrap(),
+                    "fd00::/8".parse().unwrap(),
+                ],
+                trusted_proxies: vec!["10.0.0.2/32".parse().unwrap()],
+            })
+        );
+
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var(keys[0], "10.0.0.0/33");
+        }
+        let result = Config::new();
+        cleanup_env(&keys);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_new_with_download_links() {
+        let keys = [
+            "DOWNLOAD_LINK_SECRET",
+            "DOWNLOAD_LINK_MAX_TTL",
+            "DOWNLOAD_LINK_BASE_URL",
+        ];
+        cleanup_env(&keys);
+        assert_eq!(Config::new().unwrap().download_links, None);
+
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var(keys[0], "link-key");
+            env::set_var(keys[2], "https://orchestrator.example.org/");
+        }
+        let config = Config::new().unwrap();
+        cleanup_env(&keys);
+        assert_eq!(
+            config.download_links,
+            Some(DownloadLinkConfig {
+                secret: Secret::new("link-key"),
+                max_ttl: Duration::from_secs(7 * 86400),
+                base_url: Some("https://orchestrator.example.org".to_string()),
+            })
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_new_with_access_log() {
+        cleanup_env(&["ACCESS_LOG"]);
+        assert!(Config::new().unwrap().access_log);
+
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var("ACCESS_LOG", "false");
+        }
+        let config = Config::new().unwrap();
+        cleanup_env(&["ACCESS_LOG"]);
+        assert!(!config.access_log);
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_new_with_reconcile_payloads() {
+        cleanup_env(&["RECONCILE_PAYLOADS"]);
+        assert!(Config::new().unwrap().reconcile_payloads);
+
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var("RECONCILE_PAYLOADS", "false");
+        }
+        let config = Config::new().unwrap();
+        cleanup_env(&["RECONCILE_PAYLOADS"]);
+        assert!(!config.reconcile_payloads);
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_new_with_oidc() {
+        let keys = [
+            "OIDC_ISSUER",
+            "OIDC_CLIENT_ID",
+            "OIDC_CLIENT_SECRET",
+            "OIDC_REDIRECT_URL",
+            "OIDC_ALLOWED_EMAILS",
+        ];
+        cleanup_env(&keys);
+        assert_eq!(Config::new().unwrap().oidc, None);
+
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var(keys[0], "https://sso.example.org");
+            env::set_var(keys[1], "orchestrator");
+        }
+        // The secret and redirect URL are missing
+        assert!(Config::new().is_err());
+
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var(keys[2], "client-secret");
+            env::set_var(keys[3], "https://orchestrator.example.org/auth/callback");
+        }
+        // Nobody would be kept out
+        assert!(Config::new().is_err());
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var(keys[4], " , ");
+        }
+        assert!(Config::new().is_err());
+
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var(keys[4], "Alice@example.org, @ops.example.org");
+        }
+        let config = Config::new().unwrap();
+        cleanup_env(&keys);
+        assert_eq!(
+            config.oidc,
+            Some(OidcConfig {
+                issuer: "https://sso.example.org".to_string(),
+                client_id: "orchestrator".to_string(),
+                client_secret: Secret::new("client-secret"),
+                redirect_url: "https://orchestrator.example.org/auth/callback".to_string(),
+                allowed_emails: vec![
+                    "alice@example.org".to_string(),
+                    "@ops.example.org".to_string()
+                ],
+                session_ttl: Duration::from_secs(8 * 3600),
+            })
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_new_without_admin_token() {
+        cleanup_env(&["ADMIN_TOKEN"]);
+        let config = Config::new().unwrap();
+        assert!(config.admin_token.is_none());
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_new_with_service_auth() {
+        let keys = [
+            "SERVICE_AUTHED_UPLOAD_URL",
+            "SERVICE_AUTHED_AUTH_BEARER",
+            "SERVICE_AUTHED_AUTH_HEADER_NAME",
+            "SERVICE_AUTHED_AUTH_HEADER_VALUE",
+            "SERVICE_BASIC_UPLOAD_URL",
+            "SERVICE_BASIC_AUTH_USERNAME",
+            "SERVICE_BASIC_AUTH_PASSWORD",
+        ];
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var(keys[0], "http://authed.com/upload");
+            env::set_var(keys[1], "tok");
+            env::set_var(keys[2], "X-API-Key");
+            env::set_var(keys[3], "key");
+            env::set_var(keys[4], "http://basic.com/upload");
+            env::set_var(keys[5], "alice");
+            env::set_var(keys[6], "hunter2");
+        }
+        let config = Config::new().unwrap();
+        cleanup_env(&keys);
+
+        let auth = &config.services["authed"].auth;
+        assert_eq!(auth.bearer_token, Some(Secret::new("tok")));
+        assert_eq!(auth.header_name.as_deref(), Some("X-API-Key"));
+        assert_eq!(auth.header_value, Some(Secret::new("key")));
+        let auth = &config.services["basic"].auth;
+        assert_eq!(auth.username.as_deref(), Some("alice"));
+        assert_eq!(auth.password, Some(Secret::new("hunter2")));
+
+        // Credentials never show up when the config is logged
+        let printed = format!("{config:?}");
+        assert!(!printed.contains("tok\""));
+        assert!(!printed.contains("hunter2"));
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_new_rejects_bearer_and_basic_auth() {
+        let keys = [
+            "SERVICE_AUTHED_UPLOAD_URL",
+            "SERVICE_AUTHED_AUTH_BEARER",
+            "SERVICE_AUTHED_AUTH_USERNAME",
+        ];
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var(keys[0], "http://authed.com/upload");
+            env::set_var(keys[1], "tok");
+            env::set_var(keys[2], "alice");
+        }
+        let result = Config::new();
+        cleanup_env(&keys);
+
+        let err = result.unwrap_err().to_string();
+        assert!(err.contains("SERVICE_AUTHED_AUTH_BEARER"), "{err}");
+    }
+
+    #[test]
+    fn test_service_default_has_no_auth() {
+        assert_eq!(Service::default().auth, ServiceAuth::default());
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_new_with_http_settings() {
+        let keys = [
+            "HTTP_CONNECT_TIMEOUT",
+            "HTTP_READ_TIMEOUT",
+            "HTTP_RETRIES",
+            "HTTP_CA_BUNDLE",
+            "HTTP_UPLOAD_ENCODING",
+        ];
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe {
+            env::set_var(keys[0], "3");
+            env::set_var(keys[1], "30");
+            env::set_var(keys[2], "0");
+            env::set_var(keys[3], "/etc/ssl/internal.pem");
+            env::set_var(keys[4], "zstd");
+        }
+        let config = Config::new().unwrap();
+        cleanup_env(&keys);
+
+        assert_eq!(config.http.connect_timeout, Duration::from_secs(3));
+        assert_eq!(config.http.read_timeout, Duration::from_secs(30));
+        assert_eq!(config.http.retries, 0);
+        assert_eq!(
+            config.http.ca_bundle,
+            Some(PathBuf::from("/etc/ssl/internal.pem"))
+        );
+        assert_eq!(config.http.upload_encoding, Some(Encoding::Zstd));
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_new_default_http_settings() {
+        cleanup_env(&[
+            "HTTP_CONNECT_TIMEOUT",
+            "HTTP_READ_TIMEOUT",
+            "HTTP_RETRIES",
+            "HTTP_CA_BUNDLE",
+            "HTTP_UPLOAD_ENCODING",
+        ]);
+        let config = Config::new().unwrap();
+        assert_eq!(config.http, HttpConfig::default());
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_new_invalid_http_timeout() {
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe { env::set_var("HTTP_READ_TIMEOUT", "soon") };
+        let result = Config::new();
+        cleanup_env(&["HTTP_READ_TIMEOUT"]);
+        assert!(result.is_err());
+
+        // SAFETY: serial test — no concurrent env mutation
+        unsafe { env::set_var("HTTP_UPLOAD_ENCODING", "brotli") };
+        let result = Config::new();
+        cleanup_env(&["HTTP_UPLOAD_ENCODING"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_new_invalid_service_header() {
+        for (key, value) in [
+            ("SERVICE_FOO_HEADER_", "gpu"),
+            ("SERVICE_FOO_HEADER_X_ROUTE", "gpu\n"),
+            ("SERVICE_FOO_USER_AGENT", "foo\u{7f}"),
+        ] {
+            // SAFETY: serial test — no concurrent env mutation
+            unsafe { env::set_var(key, value) };
+            let result = Config::new();
+            cleanup_env(&[key]);
+            assert!(result.is_err(), "{key}");
+        }
+    }
 }
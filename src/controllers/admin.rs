@@ -0,0 +1,1127 @@
+use crate::config::loader::Config;
+use crate::models::job_dao::Job;
+use crate::models::job_event_dao::ADMIN_ACTOR;
+use crate::models::job_note_dao::JobNote;
+use crate::models::queue_dao::QueueSnapshot;
+use crate::models::service_probe_dao::ServiceProbe;
+use crate::models::status_body::StatusBody;
+use crate::models::status_dto::Status;
+use crate::models::task_run_dao::{SchedulerStats, TaskRun};
+use crate::models::user_dao::User;
+use crate::routes::router::AppState;
+use crate::services::{server, webhook};
+use crate::utils::session::SessionStore;
+use axum::response::{IntoResponse, Response};
+use axum::{
+    extract::{Json, Path, State},
+    http::{HeaderMap, StatusCode, header},
+};
+use serde::Deserialize;
+use utoipa::{self, ToSchema};
+
+/// Statuses an operator may force a job into
+const FORCEABLE: [Status; 3] = [Status::Failed, Status::Completed, Status::Cleaned];
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ForceStatus {
+    pub status: Status,
+    pub reason: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct RejectJob {
+    /// Shown to the user, and sent with the callback of the job
+    pub reason: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct AddNote {
+    /// Who is writing, the admin token is shared so it is taken as given
+    pub author: String,
+    pub text: String,
+}
+
+/// Whether the request claims to come from an operator, with a token or a
+/// session cookie, which [`reject_unauthorized`] then checks
+pub fn claims_admin(headers: &HeaderMap, sessions: &SessionStore) -> bool {
+    headers.contains_key(header::AUTHORIZATION) || sessions.identify(headers).is_some()
+}
+
+/// Check the `Authorization: Bearer <ADMIN_TOKEN>` header or the single
+/// sign-on session, returning the rejection to send back when the request is
+/// not allowed
+pub fn reject_unauthorized(
+    headers: &HeaderMap,
+    config: &Config,
+    sessions: &SessionStore,
+) -> Option<Response> {
+    let mut body = StatusBody::new();
+
+    if config.admin_token.is_none() && config.oidc.is_none() {
+        body.message =
+            "Admin endpoints are disabled, set ADMIN_TOKEN or OIDC_ISSUER to enable them"
+                .to_string();
+        return Some((StatusCode::FORBIDDEN, Json(body)).into_response());
+    }
+
+    if config.oidc.is_some() && sessions.identify(headers).is_some() {
+        return None;
+    }
+
+    let provided = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+
+    match (provided, &config.admin_token) {
+        (Some(p), Some(token)) if constant_time_eq(p.as_bytes(), token.expose().as_bytes()) => None,
+        _ => {
+            body.message = "Invalid or missing admin token or session".to_string();
+            Some(
+                (
+                    StatusCode::UNAUTHORIZED,
+                    [(header::WWW_AUTHENTICATE, "Bearer")],
+                    Json(body),
+                )
+                    .into_response(),
+            )
+        }
+    }
+}
+
+/// Compare without short-circuiting so the token can't be guessed byte by byte
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/jobs/{id}/status",
+    params(
+        ("id" = u32, Path, description = "Job identifier")
+    ),
+    request_body = ForceStatus,
+    responses(
+        (status = 200, description = "Job status forced", body = StatusBody),
+        (status = 400, description = "Missing reason or status not allowed", body = StatusBody),
+        (status = 401, description = "Invalid or missing admin token", body = StatusBody),
+        (status = 403, description = "Admin endpoints disabled", body = StatusBody),
+        (status = 404, description = "Not found", body = StatusBody),
+        (status = 500, description = "Internal server error", body = StatusBody),
+    ),
+    security(("admin_token" = [])),
+    tag = "admin"
+)]
+pub async fn force_status(
+    State(state): State<AppState>,
+    Path(id): Path<u32>,
+    headers: HeaderMap,
+    Json(request): Json<ForceStatus>,
+) -> Response {
+    if let Some(response) = reject_unauthorized(&headers, &state.config, &state.sessions) {
+        return response;
+    }
+
+    let mut body = StatusBody::new();
+
+    if !FORCEABLE.contains(&request.status) {
+        body.message = format!(
+            "Status {} cannot be forced, use one of: failed, completed, cleaned",
+            request.status
+        );
+        return (StatusCode::BAD_REQUEST, Json(body)).into_response();
+    }
+
+    let reason = request.reason.trim();
+    if reason.is_empty() {
+        body.message = "A reason is required".to_string();
+        return (StatusCode::BAD_REQUEST, Json(body)).into_response();
+    }
+
+    let mut job = Job::new(&state.config.data_path);
+    if let Err(e) = job.retrieve_id(id, &state.pool).await {
+        let status = match e {
+            sqlx::Error::RowNotFound => {
+                body.message = format!("Job {id} not found in the database");
+                StatusCode::NOT_FOUND
+            }
+            _ => {
+                body.message = "Internal server error".to_string();
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+        return (status, Json(body)).into_response();
+    }
+
+    let old_status = job.status;
+
+    if let Err(e) = job
+        .update_status_by(request.status, ADMIN_ACTOR, Some(reason), &state.pool)
+        .await
+    {
+        tracing::error!("Could not force the status of job {id}: {:?}", e);
+        body.message = format!("Could not update the status of job {id}");
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response();
+    }
+
+    tracing::warn!(
+        "Job {id} forced from {old_status} to {} by {ADMIN_ACTOR}: {reason}",
+        request.status
+    );
+
+    if let Err(e) = webhook::enqueue(&job, &state.config.subscriptions, &state.pool).await {
+        tracing::error!("Could not queue the callback of job {id}: {:?}", e);
+    }
+
+    // A cleaned job must not leave files behind
+    if request.status == Status::Cleaned
+        && job.loc.exists()
+        && let Err(e) = job.remove_from_disk()
+    {
+        tracing::error!("Could not remove {:?}: {:?}", job.loc, e);
+    }
+
+    body.id = job.id;
+    body.status = job.status;
+    body.message = format!("Job forced from {old_status} to {}", job.status);
+    Json(body).into_response()
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/jobs/{id}/notes",
+    params(
+        ("id" = u32, Path, description = "Job identifier")
+    ),
+    request_body = AddNote,
+    responses(
+        (status = 201, description = "Note added", body = JobNote),
+        (status = 400, description = "Missing author or text", body = StatusBody),
+        (status = 401, description = "Invalid or missing admin token", body = StatusBody),
+        (status = 403, description = "Admin endpoints disabled", body = StatusBody),
+        (status = 404, description = "Not found", body = StatusBody),
+        (status = 500, description = "Internal server error", body = StatusBody),
+    ),
+    security(("admin_token" = [])),
+    tag = "admin"
+)]
+pub async fn add_job_note(
+    State(state): State<AppState>,
+    Path(id): Path<u32>,
+    headers: HeaderMap,
+    Json(request): Json<AddNote>,
+) -> Response {
+    if let Some(response) = reject_unauthorized(&headers, &state.config, &state.sessions) {
+        return response;
+    }
+
+    let mut body = StatusBody::new();
+
+    let (author, text) = (request.author.trim(), request.text.trim());
+    if author.is_empty() || text.is_empty() {
+        body.message = "An author and a text are required".to_string();
+        return (StatusCode::BAD_REQUEST, Json(body)).into_response();
+    }
+
+    let mut job = Job::new(&state.config.data_path);
+    if let Err(e) = job.retrieve_id(id, &state.pool).await {
+        let status = match e {
+            sqlx::Error::RowNotFound => {
+                body.message = format!("Job {id} not found in the database");
+                StatusCode::NOT_FOUND
+            }
+            _ => {
+                body.message = "Internal server error".to_string();
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+        return (status, Json(body)).into_response();
+    }
+
+    let mut note = JobNote::new(job.id, author, text);
+    if let Err(e) = note.add_to_db(&state.pool).await {
+        tracing::error!("Could not add a note to job {id}: {:?}", e);
+        body.message = format!("Could not add a note to job {id}");
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response();
+    }
+
+    tracing::info!("Note added to job {id} by {author}");
+    (StatusCode::CREATED, Json(note)).into_response()
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/users/{id}/release",
+    params(
+        ("id" = u32, Path, description = "User identifier")
+    ),
+    responses(
+        (status = 200, description = "Held jobs put back in the queue", body = StatusBody),
+        (status = 401, description = "Invalid or missing admin token", body = StatusBody),
+        (status = 403, description = "Admin endpoints disabled", body = StatusBody),
+        (status = 404, description = "Not found", body = StatusBody),
+        (status = 409, description = "User is still suspended", body = StatusBody),
+        (status = 500, description = "Internal server error", body = StatusBody),
+    ),
+    security(("admin_token" = [])),
+    tag = "admin"
+)]
+pub async fn release_user_jobs(
+    State(state): State<AppState>,
+    Path(id): Path<u32>,
+    headers: HeaderMap,
+) -> Response {
+    if let Some(response) = reject_unauthorized(&headers, &state.config, &state.sessions) {
+        return response;
+    }
+
+    let mut body = StatusBody::new();
+
+    let user = match User::retrieve_id(id, &state.pool).await {
+        Ok(u) => u,
+        Err(sqlx::Error::RowNotFound) => {
+            body.message = format!("User {id} not found in the database");
+            return (StatusCode::NOT_FOUND, Json(body)).into_response();
+        }
+        Err(e) => {
+            tracing::error!("Could not retrieve user {id}: {:?}", e);
+            body.message = "Internal server error".to_string();
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response();
+        }
+    };
+
+    // Releasing would only have them held again, restore the account first
+    if !user.enabled {
+        body.message = format!("User {id} is suspended, enable it before releasing its jobs");
+        return (StatusCode::CONFLICT, Json(body)).into_response();
+    }
+
+    match Job::release_held(id, &state.pool).await {
+        Ok(n) => {
+            tracing::info!("Released {n} held jobs of user {id}");
+            body.message = format!("Released {n} held jobs");
+            Json(body).into_response()
+        }
+        Err(e) => {
+            tracing::error!("Could not release the jobs of user {id}: {:?}", e);
+            body.message = format!("Could not release the jobs of user {id}");
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/queue",
+    responses(
+        (status = 200, description = "State of the scheduler", body = QueueSnapshot),
+        (status = 401, description = "Invalid or missing admin token", body = StatusBody),
+        (status = 403, description = "Admin endpoints disabled", body = StatusBody),
+        (status = 500, description = "Internal server error", body = StatusBody),
+    ),
+    security(("admin_token" = [])),
+    tag = "admin"
+)]
+pub async fn queue_snapshot(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    if let Some(response) = reject_unauthorized(&headers, &state.config, &state.sessions) {
+        return response;
+    }
+
+    match server::queue_snapshot(&state.pool, &state.config, &state.client).await {
+        Ok(snapshot) => Json(snapshot).into_response(),
+        Err(e) => {
+            tracing::error!("Could not take a snapshot of the queue: {:?}", e);
+            let mut body = StatusBody::new();
+            body.message = "Internal server error".to_string();
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/stats",
+    responses(
+        (status = 200, description = "Recent rounds of the scheduled tasks, availability of the services and versions of their clients", body = SchedulerStats),
+        (status = 401, description = "Invalid or missing admin token", body = StatusBody),
+        (status = 403, description = "Admin endpoints disabled", body = StatusBody),
+        (status = 500, description = "Internal server error", body = StatusBody),
+    ),
+    security(("admin_token" = [])),
+    tag = "admin"
+)]
+pub async fn scheduler_stats(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    if let Some(response) = reject_unauthorized(&headers, &state.config, &state.sessions) {
+        return response;
+    }
+
+    let stats = async {
+        Ok::<_, sqlx::Error>(SchedulerStats {
+            tasks: TaskRun::stats(&state.pool).await?,
+            services: ServiceProbe::availability(&state.pool, state.config.probes.down_after)
+                .await?,
+            client_versions: ServiceProbe::version_spread(
+                &state.pool,
+                &state.config.client_versions,
+            )
+            .await?,
+        })
+    };
+    match stats.await {
+        Ok(stats) => Json(stats).into_response(),
+        Err(e) => {
+            tracing::error!("Could not read the scheduler stats: {:?}", e);
+            let mut body = StatusBody::new();
+            body.message = "Internal server error".to_string();
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/jobs/held",
+    responses(
+        (status = 200, description = "Jobs held for review, oldest first", body = Vec<Job>),
+        (status = 401, description = "Invalid or missing admin token", body = StatusBody),
+        (status = 403, description = "Admin endpoints disabled", body = StatusBody),
+        (status = 500, description = "Internal server error", body = StatusBody),
+    ),
+    security(("admin_token" = [])),
+    tag = "admin"
+)]
+pub async fn list_held_jobs(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    if let Some(response) = reject_unauthorized(&headers, &state.config, &state.sessions) {
+        return response;
+    }
+
+    match Job::list_in_review(&state.pool).await {
+        Ok(jobs) => Json(jobs).into_response(),
+        Err(e) => {
+            tracing::error!("Could not list the jobs held for review: {:?}", e);
+            let mut body = StatusBody::new();
+            body.message = "Internal server error".to_string();
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response()
+        }
+    }
+}
+
+/// The job `id`, provided it is held for review
+async fn job_in_review(state: &AppState, id: u32) -> Result<Job, (StatusCode, String)> {
+    let mut job = Job::new(&state.config.data_path);
+    match job.retrieve_id(id, &state.pool).await {
+        Ok(_) if job.status == Status::Held && job.review_reason.is_some() => Ok(job),
+        Ok(_) => Err((
+            StatusCode::CONFLICT,
+            format!("Job {id} is not held for review"),
+        )),
+        Err(sqlx::Error::RowNotFound) => Err((
+            StatusCode::NOT_FOUND,
+            format!("Job {id} not found in the database"),
+        )),
+        Err(e) => {
+            tracing::error!("Could not retrieve job {id}: {:?}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Internal server error".to_string(),
+            ))
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/jobs/{id}/approve",
+    params(
+        ("id" = u32, Path, description = "Job identifier")
+    ),
+    responses(
+        (status = 200, description = "Job queued", body = StatusBody),
+        (status = 401, description = "Invalid or missing admin token", body = StatusBody),
+        (status = 403, description = "Admin endpoints disabled", body = StatusBody),
+        (status = 404, description = "Not found", body = StatusBody),
+        (status = 409, description = "Job is not held for review", body = StatusBody),
+        (status = 500, description = "Internal server error", body = StatusBody),
+    ),
+    security(("admin_token" = [])),
+    tag = "admin"
+)]
+pub async fn approve_job(
+    State(state): State<AppState>,
+    Path(id): Path<u32>,
+    headers: HeaderMap,
+) -> Response {
+    if let Some(response) = reject_unauthorized(&headers, &state.config, &state.sessions) {
+        return response;
+    }
+
+    let mut body = StatusBody::new();
+    let mut job = match job_in_review(&state, id).await {
+        Ok(job) => job,
+        Err((status, message)) => {
+            body.message = message;
+            return (status, Json(body)).into_response();
+        }
+    };
+    let flagged = job.review_reason.clone().unwrap_or_default();
+
+    // The job of a suspended user stays held, until the user is released
+    let status = match User::retrieve_id(job.user_id as u32, &state.pool).await {
+        Ok(user) if !user.enabled => Status::Held,
+        _ => Status::Queued,
+    };
+    let note = format!("approved, flagged as: {flagged}");
+    if let Err(e) = job
+        .update_review(status, None, ADMIN_ACTOR, &note, &state.pool)
+        .await
+    {
+        tracing::error!("Could not approve job {id}: {:?}", e);
+        body.message = format!("Could not update the status of job {id}");
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response();
+    }
+
+    tracing::info!("Job {id} approved by {ADMIN_ACTOR}");
+    body.id = job.id;
+    body.status = job.status;
+    body.message = format!("Job approved, now {}", job.status);
+    Json(body).into_response()
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/jobs/{id}/reject",
+    params(
+        ("id" = u32, Path, description = "Job identifier")
+    ),
+    request_body = RejectJob,
+    responses(
+        (status = 200, description = "Job marked invalid", body = StatusBody),
+        (status = 400, description = "Missing reason", body = StatusBody),
+        (status = 401, description = "Invalid or missing admin token", body = StatusBody),
+        (status = 403, description = "Admin endpoints disabled", body = StatusBody),
+        (status = 404, description = "Not found", body = StatusBody),
+        (status = 409, description = "Job is not held for review", body = StatusBody),
+        (status = 500, description = "Internal server error", body = StatusBody),
+    ),
+    security(("admin_token" = [])),
+    tag = "admin"
+)]
+pub async fn reject_job(
+    State(state): State<AppState>,
+    Path(id): Path<u32>,
+    headers: HeaderMap,
+    Json(request): Json<RejectJob>,
+) -> Response {
+    if let Some(response) = reject_unauthorized(&headers, &state.config, &state.sessions) {
+        return response;
+    }
+
+    let mut body = StatusBody::new();
+
+    let reason = request.reason.trim();
+    if reason.is_empty() {
+        body.message = "A reason is required".to_string();
+        return (StatusCode::BAD_REQUEST, Json(body)).into_response();
+    }
+
+    let mut job = match job_in_review(&state, id).await {
+        Ok(job) => job,
+        Err((status, message)) => {
+            body.message = message;
+            return (status, Json(body)).into_response();
+        }
+    };
+
+    // The reason replaces the flag, it is what the user gets to see
+    if let Err(e) = job
+        .update_review(
+            Status::Invalid,
+            Some(reason.to_string()),
+            ADMIN_ACTOR,
+            reason,
+            &state.pool,
+        )
+        .await
+    {
+        tracing::error!("Could not reject job {id}: {:?}", e);
+        body.message = format!("Could not update the status of job {id}");
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response();
+    }
+
+    if let Err(e) = webhook::enqueue(&job, &state.config.subscriptions, &state.pool).await {
+        tracing::error!("Could not queue the callback of job {id}: {:?}", e);
+    }
+
+    tracing::warn!("Job {id} rejected by {ADMIN_ACTOR}: {reason}");
+    body.id = job.id;
+    body.status = job.status;
+    body.message = reason.to_string();
+    Json(body).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::loader::{OidcConfig, Secret};
+    use crate::models::job_dto::create_jobs_table;
+    use crate::models::job_event_dao::SERVER_ACTOR;
+    use crate::models::task_run_dao::{GETTER_TASK, TaskTally};
+    use crate::routes::router::create_routes;
+    use crate::services::client::Client;
+    use crate::utils::session::{Identity, SESSION_COOKIE};
+    use axum::body::Body;
+    use axum::http::Request;
+    use sqlx::{Row, SqlitePool};
+    use std::time::Duration;
+    use tempfile::TempDir;
+    use tower::ServiceExt;
+
+    const TOKEN: &str = "test-token";
+
+    async fn setup_test_db() -> SqlitePool {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        create_jobs_table(&pool).await.unwrap();
+        User::add_if_missing(0, &pool).await.unwrap();
+        pool
+    }
+
+    fn make_config(data_path: &str) -> Config {
+        Config {
+            data_path: data_path.to_string(),
+            admin_token: Some(Secret::new(TOKEN)),
+            ..Default::default()
+        }
+    }
+
+    async fn add_job(pool: &SqlitePool, data_path: &str, status: Status) -> Job {
+        let mut job = Job::new(data_path);
+        std::fs::create_dir_all(&job.loc).unwrap();
+        job.add_to_db(pool).await.unwrap();
+        job.update_status(status, pool).await.unwrap();
+        job
+    }
+
+    fn force_request(id: u32, token: Option<&str>, body: &str) -> Request<Body> {
+        let mut builder = Request::builder()
+            .method("POST")
+            .uri(format!("/admin/jobs/{id}/status"))
+            .header("content-type", "application/json");
+        if let Some(t) = token {
+            builder = builder.header("authorization", format!("Bearer {t}"));
+        }
+        builder.body(Body::from(body.to_string())).unwrap()
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"abc", b"abc"));
+        assert!(!constant_time_eq(b"abc", b"abd"));
+        assert!(!constant_time_eq(b"abc", b"abcd"));
+    }
+
+    #[test]
+    fn test_reject_unauthorized() {
+        let config = make_config("");
+        let sessions = SessionStore::default();
+        let mut headers = HeaderMap::new();
+        assert!(reject_unauthorized(&headers, &config, &sessions).is_some());
+
+        headers.insert(header::AUTHORIZATION, "Bearer wrong".parse().unwrap());
+        assert!(reject_unauthorized(&headers, &config, &sessions).is_some());
+
+        headers.insert(
+            header::AUTHORIZATION,
+            format!("Bearer {TOKEN}").parse().unwrap(),
+        );
+        assert!(reject_unauthorized(&headers, &config, &sessions).is_none());
+
+        // Disabled when no token is configured, even with a header
+        assert!(reject_unauthorized(&headers, &Config::default(), &sessions).is_some());
+    }
+
+    #[test]
+    fn test_reject_unauthorized_session() {
+        let config = Config {
+            oidc: Some(OidcConfig {
+                issuer: "https://sso.example.org".to_string(),
+                client_id: "orchestrator".to_string(),
+                client_secret: Secret::new("client-secret"),
+                redirect_url: "https://orchestrator.example.org/auth/callback".to_string(),
+                allowed_emails: Vec::new(),
+                session_ttl: Duration::from_secs(3600),
+            }),
+            ..Default::default()
+        };
+        let sessions = SessionStore::default();
+        let mut headers = HeaderMap::new();
+        assert!(!claims_admin(&headers, &sessions));
+        assert!(reject_unauthorized(&headers, &config, &sessions).is_some());
+
+        headers.insert(
+            header::COOKIE,
+            format!("{SESSION_COOKIE}=forged").parse().unwrap(),
+        );
+        assert!(!claims_admin(&headers, &sessions));
+        assert!(reject_unauthorized(&headers, &config, &sessions).is_some());
+
+        let identity = Identity {
+            subject: "abc".to_string(),
+            email: None,
+            name: None,
+        };
+        let token = sessions.create(identity, Duration::from_secs(60));
+        headers.insert(
+            header::COOKIE,
+            format!("{SESSION_COOKIE}={token}").parse().unwrap(),
+        );
+        assert!(claims_admin(&headers, &sessions));
+        assert!(reject_unauthorized(&headers, &config, &sessions).is_none());
+
+        // Enabled by single sign-on alone, the admin token is still refused
+        headers.remove(header::COOKIE);
+        headers.insert(header::AUTHORIZATION, "Bearer anything".parse().unwrap());
+        assert!(reject_unauthorized(&headers, &config, &sessions).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_force_status_failed() {
+        let tempdir = TempDir::new().unwrap();
+        let data_path = tempdir.path().to_str().unwrap();
+        let pool = setup_test_db().await;
+        let job = add_job(&pool, data_path, Status::Running).await;
+        let app = create_routes(pool.clone(), make_config(data_path), Client::default());
+
+        let response = app
+            .oneshot(force_request(
+                job.id,
+                Some(TOKEN),
+                r#"{"status": "Failed", "reason": "client was wiped"}"#,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let mut stored = Job::new("");
+        stored.retrieve_id(job.id, &pool).await.unwrap();
+        assert_eq!(stored.status, Status::Failed);
+
+        let row = sqlx::query("SELECT * FROM job_events WHERE job_id = ? AND actor = ?")
+            .bind(job.id)
+            .bind(ADMIN_ACTOR)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(row.get::<String, _>("old_status"), "running");
+        assert_eq!(row.get::<String, _>("new_status"), "failed");
+        assert_eq!(row.get::<String, _>("reason"), "client was wiped");
+        assert_eq!(row.get::<String, _>("actor"), ADMIN_ACTOR);
+    }
+
+    #[tokio::test]
+    async fn test_force_status_cleaned_removes_files() {
+        let tempdir = TempDir::new().unwrap();
+        let data_path = tempdir.path().to_str().unwrap();
+        let pool = setup_test_db().await;
+        let job = add_job(&pool, data_path, Status::Completed).await;
+        assert!(job.loc.exists());
+        let app = create_routes(pool, make_config(data_path), Client::default());
+
+        let response = app
+            .oneshot(force_request(
+                job.id,
+                Some(TOKEN),
+                r#"{"status": "Cleaned", "reason": "disk full"}"#,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!job.loc.exists());
+    }
+
+    #[tokio::test]
+    async fn test_force_status_requires_token() {
+        let tempdir = TempDir::new().unwrap();
+        let data_path = tempdir.path().to_str().unwrap();
+        let pool = setup_test_db().await;
+        let job = add_job(&pool, data_path, Status::Running).await;
+        let body = r#"{"status": "Failed", "reason": "stuck"}"#;
+
+        let app = create_routes(pool.clone(), make_config(data_path), Client::default());
+        let response = app
+            .clone()
+            .oneshot(force_request(job.id, None, body))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = app
+            .oneshot(force_request(job.id, Some("wrong"), body))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let app = create_routes(pool, Config::default(), Client::default());
+        let response = app
+            .oneshot(force_request(job.id, Some(TOKEN), body))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_force_status_validation() {
+        let tempdir = TempDir::new().unwrap();
+        let data_path = tempdir.path().to_str().unwrap();
+        let pool = setup_test_db().await;
+        let job = add_job(&pool, data_path, Status::Running).await;
+        let app = create_routes(pool, make_config(data_path), Client::default());
+
+        // Only terminal statuses can be forced
+        let response = app
+            .clone()
+            .oneshot(force_request(
+                job.id,
+                Some(TOKEN),
+                r#"{"status": "Queued", "reason": "retry"}"#,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        // A reason is mandatory
+        let response = app
+            .clone()
+            .oneshot(force_request(
+                job.id,
+                Some(TOKEN),
+                r#"{"status": "Failed", "reason": "  "}"#,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = app
+            .oneshot(force_request(
+                999,
+                Some(TOKEN),
+                r#"{"status": "Failed", "reason": "stuck"}"#,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    fn release_request(id: u32) -> Request<Body> {
+        Request::builder()
+            .method("POST")
+            .uri(format!("/admin/users/{id}/release"))
+            .header("authorization", format!("Bearer {TOKEN}"))
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_add_job_note() {
+        let tempdir = TempDir::new().unwrap();
+        let data_path = tempdir.path().to_str().unwrap();
+        let pool = setup_test_db().await;
+        let job = add_job(&pool, data_path, Status::Queued).await;
+        let app = create_routes(pool, make_config(data_path), Client::default());
+
+        let note_request = |id: u32, token: Option<&str>, body: &str| {
+            let mut builder = Request::builder()
+                .method("POST")
+                .uri(format!("/admin/jobs/{id}/notes"))
+                .header("content-type", "application/json");
+            if let Some(t) = token {
+                builder = builder.header("authorization", format!("Bearer {t}"));
+            }
+            builder.body(Body::from(body.to_string())).unwrap()
+        };
+        let note = r#"{"author": "alice", "text": "upstream service was down, requeued manually"}"#;
+
+        let response = app
+            .clone()
+            .oneshot(note_request(job.id, None, note))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = app
+            .clone()
+            .oneshot(note_request(
+                job.id,
+                Some(TOKEN),
+                r#"{"author": "alice", "text": " "}"#,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = app
+            .clone()
+            .oneshot(note_request(9999, Some(TOKEN), note))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = app
+            .clone()
+            .oneshot(note_request(job.id, Some(TOKEN), note))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        // Notes show up in the job details, for operators only
+        let detail = |token: Option<&str>| {
+            let mut builder = Request::builder().uri(format!("/jobs/{}", job.id));
+            if let Some(t) = token {
+                builder = builder.header("authorization", format!("Bearer {t}"));
+            }
+            builder.body(Body::empty()).unwrap()
+        };
+        let response = app.clone().oneshot(detail(Some(TOKEN))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["id"], job.id);
+        assert_eq!(body["notes"][0]["author"], "alice");
+        assert_eq!(
+            body["notes"][0]["text"],
+            "upstream service was down, requeued manually"
+        );
+        assert!(body["notes"][0]["created_at"].is_string());
+
+        let response = app.clone().oneshot(detail(None)).await.unwrap();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert!(body.get("notes").is_none());
+
+        let response = app.oneshot(detail(Some("wrong"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_release_user_jobs() {
+        let tempdir = TempDir::new().unwrap();
+        let data_path = tempdir.path().to_str().unwrap();
+        let pool = setup_test_db().await;
+        let job = add_job(&pool, data_path, Status::Queued).await;
+        Job::hold_queued(0, &pool).await.unwrap();
+        sqlx::query("UPDATE users SET enabled = 0 WHERE id = 0")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let app = create_routes(pool.clone(), make_config(data_path), Client::default());
+
+        // Still suspended
+        let response = app.clone().oneshot(release_request(0)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        sqlx::query("UPDATE users SET enabled = 1 WHERE id = 0")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let response = app.clone().oneshot(release_request(0)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let mut stored = Job::new("");
+        stored.retrieve_id(job.id, &pool).await.unwrap();
+        assert_eq!(stored.status, Status::Queued);
+
+        let response = app.oneshot(release_request(999)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_queue_snapshot() {
+        let tempdir = TempDir::new().unwrap();
+        let data_path = tempdir.path().to_str().unwrap();
+        let pool = setup_test_db().await;
+        let queued = add_job(&pool, data_path, Status::Queued).await;
+        add_job(&pool, data_path, Status::Completed).await;
+        let app = create_routes(pool, make_config(data_path), Client::default());
+
+        let request = |token: &str| {
+            Request::builder()
+                .uri("/admin/queue")
+                .header("authorization", format!("Bearer {token}"))
+                .body(Body::empty())
+                .unwrap()
+        };
+        let response = app.clone().oneshot(request("wrong")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = app.oneshot(request(TOKEN)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let snapshot: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let ids: Vec<u64> = snapshot["jobs"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|j| j["id"].as_u64().unwrap())
+            .collect();
+        assert_eq!(ids, vec![queued.id as u64]);
+    }
+
+    #[tokio::test]
+    async fn test_scheduler_stats() {
+        let tempdir = TempDir::new().unwrap();
+        let data_path = tempdir.path().to_str().unwrap();
+        let pool = setup_test_db().await;
+        server::tracked(GETTER_TASK, &pool, async {
+            TaskTally {
+                processed: 2,
+                errors: 1,
+            }
+        })
+        .await;
+        ServiceProbe::record("example", Err("refused".to_string()), None, &pool)
+            .await
+            .unwrap();
+        ServiceProbe::record("other", Ok(Duration::ZERO), Some("2.2.3"), &pool)
+            .await
+            .unwrap();
+        let app = create_routes(pool, make_config(data_path), Client::default());
+
+        let request = |token: &str| {
+            Request::builder()
+                .uri("/admin/stats")
+                .header("authorization", format!("Bearer {token}"))
+                .body(Body::empty())
+                .unwrap()
+        };
+        let response = app.clone().oneshot(request("wrong")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = app.oneshot(request(TOKEN)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let stats: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let getter = &stats["tasks"]["getter"];
+        assert_eq!(getter["last_run"]["processed"], 2);
+        assert_eq!(getter["errors_last_hour"], 1);
+        assert!(getter["last_run"]["finished_at"].is_string());
+        assert!(stats["tasks"].get("sender").is_none());
+        let example = &stats["services"]["example"];
+        assert_eq!(example["failures_last_hour"], 1);
+        assert_eq!(example["history"][0]["error"], "refused");
+        // One failure is not enough to skip the service
+        assert_eq!(example["up"], true);
+        assert_eq!(stats["services"]["other"]["version"], "2.2.3");
+        let versions = &stats["client_versions"];
+        assert_eq!(versions["2.2.3"]["services"], serde_json::json!(["other"]));
+        assert_eq!(versions["2.2.3"]["standing"], "current");
+        // The example service never answered, its version is not known yet
+        assert!(versions.get("unknown").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_review() {
+        let tempdir = TempDir::new().unwrap();
+        let data_path = tempdir.path().to_str().unwrap();
+        let pool = setup_test_db().await;
+        let mut approved = add_job(&pool, data_path, Status::Queued).await;
+        approved
+            .update_review(
+                Status::Held,
+                Some("network tool: curl".to_string()),
+                SERVER_ACTOR,
+                "held for review",
+                &pool,
+            )
+            .await
+            .unwrap();
+        let mut rejected = add_job(&pool, data_path, Status::Queued).await;
+        rejected
+            .update_review(
+                Status::Held,
+                Some("network tool: wget".to_string()),
+                SERVER_ACTOR,
+                "held for review",
+                &pool,
+            )
+            .await
+            .unwrap();
+        let queued = add_job(&pool, data_path, Status::Queued).await;
+        let app = create_routes(pool.clone(), make_config(data_path), Client::default());
+
+        let request = |method: &str, uri: String, body: &str| {
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("authorization", format!("Bearer {TOKEN}"))
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap()
+        };
+
+        let response = app
+            .clone()
+            .oneshot(request("GET", "/admin/jobs/held".to_string(), ""))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let held: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(held.as_array().unwrap().len(), 2);
+        assert_eq!(held[0]["review_reason"], "network tool: curl");
+
+        let approve = |id: u32| request("POST", format!("/admin/jobs/{id}/approve"), "");
+        let response = app.clone().oneshot(approve(approved.id)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = app.clone().oneshot(approve(queued.id)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let reject =
+            |id: u32, body: &str| request("POST", format!("/admin/jobs/{id}/reject"), body);
+        let response = app
+            .clone()
+            .oneshot(reject(rejected.id, r#"{"reason": " "}"#))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let response = app
+            .clone()
+            .oneshot(reject(
+                rejected.id,
+                r#"{"reason": "Downloads are not allowed"}"#,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = app.oneshot(approve(rejected.id)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let mut stored = Job::new("");
+        stored.retrieve_id(approved.id, &pool).await.unwrap();
+        assert_eq!(stored.status, Status::Queued);
+        assert_eq!(stored.review_reason, None);
+        stored.retrieve_id(rejected.id, &pool).await.unwrap();
+        assert_eq!(stored.status, Status::Invalid);
+        assert_eq!(
+            stored.review_reason.as_deref(),
+            Some("Downloads are not allowed")
+        );
+
+        let reasons: Vec<String> =
+            sqlx::query_scalar("SELECT reason FROM job_events WHERE job_id = ? AND actor = ?")
+                .bind(rejected.id)
+                .bind(ADMIN_ACTOR)
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        assert_eq!(reasons, vec!["Downloads are not allowed"]);
+    }
+}
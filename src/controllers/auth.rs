@@ -0,0 +1,373 @@
+use crate::models::status_body::StatusBody;
+use crate::routes::router::AppState;
+use crate::services::oidc;
+use crate::utils::session::{Identity, SESSION_COOKIE, session_token};
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::{
+    extract::{Json, Query, State},
+    http::{HeaderMap, StatusCode, header},
+};
+use serde::Deserialize;
+use utoipa::{self, IntoParams};
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct LoginParams {
+    /// Path to return to after logging in, e.g. `/swagger`
+    pub redirect: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct CallbackParams {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    /// Set by the provider when the login was refused or failed
+    pub error: Option<String>,
+}
+
+/// Only paths on this server, so the login can't be turned into an open redirect
+fn local_path(redirect: Option<String>) -> String {
+    redirect
+        .filter(|r| r.starts_with('/') && !r.starts_with("//") && !r.contains('\\'))
+        .unwrap_or_else(|| "/".to_string())
+}
+
+fn error_response(status: StatusCode, message: &str) -> Response {
+    let body = StatusBody {
+        message: message.to_string(),
+        ..Default::default()
+    };
+    (status, Json(body)).into_response()
+}
+
+/// `Secure` is left out when the server is reached over plain HTTP, e.g. locally
+fn session_cookie(value: &str, max_age: u64, secure: bool) -> String {
+    let secure = if secure { "; Secure" } else { "" };
+    format!("{SESSION_COOKIE}={value}; Path=/; Max-Age={max_age}; HttpOnly; SameSite=Lax{secure}")
+}
+
+#[utoipa::path(
+    get,
+    path = "/auth/login",
+    params(LoginParams),
+    responses(
+        (status = 303, description = "Redirect to the identity provider"),
+        (status = 404, description = "Single sign-on is not configured", body = StatusBody),
+        (status = 502, description = "Identity provider unreachable", body = StatusBody),
+    ),
+    tag = "auth"
+)]
+pub async fn login(State(state): State<AppState>, Query(params): Query<LoginParams>) -> Response {
+    let Some(config) = &state.config.oidc else {
+        return error_response(StatusCode::NOT_FOUND, "Single sign-on is not configured");
+    };
+
+    let url = match state.client.oidc_discover(&config.issuer).await {
+        Ok(metadata) => {
+            let login_state = state.sessions.begin_login(local_path(params.redirect));
+            oidc::authorization_url(config, &metadata, &login_state)
+        }
+        Err(e) => Err(e),
+    };
+    match url {
+        Ok(url) => Redirect::to(&url).into_response(),
+        Err(e) => {
+            tracing::error!("Could not start the login with {}: {e}", config.issuer);
+            error_response(StatusCode::BAD_GATEWAY, "Identity provider unreachable")
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/auth/callback",
+    params(CallbackParams),
+    responses(
+        (status = 303, description = "Logged in, sets the session cookie and returns to the page the login started from"),
+        (status = 400, description = "Unknown or expired login", body = StatusBody),
+        (status = 403, description = "Not allowed to operate the orchestrator", body = StatusBody),
+        (status = 404, description = "Single sign-on is not configured", body = StatusBody),
+        (status = 502, description = "Identity provider unreachable", body = StatusBody),
+    ),
+    tag = "auth"
+)]
+pub async fn callback(
+    State(state): State<AppState>,
+    Query(params): Query<CallbackParams>,
+) -> Response {
+    let Some(config) = &state.config.oidc else {
+        return error_response(StatusCode::NOT_FOUND, "Single sign-on is not configured");
+    };
+
+    if let Some(error) = params.error {
+        tracing::warn!("Login refused by {}: {error}", config.issuer);
+        return error_response(
+            StatusCode::FORBIDDEN,
+            "Login refused by the identity provider",
+        );
+    }
+
+    // The state proves the login was started here, and not by a third party
+    let redirect = params
+        .state
+        .as_deref()
+        .and_then(|s| state.sessions.finish_login(s));
+    let (Some(redirect), Some(code)) = (redirect, params.code) else {
+        return error_response(StatusCode::BAD_REQUEST, "Unknown or expired login");
+    };
+
+    let identity = match state.client.oidc_discover(&config.issuer).await {
+        Ok(metadata) => state.client.oidc_identity(config, &metadata, &code).await,
+        Err(e) => Err(e),
+    };
+    let identity = match identity {
+        Ok(identity) => identity,
+        Err(e) => {
+            tracing::error!("Could not complete the login with {}: {e}", config.issuer);
+            return error_response(StatusCode::BAD_GATEWAY, "Identity provider unreachable");
+        }
+    };
+
+    if !oidc::is_allowed(config, &identity) {
+        tracing::warn!(
+            "Login of {} ({:?}) refused, not in OIDC_ALLOWED_EMAILS",
+            identity.subject,
+            identity.email
+        );
+        return error_response(
+            StatusCode::FORBIDDEN,
+            "Not allowed to operate the orchestrator",
+        );
+    }
+
+    tracing::info!("{} ({:?}) logged in", identity.subject, identity.email);
+    let token = state.sessions.create(identity, config.session_ttl);
+    let cookie = session_cookie(
+        &token,
+        config.session_ttl.as_secs(),
+        config.redirect_url.starts_with("https://"),
+    );
+    ([(header::SET_COOKIE, cookie)], Redirect::to(&redirect)).into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/auth/me",
+    responses(
+        (status = 200, description = "Operator the session belongs to", body = Identity),
+        (status = 401, description = "Not logged in", body = StatusBody),
+    ),
+    tag = "auth"
+)]
+pub async fn me(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    match state.sessions.identify(&headers) {
+        Some(identity) => Json(identity).into_response(),
+        None => error_response(StatusCode::UNAUTHORIZED, "Not logged in"),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/logout",
+    responses(
+        (status = 204, description = "Session closed and cookie cleared"),
+    ),
+    tag = "auth"
+)]
+pub async fn logout(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    if let Some(token) = session_token(&headers) {
+        state.sessions.remove(token);
+    }
+    let secure = state
+        .config
+        .oidc
+        .as_ref()
+        .is_some_and(|c| c.redirect_url.starts_with("https://"));
+    (
+        StatusCode::NO_CONTENT,
+        [(header::SET_COOKIE, session_cookie("", 0, secure))],
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::loader::{Config, OidcConfig, Secret};
+    use crate::models::job_dto::create_jobs_table;
+    use crate::routes::router::create_routes;
+    use crate::services::client::Client;
+    use axum::Router;
+    use axum::body::Body;
+    use axum::http::Request;
+    use mockito::{Server, ServerGuard};
+    use sqlx::SqlitePool;
+    use std::time::Duration;
+    use tower::ServiceExt;
+
+    async fn provider(email: &str) -> ServerGuard {
+        let mut server = Server::new_async().await;
+        let url = server.url();
+        server
+            .mock("GET", "/.well-known/openid-configuration")
+            .with_body(format!(
+                r#"{{"authorization_endpoint": "{url}/authorize", "token_endpoint": "{url}/token", "userinfo_endpoint": "{url}/userinfo"}}"#
+            ))
+            .create_async()
+            .await;
+        server
+            .mock("POST", "/token")
+            .with_body(r#"{"access_token": "at"}"#)
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/userinfo")
+            .with_body(format!(
+                r#"{{"sub": "abc", "email": "{email}", "email_verified": true}}"#
+            ))
+            .create_async()
+            .await;
+        server
+    }
+
+    async fn app(issuer: String) -> Router {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        create_jobs_table(&pool).await.unwrap();
+        let config = Config {
+            oidc: Some(OidcConfig {
+                issuer,
+                client_id: "orchestrator".to_string(),
+                client_secret: Secret::new("client-secret"),
+                redirect_url: "http://localhost:5000/auth/callback".to_string(),
+                allowed_emails: vec!["@example.org".to_string()],
+                session_ttl: Duration::from_secs(3600),
+            }),
+            ..Default::default()
+        };
+        create_routes(pool, config, Client::default())
+    }
+
+    async fn get(app: &Router, uri: &str, cookie: Option<&str>) -> Response {
+        let mut request = Request::builder().uri(uri);
+        if let Some(cookie) = cookie {
+            request = request.header(header::COOKIE, cookie);
+        }
+        let request = request.body(Body::empty()).unwrap();
+        app.clone().oneshot(request).await.unwrap().into_response()
+    }
+
+    /// Start a login and return the `state` the provider would send back
+    async fn start_login(app: &Router) -> String {
+        let response = get(app, "/auth/login?redirect=/swagger", None).await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        let location = response.headers()[header::LOCATION].to_str().unwrap();
+        let url = reqwest::Url::parse(location).unwrap();
+        assert!(url.path().ends_with("/authorize"));
+        url.query_pairs()
+            .find(|(k, _)| k == "state")
+            .map(|(_, v)| v.to_string())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_local_path() {
+        assert_eq!(local_path(None), "/");
+        assert_eq!(local_path(Some("/swagger".to_string())), "/swagger");
+        assert_eq!(local_path(Some("https://evil.example".to_string())), "/");
+        assert_eq!(local_path(Some("//evil.example".to_string())), "/");
+        assert_eq!(local_path(Some("/\\evil.example".to_string())), "/");
+    }
+
+    #[tokio::test]
+    async fn test_login_flow() {
+        let server = provider("alice@example.org").await;
+        let app = app(server.url()).await;
+
+        let state = start_login(&app).await;
+        let response = get(&app, &format!("/auth/callback?code=c&state={state}"), None).await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(response.headers()[header::LOCATION], "/swagger");
+        let cookie = response.headers()[header::SET_COOKIE].to_str().unwrap();
+        assert!(cookie.contains("HttpOnly"));
+        assert!(!cookie.contains("Secure"));
+        let cookie = cookie.split(';').next().unwrap().to_string();
+
+        // The state can't be replayed
+        let response = get(&app, &format!("/auth/callback?code=c&state={state}"), None).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = get(&app, "/auth/me", Some(&cookie)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let identity: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(identity["email"], "alice@example.org");
+
+        // The session opens the admin endpoints
+        let request = Request::builder()
+            .method("POST")
+            .uri("/admin/users/9999/release")
+            .header(header::COOKIE, &cookie)
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let request = Request::builder()
+            .method("POST")
+            .uri("/auth/logout")
+            .header(header::COOKIE, &cookie)
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert!(
+            response.headers()[header::SET_COOKIE]
+                .to_str()
+                .unwrap()
+                .contains("Max-Age=0")
+        );
+
+        let response = get(&app, "/auth/me", Some(&cookie)).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let request = Request::builder()
+            .method("POST")
+            .uri("/admin/users/9999/release")
+            .header(header::COOKIE, &cookie)
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_login_refused() {
+        let server = provider("mallory@elsewhere.org").await;
+        let app = app(server.url()).await;
+
+        let response = get(&app, "/auth/callback?code=c&state=forged", None).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let state = start_login(&app).await;
+        let response = get(&app, &format!("/auth/callback?code=c&state={state}"), None).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert!(!response.headers().contains_key(header::SET_COOKIE));
+
+        let state = start_login(&app).await;
+        let response = get(
+            &app,
+            &format!("/auth/callback?error=access_denied&state={state}"),
+            None,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_login_not_configured() {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        let app = create_routes(pool, Config::default(), Client::default());
+        let response = get(&app, "/auth/login", None).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
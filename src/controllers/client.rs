@@ -1,12 +1,50 @@
-use crate::models::payload_dao::Payload;
+use crate::config::loader::{Config, effective_timeout};
+use crate::models::capabilities_dto::Capabilities;
+use crate::models::error_body::{ErrorBody, ErrorCode};
+use crate::models::load_dto::LoadReport;
+use crate::models::manifest_dto::{
+    FileCheck, FileReport, MANIFEST_FIELD, ManifestEntry, verify_manifest,
+};
+use crate::models::payload_dao::{PAYLOAD_STATUS_HEADER, Payload, PayloadLogs, PayloadStatus};
+use crate::models::run_history_dao::ServiceRunStats;
 use crate::models::status_dto::Status;
+use crate::services::result_store::{
+    LocalResultStore, RESULT_KEY_HEADER, ResultStore, ResultStoreError, SharedResultStore,
+    StoredResult,
+};
+use crate::utils::io::{CHECKSUM_HEADER, file_sha256, read_new_lines};
+use crate::utils::progress::{UPLOAD_ID_HEADER, UPLOAD_SIZE_HEADER, UploadProgress, UploadSession};
+use crate::utils::quarantine::QuarantineError;
+use crate::utils::sys::free_space;
 use crate::{routes::router::AppState, utils::io::sanitize_filename};
+use axum::extract::multipart::{Field, MultipartError};
+use axum::response::sse::{Event, KeepAlive, Sse};
 use axum::response::{IntoResponse, Response};
 use axum::{
-    extract::{Json, Multipart, Path, State},
-    http::{StatusCode, header},
+    extract::{Json, Multipart, Path, Query, State},
+    http::{HeaderMap, HeaderName, StatusCode, header},
 };
+use futures::stream::{self, Stream};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::time::Duration;
 use sysinfo::System;
+use utoipa::IntoParams;
+
+// Reasons a submission is refused for, as counted under `/load`
+const MALFORMED: &str = "malformed";
+const TOO_MANY_FIELDS: &str = "too_many_fields";
+const INVALID_FIELD: &str = "invalid_field";
+const MANIFEST_MISMATCH: &str = "manifest_mismatch";
+const FILE_REJECTED: &str = "file_rejected";
+
+/// Pause between two reads of the logs streamed by `/logs/{id}/stream`
+const LOG_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Bytes of each log read at once by `/logs/{id}/stream`
+const LOG_STREAM_CHUNK: u64 = 64 * 1024;
 
 #[utoipa::path(
     post,
@@ -16,97 +54,632 @@ use sysinfo::System;
     ),
     responses(
         (status = 200, description = "File uploaded successfully", body = Payload),
-        (status = 500, description = "Internal server error"),
+        (status = 400, description = "Malformed body, too many fields, or an invalid manifest or field", body = ErrorBody),
+        (status = 422, description = "Files of the manifest missing or altered, or a file failed its checks", body = Vec<FileReport>),
+        (status = 500, description = "Internal server error", body = ErrorBody),
     ),
     tag = "files"
 )]
-pub async fn submit(State(state): State<AppState>, mut multipart: Multipart) -> Response {
+pub async fn submit(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    mut multipart: Multipart,
+) -> Response {
     let mut payload = Payload::new();
+    let mut manifest: Option<Vec<ManifestEntry>> = None;
+    // Size and checksum of each file, by part name
+    let mut received = HashMap::new();
+
+    // Progress is only tracked when the sender names the session
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    let session = header(UPLOAD_ID_HEADER).map(|id| {
+        let expected = header(UPLOAD_SIZE_HEADER).and_then(|v| v.parse().ok());
+        state.uploads.start(id.to_string(), expected)
+    });
+
+    // Refused submissions are counted by reason, see `/load`
+    let reject = |reason: &str, response: Response| {
+        state.uploads.reject(reason);
+        response
+    };
 
     // Parse the multipart form data
+    let mut fields = 0;
+    let mut requested_timeout = None;
     loop {
         let field = match multipart.next_field().await {
             Ok(Some(f)) => f,
             Ok(None) => break,
             Err(e) => {
                 tracing::error!("Multipart error: {e}");
-                return (StatusCode::BAD_REQUEST, Json(payload)).into_response();
+                return reject(MALFORMED, multipart_error(e));
             }
         };
+        fields += 1;
+        if fields > state.config.body_limits.submit_fields {
+            let limit = state.config.body_limits.submit_fields;
+            tracing::error!("Submission has more than {limit} fields");
+            let detail = format!("too many fields, at most {limit} are accepted");
+            let body = ErrorBody::new(ErrorCode::InvalidRequest, detail);
+            return reject(TOO_MANY_FIELDS, body.into_response());
+        }
         if let Some(filename) = field.file_name() {
             let clean_filename = sanitize_filename(filename);
-            let data = match field.bytes().await {
+            let part = field.name().unwrap_or(&clean_filename).to_string();
+            let data = match read_file(field, &clean_filename, session.as_ref()).await {
                 Ok(d) => d,
                 Err(e) => {
                     tracing::error!("Error reading field bytes: {e}");
-                    return (StatusCode::BAD_REQUEST, Json(payload)).into_response();
+                    return reject(MALFORMED, multipart_error(e));
                 }
             };
-            payload.add_input(clean_filename, data.to_vec());
+            received.insert(
+                part,
+                (data.len() as u64, format!("{:x}", Sha256::digest(&data))),
+            );
+            payload.add_input(clean_filename, data);
+        } else if field.name() == Some(MANIFEST_FIELD) {
+            match field.text().await.map(|t| serde_json::from_str(&t)) {
+                Ok(Ok(m)) => manifest = Some(m),
+                Ok(Err(e)) => {
+                    tracing::error!("Invalid manifest field: {e}");
+                    let detail = format!("invalid manifest: {e}");
+                    let body = ErrorBody::new(ErrorCode::InvalidRequest, detail);
+                    return reject(INVALID_FIELD, body.into_response());
+                }
+                Err(e) => {
+                    tracing::error!("Error reading manifest field: {e}");
+                    return reject(MALFORMED, multipart_error(e));
+                }
+            }
+        } else if field.name() == Some("tenant") {
+            match field.text().await {
+                Ok(t) if !t.trim().is_empty() => payload.set_tenant(sanitize_filename(t.trim())),
+                Ok(_) => {}
+                Err(e) => {
+                    tracing::error!("Error reading tenant field: {e}");
+                    return reject(MALFORMED, multipart_error(e));
+                }
+            }
+        } else if field.name() == Some("service") {
+            match field.text().await {
+                Ok(s) if !s.trim().is_empty() => payload.service = Some(s.trim().to_string()),
+                Ok(_) => {}
+                Err(e) => {
+                    tracing::error!("Error reading service field: {e}");
+                    return reject(MALFORMED, multipart_error(e));
+                }
+            }
+        } else if field.name() == Some("result_key") {
+            // The key is chosen by the server, it must stay a relative object path
+            match field.text().await {
+                Ok(k) if is_valid_key(&k) => payload.set_result_key(k),
+                Ok(k) => {
+                    tracing::error!("Invalid result_key field: {k}");
+                    let detail = format!("invalid result_key: {k}");
+                    let body = ErrorBody::new(ErrorCode::InvalidRequest, detail);
+                    return reject(INVALID_FIELD, body.into_response());
+                }
+                Err(e) => {
+                    tracing::error!("Error reading result_key field: {e}");
+                    return reject(MALFORMED, multipart_error(e));
+                }
+            }
+        } else if field.name() == Some("timeout") {
+            match field.text().await.map(|t| t.trim().parse::<u32>()) {
+                Ok(Ok(t)) if t > 0 => requested_timeout = Some(Duration::from_secs(t.into())),
+                Ok(_) => {
+                    tracing::error!("Invalid timeout field");
+                    let detail = "invalid timeout, should be a positive number of seconds";
+                    let body = ErrorBody::new(ErrorCode::InvalidRequest, detail);
+                    return reject(INVALID_FIELD, body.into_response());
+                }
+                Err(e) => {
+                    tracing::error!("Error reading timeout field: {e}");
+                    return reject(MALFORMED, multipart_error(e));
+                }
+            }
+        } else if field.name() == Some("max_output_size") {
+            match field.text().await.map(|t| t.trim().parse::<u64>()) {
+                Ok(Ok(s)) if s > 0 => payload.max_output_size = Some(s),
+                Ok(_) => {
+                    tracing::error!("Invalid max_output_size field");
+                    let detail = "invalid max_output_size, should be a positive number of bytes";
+                    let body = ErrorBody::new(ErrorCode::InvalidRequest, detail);
+                    return reject(INVALID_FIELD, body.into_response());
+                }
+                Err(e) => {
+                    tracing::error!("Error reading max_output_size field: {e}");
+                    return reject(MALFORMED, multipart_error(e));
+                }
+            }
+        } else if field.name() == Some("expires_at") {
+            match field.text().await.map(|t| t.trim().parse::<i64>()) {
+                Ok(Ok(t)) => payload.expires_at = Some(t),
+                Ok(Err(_)) => {
+                    tracing::error!("Invalid expires_at field");
+                    let detail = "invalid expires_at, should be a unix timestamp in seconds";
+                    let body = ErrorBody::new(ErrorCode::InvalidRequest, detail);
+                    return reject(INVALID_FIELD, body.into_response());
+                }
+                Err(e) => {
+                    tracing::error!("Error reading expires_at field: {e}");
+                    return reject(MALFORMED, multipart_error(e));
+                }
+            }
+        } else if field.name() == Some("sandbox") {
+            // An unknown profile is refused rather than run with weaker isolation
+            match field.text().await.map(|t| t.parse()) {
+                Ok(Ok(sandbox)) => payload.set_sandbox(sandbox),
+                Ok(Err(e)) => {
+                    tracing::error!("Invalid sandbox field: {e}");
+                    let body = ErrorBody::new(ErrorCode::InvalidRequest, e);
+                    return reject(INVALID_FIELD, body.into_response());
+                }
+                Err(e) => {
+                    tracing::error!("Error reading sandbox field: {e}");
+                    return reject(MALFORMED, multipart_error(e));
+                }
+            }
         }
     }
-    // Add job to database
-    // TODO: These error responses return empty payloads with no diagnostic info.
-    //  They are indicators of an unhealthy client — handle in a future PR.
-    let Ok(_) = payload.add_to_db(&state.pool).await else {
-        return (StatusCode::INTERNAL_SERVER_ERROR, Json(payload)).into_response();
-    };
 
-    // FIXME: This sequence can cause a race condition
-    // - prepare -> update_loc, if prepare part fails, then `loc` will not be in the DB
-    let Ok(_) = payload.prepare(&state.config.data_path) else {
-        return (StatusCode::INTERNAL_SERVER_ERROR, Json(payload)).into_response();
-    };
-    //
-    // Update loc in database after prepare() sets it
-    let Ok(_) = payload.update_loc(&state.pool).await else {
-        return (StatusCode::INTERNAL_SERVER_ERROR, Json(payload)).into_response();
+    // Senders predating the manifest are taken at their word
+    if let Some(manifest) = manifest {
+        let report = verify_manifest(&manifest, &received);
+        if report.iter().any(|r| r.check != FileCheck::Ok) {
+            tracing::error!("Submission does not match its manifest: {report:?}");
+            let response = (StatusCode::UNPROCESSABLE_ENTITY, Json(report)).into_response();
+            return reject(MANIFEST_MISMATCH, response);
+        }
+        payload.manifest_report = report;
+    }
+
+    // The submission may ask for less time than this client allows, not more
+    let timeout = effective_timeout(
+        requested_timeout,
+        state.config.run_default_timeout,
+        state.config.run_max_timeout,
+    );
+    if let (Some(requested), Some(timeout)) = (requested_timeout, timeout)
+        && requested > timeout
+    {
+        tracing::warn!("Submission asked for a timeout of {requested:?}, limited to {timeout:?}");
+    }
+    payload.timeout = timeout.map(|t| u32::try_from(t.as_secs()).unwrap_or(u32::MAX));
+    // Same for the output, the tighter limit wins
+    payload.max_output_size = match (payload.max_output_size, state.config.max_output_size) {
+        (Some(requested), Some(max)) => Some(requested.min(max)),
+        (requested, max) => requested.or(max),
     };
 
-    let Ok(_) = payload.update_status(Status::Prepared, &state.pool).await else {
-        return (StatusCode::INTERNAL_SERVER_ERROR, Json(payload)).into_response();
+    // Add job to database
+    if let Err(e) = payload.add_to_db(&state.pool).await {
+        tracing::error!("Could not store payload: {e}");
+        return ErrorBody::new(ErrorCode::DatabaseFailed, e.to_string()).into_response();
+    }
+
+    // Past this point a failure must not leave a half-written directory behind
+    let data_path = state.config.data_path_for(None, &payload.tenant);
+    let checks = &state.config.upload_checks;
+    let prepared = match payload.prepare(data_path, checks).await {
+        Ok(_) => {
+            // Update loc in database after prepare() sets it, then snapshot
+            // the files the runner checks again before executing
+            let stored = match payload.update_loc(&state.pool).await {
+                Ok(_) => payload.record_inputs(&state.pool).await,
+                Err(e) => Err(e),
+            };
+            match stored {
+                Ok(_) => payload
+                    .update_status(Status::Prepared, &state.pool)
+                    .await
+                    .map_err(database_error),
+                Err(e) => Err(database_error(e)),
+            }
+        }
+        Err(QuarantineError::Rejected(reason)) => {
+            Err(ErrorBody::new(ErrorCode::FileRejected, reason))
+        }
+        Err(e @ QuarantineError::ScanFailed(_)) => {
+            Err(ErrorBody::new(ErrorCode::Internal, e.to_string()))
+        }
+        Err(QuarantineError::Io(e)) => Err(ErrorBody::from_io(ErrorCode::Internal, &e)),
     };
+    if let Err(error) = prepared {
+        tracing::error!("Could not prepare payload {}: {error}", payload.id);
+        if let Err(e) = payload.discard(data_path, &state.pool).await {
+            tracing::error!("Could not discard payload {}: {:?}", payload.id, e);
+        }
+        if error.code == ErrorCode::FileRejected {
+            return reject(FILE_REJECTED, error.into_response());
+        }
+        return error.into_response();
+    }
 
     (StatusCode::OK, Json(payload)).into_response()
 }
 
+/// A malformed multipart body, keeping the status axum gave it, e.g. 413
+fn multipart_error(e: MultipartError) -> Response {
+    let body = ErrorBody::new(ErrorCode::InvalidRequest, e.body_text());
+    (e.status(), Json(body)).into_response()
+}
+
+fn database_error(e: sqlx::Error) -> ErrorBody {
+    match e {
+        sqlx::Error::RowNotFound => ErrorBody::new(ErrorCode::NotFound, "no such payload"),
+        e => ErrorBody::new(ErrorCode::DatabaseFailed, e.to_string()),
+    }
+}
+
+fn is_valid_key(key: &str) -> bool {
+    !key.is_empty()
+        && !key.starts_with('/')
+        && key
+            .split('/')
+            .all(|part| !part.is_empty() && part != "." && part != "..")
+}
+
+/// Read a file field chunk by chunk, counting the bytes towards the session
+async fn read_file(
+    mut field: Field<'_>,
+    filename: &str,
+    session: Option<&UploadSession>,
+) -> Result<Vec<u8>, MultipartError> {
+    let mut data = Vec::new();
+    while let Some(chunk) = field.chunk().await? {
+        if let Some(session) = session {
+            session.record(filename, chunk.len());
+        }
+        data.extend_from_slice(&chunk);
+    }
+    Ok(data)
+}
+
 #[utoipa::path(
     get,
-    path = "/retrieve/{id}",
+    path = "/uploads/{id}/progress",
+    params(
+        ("id" = String, Path, description = "Upload session named in the `x-upload-id` header")
+    ),
+    responses(
+        (status = 200, description = "Bytes received so far", body = UploadProgress),
+        (status = 404, description = "No such upload session"),
+    ),
+    tag = "files"
+)]
+pub async fn upload_progress(State(state): State<AppState>, Path(id): Path<String>) -> Response {
+    match state.uploads.progress(&id) {
+        Some(progress) => Json(progress).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/status/{id}",
     params(
         ("id" = i32, Path, description = "Payload identifier")
     ),
     responses(
-       (status = 200, description = "Job completed — returns zip file", content_type = "application/zip", body = Vec<u8>),
-       (status = 200, description = "Job not yet complete — returns current payload state", body = Payload),
-       (status = 404, description = "Payload not found", body = Payload),
-       (status = 500, description = "Internal server error", body = Payload),
+       (status = 200, description = "Status of the payload, nothing is read from disk", body = PayloadStatus),
+       (status = 404, description = "Payload not found", body = ErrorBody),
+       (status = 500, description = "Internal server error", body = ErrorBody),
    ),
     tag = "files"
 )]
-pub async fn retrieve(State(state): State<AppState>, Path(id): Path<u32>) -> Response {
+pub async fn payload_status(State(state): State<AppState>, Path(id): Path<u32>) -> Response {
+    match Payload::retrieve_status(id, &state.pool).await {
+        Ok(status) => Json(status).into_response(),
+        Err(e) => database_error(e).into_response(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/logs/{id}",
+    params(
+        ("id" = i32, Path, description = "Payload identifier")
+    ),
+    responses(
+       (status = 200, description = "The end of what `run.sh` wrote so far, each log unset until it started", body = PayloadLogs),
+       (status = 404, description = "Payload not found", body = ErrorBody),
+       (status = 500, description = "Internal server error", body = ErrorBody),
+   ),
+    tag = "files"
+)]
+pub async fn payload_logs(State(state): State<AppState>, Path(id): Path<u32>) -> Response {
     let payload = match Payload::retrieve_id(id, &state.pool).await {
         Ok(p) => p,
-        // TODO: Empty payload responses are indicators of an unhealthy client — handle in a future PR.
+        Err(e) => return database_error(e).into_response(),
+    };
+
+    match payload.read_logs(state.config.run_log_max_size) {
+        Ok(logs) => Json(logs).into_response(),
         Err(e) => {
-            let status = match e {
-                sqlx::Error::RowNotFound => StatusCode::NOT_FOUND,
-                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            tracing::error!("Could not read the logs of payload {id}: {:?}", e);
+            ErrorBody::from_io(ErrorCode::Internal, &e).into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/logs/{id}/stream",
+    params(
+        ("id" = i32, Path, description = "Payload identifier")
+    ),
+    responses(
+       (status = 200, description = "Server-Sent Events: a `stdout` or `stderr` event per line `run.sh` writes, then a `status` event once it ended", content_type = "text/event-stream"),
+       (status = 404, description = "Payload not found", body = ErrorBody),
+       (status = 500, description = "Internal server error", body = ErrorBody),
+   ),
+    tag = "files"
+)]
+pub async fn stream_logs(State(state): State<AppState>, Path(id): Path<u32>) -> Response {
+    if let Err(e) = Payload::retrieve_status(id, &state.pool).await {
+        return database_error(e).into_response();
+    }
+
+    let follower = LogFollower {
+        id,
+        pool: state.pool.clone(),
+        offsets: [0, 0],
+        pending: VecDeque::new(),
+        idle: false,
+        finished: false,
+    };
+    Sse::new(follower.into_stream())
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+/// Where `/logs/{id}/stream` got to in each log of a payload
+struct LogFollower {
+    id: u32,
+    pool: SqlitePool,
+    /// Bytes of the output to `stdout.log` and `stderr.log` already sent,
+    /// counting those the updater cut since, see `LogTrim`
+    offsets: [u64; 2],
+    pending: VecDeque<Event>,
+    /// The last read found nothing new, wait before the next one
+    idle: bool,
+    finished: bool,
+}
+
+impl LogFollower {
+    fn into_stream(self) -> impl Stream<Item = Result<Event, Infallible>> {
+        stream::unfold(self, |mut follower| async move {
+            loop {
+                if let Some(event) = follower.pending.pop_front() {
+                    return Some((Ok(event), follower));
+                }
+                if follower.finished {
+                    return None;
+                }
+                if follower.idle {
+                    tokio::time::sleep(LOG_POLL_INTERVAL).await;
+                }
+                follower.poll().await;
+            }
+        })
+    }
+
+    /// Queue the lines written since the last read. The status is read first,
+    /// so whatever the run wrote before it ended is sent before it is reported.
+    async fn poll(&mut self) {
+        let payload = match Payload::retrieve_id(self.id, &self.pool).await {
+            Ok(p) => p,
+            Err(e) => {
+                tracing::warn!("Stopped streaming the logs of payload {}: {e}", self.id);
+                self.pending
+                    .push_back(Event::default().event("error").data(e.to_string()));
+                self.finished = true;
+                return;
+            }
+        };
+        let ended = matches!(
+            payload.status,
+            Status::Completed
+                | Status::Failed
+                | Status::Killed
+                | Status::Cancelled
+                | Status::Cleaned
+                | Status::Invalid
+        );
+
+        // The logs are being rewritten, read them once it is done. No trim
+        // starts once the run ended.
+        let trim = payload.log_trim;
+        if trim.ongoing() && !ended {
+            self.idle = true;
+            return;
+        }
+
+        let offsets = self.offsets;
+        let mut drained = true;
+        let logs = [
+            ("stdout", &payload.stdout_log),
+            ("stderr", &payload.stderr_log),
+        ];
+        let followed = logs
+            .into_iter()
+            .zip(self.offsets.iter_mut())
+            .zip(trim.dropped);
+        for (((name, log), offset), dropped) in followed {
+            let Some(log) = log else {
+                continue;
             };
-            return (status, Json(Payload::new())).into_response();
+            // Lines cut before they were sent are skipped
+            let mut position = offset.saturating_sub(dropped);
+            let read = read_new_lines(log, &mut position, LOG_STREAM_CHUNK, ended);
+            *offset = dropped + position;
+            match read {
+                Ok((lines, at_end)) => {
+                    drained &= at_end;
+                    self.pending.extend(
+                        lines
+                            .into_iter()
+                            .map(|line| Event::default().event(name).data(line)),
+                    );
+                }
+                // Removed along with the rest of the payload
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    tracing::warn!("Could not read {}: {e}", log.display());
+                }
+            }
         }
+
+        // A trim started meanwhile, what was read may have moved under it
+        if !ended {
+            match Payload::retrieve_log_trim(self.id, &self.pool).await {
+                Ok(now) if now == trim => {}
+                _ => {
+                    self.offsets = offsets;
+                    self.pending.clear();
+                    self.idle = true;
+                    return;
+                }
+            }
+        }
+
+        self.idle = self.pending.is_empty();
+        if ended && drained {
+            self.pending.push_back(
+                Event::default()
+                    .event("status")
+                    .data(payload.status.to_string()),
+            );
+            self.finished = true;
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/retrieve/{id}",
+    params(
+        ("id" = i32, Path, description = "Payload identifier")
+    ),
+    responses(
+       (status = 200, description = "Job completed — returns zip file", content_type = "application/zip", body = Vec<u8>),
+       (status = 202, description = "Job not yet complete — returns current payload state, with its place in the queue while `Prepared`", body = Payload),
+       (status = 404, description = "Payload not found", body = ErrorBody),
+       (status = 500, description = "Internal server error", body = ErrorBody),
+   ),
+    tag = "files"
+)]
+pub async fn retrieve(State(state): State<AppState>, Path(id): Path<u32>) -> Response {
+    let mut payload = match Payload::retrieve_id(id, &state.pool).await {
+        Ok(p) => p,
+        Err(e) => return database_error(e).into_response(),
     };
 
     match payload.status {
-        Status::Completed => match payload.zip_directory() {
-            Ok(v) => ([(header::CONTENT_TYPE, "application/zip")], v).into_response(),
-            // TODO: Empty payload response is an indicator of an unhealthy client — handle in a future PR.
-            Err(e) => {
-                tracing::error!("Error compressing directory {:?}", e);
-                (StatusCode::INTERNAL_SERVER_ERROR, Json(Payload::new())).into_response()
+        Status::Completed => match &state.config.object_store {
+            Some(store) => {
+                let shared = SharedResultStore {
+                    client: &state.client,
+                    store,
+                };
+                send_results(shared, &payload).await
             }
+            None => send_results(LocalResultStore, &payload).await,
         },
-        _ => Json(payload).into_response(),
+        _ => {
+            // The estimate is a courtesy, the status is answered without it
+            if payload.status == Status::Prepared {
+                match payload.queue_estimate(&state.pool).await {
+                    Ok(estimate) => payload.queue = Some(estimate),
+                    Err(e) => tracing::warn!("Could not estimate the queue of payload {id}: {e}"),
+                }
+            }
+            (StatusCode::ACCEPTED, Json(payload)).into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    head,
+    path = "/retrieve/{id}",
+    params(
+        ("id" = i32, Path, description = "Payload identifier")
+    ),
+    responses(
+       (status = 200, description = "Status in `x-payload-status`, and for a completed payload the size and checksum of the archive a GET sends"),
+       (status = 404, description = "Payload not found"),
+       (status = 500, description = "Internal server error"),
+   ),
+    tag = "files"
+)]
+pub async fn retrieve_head(State(state): State<AppState>, Path(id): Path<u32>) -> Response {
+    let payload = match Payload::retrieve_id(id, &state.pool).await {
+        Ok(p) => p,
+        Err(e) => return database_error(e).into_response(),
+    };
+
+    let status = (
+        HeaderName::from_static(PAYLOAD_STATUS_HEADER),
+        payload.status.to_string(),
+    );
+    if payload.status != Status::Completed {
+        return [status].into_response();
+    }
+
+    // The archive is kept, so the GET that follows sends this very one
+    let archive = payload
+        .output_archive()
+        .and_then(|path| Ok((std::fs::metadata(&path)?.len(), file_sha256(&path)?)));
+    match archive {
+        Ok((size, checksum)) => [
+            status,
+            (header::CONTENT_TYPE, "application/zip".to_string()),
+            (header::CONTENT_LENGTH, size.to_string()),
+            (HeaderName::from_static(CHECKSUM_HEADER), checksum),
+        ]
+        .into_response(),
+        Err(e) => {
+            tracing::error!("Error zipping the results of payload {id}: {e}");
+            ErrorBody::from_io(ErrorCode::ArchiveFailed, &e).into_response()
+        }
+    }
+}
+
+/// Answer with the archive of a completed payload, or with where it was put
+async fn send_results(store: impl ResultStore, payload: &Payload) -> Response {
+    match store.put(payload).await {
+        Ok(StoredResult::Inline(v)) => {
+            // Lets the orchestrator verify the archive arrived intact
+            let checksum = format!("{:x}", Sha256::digest(&v));
+            (
+                [
+                    (header::CONTENT_TYPE, "application/zip".to_string()),
+                    (HeaderName::from_static(CHECKSUM_HEADER), checksum),
+                ],
+                v,
+            )
+                .into_response()
+        }
+        Ok(StoredResult::Shared { key, checksum }) => (
+            [
+                (HeaderName::from_static(RESULT_KEY_HEADER), key),
+                (HeaderName::from_static(CHECKSUM_HEADER), checksum),
+            ],
+            Json(payload),
+        )
+            .into_response(),
+        Err(e) => {
+            tracing::error!("Error storing the results of payload {}: {e}", payload.id);
+            match e {
+                ResultStoreError::Io(e) => ErrorBody::from_io(ErrorCode::ArchiveFailed, &e),
+                ResultStoreError::ObjectStore(e) => {
+                    ErrorBody::new(ErrorCode::ObjectStoreFailed, e.to_string())
+                }
+            }
+            .into_response()
+        }
     }
 }
 
@@ -118,31 +691,24 @@ pub async fn retrieve(State(state): State<AppState>, Path(id): Path<u32>) -> Res
     ),
     responses(
        (status = 200, description = "Returns zip file of current payload state, regardless of completion", content_type = "application/zip", body = Vec<u8>),
-       (status = 404, description = "Payload not found", body = Payload),
-       (status = 500, description = "Internal server error", body = Payload),
+       (status = 404, description = "Payload not found", body = ErrorBody),
+       (status = 422, description = "Payload directory over its `max_output_size`", body = ErrorBody),
+       (status = 500, description = "Internal server error", body = ErrorBody),
    ),
     tag = "files"
 )]
 pub async fn retrieve_partial(State(state): State<AppState>, Path(id): Path<u32>) -> Response {
     let payload = match Payload::retrieve_id(id, &state.pool).await {
         Ok(p) => p,
-        // TODO: Empty payload responses are indicators of an unhealthy client — handle in a future PR.
-        Err(e) => {
-            let status = match e {
-                sqlx::Error::RowNotFound => StatusCode::NOT_FOUND,
-                _ => StatusCode::INTERNAL_SERVER_ERROR,
-            };
-            return (status, Json(Payload::new())).into_response();
-        }
+        Err(e) => return database_error(e).into_response(),
     };
 
     // Always return the zip, regardless of status
     match payload.zip_partial() {
         Ok(v) => ([(header::CONTENT_TYPE, "application/zip")], v).into_response(),
-        // TODO: Empty payload response is an indicator of an unhealthy client — handle in a future PR.
         Err(e) => {
             tracing::error!("Error compressing directory {:?}", e);
-            (StatusCode::INTERNAL_SERVER_ERROR, Json(Payload::new())).into_response()
+            ErrorBody::from_io(ErrorCode::ArchiveFailed, &e).into_response()
         }
     }
 }
@@ -151,10 +717,10 @@ pub async fn retrieve_partial(State(state): State<AppState>, Path(id): Path<u32>
     get,
     path = "/load",
     responses(
-        (status = 200, description = "Get the load of the client", body = f32),
+        (status = 200, description = "Get the load of the client", body = LoadReport),
     ),
 )]
-pub async fn load() -> Json<f32> {
+pub async fn load(State(state): State<AppState>) -> Json<LoadReport> {
     // TODO: Implement cached background monitoring of CPU load
     let mut sys = System::new();
 
@@ -163,7 +729,43 @@ pub async fn load() -> Json<f32> {
     tokio::time::sleep(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL).await;
     sys.refresh_cpu_all();
 
-    Json(sys.global_cpu_usage())
+    Json(LoadReport {
+        cpu: sys.global_cpu_usage(),
+        // The tightest root, the server can not tell which one a payload lands on
+        disk_free: state
+            .config
+            .data_paths()
+            .into_iter()
+            .filter_map(|p| free_space(std::path::Path::new(p)))
+            .min(),
+        rejected_submissions: state.uploads.rejections(),
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/stats",
+    responses(
+        (status = 200, description = "Runtimes and failure rate of the latest runs, by service", body = BTreeMap<String, ServiceRunStats>),
+        (status = 500, description = "Internal server error", body = ErrorBody),
+    ),
+)]
+pub async fn stats(State(state): State<AppState>) -> Response {
+    match ServiceRunStats::list(&state.pool).await {
+        Ok(stats) => Json(stats).into_response(),
+        Err(e) => database_error(e).into_response(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/capabilities",
+    responses(
+        (status = 200, description = "What payloads the client can run", body = Capabilities),
+    ),
+)]
+pub async fn capabilities(State(state): State<AppState>) -> Json<Capabilities> {
+    Json(Capabilities::detect(&state.config))
 }
 
 #[utoipa::path(
@@ -194,40 +796,190 @@ pub async fn kill(State(state): State<AppState>, Path(id): Path<u32>) -> Respons
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use crate::config::loader::{Config, Service};
-    use crate::models::payload_dao::Payload;
-    use crate::models::payload_dto::create_payload_table;
-    use crate::models::status_dto::Status;
-    use crate::routes::router::create_client_routes;
-    use axum::body::Body;
-    use axum::http::{Request, StatusCode};
-    use sqlx::SqlitePool;
-    use std::collections::HashMap;
-    use std::fs;
-    use std::io::Read;
-    use tempfile::TempDir;
-    use tower::ServiceExt;
+#[utoipa::path(
+    post,
+    path = "/cancel/{id}",
+    params(
+        ("id" = u32, Path, description = "ID of payload to be cancelled")
+    ),
+    responses(
+        (status = 204, description = "Payload stopped, removed from disk and marked cancelled"),
+        (status = 404, description = "Payload not found"),
+        (status = 500, description = "Internal server error"),
+    ),
+    tag = "files"
+)]
+pub async fn cancel_payload(State(state): State<AppState>, Path(id): Path<u32>) -> Response {
+    let mut payload = match Payload::retrieve_id(id, &state.pool).await {
+        Ok(p) => p,
+        Err(sqlx::Error::RowNotFound) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            tracing::error!("Could not retrieve payload {id}: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
 
-    async fn setup_test_db() -> SqlitePool {
-        let pool = SqlitePool::connect(":memory:").await.unwrap();
-        create_payload_table(&pool).await.unwrap();
-        pool
+    if let Err(status) = stop(&mut payload, &state.pool).await {
+        return status.into_response();
+    }
+    // Marked first, so the runner does not pick a prepared one meanwhile
+    if let Err(e) = payload.update_status(Status::Cancelled, &state.pool).await {
+        tracing::error!("Could not mark payload {id} as cancelled: {:?}", e);
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+    if let Err(status) = remove_payload_dir(&payload, &state.config) {
+        return status.into_response();
     }
 
-    fn make_config(data_path: &str) -> Config {
-        let mut services = HashMap::new();
-        services.insert(
-            "test".to_string(),
-            Service {
-                name: "test".to_string(),
-                upload_url: "http://example.com/upload".to_string(),
-                download_url: "http://example.com/download".to_string(),
-                terminate_url: "http://example.com/terminate".to_string(),
-                runs_per_user: 5,
-                max_runs: 1,
-            },
+    tracing::info!("Cancelled payload {id}, its results are not wanted anymore");
+    StatusCode::NO_CONTENT.into_response()
+}
+
+/// Kill the payload should it be running
+async fn stop(payload: &mut Payload, pool: &SqlitePool) -> Result<(), StatusCode> {
+    if payload.is_running() == Some(true) {
+        if let Err(e) = payload.kill() {
+            tracing::error!("Could not stop payload {}: {e}", payload.id);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+        payload.mark_as_killed(pool).await.ok();
+    }
+    Ok(())
+}
+
+/// Remove the directory of the payload, one already gone is fine
+fn remove_payload_dir(payload: &Payload, config: &Config) -> Result<(), StatusCode> {
+    let dir = payload.dir(config.data_path_for(None, &payload.tenant));
+    match std::fs::remove_dir_all(&dir) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            tracing::error!("Could not remove {:?}: {e}", dir);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+        _ => Ok(()),
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct PayloadListParams {
+    /// Only payloads created at least this many seconds ago
+    pub older_than: Option<u64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/payloads",
+    params(PayloadListParams),
+    responses(
+        (status = 200, description = "Ids of the payloads not yet cleaned", body = Vec<u32>),
+    ),
+    tag = "files"
+)]
+pub async fn list_payloads(
+    State(state): State<AppState>,
+    Query(params): Query<PayloadListParams>,
+) -> Response {
+    let age = Duration::from_secs(params.older_than.unwrap_or_default());
+    match Payload::list_older_than(age, &state.pool).await {
+        Ok(ids) => Json(ids).into_response(),
+        Err(e) => {
+            tracing::error!("Could not list the payloads: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/payloads/{id}",
+    params(
+        ("id" = u32, Path, description = "ID of payload to be deleted")
+    ),
+    responses(
+        (status = 204, description = "Payload stopped, removed from disk and marked cleaned"),
+        (status = 404, description = "Payload not found"),
+        (status = 500, description = "Internal server error"),
+    ),
+    tag = "files"
+)]
+pub async fn delete_payload(State(state): State<AppState>, Path(id): Path<u32>) -> Response {
+    let mut payload = match Payload::retrieve_id(id, &state.pool).await {
+        Ok(p) => p,
+        Err(sqlx::Error::RowNotFound) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            tracing::error!("Could not retrieve payload {id}: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    if let Err(status) = stop(&mut payload, &state.pool).await {
+        return status.into_response();
+    }
+    if let Err(status) = remove_payload_dir(&payload, &state.config) {
+        return status.into_response();
+    }
+
+    match payload.update_status(Status::Cleaned, &state.pool).await {
+        Ok(_) => {
+            tracing::info!("Deleted payload {id}, no job refers to it anymore");
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(e) => {
+            tracing::error!("Could not mark payload {id} as cleaned: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LogFollower;
+    use crate::config::loader::{Config, ObjectStoreConfig, Service, ServiceAuth, UploadChecks};
+    use crate::models::capabilities_dto::Capabilities;
+    use crate::models::error_body::{ErrorBody, ErrorCode};
+    use crate::models::load_dto::LoadReport;
+    use crate::models::manifest_dto::{FileCheck, FileReport, MANIFEST_FIELD, ManifestEntry};
+    use crate::models::payload_dao::{Payload, PayloadLogs, PayloadStatus, STDERR_LOG, STDOUT_LOG};
+    use crate::models::payload_dto::create_payload_table;
+    use crate::models::run_history_dao::ServiceRunStats;
+    use crate::models::status_dto::Status;
+    use crate::routes::router::create_client_routes;
+    use crate::services::client::Client;
+    use crate::services::result_store::RESULT_KEY_HEADER;
+    use crate::utils::io::CHECKSUM_HEADER;
+    use crate::utils::quarantine::QUARANTINE_DIR;
+    use crate::utils::sandbox::SandboxProfile;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode, header};
+    use axum::response::IntoResponse;
+    use axum::response::sse::Sse;
+    use sha2::{Digest, Sha256};
+    use sqlx::SqlitePool;
+    use std::collections::{BTreeMap, HashMap, VecDeque};
+    use std::fs;
+    use std::io::{Read, Write};
+    use std::time::Duration;
+    use tempfile::TempDir;
+    use tower::ServiceExt;
+
+    async fn setup_test_db() -> SqlitePool {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        create_payload_table(&pool).await.unwrap();
+        pool
+    }
+
+    fn make_config(data_path: &str) -> Config {
+        let mut services = HashMap::new();
+        services.insert(
+            "test".to_string(),
+            Service {
+                name: "test".to_string(),
+                upload_url: "http://example.com/upload".to_string(),
+                download_url: "http://example.com/download".to_string(),
+                terminate_url: "http://example.com/terminate".to_string(),
+                runs_per_user: 5,
+                max_runs: 1,
+                ..Default::default()
+            },
         );
         Config {
             services,
@@ -235,6 +987,7 @@ mod tests {
             data_path: data_path.to_string(),
             max_age: std::time::Duration::from_secs(3600),
             port: 5000,
+            ..Default::default()
         }
     }
 
@@ -270,69 +1023,771 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_submit_success() {
+    async fn test_submit_success() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let config = make_config(tempdir.path().to_str().unwrap());
+        let app = create_client_routes(pool.clone(), config, Client::default());
+
+        let boundary = "testboundary123";
+        let body = build_multipart(
+            boundary,
+            &[("file", b"file content".as_slice(), Some("input.txt"))],
+        );
+
+        let request = Request::builder()
+            .method("POST")
+            .uri("/submit")
+            .header(
+                "content-type",
+                format!("multipart/form-data; boundary={boundary}"),
+            )
+            .body(Body::from(body))
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let bytes = body_bytes(response).await;
+        let payload: Payload = serde_json::from_slice(&bytes).unwrap();
+        assert!(payload.id > 0);
+        assert_eq!(payload.status, Status::Prepared);
+
+        // The runner checks the files against this snapshot
+        let stored = Payload::retrieve_id(payload.id, &pool).await.unwrap();
+        let inputs = stored.inputs.as_ref().unwrap();
+        assert_eq!(inputs.len(), 1);
+        assert_eq!(inputs[0].path, "input.txt");
+        assert_eq!(inputs[0].size, 12);
+        assert!(stored.verify_inputs().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_submit_failure_removes_directory() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let config = make_config(tempdir.path().to_str().unwrap());
+        let app = create_client_routes(pool.clone(), config, Client::default());
+
+        // The first payload gets id 1, a directory in the way of one of its
+        // files makes `prepare` fail after the quarantine directory was created
+        let dir = tempdir.path().join("1");
+        let quarantine = tempdir.path().join(QUARANTINE_DIR).join("1");
+        fs::create_dir_all(quarantine.join("blocked.txt")).unwrap();
+
+        let boundary = "testboundary123";
+        let body = build_multipart(
+            boundary,
+            &[
+                ("file", b"file content".as_slice(), Some("input.txt")),
+                ("file", b"file content".as_slice(), Some("blocked.txt")),
+            ],
+        );
+        let request = Request::builder()
+            .method("POST")
+            .uri("/submit")
+            .header(
+                "content-type",
+                format!("multipart/form-data; boundary={boundary}"),
+            )
+            .body(Body::from(body))
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(!dir.exists());
+        assert!(!quarantine.exists());
+        let stored = Payload::retrieve_id(1, &pool).await.unwrap();
+        assert_eq!(stored.status, Status::Failed);
+    }
+
+    #[tokio::test]
+    async fn test_upload_progress() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let config = make_config(tempdir.path().to_str().unwrap());
+        let app = create_client_routes(pool, config, Client::default());
+
+        let boundary = "testboundary123";
+        let body = build_multipart(
+            boundary,
+            &[
+                ("file", b"file content".as_slice(), Some("input.txt")),
+                ("file", b"more".as_slice(), Some("other.txt")),
+            ],
+        );
+        let request = Request::builder()
+            .method("POST")
+            .uri("/submit")
+            .header(
+                "content-type",
+                format!("multipart/form-data; boundary={boundary}"),
+            )
+            .header("x-upload-id", "abc")
+            .header("x-upload-size", "16")
+            .body(Body::from(body))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let progress = |id: &str| {
+            Request::builder()
+                .uri(format!("/uploads/{id}/progress"))
+                .body(Body::empty())
+                .unwrap()
+        };
+        let response = app.clone().oneshot(progress("abc")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(&body_bytes(response).await).unwrap();
+        assert_eq!(body["received"], 16);
+        assert_eq!(body["expected"], 16);
+        assert_eq!(body["files"]["input.txt"], 12);
+        assert_eq!(body["files"]["other.txt"], 4);
+        assert_eq!(body["finished"], true);
+
+        let response = app.oneshot(progress("unknown")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_submit_rejections() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let mut config = make_config(tempdir.path().to_str().unwrap());
+        config.body_limits.submit_fields = 2;
+        let app = create_client_routes(pool, config, Client::default());
+
+        let boundary = "testboundary123";
+        let request = |body: Vec<u8>| {
+            Request::builder()
+                .method("POST")
+                .uri("/submit")
+                .header(
+                    "content-type",
+                    format!("multipart/form-data; boundary={boundary}"),
+                )
+                .body(Body::from(body))
+                .unwrap()
+        };
+
+        // Cut off in the middle of a part
+        let truncated = format!(
+            "--{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n\r\nfile"
+        );
+        let response = app
+            .clone()
+            .oneshot(request(truncated.into_bytes()))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let error: ErrorBody = serde_json::from_slice(&body_bytes(response).await).unwrap();
+        assert_eq!(error.code, ErrorCode::InvalidRequest);
+
+        let file = b"file content".as_slice();
+        let body = build_multipart(
+            boundary,
+            &[
+                ("file", file, Some("a.txt")),
+                ("file", file, Some("b.txt")),
+                ("file", file, Some("c.txt")),
+            ],
+        );
+        let response = app.clone().oneshot(request(body)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let error: ErrorBody = serde_json::from_slice(&body_bytes(response).await).unwrap();
+        assert_eq!(error.code, ErrorCode::InvalidRequest);
+        assert!(error.detail.contains("at most 2"), "{}", error.detail);
+
+        let request = Request::builder().uri("/load").body(Body::empty()).unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        let load: LoadReport = serde_json::from_slice(&body_bytes(response).await).unwrap();
+        assert_eq!(load.rejected_submissions["malformed"], 1);
+        assert_eq!(load.rejected_submissions["too_many_fields"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_submit_sandbox() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let config = make_config(tempdir.path().to_str().unwrap());
+        let app = create_client_routes(pool.clone(), config, Client::default());
+
+        let boundary = "testboundary123";
+        let request = |sandbox: &str| {
+            let body = build_multipart(
+                boundary,
+                &[
+                    ("file", b"file content".as_slice(), Some("input.txt")),
+                    ("sandbox", sandbox.as_bytes(), None),
+                ],
+            );
+            Request::builder()
+                .method("POST")
+                .uri("/submit")
+                .header(
+                    "content-type",
+                    format!("multipart/form-data; boundary={boundary}"),
+                )
+                .body(Body::from(body))
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(request("untrusted")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let payload: Payload = serde_json::from_slice(&body_bytes(response).await).unwrap();
+        let stored = Payload::retrieve_id(payload.id, &pool).await.unwrap();
+        assert_eq!(stored.sandbox, SandboxProfile::Untrusted);
+
+        let response = app.oneshot(request("wide-open")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_submit_max_output_size() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let mut config = make_config(tempdir.path().to_str().unwrap());
+        config.max_output_size = Some(4096);
+        let app = create_client_routes(pool.clone(), config, Client::default());
+
+        let boundary = "testboundary123";
+        let request = |size: Option<&str>| {
+            let mut parts = vec![("file", b"file content".as_slice(), Some("input.txt"))];
+            if let Some(s) = size {
+                parts.push(("max_output_size", s.as_bytes(), None));
+            }
+            Request::builder()
+                .method("POST")
+                .uri("/submit")
+                .header(
+                    "content-type",
+                    format!("multipart/form-data; boundary={boundary}"),
+                )
+                .body(Body::from(build_multipart(boundary, &parts)))
+                .unwrap()
+        };
+
+        // The client's own limit, kept, then brought down to it
+        for (size, expected) in [(None, 4096), (Some("1024"), 1024), (Some("8192"), 4096)] {
+            let response = app.clone().oneshot(request(size)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let payload: Payload = serde_json::from_slice(&body_bytes(response).await).unwrap();
+            assert_eq!(payload.max_output_size, Some(expected));
+            let stored = Payload::retrieve_id(payload.id, &pool).await.unwrap();
+            assert_eq!(stored.max_output_size, Some(expected));
+        }
+
+        let response = app.oneshot(request(Some("0"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_submit_service() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let config = make_config(tempdir.path().to_str().unwrap());
+        let app = create_client_routes(pool.clone(), config, Client::default());
+
+        let boundary = "testboundary123";
+        let body = build_multipart(
+            boundary,
+            &[
+                ("file", b"file content", Some("input.txt")),
+                ("service", b" haddock ", None),
+            ],
+        );
+        let request = Request::builder()
+            .method("POST")
+            .uri("/submit")
+            .header(
+                "content-type",
+                format!("multipart/form-data; boundary={boundary}"),
+            )
+            .body(Body::from(body))
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let payload: Payload = serde_json::from_slice(&body_bytes(response).await).unwrap();
+        assert_eq!(payload.service.as_deref(), Some("haddock"));
+        let stored = Payload::retrieve_id(payload.id, &pool).await.unwrap();
+        assert_eq!(stored.service.as_deref(), Some("haddock"));
+    }
+
+    #[tokio::test]
+    async fn test_submit_timeout() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let mut config = make_config(tempdir.path().to_str().unwrap());
+        config.run_default_timeout = Some(Duration::from_secs(600));
+        config.run_max_timeout = Some(Duration::from_secs(3600));
+        let app = create_client_routes(pool.clone(), config, Client::default());
+
+        let boundary = "testboundary123";
+        let request = |timeout: Option<&str>| {
+            let mut parts = vec![("file", b"file content".as_slice(), Some("input.txt"))];
+            if let Some(t) = timeout {
+                parts.push(("timeout", t.as_bytes(), None));
+            }
+            Request::builder()
+                .method("POST")
+                .uri("/submit")
+                .header(
+                    "content-type",
+                    format!("multipart/form-data; boundary={boundary}"),
+                )
+                .body(Body::from(build_multipart(boundary, &parts)))
+                .unwrap()
+        };
+
+        // Defaulted, kept, then clamped to the maximum
+        for (timeout, expected) in [(None, 600), (Some("900"), 900), (Some("86400"), 3600)] {
+            let response = app.clone().oneshot(request(timeout)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let payload: Payload = serde_json::from_slice(&body_bytes(response).await).unwrap();
+            assert_eq!(payload.timeout, Some(expected));
+            let stored = Payload::retrieve_id(payload.id, &pool).await.unwrap();
+            assert_eq!(stored.timeout, Some(expected));
+        }
+
+        let response = app.oneshot(request(Some("-1"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_submit_quarantine() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let mut config = make_config(tempdir.path().to_str().unwrap());
+        config.upload_checks.script_denylist = vec!["curl".to_string()];
+        let app = create_client_routes(pool.clone(), config, Client::default());
+
+        let boundary = "testboundary123";
+        let request = |script: &[u8]| {
+            Request::builder()
+                .method("POST")
+                .uri("/submit")
+                .header(
+                    "content-type",
+                    format!("multipart/form-data; boundary={boundary}"),
+                )
+                .body(Body::from(build_multipart(
+                    boundary,
+                    &[("file", script, Some("run.sh"))],
+                )))
+                .unwrap()
+        };
+        let quarantine = tempdir.path().join(QUARANTINE_DIR);
+
+        let response = app
+            .clone()
+            .oneshot(request(b"curl example.org"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let error: ErrorBody = serde_json::from_slice(&body_bytes(response).await).unwrap();
+        assert_eq!(error.code, ErrorCode::FileRejected);
+        let rejected = Payload::retrieve_id(1, &pool).await.unwrap();
+        assert_eq!(rejected.status, Status::Failed);
+        // Neither left in quarantine nor where the runner looks
+        assert!(!quarantine.join("1").exists());
+        assert!(!tempdir.path().join("1").exists());
+
+        let response = app.oneshot(request(b"echo hello")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let payload: Payload = serde_json::from_slice(&body_bytes(response).await).unwrap();
+        assert_eq!(payload.loc, tempdir.path().join(payload.id.to_string()));
+        assert!(payload.loc.join("run.sh").exists());
+        assert_eq!(fs::read_dir(&quarantine).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_submit_expires_at() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let config = make_config(tempdir.path().to_str().unwrap());
+        let app = create_client_routes(pool.clone(), config, Client::default());
+
+        let boundary = "testboundary123";
+        let request = |expires_at: Option<&str>| {
+            let mut parts = vec![("file", b"file content".as_slice(), Some("input.txt"))];
+            if let Some(t) = expires_at {
+                parts.push(("expires_at", t.as_bytes(), None));
+            }
+            Request::builder()
+                .method("POST")
+                .uri("/submit")
+                .header(
+                    "content-type",
+                    format!("multipart/form-data; boundary={boundary}"),
+                )
+                .body(Body::from(build_multipart(boundary, &parts)))
+                .unwrap()
+        };
+
+        for (expires_at, expected) in [(None, None), (Some("1893456000"), Some(1893456000))] {
+            let response = app.clone().oneshot(request(expires_at)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let payload: Payload = serde_json::from_slice(&body_bytes(response).await).unwrap();
+            assert_eq!(payload.expires_at, expected);
+            let stored = Payload::retrieve_id(payload.id, &pool).await.unwrap();
+            assert_eq!(stored.expires_at, expected);
+        }
+
+        let response = app.oneshot(request(Some("tomorrow"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_submit_manifest() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let config = make_config(tempdir.path().to_str().unwrap());
+        let app = create_client_routes(pool.clone(), config, Client::default());
+
+        let boundary = "testboundary123";
+        let request = |manifest: &[ManifestEntry]| {
+            let manifest = serde_json::to_vec(manifest).unwrap();
+            let body = build_multipart(
+                boundary,
+                &[
+                    (MANIFEST_FIELD, manifest.as_slice(), None),
+                    (
+                        "inputs/input.txt",
+                        b"file content".as_slice(),
+                        Some("input.txt"),
+                    ),
+                ],
+            );
+            Request::builder()
+                .method("POST")
+                .uri("/submit")
+                .header(
+                    "content-type",
+                    format!("multipart/form-data; boundary={boundary}"),
+                )
+                .body(Body::from(body))
+                .unwrap()
+        };
+        let input = ManifestEntry {
+            path: "inputs/input.txt".to_string(),
+            size: 12,
+            sha256: format!("{:x}", Sha256::digest(b"file content")),
+        };
+
+        let response = app
+            .clone()
+            .oneshot(request(std::slice::from_ref(&input)))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let payload: Payload = serde_json::from_slice(&body_bytes(response).await).unwrap();
+        assert_eq!(payload.manifest_report.len(), 1);
+        assert_eq!(payload.manifest_report[0].check, FileCheck::Ok);
+
+        // A file announced but never sent fails the whole submission
+        let run = ManifestEntry {
+            path: "run.sh".to_string(),
+            size: 4,
+            sha256: "00".to_string(),
+        };
+        let response = app.clone().oneshot(request(&[input, run])).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let report: Vec<FileReport> = serde_json::from_slice(&body_bytes(response).await).unwrap();
+        assert_eq!(report[0].check, FileCheck::Ok);
+        assert_eq!(report[1].check, FileCheck::Missing);
+
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM payloads")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_retrieve_not_found() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let config = make_config(tempdir.path().to_str().unwrap());
+        let app = create_client_routes(pool, config, Client::default());
+
+        let request = Request::builder()
+            .method("GET")
+            .uri("/retrieve/9999")
+            .body(Body::empty())
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_retrieve_non_completed() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let config = make_config(tempdir.path().to_str().unwrap());
+
+        let mut payload = Payload::new();
+        payload.add_to_db(&pool).await.unwrap();
+        payload
+            .update_status(Status::Prepared, &pool)
+            .await
+            .unwrap();
+        let payload_id = payload.id;
+
+        let app = create_client_routes(pool, config, Client::default());
+
+        let request = Request::builder()
+            .method("GET")
+            .uri(format!("/retrieve/{payload_id}"))
+            .body(Body::empty())
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+        let bytes = body_bytes(response).await;
+        let retrieved: Payload = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(retrieved.status, Status::Prepared);
+        assert_eq!(retrieved.id, payload_id);
+        // Next in line, nothing ran before it to estimate from
+        let queue = retrieved.queue.unwrap();
+        assert_eq!(queue.position, 1);
+        assert_eq!(queue.eta_secs, None);
+    }
+
+    #[tokio::test]
+    async fn test_payload_status() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let config = make_config(tempdir.path().to_str().unwrap());
+
+        // Completed with no directory behind it, nothing is read from disk
+        let mut payload = Payload::new();
+        payload.loc = tempdir.path().join("gone");
+        payload.add_to_db(&pool).await.unwrap();
+        payload
+            .update_status(Status::Completed, &pool)
+            .await
+            .unwrap();
+
+        let app = create_client_routes(pool, config, Client::default());
+        let request = |id: u32| {
+            Request::builder()
+                .uri(format!("/status/{id}"))
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(request(payload.id)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let status: PayloadStatus = serde_json::from_slice(&body_bytes(response).await).unwrap();
+        assert_eq!(status.id, payload.id);
+        assert_eq!(status.status, Status::Completed);
+        assert!(!tempdir.path().join("gone").exists());
+
+        let response = app.oneshot(request(999)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let error: ErrorBody = serde_json::from_slice(&body_bytes(response).await).unwrap();
+        assert_eq!(error.code, ErrorCode::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_payload_logs() {
         let tempdir = TempDir::new().unwrap();
         let pool = setup_test_db().await;
-        let config = make_config(tempdir.path().to_str().unwrap());
-        let app = create_client_routes(pool, config);
+        let mut config = make_config(tempdir.path().to_str().unwrap());
+        config.run_log_max_size = 7;
 
-        let boundary = "testboundary123";
-        let body = build_multipart(
-            boundary,
-            &[("file", b"file content".as_slice(), Some("input.txt"))],
-        );
+        let mut payload = Payload::new();
+        payload.add_to_db(&pool).await.unwrap();
+        let payload_dir = tempdir.path().join(payload.id.to_string());
+        fs::create_dir_all(&payload_dir).unwrap();
+        payload.set_loc(payload_dir.clone());
+        payload.update_loc(&pool).await.unwrap();
 
-        let request = Request::builder()
-            .method("POST")
-            .uri("/submit")
-            .header(
-                "content-type",
-                format!("multipart/form-data; boundary={boundary}"),
-            )
-            .body(Body::from(body))
-            .unwrap();
+        let app = create_client_routes(pool.clone(), config, Client::default());
+        let request = |id: u32| {
+            Request::builder()
+                .uri(format!("/logs/{id}"))
+                .body(Body::empty())
+                .unwrap()
+        };
 
-        let response = app.oneshot(request).await.unwrap();
+        // Not started yet
+        let response = app.clone().oneshot(request(payload.id)).await.unwrap();
         assert_eq!(response.status(), StatusCode::OK);
+        let logs: PayloadLogs = serde_json::from_slice(&body_bytes(response).await).unwrap();
+        assert_eq!(logs.stdout, None);
+        assert_eq!(logs.stderr, None);
 
-        let bytes = body_bytes(response).await;
-        let payload: Payload = serde_json::from_slice(&bytes).unwrap();
-        assert!(payload.id > 0);
-        assert_eq!(payload.status, Status::Prepared);
+        fs::write(payload_dir.join(STDOUT_LOG), b"first\nsecond\n").unwrap();
+        fs::write(payload_dir.join(STDERR_LOG), b"oops\n").unwrap();
+        payload.stdout_log = Some(payload_dir.join(STDOUT_LOG));
+        payload.stderr_log = Some(payload_dir.join(STDERR_LOG));
+        payload.update_pid(&pool).await.unwrap();
+
+        let response = app.clone().oneshot(request(payload.id)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let logs: PayloadLogs = serde_json::from_slice(&body_bytes(response).await).unwrap();
+        assert_eq!(logs.id, payload.id);
+        let stdout = logs.stdout.unwrap();
+        assert_eq!(stdout.content, "second\n");
+        assert!(stdout.truncated);
+        let stderr = logs.stderr.unwrap();
+        assert_eq!(stderr.content, "oops\n");
+        assert!(!stderr.truncated);
+
+        let response = app.oneshot(request(999)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
     }
 
     #[tokio::test]
-    async fn test_retrieve_not_found() {
+    async fn test_stream_logs() {
         let tempdir = TempDir::new().unwrap();
         let pool = setup_test_db().await;
         let config = make_config(tempdir.path().to_str().unwrap());
-        let app = create_client_routes(pool, config);
 
-        let request = Request::builder()
-            .method("GET")
-            .uri("/retrieve/9999")
-            .body(Body::empty())
+        let mut payload = Payload::new();
+        payload.add_to_db(&pool).await.unwrap();
+        let payload_dir = tempdir.path().join(payload.id.to_string());
+        fs::create_dir_all(&payload_dir).unwrap();
+        fs::write(payload_dir.join(STDOUT_LOG), b"step 1\nstep 2").unwrap();
+        fs::write(payload_dir.join(STDERR_LOG), b"warning\n").unwrap();
+        payload.set_loc(payload_dir.clone());
+        payload.update_loc(&pool).await.unwrap();
+        payload.stdout_log = Some(payload_dir.join(STDOUT_LOG));
+        payload.stderr_log = Some(payload_dir.join(STDERR_LOG));
+        payload.update_pid(&pool).await.unwrap();
+        payload
+            .update_status(Status::Completed, &pool)
+            .await
             .unwrap();
 
-        let response = app.oneshot(request).await.unwrap();
+        let app = create_client_routes(pool, config, Client::default());
+        let request = |id: u32| {
+            Request::builder()
+                .uri(format!("/logs/{id}/stream"))
+                .header(header::ACCEPT_ENCODING, "gzip")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        // The run ended, everything is sent and the stream closes
+        let response = app.clone().oneshot(request(payload.id)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "text/event-stream"
+        );
+        assert!(!response.headers().contains_key(header::CONTENT_ENCODING));
+        let body = String::from_utf8(body_bytes(response).await.to_vec()).unwrap();
+        assert_eq!(
+            body,
+            "event: stdout\ndata: step 1\n\n\
+             event: stdout\ndata: step 2\n\n\
+             event: stderr\ndata: warning\n\n\
+             event: status\ndata: completed\n\n"
+        );
+
+        let response = app.oneshot(request(999)).await.unwrap();
         assert_eq!(response.status(), StatusCode::NOT_FOUND);
     }
 
     #[tokio::test]
-    async fn test_retrieve_non_completed() {
+    async fn test_stream_logs_after_trim() {
         let tempdir = TempDir::new().unwrap();
         let pool = setup_test_db().await;
-        let config = make_config(tempdir.path().to_str().unwrap());
 
         let mut payload = Payload::new();
         payload.add_to_db(&pool).await.unwrap();
+        let payload_dir = tempdir.path().join(payload.id.to_string());
+        fs::create_dir_all(&payload_dir).unwrap();
+        let stdout = payload_dir.join(STDOUT_LOG);
+        let mut writer = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&stdout)
+            .unwrap();
+        writer.write_all(b"one\ntwo\nthree\n").unwrap();
+        payload.set_loc(payload_dir.clone());
+        payload.update_loc(&pool).await.unwrap();
+        payload.stdout_log = Some(stdout.clone());
+        payload.update_pid(&pool).await.unwrap();
+        payload.update_status(Status::Running, &pool).await.unwrap();
+
+        let mut follower = LogFollower {
+            id: payload.id,
+            pool: pool.clone(),
+            offsets: [0, 0],
+            pending: VecDeque::new(),
+            idle: false,
+            finished: false,
+        };
+        follower.poll().await;
+        assert_eq!(follower.pending.len(), 3);
+
+        // Nothing is read while the logs are rewritten
+        writer.write_all(b"four\n").unwrap();
+        payload.begin_log_trim(&pool).await.unwrap();
+        follower.poll().await;
+        assert_eq!(follower.pending.len(), 3);
+        assert!(follower.idle);
+        payload.end_log_trim([0, 0], &pool).await.unwrap();
+
+        // Cut to "four\n", the follower carries on after what it sent
+        payload.cap_logs(5, &pool).await.unwrap();
+        assert_eq!(fs::read(&stdout).unwrap(), b"four\n");
+        writer.write_all(b"five\n").unwrap();
+        follower.poll().await;
+        assert_eq!(follower.offsets, [24, 0]);
+
+        // Cut past where the follower got to, the lines in between are skipped
+        writer.write_all(b"six\nseven\n").unwrap();
+        payload.cap_logs(6, &pool).await.unwrap();
+        assert_eq!(fs::read(&stdout).unwrap(), b"seven\n");
         payload
-            .update_status(Status::Prepared, &pool)
+            .update_status(Status::Completed, &pool)
             .await
             .unwrap();
+        follower.poll().await;
+
+        let response = Sse::new(follower.into_stream()).into_response();
+        let body = String::from_utf8(body_bytes(response).await.to_vec()).unwrap();
+        assert_eq!(
+            body,
+            "event: stdout\ndata: one\n\n\
+             event: stdout\ndata: two\n\n\
+             event: stdout\ndata: three\n\n\
+             event: stdout\ndata: four\n\n\
+             event: stdout\ndata: five\n\n\
+             event: stdout\ndata: seven\n\n\
+             event: status\ndata: completed\n\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_retrieve_completed() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let config = make_config(tempdir.path().to_str().unwrap());
+
+        let mut payload = Payload::new();
+        payload.add_to_db(&pool).await.unwrap();
         let payload_id = payload.id;
 
-        let app = create_client_routes(pool, config);
+        let payload_dir = tempdir.path().join(payload_id.to_string());
+        fs::create_dir_all(&payload_dir).unwrap();
+        fs::write(payload_dir.join("output.txt"), b"result data").unwrap();
+
+        payload.set_loc(payload_dir);
+        payload.update_loc(&pool).await.unwrap();
+        payload
+            .update_status(Status::Completed, &pool)
+            .await
+            .unwrap();
+
+        let app = create_client_routes(pool, config, Client::default());
 
         let request = Request::builder()
             .method("GET")
@@ -343,34 +1798,126 @@ mod tests {
         let response = app.oneshot(request).await.unwrap();
         assert_eq!(response.status(), StatusCode::OK);
 
-        let bytes = body_bytes(response).await;
-        let retrieved: Payload = serde_json::from_slice(&bytes).unwrap();
-        assert_eq!(retrieved.status, Status::Prepared);
-        assert_eq!(retrieved.id, payload_id);
+        let content_type = response
+            .headers()
+            .get("content-type")
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("");
+        assert_eq!(content_type, "application/zip");
+
+        // The checksum header matches the archive
+        let checksum = response.headers()[CHECKSUM_HEADER]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(checksum, format!("{:x}", Sha256::digest(&body)));
     }
 
     #[tokio::test]
-    async fn test_retrieve_completed() {
+    async fn test_retrieve_head() {
         let tempdir = TempDir::new().unwrap();
         let pool = setup_test_db().await;
         let config = make_config(tempdir.path().to_str().unwrap());
 
+        let mut running = Payload::new();
+        running.add_to_db(&pool).await.unwrap();
+        running.update_status(Status::Running, &pool).await.unwrap();
+
+        let mut payload = Payload::new();
+        payload.add_to_db(&pool).await.unwrap();
+        let payload_dir = tempdir.path().join(payload.id.to_string());
+        fs::create_dir_all(&payload_dir).unwrap();
+        fs::write(payload_dir.join("output.txt"), b"result data").unwrap();
+        payload.set_loc(payload_dir);
+        payload.update_loc(&pool).await.unwrap();
+        payload
+            .update_status(Status::Completed, &pool)
+            .await
+            .unwrap();
+
+        let app = create_client_routes(pool, config, Client::default());
+        let request = |method: &str, id: u32| {
+            Request::builder()
+                .method(method)
+                .uri(format!("/retrieve/{id}"))
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = app
+            .clone()
+            .oneshot(request("HEAD", running.id))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["x-payload-status"], "running");
+        assert!(response.headers().get(CHECKSUM_HEADER).is_none());
+
+        // Announces the archive the GET then sends
+        let response = app
+            .clone()
+            .oneshot(request("HEAD", payload.id))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let headers = response.headers().clone();
+        assert_eq!(headers["x-payload-status"], "completed");
+        assert!(body_bytes(response).await.is_empty());
+
+        let response = app
+            .clone()
+            .oneshot(request("GET", payload.id))
+            .await
+            .unwrap();
+        let body = body_bytes(response).await;
+        assert_eq!(headers["content-length"], body.len().to_string().as_str());
+        assert_eq!(
+            headers[CHECKSUM_HEADER],
+            format!("{:x}", Sha256::digest(&body)).as_str()
+        );
+
+        let response = app.oneshot(request("HEAD", 999)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_retrieve_shared_results() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let mut config = make_config(tempdir.path().to_str().unwrap());
+
+        let mut server = mockito::Server::new_async().await;
+        let put = server
+            .mock("PUT", "/test/abc.zip")
+            .with_status(200)
+            .create_async()
+            .await;
+        config.object_store = Some(ObjectStoreConfig {
+            url: server.url(),
+            auth: ServiceAuth::default(),
+            tier_after: Duration::from_secs(0),
+            s3: None,
+        });
+
         let mut payload = Payload::new();
+        payload.set_result_key("test/abc.zip".to_string());
         payload.add_to_db(&pool).await.unwrap();
         let payload_id = payload.id;
 
         let payload_dir = tempdir.path().join(payload_id.to_string());
         fs::create_dir_all(&payload_dir).unwrap();
         fs::write(payload_dir.join("output.txt"), b"result data").unwrap();
-
-        payload.set_loc(payload_dir);
+        payload.set_loc(payload_dir.clone());
         payload.update_loc(&pool).await.unwrap();
         payload
             .update_status(Status::Completed, &pool)
             .await
             .unwrap();
 
-        let app = create_client_routes(pool, config);
+        let app = create_client_routes(pool, config, Client::default());
 
         let request = Request::builder()
             .method("GET")
@@ -380,13 +1927,18 @@ mod tests {
 
         let response = app.oneshot(request).await.unwrap();
         assert_eq!(response.status(), StatusCode::OK);
-
-        let content_type = response
-            .headers()
-            .get("content-type")
-            .and_then(|v| v.to_str().ok())
-            .unwrap_or("");
-        assert_eq!(content_type, "application/zip");
+        put.assert_async().await;
+
+        // The archive went to the store, only its key and checksum come back
+        assert_eq!(response.headers()[RESULT_KEY_HEADER], "test/abc.zip");
+        let archive = fs::read(payload_dir.join("output.zip")).unwrap();
+        assert_eq!(
+            response.headers()[CHECKSUM_HEADER],
+            format!("{:x}", Sha256::digest(&archive)).as_str()
+        );
+        let bytes = body_bytes(response).await;
+        let retrieved: Payload = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(retrieved.id, payload_id);
     }
 
     #[tokio::test]
@@ -407,7 +1959,7 @@ mod tests {
             .await
             .unwrap();
 
-        let app = create_client_routes(pool, config);
+        let app = create_client_routes(pool, config, Client::default());
 
         let request = Request::builder()
             .method("GET")
@@ -424,7 +1976,7 @@ mod tests {
         let tempdir = TempDir::new().unwrap();
         let pool = setup_test_db().await;
         let config = make_config(tempdir.path().to_str().unwrap());
-        let app = create_client_routes(pool, config);
+        let app = create_client_routes(pool, config, Client::default());
 
         let request = Request::builder()
             .method("GET")
@@ -437,6 +1989,35 @@ mod tests {
     }
 
     #[tokio::test]
+    async fn test_retrieve_partial_output_too_large() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let config = make_config(tempdir.path().to_str().unwrap());
+
+        let mut payload = Payload::new();
+        payload.max_output_size = Some(8);
+        payload.add_to_db(&pool).await.unwrap();
+        let payload_dir = tempdir.path().join(payload.id.to_string());
+        fs::create_dir_all(&payload_dir).unwrap();
+        fs::write(payload_dir.join("test.txt"), b"more than eight bytes").unwrap();
+        payload.set_loc(payload_dir);
+        payload.update_loc(&pool).await.unwrap();
+
+        let app = create_client_routes(pool, config, Client::default());
+        let request = Request::builder()
+            .method("GET")
+            .uri(format!("/retrieve_partial/{}", payload.id))
+            .body(Body::empty())
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let error: ErrorBody = serde_json::from_slice(&body_bytes(response).await).unwrap();
+        assert_eq!(error.code, ErrorCode::OutputTooLarge);
+    }
+
+    #[tokio::test]
+    #[allow(clippy::needless_borrows_for_generic_args, clippy::len_zero)]
     async fn test_retrieve_partial_non_completed() {
         let tempdir = TempDir::new().unwrap();
         let pool = setup_test_db().await;
@@ -458,7 +2039,7 @@ mod tests {
 
         let payload_id = payload.id;
 
-        let app = create_client_routes(pool, config);
+        let app = create_client_routes(pool, config, Client::default());
 
         let request = Request::builder()
             .method("GET")
@@ -512,7 +2093,7 @@ mod tests {
             .await
             .unwrap();
 
-        let app = create_client_routes(pool, config);
+        let app = create_client_routes(pool, config, Client::default());
 
         let request = Request::builder()
             .method("GET")
@@ -546,7 +2127,7 @@ mod tests {
         payload.set_loc(invalid_dir);
         payload.update_loc(&pool).await.unwrap();
 
-        let app = create_client_routes(pool, config);
+        let app = create_client_routes(pool, config, Client::default());
 
         let request = Request::builder()
             .method("GET")
@@ -558,8 +2139,8 @@ mod tests {
         assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
 
         let bytes = body_bytes(response).await;
-        let payload_resp: Payload = serde_json::from_slice(&bytes).unwrap();
-        assert_eq!(payload_resp.id, 0);
+        let error: ErrorBody = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(error.code, ErrorCode::ArchiveFailed);
     }
 
     #[tokio::test]
@@ -567,7 +2148,7 @@ mod tests {
         let tempdir = TempDir::new().unwrap();
         let pool = setup_test_db().await;
         let config = make_config(tempdir.path().to_str().unwrap());
-        let app = create_client_routes(pool, config);
+        let app = create_client_routes(pool, config, Client::default());
 
         let request = Request::builder()
             .method("GET")
@@ -579,19 +2160,69 @@ mod tests {
         assert_eq!(response.status(), StatusCode::OK);
 
         let bytes = body_bytes(response).await;
-        let load: f32 = serde_json::from_slice(&bytes).unwrap();
+        let load: LoadReport = serde_json::from_slice(&bytes).unwrap();
         assert!(
-            load.is_finite(),
-            "CPU load should be a finite number, got {load}"
+            load.cpu.is_finite(),
+            "CPU load should be a finite number, got {}",
+            load.cpu
         );
     }
 
+    #[tokio::test]
+    async fn test_stats() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let config = make_config(tempdir.path().to_str().unwrap());
+
+        let mut payload = Payload::new();
+        payload.service = Some("svc".to_string());
+        payload.add_to_db(&pool).await.unwrap();
+        payload.update_status(Status::Running, &pool).await.unwrap();
+        payload.update_status(Status::Failed, &pool).await.unwrap();
+
+        let app = create_client_routes(pool, config, Client::default());
+        let request = Request::builder()
+            .method("GET")
+            .uri("/stats")
+            .body(Body::empty())
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let stats: BTreeMap<String, ServiceRunStats> =
+            serde_json::from_slice(&body_bytes(response).await).unwrap();
+        assert_eq!(stats["svc"].runs, 1);
+        assert_eq!(stats["svc"].failure_rate, 1.0);
+        assert_eq!(stats["svc"].p50_secs, None);
+    }
+
+    #[tokio::test]
+    async fn test_capabilities() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let config = make_config(tempdir.path().to_str().unwrap());
+        let app = create_client_routes(pool, config.clone(), Client::default());
+
+        let request = Request::builder()
+            .method("GET")
+            .uri("/capabilities")
+            .body(Body::empty())
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let bytes = body_bytes(response).await;
+        let caps: Capabilities = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(caps, Capabilities::detect(&config));
+    }
+
     #[tokio::test]
     async fn test_kill_not_found() {
         let tempdir = TempDir::new().unwrap();
         let pool = setup_test_db().await;
         let config = make_config(tempdir.path().to_str().unwrap());
-        let app = create_client_routes(pool, config);
+        let app = create_client_routes(pool, config, Client::default());
 
         let request = Request::builder()
             .method("POST")
@@ -608,7 +2239,7 @@ mod tests {
         let tempdir = TempDir::new().unwrap();
         let pool = setup_test_db().await;
         let config = make_config(tempdir.path().to_str().unwrap());
-        let app = create_client_routes(pool.clone(), config);
+        let app = create_client_routes(pool.clone(), config, Client::default());
 
         // Create a payload
         let mut payload = Payload::new();
@@ -628,4 +2259,127 @@ mod tests {
         let retrieved = Payload::retrieve_id(payload_id, &pool).await.unwrap();
         assert!(retrieved.killed);
     }
+
+    #[tokio::test]
+    async fn test_list_and_delete_payloads() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let config = make_config(tempdir.path().to_str().unwrap());
+        let app = create_client_routes(pool.clone(), config.clone(), Client::default());
+
+        let mut payload = Payload::new();
+        payload.add_to_db(&pool).await.unwrap();
+        payload
+            .prepare(&config.data_path, &UploadChecks::default())
+            .await
+            .unwrap();
+        let dir = payload.dir(&config.data_path);
+        assert!(dir.exists());
+
+        let list = |uri: &str| Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(list("/payloads")).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(
+            serde_json::from_slice::<Vec<u32>>(&body).unwrap(),
+            vec![payload.id]
+        );
+
+        let response = app
+            .clone()
+            .oneshot(list("/payloads?older_than=600"))
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(
+            serde_json::from_slice::<Vec<u32>>(&body)
+                .unwrap()
+                .is_empty()
+        );
+
+        let delete = |id: u32| {
+            Request::builder()
+                .method("DELETE")
+                .uri(format!("/payloads/{id}"))
+                .body(Body::empty())
+                .unwrap()
+        };
+        let response = app.clone().oneshot(delete(payload.id)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert!(!dir.exists());
+        let retrieved = Payload::retrieve_id(payload.id, &pool).await.unwrap();
+        assert_eq!(retrieved.status, Status::Cleaned);
+
+        let response = app.clone().oneshot(list("/payloads")).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(
+            serde_json::from_slice::<Vec<u32>>(&body)
+                .unwrap()
+                .is_empty()
+        );
+
+        let response = app.oneshot(delete(9999)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_payload() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let config = make_config(tempdir.path().to_str().unwrap());
+        let app = create_client_routes(pool.clone(), config.clone(), Client::default());
+
+        let mut payload = Payload::new();
+        payload.add_to_db(&pool).await.unwrap();
+        payload
+            .prepare(&config.data_path, &UploadChecks::default())
+            .await
+            .unwrap();
+        payload
+            .update_status(Status::Prepared, &pool)
+            .await
+            .unwrap();
+        let dir = payload.dir(&config.data_path);
+        assert!(dir.exists());
+
+        let cancel = |id: u32| {
+            Request::builder()
+                .method("POST")
+                .uri(format!("/cancel/{id}"))
+                .body(Body::empty())
+                .unwrap()
+        };
+        let response = app.clone().oneshot(cancel(payload.id)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert!(!dir.exists());
+        let retrieved = Payload::retrieve_id(payload.id, &pool).await.unwrap();
+        assert_eq!(retrieved.status, Status::Cancelled);
+
+        // Asked again by the getter, the directory being gone already
+        let response = app.clone().oneshot(cancel(payload.id)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        // Nothing left for the reconciler to delete
+        let list = Request::builder()
+            .uri("/payloads")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(list).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(
+            serde_json::from_slice::<Vec<u32>>(&body)
+                .unwrap()
+                .is_empty()
+        );
+
+        let response = app.oneshot(cancel(9999)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
 }
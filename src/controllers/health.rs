@@ -1,8 +1,10 @@
 use crate::models::health_dto::Health;
 use crate::routes::router::AppState;
+use crate::utils::storage::StorageHealth;
+use crate::utils::warmup::{Warmup, WarmupReport};
 use axum::extract::State;
 use axum::http::StatusCode;
-use axum::Json;
+use axum::{Extension, Json};
 use utoipa;
 
 #[utoipa::path(
@@ -10,11 +12,24 @@ use utoipa;
     path = "/health",
     responses(
         (status = 200, description = "Service is healthy", body = Health),
-        (status = 503, description = "Service is unhealthy")
+        (status = 503, description = "Service is unhealthy, DATA_PATH is not writable, or the client has not warmed up")
     ),
     tag = "health"
 )]
-pub async fn health(State(state): State<AppState>) -> Result<Json<Health>, StatusCode> {
+pub async fn health(
+    State(state): State<AppState>,
+    warmup: Option<Extension<Warmup>>,
+    storage: Option<Extension<StorageHealth>>,
+) -> Result<Json<Health>, StatusCode> {
+    // A client is only healthy once its warm-up command succeeded
+    if warmup.is_some_and(|Extension(w)| !w.is_ready()) {
+        return Err(StatusCode::SERVICE_UNAVAILABLE);
+    }
+    // A server refusing submissions is taken out of rotation
+    if storage.is_some_and(|Extension(s)| s.problem().is_some()) {
+        return Err(StatusCode::SERVICE_UNAVAILABLE);
+    }
+
     // Check database connectivity
     let db_status = match sqlx::query("SELECT 1").execute(&state.pool).await {
         Ok(_) => "ok",
@@ -24,29 +39,63 @@ pub async fn health(State(state): State<AppState>) -> Result<Json<Health>, Statu
     Ok(Json(Health {
         status: "ok".to_string(),
         database: db_status.to_string(),
+        version: Some(env!("CARGO_PKG_VERSION").to_string()),
     }))
 }
 
+#[utoipa::path(
+    get,
+    path = "/warmup",
+    responses(
+        (status = 200, description = "Outcome of the warm-up command", body = WarmupReport),
+        (status = 404, description = "The client has no warm-up command")
+    ),
+    tag = "health"
+)]
+pub async fn warmup(warmup: Option<Extension<Warmup>>) -> Result<Json<WarmupReport>, StatusCode> {
+    warmup
+        .and_then(|Extension(w)| w.report())
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::loader::Config;
-    use crate::routes::router::AppState;
+    use crate::config::loader::{Config, WarmupConfig};
+    use crate::routes::router::{AppState, create_client_routes, create_routes};
+    use crate::services::client::Client;
+    use crate::utils::warmup::WarmupState;
+    use axum::body::Body;
     use axum::extract::State;
+    use axum::http::Request;
     use sqlx::SqlitePool;
+    use std::time::Duration;
+    use tower::ServiceExt;
 
     #[tokio::test]
     async fn test_health_returns_ok() {
         let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
         let config = Config::new().unwrap();
-        let state = State(AppState { pool, config });
+        let state = State(AppState {
+            pool,
+            config,
+            client: Client::default(),
+            uploads: Default::default(),
+            sessions: Default::default(),
+            completions: Default::default(),
+        });
 
-        let response = health(state).await;
+        let response = health(state, None, None).await;
         assert!(response.is_ok());
 
         let health_response = response.unwrap().0;
         assert_eq!(health_response.status, "ok");
         assert_eq!(health_response.database, "ok");
+        assert_eq!(
+            health_response.version.as_deref(),
+            Some(env!("CARGO_PKG_VERSION"))
+        );
     }
 
     #[tokio::test]
@@ -57,13 +106,79 @@ mod tests {
         // Close the pool to simulate database unavailability
         pool.close().await;
 
-        let state = State(AppState { pool, config });
+        let state = State(AppState {
+            pool,
+            config,
+            client: Client::default(),
+            uploads: Default::default(),
+            sessions: Default::default(),
+            completions: Default::default(),
+        });
 
-        let response = health(state).await;
+        let response = health(state, None, None).await;
         assert!(response.is_err());
         assert_eq!(response.unwrap_err(), StatusCode::SERVICE_UNAVAILABLE);
     }
 
+    #[tokio::test]
+    async fn test_health_waits_for_warmup() {
+        let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+        let config = Config::new().unwrap();
+        let app = create_client_routes(pool, config, Client::default());
+        let warmup = Warmup::default();
+        let app = app.layer(Extension(warmup.clone()));
+
+        let get = |uri: &str| Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(get("/warmup")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let failing = WarmupConfig {
+            command: "exit 3".to_string(),
+            timeout: Duration::from_secs(5),
+        };
+        warmup.run(&failing).await;
+        let response = app.clone().oneshot(get("/health")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let response = app.clone().oneshot(get("/warmup")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let report: WarmupReport = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(report.state, WarmupState::Failed);
+        assert_eq!(report.exit_code, Some(3));
+
+        warmup
+            .run(&WarmupConfig {
+                command: "true".to_string(),
+                ..failing
+            })
+            .await;
+        let response = app.oneshot(get("/health")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_health_degraded_storage() {
+        let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+        let app = create_routes(pool, Config::new().unwrap(), Client::default());
+        let storage = StorageHealth::default();
+        let app = app.layer(Extension(storage.clone()));
+
+        let get = || {
+            Request::builder()
+                .uri("/health")
+                .body(Body::empty())
+                .unwrap()
+        };
+        let response = app.clone().oneshot(get()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        storage.degrade("No space left on device");
+        let response = app.oneshot(get()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
     #[tokio::test]
     async fn test_health_invalid_connection() {
         // Try to connect to an invalid database path
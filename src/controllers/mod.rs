@@ -1,4 +1,11 @@
+pub mod admin;
+pub mod auth;
 pub mod client;
 pub mod health;
 pub mod ping;
+pub mod quota;
 pub mod server;
+pub mod services;
+pub mod shares;
+pub mod uploads;
+pub mod users;
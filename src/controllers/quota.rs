@@ -0,0 +1,114 @@
+use crate::models::quota_dao::Quota;
+use crate::models::status_body::StatusBody;
+use crate::models::user_dao::User;
+use crate::routes::router::AppState;
+use axum::response::{IntoResponse, Response};
+use axum::{
+    extract::{Json, Query, State},
+    http::StatusCode,
+};
+use serde::Deserialize;
+use utoipa::{self, IntoParams};
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct QuotaParams {
+    /// User whose quotas are returned
+    pub user_id: Option<u32>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/quota",
+    params(QuotaParams),
+    responses(
+        (status = 200, description = "Limits, active jobs and remaining slots per service", body = Quota),
+        (status = 400, description = "Missing user_id", body = StatusBody),
+        (status = 404, description = "Unknown user", body = StatusBody),
+        (status = 500, description = "Internal server error", body = StatusBody),
+    ),
+    tag = "users"
+)]
+pub async fn quota(State(state): State<AppState>, Query(params): Query<QuotaParams>) -> Response {
+    let mut body = StatusBody::new();
+
+    let Some(user_id) = params.user_id else {
+        body.message = "Missing user_id parameter".to_string();
+        return (StatusCode::BAD_REQUEST, Json(body)).into_response();
+    };
+
+    let user = match User::retrieve_id(user_id, &state.pool).await {
+        Ok(u) => u,
+        Err(sqlx::Error::RowNotFound) => {
+            body.message = format!("User {user_id} not found in the database");
+            return (StatusCode::NOT_FOUND, Json(body)).into_response();
+        }
+        Err(e) => {
+            tracing::error!("Could not retrieve user {user_id}: {:?}", e);
+            body.message = "Internal server error".to_string();
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response();
+        }
+    };
+
+    match Quota::load(&user, &state.config, &state.pool).await {
+        Ok(quota) => Json(quota).into_response(),
+        Err(e) => {
+            tracing::error!("Could not compute the quota of user {user_id}: {:?}", e);
+            body.message = "Internal server error".to_string();
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::loader::{Config, Service};
+    use crate::models::job_dto::create_jobs_table;
+    use crate::models::user_dao::User;
+    use crate::routes::router::create_routes;
+    use crate::services::client::Client;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use sqlx::SqlitePool;
+    use tower::ServiceExt;
+
+    async fn get(uri: &str) -> (StatusCode, serde_json::Value) {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        create_jobs_table(&pool).await.unwrap();
+        User::add_if_missing(1, &pool).await.unwrap();
+
+        let mut config = Config::default();
+        config.services.insert(
+            "test".to_string(),
+            Service {
+                name: "test".to_string(),
+                runs_per_user: 5,
+                max_runs: 10,
+                ..Default::default()
+            },
+        );
+        let app = create_routes(pool, config, Client::default());
+
+        let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_quota() {
+        let (status, body) = get("/quota?user_id=1").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["user_id"], 1);
+        assert_eq!(body["services"][0]["service"], "test");
+        assert_eq!(body["services"][0]["remaining"], 5);
+    }
+
+    #[tokio::test]
+    async fn test_quota_errors() {
+        assert_eq!(get("/quota").await.0, StatusCode::BAD_REQUEST);
+        assert_eq!(get("/quota?user_id=99").await.0, StatusCode::NOT_FOUND);
+    }
+}
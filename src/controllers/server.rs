@@ -1,19 +1,40 @@
-use crate::models::job_dao::Job;
+use crate::controllers::admin::{claims_admin, reject_unauthorized};
+use crate::controllers::shares::use_share;
+use crate::models::download_dao::{Download, VIA_DOWNLOAD, VIA_GROUP, VIA_LINK, VIA_SHARE};
+use crate::models::job_dao::{Job, JobEta, JobMetadata, JobMetadataPatch, MAX_PRIORITY};
+use crate::models::job_event_dao::{JobEvent, SERVER_ACTOR, StatusChange};
+use crate::models::job_note_dao::JobNote;
+use crate::models::manifest_dto::ManifestEntry;
 use crate::models::status_body::StatusBody;
 use crate::models::status_dto::Status;
+use crate::models::user_dao::User;
+use crate::models::webhook_dao::WebhookDelivery;
+use crate::routes::access_log::AccessUser;
+use crate::routes::allowlist::client_ip;
 use crate::routes::router::AppState;
-use crate::services::client::Client;
 use crate::services::endpoint;
+use crate::services::hooks::{HookError, HookPoint, Hooks};
+use crate::services::remote_input::{self, RemoteInput};
 use crate::services::server;
-use crate::utils::io::{sanitize_filename, save_file};
+use crate::utils::download_link;
+use crate::utils::io::{ChannelWriter, sanitize_filename, save_file, write_combined_archive};
+use crate::utils::storage::write_error_status;
+use crate::utils::template;
+use axum::body::Body;
 use axum::response::{IntoResponse, Response};
 use axum::{
-    extract::{Json, Multipart, Path, State},
-    http::{StatusCode, header},
+    Extension,
+    extract::{ConnectInfo, Json, Multipart, Path, Query, State},
+    http::{HeaderMap, StatusCode, header},
 };
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::time::SystemTime;
 use tokio::fs::create_dir_all;
-use utoipa;
+use tokio_stream::wrappers::ReceiverStream;
+use utoipa::{self, IntoParams, ToSchema};
 
 #[utoipa::path(
     get,
@@ -24,12 +45,60 @@ use utoipa;
     responses(
         (status = 200, description = "Job completed — returns zip file", content_type = "application/zip", body = Vec<u8>),
         (status = 200, description = "Job not yet complete — returns current job status", body = StatusBody),
+        (status = 304, description = "Archive matches the ETag given in If-None-Match"),
         (status = 404, description = "Not found", body = StatusBody),
         (status = 500, description = "Internal server error", body = StatusBody),
     ),
     tag = "files"
 )]
-pub async fn download(State(state): State<AppState>, Path(id): Path<u32>) -> Response {
+pub async fn download(
+    State(state): State<AppState>,
+    Path(id): Path<u32>,
+    connect: Option<Extension<ConnectInfo<SocketAddr>>>,
+    headers: HeaderMap,
+) -> Response {
+    let caller = caller_ip(&state, connect, &headers);
+    serve_results(&state, id, &headers, VIA_DOWNLOAD, caller).await
+}
+
+/// Address of the caller, looking past the proxies trusted by the management allowlist
+fn caller_ip(
+    state: &AppState,
+    connect: Option<Extension<ConnectInfo<SocketAddr>>>,
+    headers: &HeaderMap,
+) -> Option<String> {
+    let Extension(ConnectInfo(addr)) = connect?;
+    let trusted = state
+        .config
+        .management_allowlist
+        .as_ref()
+        .map_or(&[][..], |a| a.trusted_proxies.as_slice());
+    Some(client_ip(addr.ip(), headers, trusted).to_string())
+}
+
+/// Keep track of an archive handed out, a failure only costs the audit entry
+async fn record_download(
+    state: &AppState,
+    job_id: u32,
+    client_ip: Option<String>,
+    via: &str,
+    bytes: i64,
+) {
+    let mut download = Download::new(job_id, client_ip, via, bytes);
+    if let Err(e) = download.add_to_db(&state.pool).await {
+        tracing::error!("Could not record the download of job {job_id}: {:?}", e);
+    }
+}
+
+/// The result archive of a completed job, its status otherwise. Archives
+/// handed out are recorded with the endpoint and caller they went to.
+pub async fn serve_results(
+    state: &AppState,
+    id: u32,
+    headers: &HeaderMap,
+    via: &str,
+    caller: Option<String>,
+) -> Response {
     let mut job = Job::new(&state.config.data_path);
     let mut body = StatusBody::new();
 
@@ -57,508 +126,3372 @@ pub async fn download(State(state): State<AppState>, Path(id): Path<u32>) -> Res
     body.status = job.status;
 
     match job.status {
-        Status::Completed => match job.download() {
-            Ok(data) => ([(header::CONTENT_TYPE, "application/zip")], data).into_response(),
-            Err(e) => {
-                tracing::error!("Error reading output file: {:?}", e);
-                body.message = "Error reading output file".to_string();
-                (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response()
+        Status::Completed => {
+            // Jobs completed before checksums were recorded get one on first download
+            let checksum = match job.checksum.clone() {
+                Some(c) => c,
+                None => match job.compute_checksum() {
+                    Ok(c) => {
+                        if let Err(e) = job.update_checksum(c.clone(), &state.pool).await {
+                            tracing::error!("Failed to store checksum of job {id}: {:?}", e);
+                        }
+                        c
+                    }
+                    Err(e) => {
+                        tracing::error!("Error reading output file: {:?}", e);
+                        body.message = "Error reading output file".to_string();
+                        return (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response();
+                    }
+                },
+            };
+            let etag = format!("\"{checksum}\"");
+
+            if etag_matches(headers, &checksum) {
+                return (StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response();
             }
-        },
-        _ => Json(body).into_response(),
+
+            if let Some(key) = &job.remote_key {
+                let response = download_remote(state, key, etag, body).await;
+                if response.status().is_success() {
+                    let bytes = job.archive_size.unwrap_or_default();
+                    record_download(state, id, caller, via, bytes).await;
+                }
+                return response;
+            }
+
+            match job.download() {
+                Ok(data) => {
+                    record_download(state, id, caller, via, data.len() as i64).await;
+                    (
+                        [
+                            (header::CONTENT_TYPE, "application/zip".to_string()),
+                            (header::ETAG, etag),
+                        ],
+                        data,
+                    )
+                        .into_response()
+                }
+                Err(e) => {
+                    tracing::error!("Error reading output file: {:?}", e);
+                    body.message = "Error reading output file".to_string();
+                    (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response()
+                }
+            }
+        }
+        _ => {
+            if let Some(reason) = job.review_reason {
+                body.message = reason;
+            }
+            // Queued jobs of a service outside its execution windows say so
+            if job.status == Status::Queued
+                && let Some(reason) = state
+                    .config
+                    .services
+                    .get(&job.service)
+                    .and_then(|s| s.closed_reason(SystemTime::now()))
+            {
+                body.message = reason;
+            }
+            Json(body).into_response()
+        }
+    }
+}
+
+/// Stream an archive that was moved to the object store back to the caller
+async fn download_remote(
+    state: &AppState,
+    key: &str,
+    etag: String,
+    mut body: StatusBody,
+) -> Response {
+    let result = match &state.config.object_store {
+        Some(store) => state.client.get_object(store, key).await,
+        None => {
+            tracing::error!(
+                "Job {} is in the object store, which is not configured",
+                body.id
+            );
+            body.message = "Error reading output file".to_string();
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response();
+        }
+    };
+
+    match result {
+        Ok(stream) => (
+            [
+                (header::CONTENT_TYPE, "application/zip".to_string()),
+                (header::ETAG, etag),
+            ],
+            Body::from_stream(stream),
+        )
+            .into_response(),
+        Err(e) => {
+            tracing::error!("Error reading {key} from the object store: {:?}", e);
+            body.message = "Error reading output file".to_string();
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response()
+        }
     }
 }
 
+/// Check whether any entity tag in `If-None-Match` matches the given checksum
+fn etag_matches(headers: &HeaderMap, checksum: &str) -> bool {
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|tag| tag.trim())
+        .any(|tag| {
+            // If-None-Match uses the weak comparison function, so a `W/` prefix is ignored
+            let tag = tag.strip_prefix("W/").unwrap_or(tag);
+            tag == "*" || tag.trim_matches('"') == checksum
+        })
+}
+
+/// A job as shown by `GET /jobs/{id}`
+#[derive(Debug, Serialize, ToSchema)]
+pub struct JobDetail {
+    #[serde(flatten)]
+    pub job: Job,
+    /// Operator notes, only shown to requests carrying the admin token
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notes: Option<Vec<JobNote>>,
+    /// Result downloads, oldest first, only shown to requests carrying the admin token
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub downloads: Option<Vec<Download>>,
+}
+
 #[utoipa::path(
     get,
-    path = "/download_partial/{id}",
+    path = "/jobs/{id}",
     params(
         ("id" = u32, Path, description = "Job identifier")
     ),
     responses(
-        (status = 200, description = "Returns zip file of current job state, regardless of completion", content_type = "application/zip", body = Vec<u8>),
+        (status = 200, description = "Job details, including the result metadata once downloaded", body = JobDetail),
+        (status = 401, description = "Invalid admin token", body = StatusBody),
         (status = 404, description = "Not found", body = StatusBody),
         (status = 500, description = "Internal server error", body = StatusBody),
     ),
-    tag = "files"
+    security((), ("admin_token" = [])),
+    tag = "jobs"
 )]
-pub async fn download_partial(State(state): State<AppState>, Path(id): Path<u32>) -> Response {
-    let mut job = Job::new(&state.config.data_path);
-    let mut body = StatusBody::new();
+pub async fn get_job(
+    State(state): State<AppState>,
+    Path(id): Path<u32>,
+    headers: HeaderMap,
+) -> Response {
+    // Operators also get the notes attached to the job and who downloaded it
+    let is_admin = claims_admin(&headers, &state.sessions);
+    if is_admin
+        && let Some(response) = reject_unauthorized(&headers, &state.config, &state.sessions)
+    {
+        return response;
+    }
 
-    let result = job.retrieve_id(id, &state.pool).await;
+    let mut job = Job::new(&state.config.data_path);
 
-    if let Err(e) = result {
-        // Error when retrieving this id, check what kind of error it was
+    if let Err(e) = job.retrieve_id(id, &state.pool).await {
+        let mut body = StatusBody::new();
         let status = match e {
-            // It is not found on the database
             sqlx::Error::RowNotFound => {
                 body.message = format!("Job {id} not found in the database");
                 StatusCode::NOT_FOUND
             }
-            // Something else
             _ => {
                 body.message = "Internal server error".to_string();
                 StatusCode::INTERNAL_SERVER_ERROR
             }
         };
-        // Return the error
         return (status, Json(body)).into_response();
     }
 
-    body.id = job.id;
-
-    // Call the client's retrieve_partial endpoint to get the current state
-    match endpoint::retrieve_partial(&job, &state.config, Client).await {
-        Ok(data) => ([(header::CONTENT_TYPE, "application/zip")], data).into_response(),
-        Err(e) => {
-            tracing::error!("Error retrieving partial data from client: {:?}", e);
-            let status = match e {
-                endpoint::DownloadPartialError::NotFound => {
-                    body.message = "Job payload not found on client".to_string();
-                    StatusCode::NOT_FOUND
-                }
-                endpoint::DownloadPartialError::InvalidService => {
-                    body.message = "Invalid service configuration".to_string();
-                    StatusCode::BAD_REQUEST
-                }
-                _ => {
-                    body.message = "Error retrieving partial data from client".to_string();
-                    StatusCode::INTERNAL_SERVER_ERROR
-                }
-            };
-            (status, Json(body)).into_response()
+    let (notes, downloads) = if is_admin {
+        let audit = tokio::try_join!(
+            JobNote::list_for_job(id, &state.pool),
+            Download::list_for_job(id, &state.pool)
+        );
+        match audit {
+            Ok((notes, downloads)) => (Some(notes), Some(downloads)),
+            Err(e) => {
+                tracing::error!(
+                    "Could not retrieve the notes and downloads of job {id}: {:?}",
+                    e
+                );
+                let mut body = StatusBody::new();
+                body.message = "Internal server error".to_string();
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response();
+            }
         }
-    }
+    } else {
+        (None, None)
+    };
+
+    Json(JobDetail {
+        job,
+        notes,
+        downloads,
+    })
+    .into_response()
 }
 
 #[utoipa::path(
-    post,
-    path = "/upload",
-    request_body(
-        content_type = "multipart/form-data",
-        description = "Upload a file and metadata fields as multipart/form-data. \
-        The request must include a file field (with any filename and content type), a 'user_id' field (integer), and a 'service' field (string). \
-        Additional fields may be included as needed."
+    get,
+    path = "/jobs/{id}/webhooks",
+    params(
+        ("id" = u32, Path, description = "Job identifier")
     ),
     responses(
-        (status = 201, description = "File uploaded successfully", body = StatusBody),
-        (status = 400, description = "Bad request"),
-        (status = 500, description = "Internal server error"),
+        (status = 200, description = "Callbacks of the job with the outcome of their last attempt, oldest first", body = Vec<WebhookDelivery>),
+        (status = 404, description = "Not found", body = StatusBody),
+        (status = 500, description = "Internal server error", body = StatusBody),
     ),
-    tag = "files"
+    tag = "jobs"
 )]
-pub async fn upload(State(state): State<AppState>, mut multipart: Multipart) -> Response {
-    // Create a new job with unique ID
-    let mut job = Job::new(&state.config.data_path);
-
-    // Create job directory
-    if create_dir_all(&job.loc).await.is_err() {
-        let mut body = StatusBody::new();
-        body.message = "Could not create job directory".to_string();
-        return (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response();
-    }
-
-    // FIXME: Move the parsing of the form to a helper function
-    let mut text_fields = HashMap::new();
-    let mut file_count = 0;
+pub async fn get_job_webhooks(State(state): State<AppState>, Path(id): Path<u32>) -> Response {
     let mut body = StatusBody::new();
-    loop {
-        let field = multipart.next_field().await;
-        let field = match field {
-            Ok(Some(f)) => f,
-            Ok(None) => break,
-            Err(e) => {
-                tracing::error!("Multipart error: {e}");
-                body.message = format!("Multipart error: {e}");
-                return (StatusCode::BAD_REQUEST, Json(body)).into_response();
-            }
-        };
-
-        let field_name = field.name().unwrap_or("unnamed").to_string();
-
-        if let Some(filename) = field.file_name() {
-            file_count += 1;
-            let filename = sanitize_filename(filename);
-            let file_path = job.loc.join(&filename);
 
-            tracing::info!("Saving file: {} to {}", filename, file_path.display());
-
-            if let Err((err_code, err_msg)) = save_file(field, &file_path).await {
-                body.message = format!("Could not save file: {err_msg}");
-                return (err_code, Json(body)).into_response();
+    let mut job = Job::new(&state.config.data_path);
+    if let Err(e) = job.retrieve_id(id, &state.pool).await {
+        let status = match e {
+            sqlx::Error::RowNotFound => {
+                body.message = format!("Job {id} not found in the database");
+                StatusCode::NOT_FOUND
             }
-        } else {
-            let text = match field.text().await {
-                Ok(t) => t,
-                Err(e) => {
-                    tracing::error!("Error reading text field: {e}");
-                    body.message = format!("Error reading text field: {e}");
-                    return (StatusCode::BAD_REQUEST, Json(body)).into_response();
-                }
-            };
-            text_fields.insert(field_name, text);
-        }
+            _ => {
+                body.message = "Internal server error".to_string();
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+        return (status, Json(body)).into_response();
     }
 
-    tracing::info!(
-        "Upload completed: {} files saved, {} text fields",
-        file_count,
-        text_fields.len()
-    );
-    // Now handle special fields
-
-    // Get the user id from the request
-    let uid_str = match text_fields.get("user_id") {
-        Some(v) => v,
-        None => {
-            body.message = "Missing user_id field".to_string();
-            return (StatusCode::BAD_REQUEST, Json(body)).into_response();
-        }
-    };
-
-    // Check if this user id is valid, it must be a number
-    let uid = match uid_str.parse::<i32>() {
-        Ok(v) => v,
-        Err(_) => {
-            body.message = "Invalid user_id, should be a number".to_string();
-            return (StatusCode::BAD_REQUEST, Json(body)).into_response();
-        }
-    };
-
-    // Get service from the request
-    let service = match text_fields.get("service") {
-        Some(v) => v,
-        None => {
-            body.message = "Missing service field".to_string();
-            return (StatusCode::BAD_REQUEST, Json(body)).into_response();
+    match WebhookDelivery::list_for_job(id, &state.pool).await {
+        Ok(deliveries) => Json(deliveries).into_response(),
+        Err(e) => {
+            tracing::error!("Could not retrieve the callbacks of job {id}: {:?}", e);
+            body.message = "Internal server error".to_string();
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response()
         }
-    };
-
-    // Validate service exists
-    if !state.config.services.contains_key(service) {
-        body.message = "Invalid service".to_string();
-        return (StatusCode::BAD_REQUEST, Json(body)).into_response();
     }
-
-    job.set_user_id(uid);
-    job.set_service(service.to_string());
-
-    // Add job to database
-    let Ok(_) = job.add_to_db(&state.pool).await else {
-        body.message = "Error while adding the job to the database".to_string();
-        return (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response();
-    };
-
-    let Ok(_) = job.update_status(Status::Queued, &state.pool).await else {
-        body.message = format!("Could not update the status of job {0}", job.id);
-        return (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response();
-    };
-
-    // Everything went fine
-    body.status = job.status;
-    body.id = job.id;
-    body.message = "Job successfully uploaded".to_string();
-
-    (StatusCode::CREATED, Json(body)).into_response()
 }
 
 #[utoipa::path(
-    post,
-    path = "/terminate/{id}",
+    get,
+    path = "/jobs/{id}/events",
     params(
         ("id" = u32, Path, description = "Job identifier")
     ),
-    responses(),
-    tag = "files"
+    responses(
+        (status = 200, description = "Recorded status changes of the job, oldest first, with the time it spent in each status and where the cleaner put its files", body = Vec<StatusChange>),
+        (status = 404, description = "Not found", body = StatusBody),
+        (status = 500, description = "Internal server error", body = StatusBody),
+    ),
+    tag = "jobs"
 )]
-pub async fn terminate(State(state): State<AppState>, Path(id): Path<u32>) -> Response {
-    // 1. Get a job from the id
-    let mut job = Job::new(&state.config.data_path);
+pub async fn get_job_events(State(state): State<AppState>, Path(id): Path<u32>) -> Response {
     let mut body = StatusBody::new();
-    let result = job.retrieve_id(id, &state.pool).await;
-    if let Err(e) = result {
-        // Error when retrieving this id, check what kind of error it was
+
+    let mut job = Job::new(&state.config.data_path);
+    if let Err(e) = job.retrieve_id(id, &state.pool).await {
         let status = match e {
-            // It is not found on the database
             sqlx::Error::RowNotFound => {
                 body.message = format!("Job {id} not found in the database");
                 StatusCode::NOT_FOUND
             }
-            // Something else
             _ => {
                 body.message = "Internal server error".to_string();
                 StatusCode::INTERNAL_SERVER_ERROR
             }
         };
-        // Return the error
         return (status, Json(body)).into_response();
     }
 
-    body.id = job.id;
-
-    // 2. Send termination signal to client
-    let status = match server::terminate_job(job, state.pool.clone(), state.config.clone()).await {
-        Ok(_) => {
-            body.message = "job terminated".to_string();
-            StatusCode::OK
-        }
-        Err(_) => {
-            body.message = "could not terminate job".to_string();
-            StatusCode::INTERNAL_SERVER_ERROR
+    match JobEvent::history(id, &state.pool).await {
+        Ok(history) => Json(history).into_response(),
+        Err(e) => {
+            tracing::error!("Could not retrieve the events of job {id}: {:?}", e);
+            body.message = "Internal server error".to_string();
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response()
         }
-    };
+    }
+}
 
-    (status, Json(body)).into_response()
+#[utoipa::path(
+    get,
+    path = "/jobs/{id}/history",
+    params(
+        ("id" = u32, Path, description = "Job identifier")
+    ),
+    responses(
+        (status = 200, description = "Same as `/jobs/{id}/events`", body = Vec<StatusChange>),
+        (status = 404, description = "Not found", body = StatusBody),
+        (status = 500, description = "Internal server error", body = StatusBody),
+    ),
+    tag = "jobs"
+)]
+pub async fn get_job_history(state: State<AppState>, id: Path<u32>) -> Response {
+    get_job_events(state, id).await
 }
 
-#[cfg(test)]
-mod tests {
-    use crate::config::loader::{Config, Service};
-    use crate::models::job_dao::Job;
+#[utoipa::path(
+    get,
+    path = "/jobs/{id}/inputs",
+    params(
+        ("id" = u32, Path, description = "Job identifier")
+    ),
+    responses(
+        (status = 200, description = "Files the job was submitted with, by path", body = Vec<ManifestEntry>),
+        (status = 404, description = "Not found, or submitted before inputs were recorded", body = StatusBody),
+        (status = 500, description = "Internal server error", body = StatusBody),
+    ),
+    tag = "jobs"
+)]
+pub async fn get_job_inputs(State(state): State<AppState>, Path(id): Path<u32>) -> Response {
+    let mut body = StatusBody::new();
+
+    match Job::retrieve_inputs(id, &state.pool).await {
+        Ok(Some(inputs)) => Json(inputs).into_response(),
+        Ok(None) => {
+            body.message = format!("No inputs recorded for job {id}");
+            (StatusCode::NOT_FOUND, Json(body)).into_response()
+        }
+        Err(sqlx::Error::RowNotFound) => {
+            body.message = format!("Job {id} not found in the database");
+            (StatusCode::NOT_FOUND, Json(body)).into_response()
+        }
+        Err(e) => {
+            tracing::error!("Could not retrieve the inputs of job {id}: {:?}", e);
+            body.message = "Internal server error".to_string();
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/jobs/{id}/eta",
+    params(
+        ("id" = u32, Path, description = "Job identifier")
+    ),
+    responses(
+        (status = 200, description = "Estimated wait and runtime of the job, from its place in the queue and the latest runtimes of its service", body = JobEta),
+        (status = 404, description = "Not found", body = StatusBody),
+        (status = 409, description = "Job already finished", body = StatusBody),
+        (status = 500, description = "Internal server error", body = StatusBody),
+    ),
+    tag = "jobs"
+)]
+pub async fn get_job_eta(State(state): State<AppState>, Path(id): Path<u32>) -> Response {
+    let mut body = StatusBody::new();
+
+    let mut job = Job::new(&state.config.data_path);
+    if let Err(e) = job.retrieve_id(id, &state.pool).await {
+        let status = match e {
+            sqlx::Error::RowNotFound => {
+                body.message = format!("Job {id} not found in the database");
+                StatusCode::NOT_FOUND
+            }
+            _ => {
+                body.message = "Internal server error".to_string();
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+        return (status, Json(body)).into_response();
+    }
+
+    match job.status {
+        Status::Queued
+        | Status::Held
+        | Status::Locked
+        | Status::Processing
+        | Status::Submitted
+        | Status::Prepared
+        | Status::Running => {}
+        status => {
+            body.id = id;
+            body.status = status;
+            body.message = format!("Job {id} is already {status}");
+            return (StatusCode::CONFLICT, Json(body)).into_response();
+        }
+    }
+
+    match server::job_eta(&job, &state.pool, &state.config, &state.client).await {
+        Ok(eta) => Json(eta).into_response(),
+        Err(e) => {
+            tracing::error!("Could not estimate when job {id} is done: {:?}", e);
+            body.message = "Internal server error".to_string();
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response()
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct JobOwnerParams {
+    /// User making the change, must own the job unless the admin token is sent
+    pub user_id: Option<i32>,
+}
+
+#[utoipa::path(
+    patch,
+    path = "/jobs/{id}",
+    params(
+        ("id" = u32, Path, description = "Job identifier"),
+        JobOwnerParams
+    ),
+    request_body = JobMetadataPatch,
+    responses(
+        (status = 200, description = "Job updated", body = Job),
+        (status = 400, description = "Missing user_id or invalid field", body = StatusBody),
+        (status = 401, description = "Invalid admin token", body = StatusBody),
+        (status = 403, description = "Job belongs to another user", body = StatusBody),
+        (status = 404, description = "Not found", body = StatusBody),
+        (status = 500, description = "Internal server error", body = StatusBody),
+    ),
+    security((), ("admin_token" = [])),
+    tag = "jobs"
+)]
+pub async fn update_job(
+    State(state): State<AppState>,
+    Path(id): Path<u32>,
+    Query(params): Query<JobOwnerParams>,
+    headers: HeaderMap,
+    Json(patch): Json<JobMetadataPatch>,
+) -> Response {
+    let mut body = StatusBody::new();
+
+    let mut job = Job::new(&state.config.data_path);
+    if let Err(e) = job.retrieve_id(id, &state.pool).await {
+        let status = match e {
+            sqlx::Error::RowNotFound => {
+                body.message = format!("Job {id} not found in the database");
+                StatusCode::NOT_FOUND
+            }
+            _ => {
+                body.message = "Internal server error".to_string();
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+        return (status, Json(body)).into_response();
+    }
+
+    if let Some(response) = reject_non_owner(&state, &headers, params.user_id, &job) {
+        return response;
+    }
+
+    let metadata = job.metadata.patched(patch);
+    if let Err(message) = metadata.validate() {
+        body.message = message;
+        return (StatusCode::BAD_REQUEST, Json(body)).into_response();
+    }
+
+    if let Err(e) = job.update_metadata(metadata, &state.pool).await {
+        tracing::error!("Could not update the metadata of job {id}: {:?}", e);
+        body.message = "Internal server error".to_string();
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response();
+    }
+
+    Json(job).into_response()
+}
+
+#[utoipa::path(
+    delete,
+    path = "/jobs/{id}",
+    params(
+        ("id" = u32, Path, description = "Job identifier"),
+        JobOwnerParams
+    ),
+    responses(
+        (status = 200, description = "Job cancelled", body = StatusBody),
+        (status = 202, description = "Client not reached, the cancellation is retried", body = StatusBody),
+        (status = 400, description = "Missing user_id", body = StatusBody),
+        (status = 401, description = "Invalid admin token", body = StatusBody),
+        (status = 403, description = "Job belongs to another user", body = StatusBody),
+        (status = 404, description = "Not found", body = StatusBody),
+        (status = 409, description = "Job is being sent, or already finished", body = StatusBody),
+        (status = 500, description = "Internal server error", body = StatusBody),
+    ),
+    security((), ("admin_token" = [])),
+    tag = "jobs"
+)]
+pub async fn cancel_job(
+    State(state): State<AppState>,
+    Path(id): Path<u32>,
+    Query(params): Query<JobOwnerParams>,
+    headers: HeaderMap,
+) -> Response {
+    let mut body = StatusBody::new();
+
+    let mut job = Job::new(&state.config.data_path);
+    if let Err(e) = job.retrieve_id(id, &state.pool).await {
+        let status = match e {
+            sqlx::Error::RowNotFound => {
+                body.message = format!("Job {id} not found in the database");
+                StatusCode::NOT_FOUND
+            }
+            _ => {
+                body.message = "Internal server error".to_string();
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+        return (status, Json(body)).into_response();
+    }
+
+    if let Some(response) = reject_non_owner(&state, &headers, params.user_id, &job) {
+        return response;
+    }
+    body.id = job.id;
+
+    let status = match job.status {
+        // Not on a client yet, nothing to tell it
+        Status::Queued | Status::Held => match job.cancel_unsent(&state.pool).await {
+            Ok(true) => {
+                body.message = "Job cancelled".to_string();
+                StatusCode::OK
+            }
+            Ok(false) => {
+                body.message = format!("Job {id} is being sent to its client, try again");
+                StatusCode::CONFLICT
+            }
+            Err(e) => {
+                tracing::error!("Could not cancel job {id}: {:?}", e);
+                body.message = "Internal server error".to_string();
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        },
+        Status::Submitted | Status::Prepared | Status::Running => {
+            match server::cancel_job(
+                &mut job,
+                state.pool.clone(),
+                state.config.clone(),
+                state.client.clone(),
+            )
+            .await
+            {
+                Ok(_) => {
+                    body.message = "Job cancelled, its payload discarded".to_string();
+                    StatusCode::OK
+                }
+                Err(e) => {
+                    tracing::warn!("Could not cancel job {id} on its client: {e}");
+                    body.message =
+                        "Could not reach the client of the job, the cancellation is retried"
+                            .to_string();
+                    StatusCode::ACCEPTED
+                }
+            }
+        }
+        Status::Processing | Status::Locked => {
+            body.message = format!("Job {id} is being sent to its client, try again");
+            StatusCode::CONFLICT
+        }
+        status => {
+            body.message = format!("Job {id} is already {status}");
+            StatusCode::CONFLICT
+        }
+    };
+    body.status = job.status;
+
+    (status, Json(body)).into_response()
+}
+
+/// Operators authenticate with the admin token, owners name themselves
+pub fn reject_non_owner(
+    state: &AppState,
+    headers: &HeaderMap,
+    user_id: Option<i32>,
+    job: &Job,
+) -> Option<Response> {
+    if claims_admin(headers, &state.sessions) {
+        return reject_unauthorized(headers, &state.config, &state.sessions);
+    }
+    let mut body = StatusBody::new();
+    match user_id {
+        None => {
+            body.message = "Missing user_id parameter".to_string();
+            Some((StatusCode::BAD_REQUEST, Json(body)).into_response())
+        }
+        Some(uid) if uid != job.user_id => {
+            body.message = format!("Job {} does not belong to user {uid}", job.id);
+            Some((StatusCode::FORBIDDEN, Json(body)).into_response())
+        }
+        Some(_) => None,
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct DownloadLinkParams {
+    /// User asking for the link, must own the job unless the admin token is sent
+    pub user_id: Option<i32>,
+    /// Seconds the link stays valid, one hour by default
+    pub expires_in: Option<u64>,
+}
+
+/// A link to the results of a job that works without any other credential
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct DownloadLink {
+    pub url: String,
+    /// Unix time after which the link stops working
+    pub expires: u64,
+}
+
+#[utoipa::path(
+    post,
+    path = "/jobs/{id}/download_link",
+    params(
+        ("id" = u32, Path, description = "Job identifier"),
+        DownloadLinkParams
+    ),
+    responses(
+        (status = 200, description = "Signed link to the results", body = DownloadLink),
+        (status = 400, description = "Missing user_id or invalid validity", body = StatusBody),
+        (status = 401, description = "Invalid admin token", body = StatusBody),
+        (status = 403, description = "Job belongs to another user", body = StatusBody),
+        (status = 404, description = "Job not found or links not configured", body = StatusBody),
+        (status = 500, description = "Internal server error", body = StatusBody),
+    ),
+    security((), ("admin_token" = [])),
+    tag = "files"
+)]
+pub async fn create_download_link(
+    State(state): State<AppState>,
+    Path(id): Path<u32>,
+    Query(params): Query<DownloadLinkParams>,
+    headers: HeaderMap,
+) -> Response {
+    let mut body = StatusBody::new();
+    let Some(links) = &state.config.download_links else {
+        body.message = "Download links are not configured".to_string();
+        return (StatusCode::NOT_FOUND, Json(body)).into_response();
+    };
+
+    let mut job = Job::new(&state.config.data_path);
+    if let Err(e) = job.retrieve_id(id, &state.pool).await {
+        let status = match e {
+            sqlx::Error::RowNotFound => {
+                body.message = format!("Job {id} not found in the database");
+                StatusCode::NOT_FOUND
+            }
+            _ => {
+                body.message = "Internal server error".to_string();
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+        return (status, Json(body)).into_response();
+    }
+    if let Some(response) = reject_non_owner(&state, &headers, params.user_id, &job) {
+        return response;
+    }
+
+    let expires_in = params.expires_in.unwrap_or(3600);
+    if expires_in == 0 || expires_in > links.max_ttl.as_secs() {
+        body.message = format!(
+            "expires_in must be between 1 and {} seconds",
+            links.max_ttl.as_secs()
+        );
+        return (StatusCode::BAD_REQUEST, Json(body)).into_response();
+    }
+
+    let expires = download_link::now() + expires_in;
+    Json(DownloadLink {
+        url: download_link::url(links, id, expires),
+        expires,
+    })
+    .into_response()
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct SignedLinkParams {
+    /// Expiry of a link from `/jobs/{id}/download_link`
+    pub expires: Option<u64>,
+    pub signature: Option<String>,
+    /// Token of a link from `/jobs/{id}/shares`, instead of a signature
+    pub share: Option<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/shared/{id}",
+    params(
+        ("id" = u32, Path, description = "Job identifier"),
+        SignedLinkParams
+    ),
+    responses(
+        (status = 200, description = "Job completed — returns zip file", content_type = "application/zip", body = Vec<u8>),
+        (status = 200, description = "Job not yet complete — returns current job status", body = StatusBody),
+        (status = 403, description = "Invalid, expired, revoked or used up link", body = StatusBody),
+        (status = 404, description = "Not found", body = StatusBody),
+    ),
+    tag = "files"
+)]
+pub async fn download_shared(
+    State(state): State<AppState>,
+    Path(id): Path<u32>,
+    Query(params): Query<SignedLinkParams>,
+    connect: Option<Extension<ConnectInfo<SocketAddr>>>,
+    headers: HeaderMap,
+) -> Response {
+    let mut body = StatusBody::new();
+    let Some(links) = &state.config.download_links else {
+        body.message = "Download links are not configured".to_string();
+        return (StatusCode::NOT_FOUND, Json(body)).into_response();
+    };
+    let allowed = match (&params.share, params.expires, &params.signature) {
+        (Some(token), _, _) => match use_share(&state, id, token).await {
+            Ok(allowed) => allowed,
+            Err(response) => return response,
+        },
+        (None, Some(expires), Some(signature)) => {
+            download_link::verify(&links.secret, id, expires, signature)
+        }
+        _ => false,
+    };
+    if !allowed {
+        body.message = "Invalid or expired link".to_string();
+        return (StatusCode::FORBIDDEN, Json(body)).into_response();
+    }
+    let via = if params.share.is_some() {
+        VIA_SHARE
+    } else {
+        VIA_LINK
+    };
+    let caller = caller_ip(&state, connect, &headers);
+    serve_results(&state, id, &headers, via, caller).await
+}
+
+/// Group identifiers are short labels, they end up in file names
+fn is_valid_group_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= 64
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct GroupOwnerParams {
+    /// User who named the group on upload, groups of different users are apart
+    pub user_id: Option<i32>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/groups/{id}/results",
+    params(
+        ("id" = String, Path, description = "Group identifier given on upload"),
+        GroupOwnerParams
+    ),
+    responses(
+        (status = 200, description = "Zip with the results of every completed job of the group, one directory per job", content_type = "application/zip", body = Vec<u8>),
+        (status = 200, description = "No job of the group is completed yet", body = StatusBody),
+        (status = 400, description = "Invalid group id or missing user_id", body = StatusBody),
+        (status = 401, description = "Invalid admin token", body = StatusBody),
+        (status = 404, description = "Not found", body = StatusBody),
+        (status = 500, description = "Internal server error", body = StatusBody),
+    ),
+    security((), ("admin_token" = [])),
+    tag = "files"
+)]
+pub async fn download_group(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(params): Query<GroupOwnerParams>,
+    connect: Option<Extension<ConnectInfo<SocketAddr>>>,
+    headers: HeaderMap,
+) -> Response {
+    let mut body = StatusBody::new();
+
+    if !is_valid_group_id(&id) {
+        body.message = "Invalid group id".to_string();
+        return (StatusCode::BAD_REQUEST, Json(body)).into_response();
+    }
+    // As for the jobs, operators authenticate and owners name themselves. Both
+    // name the owner, the group is only known together with it.
+    if claims_admin(&headers, &state.sessions)
+        && let Some(response) = reject_unauthorized(&headers, &state.config, &state.sessions)
+    {
+        return response;
+    }
+    let Some(uid) = params.user_id else {
+        body.message = "Missing user_id parameter".to_string();
+        return (StatusCode::BAD_REQUEST, Json(body)).into_response();
+    };
+
+    let jobs = match Job::list_by_group(&id, uid, &state.pool).await {
+        Ok(jobs) => jobs,
+        Err(e) => {
+            tracing::error!("Could not list the jobs of group {id}: {:?}", e);
+            body.message = "Internal server error".to_string();
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response();
+        }
+    };
+    if jobs.is_empty() {
+        body.message = format!("Group {id} of user {uid} not found in the database");
+        return (StatusCode::NOT_FOUND, Json(body)).into_response();
+    }
+
+    // Archives moved to the object store are fetched to a staging directory first
+    let staging = std::env::temp_dir().join(format!("group-{}", uuid::Uuid::new_v4()));
+    let mut members = Vec::new();
+    for job in jobs.iter().filter(|j| j.status == Status::Completed) {
+        let name = format!("job-{}", job.id);
+        match (&job.remote_key, &state.config.object_store) {
+            (Some(key), Some(store)) => {
+                let archive = staging.join(format!("{name}.zip"));
+                let fetched = match create_dir_all(&staging).await {
+                    Ok(_) => state.client.get_object_to_file(store, key, &archive).await,
+                    Err(e) => Err(e.into()),
+                };
+                match fetched {
+                    Ok(_) => members.push((job.id, name, archive)),
+                    Err(e) => tracing::error!("Could not fetch {key} from the object store: {e}"),
+                }
+            }
+            _ => {
+                let archive = job.loc.join("output.zip");
+                if archive.exists() {
+                    members.push((job.id, name, archive));
+                }
+            }
+        }
+    }
+    if members.is_empty() {
+        let _ = std::fs::remove_dir_all(&staging);
+        body.message = format!("No job of group {id} is completed yet");
+        return Json(body).into_response();
+    }
+
+    let caller = caller_ip(&state, connect, &headers);
+    for (job_id, _, archive) in &members {
+        let bytes = std::fs::metadata(archive).map_or(0, |m| m.len() as i64);
+        record_download(&state, *job_id, caller.clone(), VIA_GROUP, bytes).await;
+    }
+    let members: Vec<_> = members
+        .into_iter()
+        .map(|(_, name, archive)| (name, archive))
+        .collect();
+
+    // Build the archive on a blocking thread while it is sent, only a few
+    // chunks are held in memory at any time
+    let (tx, rx) = tokio::sync::mpsc::channel(4);
+    let group = id.clone();
+    tokio::task::spawn_blocking(move || {
+        let mut writer = ChannelWriter::new(tx);
+        let result = write_combined_archive(&members, &mut writer)
+            .map_err(std::io::Error::other)
+            .and_then(|w| w.flush());
+        if let Err(e) = result {
+            tracing::error!("Could not build the archive of group {group}: {e}");
+            writer.fail(e);
+        }
+        let _ = std::fs::remove_dir_all(&staging);
+    });
+
+    (
+        [
+            (header::CONTENT_TYPE, "application/zip".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"group-{id}.zip\""),
+            ),
+        ],
+        Body::from_stream(ReceiverStream::new(rx)),
+    )
+        .into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/download_partial/{id}",
+    params(
+        ("id" = u32, Path, description = "Job identifier")
+    ),
+    responses(
+        (status = 200, description = "Returns zip file of current job state, regardless of completion", content_type = "application/zip", body = Vec<u8>),
+        (status = 404, description = "Not found", body = StatusBody),
+        (status = 500, description = "Internal server error", body = StatusBody),
+    ),
+    tag = "files"
+)]
+pub async fn download_partial(State(state): State<AppState>, Path(id): Path<u32>) -> Response {
+    let mut job = Job::new(&state.config.data_path);
+    let mut body = StatusBody::new();
+
+    let result = job.retrieve_id(id, &state.pool).await;
+
+    if let Err(e) = result {
+        // Error when retrieving this id, check what kind of error it was
+        let status = match e {
+            // It is not found on the database
+            sqlx::Error::RowNotFound => {
+                body.message = format!("Job {id} not found in the database");
+                StatusCode::NOT_FOUND
+            }
+            // Something else
+            _ => {
+                body.message = "Internal server error".to_string();
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+        // Return the error
+        return (status, Json(body)).into_response();
+    }
+
+    body.id = job.id;
+
+    // Call the client's retrieve_partial endpoint to get the current state
+    match endpoint::retrieve_partial(&job, &state.config, state.client.clone()).await {
+        Ok(data) => ([(header::CONTENT_TYPE, "application/zip")], data).into_response(),
+        Err(e) => {
+            tracing::error!("Error retrieving partial data from client: {:?}", e);
+            let status = match e {
+                endpoint::DownloadPartialError::NotFound => {
+                    body.message = "Job payload not found on client".to_string();
+                    StatusCode::NOT_FOUND
+                }
+                endpoint::DownloadPartialError::InvalidService => {
+                    body.message = "Invalid service configuration".to_string();
+                    StatusCode::BAD_REQUEST
+                }
+                _ => {
+                    body.message = "Error retrieving partial data from client".to_string();
+                    StatusCode::INTERNAL_SERVER_ERROR
+                }
+            };
+            (status, Json(body)).into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/upload",
+    request_body(
+        content_type = "multipart/form-data",
+        description = "Upload a file and metadata fields as multipart/form-data. \
+        The request must include a file field (with any filename and content type), a 'user_id' field (integer, id of a registered user), and a 'service' field (string). \
+        An optional 'tenant' field (string) must match the tenant of the user. \
+        An optional 'group' field (string) ties related jobs together, see /groups/{id}/results. \
+        Optional 'tags' (comma separated), 'description', 'callback_url' and 'notification_email' fields can be changed later with PATCH /jobs/{id}. \
+        With an optional 'dedupe' field set to 'true', an identical recent submission of the same user is returned instead of creating a new job. \
+        Additional fields may be included as needed."
+    ),
+    responses(
+        (status = 200, description = "Identical job already submitted, returns the existing job", body = StatusBody),
+        (status = 201, description = "File uploaded successfully", body = StatusBody),
+        (status = 400, description = "Bad request"),
+        (status = 403, description = "User disabled, or service not available to the tenant or tier of the user"),
+        (status = 500, description = "Internal server error"),
+        (status = 507, description = "DATA_PATH is not writable", body = StatusBody),
+    ),
+    tag = "files"
+)]
+pub async fn upload(State(state): State<AppState>, mut multipart: Multipart) -> Response {
+    // Create a new job with unique ID
+    let job = Job::new(&state.config.data_path);
+
+    // Create job directory
+    if let Err(e) = create_dir_all(&job.loc).await {
+        tracing::error!("Could not create {:?}: {e}", job.loc);
+        let mut body = StatusBody::new();
+        body.message = "Could not create job directory".to_string();
+        return (write_error_status(&e), Json(body)).into_response();
+    }
+
+    // FIXME: Move the parsing of the form to a helper function
+    let mut text_fields = HashMap::new();
+    let mut file_count = 0;
+    let mut body = StatusBody::new();
+    loop {
+        let field = multipart.next_field().await;
+        let field = match field {
+            Ok(Some(f)) => f,
+            Ok(None) => break,
+            Err(e) => {
+                tracing::error!("Multipart error: {e}");
+                body.message = format!("Multipart error: {e}");
+                return (e.status(), Json(body)).into_response();
+            }
+        };
+
+        let field_name = field.name().unwrap_or("unnamed").to_string();
+
+        if let Some(filename) = field.file_name() {
+            file_count += 1;
+            let filename = sanitize_filename(filename);
+            let file_path = job.loc.join(&filename);
+
+            tracing::info!("Saving file: {} to {}", filename, file_path.display());
+
+            if let Err((err_code, err_msg)) = save_file(field, &file_path).await {
+                body.message = format!("Could not save file: {err_msg}");
+                return (err_code, Json(body)).into_response();
+            }
+        } else {
+            let text = match field.text().await {
+                Ok(t) => t,
+                Err(e) => {
+                    tracing::error!("Error reading text field: {e}");
+                    body.message = format!("Error reading text field: {e}");
+                    return (e.status(), Json(body)).into_response();
+                }
+            };
+            text_fields.insert(field_name, text);
+        }
+    }
+
+    tracing::info!(
+        "Upload completed: {} files saved, {} text fields",
+        file_count,
+        text_fields.len()
+    );
+
+    register_job(&state, job, &text_fields).await
+}
+
+/// A job made only of parameters, the payload is rendered from the template
+/// of the service
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ParameterSubmission {
+    pub user_id: i32,
+    pub service: String,
+    /// Values of the `{{name}}` placeholders of the template, the whole
+    /// document is saved next to `run.sh` as `params.json`
+    #[schema(value_type = Object)]
+    pub parameters: serde_json::Map<String, serde_json::Value>,
+    /// Files the server fetches into the job directory, from the hosts
+    /// allowed by `REMOTE_INPUT_HOSTS`
+    #[serde(default)]
+    pub inputs: Vec<RemoteInput>,
+    /// `tenant`, `group`, `tags`, `timeout`, ... as strings, like `/upload`
+    #[serde(flatten)]
+    pub fields: HashMap<String, String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/jobs",
+    request_body = ParameterSubmission,
+    responses(
+        (status = 200, description = "Identical job already submitted, returns the existing job", body = StatusBody),
+        (status = 201, description = "Job rendered and queued", body = StatusBody),
+        (status = 400, description = "Unknown service, service without a template, invalid parameters or inputs", body = StatusBody),
+        (status = 403, description = "User disabled, or service not available to the tenant or tier of the user", body = StatusBody),
+        (status = 500, description = "Internal server error", body = StatusBody),
+        (status = 502, description = "An input could not be fetched", body = StatusBody),
+        (status = 507, description = "DATA_PATH is not writable", body = StatusBody),
+    ),
+    tag = "files"
+)]
+pub async fn submit_parameters(
+    State(state): State<AppState>,
+    Json(submission): Json<ParameterSubmission>,
+) -> Response {
+    let mut body = StatusBody::new();
+
+    let Some(service) = state.config.services.get(&submission.service) else {
+        body.message = "Invalid service".to_string();
+        return (StatusCode::BAD_REQUEST, Json(body)).into_response();
+    };
+    let Some(template) = &service.template else {
+        body.message = format!(
+            "Service {} does not take parameter submissions, use /upload",
+            service.name
+        );
+        return (StatusCode::BAD_REQUEST, Json(body)).into_response();
+    };
+
+    // Read on every submission, so that the template can change without a restart
+    let template = match tokio::fs::read_to_string(template).await {
+        Ok(t) => t,
+        Err(e) => {
+            tracing::error!("Could not read the template {template}: {e}");
+            body.message = "Internal server error".to_string();
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response();
+        }
+    };
+    let script = match template::render(&template, &submission.parameters) {
+        Ok(s) => s,
+        Err(message) => {
+            body.message = message;
+            return (StatusCode::BAD_REQUEST, Json(body)).into_response();
+        }
+    };
+    let inputs = match remote_input::check(&submission.inputs, state.config.remote_inputs.as_ref())
+    {
+        Ok(inputs) => inputs,
+        Err(e) => {
+            body.message = e.to_string();
+            return (e.status(), Json(body)).into_response();
+        }
+    };
+
+    let job = Job::new(&state.config.data_path);
+    let document = serde_json::to_vec_pretty(&submission.parameters).unwrap_or_default();
+    let written = async {
+        create_dir_all(&job.loc).await?;
+        tokio::fs::write(job.loc.join("run.sh"), script).await?;
+        tokio::fs::write(job.loc.join("params.json"), document).await
+    };
+    if let Err(e) = written.await {
+        tracing::error!("Could not write the payload of {:?}: {e}", job.loc);
+        body.message = "Could not create job directory".to_string();
+        return (write_error_status(&e), Json(body)).into_response();
+    }
+    if let Some(config) = &state.config.remote_inputs
+        && let Err(e) = remote_input::fetch(&inputs, &job.loc, config, &state.client).await
+    {
+        tracing::warn!("Could not fetch the inputs of {:?}: {e}", job.loc);
+        if let Err(e) = job.remove_from_disk() {
+            tracing::error!("Could not remove {:?}: {e}", job.loc);
+        }
+        body.message = e.to_string();
+        return (e.status(), Json(body)).into_response();
+    }
+
+    let mut text_fields = submission.fields;
+    text_fields.insert("user_id".to_string(), submission.user_id.to_string());
+    text_fields.insert("service".to_string(), submission.service);
+    register_job(&state, job, &text_fields).await
+}
+
+/// Text fields of a submission that describe its bookkeeping, every other
+/// field is a parameter of the run and part of the input hash
+const SUBMISSION_FIELDS: &[&str] = &[
+    "user_id",
+    "service",
+    "tenant",
+    "group",
+    "dedupe",
+    "tags",
+    "description",
+    "callback_url",
+    "notification_email",
+    "timeout",
+    "priority",
+];
+
+/// Validate the text fields of a submission whose files are already in the
+/// job directory and queue the job
+pub async fn register_job(
+    state: &AppState,
+    mut job: Job,
+    text_fields: &HashMap<String, String>,
+) -> Response {
+    let mut body = StatusBody::new();
+
+    // Get the user id from the request
+    let uid_str = match text_fields.get("user_id") {
+        Some(v) => v,
+        None => {
+            body.message = "Missing user_id field".to_string();
+            return (StatusCode::BAD_REQUEST, Json(body)).into_response();
+        }
+    };
+
+    // Check if this user id is valid, it must be a number
+    let uid = match uid_str.parse::<i32>() {
+        Ok(v) => v,
+        Err(_) => {
+            body.message = "Invalid user_id, should be a number".to_string();
+            return (StatusCode::BAD_REQUEST, Json(body)).into_response();
+        }
+    };
+
+    // Get service from the request
+    let service = match text_fields.get("service") {
+        Some(v) => v,
+        None => {
+            body.message = "Missing service field".to_string();
+            return (StatusCode::BAD_REQUEST, Json(body)).into_response();
+        }
+    };
+
+    // Validate service exists
+    if !state.config.services.contains_key(service) {
+        body.message = "Invalid service".to_string();
+        return (StatusCode::BAD_REQUEST, Json(body)).into_response();
+    }
+
+    // Unknown users are registered on their first upload, unless registration
+    // is required
+    if !state.config.require_registered_users
+        && let Err(e) = User::add_if_missing(uid as u32, &state.pool).await
+    {
+        tracing::error!("Could not register user {uid}: {:?}", e);
+        body.message = "Internal server error".to_string();
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response();
+    }
+
+    // The user must be registered and enabled
+    let user = match User::retrieve_id(uid as u32, &state.pool).await {
+        Ok(u) => u,
+        Err(sqlx::Error::RowNotFound) => {
+            body.message = format!("Unknown user {uid}");
+            return (StatusCode::BAD_REQUEST, Json(body)).into_response();
+        }
+        Err(e) => {
+            tracing::error!("Could not retrieve user {uid}: {:?}", e);
+            body.message = "Internal server error".to_string();
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response();
+        }
+    };
+
+    if !user.enabled {
+        body.message = format!("User {uid} is disabled");
+        return (StatusCode::FORBIDDEN, Json(body)).into_response();
+    }
+
+    // Tenant is optional, jobs without one belong to the tenant of the user
+    let tenant = text_fields
+        .get("tenant")
+        .map(|t| t.trim().to_ascii_lowercase())
+        .filter(|t| !t.is_empty())
+        .unwrap_or_else(|| user.tenant.clone());
+
+    if !state.config.is_known_tenant(&tenant) {
+        body.message = "Invalid tenant".to_string();
+        return (StatusCode::BAD_REQUEST, Json(body)).into_response();
+    }
+
+    if tenant != user.tenant {
+        body.message = format!("User {uid} does not belong to tenant {tenant}");
+        return (StatusCode::FORBIDDEN, Json(body)).into_response();
+    }
+
+    if !state.config.tenant_allows_service(&tenant, service) {
+        body.message = format!("Service {service} is not available to tenant {tenant}");
+        return (StatusCode::FORBIDDEN, Json(body)).into_response();
+    }
+
+    if !state.config.tier_allows_service(&user.tier, service) {
+        body.message = format!("Service {service} is not available to tier {}", user.tier);
+        return (StatusCode::FORBIDDEN, Json(body)).into_response();
+    }
+
+    // The directory moves to the storage root of the service and tenant
+    let data_path = state.config.data_path_for(Some(service), &tenant);
+    if let Err(e) = job.set_tenant(tenant, data_path) {
+        tracing::error!("Could not move job to its tenant directory: {e}");
+        body.message = "Could not create job directory".to_string();
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response();
+    }
+
+    // Seconds the run may take, within the limit of the service
+    if let Some(timeout) = text_fields.get("timeout").map(|t| t.trim()) {
+        let timeout = match timeout.parse::<u32>() {
+            Ok(t) if t > 0 => t,
+            _ => {
+                body.message =
+                    "Invalid timeout, should be a positive number of seconds".to_string();
+                return (StatusCode::BAD_REQUEST, Json(body)).into_response();
+            }
+        };
+        if let Some(max) = state.config.services[service].max_timeout
+            && u64::from(timeout) > max.as_secs()
+        {
+            body.message = format!(
+                "Timeout of {timeout}s exceeds the maximum of {}s of service {service}",
+                max.as_secs()
+            );
+            return (StatusCode::BAD_REQUEST, Json(body)).into_response();
+        }
+        job.timeout = Some(timeout);
+    }
+
+    // Higher priorities are sent first
+    if let Some(priority) = text_fields.get("priority").map(|p| p.trim()) {
+        job.priority = match priority.parse::<i32>() {
+            Ok(p) if (-MAX_PRIORITY..=MAX_PRIORITY).contains(&p) => p,
+            _ => {
+                body.message = format!(
+                    "Invalid priority, should be a whole number from -{MAX_PRIORITY} to {MAX_PRIORITY}"
+                );
+                return (StatusCode::BAD_REQUEST, Json(body)).into_response();
+            }
+        };
+    }
+
+    if let Some(group) = text_fields.get("group").map(|g| g.trim()) {
+        if !is_valid_group_id(group) {
+            body.message = "Invalid group, use up to 64 letters, digits, '-' or '_'".to_string();
+            return (StatusCode::BAD_REQUEST, Json(body)).into_response();
+        }
+        job.group_id = Some(group.to_string());
+    }
+
+    let metadata = JobMetadata::default().patched(JobMetadataPatch {
+        tags: text_fields.get("tags").map(|t| {
+            t.split(',')
+                .filter(|t| !t.trim().is_empty())
+                .map(str::to_string)
+                .collect()
+        }),
+        description: text_fields.get("description").cloned(),
+        callback_url: text_fields.get("callback_url").cloned(),
+        notification_email: text_fields.get("notification_email").cloned(),
+    });
+    if let Err(message) = metadata.validate() {
+        body.message = message;
+        return (StatusCode::BAD_REQUEST, Json(body)).into_response();
+    }
+    job.metadata = metadata;
+
+    job.set_user_id(uid);
+    job.set_service(service.to_string());
+
+    // Site-specific checks, which may rewrite the files before they are
+    // hashed. They run ahead of deduplication, so a duplicate is checked too
+    // and matched on the files as the hooks left them. The job has no id yet.
+    match Hooks::new(&state.config)
+        .run(HookPoint::PreSubmit, &job)
+        .await
+    {
+        Ok(()) => {}
+        Err(HookError::Rejected(reason)) => {
+            body.message = format!("Submission rejected: {reason}");
+            return (StatusCode::UNPROCESSABLE_ENTITY, Json(body)).into_response();
+        }
+        Err(HookError::Failed(_)) => {
+            body.message = "Could not check the submission".to_string();
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response();
+        }
+    }
+
+    // Deduplication is best effort, a job without a hash is never matched
+    let inputs = job.input_manifest();
+    match &inputs {
+        Ok(inputs) => {
+            let parameters = text_fields
+                .iter()
+                .filter(|(name, _)| !SUBMISSION_FIELDS.contains(&name.as_str()))
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect();
+            job.input_hash = Some(job.compute_input_hash(inputs, &parameters));
+        }
+        Err(e) => tracing::error!("Could not hash the inputs of {:?}: {e}", job.loc),
+    }
+
+    let dedupe = text_fields
+        .get("dedupe")
+        .is_some_and(|v| v.trim().eq_ignore_ascii_case("true"));
+    if dedupe {
+        match job
+            .find_recent_duplicate(state.config.dedupe_window, &state.pool)
+            .await
+        {
+            Ok(Some(existing)) => {
+                if let Err(e) = job.remove_from_disk() {
+                    tracing::error!("Could not remove {:?}: {e}", job.loc);
+                }
+                body.id = existing.id;
+                body.status = existing.status;
+                body.input_hash = existing.input_hash;
+                body.message = format!("Identical job {} already submitted", existing.id);
+                return (StatusCode::OK, Extension(AccessUser(uid)), Json(body)).into_response();
+            }
+            Ok(None) => {}
+            Err(e) => tracing::error!("Could not look up duplicates of {:?}: {:?}", job.loc, e),
+        }
+    }
+
+    // Flagged submissions wait for an operator instead of being rejected
+    let review = match server::review_flag(&job, &state.config, &state.pool).await {
+        Ok(r) => r,
+        Err(e) => {
+            tracing::error!("Could not review the submission {:?}: {:?}", job.loc, e);
+            body.message = "Internal server error".to_string();
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response();
+        }
+    };
+
+    // Add job to database
+    let Ok(_) = job.add_to_db(&state.pool).await else {
+        body.message = "Error while adding the job to the database".to_string();
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response();
+    };
+
+    // Like the hash, the listing of the inputs is best effort
+    match inputs {
+        Ok(inputs) => {
+            if let Err(e) = job.record_inputs(&inputs, &state.pool).await {
+                tracing::error!("Could not record the inputs of job {}: {:?}", job.id, e);
+            }
+        }
+        Err(e) => tracing::error!("Could not list the inputs of {:?}: {e}", job.loc),
+    }
+
+    let queued = match review {
+        Some(reason) => {
+            tracing::warn!("Job {} held for review: {reason}", job.id);
+            let note = format!("held for review: {reason}");
+            job.update_review(Status::Held, Some(reason), SERVER_ACTOR, &note, &state.pool)
+                .await
+        }
+        None => job.update_status(Status::Queued, &state.pool).await,
+    };
+    let Ok(_) = queued else {
+        body.message = format!("Could not update the status of job {0}", job.id);
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response();
+    };
+
+    // Everything went fine
+    body.status = job.status;
+    body.id = job.id;
+    body.input_hash = job.input_hash;
+    body.message = match &job.review_reason {
+        Some(reason) => format!("Job held for review: {reason}"),
+        None => "Job successfully uploaded".to_string(),
+    };
+
+    (StatusCode::CREATED, Extension(AccessUser(uid)), Json(body)).into_response()
+}
+
+#[utoipa::path(
+    post,
+    path = "/terminate/{id}",
+    params(
+        ("id" = u32, Path, description = "Job identifier")
+    ),
+    responses(),
+    tag = "files"
+)]
+pub async fn terminate(State(state): State<AppState>, Path(id): Path<u32>) -> Response {
+    // 1. Get a job from the id
+    let mut job = Job::new(&state.config.data_path);
+    let mut body = StatusBody::new();
+    let result = job.retrieve_id(id, &state.pool).await;
+    if let Err(e) = result {
+        // Error when retrieving this id, check what kind of error it was
+        let status = match e {
+            // It is not found on the database
+            sqlx::Error::RowNotFound => {
+                body.message = format!("Job {id} not found in the database");
+                StatusCode::NOT_FOUND
+            }
+            // Something else
+            _ => {
+                body.message = "Internal server error".to_string();
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+        // Return the error
+        return (status, Json(body)).into_response();
+    }
+
+    body.id = job.id;
+
+    // 2. Send termination signal to client
+    let status = match server::terminate_job(
+        &mut job,
+        state.pool.clone(),
+        state.config.clone(),
+        state.client.clone(),
+    )
+    .await
+    {
+        Ok(_) => {
+            body.message = "job terminated".to_string();
+            StatusCode::OK
+        }
+        Err(_) => {
+            body.message = "could not terminate job".to_string();
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    };
+
+    (status, Json(body)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DownloadLink;
+    use crate::config::loader::{
+        BodyLimits, Config, DownloadLinkConfig, ObjectStoreConfig, RemoteInputConfig, Secret,
+        Service, Tenant, Tier,
+    };
+    use crate::models::download_dao::Download;
+    use crate::models::job_dao::{Job, OutputSummary};
     use crate::models::job_dto::create_jobs_table;
+    use crate::models::job_event_dao::{CLEANER_ACTOR, JobEvent, SERVER_ACTOR};
+    use crate::models::manifest_dto::ManifestEntry;
     use crate::models::status_body::StatusBody;
     use crate::models::status_dto::Status;
+    use crate::models::user_dao::User;
     use crate::routes::router::create_routes;
+    use crate::services::client::Client;
+    use crate::services::webhook;
     use axum::body::Body;
+    use axum::extract::ConnectInfo;
     use axum::http::{Request, StatusCode};
     use sqlx::SqlitePool;
     use std::collections::HashMap;
     use std::fs;
+    use std::net::SocketAddr;
+    use std::time::SystemTime;
     use tempfile::TempDir;
     use tower::ServiceExt;
 
-    async fn setup_test_db() -> SqlitePool {
-        let pool = SqlitePool::connect(":memory:").await.unwrap();
-        create_jobs_table(&pool).await.unwrap();
-        pool
+    async fn setup_test_db() -> SqlitePool {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        create_jobs_table(&pool).await.unwrap();
+        for id in [0, 1] {
+            User::add_if_missing(id, &pool).await.unwrap();
+        }
+        pool
+    }
+
+    fn make_config(data_path: &str) -> Config {
+        let mut services = HashMap::new();
+        services.insert(
+            "test".to_string(),
+            Service {
+                name: "test".to_string(),
+                upload_url: "http://example.com/upload".to_string(),
+                download_url: "http://example.com/download".to_string(),
+                terminate_url: "http://example.com/terminate".to_string(),
+                runs_per_user: 5,
+                max_runs: 1,
+                ..Default::default()
+            },
+        );
+        Config {
+            services,
+            db_path: ":memory:".to_string(),
+            data_path: data_path.to_string(),
+            max_age: std::time::Duration::from_secs(3600),
+            port: 5000,
+            ..Default::default()
+        }
+    }
+
+    fn build_multipart(boundary: &str, parts: &[(&str, &[u8], Option<&str>)]) -> Vec<u8> {
+        let mut body = Vec::new();
+        for (name, data, filename) in parts {
+            body.extend(format!("--{boundary}\r\n").as_bytes());
+            if let Some(fname) = filename {
+                body.extend(
+                    format!(
+                        "Content-Disposition: form-data; name=\"{name}\"; filename=\"{fname}\"\r\n"
+                    )
+                    .as_bytes(),
+                );
+                body.extend(b"Content-Type: application/octet-stream\r\n");
+            } else {
+                body.extend(
+                    format!("Content-Disposition: form-data; name=\"{name}\"\r\n").as_bytes(),
+                );
+            }
+            body.extend(b"\r\n");
+            body.extend(*data);
+            body.extend(b"\r\n");
+        }
+        body.extend(format!("--{boundary}--\r\n").as_bytes());
+        body
+    }
+
+    async fn body_bytes(response: axum::response::Response) -> bytes::Bytes {
+        axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_upload_success() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let config = make_config(tempdir.path().to_str().unwrap());
+        let app = create_routes(pool.clone(), config, Client::default());
+
+        let boundary = "testboundary123";
+        let body = build_multipart(
+            boundary,
+            &[
+                ("file", b"file content".as_slice(), Some("test.txt")),
+                ("user_id", b"1", None),
+                ("service", b"test", None),
+            ],
+        );
+
+        let request = Request::builder()
+            .method("POST")
+            .uri("/upload")
+            .header(
+                "content-type",
+                format!("multipart/form-data; boundary={boundary}"),
+            )
+            .body(Body::from(body))
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let bytes = body_bytes(response).await;
+        let body: StatusBody = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body.status, Status::Queued);
+        assert!(body.message.contains("Job successfully uploaded"));
+
+        let inputs = Job::retrieve_inputs(body.id, &pool).await.unwrap().unwrap();
+        assert_eq!(inputs.len(), 1);
+        assert_eq!(inputs[0].path, "test.txt");
+        assert_eq!(inputs[0].size, 12);
+    }
+
+    #[tokio::test]
+    async fn test_upload_held_for_review() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let mut config = make_config(tempdir.path().to_str().unwrap());
+        let service = config.services.get_mut("test").unwrap();
+        service.hold_suspicious = true;
+        service.hold_queued_over = Some(2);
+        let app = create_routes(pool, config, Client::default());
+
+        let upload = |script: &'static [u8]| {
+            let boundary = "testboundary123";
+            let body = build_multipart(
+                boundary,
+                &[
+                    ("file", script, Some("run.sh")),
+                    ("user_id", b"1", None),
+                    ("service", b"test", None),
+                ],
+            );
+            Request::builder()
+                .method("POST")
+                .uri("/upload")
+                .header(
+                    "content-type",
+                    format!("multipart/form-data; boundary={boundary}"),
+                )
+                .body(Body::from(body))
+                .unwrap()
+        };
+        let status = |response: axum::response::Response| async move {
+            assert_eq!(response.status(), StatusCode::CREATED);
+            let body: StatusBody = serde_json::from_slice(&body_bytes(response).await).unwrap();
+            (body.status, body.message)
+        };
+
+        let clean: &[u8] = b"#!/bin/bash\ntrap 'echo $? > .orchestrator.exit' EXIT\necho hi\n";
+        let suspicious: &[u8] = b"#!/bin/bash\ncurl http://example.com\n";
+
+        let response = app.clone().oneshot(upload(suspicious)).await.unwrap();
+        let (held, message) = status(response).await;
+        assert_eq!(held, Status::Held);
+        assert!(message.contains("network tool: curl"), "{message}");
+
+        // The held job counts as waiting, the second one reaches the limit
+        let response = app.clone().oneshot(upload(clean)).await.unwrap();
+        assert_eq!(status(response).await.0, Status::Queued);
+        let response = app.oneshot(upload(clean)).await.unwrap();
+        let (held, message) = status(response).await;
+        assert_eq!(held, Status::Held);
+        assert!(message.contains("already has 2 jobs waiting"), "{message}");
+    }
+
+    #[tokio::test]
+    async fn test_upload_too_large() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let mut config = make_config(tempdir.path().to_str().unwrap());
+        config.body_limits = BodyLimits {
+            upload: 1024,
+            default: 16,
+            ..BodyLimits::default()
+        };
+        let app = create_routes(pool, config, Client::default());
+
+        let boundary = "testboundary123";
+        let content = vec![b'x'; 2048];
+        let body = build_multipart(
+            boundary,
+            &[
+                ("user_id", b"1", None),
+                ("service", b"test", None),
+                ("file", content.as_slice(), Some("test.txt")),
+            ],
+        );
+        let request = Request::builder()
+            .method("POST")
+            .uri("/upload")
+            .header(
+                "content-type",
+                format!("multipart/form-data; boundary={boundary}"),
+            )
+            .body(Body::from(body))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let body: StatusBody = serde_json::from_slice(&body_bytes(response).await).unwrap();
+        assert_eq!(
+            body.message,
+            "Request body is larger than the limit of 1024 bytes"
+        );
+
+        // Other routes get the small limit
+        let request = Request::builder()
+            .method("POST")
+            .uri("/users")
+            .header("content-type", "application/json")
+            .body(Body::from(r#"{"name": "a rather long user name"}"#))
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let body: StatusBody = serde_json::from_slice(&body_bytes(response).await).unwrap();
+        assert!(body.message.contains("limit of 16 bytes"));
+    }
+
+    #[tokio::test]
+    async fn test_upload_with_metadata() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let config = make_config(tempdir.path().to_str().unwrap());
+        let app = create_routes(pool.clone(), config, Client::default());
+
+        let boundary = "testboundary123";
+        let upload = |email: &'static [u8]| {
+            let body = build_multipart(
+                boundary,
+                &[
+                    ("file", b"file content".as_slice(), Some("test.txt")),
+                    ("user_id", b"1", None),
+                    ("service", b"test", None),
+                    ("tags", b"docking, ,batch", None),
+                    ("callback_url", b"https://example.com/hook", None),
+                    ("notification_email", email, None),
+                ],
+            );
+            Request::builder()
+                .method("POST")
+                .uri("/upload")
+                .header(
+                    "content-type",
+                    format!("multipart/form-data; boundary={boundary}"),
+                )
+                .body(Body::from(body))
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(upload(b"not-an-email")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = app.oneshot(upload(b"alice@example.com")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let body: StatusBody = serde_json::from_slice(&body_bytes(response).await).unwrap();
+
+        let mut job = Job::new("");
+        job.retrieve_id(body.id, &pool).await.unwrap();
+        assert_eq!(job.metadata.tags, vec!["docking", "batch"]);
+        assert_eq!(
+            job.metadata.callback_url.as_deref(),
+            Some("https://example.com/hook")
+        );
+        assert_eq!(
+            job.metadata.notification_email.as_deref(),
+            Some("alice@example.com")
+        );
+        assert_eq!(job.metadata.description, None);
+    }
+
+    #[tokio::test]
+    async fn test_upload_missing_user_id() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let config = make_config(tempdir.path().to_str().unwrap());
+        let app = create_routes(pool, config, Client::default());
+
+        let boundary = "testboundary123";
+        let body = build_multipart(
+            boundary,
+            &[
+                ("file", b"file content".as_slice(), Some("test.txt")),
+                ("service", b"test", None),
+            ],
+        );
+
+        let request = Request::builder()
+            .method("POST")
+            .uri("/upload")
+            .header(
+                "content-type",
+                format!("multipart/form-data; boundary={boundary}"),
+            )
+            .body(Body::from(body))
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let bytes = body_bytes(response).await;
+        let body: StatusBody = serde_json::from_slice(&bytes).unwrap();
+        assert!(body.message.contains("Missing user_id"));
+    }
+
+    #[tokio::test]
+    async fn test_upload_invalid_user_id() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let config = make_config(tempdir.path().to_str().unwrap());
+        let app = create_routes(pool, config, Client::default());
+
+        let boundary = "testboundary123";
+        let body = build_multipart(
+            boundary,
+            &[
+                ("file", b"file content".as_slice(), Some("test.txt")),
+                ("user_id", b"abc", None),
+                ("service", b"test", None),
+            ],
+        );
+
+        let request = Request::builder()
+            .method("POST")
+            .uri("/upload")
+            .header(
+                "content-type",
+                format!("multipart/form-data; boundary={boundary}"),
+            )
+            .body(Body::from(body))
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let bytes = body_bytes(response).await;
+        let body: StatusBody = serde_json::from_slice(&bytes).unwrap();
+        assert!(body.message.contains("Invalid user_id"));
+    }
+
+    #[tokio::test]
+    async fn test_upload_missing_service() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let config = make_config(tempdir.path().to_str().unwrap());
+        let app = create_routes(pool, config, Client::default());
+
+        let boundary = "testboundary123";
+        let body = build_multipart(
+            boundary,
+            &[
+                ("file", b"file content".as_slice(), Some("test.txt")),
+                ("user_id", b"1", None),
+            ],
+        );
+
+        let request = Request::builder()
+            .method("POST")
+            .uri("/upload")
+            .header(
+                "content-type",
+                format!("multipart/form-data; boundary={boundary}"),
+            )
+            .body(Body::from(body))
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let bytes = body_bytes(response).await;
+        let body: StatusBody = serde_json::from_slice(&bytes).unwrap();
+        assert!(body.message.contains("Missing service"));
+    }
+
+    #[tokio::test]
+    async fn test_upload_invalid_service() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let config = make_config(tempdir.path().to_str().unwrap());
+        let app = create_routes(pool, config, Client::default());
+
+        let boundary = "testboundary123";
+        let body = build_multipart(
+            boundary,
+            &[
+                ("file", b"file content".as_slice(), Some("test.txt")),
+                ("user_id", b"1", None),
+                ("service", b"unknownservice", None),
+            ],
+        );
+
+        let request = Request::builder()
+            .method("POST")
+            .uri("/upload")
+            .header(
+                "content-type",
+                format!("multipart/form-data; boundary={boundary}"),
+            )
+            .body(Body::from(body))
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let bytes = body_bytes(response).await;
+        let body: StatusBody = serde_json::from_slice(&bytes).unwrap();
+        assert!(body.message.contains("Invalid service"));
+    }
+
+    #[tokio::test]
+    async fn test_upload_with_tenant() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let mut config = make_config(tempdir.path().to_str().unwrap());
+        config.tenants.insert(
+            "lab".to_string(),
+            Tenant {
+                name: "lab".to_string(),
+                ..Default::default()
+            },
+        );
+        let app = create_routes(pool.clone(), config, Client::default());
+
+        let mut user = User {
+            name: "lab user".to_string(),
+            tenant: "lab".to_string(),
+            ..Default::default()
+        };
+        user.add_to_db(&pool).await.unwrap();
+        let user_id = user.id.to_string();
+
+        let boundary = "testboundary123";
+        let body = build_multipart(
+            boundary,
+            &[
+                ("file", b"file content".as_slice(), Some("test.txt")),
+                ("user_id", user_id.as_bytes(), None),
+                ("service", b"test", None),
+                ("tenant", b"lab", None),
+            ],
+        );
+
+        let request = Request::builder()
+            .method("POST")
+            .uri("/upload")
+            .header(
+                "content-type",
+                format!("multipart/form-data; boundary={boundary}"),
+            )
+            .body(Body::from(body))
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let bytes = body_bytes(response).await;
+        let body: StatusBody = serde_json::from_slice(&bytes).unwrap();
+
+        let mut job = Job::new(tempdir.path().to_str().unwrap());
+        job.retrieve_id(body.id, &pool).await.unwrap();
+        assert_eq!(job.tenant, "lab");
+        assert!(job.loc.starts_with(tempdir.path().join("lab")));
+        assert!(job.loc.join("test.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn test_upload_unknown_tenant() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let config = make_config(tempdir.path().to_str().unwrap());
+        let app = create_routes(pool, config, Client::default());
+
+        let boundary = "testboundary123";
+        let body = build_multipart(
+            boundary,
+            &[
+                ("file", b"file content".as_slice(), Some("test.txt")),
+                ("user_id", b"1", None),
+                ("service", b"test", None),
+                ("tenant", b"nope", None),
+            ],
+        );
+
+        let request = Request::builder()
+            .method("POST")
+            .uri("/upload")
+            .header(
+                "content-type",
+                format!("multipart/form-data; boundary={boundary}"),
+            )
+            .body(Body::from(body))
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let bytes = body_bytes(response).await;
+        let body: StatusBody = serde_json::from_slice(&bytes).unwrap();
+        assert!(body.message.contains("Invalid tenant"));
+    }
+
+    #[tokio::test]
+    async fn test_upload_service_not_allowed_for_tenant() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let mut config = make_config(tempdir.path().to_str().unwrap());
+        config.tenants.insert(
+            "lab".to_string(),
+            Tenant {
+                name: "lab".to_string(),
+                services: vec!["other".to_string()],
+                ..Default::default()
+            },
+        );
+        let app = create_routes(pool.clone(), config, Client::default());
+
+        let mut user = User {
+            name: "lab user".to_string(),
+            tenant: "lab".to_string(),
+            ..Default::default()
+        };
+        user.add_to_db(&pool).await.unwrap();
+        let user_id = user.id.to_string();
+
+        let boundary = "testboundary123";
+        let body = build_multipart(
+            boundary,
+            &[
+                ("file", b"file content".as_slice(), Some("test.txt")),
+                ("user_id", user_id.as_bytes(), None),
+                ("service", b"test", None),
+                ("tenant", b"lab", None),
+            ],
+        );
+
+        let request = Request::builder()
+            .method("POST")
+            .uri("/upload")
+            .header(
+                "content-type",
+                format!("multipart/form-data; boundary={boundary}"),
+            )
+            .body(Body::from(body))
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    async fn upload_as(app: axum::Router, user_id: &str, tenant: Option<&str>) -> StatusCode {
+        let boundary = "testboundary123";
+        let mut parts = vec![
+            ("file", b"file content".as_slice(), Some("test.txt")),
+            ("user_id", user_id.as_bytes(), None),
+            ("service", b"test".as_slice(), None),
+        ];
+        if let Some(t) = tenant {
+            parts.push(("tenant", t.as_bytes(), None));
+        }
+        let body = build_multipart(boundary, &parts);
+
+        let request = Request::builder()
+            .method("POST")
+            .uri("/upload")
+            .header(
+                "content-type",
+                format!("multipart/form-data; boundary={boundary}"),
+            )
+            .body(Body::from(body))
+            .unwrap();
+
+        app.oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn test_upload_dedupe() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let config = make_config(tempdir.path().to_str().unwrap());
+        let app = create_routes(pool.clone(), config, Client::default());
+
+        let upload = |content: &'static [u8], dedupe: bool| {
+            let app = app.clone();
+            async move {
+                let boundary = "testboundary123";
+                let mut parts = vec![
+                    ("file", content, Some("test.txt")),
+                    ("user_id", b"1".as_slice(), None),
+                    ("service", b"test".as_slice(), None),
+                ];
+                if dedupe {
+                    parts.push(("dedupe", b"true".as_slice(), None));
+                }
+                let request = Request::builder()
+                    .method("POST")
+                    .uri("/upload")
+                    .header(
+                        "content-type",
+                        format!("multipart/form-data; boundary={boundary}"),
+                    )
+                    .body(Body::from(build_multipart(boundary, &parts)))
+                    .unwrap();
+                let response = app.oneshot(request).await.unwrap();
+                let status = response.status();
+                let body: StatusBody = serde_json::from_slice(&body_bytes(response).await).unwrap();
+                (status, body.id)
+            }
+        };
+
+        let (status, first) = upload(b"input", true).await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        // Same inputs with dedupe return the existing job
+        assert_eq!(upload(b"input", true).await, (StatusCode::OK, first));
+
+        // Without the flag, or with other inputs, a new job is created
+        let (status, second) = upload(b"input", false).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_ne!(second, first);
+        let (status, third) = upload(b"other input", true).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_ne!(third, first);
+
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM jobs")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 3);
+    }
+
+    #[tokio::test]
+    async fn test_upload_to_service_root() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let mut config = make_config(tempdir.path().join("data").to_str().unwrap());
+        let nvme = tempdir.path().join("nvme");
+        config.services.get_mut("test").unwrap().data_path =
+            Some(nvme.to_str().unwrap().to_string());
+        let app = create_routes(pool.clone(), config, Client::default());
+
+        let boundary = "testboundary123";
+        let body = build_multipart(
+            boundary,
+            &[
+                ("file", b"file content".as_slice(), Some("test.txt")),
+                ("user_id", b"1", None),
+                ("service", b"test", None),
+            ],
+        );
+        let request = Request::builder()
+            .method("POST")
+            .uri("/upload")
+            .header(
+                "content-type",
+                format!("multipart/form-data; boundary={boundary}"),
+            )
+            .body(Body::from(body))
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let body: StatusBody = serde_json::from_slice(&body_bytes(response).await).unwrap();
+
+        let mut job = Job::new("");
+        job.retrieve_id(body.id, &pool).await.unwrap();
+        assert_eq!(job.loc.parent().unwrap(), nvme);
+        assert!(job.loc.join("test.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn test_upload_receipt() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let config = make_config(tempdir.path().to_str().unwrap());
+        let app = create_routes(pool.clone(), config, Client::default());
+
+        let upload = |fields: &'static [(&'static str, &'static [u8])]| {
+            let app = app.clone();
+            let pool = pool.clone();
+            async move {
+                let boundary = "testboundary123";
+                let mut parts = vec![
+                    ("file", b"input".as_slice(), Some("test.txt")),
+                    ("user_id", b"1".as_slice(), None),
+                    ("service", b"test".as_slice(), None),
+                ];
+                parts.extend(fields.iter().map(|(name, value)| (*name, *value, None)));
+                let request = Request::builder()
+                    .method("POST")
+                    .uri("/upload")
+                    .header(
+                        "content-type",
+                        format!("multipart/form-data; boundary={boundary}"),
+                    )
+                    .body(Body::from(build_multipart(boundary, &parts)))
+                    .unwrap();
+                let response = app.oneshot(request).await.unwrap();
+                assert_eq!(response.status(), StatusCode::CREATED);
+                let body: StatusBody = serde_json::from_slice(&body_bytes(response).await).unwrap();
+                let mut job = Job::new("");
+                job.retrieve_id(body.id, &pool).await.unwrap();
+                assert_eq!(job.input_hash, body.input_hash);
+                body.input_hash.unwrap()
+            }
+        };
+
+        let receipt = upload(&[("steps", b"10")]).await;
+        assert_eq!(upload(&[("steps", b"10")]).await, receipt);
+        // Bookkeeping fields do not change what runs
+        assert_eq!(
+            upload(&[("steps", b"10"), ("description", b"second try")]).await,
+            receipt
+        );
+        assert_ne!(upload(&[("steps", b"20")]).await, receipt);
+        assert_ne!(upload(&[]).await, receipt);
+    }
+
+    #[tokio::test]
+    async fn test_download_group() {
+        let tempdir = TempDir::new().unwrap();
+        let data_path = tempdir.path().to_str().unwrap();
+        let pool = setup_test_db().await;
+        let app = create_routes(pool.clone(), make_config(data_path), Client::default());
+
+        let mut ids = Vec::new();
+        for (n, status) in [Status::Completed, Status::Completed, Status::Running]
+            .into_iter()
+            .enumerate()
+        {
+            let mut job = Job::new(data_path);
+            job.set_user_id(1);
+            job.group_id = Some("batch".to_string());
+            fs::create_dir_all(job.loc.join("src")).unwrap();
+            fs::write(job.loc.join("src").join("model.pdb"), format!("ATOM {n}")).unwrap();
+            crate::utils::io::zip_directory(&job.loc.join("src"), &job.loc.join("output.zip"))
+                .unwrap();
+            job.add_to_db(&pool).await.unwrap();
+            job.update_status(status, &pool).await.unwrap();
+            ids.push(job.id);
+        }
+
+        let request = Request::builder()
+            .uri("/groups/batch/results?user_id=1")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "application/zip");
+
+        let bytes = body_bytes(response).await;
+        let archive = zip::ZipArchive::new(std::io::Cursor::new(bytes)).unwrap();
+        let mut names: Vec<_> = archive.file_names().collect();
+        names.sort();
+        assert_eq!(
+            names,
+            vec![
+                format!("job-{}/model.pdb", ids[0]),
+                format!("job-{}/model.pdb", ids[1]),
+            ]
+        );
+
+        // Every member handed out is recorded, the running job is not
+        let downloads = Download::list_for_job(ids[0], &pool).await.unwrap();
+        assert_eq!(downloads.len(), 1);
+        assert_eq!(downloads[0].via, "group");
+        assert!(downloads[0].bytes > 0);
+        assert!(
+            Download::list_for_job(ids[2], &pool)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        // Groups are apart per user, and only known together with their owner
+        for (uri, expected) in [
+            ("/groups/unknown/results?user_id=1", StatusCode::NOT_FOUND),
+            ("/groups/batch/results?user_id=2", StatusCode::NOT_FOUND),
+            ("/groups/batch/results", StatusCode::BAD_REQUEST),
+            ("/groups/batch.1/results?user_id=1", StatusCode::BAD_REQUEST),
+        ] {
+            let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), expected, "{uri}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_download_tiered_job() {
+        let tempdir = TempDir::new().unwrap();
+        let data_path = tempdir.path().to_str().unwrap();
+        let pool = setup_test_db().await;
+
+        // One archive moved to the object store, one still local
+        let mut jobs = Vec::new();
+        for n in 0..2 {
+            let mut job = Job::new(data_path);
+            job.set_user_id(1);
+            job.group_id = Some("batch".to_string());
+            fs::create_dir_all(job.loc.join("src")).unwrap();
+            fs::write(job.loc.join("src").join("model.pdb"), format!("ATOM {n}")).unwrap();
+            crate::utils::io::zip_directory(&job.loc.join("src"), &job.loc.join("output.zip"))
+                .unwrap();
+            job.add_to_db(&pool).await.unwrap();
+            job.update_status(Status::Completed, &pool).await.unwrap();
+            job.update_checksum(job.compute_checksum().unwrap(), &pool)
+                .await
+                .unwrap();
+            jobs.push(job);
+        }
+        let archive = fs::read(jobs[0].loc.join("output.zip")).unwrap();
+        let key = jobs[0].object_key();
+        jobs[0].update_remote_key(key, &pool).await.unwrap();
+        jobs[0].clear_local_files().unwrap();
+
+        let mut server = mockito::Server::new_async().await;
+        let _get = server
+            .mock("GET", format!("/{}", jobs[0].object_key()).as_str())
+            .with_status(200)
+            .with_body(&archive)
+            .create_async()
+            .await;
+
+        let mut config = make_config(data_path);
+        config.object_store = Some(ObjectStoreConfig {
+            url: server.url(),
+            auth: Default::default(),
+            tier_after: std::time::Duration::from_secs(3600),
+            s3: None,
+        });
+        let app = create_routes(pool, config, Client::default());
+
+        let request = Request::builder()
+            .uri(format!("/download/{}", jobs[0].id))
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()["etag"],
+            format!("\"{}\"", jobs[0].checksum.as_ref().unwrap()).as_str()
+        );
+        assert_eq!(body_bytes(response).await, archive);
+
+        let request = Request::builder()
+            .uri("/groups/batch/results?user_id=1")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = body_bytes(response).await;
+        let archive = zip::ZipArchive::new(std::io::Cursor::new(bytes)).unwrap();
+        let mut names: Vec<_> = archive.file_names().collect();
+        names.sort();
+        assert_eq!(
+            names,
+            vec![
+                format!("job-{}/model.pdb", jobs[0].id),
+                format!("job-{}/model.pdb", jobs[1].id),
+            ]
+        );
+    }
+
+    async fn submit_json(app: axum::Router, json: serde_json::Value) -> (StatusCode, StatusBody) {
+        let request = Request::builder()
+            .method("POST")
+            .uri("/jobs")
+            .header("content-type", "application/json")
+            .body(Body::from(json.to_string()))
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = body_bytes(response).await;
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_submit_parameters() {
+        let tempdir = TempDir::new().unwrap();
+        let template = tempdir.path().join("test.sh");
+        fs::write(&template, "dock --chains {{chains}} --steps {{steps}}\n").unwrap();
+        let pool = setup_test_db().await;
+        let mut config = make_config(tempdir.path().to_str().unwrap());
+        config.services.get_mut("test").unwrap().template =
+            Some(template.to_str().unwrap().to_string());
+        let app = create_routes(pool.clone(), config, Client::default());
+
+        let document = serde_json::json!({
+            "user_id": 1,
+            "service": "test",
+            "parameters": {"chains": "A,B", "steps": 100},
+            "tags": "inline",
+            "dedupe": "true",
+        });
+        let (status, body) = submit_json(app.clone(), document.clone()).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(body.status, Status::Queued);
+
+        let mut job = Job::new("");
+        job.retrieve_id(body.id, &pool).await.unwrap();
+        assert_eq!(job.service, "test");
+        assert_eq!(job.metadata.tags, vec!["inline".to_string()]);
+        assert_eq!(
+            fs::read_to_string(job.loc.join("run.sh")).unwrap(),
+            "dock --chains 'A,B' --steps '100'\n"
+        );
+        let params: serde_json::Value =
+            serde_json::from_slice(&fs::read(job.loc.join("params.json")).unwrap()).unwrap();
+        assert_eq!(params, document["parameters"]);
+
+        // The same document is the same job
+        let (status, again) = submit_json(app, document).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(again.id, body.id);
+    }
+
+    #[tokio::test]
+    async fn test_submit_parameters_remote_inputs() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/download/1abc.pdb")
+            .with_body("ATOM")
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/download/gone.pdb")
+            .with_status(404)
+            .create_async()
+            .await;
+
+        let tempdir = TempDir::new().unwrap();
+        let template = tempdir.path().join("test.sh");
+        fs::write(&template, "dock --receptor {{receptor}}\n").unwrap();
+        let pool = setup_test_db().await;
+        let mut config = make_config(tempdir.path().to_str().unwrap());
+        config.services.get_mut("test").unwrap().template =
+            Some(template.to_str().unwrap().to_string());
+        config.remote_inputs = Some(RemoteInputConfig {
+            hosts: vec!["127.0.0.1".to_string()],
+            schemes: vec!["http".to_string()],
+            max_size: 1024,
+            max_total: 1024,
+        });
+        let app = create_routes(pool.clone(), config, Client::default());
+
+        let document = |file: &str| {
+            serde_json::json!({
+                "user_id": 1,
+                "service": "test",
+                "parameters": {"receptor": "receptor.pdb"},
+                "inputs": [{"url": format!("{}/download/{file}", server.url()), "name": "receptor.pdb"}],
+            })
+        };
+        let (status, body) = submit_json(app.clone(), document("1abc.pdb")).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let mut job = Job::new("");
+        job.retrieve_id(body.id, &pool).await.unwrap();
+        assert_eq!(fs::read(job.loc.join("receptor.pdb")).unwrap(), b"ATOM");
+
+        let (status, body) = submit_json(app.clone(), document("gone.pdb")).await;
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+        assert!(body.message.ends_with("answered 404 Not Found"));
+
+        let mut refused = document("1abc.pdb");
+        refused["inputs"][0]["url"] = "http://169.254.169.254/latest/meta-data".into();
+        let (status, body) = submit_json(app, refused).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body.message, "Inputs can't be fetched from 169.254.169.254");
+
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM jobs")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_submit_parameters_refused() {
+        let tempdir = TempDir::new().unwrap();
+        let template = tempdir.path().join("test.sh");
+        fs::write(&template, "dock --chains {{chains}}\n").unwrap();
+        let pool = setup_test_db().await;
+        let mut config = make_config(tempdir.path().to_str().unwrap());
+        config.services.insert(
+            "files".to_string(),
+            Service {
+                name: "files".to_string(),
+                ..Default::default()
+            },
+        );
+        config.services.get_mut("test").unwrap().template =
+            Some(template.to_str().unwrap().to_string());
+        config.require_registered_users = true;
+        let app = create_routes(pool.clone(), config, Client::default());
+
+        for (document, message) in [
+            (
+                serde_json::json!({"user_id": 1, "service": "files", "parameters": {}}),
+                "Service files does not take parameter submissions, use /upload",
+            ),
+            (
+                serde_json::json!({"user_id": 1, "service": "nope", "parameters": {}}),
+                "Invalid service",
+            ),
+            (
+                serde_json::json!({"user_id": 1, "service": "test", "parameters": {}}),
+                "Missing parameter chains",
+            ),
+            (
+                serde_json::json!({"user_id": 999, "service": "test", "parameters": {"chains": "A"}}),
+                "Unknown user 999",
+            ),
+            (
+                serde_json::json!({"user_id": 1, "service": "test", "parameters": {"chains": "A"},
+                    "inputs": [{"url": "https://files.rcsb.org/download/1abc.pdb"}]}),
+                "Remote inputs are not enabled",
+            ),
+        ] {
+            let (status, body) = submit_json(app.clone(), document).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+            assert_eq!(body.message, message);
+        }
+
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM jobs")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 0);
+    }
+
+    #[tokio::test]
+    async fn test_upload_group() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let config = make_config(tempdir.path().to_str().unwrap());
+        let app = create_routes(pool.clone(), config, Client::default());
+
+        for (group, expected) in [
+            ("batch-1", StatusCode::CREATED),
+            ("../x", StatusCode::BAD_REQUEST),
+        ] {
+            let boundary = "testboundary123";
+            let parts = vec![
+                ("file", b"file content".as_slice(), Some("test.txt")),
+                ("user_id", b"1".as_slice(), None),
+                ("service", b"test".as_slice(), None),
+                ("group", group.as_bytes(), None),
+            ];
+            let request = Request::builder()
+                .method("POST")
+                .uri("/upload")
+                .header(
+                    "content-type",
+                    format!("multipart/form-data; boundary={boundary}"),
+                )
+                .body(Body::from(build_multipart(boundary, &parts)))
+                .unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), expected);
+        }
+
+        let jobs = Job::list_by_group("batch-1", 1, &pool).await.unwrap();
+        assert_eq!(jobs.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_upload_timeout() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let mut config = make_config(tempdir.path().to_str().unwrap());
+        config.services.get_mut("test").unwrap().max_timeout =
+            Some(std::time::Duration::from_secs(3600));
+        let app = create_routes(pool.clone(), config, Client::default());
+
+        for (timeout, expected) in [
+            ("600", StatusCode::CREATED),
+            ("7200", StatusCode::BAD_REQUEST),
+            ("0", StatusCode::BAD_REQUEST),
+            ("soon", StatusCode::BAD_REQUEST),
+        ] {
+            let boundary = "testboundary123";
+            let parts = vec![
+                ("file", b"file content".as_slice(), Some("test.txt")),
+                ("user_id", b"1".as_slice(), None),
+                ("service", b"test".as_slice(), None),
+                ("timeout", timeout.as_bytes(), None),
+            ];
+            let request = Request::builder()
+                .method("POST")
+                .uri("/upload")
+                .header(
+                    "content-type",
+                    format!("multipart/form-data; boundary={boundary}"),
+                )
+                .body(Body::from(build_multipart(boundary, &parts)))
+                .unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), expected, "timeout {timeout}");
+        }
+
+        let timeouts: Vec<Option<u32>> = sqlx::query_scalar("SELECT timeout FROM jobs")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(timeouts, vec![Some(600)]);
+    }
+
+    #[tokio::test]
+    async fn test_upload_priority() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let config = make_config(tempdir.path().to_str().unwrap());
+        let app = create_routes(pool.clone(), config, Client::default());
+
+        for (priority, expected) in [
+            ("5", StatusCode::CREATED),
+            ("-10", StatusCode::CREATED),
+            ("11", StatusCode::BAD_REQUEST),
+            ("urgent", StatusCode::BAD_REQUEST),
+        ] {
+            let boundary = "testboundary123";
+            let parts = vec![
+                ("file", b"file content".as_slice(), Some("test.txt")),
+                ("user_id", b"1".as_slice(), None),
+                ("service", b"test".as_slice(), None),
+                ("priority", priority.as_bytes(), None),
+            ];
+            let request = Request::builder()
+                .method("POST")
+                .uri("/upload")
+                .header(
+                    "content-type",
+                    format!("multipart/form-data; boundary={boundary}"),
+                )
+                .body(Body::from(build_multipart(boundary, &parts)))
+                .unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), expected, "priority {priority}");
+        }
+
+        let priorities: Vec<i32> = sqlx::query_scalar("SELECT priority FROM jobs ORDER BY id")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(priorities, vec![5, -10]);
+    }
+
+    #[tokio::test]
+    async fn test_upload_pre_submit_hook() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let mut config = make_config(tempdir.path().to_str().unwrap());
+        // Turns down the urgent jobs, and fails on the ones of priority 1
+        let hook = tempdir.path().join("pre-submit");
+        std::fs::write(
+            &hook,
+            "#!/bin/sh\ninput=$(cat)\n\
+             case \"$input\" in\n\
+             *'\"priority\":5'*) echo 'no budget for urgent jobs' >&2; exit 1 ;;\n\
+             *'\"priority\":1'*) exit 2 ;;\n\
+             esac\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&hook, std::fs::Permissions::from_mode(0o755)).unwrap();
+        config.hooks.pre_submit = Some(hook);
+        let app = create_routes(pool.clone(), config, Client::default());
+
+        for (priority, expected, message) in [
+            ("0", StatusCode::CREATED, "Job successfully uploaded"),
+            (
+                "5",
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "Submission rejected: no budget for urgent jobs",
+            ),
+            (
+                "1",
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Could not check the submission",
+            ),
+        ] {
+            let boundary = "testboundary123";
+            let parts = vec![
+                ("file", b"file content".as_slice(), Some("test.txt")),
+                ("user_id", b"1".as_slice(), None),
+                ("service", b"test".as_slice(), None),
+                ("priority", priority.as_bytes(), None),
+            ];
+            let request = Request::builder()
+                .method("POST")
+                .uri("/upload")
+                .header(
+                    "content-type",
+                    format!("multipart/form-data; boundary={boundary}"),
+                )
+                .body(Body::from(build_multipart(boundary, &parts)))
+                .unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), expected, "priority {priority}");
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let body: StatusBody = serde_json::from_slice(&body).unwrap();
+            assert_eq!(body.message, message);
+        }
+
+        let priorities: Vec<i32> = sqlx::query_scalar("SELECT priority FROM jobs ORDER BY id")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(priorities, vec![0]);
+    }
+
+    #[tokio::test]
+    async fn test_upload_dedupe_after_pre_submit_hook() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let mut config = make_config(tempdir.path().join("data").to_str().unwrap());
+        // Lowercases the input, and turns everything down once `closed` exists
+        let closed = tempdir.path().join("closed");
+        let hook = tempdir.path().join("pre-submit");
+        std::fs::write(
+            &hook,
+            format!(
+                r#"#!/bin/sh
+loc=$(sed -n 's/.*"loc":"\([^"]*\)".*/\1/p')
+if [ -e {} ]; then echo 'submissions closed' >&2; exit 1; fi
+tr A-Z a-z < "$loc/test.txt" > "$loc/lower" && mv "$loc/lower" "$loc/test.txt"
+"#,
+                closed.display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&hook, std::fs::Permissions::from_mode(0o755)).unwrap();
+        config.hooks.pre_submit = Some(hook);
+        let app = create_routes(pool.clone(), config, Client::default());
+
+        let upload = |content: &'static [u8]| {
+            let app = app.clone();
+            async move {
+                let boundary = "testboundary123";
+                let parts = vec![
+                    ("file", content, Some("test.txt")),
+                    ("user_id", b"1".as_slice(), None),
+                    ("service", b"test".as_slice(), None),
+                    ("dedupe", b"true".as_slice(), None),
+                ];
+                let request = Request::builder()
+                    .method("POST")
+                    .uri("/upload")
+                    .header(
+                        "content-type",
+                        format!("multipart/form-data; boundary={boundary}"),
+                    )
+                    .body(Body::from(build_multipart(boundary, &parts)))
+                    .unwrap();
+                let response = app.oneshot(request).await.unwrap();
+                let status = response.status();
+                let body: StatusBody = serde_json::from_slice(&body_bytes(response).await).unwrap();
+                (status, body.id)
+            }
+        };
+
+        let (status, first) = upload(b"INPUT").await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        // Matched on the files as the hook left them
+        assert_eq!(upload(b"input").await, (StatusCode::OK, first));
+
+        // A duplicate is still checked
+        std::fs::write(&closed, "").unwrap();
+        assert_eq!(upload(b"input").await.0, StatusCode::UNPROCESSABLE_ENTITY);
+
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM jobs")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 1);
     }
 
-    fn make_config(data_path: &str) -> Config {
-        let mut services = HashMap::new();
-        services.insert(
-            "test".to_string(),
-            Service {
-                name: "test".to_string(),
-                upload_url: "http://example.com/upload".to_string(),
-                download_url: "http://example.com/download".to_string(),
-                terminate_url: "http://example.com/terminate".to_string(),
-                runs_per_user: 5,
-                max_runs: 1,
+    #[tokio::test]
+    async fn test_upload_unknown_user() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let mut config = make_config(tempdir.path().to_str().unwrap());
+
+        // Registered on the first upload
+        let app = create_routes(pool.clone(), config.clone(), Client::default());
+        assert_eq!(upload_as(app, "999", None).await, StatusCode::CREATED);
+        assert_eq!(User::retrieve_id(999, &pool).await.unwrap().name, "user999");
+
+        config.require_registered_users = true;
+        let app = create_routes(pool, config, Client::default());
+        assert_eq!(upload_as(app, "998", None).await, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_upload_disabled_user() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let config = make_config(tempdir.path().to_str().unwrap());
+
+        let mut user = User {
+            name: "disabled".to_string(),
+            enabled: false,
+            ..Default::default()
+        };
+        user.add_to_db(&pool).await.unwrap();
+
+        let app = create_routes(pool, config, Client::default());
+        assert_eq!(
+            upload_as(app, &user.id.to_string(), None).await,
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[tokio::test]
+    async fn test_upload_tenant_of_user() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let mut config = make_config(tempdir.path().to_str().unwrap());
+        config.tenants.insert(
+            "lab".to_string(),
+            Tenant {
+                name: "lab".to_string(),
+                ..Default::default()
             },
         );
-        Config {
-            services,
-            db_path: ":memory:".to_string(),
-            data_path: data_path.to_string(),
-            max_age: std::time::Duration::from_secs(3600),
-            port: 5000,
-        }
+
+        let mut user = User {
+            name: "lab user".to_string(),
+            tenant: "lab".to_string(),
+            ..Default::default()
+        };
+        user.add_to_db(&pool).await.unwrap();
+        let user_id = user.id.to_string();
+
+        let app = create_routes(pool.clone(), config, Client::default());
+
+        // Without a tenant field the job goes to the tenant of the user
+        assert_eq!(
+            upload_as(app.clone(), &user_id, None).await,
+            StatusCode::CREATED
+        );
+        let tenant: String = sqlx::query_scalar("SELECT tenant FROM jobs")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(tenant, "lab");
+
+        // A user cannot submit to a tenant it does not belong to
+        assert_eq!(
+            upload_as(app, "1", Some("lab")).await,
+            StatusCode::FORBIDDEN
+        );
     }
 
-    fn build_multipart(boundary: &str, parts: &[(&str, &[u8], Option<&str>)]) -> Vec<u8> {
-        let mut body = Vec::new();
-        for (name, data, filename) in parts {
-            body.extend(format!("--{boundary}\r\n").as_bytes());
-            if let Some(fname) = filename {
-                body.extend(
-                    format!(
-                        "Content-Disposition: form-data; name=\"{name}\"; filename=\"{fname}\"\r\n"
-                    )
-                    .as_bytes(),
-                );
-                body.extend(b"Content-Type: application/octet-stream\r\n");
-            } else {
-                body.extend(
-                    format!("Content-Disposition: form-data; name=\"{name}\"\r\n").as_bytes(),
-                );
-            }
-            body.extend(b"\r\n");
-            body.extend(*data);
-            body.extend(b"\r\n");
-        }
-        body.extend(format!("--{boundary}--\r\n").as_bytes());
-        body
+    #[tokio::test]
+    async fn test_upload_service_not_allowed_for_tier() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let mut config = make_config(tempdir.path().to_str().unwrap());
+        config.tiers.insert(
+            "trial".to_string(),
+            Tier {
+                name: "trial".to_string(),
+                services: vec!["other".to_string()],
+                ..Default::default()
+            },
+        );
+
+        let mut user = User {
+            name: "trial user".to_string(),
+            tier: "trial".to_string(),
+            ..Default::default()
+        };
+        user.add_to_db(&pool).await.unwrap();
+
+        let app = create_routes(pool.clone(), config, Client::default());
+
+        assert_eq!(
+            upload_as(app.clone(), &user.id.to_string(), None).await,
+            StatusCode::FORBIDDEN
+        );
+        // Users of other tiers are not restricted
+        assert_eq!(upload_as(app, "1", None).await, StatusCode::CREATED);
     }
 
-    async fn body_bytes(response: axum::response::Response) -> bytes::Bytes {
-        axum::body::to_bytes(response.into_body(), usize::MAX)
+    #[tokio::test]
+    async fn test_get_job() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let config = make_config(tempdir.path().to_str().unwrap());
+
+        let mut job = Job::new(tempdir.path().to_str().unwrap());
+        job.set_user_id(1);
+        job.set_service("test".to_string());
+        job.add_to_db(&pool).await.unwrap();
+        job.update_checksum("abc123".to_string(), &pool)
             .await
-            .unwrap()
+            .unwrap();
+        let summary = OutputSummary {
+            archive_size: 512,
+            file_count: 4,
+            exit_code: Some(0),
+        };
+        job.update_output_summary(summary, &pool).await.unwrap();
+
+        let app = create_routes(pool, config, Client::default());
+
+        let request = Request::builder()
+            .uri(format!("/jobs/{}", job.id))
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body: serde_json::Value = serde_json::from_slice(&body_bytes(response).await).unwrap();
+        assert_eq!(body["id"], job.id);
+        assert_eq!(body["checksum"], "abc123");
+        assert_eq!(body["archive_size"], 512);
+        assert_eq!(body["file_count"], 4);
+        assert_eq!(body["exit_code"], 0);
+
+        let request = Request::builder()
+            .uri("/jobs/9999")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
     }
 
     #[tokio::test]
-    async fn test_upload_success() {
+    async fn test_get_job_webhooks() {
         let tempdir = TempDir::new().unwrap();
         let pool = setup_test_db().await;
         let config = make_config(tempdir.path().to_str().unwrap());
-        let app = create_routes(pool, config);
 
-        let boundary = "testboundary123";
-        let body = build_multipart(
-            boundary,
-            &[
-                ("file", b"file content".as_slice(), Some("test.txt")),
-                ("user_id", b"1", None),
-                ("service", b"test", None),
-            ],
-        );
+        let mut job = Job::new(tempdir.path().to_str().unwrap());
+        job.set_user_id(1);
+        job.metadata.callback_url = Some("https://example.com/hook".to_string());
+        job.add_to_db(&pool).await.unwrap();
+        job.update_status(Status::Failed, &pool).await.unwrap();
+        webhook::enqueue(&job, &HashMap::new(), &pool)
+            .await
+            .unwrap();
+
+        let app = create_routes(pool, config, Client::default());
 
         let request = Request::builder()
-            .method("POST")
-            .uri("/upload")
-            .header(
-                "content-type",
-                format!("multipart/form-data; boundary={boundary}"),
-            )
-            .body(Body::from(body))
+            .uri(format!("/jobs/{}/webhooks", job.id))
+            .body(Body::empty())
             .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(&body_bytes(response).await).unwrap();
+        assert_eq!(body[0]["url"], "https://example.com/hook");
+        assert_eq!(body[0]["event"], "job.failed");
+        assert_eq!(body[0]["state"], "Pending");
+        assert_eq!(body[0]["attempts"], 0);
 
+        let request = Request::builder()
+            .uri("/jobs/9999/webhooks")
+            .body(Body::empty())
+            .unwrap();
         let response = app.oneshot(request).await.unwrap();
-        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_get_job_events() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let config = make_config(tempdir.path().to_str().unwrap());
+
+        let mut job = Job::new(tempdir.path().to_str().unwrap());
+        job.set_user_id(1);
+        job.add_to_db(&pool).await.unwrap();
+        JobEvent::new(job.id, Status::Completed, Status::Cleaned, CLEANER_ACTOR)
+            .with_reason("files deleted")
+            .add_to_db(&pool)
+            .await
+            .unwrap();
+
+        let app = create_routes(pool, config, Client::default());
+
+        let request = Request::builder()
+            .uri(format!("/jobs/{}/events", job.id))
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(&body_bytes(response).await).unwrap();
+        assert_eq!(body.as_array().unwrap().len(), 1);
+        assert_eq!(body[0]["old_status"], "Completed");
+        assert_eq!(body[0]["new_status"], "Cleaned");
+        assert_eq!(body[0]["actor"], "cleaner");
+        assert_eq!(body[0]["reason"], "files deleted");
+        assert!(body[0]["secs_in_old_status"].is_u64());
+
+        let request = Request::builder()
+            .uri("/jobs/9999/events")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_get_job_eta() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let mut config = make_config(tempdir.path().to_str().unwrap());
+        // No client to ask for runtimes
+        config.services.get_mut("test").unwrap().upload_url =
+            "http://127.0.0.1:1/upload".to_string();
+
+        let mut ids = Vec::new();
+        for status in [Status::Queued, Status::Completed] {
+            let mut job = Job::new(tempdir.path().to_str().unwrap());
+            job.set_user_id(1);
+            job.set_service("test".to_string());
+            job.add_to_db(&pool).await.unwrap();
+            job.update_status(status, &pool).await.unwrap();
+            ids.push(job.id);
+        }
+
+        let app = create_routes(pool, config, Client::default());
+
+        let request = Request::builder()
+            .uri(format!("/jobs/{}/eta", ids[0]))
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(&body_bytes(response).await).unwrap();
+        assert_eq!(body["queue_position"], 1);
+        assert_eq!(body["max_runs"], 1);
+        assert!(body["eta_secs"].is_null());
+
+        let request = Request::builder()
+            .uri(format!("/jobs/{}/eta", ids[1]))
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let request = Request::builder()
+            .uri("/jobs/9999/eta")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_get_job_history() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let config = make_config(tempdir.path().to_str().unwrap());
+
+        let mut job = Job::new(tempdir.path().to_str().unwrap());
+        job.set_user_id(1);
+        job.add_to_db(&pool).await.unwrap();
+        for status in [Status::Queued, Status::Submitted, Status::Completed] {
+            job.update_status(status, &pool).await.unwrap();
+        }
+
+        let app = create_routes(pool, config, Client::default());
+
+        let request = Request::builder()
+            .uri(format!("/jobs/{}/history", job.id))
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(&body_bytes(response).await).unwrap();
+        let statuses: Vec<&str> = body
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|c| c["new_status"].as_str().unwrap())
+            .collect();
+        assert_eq!(statuses, ["Queued", "Submitted", "Completed"]);
+        assert_eq!(body[2]["old_status"], "Submitted");
+        assert_eq!(body[2]["actor"], SERVER_ACTOR);
+        assert!(body[2]["secs_in_old_status"].is_u64());
+
+        let request = Request::builder()
+            .uri("/jobs/9999/history")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_get_job_inputs() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let config = make_config(tempdir.path().to_str().unwrap());
+
+        let mut job = Job::new(tempdir.path().to_str().unwrap());
+        job.set_user_id(1);
+        job.add_to_db(&pool).await.unwrap();
+        let mut older = Job::new(tempdir.path().to_str().unwrap());
+        older.set_user_id(1);
+        older.add_to_db(&pool).await.unwrap();
+
+        let inputs = vec![ManifestEntry {
+            path: "run.sh".to_string(),
+            size: 4,
+            sha256: "aa".to_string(),
+        }];
+        job.record_inputs(&inputs, &pool).await.unwrap();
+
+        let app = create_routes(pool, config, Client::default());
+        let get = |id: u32| {
+            Request::builder()
+                .uri(format!("/jobs/{id}/inputs"))
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(get(job.id)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let listed: Vec<ManifestEntry> =
+            serde_json::from_slice(&body_bytes(response).await).unwrap();
+        assert_eq!(listed, inputs);
+
+        let response = app.clone().oneshot(get(older.id)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = app.oneshot(get(9999)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_update_job() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let mut config = make_config(tempdir.path().to_str().unwrap());
+        config.admin_token = Some(Secret::new("admin-secret"));
+
+        let mut job = Job::new(tempdir.path().to_str().unwrap());
+        job.set_user_id(1);
+        job.set_service("test".to_string());
+        job.metadata.description = Some("docking run".to_string());
+        job.add_to_db(&pool).await.unwrap();
+
+        let app = create_routes(pool.clone(), config, Client::default());
+        let patch = |query: &str, auth: Option<&str>, body: &str| {
+            let mut request = Request::builder()
+                .method("PATCH")
+                .uri(format!("/jobs/{}{query}", job.id))
+                .header("content-type", "application/json");
+            if let Some(auth) = auth {
+                request = request.header("authorization", auth);
+            }
+            request.body(Body::from(body.to_string())).unwrap()
+        };
+
+        // The owner fixes a typo in the callback, the description is kept
+        let request = patch(
+            "?user_id=1",
+            None,
+            r#"{"callback_url": "https://example.com/hook", "tags": [" docking ", "batch"]}"#,
+        );
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(&body_bytes(response).await).unwrap();
+        assert_eq!(body["callback_url"], "https://example.com/hook");
+        assert_eq!(body["tags"], serde_json::json!(["docking", "batch"]));
+        assert_eq!(body["description"], "docking run");
+
+        // Another user may not
+        let request = patch("?user_id=0", None, r#"{"description": "mine"}"#);
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let request = patch("", None, r#"{"description": "mine"}"#);
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let request = patch(
+            "?user_id=1",
+            None,
+            r#"{"callback_url": "ftp://example.com"}"#,
+        );
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
 
-        let bytes = body_bytes(response).await;
-        let body: StatusBody = serde_json::from_slice(&bytes).unwrap();
-        assert_eq!(body.status, Status::Queued);
-        assert!(body.message.contains("Job successfully uploaded"));
-    }
+        // An operator can, and an empty string clears the field
+        let request = patch("", Some("Bearer wrong"), r#"{"description": ""}"#);
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
 
-    #[tokio::test]
-    async fn test_upload_missing_user_id() {
-        let tempdir = TempDir::new().unwrap();
-        let pool = setup_test_db().await;
-        let config = make_config(tempdir.path().to_str().unwrap());
-        let app = create_routes(pool, config);
+        let request = patch("", Some("Bearer admin-secret"), r#"{"description": ""}"#);
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
 
-        let boundary = "testboundary123";
-        let body = build_multipart(
-            boundary,
-            &[
-                ("file", b"file content".as_slice(), Some("test.txt")),
-                ("service", b"test", None),
-            ],
+        let mut stored = Job::new("");
+        stored.retrieve_id(job.id, &pool).await.unwrap();
+        assert_eq!(stored.metadata.description, None);
+        assert_eq!(
+            stored.metadata.callback_url.as_deref(),
+            Some("https://example.com/hook")
         );
 
         let request = Request::builder()
-            .method("POST")
-            .uri("/upload")
-            .header(
-                "content-type",
-                format!("multipart/form-data; boundary={boundary}"),
-            )
-            .body(Body::from(body))
+            .method("PATCH")
+            .uri("/jobs/9999?user_id=1")
+            .header("content-type", "application/json")
+            .body(Body::from("{}"))
             .unwrap();
-
         let response = app.oneshot(request).await.unwrap();
-        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
-
-        let bytes = body_bytes(response).await;
-        let body: StatusBody = serde_json::from_slice(&bytes).unwrap();
-        assert!(body.message.contains("Missing user_id"));
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
     }
 
     #[tokio::test]
-    async fn test_upload_invalid_user_id() {
+    async fn test_cancel_job() {
         let tempdir = TempDir::new().unwrap();
         let pool = setup_test_db().await;
         let config = make_config(tempdir.path().to_str().unwrap());
-        let app = create_routes(pool, config);
-
-        let boundary = "testboundary123";
-        let body = build_multipart(
-            boundary,
-            &[
-                ("file", b"file content".as_slice(), Some("test.txt")),
-                ("user_id", b"abc", None),
-                ("service", b"test", None),
-            ],
-        );
 
-        let request = Request::builder()
-            .method("POST")
-            .uri("/upload")
-            .header(
-                "content-type",
-                format!("multipart/form-data; boundary={boundary}"),
-            )
-            .body(Body::from(body))
+        let mut queued = Job::new(tempdir.path().to_str().unwrap());
+        queued.set_user_id(1);
+        queued.set_service("test".to_string());
+        queued.add_to_db(&pool).await.unwrap();
+        queued.update_status(Status::Queued, &pool).await.unwrap();
+        let mut claimed = Job::new(tempdir.path().to_str().unwrap());
+        claimed.set_user_id(1);
+        claimed.set_service("test".to_string());
+        claimed.add_to_db(&pool).await.unwrap();
+        claimed.update_status(Status::Queued, &pool).await.unwrap();
+        sqlx::query("UPDATE jobs SET claimed_at = datetime('now') WHERE id = ?")
+            .bind(claimed.id)
+            .execute(&pool)
+            .await
             .unwrap();
 
-        let response = app.oneshot(request).await.unwrap();
-        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let app = create_routes(pool.clone(), config, Client::default());
+        let cancel = |uri: String| async {
+            let request = Request::builder()
+                .method("DELETE")
+                .uri(uri)
+                .body(Body::empty())
+                .unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            let status = response.status();
+            let body: StatusBody = serde_json::from_slice(&body_bytes(response).await).unwrap();
+            (status, body)
+        };
 
-        let bytes = body_bytes(response).await;
-        let body: StatusBody = serde_json::from_slice(&bytes).unwrap();
-        assert!(body.message.contains("Invalid user_id"));
+        let (status, _) = cancel(format!("/jobs/{}?user_id=0", queued.id)).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let (status, body) = cancel(format!("/jobs/{}?user_id=1", queued.id)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body.status, Status::Cancelled);
+        let mut stored = Job::new("");
+        stored.retrieve_id(queued.id, &pool).await.unwrap();
+        assert_eq!(stored.status, Status::Cancelled);
+
+        let (status, body) = cancel(format!("/jobs/{}?user_id=1", queued.id)).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(
+            body.message,
+            format!("Job {} is already cancelled", queued.id)
+        );
+
+        // The sender is about to send it
+        let (status, body) = cancel(format!("/jobs/{}?user_id=1", claimed.id)).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body.status, Status::Queued);
+
+        let (status, _) = cancel("/jobs/9999?user_id=1".to_string()).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
     }
 
     #[tokio::test]
-    async fn test_upload_missing_service() {
+    async fn test_download_link() {
         let tempdir = TempDir::new().unwrap();
         let pool = setup_test_db().await;
-        let config = make_config(tempdir.path().to_str().unwrap());
-        let app = create_routes(pool, config);
+        let mut config = make_config(tempdir.path().to_str().unwrap());
+        config.download_links = Some(DownloadLinkConfig {
+            secret: Secret::new("link-key"),
+            max_ttl: std::time::Duration::from_secs(86400),
+            base_url: Some("https://orchestrator.example.org".to_string()),
+        });
 
-        let boundary = "testboundary123";
-        let body = build_multipart(
-            boundary,
-            &[
-                ("file", b"file content".as_slice(), Some("test.txt")),
-                ("user_id", b"1", None),
-            ],
-        );
+        let mut job = Job::new(tempdir.path().to_str().unwrap());
+        job.set_user_id(1);
+        job.set_service("test".to_string());
+        job.add_to_db(&pool).await.unwrap();
+        fs::create_dir_all(&job.loc).unwrap();
+        fs::write(job.loc.join("output.zip"), b"fake zip content").unwrap();
+        job.update_status(Status::Completed, &pool).await.unwrap();
 
-        let request = Request::builder()
-            .method("POST")
-            .uri("/upload")
-            .header(
-                "content-type",
-                format!("multipart/form-data; boundary={boundary}"),
-            )
-            .body(Body::from(body))
-            .unwrap();
+        let app = create_routes(pool, config, Client::default());
+        let post = |query: &str| {
+            Request::builder()
+                .method("POST")
+                .uri(format!("/jobs/{}/download_link{query}", job.id))
+                .body(Body::empty())
+                .unwrap()
+        };
+        let get = |uri: &str| Request::builder().uri(uri).body(Body::empty()).unwrap();
 
-        let response = app.oneshot(request).await.unwrap();
+        let response = app.clone().oneshot(post("?user_id=0")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = app
+            .clone()
+            .oneshot(post("?user_id=1&expires_in=172800"))
+            .await
+            .unwrap();
         assert_eq!(response.status(), StatusCode::BAD_REQUEST);
 
-        let bytes = body_bytes(response).await;
-        let body: StatusBody = serde_json::from_slice(&bytes).unwrap();
-        assert!(body.message.contains("Missing service"));
+        let response = app
+            .clone()
+            .oneshot(post("?user_id=1&expires_in=600"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let link: DownloadLink = serde_json::from_slice(&body_bytes(response).await).unwrap();
+        let path = link
+            .url
+            .strip_prefix("https://orchestrator.example.org")
+            .unwrap();
+        assert!(path.starts_with(&format!("/shared/{}?expires={}&", job.id, link.expires)));
+
+        // The link alone is enough
+        let response = app.clone().oneshot(get(path)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body_bytes(response).await.as_ref(), b"fake zip content");
+
+        // Tampering with the expiry or the job breaks the signature
+        let later = path.replace(
+            &format!("expires={}", link.expires),
+            &format!("expires={}", link.expires + 3600),
+        );
+        let response = app.clone().oneshot(get(&later)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let other = path.replace(&format!("/shared/{}", job.id), "/shared/9999");
+        let response = app.oneshot(get(&other)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
     }
 
     #[tokio::test]
-    async fn test_upload_invalid_service() {
+    async fn test_download_link_not_configured() {
         let tempdir = TempDir::new().unwrap();
         let pool = setup_test_db().await;
         let config = make_config(tempdir.path().to_str().unwrap());
-        let app = create_routes(pool, config);
-
-        let boundary = "testboundary123";
-        let body = build_multipart(
-            boundary,
-            &[
-                ("file", b"file content".as_slice(), Some("test.txt")),
-                ("user_id", b"1", None),
-                ("service", b"unknownservice", None),
-            ],
-        );
+        let app = create_routes(pool, config, Client::default());
 
         let request = Request::builder()
             .method("POST")
-            .uri("/upload")
-            .header(
-                "content-type",
-                format!("multipart/form-data; boundary={boundary}"),
-            )
-            .body(Body::from(body))
+            .uri("/jobs/1/download_link?user_id=1")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let request = Request::builder()
+            .uri("/shared/1?expires=1&signature=00")
+            .body(Body::empty())
             .unwrap();
-
         let response = app.oneshot(request).await.unwrap();
-        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
-
-        let bytes = body_bytes(response).await;
-        let body: StatusBody = serde_json::from_slice(&bytes).unwrap();
-        assert!(body.message.contains("Invalid service"));
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
     }
 
     #[tokio::test]
@@ -566,7 +3499,7 @@ mod tests {
         let tempdir = TempDir::new().unwrap();
         let pool = setup_test_db().await;
         let config = make_config(tempdir.path().to_str().unwrap());
-        let app = create_routes(pool, config);
+        let app = create_routes(pool, config, Client::default());
 
         let request = Request::builder()
             .method("GET")
@@ -599,7 +3532,7 @@ mod tests {
         job.update_status(Status::Queued, &pool).await.unwrap();
         let job_id = job.id;
 
-        let app = create_routes(pool, config);
+        let app = create_routes(pool, config, Client::default());
 
         let request = Request::builder()
             .method("GET")
@@ -614,6 +3547,41 @@ mod tests {
         let body: StatusBody = serde_json::from_slice(&bytes).unwrap();
         assert_eq!(body.id, job_id);
         assert_eq!(body.status, Status::Queued);
+        assert_eq!(body.message, "");
+    }
+
+    #[tokio::test]
+    async fn test_download_queued_outside_window() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let mut config = make_config(tempdir.path().to_str().unwrap());
+        // Only open the day after tomorrow
+        let today = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            / 86400;
+        let day = ["thu", "fri", "sat", "sun", "mon", "tue", "wed"][(today as usize + 2) % 7];
+        config.services.get_mut("test").unwrap().windows = Some(day.parse().unwrap());
+
+        let mut job = Job::new(tempdir.path().to_str().unwrap());
+        job.set_user_id(1);
+        job.set_service("test".to_string());
+        job.add_to_db(&pool).await.unwrap();
+        job.update_status(Status::Queued, &pool).await.unwrap();
+
+        let app = create_routes(pool, config, Client::default());
+        let request = Request::builder()
+            .uri(format!("/download/{}", job.id))
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: StatusBody = serde_json::from_slice(&body_bytes(response).await).unwrap();
+        assert_eq!(body.status, Status::Queued);
+        let expected = "Outside the execution windows of service test, the next one opens ";
+        assert!(body.message.starts_with(expected), "{}", body.message);
+        assert!(body.message.ends_with(" 00:00 UTC"), "{}", body.message);
     }
 
     #[tokio::test]
@@ -629,7 +3597,7 @@ mod tests {
         job.update_status(Status::Running, &pool).await.unwrap();
         let job_id = job.id;
 
-        let app = create_routes(pool, config);
+        let app = create_routes(pool, config, Client::default());
 
         let request = Request::builder()
             .method("GET")
@@ -663,7 +3631,7 @@ mod tests {
         job.update_status(Status::Completed, &pool).await.unwrap();
         let job_id = job.id;
 
-        let app = create_routes(pool, config);
+        let app = create_routes(pool, config, Client::default());
 
         let request = Request::builder()
             .method("GET")
@@ -685,6 +3653,161 @@ mod tests {
         assert_eq!(&bytes[..], b"fake zip content");
     }
 
+    #[tokio::test]
+    async fn test_download_completed_job_sets_etag() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let config = make_config(tempdir.path().to_str().unwrap());
+
+        let mut job = Job::new(tempdir.path().to_str().unwrap());
+        job.set_user_id(1);
+        job.set_service("test".to_string());
+        job.add_to_db(&pool).await.unwrap();
+
+        fs::create_dir_all(&job.loc).unwrap();
+        fs::write(job.loc.join("output.zip"), b"abc").unwrap();
+
+        job.update_status(Status::Completed, &pool).await.unwrap();
+        let job_id = job.id;
+
+        let app = create_routes(pool.clone(), config, Client::default());
+
+        let request = Request::builder()
+            .method("GET")
+            .uri(format!("/download/{job_id}"))
+            .body(Body::empty())
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let etag = response
+            .headers()
+            .get("etag")
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("");
+        assert_eq!(
+            etag,
+            "\"ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad\""
+        );
+
+        // The lazily computed checksum is persisted
+        let mut stored = Job::new("");
+        stored.retrieve_id(job_id, &pool).await.unwrap();
+        assert!(stored.checksum.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_download_if_none_match_not_modified() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let config = make_config(tempdir.path().to_str().unwrap());
+
+        let mut job = Job::new(tempdir.path().to_str().unwrap());
+        job.set_user_id(1);
+        job.set_service("test".to_string());
+        job.add_to_db(&pool).await.unwrap();
+
+        fs::create_dir_all(&job.loc).unwrap();
+        fs::write(job.loc.join("output.zip"), b"fake zip content").unwrap();
+
+        job.update_checksum("deadbeef".to_string(), &pool)
+            .await
+            .unwrap();
+        job.update_status(Status::Completed, &pool).await.unwrap();
+        let job_id = job.id;
+
+        let app = create_routes(pool, config, Client::default());
+
+        let request = Request::builder()
+            .method("GET")
+            .uri(format!("/download/{job_id}"))
+            .header("if-none-match", "\"other\", \"deadbeef\"")
+            .body(Body::empty())
+            .unwrap();
+
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers().get("etag").unwrap(), "\"deadbeef\"");
+        let bytes = body_bytes(response).await;
+        assert!(bytes.is_empty());
+
+        // A stale tag still gets the archive
+        let request = Request::builder()
+            .method("GET")
+            .uri(format!("/download/{job_id}"))
+            .header("if-none-match", "\"stale\"")
+            .body(Body::empty())
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = body_bytes(response).await;
+        assert_eq!(&bytes[..], b"fake zip content");
+    }
+
+    #[tokio::test]
+    async fn test_download_recorded() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = setup_test_db().await;
+        let mut config = make_config(tempdir.path().to_str().unwrap());
+        config.admin_token = Some(Secret::new("s3cret"));
+
+        let mut job = Job::new(tempdir.path().to_str().unwrap());
+        job.set_user_id(1);
+        job.set_service("test".to_string());
+        job.add_to_db(&pool).await.unwrap();
+        fs::create_dir_all(&job.loc).unwrap();
+        fs::write(job.loc.join("output.zip"), b"fake zip content").unwrap();
+        job.update_checksum("deadbeef".to_string(), &pool)
+            .await
+            .unwrap();
+        job.update_status(Status::Completed, &pool).await.unwrap();
+        let job_id = job.id;
+
+        let app = create_routes(pool.clone(), config, Client::default());
+
+        let mut request = Request::builder()
+            .uri(format!("/download/{job_id}"))
+            .body(Body::empty())
+            .unwrap();
+        request
+            .extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from(([10, 0, 0, 7], 40000))));
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // A revalidation hands nothing out
+        let request = Request::builder()
+            .uri(format!("/download/{job_id}"))
+            .header("if-none-match", "\"deadbeef\"")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+        let detail = |token: Option<&str>| {
+            let mut builder = Request::builder().uri(format!("/jobs/{job_id}"));
+            if let Some(t) = token {
+                builder = builder.header("authorization", format!("Bearer {t}"));
+            }
+            builder.body(Body::empty()).unwrap()
+        };
+        let response = app.clone().oneshot(detail(Some("s3cret"))).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body_bytes(response).await).unwrap();
+        let downloads = body["downloads"].as_array().unwrap();
+        assert_eq!(downloads.len(), 1);
+        assert_eq!(downloads[0]["client_ip"], "10.0.0.7");
+        assert_eq!(downloads[0]["via"], "download");
+        assert_eq!(downloads[0]["bytes"], 16);
+        assert!(downloads[0]["downloaded_at"].is_string());
+
+        // Who downloaded what is for operators only
+        let response = app.oneshot(detail(None)).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body_bytes(response).await).unwrap();
+        assert!(body.get("downloads").is_none());
+    }
+
     #[tokio::test]
     async fn test_download_completed_missing_file() {
         let tempdir = TempDir::new().unwrap();
@@ -699,7 +3822,7 @@ mod tests {
         job.update_status(Status::Completed, &pool).await.unwrap();
         let job_id = job.id;
 
-        let app = create_routes(pool, config);
+        let app = create_routes(pool, config, Client::default());
 
         let request = Request::builder()
             .method("GET")
@@ -724,7 +3847,7 @@ mod tests {
         let tempdir = TempDir::new().unwrap();
         let pool = setup_test_db().await;
         let config = make_config(tempdir.path().to_str().unwrap());
-        let app = create_routes(pool, config);
+        let app = create_routes(pool, config, Client::default());
 
         let request = Request::builder()
             .method("GET")
@@ -775,7 +3898,7 @@ mod tests {
             service.download_url = format!("{}/retrieve", server.url());
         }
 
-        let app = create_routes(pool, config_with_mock);
+        let app = create_routes(pool, config_with_mock, Client::default());
 
         let request = Request::builder()
             .method("GET")
@@ -828,7 +3951,7 @@ mod tests {
             service.download_url = format!("{}/retrieve", server.url());
         }
 
-        let app = create_routes(pool, config_with_mock);
+        let app = create_routes(pool, config_with_mock, Client::default());
 
         let request = Request::builder()
             .method("GET")
@@ -860,7 +3983,7 @@ mod tests {
         job.update_status(Status::Running, &pool).await.unwrap();
         let job_id = job.id;
 
-        let app = create_routes(pool, config);
+        let app = create_routes(pool, config, Client::default());
 
         let request = Request::builder()
             .method("GET")
@@ -885,7 +4008,7 @@ mod tests {
         let tempdir = TempDir::new().unwrap();
         let pool = setup_test_db().await;
         let config = make_config(tempdir.path().to_str().unwrap());
-        let app = create_routes(pool, config);
+        let app = create_routes(pool, config, Client::default());
 
         let request = Request::builder()
             .method("POST")
@@ -935,7 +4058,7 @@ mod tests {
             service.terminate_url = format!("{}/terminate", server.url());
         }
 
-        let app = create_routes(pool, config_with_mock);
+        let app = create_routes(pool, config_with_mock, Client::default());
 
         let request = Request::builder()
             .method("POST")
@@ -984,7 +4107,7 @@ mod tests {
             service.terminate_url = format!("{}/terminate", server.url());
         }
 
-        let app = create_routes(pool, config_with_mock);
+        let app = create_routes(pool, config_with_mock, Client::default());
 
         let request = Request::builder()
             .method("POST")
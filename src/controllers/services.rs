@@ -0,0 +1,197 @@
+use crate::models::status_body::StatusBody;
+use crate::models::user_dao::User;
+use crate::routes::router::AppState;
+use axum::response::{IntoResponse, Response};
+use axum::{
+    extract::{Json, Query, State},
+    http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::{self, IntoParams, ToSchema};
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct ServicesParams {
+    /// User whose available services are returned
+    pub user_id: Option<u32>,
+}
+
+/// A service a user may submit to
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct AvailableService {
+    pub name: String,
+    /// Takes parameter submissions on `POST /jobs`, on top of `/upload`
+    pub parameters: bool,
+    /// Active jobs the user may have on this service
+    pub runs_per_user: u16,
+    /// Longest run time a submission may ask for, in seconds, unbounded when unset
+    pub max_timeout: Option<u64>,
+    /// Bytes the results of a job may take, unbounded when unset
+    pub max_output_size: Option<u64>,
+}
+
+/// The services open to both the tenant and the tier of a user
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ServiceCatalog {
+    pub user_id: u32,
+    pub tenant: String,
+    pub tier: String,
+    pub services: Vec<AvailableService>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/services",
+    params(ServicesParams),
+    responses(
+        (status = 200, description = "Services the user may submit to", body = ServiceCatalog),
+        (status = 400, description = "Missing user_id", body = StatusBody),
+        (status = 403, description = "User is disabled", body = StatusBody),
+        (status = 404, description = "Unknown user", body = StatusBody),
+        (status = 500, description = "Internal server error", body = StatusBody),
+    ),
+    tag = "users"
+)]
+pub async fn list_services(
+    State(state): State<AppState>,
+    Query(params): Query<ServicesParams>,
+) -> Response {
+    let mut body = StatusBody::new();
+
+    let Some(user_id) = params.user_id else {
+        body.message = "Missing user_id parameter".to_string();
+        return (StatusCode::BAD_REQUEST, Json(body)).into_response();
+    };
+
+    let user = match User::retrieve_id(user_id, &state.pool).await {
+        Ok(u) => u,
+        Err(sqlx::Error::RowNotFound) => {
+            body.message = format!("User {user_id} not found in the database");
+            return (StatusCode::NOT_FOUND, Json(body)).into_response();
+        }
+        Err(e) => {
+            tracing::error!("Could not retrieve user {user_id}: {:?}", e);
+            body.message = "Internal server error".to_string();
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response();
+        }
+    };
+
+    if !user.enabled {
+        body.message = format!("User {user_id} is disabled");
+        return (StatusCode::FORBIDDEN, Json(body)).into_response();
+    }
+
+    let config = &state.config;
+    let mut services: Vec<AvailableService> = config
+        .services
+        .values()
+        .filter(|s| config.user_allows_service(&user.tenant, &user.tier, &s.name))
+        .map(|s| AvailableService {
+            name: s.name.clone(),
+            parameters: s.template.is_some(),
+            runs_per_user: user
+                .runs_per_user
+                .unwrap_or_else(|| config.runs_per_user_for(&user.tenant, s)),
+            max_timeout: s.max_timeout.map(|t| t.as_secs()),
+            max_output_size: s.max_output_size,
+        })
+        .collect();
+    services.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Json(ServiceCatalog {
+        user_id: user.id,
+        tenant: user.tenant,
+        tier: user.tier,
+        services,
+    })
+    .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ServiceCatalog;
+    use crate::config::loader::{Config, Service, Tenant, Tier};
+    use crate::models::job_dto::create_jobs_table;
+    use crate::routes::router::create_routes;
+    use crate::services::client::Client;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use sqlx::SqlitePool;
+    use tower::ServiceExt;
+
+    async fn get(uri: &str) -> (StatusCode, bytes::Bytes) {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        create_jobs_table(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO users (id, name, tenant, tier, enabled) VALUES (1, 'user1', 'default', 'standard', 1), (2, 'user2', 'lab', 'trial', 1), (3, 'user3', 'default', 'standard', 0)",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let mut config = Config::default();
+        for name in ["alpha", "beta", "gamma"] {
+            config.services.insert(
+                name.to_string(),
+                Service {
+                    name: name.to_string(),
+                    runs_per_user: 5,
+                    max_runs: 10,
+                    ..Default::default()
+                },
+            );
+        }
+        config.tenants.insert(
+            "lab".to_string(),
+            Tenant {
+                name: "lab".to_string(),
+                services: vec!["alpha".to_string(), "beta".to_string()],
+                ..Default::default()
+            },
+        );
+        config.tiers.insert(
+            "trial".to_string(),
+            Tier {
+                name: "trial".to_string(),
+                services: vec!["beta".to_string(), "gamma".to_string()],
+                ..Default::default()
+            },
+        );
+        let app = create_routes(pool, config, Client::default());
+
+        let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, bytes)
+    }
+
+    fn names(catalog: &ServiceCatalog) -> Vec<&str> {
+        catalog.services.iter().map(|s| s.name.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_list_services() {
+        let (status, bytes) = get("/services?user_id=1").await;
+        assert_eq!(status, StatusCode::OK);
+        let catalog: ServiceCatalog = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(names(&catalog), vec!["alpha", "beta", "gamma"]);
+        assert_eq!(catalog.services[0].runs_per_user, 5);
+
+        // Only what both the tenant and the tier allow
+        let (status, bytes) = get("/services?user_id=2").await;
+        assert_eq!(status, StatusCode::OK);
+        let catalog: ServiceCatalog = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(catalog.tenant, "lab");
+        assert_eq!(catalog.tier, "trial");
+        assert_eq!(names(&catalog), vec!["beta"]);
+    }
+
+    #[tokio::test]
+    async fn test_list_services_errors() {
+        assert_eq!(get("/services").await.0, StatusCode::BAD_REQUEST);
+        assert_eq!(get("/services?user_id=3").await.0, StatusCode::FORBIDDEN);
+        assert_eq!(get("/services?user_id=99").await.0, StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,398 @@
+use crate::controllers::server::reject_non_owner;
+use crate::models::job_dao::Job;
+use crate::models::share_dao::Share;
+use crate::models::status_body::StatusBody;
+use crate::models::status_dto::Status;
+use crate::routes::router::AppState;
+use axum::response::{IntoResponse, Response};
+use axum::{
+    extract::{Json, Path, Query, State},
+    http::{HeaderMap, StatusCode},
+};
+use serde::Deserialize;
+use std::time::Duration;
+use utoipa::{self, IntoParams};
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct ShareParams {
+    /// User managing the shares, must own the job unless the admin token is sent
+    pub user_id: Option<i32>,
+    /// Seconds the share stays valid, one day by default
+    pub expires_in: Option<u64>,
+    /// Downloads allowed, unlimited by default
+    pub max_downloads: Option<u32>,
+}
+
+fn error_response(status: StatusCode, message: String) -> Response {
+    let body = StatusBody {
+        message,
+        ..Default::default()
+    };
+    (status, Json(body)).into_response()
+}
+
+fn internal_error(e: sqlx::Error) -> Response {
+    tracing::error!("Could not access the shares: {:?}", e);
+    error_response(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "Internal server error".to_string(),
+    )
+}
+
+/// The job, after checking shares are enabled and the caller owns it
+async fn owned_job(
+    state: &AppState,
+    id: u32,
+    headers: &HeaderMap,
+    user_id: Option<i32>,
+) -> Result<Job, Response> {
+    if state.config.download_links.is_none() {
+        return Err(error_response(
+            StatusCode::NOT_FOUND,
+            "Download links are not configured".to_string(),
+        ));
+    }
+    let mut job = Job::new(&state.config.data_path);
+    match job.retrieve_id(id, &state.pool).await {
+        Ok(_) => {}
+        Err(sqlx::Error::RowNotFound) => {
+            return Err(error_response(
+                StatusCode::NOT_FOUND,
+                format!("Job {id} not found in the database"),
+            ));
+        }
+        Err(e) => return Err(internal_error(e)),
+    }
+    match reject_non_owner(state, headers, user_id, &job) {
+        Some(response) => Err(response),
+        None => Ok(job),
+    }
+}
+
+/// The link to hand out for `share`
+fn with_url(state: &AppState, mut share: Share) -> Share {
+    let base = state
+        .config
+        .download_links
+        .as_ref()
+        .and_then(|l| l.base_url.as_deref())
+        .unwrap_or_default();
+    share.url = Some(format!(
+        "{base}/shared/{}?share={}",
+        share.job_id, share.token
+    ));
+    share
+}
+
+#[utoipa::path(
+    post,
+    path = "/jobs/{id}/shares",
+    params(
+        ("id" = u32, Path, description = "Job identifier"),
+        ShareParams
+    ),
+    responses(
+        (status = 201, description = "Share created, with its link", body = Share),
+        (status = 400, description = "Missing user_id or invalid validity", body = StatusBody),
+        (status = 401, description = "Invalid admin token", body = StatusBody),
+        (status = 403, description = "Job belongs to another user", body = StatusBody),
+        (status = 404, description = "Job not found or links not configured", body = StatusBody),
+        (status = 500, description = "Internal server error", body = StatusBody),
+    ),
+    security((), ("admin_token" = [])),
+    tag = "files"
+)]
+pub async fn create_share(
+    State(state): State<AppState>,
+    Path(id): Path<u32>,
+    Query(params): Query<ShareParams>,
+    headers: HeaderMap,
+) -> Response {
+    let job = match owned_job(&state, id, &headers, params.user_id).await {
+        Ok(job) => job,
+        Err(response) => return response,
+    };
+    let max_ttl = state
+        .config
+        .download_links
+        .as_ref()
+        .map_or(Duration::ZERO, |l| l.max_ttl);
+
+    let expires_in = params.expires_in.unwrap_or(86400);
+    if expires_in == 0 || expires_in > max_ttl.as_secs() {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            format!(
+                "expires_in must be between 1 and {} seconds",
+                max_ttl.as_secs()
+            ),
+        );
+    }
+    if params.max_downloads == Some(0) {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            "max_downloads must be at least 1".to_string(),
+        );
+    }
+
+    let mut share = Share::new(job.id, params.max_downloads);
+    if let Err(e) = share
+        .add_to_db(Duration::from_secs(expires_in), &state.pool)
+        .await
+    {
+        return internal_error(e);
+    }
+    (StatusCode::CREATED, Json(with_url(&state, share))).into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/jobs/{id}/shares",
+    params(
+        ("id" = u32, Path, description = "Job identifier"),
+        ShareParams
+    ),
+    responses(
+        (status = 200, description = "Shares of the job, revoked and expired ones included", body = Vec<Share>),
+        (status = 400, description = "Missing user_id", body = StatusBody),
+        (status = 401, description = "Invalid admin token", body = StatusBody),
+        (status = 403, description = "Job belongs to another user", body = StatusBody),
+        (status = 404, description = "Job not found or links not configured", body = StatusBody),
+        (status = 500, description = "Internal server error", body = StatusBody),
+    ),
+    security((), ("admin_token" = [])),
+    tag = "files"
+)]
+pub async fn list_shares(
+    State(state): State<AppState>,
+    Path(id): Path<u32>,
+    Query(params): Query<ShareParams>,
+    headers: HeaderMap,
+) -> Response {
+    let job = match owned_job(&state, id, &headers, params.user_id).await {
+        Ok(job) => job,
+        Err(response) => return response,
+    };
+    match Share::list_for_job(job.id, &state.pool).await {
+        Ok(shares) => {
+            let shares: Vec<Share> = shares.into_iter().map(|s| with_url(&state, s)).collect();
+            Json(shares).into_response()
+        }
+        Err(e) => internal_error(e),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/jobs/{id}/shares/{share_id}",
+    params(
+        ("id" = u32, Path, description = "Job identifier"),
+        ("share_id" = u32, Path, description = "Share identifier"),
+        ShareParams
+    ),
+    responses(
+        (status = 200, description = "Share revoked", body = Share),
+        (status = 400, description = "Missing user_id", body = StatusBody),
+        (status = 401, description = "Invalid admin token", body = StatusBody),
+        (status = 403, description = "Job belongs to another user", body = StatusBody),
+        (status = 404, description = "Job or share not found, or links not configured", body = StatusBody),
+        (status = 500, description = "Internal server error", body = StatusBody),
+    ),
+    security((), ("admin_token" = [])),
+    tag = "files"
+)]
+pub async fn revoke_share(
+    State(state): State<AppState>,
+    Path((id, share_id)): Path<(u32, u32)>,
+    Query(params): Query<ShareParams>,
+    headers: HeaderMap,
+) -> Response {
+    let job = match owned_job(&state, id, &headers, params.user_id).await {
+        Ok(job) => job,
+        Err(response) => return response,
+    };
+    match Share::revoke(job.id, share_id, &state.pool).await {
+        Ok(Some(share)) => Json(with_url(&state, share)).into_response(),
+        Ok(None) => error_response(
+            StatusCode::NOT_FOUND,
+            format!("Share {share_id} of job {id} not found"),
+        ),
+        Err(e) => internal_error(e),
+    }
+}
+
+/// Whether `token` opens the results of job `id`. Only the download of a
+/// completed job counts against the limit, looking at the status doesn't.
+pub async fn use_share(state: &AppState, id: u32, token: &str) -> Result<bool, Response> {
+    match Share::find_active(id, token, &state.pool).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return Ok(false),
+        Err(e) => return Err(internal_error(e)),
+    }
+    let mut job = Job::new(&state.config.data_path);
+    if job.retrieve_id(id, &state.pool).await.is_err() || job.status != Status::Completed {
+        return Ok(true);
+    }
+    match Share::claim(id, token, &state.pool).await {
+        Ok(claimed) => Ok(claimed.is_some()),
+        Err(e) => Err(internal_error(e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::loader::{Config, DownloadLinkConfig, Secret};
+    use crate::models::job_dto::create_jobs_table;
+    use crate::models::user_dao::User;
+    use crate::routes::router::create_routes;
+    use crate::services::client::Client;
+    use axum::Router;
+    use axum::body::Body;
+    use axum::http::Request;
+    use sqlx::SqlitePool;
+    use std::fs;
+    use tempfile::TempDir;
+    use tower::ServiceExt;
+
+    async fn setup(tempdir: &TempDir) -> (Router, Job, SqlitePool) {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        create_jobs_table(&pool).await.unwrap();
+        User::add_if_missing(1, &pool).await.unwrap();
+        let data_path = tempdir.path().to_str().unwrap();
+        let mut job = Job::new(data_path);
+        job.set_user_id(1);
+        job.add_to_db(&pool).await.unwrap();
+        fs::create_dir_all(&job.loc).unwrap();
+        fs::write(job.loc.join("output.zip"), b"fake zip content").unwrap();
+
+        let config = Config {
+            data_path: data_path.to_string(),
+            download_links: Some(DownloadLinkConfig {
+                secret: Secret::new("link-key"),
+                max_ttl: Duration::from_secs(86400),
+                base_url: None,
+            }),
+            ..Default::default()
+        };
+        (
+            create_routes(pool.clone(), config, Client::default()),
+            job,
+            pool,
+        )
+    }
+
+    fn request(method: &str, uri: &str) -> Request<Body> {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    async fn json<T: serde::de::DeserializeOwned>(response: Response) -> T {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_share_download_limit() {
+        let tempdir = TempDir::new().unwrap();
+        let (app, mut job, pool) = setup(&tempdir).await;
+        let uri = format!("/jobs/{}/shares", job.id);
+
+        let response = app
+            .clone()
+            .oneshot(request("POST", &format!("{uri}?user_id=2")))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = app
+            .clone()
+            .oneshot(request("POST", &format!("{uri}?user_id=1&max_downloads=0")))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = app
+            .clone()
+            .oneshot(request("POST", &format!("{uri}?user_id=1&max_downloads=1")))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let share: serde_json::Value = json(response).await;
+        let url = share["url"].as_str().unwrap().to_string();
+        assert!(url.starts_with(&format!("/shared/{}?share=", job.id)));
+
+        // Looking at a job that is still running doesn't use up the link
+        let response = app.clone().oneshot(request("GET", &url)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        job.update_status(Status::Completed, &pool).await.unwrap();
+
+        let response = app.clone().oneshot(request("GET", &url)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(bytes.as_ref(), b"fake zip content");
+
+        let response = app.clone().oneshot(request("GET", &url)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let response = app
+            .oneshot(request("GET", &format!("{uri}?user_id=1")))
+            .await
+            .unwrap();
+        let shares: Vec<serde_json::Value> = json(response).await;
+        assert_eq!(shares.len(), 1);
+        assert_eq!(shares[0]["downloads"], 1);
+        assert_eq!(shares[0]["url"], url.as_str());
+    }
+
+    #[tokio::test]
+    async fn test_revoke_share() {
+        let tempdir = TempDir::new().unwrap();
+        let (app, mut job, pool) = setup(&tempdir).await;
+        job.update_status(Status::Completed, &pool).await.unwrap();
+        let uri = format!("/jobs/{}/shares", job.id);
+
+        let response = app
+            .clone()
+            .oneshot(request("POST", &format!("{uri}?user_id=1")))
+            .await
+            .unwrap();
+        let share: serde_json::Value = json(response).await;
+        let url = share["url"].as_str().unwrap().to_string();
+        let response = app.clone().oneshot(request("GET", &url)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let revoke = format!("{uri}/{}?user_id=1", share["id"]);
+        let response = app
+            .clone()
+            .oneshot(request(
+                "DELETE",
+                &format!("{uri}/{}?user_id=2", share["id"]),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = app
+            .clone()
+            .oneshot(request("DELETE", &revoke))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let revoked: serde_json::Value = json(response).await;
+        assert_eq!(revoked["revoked"], true);
+
+        let response = app.clone().oneshot(request("GET", &url)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = app
+            .oneshot(request("DELETE", &format!("{uri}/9999?user_id=1")))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,818 @@
+use crate::config::loader::{ObjectStoreConfig, S3Credentials};
+use crate::controllers::server::register_job;
+use crate::models::job_dao::Job;
+use crate::models::status_body::StatusBody;
+use crate::models::user_dao::User;
+use crate::routes::router::AppState;
+use crate::services::object_store::ObjectStoreError;
+use crate::services::presign::presign;
+use crate::utils::download_link;
+use crate::utils::io::sanitize_filename;
+use crate::utils::session::random_token;
+use crate::utils::storage::write_error_status;
+use axum::body::{Bytes, to_bytes};
+use axum::response::{IntoResponse, Response};
+use axum::{
+    extract::{Json, Path, State},
+    http::{StatusCode, header},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::fs::{create_dir_all, remove_dir_all};
+use utoipa::{self, ToSchema};
+
+/// Objects uploaded directly to the store live under this prefix
+pub const UPLOAD_KEY_PREFIX: &str = "uploads";
+
+/// Most files a single direct upload can hold
+const MAX_FILES: usize = 100;
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UploadRequest {
+    pub user_id: i32,
+    /// Names of the input files, reduced to their last path component
+    pub files: Vec<String>,
+}
+
+/// Where to `PUT` each input file, the URLs stop working after `expires`
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct DirectUpload {
+    pub upload_id: String,
+    /// Seconds since the epoch
+    pub expires: u64,
+    pub files: Vec<UploadUrl>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct UploadUrl {
+    pub name: String,
+    pub url: String,
+}
+
+/// The files that were uploaded and the same text fields as `/upload`
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CompleteUpload {
+    pub files: Vec<String>,
+    /// `user_id`, `service`, `tenant`, `group`, `tags`, ... as strings
+    #[serde(flatten)]
+    pub fields: HashMap<String, String>,
+}
+
+/// Status and message of a refused request
+type Rejection = (StatusCode, String);
+
+/// How long the outcome of a completed upload can be polled
+const OUTCOME_RETENTION: Duration = Duration::from_secs(3600);
+
+#[derive(Debug, Clone)]
+pub enum Completion {
+    Fetching,
+    /// The answer `/upload` would have given
+    Done {
+        status: StatusCode,
+        body: Bytes,
+        at: Instant,
+    },
+}
+
+/// In-memory record of the direct uploads whose files are fetched in the
+/// background, lost on restart
+#[derive(Debug, Clone, Default)]
+pub struct Completions {
+    uploads: Arc<Mutex<HashMap<String, Completion>>>,
+}
+
+impl Completions {
+    /// Claim the upload `id`, false while it is already being fetched
+    fn start(&self, id: &str) -> bool {
+        let mut uploads = self.uploads.lock().unwrap();
+        uploads.retain(|_, c| match c {
+            Completion::Fetching => true,
+            Completion::Done { at, .. } => at.elapsed() < OUTCOME_RETENTION,
+        });
+        if matches!(uploads.get(id), Some(Completion::Fetching)) {
+            return false;
+        }
+        uploads.insert(id.to_string(), Completion::Fetching);
+        true
+    }
+
+    /// Release an upload refused before its files were fetched
+    fn forget(&self, id: &str) {
+        self.uploads.lock().unwrap().remove(id);
+    }
+
+    async fn finish(&self, id: &str, response: Response) {
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap_or_default();
+        self.uploads.lock().unwrap().insert(
+            id.to_string(),
+            Completion::Done {
+                status,
+                body,
+                at: Instant::now(),
+            },
+        );
+    }
+
+    pub fn get(&self, id: &str) -> Option<Completion> {
+        self.uploads.lock().unwrap().get(id).cloned()
+    }
+}
+
+fn error_response(status: StatusCode, message: String) -> Response {
+    let body = StatusBody {
+        message,
+        ..Default::default()
+    };
+    (status, Json(body)).into_response()
+}
+
+/// The object store and its keys, when it can sign URLs
+fn s3_store(state: &AppState) -> Result<(&ObjectStoreConfig, &S3Credentials), Rejection> {
+    match &state.config.object_store {
+        Some(store @ ObjectStoreConfig { s3: Some(s3), .. }) => Ok((store, s3)),
+        _ => Err((
+            StatusCode::NOT_FOUND,
+            "Direct uploads need an S3 object store".to_string(),
+        )),
+    }
+}
+
+/// Sanitized, unique file names, or the reason they can't be used
+fn file_names(files: &[String]) -> Result<Vec<String>, Rejection> {
+    if files.is_empty() || files.len() > MAX_FILES {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Between 1 and {MAX_FILES} files can be uploaded"),
+        ));
+    }
+    let names: Vec<String> = files.iter().map(|f| sanitize_filename(f)).collect();
+    if names.iter().collect::<HashSet<_>>().len() != names.len() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "File names must be unique".to_string(),
+        ));
+    }
+    Ok(names)
+}
+
+fn is_valid_upload_id(id: &str) -> bool {
+    id.len() == 64 && id.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+fn object_key(upload_id: &str, name: &str) -> String {
+    format!("{UPLOAD_KEY_PREFIX}/{upload_id}/{name}")
+}
+
+fn signed_url(
+    store: &ObjectStoreConfig,
+    s3: &S3Credentials,
+    method: &str,
+    key: &str,
+) -> Result<String, Rejection> {
+    presign(
+        store,
+        s3,
+        method,
+        key,
+        download_link::now(),
+        s3.url_ttl.as_secs(),
+    )
+    .ok_or_else(|| {
+        tracing::error!("Could not sign a URL for {key}, check OBJECT_STORE_URL");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Internal server error".to_string(),
+        )
+    })
+}
+
+#[utoipa::path(
+    post,
+    path = "/uploads",
+    request_body = UploadRequest,
+    responses(
+        (status = 201, description = "Pre-signed URLs to PUT the files to", body = DirectUpload),
+        (status = 400, description = "Invalid files or unknown user", body = StatusBody),
+        (status = 403, description = "User is disabled", body = StatusBody),
+        (status = 404, description = "No S3 object store configured", body = StatusBody),
+    ),
+    tag = "files"
+)]
+pub async fn begin_upload(
+    State(state): State<AppState>,
+    Json(request): Json<UploadRequest>,
+) -> Response {
+    let (store, s3) = match s3_store(&state) {
+        Ok(s) => s,
+        Err((status, message)) => return error_response(status, message),
+    };
+    let names = match file_names(&request.files) {
+        Ok(n) => n,
+        Err((status, message)) => return error_response(status, message),
+    };
+
+    // Unknown users are registered as on `/upload`, unless registration is required
+    let uid = request.user_id;
+    if !state.config.require_registered_users
+        && let Err(e) = User::add_if_missing(uid as u32, &state.pool).await
+    {
+        tracing::error!("Could not register user {uid}: {:?}", e);
+        return error_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Internal server error".to_string(),
+        );
+    }
+    match User::retrieve_id(uid as u32, &state.pool).await {
+        Ok(user) if !user.enabled => {
+            return error_response(StatusCode::FORBIDDEN, format!("User {uid} is disabled"));
+        }
+        Ok(_) => {}
+        Err(sqlx::Error::RowNotFound) => {
+            return error_response(StatusCode::BAD_REQUEST, format!("Unknown user {uid}"));
+        }
+        Err(e) => {
+            tracing::error!("Could not retrieve user {uid}: {:?}", e);
+            return error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Internal server error".to_string(),
+            );
+        }
+    }
+
+    let upload_id = random_token();
+    let mut files = Vec::with_capacity(names.len());
+    for name in names {
+        match signed_url(store, s3, "PUT", &object_key(&upload_id, &name)) {
+            Ok(url) => files.push(UploadUrl { name, url }),
+            Err((status, message)) => return error_response(status, message),
+        }
+    }
+
+    let upload = DirectUpload {
+        upload_id,
+        expires: download_link::now() + s3.url_ttl.as_secs(),
+        files,
+    };
+    (StatusCode::CREATED, Json(upload)).into_response()
+}
+
+#[utoipa::path(
+    post,
+    path = "/uploads/{upload_id}/complete",
+    params(
+        ("upload_id" = String, Path, description = "Identifier returned by `POST /uploads`")
+    ),
+    request_body = CompleteUpload,
+    responses(
+        (status = 202, description = "Files are being fetched, see `GET /uploads/{upload_id}`", body = StatusBody),
+        (status = 400, description = "A file is missing or the request is invalid", body = StatusBody),
+        (status = 404, description = "No S3 object store configured", body = StatusBody),
+        (status = 409, description = "The upload is already being completed", body = StatusBody),
+        (status = 413, description = "The files are larger than the upload limit", body = StatusBody),
+        (status = 502, description = "The object store could not be read", body = StatusBody),
+        (status = 507, description = "DATA_PATH is not writable", body = StatusBody),
+    ),
+    tag = "files"
+)]
+pub async fn complete_upload(
+    State(state): State<AppState>,
+    Path(upload_id): Path<String>,
+    Json(request): Json<CompleteUpload>,
+) -> Response {
+    let (store, s3) = match s3_store(&state) {
+        Ok(s) => s,
+        Err((status, message)) => return error_response(status, message),
+    };
+    if !is_valid_upload_id(&upload_id) {
+        return error_response(StatusCode::BAD_REQUEST, "Invalid upload id".to_string());
+    }
+    let names = match file_names(&request.files) {
+        Ok(n) => n,
+        Err((status, message)) => return error_response(status, message),
+    };
+    let urls = match signed_urls(store, s3, "HEAD", &upload_id, &names) {
+        Ok(urls) => urls,
+        Err((status, message)) => return error_response(status, message),
+    };
+
+    if !state.completions.start(&upload_id) {
+        return error_response(
+            StatusCode::CONFLICT,
+            format!("Upload {upload_id} is already being completed"),
+        );
+    }
+    // Only the sizes are checked before answering, the store does not send
+    // the files until they are fetched in the background
+    if let Err((status, message)) = check_objects(&state, &upload_id, &names, &urls).await {
+        state.completions.forget(&upload_id);
+        return error_response(status, message);
+    }
+
+    let body = StatusBody {
+        message: format!("Fetching the files of upload {upload_id}"),
+        ..Default::default()
+    };
+    tokio::spawn(async move {
+        let response = fetch_and_register(&state, &upload_id, &names, &request.fields).await;
+        state.completions.finish(&upload_id, response).await;
+    });
+    (StatusCode::ACCEPTED, Json(body)).into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/uploads/{upload_id}",
+    params(
+        ("upload_id" = String, Path, description = "Identifier returned by `POST /uploads`")
+    ),
+    responses(
+        (status = 201, description = "Job created, or any answer of `/upload`", body = StatusBody),
+        (status = 202, description = "Files are still being fetched", body = StatusBody),
+        (status = 400, description = "Invalid upload id", body = StatusBody),
+        (status = 404, description = "Upload not being completed", body = StatusBody),
+    ),
+    tag = "files"
+)]
+pub async fn upload_outcome(
+    State(state): State<AppState>,
+    Path(upload_id): Path<String>,
+) -> Response {
+    if !is_valid_upload_id(&upload_id) {
+        return error_response(StatusCode::BAD_REQUEST, "Invalid upload id".to_string());
+    }
+    match state.completions.get(&upload_id) {
+        None => error_response(
+            StatusCode::NOT_FOUND,
+            format!("Upload {upload_id} is not being completed"),
+        ),
+        Some(Completion::Fetching) => error_response(
+            StatusCode::ACCEPTED,
+            format!("Fetching the files of upload {upload_id}"),
+        ),
+        Some(Completion::Done { status, body, .. }) => {
+            (status, [(header::CONTENT_TYPE, "application/json")], body).into_response()
+        }
+    }
+}
+
+/// Pre-signed `method` URLs of the files of an upload
+fn signed_urls(
+    store: &ObjectStoreConfig,
+    s3: &S3Credentials,
+    method: &str,
+    upload_id: &str,
+    names: &[String],
+) -> Result<Vec<String>, Rejection> {
+    names
+        .iter()
+        .map(|name| signed_url(store, s3, method, &object_key(upload_id, name)))
+        .collect()
+}
+
+/// The answer to an object store request about the file `name` that failed
+fn store_rejection(
+    state: &AppState,
+    upload_id: &str,
+    name: &str,
+    e: ObjectStoreError,
+) -> Rejection {
+    match e {
+        ObjectStoreError::NotFound => (
+            StatusCode::BAD_REQUEST,
+            format!("File {name} was not uploaded"),
+        ),
+        ObjectStoreError::TooLarge(_) => too_large(state),
+        e => {
+            tracing::error!("Could not fetch {name} of upload {upload_id}: {e}");
+            (
+                StatusCode::BAD_GATEWAY,
+                "Could not read the object store".to_string(),
+            )
+        }
+    }
+}
+
+fn too_large(state: &AppState) -> Rejection {
+    (
+        StatusCode::PAYLOAD_TOO_LARGE,
+        format!(
+            "Files are larger than the limit of {} bytes",
+            state.config.body_limits.upload
+        ),
+    )
+}
+
+/// Every file was uploaded and, from the sizes the store reports, they fit the
+/// same limit as `/upload` together
+async fn check_objects(
+    state: &AppState,
+    upload_id: &str,
+    names: &[String],
+    urls: &[String],
+) -> Result<(), Rejection> {
+    let mut total = 0;
+    for (name, url) in names.iter().zip(urls) {
+        match state.client.head_presigned(url).await {
+            Ok(size) => total += size.unwrap_or(0),
+            Err(e) => return Err(store_rejection(state, upload_id, name, e)),
+        }
+    }
+    if total > state.config.body_limits.upload as u64 {
+        return Err(too_large(state));
+    }
+    Ok(())
+}
+
+/// Fetch the files into a new job directory and register the job as `/upload`
+/// does, answering what `/upload` would
+async fn fetch_and_register(
+    state: &AppState,
+    upload_id: &str,
+    names: &[String],
+    fields: &HashMap<String, String>,
+) -> Response {
+    let (store, s3) = match s3_store(state) {
+        Ok(s) => s,
+        Err((status, message)) => return error_response(status, message),
+    };
+    let urls = match signed_urls(store, s3, "GET", upload_id, names) {
+        Ok(urls) => urls,
+        Err((status, message)) => return error_response(status, message),
+    };
+
+    let job = Job::new(&state.config.data_path);
+    if let Err(e) = create_dir_all(&job.loc).await {
+        tracing::error!("Could not create {:?}: {e}", job.loc);
+        return error_response(
+            write_error_status(&e),
+            "Could not create job directory".to_string(),
+        );
+    }
+
+    // The sizes were checked, but the objects can be replaced since
+    let mut remaining = state.config.body_limits.upload as u64;
+    for (name, url) in names.iter().zip(&urls) {
+        let (status, message) = match state
+            .client
+            .get_presigned_to_file(url, &job.loc.join(name), remaining)
+            .await
+        {
+            Ok(size) => {
+                remaining -= size;
+                continue;
+            }
+            Err(e) => store_rejection(state, upload_id, name, e),
+        };
+        if let Err(e) = job.remove_from_disk() {
+            tracing::error!("Could not remove {:?}: {e}", job.loc);
+        }
+        return error_response(status, message);
+    }
+
+    tracing::info!(
+        "Direct upload {upload_id} fetched: {} files, {} text fields",
+        names.len(),
+        fields.len()
+    );
+
+    let loc = job.loc.clone();
+    let response = register_job(state, job, fields).await;
+    if !response.status().is_success()
+        && let Err(e) = remove_dir_all(&loc).await
+    {
+        tracing::error!("Could not remove {loc:?}: {e}");
+    }
+
+    // Kept on failure so the upload can be completed again with fixed fields
+    if response.status().is_success() {
+        for name in names {
+            let Ok(url) = signed_url(store, s3, "DELETE", &object_key(upload_id, name)) else {
+                continue;
+            };
+            if let Err(e) = state.client.delete_presigned(&url).await {
+                tracing::error!("Could not remove {name} of upload {upload_id}: {e}");
+            }
+        }
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::loader::{Config, Secret};
+    use crate::models::job_dto::create_jobs_table;
+    use crate::models::status_dto::Status;
+    use crate::routes::router::create_routes;
+    use crate::services::client::Client;
+    use axum::Router;
+    use axum::body::{Body, to_bytes};
+    use axum::http::Request;
+    use mockito::{Matcher, Server};
+    use sqlx::SqlitePool;
+    use std::time::Duration;
+    use tempfile::TempDir;
+    use tower::ServiceExt;
+
+    async fn setup(store_url: Option<String>) -> (TempDir, Config, SqlitePool) {
+        let tempdir = TempDir::new().unwrap();
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        create_jobs_table(&pool).await.unwrap();
+        User::add_if_missing(1, &pool).await.unwrap();
+
+        let mut config = Config {
+            data_path: tempdir.path().to_str().unwrap().to_string(),
+            ..Default::default()
+        };
+        config
+            .services
+            .insert("example".to_string(), Default::default());
+        config.object_store = store_url.map(|url| ObjectStoreConfig {
+            url,
+            auth: Default::default(),
+            tier_after: Duration::from_secs(3600),
+            s3: Some(S3Credentials {
+                access_key: "key".to_string(),
+                secret_key: Secret::new("secret"),
+                region: "us-east-1".to_string(),
+                url_ttl: Duration::from_secs(600),
+            }),
+        });
+        (tempdir, config, pool)
+    }
+
+    fn post_json(uri: &str, body: serde_json::Value) -> Request<Body> {
+        Request::builder()
+            .method("POST")
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    /// The answer to a completed upload, once its files are fetched
+    async fn outcome(app: &Router, upload_id: &str) -> Response {
+        for _ in 0..200 {
+            let request = Request::builder()
+                .uri(format!("/uploads/{upload_id}"))
+                .body(Body::empty())
+                .unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            if response.status() != StatusCode::ACCEPTED {
+                return response;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("Upload {upload_id} was never fetched");
+    }
+
+    #[tokio::test]
+    async fn test_direct_upload() {
+        let mut server = Server::new_async().await;
+        let (_tempdir, config, pool) = setup(Some(server.url())).await;
+        let app = create_routes(pool.clone(), config, Client::default());
+
+        let response = app
+            .clone()
+            .oneshot(post_json(
+                "/uploads",
+                serde_json::json!({"user_id": 1, "files": ["dir/input.pdb", "params.txt"]}),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let upload: DirectUpload = serde_json::from_slice(&body).unwrap();
+        assert!(is_valid_upload_id(&upload.upload_id));
+        assert_eq!(upload.files[0].name, "input.pdb");
+        let prefix = format!("{}/uploads/{}/input.pdb?", server.url(), upload.upload_id);
+        assert!(upload.files[0].url.starts_with(&prefix));
+        assert!(upload.files[0].url.contains("X-Amz-Signature="));
+
+        let key = |name: &str| {
+            Matcher::Regex(format!(
+                r"^/uploads/{}/{name}\?X-Amz-Algorithm=",
+                upload.upload_id
+            ))
+        };
+        let _sizes = server
+            .mock("HEAD", Matcher::Regex(r"^/uploads/".to_string()))
+            .with_header("content-length", "8")
+            .expect(2)
+            .create_async()
+            .await;
+        let _input = server
+            .mock("GET", key("input.pdb"))
+            .with_body("ATOM")
+            .create_async()
+            .await;
+        let _params = server
+            .mock("GET", key("params.txt"))
+            .with_body("steps=10")
+            .create_async()
+            .await;
+        let deleted = server
+            .mock("DELETE", Matcher::Regex(r"^/uploads/".to_string()))
+            .with_status(204)
+            .expect(2)
+            .create_async()
+            .await;
+
+        let uri = format!("/uploads/{}/complete", upload.upload_id);
+        let response = app
+            .clone()
+            .oneshot(post_json(
+                &uri,
+                serde_json::json!({
+                    "files": ["input.pdb", "params.txt"],
+                    "user_id": "1",
+                    "service": "example",
+                }),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+        let response = outcome(&app, &upload.upload_id).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let status: StatusBody = serde_json::from_slice(&body).unwrap();
+        assert_eq!(status.status, Status::Queued);
+        deleted.assert_async().await;
+
+        let mut job = Job::new("");
+        job.retrieve_id(status.id, &pool).await.unwrap();
+        assert_eq!(std::fs::read(job.loc.join("input.pdb")).unwrap(), b"ATOM");
+        assert_eq!(
+            std::fs::read(job.loc.join("params.txt")).unwrap(),
+            b"steps=10"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_direct_upload_missing_file() {
+        let mut server = Server::new_async().await;
+        let (_tempdir, config, pool) = setup(Some(server.url())).await;
+        let app = create_routes(pool, config, Client::default());
+        let _missing = server
+            .mock("HEAD", Matcher::Any)
+            .with_status(404)
+            .create_async()
+            .await;
+
+        let uri = format!("/uploads/{}/complete", "a".repeat(64));
+        let body =
+            serde_json::json!({"files": ["input.pdb"], "user_id": "1", "service": "example"});
+        let response = app.clone().oneshot(post_json(&uri, body)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let body =
+            serde_json::json!({"files": ["input.pdb"], "user_id": "1", "service": "example"});
+        let response = app
+            .oneshot(post_json("/uploads/abc/complete", body))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_direct_upload_refused_job_removed() {
+        let mut server = Server::new_async().await;
+        let (tempdir, config, pool) = setup(Some(server.url())).await;
+        let app = create_routes(pool, config, Client::default());
+        let _size = server
+            .mock("HEAD", Matcher::Any)
+            .with_header("content-length", "4")
+            .create_async()
+            .await;
+        let _input = server
+            .mock("GET", Matcher::Any)
+            .with_body("ATOM")
+            .create_async()
+            .await;
+
+        let upload_id = "a".repeat(64);
+        let uri = format!("/uploads/{upload_id}/complete");
+        let body =
+            serde_json::json!({"files": ["input.pdb"], "user_id": "1", "service": "unknown"});
+        let response = app.clone().oneshot(post_json(&uri, body)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let response = outcome(&app, &upload_id).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        // The fetched files don't outlive the refused job
+        assert_eq!(std::fs::read_dir(tempdir.path()).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_direct_upload_too_large() {
+        let mut server = Server::new_async().await;
+        let (_tempdir, mut config, pool) = setup(Some(server.url())).await;
+        config.body_limits.upload = 10;
+        let app = create_routes(pool, config, Client::default());
+        let _size = server
+            .mock("HEAD", Matcher::Any)
+            .with_header("content-length", "6")
+            .create_async()
+            .await;
+        let fetched = server
+            .mock("GET", Matcher::Any)
+            .expect(0)
+            .create_async()
+            .await;
+
+        // Refused from the sizes alone, nothing is downloaded
+        let upload_id = "a".repeat(64);
+        let uri = format!("/uploads/{upload_id}/complete");
+        let body = serde_json::json!({
+            "files": ["a.pdb", "b.pdb"], "user_id": "1", "service": "example"
+        });
+        let response = app.clone().oneshot(post_json(&uri, body)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        fetched.assert_async().await;
+
+        let response = outcome(&app, &upload_id).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_completions() {
+        let completions = Completions::default();
+        assert!(completions.start("a"));
+        assert!(!completions.start("a"));
+        assert!(matches!(completions.get("a"), Some(Completion::Fetching)));
+
+        completions.forget("a");
+        assert!(completions.get("a").is_none());
+        assert!(completions.start("a"));
+    }
+
+    #[tokio::test]
+    async fn test_direct_upload_not_configured() {
+        let (_tempdir, config, pool) = setup(None).await;
+        let app = create_routes(pool, config, Client::default());
+
+        let response = app
+            .clone()
+            .oneshot(post_json(
+                "/uploads",
+                serde_json::json!({"user_id": 1, "files": ["input.pdb"]}),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_direct_upload_registers_user() {
+        let server = Server::new_async().await;
+        let (_tempdir, config, pool) = setup(Some(server.url())).await;
+        let app = create_routes(pool.clone(), config, Client::default());
+
+        let response = app
+            .oneshot(post_json(
+                "/uploads",
+                serde_json::json!({"user_id": 2, "files": ["input.pdb"]}),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert!(User::retrieve_id(2, &pool).await.unwrap().enabled);
+    }
+
+    #[tokio::test]
+    async fn test_direct_upload_invalid_request() {
+        let server = Server::new_async().await;
+        let (_tempdir, mut config, pool) = setup(Some(server.url())).await;
+        config.require_registered_users = true;
+        let app = create_routes(pool, config, Client::default());
+
+        for (body, expected) in [
+            (
+                serde_json::json!({"user_id": 2, "files": ["input.pdb"]}),
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                serde_json::json!({"user_id": 1, "files": []}),
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                serde_json::json!({"user_id": 1, "files": ["a/input.pdb", "b/input.pdb"]}),
+                StatusCode::BAD_REQUEST,
+            ),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(post_json("/uploads", body))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), expected);
+        }
+    }
+}
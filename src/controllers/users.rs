@@ -0,0 +1,513 @@
+use crate::controllers::admin::reject_unauthorized;
+use crate::models::job_dao::Job;
+use crate::models::status_body::StatusBody;
+use crate::models::user_dao::User;
+use crate::routes::router::AppState;
+use axum::response::{IntoResponse, Response};
+use axum::{
+    extract::{Json, Path, State},
+    http::{HeaderMap, StatusCode},
+};
+use utoipa;
+
+/// Map a database error on the users table to a response
+fn error_response(e: sqlx::Error, id: u32) -> Response {
+    let mut body = StatusBody::new();
+    let status = match e {
+        sqlx::Error::RowNotFound => {
+            body.message = format!("User {id} not found in the database");
+            StatusCode::NOT_FOUND
+        }
+        sqlx::Error::Database(ref db) if db.is_unique_violation() => {
+            body.message = "A user with this email already exists".to_string();
+            StatusCode::CONFLICT
+        }
+        sqlx::Error::Database(ref db) if db.is_foreign_key_violation() => {
+            body.message = format!("User {id} still has jobs");
+            StatusCode::CONFLICT
+        }
+        _ => {
+            tracing::error!("Users table error: {:?}", e);
+            body.message = "Internal server error".to_string();
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    };
+    (status, Json(body)).into_response()
+}
+
+/// Validate the user fields and its tenant
+fn validate(state: &AppState, user: &User) -> Result<(), String> {
+    user.validate()?;
+    if !state.config.is_known_tenant(&user.tenant) {
+        return Err("Invalid tenant".to_string());
+    }
+    Ok(())
+}
+
+#[utoipa::path(
+    post,
+    path = "/users",
+    request_body = User,
+    responses(
+        (status = 201, description = "User created", body = User),
+        (status = 400, description = "Invalid user", body = StatusBody),
+        (status = 409, description = "Email already in use", body = StatusBody),
+        (status = 401, description = "Invalid or missing admin token", body = StatusBody),
+        (status = 403, description = "Admin endpoints disabled", body = StatusBody),
+        (status = 500, description = "Internal server error", body = StatusBody),
+    ),
+    security(("admin_token" = [])),
+    tag = "users"
+)]
+pub async fn create_user(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(mut user): Json<User>,
+) -> Response {
+    if let Some(response) = reject_unauthorized(&headers, &state.config, &state.sessions) {
+        return response;
+    }
+
+    if let Err(message) = validate(&state, &user) {
+        let body = StatusBody {
+            message,
+            ..Default::default()
+        };
+        return (StatusCode::BAD_REQUEST, Json(body)).into_response();
+    }
+
+    match user.add_to_db(&state.pool).await {
+        Ok(_) => (StatusCode::CREATED, Json(user)).into_response(),
+        Err(e) => error_response(e, user.id),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/users",
+    responses(
+        (status = 200, description = "All users", body = Vec<User>),
+        (status = 401, description = "Invalid or missing admin token", body = StatusBody),
+        (status = 403, description = "Admin endpoints disabled", body = StatusBody),
+        (status = 500, description = "Internal server error", body = StatusBody),
+    ),
+    security(("admin_token" = [])),
+    tag = "users"
+)]
+pub async fn list_users(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    if let Some(response) = reject_unauthorized(&headers, &state.config, &state.sessions) {
+        return response;
+    }
+
+    match User::list(&state.pool).await {
+        Ok(users) => Json(users).into_response(),
+        Err(e) => error_response(e, 0),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/users/{id}",
+    params(
+        ("id" = u32, Path, description = "User identifier")
+    ),
+    responses(
+        (status = 200, description = "User found", body = User),
+        (status = 404, description = "Not found", body = StatusBody),
+        (status = 401, description = "Invalid or missing admin token", body = StatusBody),
+        (status = 403, description = "Admin endpoints disabled", body = StatusBody),
+        (status = 500, description = "Internal server error", body = StatusBody),
+    ),
+    security(("admin_token" = [])),
+    tag = "users"
+)]
+pub async fn get_user(
+    State(state): State<AppState>,
+    Path(id): Path<u32>,
+    headers: HeaderMap,
+) -> Response {
+    if let Some(response) = reject_unauthorized(&headers, &state.config, &state.sessions) {
+        return response;
+    }
+
+    match User::retrieve_id(id, &state.pool).await {
+        Ok(user) => Json(user).into_response(),
+        Err(e) => error_response(e, id),
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/users/{id}",
+    params(
+        ("id" = u32, Path, description = "User identifier")
+    ),
+    request_body = User,
+    responses(
+        (status = 200, description = "User updated", body = User),
+        (status = 400, description = "Invalid user", body = StatusBody),
+        (status = 404, description = "Not found", body = StatusBody),
+        (status = 409, description = "Email already in use", body = StatusBody),
+        (status = 401, description = "Invalid or missing admin token", body = StatusBody),
+        (status = 403, description = "Admin endpoints disabled", body = StatusBody),
+        (status = 500, description = "Internal server error", body = StatusBody),
+    ),
+    security(("admin_token" = [])),
+    tag = "users"
+)]
+pub async fn update_user(
+    State(state): State<AppState>,
+    Path(id): Path<u32>,
+    headers: HeaderMap,
+    Json(mut user): Json<User>,
+) -> Response {
+    if let Some(response) = reject_unauthorized(&headers, &state.config, &state.sessions) {
+        return response;
+    }
+
+    if let Err(message) = validate(&state, &user) {
+        let body = StatusBody {
+            message,
+            ..Default::default()
+        };
+        return (StatusCode::BAD_REQUEST, Json(body)).into_response();
+    }
+
+    // The id in the path is authoritative
+    user.id = id;
+    if let Err(e) = user.update(&state.pool).await {
+        return error_response(e, id);
+    }
+
+    // A suspended user keeps its queued jobs, but they are not dispatched
+    if !user.enabled {
+        match Job::hold_queued(id, &state.pool).await {
+            Ok(0) => {}
+            Ok(n) => tracing::info!("Held {n} queued jobs of suspended user {id}"),
+            Err(e) => tracing::error!("Could not hold the jobs of user {id}: {:?}", e),
+        }
+    }
+
+    Json(user).into_response()
+}
+
+#[utoipa::path(
+    delete,
+    path = "/users/{id}",
+    params(
+        ("id" = u32, Path, description = "User identifier")
+    ),
+    responses(
+        (status = 204, description = "User deleted"),
+        (status = 404, description = "Not found", body = StatusBody),
+        (status = 409, description = "User still has jobs, disable it instead", body = StatusBody),
+        (status = 401, description = "Invalid or missing admin token", body = StatusBody),
+        (status = 403, description = "Admin endpoints disabled", body = StatusBody),
+        (status = 500, description = "Internal server error", body = StatusBody),
+    ),
+    security(("admin_token" = [])),
+    tag = "users"
+)]
+pub async fn delete_user(
+    State(state): State<AppState>,
+    Path(id): Path<u32>,
+    headers: HeaderMap,
+) -> Response {
+    if let Some(response) = reject_unauthorized(&headers, &state.config, &state.sessions) {
+        return response;
+    }
+
+    match User::delete(id, &state.pool).await {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => error_response(e, id),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::loader::{Config, Secret};
+    use crate::models::job_dao::Job;
+    use crate::models::job_dto::create_jobs_table;
+    use crate::models::status_dto::Status;
+    use crate::models::user_dao::User;
+    use crate::routes::router::create_routes;
+    use crate::services::client::Client;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use sqlx::SqlitePool;
+    use tower::ServiceExt;
+
+    const TOKEN: &str = "secret-admin-token";
+
+    fn make_config() -> Config {
+        Config {
+            admin_token: Some(Secret::new(TOKEN)),
+            ..Default::default()
+        }
+    }
+
+    fn test_user(name: &str) -> User {
+        User {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    async fn setup_test_db() -> SqlitePool {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        create_jobs_table(&pool).await.unwrap();
+        pool
+    }
+
+    fn json_request(method: &str, uri: &str, body: &str) -> Request<Body> {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json")
+            .header("authorization", format!("Bearer {TOKEN}"))
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    async fn body_json<T: serde::de::DeserializeOwned>(response: axum::response::Response) -> T {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_users_require_admin() {
+        let pool = setup_test_db().await;
+        let mut alice = test_user("alice");
+        alice.add_to_db(&pool).await.unwrap();
+        let app = create_routes(pool.clone(), make_config(), Client::default());
+
+        let user = format!("/users/{}", alice.id);
+        let body = r#"{"name": "alice", "tier": "premium", "enabled": true}"#;
+        for (method, uri) in [
+            ("POST", "/users"),
+            ("GET", "/users"),
+            ("GET", user.as_str()),
+            ("PUT", user.as_str()),
+            ("DELETE", user.as_str()),
+        ] {
+            let request = Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("content-type", "application/json")
+                .body(Body::from(body))
+                .unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(
+                response.status(),
+                StatusCode::UNAUTHORIZED,
+                "{method} {uri}"
+            );
+        }
+
+        // Nothing was changed
+        assert_eq!(User::retrieve_id(alice.id, &pool).await.unwrap(), alice);
+        assert_eq!(User::list(&pool).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_create_user() {
+        let pool = setup_test_db().await;
+        let app = create_routes(pool.clone(), make_config(), Client::default());
+
+        let response = app
+            .oneshot(json_request(
+                "POST",
+                "/users",
+                r#"{"name": "alice", "email": "alice@example.com", "runs_per_user": 2}"#,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let user: User = body_json(response).await;
+        assert!(user.id > 0);
+        assert_eq!(user.runs_per_user, Some(2));
+        assert_eq!(User::retrieve_id(user.id, &pool).await.unwrap(), user);
+    }
+
+    #[tokio::test]
+    async fn test_create_user_invalid() {
+        let pool = setup_test_db().await;
+        let app = create_routes(pool, make_config(), Client::default());
+
+        let response = app
+            .clone()
+            .oneshot(json_request("POST", "/users", r#"{"name": ""}"#))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = app
+            .clone()
+            .oneshot(json_request(
+                "POST",
+                "/users",
+                r#"{"name": "alice", "tenant": "unknown"}"#,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = app
+            .oneshot(json_request(
+                "POST",
+                "/users",
+                r#"{"name": "alice", "notifications": {"quiet_hours": "late"}}"#,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_create_user_duplicate_email() {
+        let pool = setup_test_db().await;
+        let app = create_routes(pool, make_config(), Client::default());
+
+        let body = r#"{"name": "alice", "email": "alice@example.com"}"#;
+        let response = app
+            .clone()
+            .oneshot(json_request("POST", "/users", body))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let response = app
+            .oneshot(json_request("POST", "/users", body))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn test_list_and_get_users() {
+        let pool = setup_test_db().await;
+        let mut alice = test_user("alice");
+        alice.add_to_db(&pool).await.unwrap();
+        test_user("bob").add_to_db(&pool).await.unwrap();
+        let app = create_routes(pool, make_config(), Client::default());
+
+        let response = app
+            .clone()
+            .oneshot(json_request("GET", "/users", ""))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let users: Vec<User> = body_json(response).await;
+        assert_eq!(users.len(), 2);
+
+        let response = app
+            .clone()
+            .oneshot(json_request("GET", &format!("/users/{}", alice.id), ""))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let user: User = body_json(response).await;
+        assert_eq!(user, alice);
+
+        let response = app
+            .oneshot(json_request("GET", "/users/999", ""))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_update_user() {
+        let pool = setup_test_db().await;
+        let mut alice = test_user("alice");
+        alice.add_to_db(&pool).await.unwrap();
+        let app = create_routes(pool.clone(), make_config(), Client::default());
+
+        let response = app
+            .clone()
+            .oneshot(json_request(
+                "PUT",
+                &format!("/users/{}", alice.id),
+                r#"{"name": "alice", "tier": "premium", "enabled": false}"#,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let updated = User::retrieve_id(alice.id, &pool).await.unwrap();
+        assert_eq!(updated.tier, "premium");
+        assert!(!updated.enabled);
+
+        let response = app
+            .oneshot(json_request("PUT", "/users/999", r#"{"name": "ghost"}"#))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_update_user_holds_queued_jobs() {
+        let pool = setup_test_db().await;
+        let mut alice = test_user("alice");
+        alice.add_to_db(&pool).await.unwrap();
+
+        let mut job = Job::new("");
+        job.set_user_id(alice.id as i32);
+        job.add_to_db(&pool).await.unwrap();
+        job.update_status(Status::Queued, &pool).await.unwrap();
+
+        let app = create_routes(pool.clone(), make_config(), Client::default());
+        let response = app
+            .oneshot(json_request(
+                "PUT",
+                &format!("/users/{}", alice.id),
+                r#"{"name": "alice", "enabled": false}"#,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        job.retrieve_id(job.id, &pool).await.unwrap();
+        assert_eq!(job.status, Status::Held);
+    }
+
+    #[tokio::test]
+    async fn test_delete_user() {
+        let pool = setup_test_db().await;
+        let mut alice = test_user("alice");
+        alice.add_to_db(&pool).await.unwrap();
+        let mut bob = test_user("bob");
+        bob.add_to_db(&pool).await.unwrap();
+
+        // Bob has a job, so the foreign key keeps him around
+        let mut job = Job::new("");
+        job.set_user_id(bob.id as i32);
+        job.add_to_db(&pool).await.unwrap();
+
+        let app = create_routes(pool.clone(), make_config(), Client::default());
+
+        let response = app
+            .clone()
+            .oneshot(json_request("DELETE", &format!("/users/{}", alice.id), ""))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert!(User::retrieve_id(alice.id, &pool).await.is_err());
+
+        let response = app
+            .clone()
+            .oneshot(json_request("DELETE", &format!("/users/{}", bob.id), ""))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let response = app
+            .oneshot(json_request("DELETE", "/users/999", ""))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,193 @@
+use sqlx::{Connection, SqliteConnection, SqlitePool};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const SNAPSHOT_PREFIX: &str = "orchestrator-";
+const SNAPSHOT_SUFFIX: &str = ".sqlite";
+
+/// Snapshots sent to the object store live under this prefix
+pub const BACKUP_KEY_PREFIX: &str = "backups";
+
+#[derive(Debug, thiserror::Error)]
+pub enum BackupError {
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Backup failed the integrity check: {0}")]
+    Corrupt(String),
+}
+
+/// Key of a snapshot in the object store
+pub fn backup_key(snapshot: &Path) -> String {
+    let name = snapshot.file_name().unwrap_or_default().to_string_lossy();
+    format!("{BACKUP_KEY_PREFIX}/{name}")
+}
+
+/// Write a consistent copy of the database to `dir` while it stays in use
+pub async fn snapshot(pool: &SqlitePool, dir: &Path) -> Result<PathBuf, BackupError> {
+    fs::create_dir_all(dir)?;
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    let path = dir.join(format!("{SNAPSHOT_PREFIX}{millis}{SNAPSHOT_SUFFIX}"));
+
+    sqlx::query("VACUUM INTO ?")
+        .bind(path.to_string_lossy().to_string())
+        .execute(pool)
+        .await?;
+    Ok(path)
+}
+
+/// Snapshots found in `dir`, oldest first
+pub fn list(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut snapshots: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| {
+                p.file_name()
+                    .and_then(|n| n.to_str())
+                    .is_some_and(|n| n.starts_with(SNAPSHOT_PREFIX) && n.ends_with(SNAPSHOT_SUFFIX))
+            })
+            .collect(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e),
+    };
+    // Same width timestamps, the names sort chronologically
+    snapshots.sort();
+    Ok(snapshots)
+}
+
+/// Age of the newest snapshot in `dir`, `None` when there is none
+pub fn latest_age(dir: &Path) -> io::Result<Option<Duration>> {
+    let Some(latest) = list(dir)?.pop() else {
+        return Ok(None);
+    };
+    let modified = fs::metadata(latest)?.modified()?;
+    Ok(Some(
+        SystemTime::now()
+            .duration_since(modified)
+            .unwrap_or_default(),
+    ))
+}
+
+/// Remove all but the `keep` newest snapshots, returns the removed ones
+pub fn prune(dir: &Path, keep: usize) -> io::Result<Vec<PathBuf>> {
+    let snapshots = list(dir)?;
+    let excess = snapshots.len().saturating_sub(keep);
+    let removed: Vec<PathBuf> = snapshots.into_iter().take(excess).collect();
+    for path in &removed {
+        fs::remove_file(path)?;
+    }
+    Ok(removed)
+}
+
+/// Replace the database at `db_path` with `backup`, the server must be stopped
+pub async fn restore(backup: &Path, db_path: &str) -> Result<(), BackupError> {
+    let mut conn =
+        SqliteConnection::connect(&format!("sqlite://{}?mode=ro", backup.display())).await?;
+    let result: String = sqlx::query_scalar("PRAGMA integrity_check")
+        .fetch_one(&mut conn)
+        .await?;
+    conn.close().await?;
+    if result != "ok" {
+        return Err(BackupError::Corrupt(result));
+    }
+
+    // Copy next to the database first, so a failed copy leaves it untouched
+    let staged = format!("{db_path}.restore");
+    fs::copy(backup, &staged)?;
+    for suffix in ["-wal", "-shm"] {
+        match fs::remove_file(format!("{db_path}{suffix}")) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+    }
+    fs::rename(&staged, db_path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datasource::db::init_db;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_snapshot_and_restore() {
+        let tempdir = TempDir::new().unwrap();
+        let db_path = tempdir.path().join("db.sqlite");
+        let db_path = db_path.to_str().unwrap();
+        let backups = tempdir.path().join("backups");
+
+        let pool = init_db(db_path).await;
+        sqlx::query("INSERT INTO users (id, name) VALUES (1, 'before')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let path = snapshot(&pool, &backups).await.unwrap();
+        assert_eq!(list(&backups).unwrap(), vec![path.clone()]);
+
+        sqlx::query("INSERT INTO users (id, name) VALUES (2, 'after')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        pool.close().await;
+
+        restore(&path, db_path).await.unwrap();
+        let pool = init_db(db_path).await;
+        let names: Vec<String> = sqlx::query_scalar("SELECT name FROM users")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(names, vec!["before"]);
+    }
+
+    #[tokio::test]
+    async fn test_restore_rejects_corrupt_backup() {
+        let tempdir = TempDir::new().unwrap();
+        let backup = tempdir.path().join("orchestrator-1.sqlite");
+        fs::write(&backup, b"not a database").unwrap();
+        let db_path = tempdir.path().join("db.sqlite");
+        fs::write(&db_path, b"current").unwrap();
+
+        let result = restore(&backup, db_path.to_str().unwrap()).await;
+        assert!(result.is_err());
+        assert_eq!(fs::read(&db_path).unwrap(), b"current");
+    }
+
+    #[test]
+    fn test_prune() {
+        let tempdir = TempDir::new().unwrap();
+        assert_eq!(latest_age(tempdir.path()).unwrap(), None);
+        for millis in [1000, 2000, 3000] {
+            fs::write(
+                tempdir.path().join(format!("orchestrator-{millis}.sqlite")),
+                b"",
+            )
+            .unwrap();
+        }
+        fs::write(tempdir.path().join("unrelated.txt"), b"").unwrap();
+
+        let removed = prune(tempdir.path(), 2).unwrap();
+        assert_eq!(
+            removed,
+            vec![tempdir.path().join("orchestrator-1000.sqlite")]
+        );
+        assert_eq!(list(tempdir.path()).unwrap().len(), 2);
+        assert!(tempdir.path().join("unrelated.txt").exists());
+        assert!(latest_age(tempdir.path()).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_backup_key() {
+        assert_eq!(
+            backup_key(Path::new("/backups/orchestrator-1000.sqlite")),
+            "backups/orchestrator-1000.sqlite"
+        );
+    }
+}
@@ -1,6 +1,6 @@
 use crate::models::job_dto::create_jobs_table;
 use crate::models::payload_dto::create_payload_table;
-use sqlx::{Pool, Sqlite, SqlitePool};
+use sqlx::{Pool, Row, Sqlite, SqliteConnection, SqlitePool};
 use tracing::info;
 
 pub async fn init_db(db_path: &str) -> Pool<Sqlite> {
@@ -31,6 +31,39 @@ pub async fn init_payload_db(db_path: &str) -> Pool<Sqlite> {
     pool
 }
 
+/// Add a column to an existing table if it is not there yet.
+///
+/// The schema is created with `CREATE TABLE IF NOT EXISTS`, which does nothing for
+/// databases created by older versions. Columns introduced later are added here so
+/// those databases are upgraded in place.
+///
+/// Takes a single connection: other pooled connections opened while the schema
+/// changes can keep describing `SELECT *` with the old columns.
+pub async fn add_column_if_missing(
+    conn: &mut SqliteConnection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<(), sqlx::Error> {
+    let rows = sqlx::query(&format!("PRAGMA table_info({table})"))
+        .fetch_all(&mut *conn)
+        .await?;
+
+    let exists = rows
+        .iter()
+        .any(|row| row.get::<String, _>("name") == column);
+
+    if !exists {
+        sqlx::query(&format!(
+            "ALTER TABLE {table} ADD COLUMN {column} {definition}"
+        ))
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -150,4 +183,27 @@ mod tests {
 
         pool.close().await;
     }
+
+    #[tokio::test]
+    async fn test_add_column_if_missing() {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        sqlx::query("CREATE TABLE things (id INTEGER PRIMARY KEY)")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let mut conn = pool.acquire().await.unwrap();
+        add_column_if_missing(&mut conn, "things", "label", "TEXT")
+            .await
+            .unwrap();
+        // Second call is a no-op
+        add_column_if_missing(&mut conn, "things", "label", "TEXT")
+            .await
+            .unwrap();
+
+        let result = sqlx::query("SELECT label FROM things")
+            .fetch_all(&pool)
+            .await;
+        assert!(result.is_ok());
+    }
 }
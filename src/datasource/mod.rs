@@ -1,2 +1,3 @@
+pub mod backup;
 pub mod db;
 pub mod fs;
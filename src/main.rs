@@ -6,12 +6,18 @@ mod routes;
 mod services;
 mod utils;
 use crate::datasource::fs::init_fs;
+use crate::models::task_run_dao::{CLEANER_TASK, GETTER_TASK, PROBER_TASK, SENDER_TASK};
 use crate::routes::router::create_routes;
+use crate::utils::storage::StorageHealth;
+use crate::utils::warmup::Warmup;
 use crate::{datasource::db::init_db, routes::router::create_client_routes};
+use axum::Extension;
 use clap::{Parser, Subcommand};
 use config::loader::Config;
-use services::{client, server};
+use services::{alert, client, datasets, loadgen, server};
 use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use tokio::net::TcpListener;
 use tokio_schedule::{Job, every};
 
@@ -28,6 +34,52 @@ enum Commands {
 
     #[command(about = "Run orchestrator client")]
     Client {},
+
+    #[command(about = "Restore the server database from a backup")]
+    Restore {
+        /// Snapshot file, or its object store key with `--remote`
+        backup: String,
+        /// Fetch the snapshot from the object store
+        #[arg(long)]
+        remote: bool,
+    },
+
+    #[command(about = "Repair the status of the jobs that diverged from their client")]
+    Reconcile {},
+
+    #[command(about = "Print the state of the scheduler as JSON, to attach to bug reports")]
+    QueueSnapshot {
+        /// Write the snapshot to this file instead of the standard output,
+        /// which the logs also go to
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+
+    #[command(about = "Send synthetic jobs to a server and report latencies and errors")]
+    Loadgen {
+        /// Address of the server, e.g. http://localhost:5000
+        target: String,
+        /// Service the jobs are submitted to
+        #[arg(long)]
+        service: String,
+        /// Registered user submitting the jobs
+        #[arg(long, default_value_t = 1)]
+        user_id: u32,
+        #[arg(long, default_value_t = 100)]
+        jobs: usize,
+        /// Jobs in flight at the same time
+        #[arg(long, default_value_t = 10)]
+        concurrency: usize,
+        /// Bytes of the input file sent with each job
+        #[arg(long, default_value_t = 1024)]
+        input_size: usize,
+        /// Seconds between two status checks of a job
+        #[arg(long, default_value_t = 1)]
+        poll_interval: u64,
+        /// Seconds after which a job not completed counts as an error
+        #[arg(long, default_value_t = 600)]
+        timeout: u64,
+    },
 }
 
 #[tokio::main]
@@ -52,39 +104,165 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         Commands::Client {} => {
             start_client(config).await?;
         }
+        Commands::Restore { backup, remote } => {
+            restore(config, backup, *remote).await?;
+        }
+        Commands::Reconcile {} => {
+            reconcile(config).await?;
+        }
+        Commands::QueueSnapshot { output } => {
+            queue_snapshot(config, output.as_deref()).await?;
+        }
+        Commands::Loadgen {
+            target,
+            service,
+            user_id,
+            jobs,
+            concurrency,
+            input_size,
+            poll_interval,
+            timeout,
+        } => {
+            let options = loadgen::LoadgenOptions {
+                target: target.clone(),
+                service: service.clone(),
+                user_id: *user_id,
+                jobs: *jobs,
+                concurrency: *concurrency,
+                input_size: *input_size,
+                poll_interval: Duration::from_secs(*poll_interval),
+                timeout: Duration::from_secs(*timeout),
+            };
+            let report = loadgen::run(&client::Client::new(&config.http)?, &options).await;
+            println!("{report}");
+        }
     }
 
     Ok(())
 }
 
-async fn start_server(config: Config) -> anyhow::Result<()> {
+async fn start_server(mut config: Config) -> anyhow::Result<()> {
     // Initialize the database
     let pool = init_db(&config.db_path).await;
 
-    // Initialize the filesystem
-    let _ = init_fs(&config.data_path).await;
+    // Initialize the filesystem, every storage root
+    for data_path in config.data_paths() {
+        init_fs(data_path).await;
+    }
+
+    // One HTTP client shared by all the requests to the services
+    let http_client = client::Client::for_services(&config.http, &config.services)?
+        .with_chaos(config.chaos.clone());
+
+    // Learn what the clients can run before sending them anything
+    server::register(&mut config, &http_client).await;
 
     // Create a scheduled job
     let sender_task = every(500).millisecond().perform(|| {
         let pool_clone = pool.clone();
         let config_clone = config.clone();
-        async move { server::sender(pool_clone, config_clone).await }
+        let client_clone = http_client.clone();
+        async move {
+            let round = server::sender(pool_clone.clone(), config_clone, client_clone);
+            server::tracked(SENDER_TASK, &pool_clone, round).await
+        }
     });
 
     let getter_task = every(500).millisecond().perform(|| {
         let pool_clone = pool.clone();
         let config_clone = config.clone();
-        async move { server::getter(pool_clone, config_clone).await }
+        let client_clone = http_client.clone();
+        async move {
+            let round = server::getter(pool_clone.clone(), config_clone, client_clone);
+            server::tracked(GETTER_TASK, &pool_clone, round).await
+        }
     });
 
     let cleaner_task = every(60).second().perform(|| {
         let pool_clone = pool.clone();
         let config_clone = config.clone();
-        async move { server::cleaner(pool_clone, config_clone).await }
+        let client_clone = http_client.clone();
+        async move {
+            let round = server::cleaner(pool_clone.clone(), config_clone, client_clone);
+            server::tracked(CLEANER_TASK, &pool_clone, round).await
+        }
+    });
+
+    let tierer_task = every(300).second().perform(|| {
+        let pool_clone = pool.clone();
+        let config_clone = config.clone();
+        let client_clone = http_client.clone();
+        async move { server::tierer(pool_clone, config_clone, client_clone).await }
+    });
+
+    let packer_task = every(300).second().perform(|| {
+        let pool_clone = pool.clone();
+        let config_clone = config.clone();
+        async move { server::packer(pool_clone, config_clone).await }
+    });
+
+    let reconciler_task = every(600).second().perform(|| {
+        let pool_clone = pool.clone();
+        let config_clone = config.clone();
+        let client_clone = http_client.clone();
+        async move {
+            server::reconciler(pool_clone, config_clone, client_clone).await;
+        }
+    });
+
+    let backup_task = every(60).second().perform(|| {
+        let pool_clone = pool.clone();
+        let config_clone = config.clone();
+        let client_clone = http_client.clone();
+        async move { server::backup(pool_clone, config_clone, client_clone).await }
+    });
+
+    let notifier_task = every(5).second().perform(|| {
+        let pool_clone = pool.clone();
+        let config_clone = config.clone();
+        let client_clone = http_client.clone();
+        async move { server::notifier(pool_clone, config_clone, client_clone).await }
+    });
+
+    let watchdog_task = every(60).second().perform(|| {
+        let pool_clone = pool.clone();
+        let config_clone = config.clone();
+        let client_clone = http_client.clone();
+        async move { server::watchdog(pool_clone, config_clone, client_clone).await }
+    });
+
+    let probe_interval = u32::try_from(config.probes.interval.as_secs()).unwrap_or(u32::MAX);
+    let prober_task = every(probe_interval).seconds().perform(|| {
+        let pool_clone = pool.clone();
+        let config_clone = config.clone();
+        let client_clone = http_client.clone();
+        async move {
+            let round = server::prober(pool_clone.clone(), config_clone, client_clone);
+            server::tracked(PROBER_TASK, &pool_clone, round).await
+        }
+    });
+
+    let alert_state = alert::AlertState::default();
+    let monitor_task = every(60).second().perform(|| {
+        let pool_clone = pool.clone();
+        let config_clone = config.clone();
+        let client_clone = http_client.clone();
+        let state_clone = alert_state.clone();
+        async move { server::monitor(pool_clone, config_clone, client_clone, state_clone).await }
+    });
+
+    // Submissions are refused while DATA_PATH is not writable
+    let storage = StorageHealth::default();
+    let storage_task = every(30).second().perform(|| {
+        let config_clone = config.clone();
+        let client_clone = http_client.clone();
+        let storage_clone = storage.clone();
+        async move { server::storage_check(config_clone, client_clone, storage_clone).await }
     });
 
     // Create app
-    let app = create_routes(pool.clone(), config.clone());
+    let app = create_routes(pool.clone(), config.clone(), http_client.clone())
+        .layer(Extension(storage.clone()));
 
     // Initialize socket
     let addr = SocketAddr::from(([0, 0, 0, 0], config.port));
@@ -96,16 +274,80 @@ async fn start_server(config: Config) -> anyhow::Result<()> {
         _ = sender_task => {},
         _ = getter_task => {},
         _ = cleaner_task => {},
-        _ = axum::serve(listener, app.into_make_service()) => {},
+        _ = tierer_task => {},
+        _ = packer_task => {},
+        _ = reconciler_task => {},
+        _ = backup_task => {},
+        _ = notifier_task => {},
+        _ = watchdog_task => {},
+        _ = monitor_task => {},
+        _ = storage_task => {},
+        _ = prober_task => {},
+        _ = axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()) => {},
     }
 
     Ok(())
 }
 
+async fn restore(config: Config, backup: &str, remote: bool) -> anyhow::Result<()> {
+    let path = if remote {
+        let Some(store) = &config.object_store else {
+            anyhow::bail!("--remote needs OBJECT_STORE_URL");
+        };
+        let path = std::env::temp_dir().join(format!("restore-{}.sqlite", uuid::Uuid::new_v4()));
+        client::Client::new(&config.http)?
+            .get_object_to_file(store, backup, &path)
+            .await?;
+        path
+    } else {
+        PathBuf::from(backup)
+    };
+
+    datasource::backup::restore(&path, &config.db_path).await?;
+    tracing::info!("restored {} from {}", config.db_path, backup);
+    if remote {
+        let _ = std::fs::remove_file(&path);
+    }
+    Ok(())
+}
+
+async fn reconcile(config: Config) -> anyhow::Result<()> {
+    let pool = init_db(&config.db_path).await;
+    let http_client = client::Client::for_services(&config.http, &config.services)?;
+
+    let report = server::reconcile(&pool, &config, &http_client).await?;
+    tracing::info!(
+        "checked {} jobs: {} corrected, {} could not be checked",
+        report.checked,
+        report.corrected,
+        report.unreachable
+    );
+    Ok(())
+}
+
+async fn queue_snapshot(config: Config, output: Option<&Path>) -> anyhow::Result<()> {
+    let pool = init_db(&config.db_path).await;
+    let http_client = client::Client::new(&config.http)?;
+
+    let snapshot = server::queue_snapshot(&pool, &config, &http_client).await?;
+    let json = serde_json::to_string_pretty(&snapshot)?;
+    match output {
+        Some(path) => {
+            std::fs::write(path, json)?;
+            tracing::info!("queue snapshot written to {}", path.display());
+        }
+        None => println!("{json}"),
+    }
+    Ok(())
+}
+
 async fn start_client(config: Config) -> anyhow::Result<()> {
     // Initialize database
     let pool = datasource::db::init_payload_db(&config.db_path).await;
 
+    // Remove what submissions interrupted by the last shutdown left behind
+    client::sweep_unprepared(pool.clone(), config.clone()).await;
+
     // Create the runner task
     let runner_task = every(500).millisecond().perform(|| {
         let pool_clone = pool.clone();
@@ -127,8 +369,23 @@ async fn start_client(config: Config) -> anyhow::Result<()> {
         async move { client::cleaner(pool_clone, config_clone).await }
     });
 
+    // Only used to put results in the object store shared with the server
+    // and to fetch the reference datasets
+    let http_client = client::Client::new(&config.http)?;
+
+    // Runs until every dataset is in the cache, the runner waits for it
+    tokio::spawn(datasets::fetcher(config.clone(), http_client.clone()));
+
+    // `/health` fails until the warm-up command, if any, succeeded
+    let warmup = Warmup::default();
+    if let Some(warmup_config) = config.warmup.clone() {
+        let warmup = warmup.clone();
+        tokio::spawn(async move { warmup.run(&warmup_config).await });
+    }
+
     // Create app
-    let client_app = create_client_routes(pool.clone(), config.clone());
+    let client_app =
+        create_client_routes(pool.clone(), config.clone(), http_client).layer(Extension(warmup));
 
     // Initialize socket
     let addr = SocketAddr::from(([0, 0, 0, 0], config.port));
@@ -140,7 +397,7 @@ async fn start_client(config: Config) -> anyhow::Result<()> {
         _ = runner_task => {},
         _ = updater_task => {},
         _ = cleaner_task => {},
-        _ = axum::serve(listener, client_app.into_make_service()) => {},
+        _ = axum::serve(listener, client_app.into_make_service_with_connect_info::<SocketAddr>()) => {},
     };
 
     Ok(())
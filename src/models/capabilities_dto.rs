@@ -0,0 +1,126 @@
+use crate::config::loader::{Config, Service};
+use crate::models::payload_dao::{MIN_SCHEMA_VERSION, SCHEMA_VERSION};
+use crate::utils::sandbox::{SandboxProfile, find_executable, pinned_path};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// How a client starts `run.sh`
+pub const EXECUTOR: &str = "bash";
+
+/// Interpreters looked for on the client
+const INTERPRETERS: [&str; 6] = ["sh", "bash", "python3", "perl", "Rscript", "julia"];
+
+/// What a client reports under `GET /capabilities`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Capabilities {
+    /// Oldest payload schema version the client accepts
+    pub min_schema_version: u32,
+    /// Payload schema version the client speaks
+    pub schema_version: u32,
+    /// How `run.sh` is started, see [`EXECUTOR`]
+    pub executor: String,
+    /// Sandbox profiles the client can apply
+    pub sandbox_profiles: Vec<SandboxProfile>,
+    /// Largest upload `/submit` takes, in bytes
+    pub max_upload_size: u64,
+    /// Interpreters `run.sh` can call, found on the `PATH` the payloads get
+    pub interpreters: Vec<String>,
+}
+
+impl Capabilities {
+    /// The capabilities of this client
+    pub fn detect(config: &Config) -> Capabilities {
+        // Payloads only see the directories of the pinned binaries
+        let path = if config.pinned_binaries.is_empty() {
+            std::env::var_os("PATH").unwrap_or_default()
+        } else {
+            pinned_path(&config.pinned_binaries)
+        };
+        Capabilities {
+            min_schema_version: MIN_SCHEMA_VERSION,
+            schema_version: SCHEMA_VERSION,
+            executor: EXECUTOR.to_string(),
+            sandbox_profiles: SandboxProfile::available(),
+            max_upload_size: config.body_limits.upload as u64,
+            interpreters: INTERPRETERS
+                .into_iter()
+                .filter(|i| find_executable(i, &path).is_some())
+                .map(str::to_string)
+                .collect(),
+        }
+    }
+
+    /// Why a job of `service` with `size` bytes of input can not be sent to
+    /// this client, `None` when it can
+    pub fn incompatibility(&self, service: &Service, size: u64) -> Option<String> {
+        if SCHEMA_VERSION < self.min_schema_version || self.schema_version < MIN_SCHEMA_VERSION {
+            return Some(format!(
+                "its client speaks payload schemas {} to {}, ours is {SCHEMA_VERSION}",
+                self.min_schema_version, self.schema_version
+            ));
+        }
+        if !self.sandbox_profiles.contains(&service.sandbox) {
+            return Some(format!(
+                "its client can not apply the {} sandbox",
+                service.sandbox
+            ));
+        }
+        if size > self.max_upload_size {
+            return Some(format!(
+                "it needs {size} bytes and its client takes at most {}",
+                self.max_upload_size
+            ));
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capabilities() -> Capabilities {
+        Capabilities {
+            min_schema_version: MIN_SCHEMA_VERSION,
+            schema_version: SCHEMA_VERSION,
+            executor: EXECUTOR.to_string(),
+            sandbox_profiles: vec![SandboxProfile::Legacy],
+            max_upload_size: 100,
+            interpreters: vec!["bash".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_detect() {
+        let config = Config::default();
+        let caps = Capabilities::detect(&config);
+        assert_eq!(caps.schema_version, SCHEMA_VERSION);
+        assert_eq!(caps.max_upload_size, config.body_limits.upload as u64);
+        // Profiles without a wrapper never need anything installed
+        assert!(caps.sandbox_profiles.contains(&SandboxProfile::Legacy));
+        assert!(
+            caps.sandbox_profiles
+                .contains(&SandboxProfile::TrustedInternal)
+        );
+    }
+
+    #[test]
+    fn test_incompatibility() {
+        let service = Service::default();
+        assert_eq!(capabilities().incompatibility(&service, 100), None);
+        assert!(capabilities().incompatibility(&service, 101).is_some());
+
+        let untrusted = Service {
+            sandbox: SandboxProfile::Untrusted,
+            ..Default::default()
+        };
+        assert!(capabilities().incompatibility(&untrusted, 0).is_some());
+
+        let newer = Capabilities {
+            min_schema_version: SCHEMA_VERSION + 1,
+            schema_version: SCHEMA_VERSION + 1,
+            ..capabilities()
+        };
+        assert!(newer.incompatibility(&service, 0).is_some());
+    }
+}
@@ -0,0 +1,52 @@
+use utoipa::ToSchema;
+
+/// Served by `/download/{id}`
+pub const VIA_DOWNLOAD: &str = "download";
+/// Served by `/shared/{id}` with a signed link
+pub const VIA_LINK: &str = "link";
+/// Served by `/shared/{id}` with a share token
+pub const VIA_SHARE: &str = "share";
+/// Served as part of `/groups/{id}/results`
+pub const VIA_GROUP: &str = "group";
+
+/// A result archive handed out, as stored in the `downloads` table
+#[derive(serde::Serialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct Download {
+    pub id: u32,
+    pub job_id: u32,
+    /// Address of the caller, unknown when the server is not told the peer
+    pub client_ip: Option<String>,
+    /// Endpoint the archive was served by: `download`, `link`, `share` or `group`
+    pub via: String,
+    pub bytes: i64,
+    /// UTC time of the download, set by the database
+    pub downloaded_at: Option<String>,
+}
+
+impl Download {
+    pub fn new(job_id: u32, client_ip: Option<String>, via: &str, bytes: i64) -> Download {
+        Download {
+            id: 0,
+            job_id,
+            client_ip,
+            via: via.to_string(),
+            bytes,
+            downloaded_at: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_new() {
+        let download = Download::new(1, Some("10.0.0.1".to_string()), VIA_SHARE, 2048);
+        assert_eq!(download.id, 0);
+        assert_eq!(download.job_id, 1);
+        assert_eq!(download.via, "share");
+        assert_eq!(download.bytes, 2048);
+        assert_eq!(download.downloaded_at, None);
+    }
+}
@@ -0,0 +1,124 @@
+use crate::models::download_dao::Download;
+use sqlx::sqlite::SqliteRow;
+use sqlx::{Row, SqliteConnection, SqlitePool};
+
+pub async fn create_downloads_table(conn: &mut SqliteConnection) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS downloads (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            job_id INTEGER NOT NULL REFERENCES jobs(id),
+            client_ip TEXT,
+            via TEXT NOT NULL,
+            bytes INTEGER NOT NULL,
+            downloaded_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )
+    "#,
+    )
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS downloads_job ON downloads (job_id, id)")
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+impl Download {
+    fn from_row(row: &SqliteRow) -> Download {
+        Download {
+            id: row.get("id"),
+            job_id: row.get("job_id"),
+            client_ip: row.get("client_ip"),
+            via: row.get("via"),
+            bytes: row.get("bytes"),
+            downloaded_at: row.get("downloaded_at"),
+        }
+    }
+
+    pub async fn add_to_db(&mut self, pool: &SqlitePool) -> Result<(), sqlx::Error> {
+        let row = sqlx::query(
+            "INSERT INTO downloads (job_id, client_ip, via, bytes) VALUES (?, ?, ?, ?) RETURNING id, downloaded_at",
+        )
+        .bind(self.job_id)
+        .bind(&self.client_ip)
+        .bind(&self.via)
+        .bind(self.bytes)
+        .fetch_one(pool)
+        .await?;
+
+        self.id = row.get("id");
+        self.downloaded_at = row.get("downloaded_at");
+
+        Ok(())
+    }
+
+    /// Downloads of a job, oldest first
+    pub async fn list_for_job(
+        job_id: u32,
+        pool: &SqlitePool,
+    ) -> Result<Vec<Download>, sqlx::Error> {
+        let rows = sqlx::query("SELECT * FROM downloads WHERE job_id = ? ORDER BY id")
+            .bind(job_id)
+            .fetch_all(pool)
+            .await?;
+
+        Ok(rows.iter().map(Download::from_row).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::download_dao::{VIA_DOWNLOAD, VIA_GROUP};
+    use crate::models::job_dao::Job;
+    use crate::models::job_dto::create_jobs_table;
+    use crate::models::user_dao::User;
+
+    async fn setup_test_db() -> SqlitePool {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        create_jobs_table(&pool).await.unwrap();
+        User::add_if_missing(0, &pool).await.unwrap();
+        pool
+    }
+
+    #[tokio::test]
+    async fn test_add_and_list() {
+        let pool = setup_test_db().await;
+        let mut job = Job::new("");
+        job.add_to_db(&pool).await.unwrap();
+        let mut other = Job::new("");
+        other.add_to_db(&pool).await.unwrap();
+
+        let mut first = Download::new(job.id, Some("10.0.0.1".to_string()), VIA_DOWNLOAD, 100);
+        first.add_to_db(&pool).await.unwrap();
+        assert!(first.id > 0);
+        assert!(first.downloaded_at.is_some());
+        Download::new(job.id, None, VIA_GROUP, 100)
+            .add_to_db(&pool)
+            .await
+            .unwrap();
+        Download::new(other.id, None, VIA_DOWNLOAD, 5)
+            .add_to_db(&pool)
+            .await
+            .unwrap();
+
+        let downloads = Download::list_for_job(job.id, &pool).await.unwrap();
+        assert_eq!(downloads.len(), 2);
+        assert_eq!(downloads[0], first);
+        assert_eq!(downloads[1].via, "group");
+        assert_eq!(downloads[1].client_ip, None);
+        assert!(
+            Download::list_for_job(9999, &pool)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_add_unknown_job() {
+        let pool = setup_test_db().await;
+        let mut download = Download::new(42, None, VIA_DOWNLOAD, 1);
+        assert!(download.add_to_db(&pool).await.is_err());
+    }
+}
@@ -0,0 +1,190 @@
+use axum::Json;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io;
+use utoipa::ToSchema;
+
+/// Why a request to a client failed, so the server can tell failures apart
+/// that share an HTTP status
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The client has no such payload
+    NotFound,
+    /// A field of the submission is malformed
+    InvalidRequest,
+    /// A submitted file failed the checks it goes through in quarantine
+    FileRejected,
+    /// The client ran out of disk space
+    DiskFull,
+    /// The results could not be zipped
+    ArchiveFailed,
+    /// The payload wrote more than it may, its results are not archived
+    OutputTooLarge,
+    /// The results could not be put in the object store
+    ObjectStoreFailed,
+    /// The database of the client failed
+    DatabaseFailed,
+    /// Anything else, also what is assumed of clients answering free text
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::NotFound => "not_found",
+            ErrorCode::InvalidRequest => "invalid_request",
+            ErrorCode::FileRejected => "file_rejected",
+            ErrorCode::DiskFull => "disk_full",
+            ErrorCode::ArchiveFailed => "archive_failed",
+            ErrorCode::OutputTooLarge => "output_too_large",
+            ErrorCode::ObjectStoreFailed => "object_store_failed",
+            ErrorCode::DatabaseFailed => "database_failed",
+            ErrorCode::Internal => "internal",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ErrorCode::NotFound => StatusCode::NOT_FOUND,
+            ErrorCode::InvalidRequest => StatusCode::BAD_REQUEST,
+            ErrorCode::FileRejected | ErrorCode::OutputTooLarge => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::DiskFull => StatusCode::INSUFFICIENT_STORAGE,
+            ErrorCode::ObjectStoreFailed => StatusCode::BAD_GATEWAY,
+            ErrorCode::ArchiveFailed | ErrorCode::DatabaseFailed | ErrorCode::Internal => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+
+    /// Whether the same request may succeed later, e.g. once space was freed
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            ErrorCode::DiskFull
+                | ErrorCode::ObjectStoreFailed
+                | ErrorCode::DatabaseFailed
+                | ErrorCode::Internal
+        )
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Body of the error responses of a client
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ErrorBody {
+    pub code: ErrorCode,
+    pub detail: String,
+}
+
+impl ErrorBody {
+    pub fn new(code: ErrorCode, detail: impl Into<String>) -> ErrorBody {
+        ErrorBody {
+            code,
+            detail: detail.into(),
+        }
+    }
+
+    /// `code` for a failed I/O operation, unless the disk was full or the
+    /// output too large to archive
+    pub fn from_io(code: ErrorCode, e: &io::Error) -> ErrorBody {
+        let code = match e.kind() {
+            io::ErrorKind::StorageFull => ErrorCode::DiskFull,
+            io::ErrorKind::FileTooLarge => ErrorCode::OutputTooLarge,
+            _ => code,
+        };
+        ErrorBody::new(code, e.to_string())
+    }
+
+    /// Read the body of an error response. Older clients answer something
+    /// else, their failure is coded after the HTTP status.
+    pub fn parse(status: StatusCode, body: &[u8]) -> ErrorBody {
+        serde_json::from_slice(body).unwrap_or_else(|_| {
+            let code = match status {
+                StatusCode::NOT_FOUND => ErrorCode::NotFound,
+                StatusCode::INSUFFICIENT_STORAGE => ErrorCode::DiskFull,
+                s if s.is_client_error() => ErrorCode::InvalidRequest,
+                _ => ErrorCode::Internal,
+            };
+            ErrorBody::new(code, String::from_utf8_lossy(body).trim())
+        })
+    }
+}
+
+impl fmt::Display for ErrorBody {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.code, self.detail)
+    }
+}
+
+impl IntoResponse for ErrorBody {
+    fn into_response(self) -> Response {
+        (self.code.status(), Json(self)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serialization() {
+        let body = ErrorBody::new(ErrorCode::DiskFull, "no space left");
+        let json = serde_json::to_string(&body).unwrap();
+        assert_eq!(json, r#"{"code":"disk_full","detail":"no space left"}"#);
+        assert_eq!(
+            ErrorBody::parse(StatusCode::INSUFFICIENT_STORAGE, json.as_bytes()),
+            body
+        );
+    }
+
+    #[test]
+    fn test_parse_free_text() {
+        let body = ErrorBody::parse(StatusCode::INTERNAL_SERVER_ERROR, b"Internal Server Error");
+        assert_eq!(body.code, ErrorCode::Internal);
+        assert_eq!(body.detail, "Internal Server Error");
+
+        // Older clients answered a missing payload with an empty one
+        let body = ErrorBody::parse(StatusCode::NOT_FOUND, br#"{"id":0}"#);
+        assert_eq!(body.code, ErrorCode::NotFound);
+        let body = ErrorBody::parse(StatusCode::BAD_REQUEST, b"");
+        assert_eq!(body.code, ErrorCode::InvalidRequest);
+    }
+
+    #[test]
+    fn test_from_io() {
+        let full = io::Error::from(io::ErrorKind::StorageFull);
+        assert_eq!(
+            ErrorBody::from_io(ErrorCode::ArchiveFailed, &full).code,
+            ErrorCode::DiskFull
+        );
+        let large = io::Error::from(io::ErrorKind::FileTooLarge);
+        assert_eq!(
+            ErrorBody::from_io(ErrorCode::ArchiveFailed, &large).code,
+            ErrorCode::OutputTooLarge
+        );
+        let denied = io::Error::from(io::ErrorKind::PermissionDenied);
+        assert_eq!(
+            ErrorBody::from_io(ErrorCode::ArchiveFailed, &denied).code,
+            ErrorCode::ArchiveFailed
+        );
+    }
+
+    #[test]
+    fn test_status() {
+        assert_eq!(ErrorCode::NotFound.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            ErrorCode::DiskFull.status(),
+            StatusCode::INSUFFICIENT_STORAGE
+        );
+        assert!(ErrorCode::DiskFull.is_transient());
+        assert!(!ErrorCode::ArchiveFailed.is_transient());
+    }
+}
@@ -5,6 +5,10 @@ use utoipa::ToSchema;
 pub struct Health {
     pub status: String,
     pub database: String,
+    /// Version of the binary answering, recorded by the server's prober.
+    /// Unset in the answers of older clients.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
 }
 
 #[cfg(test)]
@@ -16,6 +20,7 @@ mod tests {
         let health = Health {
             status: "healthy".to_string(),
             database: "connected".to_string(),
+            version: None,
         };
 
         assert_eq!(health.status, "healthy");
@@ -27,6 +32,7 @@ mod tests {
         let health = Health {
             status: "healthy".to_string(),
             database: "connected".to_string(),
+            version: None,
         };
 
         let json = serde_json::to_string(&health).unwrap();
@@ -41,6 +47,8 @@ mod tests {
 
         assert_eq!(health.status, "healthy");
         assert_eq!(health.database, "connected");
+        // Older clients report no version
+        assert_eq!(health.version, None);
     }
 
     #[test]
@@ -48,6 +56,7 @@ mod tests {
         let original = Health {
             status: "degraded".to_string(),
             database: "disconnected".to_string(),
+            version: Some("2.2.3".to_string()),
         };
 
         let json = serde_json::to_string(&original).unwrap();
@@ -55,5 +64,6 @@ mod tests {
 
         assert_eq!(deserialized.status, original.status);
         assert_eq!(deserialized.database, original.database);
+        assert_eq!(deserialized.version, original.version);
     }
 }
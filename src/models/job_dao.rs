@@ -1,10 +1,35 @@
+use crate::config::loader::{DEFAULT_TENANT, tenant_data_path};
+use crate::models::manifest_dto::{ManifestEntry, dir_manifest};
+use crate::models::payload_dao::EXIT_FILE;
 use crate::models::status_dto::Status;
+use crate::utils::io::{dir_size, extract_archive, move_dir, pack_directory, unpack_directory};
+use crate::utils::result_summary::{MAX_RESULT_SIZE, RESULT_FILE, ResultSummary};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
 use std::fs;
 use std::io::Read;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
 use utoipa::ToSchema;
 use uuid::Uuid;
 
+/// The files of an idle job directory are packed into this archive to save
+/// inodes and space, see [`Job::pack`]
+pub const PACKED_FILE: &str = ".orchestrator.packed.zip";
+
+/// A submission may ask for a priority from `-MAX_PRIORITY` to `MAX_PRIORITY`,
+/// `0` by default
+pub const MAX_PRIORITY: i32 = 10;
+
+/// Run `f` on `dir` and restore the modification time it had before, which
+/// the cleaner counts the retention from
+fn keeping_mtime<T>(dir: &Path, f: impl FnOnce() -> std::io::Result<T>) -> std::io::Result<T> {
+    let modified = fs::metadata(dir)?.modified()?;
+    let result = f()?;
+    fs::File::open(dir)?.set_modified(modified)?;
+    Ok(result)
+}
+
 #[derive(serde::Serialize, Debug, ToSchema)]
 pub struct Job {
     pub id: u32,
@@ -14,6 +39,260 @@ pub struct Job {
     #[schema(value_type = String)]
     pub loc: PathBuf,
     pub dest_id: u32,
+    pub checksum: Option<String>,
+    pub tenant: String,
+    /// Size of `output.zip` in bytes
+    pub archive_size: Option<i64>,
+    /// Number of files in `output.zip`
+    pub file_count: Option<u32>,
+    /// Exit code of `run.sh` as reported by the client
+    pub exit_code: Option<i32>,
+    /// SHA-256 over the service and the submitted files, used to spot resubmissions
+    pub input_hash: Option<String>,
+    /// Optional caller-chosen label tying related jobs together
+    pub group_id: Option<String>,
+    /// Key of `output.zip` in the object store once it was moved off the local disk
+    pub remote_key: Option<String>,
+    /// Why the job is held for review, or why the review rejected it
+    pub review_reason: Option<String>,
+    /// Downloads of the results that failed in a row
+    pub download_attempts: u32,
+    /// Uploads to the client that failed and were retried
+    pub retry_count: u32,
+    /// Seconds the submission asked the run to be limited to
+    pub timeout: Option<u32>,
+    /// Jobs of a higher priority are sent first, see `Queue::load`
+    pub priority: i32,
+    /// Termination was asked for, the getter asks the client again instead
+    /// of polling it until the client confirms
+    pub cancel_requested: bool,
+    /// Cancelled by its owner rather than terminated: the client discards the
+    /// payload and the job ends `Cancelled` instead of `Killed`
+    pub discard_requested: bool,
+    /// The callback was told the results are about to be cleaned
+    pub expiry_notified: bool,
+    /// What `run.sh` wrote in `result.json`, to read the headline numbers
+    /// without downloading the archive
+    pub result_summary: Option<ResultSummary>,
+    /// Why the job was last marked `failed`, `invalid` or `unknown`
+    pub last_error: Option<JobError>,
+    #[serde(flatten)]
+    pub metadata: JobMetadata,
+}
+
+/// [`JobError::source`] of the failures the client reported
+pub const RUNNER_SOURCE: &str = "runner";
+
+/// Longest part of an answer of a client kept in [`JobError::body`]
+pub const ERROR_BODY_EXCERPT: usize = 512;
+
+/// What went wrong with a job, shown on `GET /jobs/{id}` since the users
+/// can't read the logs of the server
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct JobError {
+    /// What gave up on the job: `sender`, `getter` or `runner`
+    pub source: String,
+    /// Kind of failure, e.g. `timed_out`, `rejected` or `request_failed`
+    pub variant: String,
+    pub message: String,
+    /// Status the client answered with, unset when it did not answer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http_status: Option<u16>,
+    /// Start of the body of that answer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+}
+
+impl JobError {
+    pub fn new(source: &str, variant: &str, message: impl Into<String>) -> JobError {
+        JobError {
+            source: source.to_string(),
+            variant: variant.to_string(),
+            message: message.into(),
+            http_status: None,
+            body: None,
+        }
+    }
+
+    /// Keep the status of the answer of the client, and at most
+    /// [`ERROR_BODY_EXCERPT`] bytes of its body
+    pub fn with_response(mut self, status: u16, body: Option<&str>) -> JobError {
+        self.http_status = Some(status);
+        self.body = body.map(|b| {
+            let mut end = b.len().min(ERROR_BODY_EXCERPT);
+            while !b.is_char_boundary(end) {
+                end -= 1;
+            }
+            b[..end].to_string()
+        });
+        self
+    }
+}
+
+/// Fields the owner of a job may still change after submission
+#[derive(serde::Serialize, Debug, Default, Clone, PartialEq, ToSchema)]
+pub struct JobMetadata {
+    pub tags: Vec<String>,
+    pub description: Option<String>,
+    /// Called once the job reaches a final status
+    pub callback_url: Option<String>,
+    pub notification_email: Option<String>,
+}
+
+/// Partial update of [`JobMetadata`], absent fields are left unchanged and
+/// empty strings clear the field
+#[derive(serde::Deserialize, Debug, Default, ToSchema)]
+pub struct JobMetadataPatch {
+    pub tags: Option<Vec<String>>,
+    pub description: Option<String>,
+    pub callback_url: Option<String>,
+    pub notification_email: Option<String>,
+}
+
+const MAX_TAGS: usize = 32;
+const MAX_TAG_LEN: usize = 64;
+const MAX_DESCRIPTION_LEN: usize = 1024;
+
+impl JobMetadata {
+    /// The metadata with `patch` applied, values are trimmed
+    pub fn patched(&self, patch: JobMetadataPatch) -> JobMetadata {
+        let clearable = |new: Option<String>, old: &Option<String>| match new {
+            Some(v) => Some(v.trim().to_string()).filter(|v| !v.is_empty()),
+            None => old.clone(),
+        };
+        JobMetadata {
+            tags: match patch.tags {
+                Some(tags) => tags.iter().map(|t| t.trim().to_string()).collect(),
+                None => self.tags.clone(),
+            },
+            description: clearable(patch.description, &self.description),
+            callback_url: clearable(patch.callback_url, &self.callback_url),
+            notification_email: clearable(patch.notification_email, &self.notification_email),
+        }
+    }
+
+    /// Check the fields a client is allowed to set
+    pub fn validate(&self) -> Result<(), String> {
+        if self.tags.len() > MAX_TAGS {
+            return Err(format!("At most {MAX_TAGS} tags are allowed"));
+        }
+        if self
+            .tags
+            .iter()
+            .any(|t| t.is_empty() || t.chars().count() > MAX_TAG_LEN)
+        {
+            return Err(format!("Tags must have 1 to {MAX_TAG_LEN} characters"));
+        }
+        if let Some(description) = &self.description
+            && description.chars().count() > MAX_DESCRIPTION_LEN
+        {
+            return Err(format!(
+                "Description must not exceed {MAX_DESCRIPTION_LEN} characters"
+            ));
+        }
+        if let Some(url) = &self.callback_url
+            && !reqwest::Url::parse(url).is_ok_and(|u| matches!(u.scheme(), "http" | "https"))
+        {
+            return Err("Invalid callback_url, use an http or https URL".to_string());
+        }
+        if let Some(email) = &self.notification_email
+            && !email.contains('@')
+        {
+            return Err("Invalid notification_email".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// What was found in a downloaded `output.zip`
+#[derive(Debug, PartialEq)]
+pub struct OutputSummary {
+    pub archive_size: i64,
+    pub file_count: u32,
+    pub exit_code: Option<i32>,
+}
+
+/// When a job should be done, what `GET /jobs/{id}/eta` answers. Runs are
+/// assumed to take the median runtime of the latest runs of the service,
+/// the estimates are unset while the client has none.
+#[derive(Debug, PartialEq, serde::Serialize, ToSchema)]
+pub struct JobEta {
+    pub id: u32,
+    pub status: Status,
+    /// Place of a queued job among the queued jobs of its service, 1 being
+    /// the next one sent
+    pub queue_position: Option<u32>,
+    /// Jobs of the service taking a slot on its client
+    pub active: u32,
+    /// Slots of the service on its client
+    pub max_runs: u16,
+    /// Median seconds taken by the latest completed runs of the service
+    pub median_run_secs: Option<u64>,
+    /// Seconds until the job should start running, unset for a held job
+    pub wait_secs: Option<u64>,
+    /// Seconds the run should still take
+    pub run_secs: Option<u64>,
+    /// Seconds until the job should be done, `wait_secs` plus `run_secs`
+    pub eta_secs: Option<u64>,
+}
+
+impl JobEta {
+    /// Estimate for `job`, `ahead` queued jobs of its service going before it
+    /// and `active` taking the `max_runs` slots. Each slot is assumed to free
+    /// up after `median_run_secs`.
+    pub fn new(
+        job: &Job,
+        ahead: u32,
+        active: u32,
+        max_runs: u16,
+        median_run_secs: Option<u64>,
+    ) -> JobEta {
+        let slots = u64::from(max_runs.max(1));
+        let (queue_position, wait_secs) = match job.status {
+            Status::Queued => {
+                // Jobs that must finish before a slot is left for this one
+                let waiting_on = (u64::from(active) + u64::from(ahead) + 1).saturating_sub(slots);
+                (
+                    Some(ahead + 1),
+                    median_run_secs.map(|r| waiting_on.div_ceil(slots) * r),
+                )
+            }
+            Status::Held | Status::Locked => (None, None),
+            _ => (None, Some(0)),
+        };
+        let mut eta = JobEta {
+            id: job.id,
+            status: job.status,
+            queue_position,
+            active,
+            max_runs,
+            median_run_secs,
+            wait_secs,
+            run_secs: median_run_secs,
+            eta_secs: None,
+        };
+        eta.update_total();
+        eta
+    }
+
+    /// Account for a run started `elapsed` ago
+    pub fn running_for(mut self, elapsed: Duration) -> JobEta {
+        self.run_secs = self.run_secs.map(|r| r.saturating_sub(elapsed.as_secs()));
+        self.update_total();
+        self
+    }
+
+    /// Account for the service not sending jobs before `delay`, e.g. until
+    /// its next execution window opens
+    pub fn delayed_by(mut self, delay: Duration) -> JobEta {
+        self.wait_secs = self.wait_secs.map(|w| w.max(delay.as_secs()));
+        self.update_total();
+        self
+    }
+
+    fn update_total(&mut self) {
+        self.eta_secs = self.wait_secs.zip(self.run_secs).map(|(w, r)| w + r);
+    }
 }
 
 impl Job {
@@ -26,6 +305,25 @@ impl Job {
             status: Status::Unknown,
             loc,
             dest_id: 0,
+            checksum: None,
+            tenant: DEFAULT_TENANT.to_string(),
+            archive_size: None,
+            file_count: None,
+            exit_code: None,
+            input_hash: None,
+            group_id: None,
+            remote_key: None,
+            review_reason: None,
+            download_attempts: 0,
+            retry_count: 0,
+            timeout: None,
+            priority: 0,
+            cancel_requested: false,
+            discard_requested: false,
+            expiry_notified: false,
+            result_summary: None,
+            last_error: None,
+            metadata: JobMetadata::default(),
         }
     }
 
@@ -36,10 +334,183 @@ impl Job {
         Ok(buffer)
     }
 
+    /// SHA-256 of the downloaded `output.zip`, hex encoded
+    pub fn compute_checksum(&self) -> Result<String, std::io::Error> {
+        let mut file = fs::File::open(self.loc.join("output.zip"))?;
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut file, &mut hasher)?;
+        Ok(format!("{:x}", hasher.finalize()))
+    }
+
+    /// Hash the service, the manifest of the submitted files and the
+    /// parameters of the submission, so identical submissions get identical
+    /// hashes whatever order their files and fields came in
+    pub fn compute_input_hash(
+        &self,
+        inputs: &[ManifestEntry],
+        parameters: &BTreeMap<String, String>,
+    ) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.service.as_bytes());
+        hasher.update([0]);
+
+        let mut inputs: Vec<_> = inputs.iter().collect();
+        inputs.sort_by(|a, b| a.path.cmp(&b.path));
+        for entry in inputs {
+            hasher.update(entry.path.as_bytes());
+            hasher.update([0]);
+            hasher.update(entry.size.to_le_bytes());
+            hasher.update(entry.sha256.to_ascii_lowercase().as_bytes());
+            hasher.update([0]);
+        }
+
+        // Keeps a parameter from passing for a file
+        hasher.update([1]);
+        for (name, value) in parameters {
+            hasher.update(name.as_bytes());
+            hasher.update([0]);
+            hasher.update(value.as_bytes());
+            hasher.update([0]);
+        }
+
+        format!("{:x}", hasher.finalize())
+    }
+
+    /// Size and checksum of every submitted file, by path relative to the job
+    /// directory
+    pub fn input_manifest(&self) -> Result<Vec<ManifestEntry>, std::io::Error> {
+        dir_manifest(&self.loc)
+    }
+
+    /// Inspect the downloaded `output.zip`. The exit code comes from the
+    /// exit file the client's `run.sh` trap leaves in the archive.
+    pub fn summarize_output(&self) -> Result<OutputSummary, std::io::Error> {
+        let path = self.loc.join("output.zip");
+        let archive_size = fs::metadata(&path)?.len() as i64;
+        let mut archive = zip::ZipArchive::new(fs::File::open(&path)?)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+
+        let file_count = archive.file_names().filter(|n| !n.ends_with('/')).count() as u32;
+
+        let exit_code = match archive.by_name(EXIT_FILE) {
+            Ok(mut entry) => {
+                let mut content = String::new();
+                entry.read_to_string(&mut content)?;
+                content.trim().parse::<i32>().ok()
+            }
+            Err(_) => None,
+        };
+
+        Ok(OutputSummary {
+            archive_size,
+            file_count,
+            exit_code,
+        })
+    }
+
+    /// The `result.json` in the downloaded `output.zip`, for the clients
+    /// that do not report it with the status. An invalid one is logged.
+    pub fn read_result_summary(&self) -> Result<Option<ResultSummary>, std::io::Error> {
+        let mut archive = zip::ZipArchive::new(fs::File::open(self.loc.join("output.zip"))?)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        let Ok(entry) = archive.by_name(RESULT_FILE) else {
+            return Ok(None);
+        };
+        let mut content = Vec::new();
+        // One byte over is enough to refuse it
+        entry.take(MAX_RESULT_SIZE + 1).read_to_end(&mut content)?;
+        match ResultSummary::parse(&content) {
+            Ok(summary) => Ok(Some(summary)),
+            Err(e) => {
+                tracing::warn!("Ignoring {RESULT_FILE} of job {}: {e}", self.id);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Unpack the downloaded `output.zip` next to it in the job directory
+    pub fn extract_output(&self) -> Result<usize, std::io::Error> {
+        // Packed inputs would later be restored over the extracted results
+        self.unpack()?;
+        extract_archive(&self.loc.join("output.zip"), &self.loc)
+    }
+
+    pub fn is_packed(&self) -> bool {
+        self.loc.join(PACKED_FILE).exists()
+    }
+
+    /// Pack every file of the job directory but `output.zip`, which is served
+    /// as is, into a single archive. Returns the number of files packed.
+    pub fn pack(&self) -> Result<usize, std::io::Error> {
+        let archive = self.loc.join(PACKED_FILE);
+        keeping_mtime(&self.loc, || {
+            let packed = pack_directory(&self.loc, &archive, &["output.zip"])?;
+            if packed == 0 {
+                fs::remove_file(&archive)?;
+            }
+            Ok(packed)
+        })
+    }
+
+    /// Restore the files of a packed job directory, a no-op when it is not packed
+    pub fn unpack(&self) -> Result<usize, std::io::Error> {
+        if !self.is_packed() {
+            return Ok(0);
+        }
+        keeping_mtime(&self.loc, || {
+            unpack_directory(&self.loc, &self.loc.join(PACKED_FILE))
+        })
+    }
+
     pub fn remove_from_disk(&self) -> Result<(), std::io::Error> {
         fs::remove_dir_all(&self.loc)
     }
 
+    /// When the cleaner retires the job, `max_age` after its directory was
+    /// last modified. `None` while the directory can't be read.
+    pub fn expires_at(&self, max_age: Duration) -> Option<SystemTime> {
+        let modified = fs::metadata(&self.loc).ok()?.modified().ok()?;
+        modified.checked_add(max_age)
+    }
+
+    /// Bytes taken by the files of the job, what its client has to receive
+    pub fn input_size(&self) -> u64 {
+        dir_size(&self.loc)
+    }
+
+    /// Names the transfer of the job to its client, whose progress is then
+    /// available under `GET /uploads/{id}/progress`
+    pub fn upload_id(&self) -> String {
+        self.loc
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string()
+    }
+
+    /// Key of `output.zip` in the object store, unique like the job directory
+    pub fn object_key(&self) -> String {
+        let dir = self.loc.file_name().unwrap_or_default().to_string_lossy();
+        format!("{}/{dir}.zip", self.tenant)
+    }
+
+    /// Empty the job directory once its archive lives in the object store.
+    /// The directory itself stays with its modification time, so the cleaner
+    /// still retires the job when its retention runs out.
+    pub fn clear_local_files(&self) -> Result<(), std::io::Error> {
+        keeping_mtime(&self.loc, || {
+            for entry in fs::read_dir(&self.loc)? {
+                let path = entry?.path();
+                if path.is_dir() {
+                    fs::remove_dir_all(path)?;
+                } else {
+                    fs::remove_file(path)?;
+                }
+            }
+            Ok(())
+        })
+    }
+
     pub fn set_service(&mut self, service: String) {
         self.service = service
     }
@@ -48,6 +519,25 @@ impl Job {
         self.user_id = user_id;
     }
 
+    /// Assign the job to a tenant, moving its directory into the tenant
+    /// namespace of `data_path`, which may be another storage root
+    pub fn set_tenant(&mut self, tenant: String, data_path: &str) -> Result<(), std::io::Error> {
+        let dir_name = self.loc.file_name().map(|n| n.to_os_string());
+        if let Some(dir_name) = dir_name {
+            let tenant_dir = tenant_data_path(data_path, &tenant);
+            let new_loc = tenant_dir.join(dir_name);
+            if new_loc != self.loc {
+                fs::create_dir_all(&tenant_dir)?;
+                if self.loc.exists() {
+                    move_dir(&self.loc, &new_loc)?;
+                }
+                self.loc = new_loc;
+            }
+        }
+        self.tenant = tenant;
+        Ok(())
+    }
+
     pub fn get_status(&self) -> Status {
         self.status
     }
@@ -57,10 +547,49 @@ impl Job {
 mod test {
 
     use super::*;
+    use crate::utils::io::file_sha256;
     use std::fs;
     use std::path::Path;
     use tempfile::TempDir;
 
+    #[test]
+    fn test_job_eta() {
+        let mut job = Job::new("");
+        job.id = 7;
+        job.status = Status::Queued;
+
+        // Third in line with 2 runs on the 2 slots: waits for 2 runs to end,
+        // one period
+        let eta = JobEta::new(&job, 2, 2, 2, Some(100));
+        assert_eq!(eta.queue_position, Some(3));
+        assert_eq!(eta.wait_secs, Some(200));
+        assert_eq!(eta.eta_secs, Some(300));
+        assert_eq!(JobEta::new(&job, 0, 1, 2, Some(100)).wait_secs, Some(0));
+        assert_eq!(JobEta::new(&job, 0, 0, 0, Some(100)).wait_secs, Some(0));
+
+        // Nothing is known without a runtime
+        let eta = JobEta::new(&job, 2, 2, 2, None);
+        assert_eq!((eta.wait_secs, eta.eta_secs), (None, None));
+
+        // A window opening later holds the job back
+        let eta = JobEta::new(&job, 0, 0, 2, Some(100)).delayed_by(Duration::from_secs(500));
+        assert_eq!(eta.eta_secs, Some(600));
+
+        job.status = Status::Running;
+        let eta = JobEta::new(&job, 0, 1, 2, Some(100)).running_for(Duration::from_secs(30));
+        assert_eq!(eta.queue_position, None);
+        assert_eq!((eta.wait_secs, eta.run_secs), (Some(0), Some(70)));
+        let eta = JobEta::new(&job, 0, 1, 2, Some(100)).running_for(Duration::from_secs(300));
+        assert_eq!(eta.eta_secs, Some(0));
+
+        job.status = Status::Held;
+        let eta = JobEta::new(&job, 0, 0, 2, Some(100));
+        assert_eq!(
+            (eta.wait_secs, eta.run_secs, eta.eta_secs),
+            (None, Some(100), None)
+        );
+    }
+
     #[tokio::test]
     async fn test_download() {
         let tempdir = TempDir::new().unwrap();
@@ -74,6 +603,209 @@ mod test {
         assert_eq!(result, test_data);
     }
 
+    #[test]
+    fn test_metadata_patched() {
+        let metadata = JobMetadata {
+            tags: vec!["a".to_string()],
+            description: Some("first".to_string()),
+            callback_url: Some("https://example.com/hook".to_string()),
+            notification_email: None,
+        };
+        let patched = metadata.patched(JobMetadataPatch {
+            description: Some(" ".to_string()),
+            notification_email: Some(" bob@example.com ".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(patched.tags, vec!["a"]);
+        assert_eq!(patched.description, None);
+        assert_eq!(patched.callback_url, metadata.callback_url);
+        assert_eq!(
+            patched.notification_email.as_deref(),
+            Some("bob@example.com")
+        );
+    }
+
+    #[test]
+    fn test_metadata_validate() {
+        assert!(JobMetadata::default().validate().is_ok());
+
+        let invalid = [
+            JobMetadata {
+                tags: vec![String::new()],
+                ..Default::default()
+            },
+            JobMetadata {
+                tags: vec!["t".to_string(); MAX_TAGS + 1],
+                ..Default::default()
+            },
+            JobMetadata {
+                description: Some("x".repeat(MAX_DESCRIPTION_LEN + 1)),
+                ..Default::default()
+            },
+            JobMetadata {
+                callback_url: Some("not a url".to_string()),
+                ..Default::default()
+            },
+            JobMetadata {
+                callback_url: Some("file:///etc/passwd".to_string()),
+                ..Default::default()
+            },
+            JobMetadata {
+                notification_email: Some("bob".to_string()),
+                ..Default::default()
+            },
+        ];
+        for metadata in invalid {
+            assert!(metadata.validate().is_err(), "{metadata:?}");
+        }
+    }
+
+    #[test]
+    fn test_compute_checksum() {
+        let tempdir = TempDir::new().unwrap();
+        let job = Job::new(tempdir.path().to_str().unwrap());
+
+        fs::create_dir_all(&job.loc).unwrap();
+        fs::write(job.loc.join("output.zip"), b"abc").unwrap();
+
+        assert_eq!(
+            job.compute_checksum().unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn test_summarize_output() {
+        let tempdir = TempDir::new().unwrap();
+        let job = Job::new(tempdir.path().join("jobs").to_str().unwrap());
+        fs::create_dir_all(&job.loc).unwrap();
+
+        let src = tempdir.path().join("src");
+        fs::create_dir_all(src.join("run1")).unwrap();
+        fs::write(src.join("run1").join("model.pdb"), b"ATOM").unwrap();
+        fs::write(src.join("run.sh"), b"echo").unwrap();
+        fs::write(src.join(EXIT_FILE), b"3\n").unwrap();
+        let archive = job.loc.join("output.zip");
+        crate::utils::io::zip_directory(&src, &archive).unwrap();
+
+        let summary = job.summarize_output().unwrap();
+        assert_eq!(
+            summary.archive_size,
+            fs::metadata(&archive).unwrap().len() as i64
+        );
+        assert_eq!(summary.file_count, 3);
+        assert_eq!(summary.exit_code, Some(3));
+
+        // No exit file, no exit code
+        fs::remove_file(src.join(EXIT_FILE)).unwrap();
+        crate::utils::io::zip_directory(&src, &archive).unwrap();
+        let summary = job.summarize_output().unwrap();
+        assert_eq!(summary.file_count, 2);
+        assert_eq!(summary.exit_code, None);
+    }
+
+    #[test]
+    fn test_read_result_summary() {
+        let tempdir = TempDir::new().unwrap();
+        let job = Job::new(tempdir.path().join("jobs").to_str().unwrap());
+        fs::create_dir_all(&job.loc).unwrap();
+        let src = tempdir.path().join("src");
+        fs::create_dir_all(&src).unwrap();
+        fs::write(src.join("run.sh"), b"echo").unwrap();
+        let archive = job.loc.join("output.zip");
+
+        crate::utils::io::zip_directory(&src, &archive).unwrap();
+        assert_eq!(job.read_result_summary().unwrap(), None);
+
+        fs::write(
+            src.join(RESULT_FILE),
+            br#"{"status": "converged", "metrics": {"rmsd": 1.2}}"#,
+        )
+        .unwrap();
+        crate::utils::io::zip_directory(&src, &archive).unwrap();
+        let summary = job.read_result_summary().unwrap().unwrap();
+        assert_eq!(summary.status.as_deref(), Some("converged"));
+        assert_eq!(summary.metrics["rmsd"], serde_json::json!(1.2));
+
+        // An invalid one is left out
+        fs::write(src.join(RESULT_FILE), br#"{"metrics": [1]}"#).unwrap();
+        crate::utils::io::zip_directory(&src, &archive).unwrap();
+        assert_eq!(job.read_result_summary().unwrap(), None);
+    }
+
+    #[test]
+    fn test_compute_input_hash() {
+        let tempdir = TempDir::new().unwrap();
+        let hash_job = |service: &str, files: &[(&str, &[u8])], parameters: &[(&str, &str)]| {
+            let mut job = Job::new(tempdir.path().to_str().unwrap());
+            job.set_service(service.to_string());
+            for (name, content) in files {
+                let path = job.loc.join(name);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, content).unwrap();
+            }
+            let parameters = parameters
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect();
+            job.compute_input_hash(&job.input_manifest().unwrap(), &parameters)
+        };
+        let write_job = |service: &str, files: &[(&str, &[u8])]| hash_job(service, files, &[]);
+
+        let original = write_job("haddock", &[("run.sh", b"echo"), ("data/a.pdb", b"ATOM")]);
+        let same = write_job("haddock", &[("data/a.pdb", b"ATOM"), ("run.sh", b"echo")]);
+        assert_eq!(original, same);
+
+        let files: &[(&str, &[u8])] = &[("run.sh", b"echo"), ("data/a.pdb", b"ATOM")];
+        assert_ne!(original, hash_job("haddock", files, &[("steps", "10")]));
+        assert_eq!(
+            hash_job("haddock", files, &[("steps", "10"), ("seed", "1")]),
+            hash_job("haddock", files, &[("seed", "1"), ("steps", "10")])
+        );
+        assert_ne!(
+            hash_job("haddock", files, &[("steps", "10")]),
+            hash_job("haddock", files, &[("steps", "100")])
+        );
+
+        assert_ne!(
+            original,
+            write_job("other", &[("run.sh", b"echo"), ("data/a.pdb", b"ATOM")])
+        );
+        assert_ne!(
+            original,
+            write_job("haddock", &[("run.sh", b"echo"), ("data/b.pdb", b"ATOM")])
+        );
+        assert_ne!(
+            original,
+            write_job("haddock", &[("run.sh", b"echo"), ("data/a.pdb", b"HETATM")])
+        );
+    }
+
+    #[test]
+    fn test_input_manifest() {
+        let tempdir = TempDir::new().unwrap();
+        let job = Job::new(tempdir.path().to_str().unwrap());
+        fs::create_dir_all(job.loc.join("data")).unwrap();
+        fs::write(job.loc.join("run.sh"), b"echo").unwrap();
+        fs::write(job.loc.join("data/a.pdb"), b"ATOM").unwrap();
+
+        let inputs = job.input_manifest().unwrap();
+        let paths: Vec<_> = inputs.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, ["data/a.pdb", "run.sh"]);
+        assert_eq!(inputs[1].size, 4);
+        assert_eq!(
+            inputs[1].sha256,
+            file_sha256(&job.loc.join("run.sh")).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_compute_checksum_missing_file() {
+        let tempdir = TempDir::new().unwrap();
+        let job = Job::new(tempdir.path().to_str().unwrap());
+        assert!(job.compute_checksum().is_err());
+    }
+
     #[test]
     fn test_remove_from_disk() {
         let tempdir = TempDir::new().unwrap();
@@ -90,6 +822,108 @@ mod test {
         assert!(!Path::new(&job.loc).exists());
     }
 
+    #[test]
+    fn test_expires_at() {
+        let tempdir = TempDir::new().unwrap();
+        let job = Job::new(tempdir.path().to_str().unwrap());
+        let max_age = Duration::from_secs(3600);
+        assert_eq!(job.expires_at(max_age), None);
+
+        fs::create_dir_all(&job.loc).unwrap();
+        let modified = fs::metadata(&job.loc).unwrap().modified().unwrap();
+        assert_eq!(job.expires_at(max_age), Some(modified + max_age));
+    }
+
+    #[test]
+    fn test_clear_local_files() {
+        let tempdir = TempDir::new().unwrap();
+        let job = Job::new(tempdir.path().to_str().unwrap());
+        fs::create_dir_all(job.loc.join("run1")).unwrap();
+        fs::write(job.loc.join("run1").join("model.pdb"), b"ATOM").unwrap();
+        fs::write(job.loc.join("output.zip"), b"abc").unwrap();
+        let modified = fs::metadata(&job.loc).unwrap().modified().unwrap();
+
+        job.clear_local_files().unwrap();
+
+        assert_eq!(fs::read_dir(&job.loc).unwrap().count(), 0);
+        assert_eq!(
+            fs::metadata(&job.loc).unwrap().modified().unwrap(),
+            modified
+        );
+    }
+
+    #[test]
+    fn test_pack_and_unpack() {
+        let tempdir = TempDir::new().unwrap();
+        let job = Job::new(tempdir.path().to_str().unwrap());
+        fs::create_dir_all(job.loc.join("inputs")).unwrap();
+        fs::write(job.loc.join("run.sh"), b"echo").unwrap();
+        fs::write(job.loc.join("inputs").join("a.pdb"), b"ATOM").unwrap();
+        fs::write(job.loc.join("output.zip"), b"abc").unwrap();
+        let modified = fs::metadata(&job.loc).unwrap().modified().unwrap();
+
+        assert_eq!(job.pack().unwrap(), 2);
+        assert!(job.is_packed());
+        assert!(!job.loc.join("run.sh").exists());
+        assert!(job.loc.join("output.zip").exists());
+        assert_eq!(
+            fs::metadata(&job.loc).unwrap().modified().unwrap(),
+            modified
+        );
+
+        assert_eq!(job.unpack().unwrap(), 2);
+        assert!(!job.is_packed());
+        assert_eq!(
+            fs::read(job.loc.join("inputs").join("a.pdb")).unwrap(),
+            b"ATOM"
+        );
+        assert_eq!(job.unpack().unwrap(), 0);
+
+        // Nothing but the results, nothing to pack
+        fs::remove_file(job.loc.join("run.sh")).unwrap();
+        fs::remove_dir_all(job.loc.join("inputs")).unwrap();
+        assert_eq!(job.pack().unwrap(), 0);
+        assert!(!job.is_packed());
+    }
+
+    #[test]
+    fn test_extract_output_unpacks_first() {
+        let tempdir = TempDir::new().unwrap();
+        let job = Job::new(tempdir.path().join("jobs").to_str().unwrap());
+        fs::create_dir_all(&job.loc).unwrap();
+        fs::write(job.loc.join("model.pdb"), b"input").unwrap();
+        job.pack().unwrap();
+
+        let src = tempdir.path().join("src");
+        fs::create_dir_all(&src).unwrap();
+        fs::write(src.join("model.pdb"), b"refined").unwrap();
+        crate::utils::io::zip_directory(&src, &job.loc.join("output.zip")).unwrap();
+
+        job.extract_output().unwrap();
+        assert!(!job.is_packed());
+        assert_eq!(fs::read(job.loc.join("model.pdb")).unwrap(), b"refined");
+    }
+
+    #[test]
+    fn test_object_key() {
+        let mut job = Job::new("/data");
+        job.loc = PathBuf::from("/data/acme/0b5e");
+        job.tenant = "acme".to_string();
+        assert_eq!(job.object_key(), "acme/0b5e.zip");
+    }
+
+    #[test]
+    fn test_input_size() {
+        let tempdir = TempDir::new().unwrap();
+        let job = Job::new(tempdir.path().to_str().unwrap());
+        assert_eq!(job.input_size(), 0);
+
+        fs::create_dir_all(job.loc.join("inputs")).unwrap();
+        fs::write(job.loc.join("run.sh"), b"echo").unwrap();
+        fs::write(job.loc.join("inputs").join("model.pdb"), b"ATOM").unwrap();
+        assert_eq!(job.input_size(), 8);
+    }
+
     #[test]
     fn test_set_service() {
         let mut job = Job::new("");
@@ -97,6 +931,57 @@ mod test {
         assert_eq!(job.service, "test".to_string())
     }
 
+    #[test]
+    fn test_set_tenant_moves_directory() {
+        let tempdir = TempDir::new().unwrap();
+        let data_path = tempdir.path().to_str().unwrap();
+        let mut job = Job::new(data_path);
+        assert_eq!(job.tenant, DEFAULT_TENANT);
+
+        fs::create_dir_all(&job.loc).unwrap();
+        fs::write(job.loc.join("input.txt"), b"data").unwrap();
+        let old_loc = job.loc.clone();
+
+        job.set_tenant("lab".to_string(), data_path).unwrap();
+
+        assert_eq!(job.tenant, "lab");
+        assert_eq!(job.loc.parent().unwrap(), tempdir.path().join("lab"));
+        assert_eq!(job.loc.file_name(), old_loc.file_name());
+        assert!(!old_loc.exists());
+        assert!(job.loc.join("input.txt").exists());
+    }
+
+    #[test]
+    fn test_set_tenant_moves_to_other_root() {
+        let tempdir = TempDir::new().unwrap();
+        let data_path = tempdir.path().join("data");
+        let nvme = tempdir.path().join("nvme");
+        let mut job = Job::new(data_path.to_str().unwrap());
+        fs::create_dir_all(&job.loc).unwrap();
+        fs::write(job.loc.join("input.txt"), b"data").unwrap();
+        let old_loc = job.loc.clone();
+
+        job.set_tenant("lab".to_string(), nvme.to_str().unwrap())
+            .unwrap();
+
+        assert_eq!(job.loc.parent().unwrap(), nvme.join("lab"));
+        assert!(!old_loc.exists());
+        assert!(job.loc.join("input.txt").exists());
+    }
+
+    #[test]
+    fn test_set_default_tenant_keeps_location() {
+        let tempdir = TempDir::new().unwrap();
+        let data_path = tempdir.path().to_str().unwrap();
+        let mut job = Job::new(data_path);
+        let old_loc = job.loc.clone();
+
+        job.set_tenant(DEFAULT_TENANT.to_string(), data_path)
+            .unwrap();
+
+        assert_eq!(job.loc, old_loc);
+    }
+
     #[test]
     fn test_set_user_id() {
         let mut job = Job::new("");
@@ -1,15 +1,37 @@
+use std::collections::HashSet;
 use std::path::PathBuf;
-
-use crate::models::job_dao::Job;
+use std::time::Duration;
+
+use crate::datasource::db::add_column_if_missing;
+use crate::models::download_dto::create_downloads_table;
+use crate::models::job_dao::{Job, JobError, JobMetadata, OutputSummary};
+use crate::models::job_event_dao::{ADMIN_ACTOR, JobEvent, SERVER_ACTOR, WATCHDOG_ACTOR};
+use crate::models::job_event_dto::create_job_events_table;
+use crate::models::job_note_dto::create_job_notes_table;
+use crate::models::manifest_dto::ManifestEntry;
+use crate::models::queue_dto::CLAIM_TTL;
+use crate::models::service_probe_dto::create_service_probes_table;
+use crate::models::share_dto::create_shares_table;
 use crate::models::status_dto::Status;
-use sqlx::{Row, SqlitePool};
+use crate::models::task_run_dto::create_task_runs_table;
+use crate::models::user_dto::create_users_table;
+use crate::models::webhook_dto::create_webhook_deliveries_table;
+use crate::utils::result_summary::ResultSummary;
+use sqlx::sqlite::SqliteRow;
+use sqlx::{Row, SqliteConnection, SqlitePool};
 
 pub async fn create_jobs_table(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    // The whole schema is set up on one connection, see `add_column_if_missing`
+    let mut conn = pool.acquire().await?;
+
+    // Jobs reference their user
+    create_users_table(&mut conn).await?;
+
     sqlx::query(
         r#"
         CREATE TABLE IF NOT EXISTS jobs (
             id INTEGER PRIMARY KEY AUTOINCREMENT,
-            user_id INTEGER NOT NULL,
+            user_id INTEGER NOT NULL REFERENCES users(id),
             service TEXT NOT NULL,
             status TEXT NOT NULL,
             loc TEXT NOT NULL,
@@ -18,21 +40,179 @@ pub async fn create_jobs_table(pool: &SqlitePool) -> Result<(), sqlx::Error> {
         )
     "#,
     )
-    .execute(pool)
+    .execute(&mut *conn)
+    .await?;
+
+    // Databases from before the users table hold jobs of ids never registered.
+    // Their `jobs` table keeps no foreign key, only new databases get one.
+    sqlx::query(
+        "INSERT OR IGNORE INTO users (id, name) SELECT DISTINCT user_id, 'user' || user_id FROM jobs",
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    // Columns added after the initial schema
+    add_column_if_missing(&mut conn, "jobs", "checksum", "TEXT").await?;
+    add_column_if_missing(
+        &mut conn,
+        "jobs",
+        "tenant",
+        "TEXT NOT NULL DEFAULT 'default'",
+    )
+    .await?;
+    add_column_if_missing(&mut conn, "jobs", "archive_size", "INTEGER").await?;
+    add_column_if_missing(&mut conn, "jobs", "file_count", "INTEGER").await?;
+    add_column_if_missing(&mut conn, "jobs", "exit_code", "INTEGER").await?;
+    add_column_if_missing(&mut conn, "jobs", "input_hash", "TEXT").await?;
+    add_column_if_missing(&mut conn, "jobs", "group_id", "TEXT").await?;
+    add_column_if_missing(&mut conn, "jobs", "remote_key", "TEXT").await?;
+    add_column_if_missing(&mut conn, "jobs", "tags", "TEXT").await?;
+    add_column_if_missing(&mut conn, "jobs", "description", "TEXT").await?;
+    add_column_if_missing(&mut conn, "jobs", "callback_url", "TEXT").await?;
+    add_column_if_missing(&mut conn, "jobs", "notification_email", "TEXT").await?;
+    add_column_if_missing(&mut conn, "jobs", "review_reason", "TEXT").await?;
+    add_column_if_missing(
+        &mut conn,
+        "jobs",
+        "download_attempts",
+        "INTEGER NOT NULL DEFAULT 0",
+    )
+    .await?;
+    add_column_if_missing(&mut conn, "jobs", "retry_at", "DATETIME").await?;
+    add_column_if_missing(
+        &mut conn,
+        "jobs",
+        "retry_count",
+        "INTEGER NOT NULL DEFAULT 0",
+    )
+    .await?;
+    // Jobs from before it count from their creation
+    add_column_if_missing(&mut conn, "jobs", "status_since", "DATETIME").await?;
+    // The submitted files as a JSON list of manifest entries
+    add_column_if_missing(&mut conn, "jobs", "inputs", "TEXT").await?;
+    add_column_if_missing(&mut conn, "jobs", "timeout", "INTEGER").await?;
+    add_column_if_missing(
+        &mut conn,
+        "jobs",
+        "cancel_requested",
+        "BOOLEAN NOT NULL DEFAULT 0",
+    )
     .await?;
+    add_column_if_missing(
+        &mut conn,
+        "jobs",
+        "discard_requested",
+        "BOOLEAN NOT NULL DEFAULT 0",
+    )
+    .await?;
+    add_column_if_missing(
+        &mut conn,
+        "jobs",
+        "expiry_notified",
+        "BOOLEAN NOT NULL DEFAULT 0",
+    )
+    .await?;
+    // `result.json` of the run, as JSON
+    add_column_if_missing(&mut conn, "jobs", "result_summary", "TEXT").await?;
+    // A `JobError` as JSON
+    add_column_if_missing(&mut conn, "jobs", "last_error", "TEXT").await?;
+    add_column_if_missing(&mut conn, "jobs", "priority", "INTEGER NOT NULL DEFAULT 0").await?;
+    // When `Queue::load` picked the job to send, unset once it leaves the queue
+    add_column_if_missing(&mut conn, "jobs", "claimed_at", "DATETIME").await?;
+
+    // Status transitions, operator notes and callbacks reference their job
+    create_job_events_table(&mut conn).await?;
+    create_job_notes_table(&mut conn).await?;
+    create_downloads_table(&mut conn).await?;
+    create_webhook_deliveries_table(&mut conn).await?;
+    create_task_runs_table(&mut conn).await?;
+    create_service_probes_table(&mut conn).await?;
+    create_shares_table(&mut conn).await?;
+
     Ok(())
 }
 
+/// No tags are stored as NULL
+fn tags_json(tags: &[String]) -> Option<String> {
+    (!tags.is_empty()).then(|| serde_json::to_string(tags).unwrap_or_default())
+}
+
+/// A transaction recording a status change. Its write lock is taken up
+/// front: reading the old status first and upgrading to a write later fails
+/// outright when another connection wrote in between, as in `Queue::load`.
+async fn begin_transition(
+    pool: &SqlitePool,
+) -> Result<sqlx::Transaction<'static, sqlx::Sqlite>, sqlx::Error> {
+    pool.begin_with("BEGIN IMMEDIATE").await
+}
+
 impl Job {
+    /// Build a `Job` from a row of the `jobs` table
+    pub fn from_row(row: &SqliteRow) -> Job {
+        let status: String = row.get("status");
+        let loc: String = row.get("loc");
+        let dest_id: Option<u32> = row.get("dest_id");
+        // Tags are kept as a JSON array
+        let tags: Option<String> = row.get("tags");
+        let result_summary: Option<String> = row.get("result_summary");
+        let last_error: Option<String> = row.get("last_error");
+
+        Job {
+            id: row.get("id"),
+            user_id: row.get("user_id"),
+            service: row.get("service"),
+            status: Status::from_string(&status),
+            loc: PathBuf::from(loc),
+            dest_id: dest_id.unwrap_or_default(),
+            checksum: row.get("checksum"),
+            tenant: row.get("tenant"),
+            archive_size: row.get("archive_size"),
+            file_count: row.get("file_count"),
+            exit_code: row.get("exit_code"),
+            input_hash: row.get("input_hash"),
+            group_id: row.get("group_id"),
+            remote_key: row.get("remote_key"),
+            review_reason: row.get("review_reason"),
+            download_attempts: row.get("download_attempts"),
+            retry_count: row.get("retry_count"),
+            timeout: row.get("timeout"),
+            priority: row.get("priority"),
+            cancel_requested: row.get("cancel_requested"),
+            discard_requested: row.get("discard_requested"),
+            expiry_notified: row.get("expiry_notified"),
+            result_summary: result_summary.and_then(|s| serde_json::from_str(&s).ok()),
+            last_error: last_error.and_then(|e| serde_json::from_str(&e).ok()),
+            metadata: JobMetadata {
+                tags: tags
+                    .and_then(|t| serde_json::from_str(&t).ok())
+                    .unwrap_or_default(),
+                description: row.get("description"),
+                callback_url: row.get("callback_url"),
+                notification_email: row.get("notification_email"),
+            },
+        }
+    }
+
     pub async fn add_to_db(&mut self, pool: &SqlitePool) -> Result<(), sqlx::Error> {
-        let result =
-            sqlx::query("INSERT INTO jobs (user_id, loc, status, service) VALUES (?, ?, ?, ?)")
-                .bind(self.user_id)
-                .bind(self.loc.to_str())
-                .bind(self.status.to_string())
-                .bind(self.service.to_string())
-                .execute(pool)
-                .await?;
+        let result = sqlx::query(
+            "INSERT INTO jobs (user_id, loc, status, service, tenant, input_hash, group_id, tags, description, callback_url, notification_email, review_reason, timeout, priority) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(self.user_id)
+        .bind(self.loc.to_str())
+        .bind(self.status.to_string())
+        .bind(self.service.to_string())
+        .bind(&self.tenant)
+        .bind(&self.input_hash)
+        .bind(&self.group_id)
+        .bind(tags_json(&self.metadata.tags))
+        .bind(&self.metadata.description)
+        .bind(&self.metadata.callback_url)
+        .bind(&self.metadata.notification_email)
+        .bind(&self.review_reason)
+        .bind(self.timeout)
+        .bind(self.priority)
+        .execute(pool)
+        .await?;
 
         let job_id = result.last_insert_rowid();
         self.id = job_id as u32;
@@ -45,17 +225,83 @@ impl Job {
         status: Status,
         pool: &SqlitePool,
     ) -> Result<(), sqlx::Error> {
-        let _result = sqlx::query("UPDATE jobs SET status = ? WHERE id = ?")
-            .bind(status.to_string())
-            .bind(self.id)
-            .execute(pool)
+        self.update_status_by(status, SERVER_ACTOR, None, pool)
+            .await
+    }
+
+    /// Set the status and record the transition in the history of the job,
+    /// attributed to `actor`. Setting the status it already has is only
+    /// recorded along with a `reason`.
+    pub async fn update_status_by(
+        &mut self,
+        status: Status,
+        actor: &str,
+        reason: Option<&str>,
+        pool: &SqlitePool,
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = begin_transition(pool).await?;
+        self.record_transition(status, actor, reason, &mut tx)
             .await?;
+        sqlx::query(
+            // A claim only holds while the job is queued, see `Queue::load`
+            "UPDATE jobs SET status = ?, status_since = CURRENT_TIMESTAMP, claimed_at = NULL WHERE id = ?",
+        )
+        .bind(status.to_string())
+        .bind(self.id)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
 
         self.status = status;
 
         Ok(())
     }
 
+    /// Record in `job_events` the change to `status` about to be stored,
+    /// nothing for a job not in the database.
+    ///
+    /// `Locked` only keeps other tasks off a job while the server handles it,
+    /// see `terminate_job`, so it is left out: a job locked and put back after
+    /// a failure records nothing, one locked and then killed records the
+    /// change from the status it had before.
+    async fn record_transition(
+        &self,
+        status: Status,
+        actor: &str,
+        reason: Option<&str>,
+        conn: &mut SqliteConnection,
+    ) -> Result<(), sqlx::Error> {
+        if status == Status::Locked {
+            return Ok(());
+        }
+        let old: Option<String> = sqlx::query_scalar("SELECT status FROM jobs WHERE id = ?")
+            .bind(self.id)
+            .fetch_optional(&mut *conn)
+            .await?;
+        let Some(mut old) = old.map(|s| Status::from_string(&s)) else {
+            return Ok(());
+        };
+        if old == Status::Locked {
+            let last: Option<String> = sqlx::query_scalar(
+                "SELECT new_status FROM job_events WHERE job_id = ? ORDER BY id DESC LIMIT 1",
+            )
+            .bind(self.id)
+            .fetch_optional(&mut *conn)
+            .await?;
+            if let Some(last) = last {
+                old = Status::from_string(&last);
+            }
+        }
+        if old == status && reason.is_none() {
+            return Ok(());
+        }
+        let mut event = JobEvent::new(self.id, old, status, actor);
+        if let Some(reason) = reason {
+            event = event.with_reason(reason);
+        }
+        event.add_to_db(conn).await
+    }
+
     pub async fn update_dest_id(
         &mut self,
         dest_id: u32,
@@ -72,6 +318,94 @@ impl Job {
         Ok(())
     }
 
+    pub async fn update_checksum(
+        &mut self,
+        checksum: String,
+        pool: &SqlitePool,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE jobs SET checksum = ? WHERE id = ?")
+            .bind(&checksum)
+            .bind(self.id)
+            .execute(pool)
+            .await?;
+
+        self.checksum = Some(checksum);
+
+        Ok(())
+    }
+
+    pub async fn update_output_summary(
+        &mut self,
+        summary: OutputSummary,
+        pool: &SqlitePool,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE jobs SET archive_size = ?, file_count = ?, exit_code = ? WHERE id = ?")
+            .bind(summary.archive_size)
+            .bind(summary.file_count)
+            .bind(summary.exit_code)
+            .bind(self.id)
+            .execute(pool)
+            .await?;
+
+        self.archive_size = Some(summary.archive_size);
+        self.file_count = Some(summary.file_count);
+        self.exit_code = summary.exit_code;
+
+        Ok(())
+    }
+
+    pub async fn update_result_summary(
+        &mut self,
+        summary: ResultSummary,
+        pool: &SqlitePool,
+    ) -> Result<(), sqlx::Error> {
+        let json = serde_json::to_string(&summary).map_err(|e| sqlx::Error::Encode(e.into()))?;
+        sqlx::query("UPDATE jobs SET result_summary = ? WHERE id = ?")
+            .bind(json)
+            .bind(self.id)
+            .execute(pool)
+            .await?;
+        self.result_summary = Some(summary);
+        Ok(())
+    }
+
+    /// Keep why the job failed, replacing what an earlier failure left
+    pub async fn update_last_error(
+        &mut self,
+        error: JobError,
+        pool: &SqlitePool,
+    ) -> Result<(), sqlx::Error> {
+        let json = serde_json::to_string(&error).map_err(|e| sqlx::Error::Encode(e.into()))?;
+        sqlx::query("UPDATE jobs SET last_error = ? WHERE id = ?")
+            .bind(json)
+            .bind(self.id)
+            .execute(pool)
+            .await?;
+        self.last_error = Some(error);
+        Ok(())
+    }
+
+    pub async fn update_metadata(
+        &mut self,
+        metadata: JobMetadata,
+        pool: &SqlitePool,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE jobs SET tags = ?, description = ?, callback_url = ?, notification_email = ? WHERE id = ?",
+        )
+        .bind(tags_json(&metadata.tags))
+        .bind(&metadata.description)
+        .bind(&metadata.callback_url)
+        .bind(&metadata.notification_email)
+        .bind(self.id)
+        .execute(pool)
+        .await?;
+
+        self.metadata = metadata;
+
+        Ok(())
+    }
+
     pub async fn retrieve_id(&mut self, id: u32, pool: &SqlitePool) -> Result<(), sqlx::Error> {
         let row = sqlx::query("SELECT * FROM jobs WHERE id = ?")
             .bind(id)
@@ -79,19 +413,505 @@ impl Job {
             .await?
             .ok_or(sqlx::Error::RowNotFound)?;
 
-        let status: String = row.get("status");
-        let loc: String = row.get("loc");
+        *self = Job::from_row(&row);
+
+        Ok(())
+    }
+
+    /// Keep the listing of the submitted files, it outlives the files themselves
+    pub async fn record_inputs(
+        &self,
+        inputs: &[ManifestEntry],
+        pool: &SqlitePool,
+    ) -> Result<(), sqlx::Error> {
+        let inputs = serde_json::to_string(inputs).map_err(|e| sqlx::Error::Encode(e.into()))?;
+        sqlx::query("UPDATE jobs SET inputs = ? WHERE id = ?")
+            .bind(inputs)
+            .bind(self.id)
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
+
+    /// The files job `id` was submitted with, `None` for jobs from before
+    /// they were recorded
+    pub async fn retrieve_inputs(
+        id: u32,
+        pool: &SqlitePool,
+    ) -> Result<Option<Vec<ManifestEntry>>, sqlx::Error> {
+        let inputs: Option<String> = sqlx::query_scalar("SELECT inputs FROM jobs WHERE id = ?")
+            .bind(id)
+            .fetch_optional(pool)
+            .await?
+            .ok_or(sqlx::Error::RowNotFound)?;
+        inputs
+            .map(|i| serde_json::from_str(&i))
+            .transpose()
+            .map_err(|e| sqlx::Error::Decode(e.into()))
+    }
+
+    /// Most recent job of the same user with the same inputs, submitted within
+    /// `window` and not failed or discarded
+    pub async fn find_recent_duplicate(
+        &self,
+        window: Duration,
+        pool: &SqlitePool,
+    ) -> Result<Option<Job>, sqlx::Error> {
+        let Some(input_hash) = &self.input_hash else {
+            return Ok(None);
+        };
+
+        let row = sqlx::query(
+            r#"
+            SELECT * FROM jobs
+            WHERE user_id = ? AND service = ? AND input_hash = ? AND id != ?
+              AND status NOT IN ('failed', 'invalid', 'killed', 'cancelled', 'cleaned')
+              AND created_at >= datetime('now', ?)
+            ORDER BY id DESC
+            LIMIT 1
+            "#,
+        )
+        .bind(self.user_id)
+        .bind(&self.service)
+        .bind(input_hash)
+        .bind(self.id)
+        .bind(format!("-{} seconds", window.as_secs()))
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(row.as_ref().map(Job::from_row))
+    }
+
+    /// Every job of a group of `user_id`, oldest first. Groups are named by
+    /// their owners, two users can use the same name.
+    pub async fn list_by_group(
+        group_id: &str,
+        user_id: i32,
+        pool: &SqlitePool,
+    ) -> Result<Vec<Job>, sqlx::Error> {
+        let rows = sqlx::query("SELECT * FROM jobs WHERE group_id = ? AND user_id = ? ORDER BY id")
+            .bind(group_id)
+            .bind(user_id)
+            .fetch_all(pool)
+            .await?;
+
+        Ok(rows.iter().map(Job::from_row).collect())
+    }
+
+    /// Completed jobs whose `output.zip` is still on the local disk
+    pub async fn list_local_completed(pool: &SqlitePool) -> Result<Vec<Job>, sqlx::Error> {
+        let rows = sqlx::query("SELECT * FROM jobs WHERE status = ? AND remote_key IS NULL")
+            .bind(Status::Completed.to_string())
+            .fetch_all(pool)
+            .await?;
+
+        Ok(rows.iter().map(Job::from_row).collect())
+    }
+
+    /// Jobs the cleaner may remove once aged out: all but the cleaned and held ones
+    pub async fn list_cleanable(pool: &SqlitePool) -> Result<Vec<Job>, sqlx::Error> {
+        let rows = sqlx::query("SELECT * FROM jobs WHERE status NOT IN (?, ?) ORDER BY id")
+            .bind(Status::Cleaned.to_string())
+            .bind(Status::Held.to_string())
+            .fetch_all(pool)
+            .await?;
+
+        Ok(rows.iter().map(Job::from_row).collect())
+    }
+
+    /// Jobs whose directory may be packed: sent off or finished, and still on the local disk
+    pub async fn list_packable(pool: &SqlitePool) -> Result<Vec<Job>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT * FROM jobs WHERE status IN (?, ?) AND remote_key IS NULL ORDER BY id",
+        )
+        .bind(Status::Submitted.to_string())
+        .bind(Status::Completed.to_string())
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows.iter().map(Job::from_row).collect())
+    }
+
+    pub async fn update_remote_key(
+        &mut self,
+        remote_key: String,
+        pool: &SqlitePool,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE jobs SET remote_key = ? WHERE id = ?")
+            .bind(&remote_key)
+            .bind(self.id)
+            .execute(pool)
+            .await?;
+
+        self.remote_key = Some(remote_key);
+
+        Ok(())
+    }
+
+    /// Hold every queued job of a user so the sender skips them,
+    /// returning how many were held
+    pub async fn hold_queued(user_id: u32, pool: &SqlitePool) -> Result<u64, sqlx::Error> {
+        let mut tx = pool.begin().await?;
+        sqlx::query(
+            r#"
+            INSERT INTO job_events (job_id, old_status, new_status, reason, actor)
+            SELECT id, status, ?, 'user suspended', ? FROM jobs WHERE user_id = ? AND status = ?
+            "#,
+        )
+        .bind(Status::Held.to_string())
+        .bind(ADMIN_ACTOR)
+        .bind(user_id)
+        .bind(Status::Queued.to_string())
+        .execute(&mut *tx)
+        .await?;
+        let result = sqlx::query(
+            "UPDATE jobs SET status = ?, status_since = CURRENT_TIMESTAMP WHERE user_id = ? AND status = ?",
+        )
+            .bind(Status::Held.to_string())
+            .bind(user_id)
+            .bind(Status::Queued.to_string())
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Put the held jobs of a user back in the queue, returning how many were
+    /// released. The ones awaiting review stay held.
+    pub async fn release_held(user_id: u32, pool: &SqlitePool) -> Result<u64, sqlx::Error> {
+        let mut tx = pool.begin().await?;
+        sqlx::query(
+            r#"
+            INSERT INTO job_events (job_id, old_status, new_status, reason, actor)
+            SELECT id, status, ?, 'user restored', ? FROM jobs
+            WHERE user_id = ? AND status = ? AND review_reason IS NULL
+            "#,
+        )
+        .bind(Status::Queued.to_string())
+        .bind(ADMIN_ACTOR)
+        .bind(user_id)
+        .bind(Status::Held.to_string())
+        .execute(&mut *tx)
+        .await?;
+        let result = sqlx::query(
+            "UPDATE jobs SET status = ?, status_since = CURRENT_TIMESTAMP WHERE user_id = ? AND status = ? AND review_reason IS NULL",
+        )
+        .bind(Status::Queued.to_string())
+        .bind(user_id)
+        .bind(Status::Held.to_string())
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Jobs held for review, oldest first
+    pub async fn list_in_review(pool: &SqlitePool) -> Result<Vec<Job>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT * FROM jobs WHERE status = ? AND review_reason IS NOT NULL ORDER BY id",
+        )
+        .bind(Status::Held.to_string())
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows.iter().map(Job::from_row).collect())
+    }
+
+    /// Jobs of a user waiting on a service, queued or held
+    pub async fn count_waiting(
+        user_id: i32,
+        service: &str,
+        pool: &SqlitePool,
+    ) -> Result<u32, sqlx::Error> {
+        sqlx::query_scalar(
+            "SELECT COUNT(*) FROM jobs WHERE user_id = ? AND service = ? AND status IN (?, ?)",
+        )
+        .bind(user_id)
+        .bind(service)
+        .bind(Status::Queued.to_string())
+        .bind(Status::Held.to_string())
+        .fetch_one(pool)
+        .await
+    }
+
+    /// Number of queued jobs and, when there is any, how long the oldest one
+    /// has been waiting
+    pub async fn queue_watermarks(
+        pool: &SqlitePool,
+    ) -> Result<(u32, Option<Duration>), sqlx::Error> {
+        let (depth, oldest): (u32, Option<i64>) = sqlx::query_as(
+            r#"
+            SELECT COUNT(*),
+                   MAX(CAST(strftime('%s', 'now') AS INTEGER)
+                       - CAST(strftime('%s', COALESCE(status_since, created_at)) AS INTEGER))
+            FROM jobs WHERE status = ?
+            "#,
+        )
+        .bind(Status::Queued.to_string())
+        .fetch_one(pool)
+        .await?;
+
+        Ok((depth, oldest.map(|s| Duration::from_secs(s.max(0) as u64))))
+    }
+
+    /// Queued jobs of the service the sender picks before this one, and the
+    /// jobs of the service taking a slot on its client
+    pub async fn queue_position(&self, pool: &SqlitePool) -> Result<(u32, u32), sqlx::Error> {
+        sqlx::query_as(
+            r#"
+            SELECT
+              (SELECT COUNT(*) FROM jobs
+               WHERE service = ? AND status = ?
+                 AND (priority > ? OR (priority = ? AND id < ?))),
+              (SELECT COUNT(*) FROM jobs
+               WHERE service = ? AND status IN (?, ?, ?, ?))
+            "#,
+        )
+        .bind(&self.service)
+        .bind(Status::Queued.to_string())
+        .bind(self.priority)
+        .bind(self.priority)
+        .bind(self.id)
+        .bind(&self.service)
+        .bind(Status::Processing.to_string())
+        .bind(Status::Submitted.to_string())
+        .bind(Status::Prepared.to_string())
+        .bind(Status::Running.to_string())
+        .fetch_one(pool)
+        .await
+    }
+
+    /// How long the job has been in its current status
+    pub async fn status_age(&self, pool: &SqlitePool) -> Result<Duration, sqlx::Error> {
+        let secs: i64 = sqlx::query_scalar(
+            r#"
+            SELECT CAST(strftime('%s', 'now') AS INTEGER)
+                   - CAST(strftime('%s', COALESCE(status_since, created_at)) AS INTEGER)
+            FROM jobs WHERE id = ?
+            "#,
+        )
+        .bind(self.id)
+        .fetch_one(pool)
+        .await?;
+
+        Ok(Duration::from_secs(secs.max(0) as u64))
+    }
+
+    /// Set the status together with the review reason, `None` clearing it.
+    /// The decision is recorded in the history of the job as `note`, by
+    /// `actor`.
+    pub async fn update_review(
+        &mut self,
+        status: Status,
+        reason: Option<String>,
+        actor: &str,
+        note: &str,
+        pool: &SqlitePool,
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = begin_transition(pool).await?;
+        self.record_transition(status, actor, Some(note), &mut tx)
+            .await?;
+        sqlx::query(
+            "UPDATE jobs SET status = ?, review_reason = ?, status_since = CURRENT_TIMESTAMP WHERE id = ?",
+        )
+            .bind(status.to_string())
+            .bind(&reason)
+            .bind(self.id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        self.status = status;
+        self.review_reason = reason;
+
+        Ok(())
+    }
+
+    /// Jobs of `service` submitted for longer than `after` that the watchdog
+    /// has not reported since, oldest first
+    pub async fn list_stale(
+        service: &str,
+        after: Duration,
+        pool: &SqlitePool,
+    ) -> Result<Vec<Job>, sqlx::Error> {
+        let rows = sqlx::query(
+            r#"
+            SELECT * FROM jobs
+            WHERE service = ? AND status = ?
+              AND COALESCE(status_since, created_at) <= datetime('now', ?)
+              AND NOT EXISTS (
+                SELECT 1 FROM job_events e
+                WHERE e.job_id = jobs.id AND e.actor = ?
+                  AND e.created_at >= COALESCE(jobs.status_since, jobs.created_at)
+              )
+            ORDER BY id
+            "#,
+        )
+        .bind(service)
+        .bind(Status::Submitted.to_string())
+        .bind(format!("-{} seconds", after.as_secs()))
+        .bind(WATCHDOG_ACTOR)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows.iter().map(Job::from_row).collect())
+    }
+
+    /// Count a failed download of the results, the getter skips the job
+    /// until `retry_in` has passed
+    pub async fn record_download_failure(
+        &mut self,
+        retry_in: Duration,
+        pool: &SqlitePool,
+    ) -> Result<(), sqlx::Error> {
+        let attempts: u32 = sqlx::query_scalar(
+            "UPDATE jobs SET download_attempts = download_attempts + 1, retry_at = datetime('now', ?) WHERE id = ? RETURNING download_attempts",
+        )
+        .bind(format!("+{} seconds", retry_in.as_secs()))
+        .bind(self.id)
+        .fetch_one(pool)
+        .await?;
+
+        self.download_attempts = attempts;
+
+        Ok(())
+    }
+
+    /// Count a failed upload to the client, the sender leaves the job queued
+    /// until `retry_in` has passed
+    pub async fn record_submit_failure(
+        &mut self,
+        retry_in: Duration,
+        pool: &SqlitePool,
+    ) -> Result<(), sqlx::Error> {
+        let retries: u32 = sqlx::query_scalar(
+            "UPDATE jobs SET retry_count = retry_count + 1, retry_at = datetime('now', ?) WHERE id = ? RETURNING retry_count",
+        )
+        .bind(format!("+{} seconds", retry_in.as_secs()))
+        .bind(self.id)
+        .fetch_one(pool)
+        .await?;
+
+        self.retry_count = retries;
+
+        Ok(())
+    }
+
+    /// Forget the failed downloads once the client answered again
+    pub async fn reset_download_attempts(&mut self, pool: &SqlitePool) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE jobs SET download_attempts = 0, retry_at = NULL WHERE id = ?")
+            .bind(self.id)
+            .execute(pool)
+            .await?;
+
+        self.download_attempts = 0;
+
+        Ok(())
+    }
+
+    /// Remember that the job is to be terminated, it stays so once killed
+    pub async fn request_cancel(&mut self, pool: &SqlitePool) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE jobs SET cancel_requested = 1 WHERE id = ?")
+            .bind(self.id)
+            .execute(pool)
+            .await?;
+
+        self.cancel_requested = true;
+
+        Ok(())
+    }
+
+    /// Remember that the job is to be cancelled, the getter asks the client
+    /// again should it not answer
+    pub async fn request_discard(&mut self, pool: &SqlitePool) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE jobs SET cancel_requested = 1, discard_requested = 1 WHERE id = ?")
+            .bind(self.id)
+            .execute(pool)
+            .await?;
+
+        self.cancel_requested = true;
+        self.discard_requested = true;
+
+        Ok(())
+    }
+
+    /// Cancel a job not sent to its client yet. `false` when the sender
+    /// claimed or sent it meanwhile, it is left as it is then.
+    pub async fn cancel_unsent(&mut self, pool: &SqlitePool) -> Result<bool, sqlx::Error> {
+        let unsent = format!(
+            r#"
+            WHERE id = ? AND status IN (?, ?)
+              AND (claimed_at IS NULL OR claimed_at <= datetime('now', '-{} seconds'))
+            "#,
+            CLAIM_TTL.as_secs()
+        );
+        let mut tx = pool.begin().await?;
+        sqlx::query(&format!(
+            r#"
+            INSERT INTO job_events (job_id, old_status, new_status, reason, actor)
+            SELECT id, status, ?, 'cancelled before it was sent', ? FROM jobs {unsent}
+            "#
+        ))
+        .bind(Status::Cancelled.to_string())
+        .bind(SERVER_ACTOR)
+        .bind(self.id)
+        .bind(Status::Queued.to_string())
+        .bind(Status::Held.to_string())
+        .execute(&mut *tx)
+        .await?;
+        let result = sqlx::query(&format!(
+            "UPDATE jobs SET status = ?, status_since = CURRENT_TIMESTAMP, claimed_at = NULL {unsent}"
+        ))
+        .bind(Status::Cancelled.to_string())
+        .bind(self.id)
+        .bind(Status::Queued.to_string())
+        .bind(Status::Held.to_string())
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+        self.status = Status::Cancelled;
+        Ok(true)
+    }
+
+    /// Remember the expiry notice went out, it is sent once
+    pub async fn mark_expiry_notified(&mut self, pool: &SqlitePool) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE jobs SET expiry_notified = 1 WHERE id = ?")
+            .bind(self.id)
+            .execute(pool)
+            .await?;
 
-        self.id = row.get("id");
-        self.user_id = row.get("user_id");
-        self.service = row.get("service");
-        self.status = Status::from_string(&status);
-        self.loc = PathBuf::from(loc);
-        self.dest_id = row.get("dest_id");
+        self.expiry_notified = true;
 
         Ok(())
     }
 
+    /// Payload ids of `services` that jobs still point at, the jobs killed or
+    /// cleaned don't need their payload anymore
+    pub async fn referenced_dest_ids(
+        services: &[String],
+        pool: &SqlitePool,
+    ) -> Result<HashSet<u32>, sqlx::Error> {
+        let ids: Vec<u32> = sqlx::query_scalar(
+            r#"
+            SELECT DISTINCT dest_id FROM jobs
+            WHERE service IN (SELECT value FROM json_each(?))
+              AND dest_id IS NOT NULL AND dest_id != 0
+              AND status NOT IN ('killed', 'cancelled', 'cleaned')
+            "#,
+        )
+        .bind(serde_json::to_string(services).unwrap_or_default())
+        .fetch_all(pool)
+        .await?;
+
+        Ok(ids.into_iter().collect())
+    }
+
     pub async fn retrieve_by_loc(
         &mut self,
         loc: String,
@@ -103,15 +923,7 @@ impl Job {
             .await?
             .ok_or(sqlx::Error::RowNotFound)?;
 
-        let status: String = row.get("status");
-        let loc: String = row.get("loc");
-
-        self.id = row.get("id");
-        self.user_id = row.get("user_id");
-        self.service = row.get("service");
-        self.status = Status::from_string(&status);
-        self.loc = PathBuf::from(loc);
-        self.dest_id = row.get("dest_id");
+        *self = Job::from_row(&row);
 
         Ok(())
     }
@@ -120,13 +932,17 @@ impl Job {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::models::job_dao::Job;
+    use crate::models::job_dao::{ERROR_BODY_EXCERPT, Job};
+    use crate::models::user_dao::User;
     use sqlx::SqlitePool;
     use tempfile::TempDir;
 
     async fn setup_test_db() -> SqlitePool {
         let pool = SqlitePool::connect(":memory:").await.unwrap();
         create_jobs_table(&pool).await.unwrap();
+        for id in [0, 1, 2, 42] {
+            User::add_if_missing(id, &pool).await.unwrap();
+        }
         pool
     }
 
@@ -160,6 +976,32 @@ mod tests {
 
     // ===== add_to_db tests =====
 
+    #[tokio::test]
+    async fn test_create_jobs_table_registers_users() {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+
+        // Jobs of a database from before the users table
+        sqlx::query(
+            "CREATE TABLE jobs (id INTEGER PRIMARY KEY AUTOINCREMENT, user_id INTEGER NOT NULL, service TEXT NOT NULL, status TEXT NOT NULL, loc TEXT NOT NULL, dest_id INTEGER, created_at DATETIME DEFAULT CURRENT_TIMESTAMP)",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO jobs (user_id, service, status, loc) VALUES (7, 'a', 'completed', ''), (7, 'a', 'queued', ''), (8, 'a', 'queued', '')",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        create_jobs_table(&pool).await.unwrap();
+        let names: Vec<String> = sqlx::query_scalar("SELECT name FROM users ORDER BY id")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(names, ["user7", "user8"]);
+    }
+
     #[tokio::test]
     async fn test_add_to_db() {
         let pool = setup_test_db().await;
@@ -221,6 +1063,42 @@ mod tests {
         assert_eq!(status, Status::Processing);
     }
 
+    #[tokio::test]
+    async fn test_update_status_concurrent() {
+        // Separate connections to a file, as the scheduler tasks and handlers use
+        let tempdir = TempDir::new().unwrap();
+        let db_path = tempdir.path().join("jobs.db");
+        let pool = crate::datasource::db::init_db(db_path.to_str().unwrap()).await;
+        User::add_if_missing(1, &pool).await.unwrap();
+
+        let mut jobs = Vec::new();
+        for _ in 0..16 {
+            let mut job = Job::new("");
+            job.set_user_id(1);
+            job.add_to_db(&pool).await.unwrap();
+            jobs.push(job);
+        }
+
+        let updates = jobs.into_iter().map(|mut job| {
+            let pool = pool.clone();
+            tokio::spawn(async move {
+                for status in [Status::Processing, Status::Submitted, Status::Completed] {
+                    job.update_status(status, &pool).await?;
+                }
+                Ok::<_, sqlx::Error>(())
+            })
+        });
+        for result in futures::future::join_all(updates).await {
+            result.unwrap().unwrap();
+        }
+
+        let events: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM job_events")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(events, 16 * 3);
+    }
+
     #[tokio::test]
     async fn test_update_status_multiple_transitions() {
         let pool = setup_test_db().await;
@@ -242,6 +1120,56 @@ mod tests {
         assert_eq!(job.status, Status::Completed);
     }
 
+    #[tokio::test]
+    async fn test_add_to_db_persists_tenant() {
+        let pool = setup_test_db().await;
+        let tempdir = TempDir::new().unwrap();
+
+        let mut job = Job::new(tempdir.path().to_str().unwrap());
+        job.set_user_id(1);
+        job.set_service("test".to_string());
+        job.set_tenant("lab".to_string(), tempdir.path().to_str().unwrap())
+            .unwrap();
+        job.add_to_db(&pool).await.unwrap();
+
+        let mut retrieved = Job::new("");
+        retrieved.retrieve_id(job.id, &pool).await.unwrap();
+        assert_eq!(retrieved.tenant, "lab");
+        assert_eq!(retrieved.loc, job.loc);
+    }
+
+    #[tokio::test]
+    async fn test_add_to_db_persists_timeout() {
+        let pool = setup_test_db().await;
+        let tempdir = TempDir::new().unwrap();
+
+        let mut job = Job::new(tempdir.path().to_str().unwrap());
+        job.set_user_id(1);
+        job.set_service("test".to_string());
+        job.timeout = Some(1800);
+        job.add_to_db(&pool).await.unwrap();
+
+        let mut retrieved = Job::new("");
+        retrieved.retrieve_id(job.id, &pool).await.unwrap();
+        assert_eq!(retrieved.timeout, Some(1800));
+    }
+
+    #[tokio::test]
+    async fn test_add_to_db_persists_priority() {
+        let pool = setup_test_db().await;
+        let tempdir = TempDir::new().unwrap();
+
+        let mut job = Job::new(tempdir.path().to_str().unwrap());
+        job.set_user_id(1);
+        job.set_service("test".to_string());
+        job.priority = -3;
+        job.add_to_db(&pool).await.unwrap();
+
+        let mut retrieved = Job::new("");
+        retrieved.retrieve_id(job.id, &pool).await.unwrap();
+        assert_eq!(retrieved.priority, -3);
+    }
+
     // ===== update_dest_id tests =====
 
     #[tokio::test]
@@ -286,6 +1214,548 @@ mod tests {
         assert_eq!(job.dest_id, 200);
     }
 
+    // ===== update_checksum tests =====
+
+    #[tokio::test]
+    async fn test_update_checksum() {
+        let pool = setup_test_db().await;
+        let tempdir = TempDir::new().unwrap();
+
+        let mut job = Job::new(tempdir.path().to_str().unwrap());
+        job.set_user_id(1);
+        job.set_service("test".to_string());
+        job.add_to_db(&pool).await.unwrap();
+        assert_eq!(job.checksum, None);
+
+        job.update_checksum("abc123".to_string(), &pool)
+            .await
+            .unwrap();
+        assert_eq!(job.checksum.as_deref(), Some("abc123"));
+
+        let mut retrieved = Job::new("");
+        retrieved.retrieve_id(job.id, &pool).await.unwrap();
+        assert_eq!(retrieved.checksum.as_deref(), Some("abc123"));
+    }
+
+    #[tokio::test]
+    async fn test_update_output_summary() {
+        let pool = setup_test_db().await;
+        let tempdir = TempDir::new().unwrap();
+
+        let mut job = Job::new(tempdir.path().to_str().unwrap());
+        job.set_user_id(1);
+        job.add_to_db(&pool).await.unwrap();
+        assert_eq!(job.archive_size, None);
+
+        let summary = OutputSummary {
+            archive_size: 2048,
+            file_count: 7,
+            exit_code: Some(0),
+        };
+        job.update_output_summary(summary, &pool).await.unwrap();
+
+        let mut retrieved = Job::new("");
+        retrieved.retrieve_id(job.id, &pool).await.unwrap();
+        assert_eq!(retrieved.archive_size, Some(2048));
+        assert_eq!(retrieved.file_count, Some(7));
+        assert_eq!(retrieved.exit_code, Some(0));
+    }
+
+    #[tokio::test]
+    async fn test_update_result_summary() {
+        let pool = setup_test_db().await;
+        let tempdir = TempDir::new().unwrap();
+
+        let mut job = Job::new(tempdir.path().to_str().unwrap());
+        job.set_user_id(1);
+        job.add_to_db(&pool).await.unwrap();
+
+        let mut retrieved = Job::new("");
+        retrieved.retrieve_id(job.id, &pool).await.unwrap();
+        assert_eq!(retrieved.result_summary, None);
+
+        let summary = ResultSummary {
+            status: Some("converged".to_string()),
+            metrics: [("score".to_string(), serde_json::json!(-42.5))].into(),
+            warnings: Vec::new(),
+        };
+        job.update_result_summary(summary.clone(), &pool)
+            .await
+            .unwrap();
+        retrieved.retrieve_id(job.id, &pool).await.unwrap();
+        assert_eq!(retrieved.result_summary, Some(summary));
+    }
+
+    #[tokio::test]
+    async fn test_update_last_error() {
+        let pool = setup_test_db().await;
+        let tempdir = TempDir::new().unwrap();
+
+        let mut job = Job::new(tempdir.path().to_str().unwrap());
+        job.set_user_id(1);
+        job.add_to_db(&pool).await.unwrap();
+
+        let mut retrieved = Job::new("");
+        retrieved.retrieve_id(job.id, &pool).await.unwrap();
+        assert_eq!(retrieved.last_error, None);
+
+        let error = JobError::new("sender", "rejected", "Client rejected the upload")
+            .with_response(400, Some(&"x".repeat(ERROR_BODY_EXCERPT + 10)));
+        assert_eq!(error.body.as_ref().unwrap().len(), ERROR_BODY_EXCERPT);
+        job.update_last_error(error.clone(), &pool).await.unwrap();
+        retrieved.retrieve_id(job.id, &pool).await.unwrap();
+        assert_eq!(retrieved.last_error, Some(error));
+    }
+
+    #[test]
+    fn test_job_error_excerpt_on_char_boundary() {
+        let body = "é".repeat(ERROR_BODY_EXCERPT);
+        let error = JobError::new("getter", "rejected", "").with_response(500, Some(&body));
+        let excerpt = error.body.unwrap();
+        assert!(excerpt.len() <= ERROR_BODY_EXCERPT);
+        assert!(excerpt.chars().all(|c| c == 'é'));
+    }
+
+    #[tokio::test]
+    async fn test_update_metadata() {
+        let pool = setup_test_db().await;
+        let tempdir = TempDir::new().unwrap();
+
+        let mut job = Job::new(tempdir.path().to_str().unwrap());
+        job.set_user_id(1);
+        job.metadata.description = Some("first try".to_string());
+        job.add_to_db(&pool).await.unwrap();
+
+        let mut retrieved = Job::new("");
+        retrieved.retrieve_id(job.id, &pool).await.unwrap();
+        assert_eq!(retrieved.metadata.description.as_deref(), Some("first try"));
+        assert!(retrieved.metadata.tags.is_empty());
+
+        let metadata = JobMetadata {
+            tags: vec!["docking".to_string(), "batch-2".to_string()],
+            description: None,
+            callback_url: Some("https://example.com/hook".to_string()),
+            notification_email: Some("alice@example.com".to_string()),
+        };
+        job.update_metadata(metadata.clone(), &pool).await.unwrap();
+
+        retrieved.retrieve_id(job.id, &pool).await.unwrap();
+        assert_eq!(retrieved.metadata, metadata);
+    }
+
+    #[tokio::test]
+    async fn test_record_inputs() {
+        let pool = setup_test_db().await;
+
+        let mut job = Job::new("");
+        job.set_user_id(1);
+        job.add_to_db(&pool).await.unwrap();
+        assert_eq!(Job::retrieve_inputs(job.id, &pool).await.unwrap(), None);
+
+        let inputs = vec![ManifestEntry {
+            path: "run.sh".to_string(),
+            size: 4,
+            sha256: "aa".to_string(),
+        }];
+        job.record_inputs(&inputs, &pool).await.unwrap();
+        assert_eq!(
+            Job::retrieve_inputs(job.id, &pool).await.unwrap(),
+            Some(inputs)
+        );
+
+        assert!(matches!(
+            Job::retrieve_inputs(9999, &pool).await,
+            Err(sqlx::Error::RowNotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_list_local_completed_and_remote_key() {
+        let pool = setup_test_db().await;
+
+        let mut ids = Vec::new();
+        for status in [Status::Completed, Status::Completed, Status::Running] {
+            let mut job = Job::new("");
+            job.set_user_id(1);
+            job.add_to_db(&pool).await.unwrap();
+            job.update_status(status, &pool).await.unwrap();
+            ids.push(job.id);
+        }
+
+        let mut tiered = Job::new("");
+        tiered.retrieve_id(ids[0], &pool).await.unwrap();
+        tiered
+            .update_remote_key("default/abc.zip".to_string(), &pool)
+            .await
+            .unwrap();
+
+        let local = Job::list_local_completed(&pool).await.unwrap();
+        assert_eq!(local.iter().map(|j| j.id).collect::<Vec<_>>(), vec![ids[1]]);
+
+        let mut retrieved = Job::new("");
+        retrieved.retrieve_id(ids[0], &pool).await.unwrap();
+        assert_eq!(retrieved.remote_key.as_deref(), Some("default/abc.zip"));
+    }
+
+    #[tokio::test]
+    async fn test_list_packable() {
+        let pool = setup_test_db().await;
+
+        let mut ids = Vec::new();
+        for status in [
+            Status::Submitted,
+            Status::Completed,
+            Status::Queued,
+            Status::Completed,
+        ] {
+            let mut job = Job::new("");
+            job.set_user_id(1);
+            job.add_to_db(&pool).await.unwrap();
+            job.update_status(status, &pool).await.unwrap();
+            ids.push(job);
+        }
+        ids[3]
+            .update_remote_key("default/abc.zip".to_string(), &pool)
+            .await
+            .unwrap();
+
+        let packable = Job::list_packable(&pool).await.unwrap();
+        assert_eq!(
+            packable.iter().map(|j| j.id).collect::<Vec<_>>(),
+            vec![ids[0].id, ids[1].id]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_referenced_dest_ids() {
+        let pool = setup_test_db().await;
+
+        for (service, dest_id, status) in [
+            ("a", 1, Status::Running),
+            ("a", 2, Status::Killed),
+            ("a", 3, Status::Cleaned),
+            ("a", 4, Status::Completed),
+            ("b", 5, Status::Submitted),
+            ("c", 6, Status::Submitted),
+            ("a", 0, Status::Queued),
+        ] {
+            let mut job = Job::new("");
+            job.set_user_id(1);
+            job.set_service(service.to_string());
+            job.add_to_db(&pool).await.unwrap();
+            job.update_dest_id(dest_id, &pool).await.unwrap();
+            job.update_status(status, &pool).await.unwrap();
+        }
+
+        let services = ["a".to_string(), "b".to_string()];
+        let referenced = Job::referenced_dest_ids(&services, &pool).await.unwrap();
+        assert_eq!(referenced, HashSet::from([1, 4, 5]));
+    }
+
+    #[tokio::test]
+    async fn test_find_recent_duplicate() {
+        let pool = setup_test_db().await;
+        let window = Duration::from_secs(600);
+
+        let new_job = |user_id: i32, hash: &str| {
+            let mut job = Job::new("");
+            job.set_user_id(user_id);
+            job.set_service("test".to_string());
+            job.input_hash = Some(hash.to_string());
+            job
+        };
+
+        let mut original = new_job(1, "abc");
+        original.add_to_db(&pool).await.unwrap();
+        original.update_status(Status::Queued, &pool).await.unwrap();
+
+        let duplicate = new_job(1, "abc");
+        let found = duplicate
+            .find_recent_duplicate(window, &pool)
+            .await
+            .unwrap();
+        assert_eq!(found.map(|j| j.id), Some(original.id));
+
+        // Other inputs or another user are not duplicates
+        for job in [new_job(1, "def"), new_job(2, "abc")] {
+            assert!(
+                job.find_recent_duplicate(window, &pool)
+                    .await
+                    .unwrap()
+                    .is_none()
+            );
+        }
+
+        // Failed jobs may be resubmitted
+        original.update_status(Status::Failed, &pool).await.unwrap();
+        assert!(
+            duplicate
+                .find_recent_duplicate(window, &pool)
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        // So can jobs older than the window
+        original.update_status(Status::Queued, &pool).await.unwrap();
+        sqlx::query("UPDATE jobs SET created_at = datetime('now', '-1 hour') WHERE id = ?")
+            .bind(original.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert!(
+            duplicate
+                .find_recent_duplicate(window, &pool)
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_list_by_group() {
+        let pool = setup_test_db().await;
+
+        for (user_id, group) in [
+            (1, Some("batch-1")),
+            (1, None),
+            (1, Some("batch-1")),
+            (1, Some("batch-2")),
+            (2, Some("batch-1")),
+        ] {
+            let mut job = Job::new("");
+            job.set_user_id(user_id);
+            job.group_id = group.map(str::to_string);
+            job.add_to_db(&pool).await.unwrap();
+        }
+
+        let jobs = Job::list_by_group("batch-1", 1, &pool).await.unwrap();
+        assert_eq!(jobs.iter().map(|j| j.id).collect::<Vec<_>>(), vec![1, 3]);
+        assert_eq!(jobs[0].group_id.as_deref(), Some("batch-1"));
+        let jobs = Job::list_by_group("batch-1", 2, &pool).await.unwrap();
+        assert_eq!(jobs.iter().map(|j| j.id).collect::<Vec<_>>(), vec![5]);
+        assert!(
+            Job::list_by_group("nope", 1, &pool)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hold_and_release() {
+        let pool = setup_test_db().await;
+
+        let mut jobs = Vec::new();
+        for (user_id, status) in [
+            (1, Status::Queued),
+            (1, Status::Running),
+            (2, Status::Queued),
+        ] {
+            let mut job = Job::new("");
+            job.set_user_id(user_id);
+            job.add_to_db(&pool).await.unwrap();
+            job.update_status(status, &pool).await.unwrap();
+            jobs.push(job);
+        }
+
+        // Only the queued jobs of the user are held
+        assert_eq!(Job::hold_queued(1, &pool).await.unwrap(), 1);
+        let status = |id| {
+            let pool = pool.clone();
+            async move {
+                let mut job = Job::new("");
+                job.retrieve_id(id, &pool).await.unwrap();
+                job.status
+            }
+        };
+        assert_eq!(status(jobs[0].id).await, Status::Held);
+        assert_eq!(status(jobs[1].id).await, Status::Running);
+        assert_eq!(status(jobs[2].id).await, Status::Queued);
+
+        assert_eq!(Job::release_held(1, &pool).await.unwrap(), 1);
+        assert_eq!(status(jobs[0].id).await, Status::Queued);
+        assert_eq!(Job::release_held(1, &pool).await.unwrap(), 0);
+
+        let reasons: Vec<_> = JobEvent::history(jobs[0].id, &pool)
+            .await
+            .unwrap()
+            .into_iter()
+            .filter_map(|c| c.event.reason)
+            .collect();
+        assert_eq!(reasons, ["user suspended", "user restored"]);
+        assert_eq!(JobEvent::history(jobs[1].id, &pool).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_review() {
+        let pool = setup_test_db().await;
+
+        let mut held = Job::new("");
+        held.set_user_id(1);
+        held.set_service("a".to_string());
+        held.add_to_db(&pool).await.unwrap();
+        held.update_review(
+            Status::Held,
+            Some("curl in run.sh".to_string()),
+            SERVER_ACTOR,
+            "held for review",
+            &pool,
+        )
+        .await
+        .unwrap();
+
+        let mut queued = Job::new("");
+        queued.set_user_id(1);
+        queued.set_service("a".to_string());
+        queued.add_to_db(&pool).await.unwrap();
+        queued.update_status(Status::Queued, &pool).await.unwrap();
+
+        assert_eq!(Job::count_waiting(1, "a", &pool).await.unwrap(), 2);
+        assert_eq!(Job::count_waiting(1, "b", &pool).await.unwrap(), 0);
+
+        // Restoring the user does not bypass the review
+        assert_eq!(Job::release_held(1, &pool).await.unwrap(), 0);
+
+        let in_review = Job::list_in_review(&pool).await.unwrap();
+        assert_eq!(in_review.len(), 1);
+        assert_eq!(in_review[0].id, held.id);
+        assert_eq!(
+            in_review[0].review_reason.as_deref(),
+            Some("curl in run.sh")
+        );
+
+        held.update_review(Status::Queued, None, ADMIN_ACTOR, "approved", &pool)
+            .await
+            .unwrap();
+        assert!(Job::list_in_review(&pool).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_queue_watermarks() {
+        let pool = setup_test_db().await;
+        assert_eq!(Job::queue_watermarks(&pool).await.unwrap(), (0, None));
+
+        for status in [Status::Queued, Status::Queued, Status::Submitted] {
+            let mut job = Job::new("");
+            job.set_user_id(1);
+            job.set_service("a".to_string());
+            job.add_to_db(&pool).await.unwrap();
+            job.update_status(status, &pool).await.unwrap();
+        }
+        sqlx::query("UPDATE jobs SET status_since = datetime('now', '-2 hours') WHERE id = 1")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE jobs SET status_since = datetime('now', '-5 hours') WHERE id = 3")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let (depth, oldest) = Job::queue_watermarks(&pool).await.unwrap();
+        assert_eq!(depth, 2);
+        let oldest = oldest.unwrap().as_secs();
+        assert!((7200..7260).contains(&oldest), "{oldest}");
+    }
+
+    #[tokio::test]
+    async fn test_queue_position() {
+        let pool = setup_test_db().await;
+
+        let mut jobs = Vec::new();
+        for (status, priority) in [
+            (Status::Running, 0),
+            (Status::Submitted, 0),
+            (Status::Queued, 0),
+            (Status::Queued, 5),
+            (Status::Queued, 0),
+            (Status::Completed, 0),
+        ] {
+            let mut job = Job::new("");
+            job.set_user_id(1);
+            job.set_service("a".to_string());
+            job.priority = priority;
+            job.add_to_db(&pool).await.unwrap();
+            job.update_status(status, &pool).await.unwrap();
+            jobs.push(job);
+        }
+        let mut other = Job::new("");
+        other.set_user_id(1);
+        other.set_service("b".to_string());
+        other.add_to_db(&pool).await.unwrap();
+        other.update_status(Status::Running, &pool).await.unwrap();
+
+        // The prioritized job goes first, then the oldest
+        assert_eq!(jobs[3].queue_position(&pool).await.unwrap(), (0, 2));
+        assert_eq!(jobs[2].queue_position(&pool).await.unwrap(), (1, 2));
+        assert_eq!(jobs[4].queue_position(&pool).await.unwrap(), (2, 2));
+        assert_eq!(other.queue_position(&pool).await.unwrap(), (0, 1));
+
+        sqlx::query("UPDATE jobs SET status_since = datetime('now', '-2 hours') WHERE id = ?")
+            .bind(jobs[0].id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        let age = jobs[0].status_age(&pool).await.unwrap().as_secs();
+        assert!((7200..7260).contains(&age), "{age}");
+        assert!(jobs[1].status_age(&pool).await.unwrap().as_secs() < 60);
+    }
+
+    #[tokio::test]
+    async fn test_list_stale() {
+        let pool = setup_test_db().await;
+        let after = Duration::from_secs(3600);
+
+        let mut ids = Vec::new();
+        for _ in 0..2 {
+            let mut job = Job::new("");
+            job.set_user_id(1);
+            job.set_service("a".to_string());
+            job.add_to_db(&pool).await.unwrap();
+            job.update_status(Status::Submitted, &pool).await.unwrap();
+            ids.push(job.id);
+        }
+        assert!(Job::list_stale("a", after, &pool).await.unwrap().is_empty());
+
+        sqlx::query("UPDATE jobs SET status_since = datetime('now', '-2 hours')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let stale = Job::list_stale("a", after, &pool).await.unwrap();
+        assert_eq!(stale.iter().map(|j| j.id).collect::<Vec<_>>(), ids);
+        assert!(Job::list_stale("b", after, &pool).await.unwrap().is_empty());
+
+        // Reported once only
+        JobEvent::new(ids[0], Status::Submitted, Status::Submitted, WATCHDOG_ACTOR)
+            .add_to_db(&pool)
+            .await
+            .unwrap();
+        let stale = Job::list_stale("a", after, &pool).await.unwrap();
+        assert_eq!(stale.len(), 1);
+        assert_eq!(stale[0].id, ids[1]);
+    }
+
+    #[tokio::test]
+    async fn test_create_jobs_table_upgrades_old_schema() {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        sqlx::query(
+            "CREATE TABLE jobs (id INTEGER PRIMARY KEY AUTOINCREMENT, user_id INTEGER NOT NULL, service TEXT NOT NULL, status TEXT NOT NULL, loc TEXT NOT NULL, dest_id INTEGER, created_at DATETIME DEFAULT CURRENT_TIMESTAMP)",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        create_jobs_table(&pool).await.unwrap();
+        for id in [0, 1, 2, 42] {
+            User::add_if_missing(id, &pool).await.unwrap();
+        }
+
+        let result = sqlx::query("SELECT checksum, tenant FROM jobs")
+            .fetch_all(&pool)
+            .await;
+        assert!(result.is_ok());
+    }
+
     // ===== retrieve_id tests =====
 
     #[tokio::test]
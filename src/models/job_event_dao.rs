@@ -0,0 +1,83 @@
+use crate::models::status_dto::Status;
+use utoipa::ToSchema;
+
+/// Actor recorded for the routine transitions of a job, see `Job::update_status`
+pub const SERVER_ACTOR: &str = "server";
+
+/// Actor recorded for the transitions made through the admin endpoints
+pub const ADMIN_ACTOR: &str = "admin";
+
+/// Actor recorded for corrections made by `job-orchestrator reconcile`
+pub const RECONCILE_ACTOR: &str = "reconcile";
+
+/// Actor recorded when the sender gives up on the upload of a job
+pub const SENDER_ACTOR: &str = "sender";
+
+/// Actor recorded for the statuses the getter collects from the clients, and
+/// when it gives up on the results of a job
+pub const GETTER_ACTOR: &str = "getter";
+
+/// Actor recorded when the watchdog finds a job stuck in `submitted`
+pub const WATCHDOG_ACTOR: &str = "watchdog";
+
+/// Actor recorded when the cleaner retires a job, the reason tells where its files went
+pub const CLEANER_ACTOR: &str = "cleaner";
+
+/// A status transition of a job, as stored in the `job_events` table
+#[derive(serde::Serialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct JobEvent {
+    pub id: u32,
+    pub job_id: u32,
+    pub old_status: Status,
+    pub new_status: Status,
+    pub reason: Option<String>,
+    pub actor: String,
+    /// UTC time of the transition, set by the database
+    pub created_at: Option<String>,
+}
+
+impl JobEvent {
+    pub fn new(job_id: u32, old_status: Status, new_status: Status, actor: &str) -> JobEvent {
+        JobEvent {
+            id: 0,
+            job_id,
+            old_status,
+            new_status,
+            reason: None,
+            actor: actor.to_string(),
+            created_at: None,
+        }
+    }
+
+    pub fn with_reason(mut self, reason: &str) -> JobEvent {
+        self.reason = Some(reason.to_string());
+        self
+    }
+}
+
+/// A transition in the history of a job, what `GET /jobs/{id}/history` lists
+#[derive(serde::Serialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct StatusChange {
+    #[serde(flatten)]
+    pub event: JobEvent,
+    /// Seconds the job spent in `old_status`, since the previous transition
+    /// or since it was created for the first one
+    pub secs_in_old_status: Option<u64>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_new() {
+        let event = JobEvent::new(1, Status::Running, Status::Failed, ADMIN_ACTOR);
+        assert_eq!(event.id, 0);
+        assert_eq!(event.job_id, 1);
+        assert_eq!(event.reason, None);
+        assert_eq!(event.actor, ADMIN_ACTOR);
+
+        let event = event.with_reason("client lost");
+        assert_eq!(event.reason.as_deref(), Some("client lost"));
+    }
+}
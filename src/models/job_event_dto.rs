@@ -0,0 +1,211 @@
+use crate::models::job_event_dao::{JobEvent, StatusChange};
+use crate::models::status_dto::Status;
+use sqlx::sqlite::SqliteRow;
+use sqlx::{Executor, Row, Sqlite, SqliteConnection, SqlitePool};
+
+pub async fn create_job_events_table(conn: &mut SqliteConnection) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS job_events (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            job_id INTEGER NOT NULL REFERENCES jobs(id),
+            old_status TEXT NOT NULL,
+            new_status TEXT NOT NULL,
+            reason TEXT,
+            actor TEXT NOT NULL,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )
+    "#,
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+impl JobEvent {
+    fn from_row(row: &SqliteRow) -> JobEvent {
+        let old_status: String = row.get("old_status");
+        let new_status: String = row.get("new_status");
+        JobEvent {
+            id: row.get("id"),
+            job_id: row.get("job_id"),
+            old_status: Status::from_string(&old_status),
+            new_status: Status::from_string(&new_status),
+            reason: row.get("reason"),
+            actor: row.get("actor"),
+            created_at: row.get("created_at"),
+        }
+    }
+
+    pub async fn add_to_db<'e, E>(&mut self, executor: E) -> Result<(), sqlx::Error>
+    where
+        E: Executor<'e, Database = Sqlite>,
+    {
+        let result = sqlx::query(
+            "INSERT INTO job_events (job_id, old_status, new_status, reason, actor) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(self.job_id)
+        .bind(self.old_status.to_string())
+        .bind(self.new_status.to_string())
+        .bind(&self.reason)
+        .bind(&self.actor)
+        .execute(executor)
+        .await?;
+
+        self.id = result.last_insert_rowid() as u32;
+
+        Ok(())
+    }
+
+    /// Transitions of a job, oldest first, with the time it spent in each
+    /// status
+    pub async fn history(job_id: u32, pool: &SqlitePool) -> Result<Vec<StatusChange>, sqlx::Error> {
+        let rows = sqlx::query(
+            r#"
+            SELECT e.*,
+                   CAST(strftime('%s', e.created_at) AS INTEGER)
+                   - CAST(strftime('%s', COALESCE(
+                       LAG(e.created_at) OVER (ORDER BY e.id), j.created_at
+                     )) AS INTEGER) AS secs_in_old_status
+            FROM job_events e JOIN jobs j ON j.id = e.job_id
+            WHERE e.job_id = ?
+            ORDER BY e.id
+            "#,
+        )
+        .bind(job_id)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| StatusChange {
+                event: JobEvent::from_row(row),
+                secs_in_old_status: row
+                    .get::<Option<i64>, _>("secs_in_old_status")
+                    .map(|s| s.max(0) as u64),
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::job_dao::Job;
+    use crate::models::job_dto::create_jobs_table;
+    use crate::models::job_event_dao::{ADMIN_ACTOR, CLEANER_ACTOR, SERVER_ACTOR};
+    use crate::models::user_dao::User;
+
+    #[tokio::test]
+    async fn test_record() {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        create_jobs_table(&pool).await.unwrap();
+        User::add_if_missing(0, &pool).await.unwrap();
+
+        let mut job = Job::new("");
+        job.add_to_db(&pool).await.unwrap();
+
+        let mut event = JobEvent::new(job.id, Status::Running, Status::Failed, ADMIN_ACTOR)
+            .with_reason("client lost");
+        event.add_to_db(&pool).await.unwrap();
+        assert!(event.id > 0);
+
+        let row = sqlx::query("SELECT * FROM job_events WHERE job_id = ?")
+            .bind(job.id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(row.get::<String, _>("old_status"), "running");
+        assert_eq!(row.get::<String, _>("new_status"), "failed");
+        assert_eq!(row.get::<String, _>("reason"), "client lost");
+        assert_eq!(row.get::<String, _>("actor"), "admin");
+    }
+
+    #[tokio::test]
+    async fn test_history_events() {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        create_jobs_table(&pool).await.unwrap();
+        User::add_if_missing(0, &pool).await.unwrap();
+
+        let mut job = Job::new("");
+        job.add_to_db(&pool).await.unwrap();
+        JobEvent::new(job.id, Status::Running, Status::Completed, ADMIN_ACTOR)
+            .add_to_db(&pool)
+            .await
+            .unwrap();
+        JobEvent::new(job.id, Status::Completed, Status::Cleaned, CLEANER_ACTOR)
+            .with_reason("results deleted")
+            .add_to_db(&pool)
+            .await
+            .unwrap();
+
+        let events: Vec<_> = JobEvent::history(job.id, &pool)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|c| c.event)
+            .collect();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].new_status, Status::Completed);
+        assert_eq!(events[1].old_status, Status::Completed);
+        assert_eq!(events[1].new_status, Status::Cleaned);
+        assert_eq!(events[1].actor, "cleaner");
+        assert_eq!(events[1].reason.as_deref(), Some("results deleted"));
+        assert!(events[1].created_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_history() {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        create_jobs_table(&pool).await.unwrap();
+        User::add_if_missing(0, &pool).await.unwrap();
+
+        let mut job = Job::new("");
+        job.add_to_db(&pool).await.unwrap();
+        for status in [Status::Queued, Status::Submitted, Status::Submitted] {
+            job.update_status(status, &pool).await.unwrap();
+        }
+        job.update_status_by(Status::Failed, ADMIN_ACTOR, Some("client lost"), &pool)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE jobs SET created_at = datetime('now', '-1 hour')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query(
+            "UPDATE job_events SET created_at = datetime('now', '-10 minutes') WHERE id = 1",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        // Setting the same status again is no transition
+        let history = JobEvent::history(job.id, &pool).await.unwrap();
+        let transitions: Vec<_> = history
+            .iter()
+            .map(|c| (c.event.new_status, c.event.actor.as_str()))
+            .collect();
+        assert_eq!(
+            transitions,
+            [
+                (Status::Queued, SERVER_ACTOR),
+                (Status::Submitted, SERVER_ACTOR),
+                (Status::Failed, ADMIN_ACTOR),
+            ]
+        );
+        assert_eq!(history[0].secs_in_old_status, Some(3000));
+        let queued = history[1].secs_in_old_status.unwrap();
+        assert!((600..660).contains(&queued), "{queued}");
+        assert_eq!(history[2].event.reason.as_deref(), Some("client lost"));
+        assert!(JobEvent::history(9999, &pool).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_record_unknown_job() {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        create_jobs_table(&pool).await.unwrap();
+
+        let mut event = JobEvent::new(42, Status::Queued, Status::Failed, ADMIN_ACTOR);
+        assert!(event.add_to_db(&pool).await.is_err());
+    }
+}
@@ -0,0 +1,38 @@
+use utoipa::ToSchema;
+
+/// A comment an operator attached to a job, as stored in the `job_notes` table
+#[derive(serde::Serialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct JobNote {
+    pub id: u32,
+    pub job_id: u32,
+    pub author: String,
+    pub text: String,
+    /// UTC time the note was added, set by the database
+    pub created_at: Option<String>,
+}
+
+impl JobNote {
+    pub fn new(job_id: u32, author: &str, text: &str) -> JobNote {
+        JobNote {
+            id: 0,
+            job_id,
+            author: author.to_string(),
+            text: text.to_string(),
+            created_at: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_new() {
+        let note = JobNote::new(1, "alice", "upstream service was down, requeued manually");
+        assert_eq!(note.id, 0);
+        assert_eq!(note.job_id, 1);
+        assert_eq!(note.author, "alice");
+        assert_eq!(note.created_at, None);
+    }
+}
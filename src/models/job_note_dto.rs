@@ -0,0 +1,109 @@
+use crate::models::job_note_dao::JobNote;
+use sqlx::sqlite::SqliteRow;
+use sqlx::{Row, SqliteConnection, SqlitePool};
+
+pub async fn create_job_notes_table(conn: &mut SqliteConnection) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS job_notes (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            job_id INTEGER NOT NULL REFERENCES jobs(id),
+            author TEXT NOT NULL,
+            text TEXT NOT NULL,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )
+    "#,
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+impl JobNote {
+    fn from_row(row: &SqliteRow) -> JobNote {
+        JobNote {
+            id: row.get("id"),
+            job_id: row.get("job_id"),
+            author: row.get("author"),
+            text: row.get("text"),
+            created_at: row.get("created_at"),
+        }
+    }
+
+    /// Notes are append-only, there is no update or delete
+    pub async fn add_to_db(&mut self, pool: &SqlitePool) -> Result<(), sqlx::Error> {
+        let row = sqlx::query(
+            "INSERT INTO job_notes (job_id, author, text) VALUES (?, ?, ?) RETURNING id, created_at",
+        )
+        .bind(self.job_id)
+        .bind(&self.author)
+        .bind(&self.text)
+        .fetch_one(pool)
+        .await?;
+
+        self.id = row.get("id");
+        self.created_at = row.get("created_at");
+
+        Ok(())
+    }
+
+    /// Notes of a job, oldest first
+    pub async fn list_for_job(job_id: u32, pool: &SqlitePool) -> Result<Vec<JobNote>, sqlx::Error> {
+        let rows = sqlx::query("SELECT * FROM job_notes WHERE job_id = ? ORDER BY id")
+            .bind(job_id)
+            .fetch_all(pool)
+            .await?;
+
+        Ok(rows.iter().map(JobNote::from_row).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::job_dao::Job;
+    use crate::models::job_dto::create_jobs_table;
+    use crate::models::user_dao::User;
+
+    async fn setup_test_db() -> SqlitePool {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        create_jobs_table(&pool).await.unwrap();
+        User::add_if_missing(0, &pool).await.unwrap();
+        pool
+    }
+
+    #[tokio::test]
+    async fn test_add_and_list() {
+        let pool = setup_test_db().await;
+        let mut job = Job::new("");
+        job.add_to_db(&pool).await.unwrap();
+        let mut other = Job::new("");
+        other.add_to_db(&pool).await.unwrap();
+
+        let mut first = JobNote::new(job.id, "alice", "upstream service was down");
+        first.add_to_db(&pool).await.unwrap();
+        assert!(first.id > 0);
+        assert!(first.created_at.is_some());
+        JobNote::new(job.id, "bob", "requeued manually")
+            .add_to_db(&pool)
+            .await
+            .unwrap();
+        JobNote::new(other.id, "bob", "unrelated")
+            .add_to_db(&pool)
+            .await
+            .unwrap();
+
+        let notes = JobNote::list_for_job(job.id, &pool).await.unwrap();
+        assert_eq!(notes.len(), 2);
+        assert_eq!(notes[0], first);
+        assert_eq!(notes[1].author, "bob");
+        assert!(JobNote::list_for_job(9999, &pool).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_add_unknown_job() {
+        let pool = setup_test_db().await;
+        let mut note = JobNote::new(42, "alice", "nothing to see");
+        assert!(note.add_to_db(&pool).await.is_err());
+    }
+}
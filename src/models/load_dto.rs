@@ -0,0 +1,46 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use utoipa::ToSchema;
+
+/// What a client reports under `GET /load`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct LoadReport {
+    /// CPU usage in percent
+    pub cpu: f32,
+    /// Bytes available to the client under its data path, `None` when unknown
+    pub disk_free: Option<u64>,
+    /// Submissions refused since the client started, by reason
+    #[serde(default)]
+    pub rejected_submissions: BTreeMap<String, u64>,
+}
+
+impl LoadReport {
+    /// Whether a payload of `size` bytes fits, an unknown free space never
+    /// holds a job back
+    pub fn fits(&self, size: u64) -> bool {
+        self.disk_free.is_none_or(|free| size <= free)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fits() {
+        let report = LoadReport {
+            cpu: 10.0,
+            disk_free: Some(100),
+            rejected_submissions: BTreeMap::new(),
+        };
+        assert!(report.fits(100));
+        assert!(!report.fits(101));
+
+        let unknown = LoadReport {
+            cpu: 10.0,
+            disk_free: None,
+            rejected_submissions: BTreeMap::new(),
+        };
+        assert!(unknown.fits(u64::MAX));
+    }
+}
@@ -0,0 +1,127 @@
+use crate::utils::io::file_sha256;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use utoipa::ToSchema;
+use walkdir::WalkDir;
+
+/// Multipart part listing the files of a submission, sent before them
+pub const MANIFEST_FIELD: &str = "manifest";
+
+/// A file the orchestrator sends, as it was on its disk
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ManifestEntry {
+    /// Path relative to the job directory, also the name of its multipart part
+    pub path: String,
+    pub size: u64,
+    /// Hex SHA-256 of the content
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FileCheck {
+    Ok,
+    Missing,
+    SizeMismatch,
+    ChecksumMismatch,
+}
+
+/// How one file of the manifest arrived on the client
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct FileReport {
+    pub path: String,
+    pub check: FileCheck,
+    /// Bytes received, `None` when the file never arrived
+    pub received: Option<u64>,
+}
+
+/// Compare the manifest with the size and checksum of the files `received`,
+/// keyed by part name
+pub fn verify_manifest(
+    manifest: &[ManifestEntry],
+    received: &HashMap<String, (u64, String)>,
+) -> Vec<FileReport> {
+    manifest
+        .iter()
+        .map(|entry| {
+            let got = received.get(&entry.path);
+            let check = match got {
+                None => FileCheck::Missing,
+                Some((size, _)) if *size != entry.size => FileCheck::SizeMismatch,
+                Some((_, sha256)) if !sha256.eq_ignore_ascii_case(&entry.sha256) => {
+                    FileCheck::ChecksumMismatch
+                }
+                Some(_) => FileCheck::Ok,
+            };
+            FileReport {
+                path: entry.path.clone(),
+                check,
+                received: got.map(|(size, _)| *size),
+            }
+        })
+        .collect()
+}
+
+/// Size and checksum of every file under `dir`, by path relative to it
+pub fn dir_manifest(dir: &Path) -> io::Result<Vec<ManifestEntry>> {
+    let mut entries = Vec::new();
+    for entry in WalkDir::new(dir).sort_by_file_name() {
+        let entry = entry.map_err(io::Error::other)?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let relative = entry.path().strip_prefix(dir).unwrap_or(entry.path());
+        entries.push(ManifestEntry {
+            path: relative.to_string_lossy().to_string(),
+            size: entry.metadata().map_err(io::Error::other)?.len(),
+            sha256: file_sha256(entry.path())?,
+        });
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str, size: u64, sha256: &str) -> ManifestEntry {
+        ManifestEntry {
+            path: path.to_string(),
+            size,
+            sha256: sha256.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_verify_manifest() {
+        let manifest = vec![
+            entry("run.sh", 4, "aa"),
+            entry("inputs/a.pdb", 10, "bb"),
+            entry("inputs/b.pdb", 10, "cc"),
+            entry("inputs/c.pdb", 10, "dd"),
+        ];
+        let received = HashMap::from([
+            ("run.sh".to_string(), (4, "AA".to_string())),
+            ("inputs/a.pdb".to_string(), (7, "bb".to_string())),
+            ("inputs/b.pdb".to_string(), (10, "ff".to_string())),
+            ("extra.txt".to_string(), (1, "ee".to_string())),
+        ]);
+
+        let report = |path: &str, check, received| FileReport {
+            path: path.to_string(),
+            check,
+            received,
+        };
+        assert_eq!(
+            verify_manifest(&manifest, &received),
+            vec![
+                report("run.sh", FileCheck::Ok, Some(4)),
+                report("inputs/a.pdb", FileCheck::SizeMismatch, Some(7)),
+                report("inputs/b.pdb", FileCheck::ChecksumMismatch, Some(10)),
+                report("inputs/c.pdb", FileCheck::Missing, None),
+            ]
+        );
+    }
+}
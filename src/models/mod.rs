@@ -1,10 +1,34 @@
 pub mod status_body;
+pub mod capabilities_dto;
+pub mod error_body;
+pub mod download_dao;
+pub mod download_dto;
 pub mod health_dto;
 pub mod job_dao;
 pub mod job_dto;
+pub mod job_event_dao;
+pub mod job_event_dto;
+pub mod job_note_dao;
+pub mod job_note_dto;
+pub mod load_dto;
+pub mod manifest_dto;
 pub mod payload_dao;
 pub mod payload_dto;
 pub mod ping_dto;
 pub mod queue_dao;
 pub mod queue_dto;
+pub mod quota_dao;
+pub mod quota_dto;
+pub mod run_history_dao;
+pub mod run_history_dto;
+pub mod service_probe_dao;
+pub mod service_probe_dto;
+pub mod share_dao;
+pub mod share_dto;
 pub mod status_dto;
+pub mod task_run_dao;
+pub mod task_run_dto;
+pub mod user_dao;
+pub mod user_dto;
+pub mod webhook_dao;
+pub mod webhook_dto;
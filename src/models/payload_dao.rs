@@ -1,27 +1,204 @@
+use crate::config::loader::{DEFAULT_TENANT, UploadChecks, tenant_data_path};
+use crate::models::manifest_dto::{
+    FileCheck, FileReport, ManifestEntry, dir_manifest, verify_manifest,
+};
 use crate::models::status_dto::Status;
 use crate::services::client::ClientError;
 use crate::utils;
+use crate::utils::quarantine::{self, QUARANTINE_DIR, QuarantineError};
+use crate::utils::result_summary::ResultSummary;
+use crate::utils::sandbox::{PinnedBinary, SandboxProfile, pinned_path, shipped_executables};
 use crate::utils::sys::is_pid_running;
+use axum::http::HeaderMap;
+use sqlx::SqlitePool;
 use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
-use std::process::Command;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 use utoipa::ToSchema;
 
+/// Version of the `Payload` JSON produced by this build. Bump it when the
+/// layout changes in a way older peers cannot ignore.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Oldest peer schema this build still understands
+pub const MIN_SCHEMA_VERSION: u32 = 1;
+
+/// Header used by the orchestrator and the clients to announce their `SCHEMA_VERSION`
+pub const SCHEMA_VERSION_HEADER: &str = "x-payload-schema-version";
+
+/// Status of the payload, in the answer to `HEAD /retrieve/{id}`
+pub const PAYLOAD_STATUS_HEADER: &str = "x-payload-status";
+
+// Fields other than `id` and `status` fall back to defaults so payloads from
+// peers on another schema version still deserialize
 #[derive(serde::Serialize, serde::Deserialize, Debug, ToSchema)]
 pub struct Payload {
     pub id: u32,
+    #[serde(default)]
     input: HashMap<String, Vec<u8>>,
     pub status: Status,
     #[schema(value_type = String)]
+    #[serde(default)]
     pub loc: PathBuf,
+    #[serde(default)]
     pub pid: u32,
+    #[serde(default)]
+    pub killed: bool,
+    #[serde(default = "default_tenant")]
+    pub tenant: String,
+    #[serde(default = "legacy_schema_version")]
+    pub schema_version: u32,
+    #[serde(default)]
+    pub sandbox: SandboxProfile,
+    /// Object store key the server wants the results under, see `ResultStore`
+    #[serde(default)]
+    pub result_key: Option<String>,
+    /// Seconds the run may take before it is stopped, unbounded when unset
+    #[serde(default)]
+    pub timeout: Option<u32>,
+    /// Unix time from which the orchestrator no longer asks for the results,
+    /// the cleaner removes them then
+    #[serde(default)]
+    pub expires_at: Option<i64>,
+    /// Bytes the payload directory may hold once run, unbounded when unset
+    #[serde(default)]
+    pub max_output_size: Option<u64>,
+    /// Why the client failed the payload, unset when the run itself failed
+    #[serde(default)]
+    pub failure: Option<String>,
+    /// What `run.sh` wrote in `result.json`, read once it exited
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<ResultSummary>,
+    /// Service the orchestrator sent it for, its past runs give the ETA
+    #[serde(default)]
+    pub service: Option<String>,
+    /// Where it stands in the execution queue, only in the answer to
+    /// `GET /retrieve/{id}` while it is `Prepared`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub queue: Option<QueueEstimate>,
+    /// How the files of the manifest sent with the submission arrived, only
+    /// in the response to `/submit`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub manifest_report: Vec<FileReport>,
+    /// The files of the payload once prepared, checked again before it runs.
+    /// Unset for payloads prepared before the snapshot was taken.
+    #[serde(skip)]
+    pub inputs: Option<Vec<ManifestEntry>>,
+    /// Where the output of `run.sh` goes, unset until it started
+    #[serde(skip)]
+    pub stdout_log: Option<PathBuf>,
+    #[serde(skip)]
+    pub stderr_log: Option<PathBuf>,
+    /// How far the updater cut the logs while the run went on
+    #[serde(skip)]
+    pub log_trim: LogTrim,
+}
+
+/// Bytes cut from the start of `stdout.log` and `stderr.log`, so a byte read
+/// at some point of a log sits that much further along the whole output.
+/// `generation` is odd while the updater rewrites the logs, and moves on each
+/// time it starts and ends, so a reader can tell its read overlapped one.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct LogTrim {
+    pub generation: i64,
+    pub dropped: [u64; 2],
+}
+
+impl LogTrim {
+    pub fn ongoing(&self) -> bool {
+        self.generation % 2 == 1
+    }
+}
+
+/// What `GET /logs/{id}` answers, each log unset until the payload started
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct PayloadLogs {
+    pub id: u32,
+    pub status: Status,
+    pub stdout: Option<LogTail>,
+    pub stderr: Option<LogTail>,
+}
+
+/// The end of a log of a run
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct LogTail {
+    pub content: String,
+    /// The start of the log was left out to keep it under `RUN_LOG_MAX_SIZE`
+    pub truncated: bool,
+}
+
+/// What `GET /status/{id}` answers, read from the database alone so the
+/// orchestrator can poll it without the client zipping anything
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct PayloadStatus {
+    pub id: u32,
+    pub status: Status,
+    pub progress: PayloadProgress,
+    /// See `Payload::failure`
+    #[serde(default)]
+    pub failure: Option<String>,
+    /// See `Payload::summary`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<ResultSummary>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct QueueEstimate {
+    /// 1 for the next payload the runner starts
+    pub position: u32,
+    /// Seconds until the results are expected, the median run of the service
+    /// in `GET /stats`; unset until one completed
+    pub eta_secs: Option<u64>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Default, PartialEq, ToSchema)]
+pub struct PayloadProgress {
+    /// Seconds spent in the current status
+    pub elapsed_secs: u64,
     pub killed: bool,
 }
 
+fn default_tenant() -> String {
+    DEFAULT_TENANT.to_string()
+}
+
+/// Payloads from clients predating the field
+fn legacy_schema_version() -> u32 {
+    1
+}
+
+/// Schema version announced by a peer, legacy peers send no header
+pub fn peer_schema_version(headers: &HeaderMap) -> u32 {
+    headers
+        .get(SCHEMA_VERSION_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or_else(legacy_schema_version)
+}
+
+/// Newer peers are expected to stay readable, only outdated ones are refused
+pub fn is_supported_schema(version: u32) -> bool {
+    version >= MIN_SCHEMA_VERSION
+}
+
 const RUN_FILE: &str = "run.sh";
 const OUTPUT_FILE: &str = "output.zip";
-const EXIT_FILE: &str = ".orchestrator.exit";
+/// What `run.sh` writes to its standard output and error, shipped with the results
+pub const STDOUT_LOG: &str = "stdout.log";
+pub const STDERR_LOG: &str = "stderr.log";
+/// Written by the `run.sh` exit trap, also shipped inside `output.zip`
+pub const EXIT_FILE: &str = ".orchestrator.exit";
+
+/// Empty `path`, opened for appending
+fn open_log(path: &Path) -> std::io::Result<fs::File> {
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    file.set_len(0)?;
+    Ok(file)
+}
 
 impl Payload {
     pub fn new() -> Payload {
@@ -32,6 +209,22 @@ impl Payload {
             loc: PathBuf::new(),
             pid: 0,
             killed: false,
+            tenant: default_tenant(),
+            schema_version: SCHEMA_VERSION,
+            sandbox: SandboxProfile::default(),
+            result_key: None,
+            timeout: None,
+            expires_at: None,
+            max_output_size: None,
+            failure: None,
+            summary: None,
+            service: None,
+            queue: None,
+            manifest_report: Vec::new(),
+            inputs: None,
+            stdout_log: None,
+            stderr_log: None,
+            log_trim: LogTrim::default(),
         }
     }
 
@@ -51,61 +244,315 @@ impl Payload {
         self.loc = loc;
     }
 
+    pub fn set_tenant(&mut self, tenant: String) {
+        self.tenant = tenant;
+    }
+
+    pub fn set_sandbox(&mut self, sandbox: SandboxProfile) {
+        self.sandbox = sandbox;
+    }
+
+    pub fn set_result_key(&mut self, key: String) {
+        self.result_key = Some(key);
+    }
+
     pub fn remove_from_disk(&self) -> Result<(), std::io::Error> {
         fs::remove_dir_all(&self.loc)
     }
 
-    pub fn prepare(&mut self, data_path: &str) -> Result<(), std::io::Error> {
-        self.loc = std::path::Path::new(&data_path).join(self.id.to_string());
+    /// Directory the payload is prepared in, known before `prepare` sets `loc`
+    pub fn dir(&self, data_path: &str) -> PathBuf {
+        tenant_data_path(data_path, &self.tenant).join(self.id.to_string())
+    }
 
-        // Create directory for this payload
-        fs::create_dir_all(&self.loc)?;
+    /// Directory the files wait in until they are checked, see `QUARANTINE_DIR`
+    pub fn quarantine_dir(&self, data_path: &str) -> PathBuf {
+        tenant_data_path(data_path, &self.tenant)
+            .join(QUARANTINE_DIR)
+            .join(self.id.to_string())
+    }
 
-        // Dump data to this directory
-        self.input.iter_mut().for_each(|(filename, data)| {
-            fs::write(self.loc.join(filename), data).expect("Unable to write file")
-        });
+    /// Write the files to the quarantine directory, where the runner never
+    /// looks, and return it
+    pub fn stage(&self, data_path: &str) -> Result<PathBuf, std::io::Error> {
+        let quarantine = self.quarantine_dir(data_path);
+        fs::create_dir_all(&quarantine)?;
+        for (filename, data) in &self.input {
+            fs::write(quarantine.join(filename), data)?;
+        }
+        Ok(quarantine)
+    }
 
+    /// Move the checked files from quarantine to the payload directory, all
+    /// at once
+    pub fn release(&mut self, data_path: &str) -> Result<(), std::io::Error> {
+        let dir = self.dir(data_path);
+        // Left by a payload of an earlier database with the same id, no
+        // payload of this one refers to it
+        if dir.exists() {
+            fs::remove_dir_all(&dir)?;
+        }
+        fs::rename(self.quarantine_dir(data_path), &dir)?;
+        self.loc = dir;
         Ok(())
     }
 
-    pub fn zip_directory(self) -> Result<Vec<u8>, std::io::Error> {
-        // Get everything from the `loc` and return it
-        let result = self.loc.join(OUTPUT_FILE);
+    /// Stage the files, check them, and only then move them to the payload
+    /// directory the runner works in
+    pub async fn prepare(
+        &mut self,
+        data_path: &str,
+        checks: &UploadChecks,
+    ) -> Result<(), QuarantineError> {
+        let staged = self.stage(data_path)?;
+        // Reading the files and the scanner block
+        let checks = checks.clone();
+        tokio::task::spawn_blocking(move || quarantine::validate(&staged, &checks))
+            .await
+            .map_err(|e| QuarantineError::ScanFailed(e.to_string()))??;
+        self.release(data_path)?;
+        Ok(())
+    }
+
+    /// Fails with `InputsChanged` when the files of the payload are no longer
+    /// those it was prepared with, edited on disk or written by another run
+    pub fn verify_inputs(&self) -> Result<(), ClientError> {
+        let Some(inputs) = &self.inputs else {
+            return Ok(());
+        };
+        let current = dir_manifest(&self.loc).map_err(|e| ClientError::InputsChanged {
+            reason: format!("could not read them: {e}"),
+        })?;
+        let received: HashMap<String, (u64, String)> = current
+            .iter()
+            .map(|e| (e.path.clone(), (e.size, e.sha256.clone())))
+            .collect();
+
+        let mut changed: Vec<String> = verify_manifest(inputs, &received)
+            .into_iter()
+            .filter_map(|r| match r.check {
+                FileCheck::Ok => None,
+                FileCheck::Missing => Some(format!("{} removed", r.path)),
+                FileCheck::SizeMismatch | FileCheck::ChecksumMismatch => {
+                    Some(format!("{} modified", r.path))
+                }
+            })
+            .collect();
+        changed.extend(
+            current
+                .iter()
+                .filter(|e| !inputs.iter().any(|i| i.path == e.path))
+                .map(|e| format!("{} added", e.path)),
+        );
+        if changed.is_empty() {
+            return Ok(());
+        }
+        Err(ClientError::InputsChanged {
+            reason: changed.join(", "),
+        })
+    }
 
-        // Check if output.zip exists to avoid re-zipping
-        if !result.exists() {
-            // Not exists, create it by zipping the directory
-            utils::io::zip_directory(&self.loc, &result)?
+    /// Fails with `FileTooLarge` when the payload directory holds more than
+    /// `max_output_size`, nothing is archived then
+    pub fn check_output_size(&self) -> Result<(), std::io::Error> {
+        let Some(max) = self.max_output_size else {
+            return Ok(());
+        };
+        let size = utils::io::dir_size(&self.loc);
+        if size > max {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::FileTooLarge,
+                format!("output of {size} bytes is over the limit of {max} bytes"),
+            ));
         }
+        Ok(())
+    }
 
-        // Read the output.zip file and return its content
-        std::fs::read(&result)
+    /// Path of `output.zip`, zipping the directory first unless the archive
+    /// is newer than everything in it
+    pub fn output_archive(&self) -> Result<PathBuf, std::io::Error> {
+        let result = self.loc.join(OUTPUT_FILE);
+        if let Ok(zipped) = fs::metadata(&result).and_then(|m| m.modified())
+            && utils::io::newest_mtime(&self.loc, &result)? <= zipped
+        {
+            return Ok(result);
+        }
+        self.check_output_size()?;
+
+        // The stale archive must not end up inside the new one
+        match fs::remove_file(&result) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        let archive = utils::io::zip_directory_to_bytes(&self.loc)?;
+
+        // Concurrent retrieves must never serve a half-written archive
+        let nanos = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let partial = self.loc.join(format!(".{OUTPUT_FILE}.{nanos}"));
+        fs::write(&partial, archive)?;
+        fs::rename(&partial, &result)?;
+
+        // The rename touched the directory, the archive must not look older
+        fs::File::options()
+            .write(true)
+            .open(&result)?
+            .set_modified(SystemTime::now())?;
+        Ok(result)
     }
 
     /// Zip the payload directory to bytes, regardless of its current state.
     /// This is used for partial downloads to debug stuck or incomplete runs.
-    /// Unlike zip_directory, this does not create or read from output.zip.
+    /// Unlike output_archive, this does not create or read from output.zip.
     pub fn zip_partial(self) -> Result<Vec<u8>, std::io::Error> {
+        self.check_output_size()?;
         // Zip the directory to bytes directly without using output.zip
         utils::io::zip_directory_to_bytes(&self.loc).map_err(std::io::Error::other)
     }
 
-    pub fn execute(&mut self) -> Result<(), ClientError> {
+    /// Start `run.sh`; with `pinned` set it may only use those host binaries.
+    /// `scratch` is where it may put the files that are not results.
+    pub fn execute(
+        &mut self,
+        pinned: &[PinnedBinary],
+        scratch: Option<&Path>,
+    ) -> Result<(), ClientError> {
         let run_script = self.loc.join(RUN_FILE);
-        utils::io::validate_script(&run_script)?;
+        let policy = self.sandbox.policy();
+        if policy.validate_script {
+            utils::io::validate_script(&run_script)?;
+        } else {
+            utils::io::validate_exit_trap(&run_script)?;
+        }
+
+        let path = if pinned.is_empty() {
+            None
+        } else {
+            self.check_pinned(pinned, &run_script)?;
+            Some(pinned_path(pinned))
+        };
+
+        // Written straight to the files, the run outlives a restart of the
+        // client. Appending lets the updater shrink them while the run goes on.
+        let stdout_log = self.loc.join(STDOUT_LOG);
+        let stderr_log = self.loc.join(STDERR_LOG);
+        let (stdout, stderr) = open_log(&stdout_log)
+            .and_then(|out| Ok((out, open_log(&stderr_log)?)))
+            .map_err(|_| ClientError::Execution)?;
 
-        let child = Command::new("bash")
-            .arg(run_script)
-            .current_dir(&self.loc)
+        let child = policy
+            .command(&self.loc, &run_script, path.as_deref(), scratch)
+            .stdout(stdout)
+            .stderr(stderr)
             .spawn()
             .map_err(|_| ClientError::Execution)?;
 
         self.pid = child.id();
+        self.stdout_log = Some(stdout_log);
+        self.stderr_log = Some(stderr_log);
 
         Ok(())
     }
 
+    /// Keep the last `max` bytes of each log of the run, once it is over
+    pub async fn trim_logs(&self, max: u64, pool: &SqlitePool) -> std::io::Result<()> {
+        self.shrink_logs(max, max, utils::io::keep_tail, pool).await
+    }
+
+    /// Bring the logs of a run still going back to their last `max` bytes,
+    /// once they grew past twice that so they are not rewritten every round
+    pub async fn cap_logs(&self, max: u64, pool: &SqlitePool) -> std::io::Result<()> {
+        self.shrink_logs(max.saturating_mul(2), max, utils::io::shrink_to_tail, pool)
+            .await
+    }
+
+    /// Shrink the logs longer than `limit` to `max` bytes, within a
+    /// `LogTrim` generation so `/logs/{id}/stream` keeps its place
+    async fn shrink_logs(
+        &self,
+        limit: u64,
+        max: u64,
+        shrink: fn(&Path, u64) -> std::io::Result<u64>,
+        pool: &SqlitePool,
+    ) -> std::io::Result<()> {
+        let too_long = |log: &Path| fs::metadata(log).is_ok_and(|m| m.len() > limit);
+        let logs = [&self.stdout_log, &self.stderr_log].map(|log| log.as_deref());
+        if !logs.into_iter().flatten().any(too_long) {
+            return Ok(());
+        }
+
+        self.begin_log_trim(pool)
+            .await
+            .map_err(std::io::Error::other)?;
+        let mut dropped = [0; 2];
+        let mut result = Ok(());
+        for (log, dropped) in logs.into_iter().zip(dropped.iter_mut()) {
+            let Some(log) = log.filter(|log| too_long(log)) else {
+                continue;
+            };
+            match shrink(log, max) {
+                Ok(n) => *dropped = n,
+                // Removed along with the rest of the payload meanwhile
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+        // Ended even when a log could not be shrunk, followers wait meanwhile
+        self.end_log_trim(dropped, pool)
+            .await
+            .map_err(std::io::Error::other)?;
+        result
+    }
+
+    /// The last `max` bytes of each log of the run, as far as it went
+    pub fn read_logs(&self, max: u64) -> std::io::Result<PayloadLogs> {
+        let tail = |log: &Option<PathBuf>| -> std::io::Result<Option<LogTail>> {
+            let Some(log) = log else {
+                return Ok(None);
+            };
+            match utils::io::read_tail(log, max) {
+                Ok((content, truncated)) => Ok(Some(LogTail {
+                    content: String::from_utf8_lossy(&content).into_owned(),
+                    truncated,
+                })),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e),
+            }
+        };
+        Ok(PayloadLogs {
+            id: self.id,
+            status: self.status,
+            stdout: tail(&self.stdout_log)?,
+            stderr: tail(&self.stderr_log)?,
+        })
+    }
+
+    /// The pinned binaries must be untouched and the payload must not bring its own
+    fn check_pinned(&self, pinned: &[PinnedBinary], run_script: &Path) -> Result<(), ClientError> {
+        for binary in pinned {
+            binary
+                .verify()
+                .map_err(|reason| ClientError::PinnedBinary { reason })?;
+        }
+
+        let shipped =
+            shipped_executables(&self.loc, run_script).map_err(|_| ClientError::Execution)?;
+        if let Some(path) = shipped.first() {
+            return Err(ClientError::UnsafePayload {
+                reason: format!(
+                    "ships the executable {}",
+                    path.strip_prefix(&self.loc).unwrap_or(path).display()
+                ),
+            });
+        }
+        Ok(())
+    }
+
     pub fn kill(&mut self) -> std::io::Result<()> {
         if self.pid == 0 {
             return Ok(());
@@ -188,7 +635,7 @@ mod test {
         let temp_dir = tempfile::tempdir().unwrap();
         let data_path = temp_dir.path().to_str().unwrap();
 
-        let result = p.prepare(data_path);
+        let result = p.prepare(data_path, &UploadChecks::default()).await;
         assert!(result.is_ok());
 
         let expected_path = temp_dir.path().join("1").join("test.txt");
@@ -198,6 +645,74 @@ mod test {
         assert_eq!(content, "Test data");
     }
 
+    #[test]
+    fn test_verify_inputs() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut p = Payload::new();
+        p.loc = temp_dir.path().to_path_buf();
+        fs::write(p.loc.join("run.sh"), "echo 1").unwrap();
+        fs::write(p.loc.join("a.pdb"), "ATOM").unwrap();
+        fs::write(p.loc.join("b.pdb"), "ATOM").unwrap();
+
+        // Prepared before the snapshot was taken
+        assert!(p.verify_inputs().is_ok());
+
+        p.inputs = Some(dir_manifest(&p.loc).unwrap());
+        assert!(p.verify_inputs().is_ok());
+
+        fs::write(p.loc.join("run.sh"), "echo 2").unwrap();
+        fs::remove_file(p.loc.join("a.pdb")).unwrap();
+        fs::write(p.loc.join("c.pdb"), "ATOM").unwrap();
+        match p.verify_inputs() {
+            Err(ClientError::InputsChanged { reason }) => {
+                assert_eq!(reason, "a.pdb removed, run.sh modified, c.pdb added")
+            }
+            other => panic!("expected InputsChanged, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_stage_and_release() {
+        let mut p = Payload::new();
+        p.id = 1;
+        p.add_input("run.sh".to_string(), b"echo".to_vec());
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let data_path = temp_dir.path().to_str().unwrap();
+        // Stale files of an earlier payload with the same id
+        fs::create_dir_all(p.dir(data_path)).unwrap();
+        fs::write(p.dir(data_path).join("old.txt"), b"old").unwrap();
+
+        let quarantine = p.stage(data_path).unwrap();
+        assert_eq!(quarantine, temp_dir.path().join(QUARANTINE_DIR).join("1"));
+        assert!(quarantine.join("run.sh").exists());
+        assert_eq!(p.loc, PathBuf::new());
+
+        p.release(data_path).unwrap();
+        assert!(!quarantine.exists());
+        assert_eq!(p.loc, temp_dir.path().join("1"));
+        assert!(p.loc.join("run.sh").exists());
+        assert!(!p.loc.join("old.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn test_prepare_with_tenant() {
+        let mut p = Payload::new();
+        p.id = 1;
+        p.set_tenant("lab".to_string());
+        p.add_input("test.txt".to_string(), b"Test data".to_vec());
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let data_path = temp_dir.path().to_str().unwrap();
+
+        p.prepare(data_path, &UploadChecks::default())
+            .await
+            .unwrap();
+
+        let expected_path = temp_dir.path().join("lab").join("1").join("test.txt");
+        assert!(expected_path.exists());
+    }
+
     #[test]
     fn test_new() {
         let p = Payload::new();
@@ -207,6 +722,7 @@ mod test {
         assert_eq!(p.loc, PathBuf::new());
         assert_eq!(p.pid, 0);
         assert!(!p.killed);
+        assert_eq!(p.tenant, DEFAULT_TENANT);
     }
 
     #[test]
@@ -325,6 +841,58 @@ mod test {
         let _ = child.wait();
     }
 
+    #[test]
+    fn test_output_archive_cached() {
+        let mut p = Payload::new();
+        let temp_dir = tempfile::tempdir().unwrap();
+        p.loc = temp_dir.path().to_path_buf();
+        fs::write(p.loc.join("result.txt"), b"result data").unwrap();
+
+        let archive = p.output_archive().unwrap();
+        let zipped = fs::metadata(&archive).unwrap().modified().unwrap();
+
+        // Nothing changed, the archive is served as is
+        assert_eq!(p.output_archive().unwrap(), archive);
+        assert_eq!(fs::metadata(&archive).unwrap().modified().unwrap(), zipped);
+
+        // A file showing up afterwards invalidates it
+        let late = p.loc.join("late.txt");
+        fs::write(&late, b"late data").unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&late)
+            .unwrap()
+            .set_modified(zipped + std::time::Duration::from_secs(1))
+            .unwrap();
+        p.output_archive().unwrap();
+        assert!(fs::metadata(&archive).unwrap().modified().unwrap() > zipped);
+
+        let mut zip = zip::ZipArchive::new(fs::File::open(&archive).unwrap()).unwrap();
+        let mut names: Vec<_> = zip.file_names().map(str::to_string).collect();
+        names.sort();
+        assert_eq!(names, ["late.txt", "result.txt"]);
+        assert!(zip.by_name(OUTPUT_FILE).is_err());
+    }
+
+    #[test]
+    fn test_output_size_limit() {
+        let mut p = Payload::new();
+        let temp_dir = tempfile::tempdir().unwrap();
+        p.loc = temp_dir.path().to_path_buf();
+        fs::write(p.loc.join("result.txt"), vec![b'x'; 100]).unwrap();
+        assert!(p.check_output_size().is_ok());
+
+        p.max_output_size = Some(100);
+        assert!(p.check_output_size().is_ok());
+
+        p.max_output_size = Some(99);
+        let err = p.output_archive().unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::FileTooLarge);
+        assert!(!p.loc.join(OUTPUT_FILE).exists());
+        let err = p.zip_partial().unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::FileTooLarge);
+    }
+
     #[tokio::test]
     async fn test_zip_partial() {
         let mut p = Payload::new();
@@ -386,4 +954,36 @@ mod test {
         let archive = zip::ZipArchive::new(cursor).unwrap();
         assert_eq!(archive.len(), 0);
     }
+
+    #[test]
+    fn test_deserialize_other_schema_versions() {
+        // Legacy clients send no version and a newer one may drop or add fields
+        let legacy: Payload = serde_json::from_str(
+            r#"{"id": 1, "input": {}, "status": "Running", "loc": "/tmp/x", "pid": 7, "killed": false}"#,
+        )
+        .unwrap();
+        assert_eq!(legacy.schema_version, 1);
+        assert_eq!(legacy.pid, 7);
+
+        let newer: Payload = serde_json::from_str(
+            r#"{"id": 2, "status": "Completed", "schema_version": 9, "extra": true}"#,
+        )
+        .unwrap();
+        assert_eq!(newer.id, 2);
+        assert_eq!(newer.status, Status::Completed);
+        assert_eq!(newer.schema_version, 9);
+
+        assert_eq!(Payload::new().schema_version, SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn test_peer_schema_version() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(peer_schema_version(&headers), 1);
+
+        headers.insert(SCHEMA_VERSION_HEADER, "3".parse().unwrap());
+        assert_eq!(peer_schema_version(&headers), 3);
+        assert!(is_supported_schema(3));
+        assert!(!is_supported_schema(0));
+    }
 }
@@ -1,9 +1,19 @@
-use crate::models::payload_dao::Payload;
+use crate::datasource::db::add_column_if_missing;
+use crate::models::manifest_dto::{ManifestEntry, dir_manifest};
+use crate::models::payload_dao::{LogTrim, Payload, PayloadProgress, PayloadStatus, QueueEstimate};
+use crate::models::run_history_dao::ServiceRunStats;
+use crate::models::run_history_dto::create_run_history_table;
 use crate::models::status_dto::Status;
+use crate::utils::result_summary::ResultSummary;
+use sqlx::sqlite::SqliteRow;
 use sqlx::{Row, SqlitePool};
 use std::path::PathBuf;
+use std::time::Duration;
 
 pub async fn create_payload_table(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    // The whole schema is set up on one connection, see `add_column_if_missing`
+    let mut conn = pool.acquire().await?;
+
     sqlx::query(
         r#"
         CREATE TABLE IF NOT EXISTS payloads (
@@ -16,21 +26,135 @@ pub async fn create_payload_table(pool: &SqlitePool) -> Result<(), sqlx::Error>
         )
     "#,
     )
-    .execute(pool)
+    .execute(&mut *conn)
+    .await?;
+
+    // Columns added after the initial schema
+    add_column_if_missing(
+        &mut conn,
+        "payloads",
+        "tenant",
+        "TEXT NOT NULL DEFAULT 'default'",
+    )
+    .await?;
+    add_column_if_missing(
+        &mut conn,
+        "payloads",
+        "sandbox",
+        "TEXT NOT NULL DEFAULT 'legacy'",
+    )
+    .await?;
+    add_column_if_missing(&mut conn, "payloads", "result_key", "TEXT").await?;
+    add_column_if_missing(&mut conn, "payloads", "status_since", "DATETIME").await?;
+    add_column_if_missing(&mut conn, "payloads", "timeout", "INTEGER").await?;
+    add_column_if_missing(&mut conn, "payloads", "expires_at", "INTEGER").await?;
+    add_column_if_missing(&mut conn, "payloads", "max_output_size", "INTEGER").await?;
+    add_column_if_missing(&mut conn, "payloads", "failure", "TEXT").await?;
+    add_column_if_missing(&mut conn, "payloads", "service", "TEXT").await?;
+    // `result.json` of the run, as JSON
+    add_column_if_missing(&mut conn, "payloads", "summary", "TEXT").await?;
+    // The files once prepared, as a JSON list of manifest entries
+    add_column_if_missing(&mut conn, "payloads", "inputs", "TEXT").await?;
+    add_column_if_missing(&mut conn, "payloads", "stdout_log", "TEXT").await?;
+    add_column_if_missing(&mut conn, "payloads", "stderr_log", "TEXT").await?;
+    // See `LogTrim`
+    add_column_if_missing(
+        &mut conn,
+        "payloads",
+        "log_generation",
+        "INTEGER NOT NULL DEFAULT 0",
+    )
+    .await?;
+    add_column_if_missing(
+        &mut conn,
+        "payloads",
+        "stdout_dropped",
+        "INTEGER NOT NULL DEFAULT 0",
+    )
     .await?;
+    add_column_if_missing(
+        &mut conn,
+        "payloads",
+        "stderr_dropped",
+        "INTEGER NOT NULL DEFAULT 0",
+    )
+    .await?;
+
+    create_run_history_table(&mut conn).await?;
+
     Ok(())
 }
 
+fn log_trim_from_row(row: &SqliteRow) -> LogTrim {
+    let dropped = |column| row.get::<i64, _>(column).max(0) as u64;
+    LogTrim {
+        generation: row.get("log_generation"),
+        dropped: [dropped("stdout_dropped"), dropped("stderr_dropped")],
+    }
+}
+
+/// Summaries are stored as JSON, one that no longer parses is dropped
+fn summary_from_json(json: Option<String>) -> Option<ResultSummary> {
+    json.and_then(|s| serde_json::from_str(&s).ok())
+}
+
+/// Snapshots are stored as JSON, one that no longer parses is not checked
+pub(crate) fn inputs_from_json(json: Option<String>) -> Option<Vec<ManifestEntry>> {
+    json.and_then(|s| serde_json::from_str(&s).ok())
+}
+
 impl Payload {
+    /// Build a `Payload` from a row of the `payloads` table
+    fn from_row(row: &SqliteRow) -> Payload {
+        let status: String = row.get("status");
+        let loc: Option<String> = row.get("loc");
+
+        let mut payload = Payload::new();
+        payload.id = row.get("id");
+        payload.status = Status::from_string(&status);
+        payload.loc = loc.map(PathBuf::from).unwrap_or_default();
+        payload.pid = row.get("pid");
+        payload.killed = row.get("killed");
+        payload.tenant = row.get("tenant");
+        payload.sandbox = row.get::<String, _>("sandbox").parse().unwrap_or_default();
+        payload.result_key = row.get("result_key");
+        payload.timeout = row.get("timeout");
+        payload.expires_at = row.get("expires_at");
+        payload.max_output_size = row
+            .get::<Option<i64>, _>("max_output_size")
+            .map(|s| s.max(0) as u64);
+        payload.failure = row.get("failure");
+        payload.summary = summary_from_json(row.get("summary"));
+        payload.service = row.get("service");
+        payload.inputs = inputs_from_json(row.get("inputs"));
+        payload.stdout_log = row
+            .get::<Option<String>, _>("stdout_log")
+            .map(PathBuf::from);
+        payload.stderr_log = row
+            .get::<Option<String>, _>("stderr_log")
+            .map(PathBuf::from);
+        payload.log_trim = log_trim_from_row(row);
+        payload
+    }
+
     pub async fn add_to_db(&mut self, pool: &SqlitePool) -> Result<(), sqlx::Error> {
         // NOTE: This `loc` will not exist on disk until `prepare` is called!
         let loc_str = self.loc.to_string_lossy();
 
-        let result = sqlx::query("INSERT INTO payloads (status, loc) VALUES (?, ?)")
-            .bind(self.status.to_string())
-            .bind(loc_str)
-            .execute(pool)
-            .await?;
+        let result = sqlx::query(
+            "INSERT INTO payloads (status, loc, tenant, sandbox, result_key, timeout, expires_at, max_output_size, service) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(self.status.to_string())
+        .bind(loc_str)
+        .bind(&self.tenant)
+        .bind(self.sandbox.as_str())
+        .bind(&self.result_key)
+        .bind(self.timeout)
+        .bind(self.expires_at)
+        .bind(self.max_output_size.map(|s| i64::try_from(s).unwrap_or(i64::MAX)))
+        .bind(&self.service)
+        .execute(pool)
+        .await?;
 
         let id = result.last_insert_rowid();
         self.id = id as u32;
@@ -43,11 +167,19 @@ impl Payload {
         status: Status,
         pool: &SqlitePool,
     ) -> Result<(), sqlx::Error> {
-        let _result = sqlx::query("UPDATE payloads SET status = ? WHERE id = ?")
-            .bind(status.to_string())
-            .bind(self.id)
-            .execute(pool)
-            .await?;
+        // A finished run is kept for the statistics of its service, this
+        // reads how long it has been running so it goes first
+        if matches!(status, Status::Completed | Status::Failed) {
+            ServiceRunStats::record(self.id, status, pool).await?;
+        }
+
+        let _result = sqlx::query(
+            "UPDATE payloads SET status = ?, status_since = CURRENT_TIMESTAMP WHERE id = ?",
+        )
+        .bind(status.to_string())
+        .bind(self.id)
+        .execute(pool)
+        .await?;
 
         self.status = status;
 
@@ -66,9 +198,40 @@ impl Payload {
         Ok(())
     }
 
+    /// Record the process of the run and where its output goes, its logs
+    /// start empty
     pub async fn update_pid(&mut self, pool: &SqlitePool) -> Result<(), sqlx::Error> {
-        sqlx::query("UPDATE payloads SET pid = ? WHERE id = ?")
-            .bind(self.pid)
+        let path = |log: &Option<PathBuf>| log.as_ref().map(|p| p.to_string_lossy().into_owned());
+        sqlx::query(
+            "UPDATE payloads SET pid = ?, stdout_log = ?, stderr_log = ?, stdout_dropped = 0, stderr_dropped = 0 WHERE id = ?",
+        )
+        .bind(self.pid)
+        .bind(path(&self.stdout_log))
+        .bind(path(&self.stderr_log))
+        .bind(self.id)
+        .execute(pool)
+        .await?;
+        self.log_trim.dropped = [0; 2];
+
+        Ok(())
+    }
+
+    /// Where the logs of payload `id` were cut, see `LogTrim`
+    pub async fn retrieve_log_trim(id: u32, pool: &SqlitePool) -> Result<LogTrim, sqlx::Error> {
+        let row = sqlx::query(
+            "SELECT log_generation, stdout_dropped, stderr_dropped FROM payloads WHERE id = ?",
+        )
+        .bind(id)
+        .fetch_one(pool)
+        .await?;
+
+        Ok(log_trim_from_row(&row))
+    }
+
+    /// The updater is about to rewrite the logs, the generation turns odd.
+    /// One left odd by a trim that could not end moves on all the same.
+    pub async fn begin_log_trim(&self, pool: &SqlitePool) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE payloads SET log_generation = (log_generation + 1) | 1 WHERE id = ?")
             .bind(self.id)
             .execute(pool)
             .await?;
@@ -76,6 +239,32 @@ impl Payload {
         Ok(())
     }
 
+    /// The logs were rewritten, `dropped` bytes went from each, the
+    /// generation turns even
+    pub async fn end_log_trim(
+        &self,
+        dropped: [u64; 2],
+        pool: &SqlitePool,
+    ) -> Result<(), sqlx::Error> {
+        let bytes = |n: u64| i64::try_from(n).unwrap_or(i64::MAX);
+        sqlx::query(
+            r#"
+            UPDATE payloads
+            SET log_generation = (log_generation | 1) + 1,
+                stdout_dropped = stdout_dropped + ?,
+                stderr_dropped = stderr_dropped + ?
+            WHERE id = ?
+            "#,
+        )
+        .bind(bytes(dropped[0]))
+        .bind(bytes(dropped[1]))
+        .bind(self.id)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
     pub async fn mark_as_killed(&mut self, pool: &SqlitePool) -> Result<(), sqlx::Error> {
         sqlx::query("UPDATE payloads SET killed = ? WHERE id = ?")
             .bind(true)
@@ -94,17 +283,187 @@ impl Payload {
             .fetch_one(pool)
             .await?;
 
-        let status: String = row.get("status");
-        let loc: Option<String> = row.get("loc");
+        Ok(Payload::from_row(&row))
+    }
 
-        let mut payload = Payload::new();
-        payload.id = row.get("id");
-        payload.status = Status::from_string(&status);
-        payload.loc = loc.map(PathBuf::from).unwrap_or_default();
-        payload.pid = row.get("pid");
-        payload.killed = row.get("killed");
+    /// Status of payload `id` and how long it has been in it
+    pub async fn retrieve_status(id: u32, pool: &SqlitePool) -> Result<PayloadStatus, sqlx::Error> {
+        let (status, killed, elapsed, failure, summary): (
+            String,
+            bool,
+            i64,
+            Option<String>,
+            Option<String>,
+        ) = sqlx::query_as(
+            r#"
+            SELECT status, killed,
+                   CAST(strftime('%s', 'now') AS INTEGER)
+                   - CAST(strftime('%s', COALESCE(status_since, created_at)) AS INTEGER),
+                   failure, summary
+            FROM payloads WHERE id = ?
+            "#,
+        )
+        .bind(id)
+        .fetch_one(pool)
+        .await?;
+
+        Ok(PayloadStatus {
+            id,
+            status: Status::from_string(&status),
+            progress: PayloadProgress {
+                elapsed_secs: elapsed.max(0) as u64,
+                killed,
+            },
+            failure,
+            summary: summary_from_json(summary),
+        })
+    }
+
+    /// Position of this `Prepared` payload among the others, and the median
+    /// of the latest runs of its service
+    pub async fn queue_estimate(&self, pool: &SqlitePool) -> Result<QueueEstimate, sqlx::Error> {
+        let position: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM payloads WHERE status = ? AND id <= ?")
+                .bind(Status::Prepared.to_string())
+                .bind(self.id)
+                .fetch_one(pool)
+                .await?;
+        let stats = match &self.service {
+            Some(service) => ServiceRunStats::for_service(service, pool).await?,
+            None => None,
+        };
+
+        Ok(QueueEstimate {
+            position: position.max(1) as u32,
+            eta_secs: stats.and_then(|s| s.p50_secs),
+        })
+    }
+
+    /// Mark the payload `Failed` for `reason`, one the run itself did not give
+    pub async fn fail(&mut self, reason: String, pool: &SqlitePool) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE payloads SET failure = ? WHERE id = ?")
+            .bind(&reason)
+            .bind(self.id)
+            .execute(pool)
+            .await?;
+        self.failure = Some(reason);
+        self.update_status(Status::Failed, pool).await
+    }
+
+    /// Snapshot the files of the prepared payload, see `Payload::verify_inputs`
+    pub async fn record_inputs(&mut self, pool: &SqlitePool) -> Result<(), sqlx::Error> {
+        let loc = self.loc.clone();
+        let inputs = tokio::task::spawn_blocking(move || dir_manifest(&loc))
+            .await
+            .map_err(|e| sqlx::Error::Io(std::io::Error::other(e)))??;
+        let json = serde_json::to_string(&inputs).map_err(|e| sqlx::Error::Encode(e.into()))?;
+        sqlx::query("UPDATE payloads SET inputs = ? WHERE id = ?")
+            .bind(json)
+            .bind(self.id)
+            .execute(pool)
+            .await?;
+        self.inputs = Some(inputs);
+        Ok(())
+    }
+
+    /// Store what `run.sh` wrote in `result.json`
+    pub async fn record_summary(
+        &mut self,
+        summary: ResultSummary,
+        pool: &SqlitePool,
+    ) -> Result<(), sqlx::Error> {
+        let json = serde_json::to_string(&summary).map_err(|e| sqlx::Error::Encode(e.into()))?;
+        sqlx::query("UPDATE payloads SET summary = ? WHERE id = ?")
+            .bind(json)
+            .bind(self.id)
+            .execute(pool)
+            .await?;
+        self.summary = Some(summary);
+        Ok(())
+    }
+
+    /// Drop what a submission that failed midway left behind: its directory
+    /// is removed and the payload marked `Failed` so it is not picked up again
+    pub async fn discard(&mut self, data_path: &str, pool: &SqlitePool) -> Result<(), sqlx::Error> {
+        for dir in [self.quarantine_dir(data_path), self.dir(data_path)] {
+            if dir.exists()
+                && let Err(e) = std::fs::remove_dir_all(&dir)
+            {
+                tracing::error!("Could not remove {:?}: {e}", dir);
+            }
+        }
+        self.update_status(Status::Failed, pool).await
+    }
+
+    /// Payloads whose submission never got as far as `Prepared`
+    pub async fn list_unprepared(pool: &SqlitePool) -> Result<Vec<Payload>, sqlx::Error> {
+        let rows = sqlx::query("SELECT * FROM payloads WHERE status = ?")
+            .bind(Status::Unknown.to_string())
+            .fetch_all(pool)
+            .await?;
+
+        Ok(rows.iter().map(Payload::from_row).collect())
+    }
+
+    /// Payloads waiting to run or running, the ones whose scratch directory
+    /// is still needed
+    pub async fn list_active(pool: &SqlitePool) -> Result<Vec<Payload>, sqlx::Error> {
+        let rows = sqlx::query("SELECT * FROM payloads WHERE status IN (?, ?)")
+            .bind(Status::Prepared.to_string())
+            .bind(Status::Running.to_string())
+            .fetch_all(pool)
+            .await?;
+
+        Ok(rows.iter().map(Payload::from_row).collect())
+    }
+
+    /// Running payloads that have been running for longer than their timeout
+    pub async fn list_timed_out(pool: &SqlitePool) -> Result<Vec<Payload>, sqlx::Error> {
+        let rows = sqlx::query(
+            r#"
+            SELECT * FROM payloads
+            WHERE status = ? AND timeout IS NOT NULL
+              AND CAST(strftime('%s', 'now') AS INTEGER)
+                  - CAST(strftime('%s', COALESCE(status_since, created_at)) AS INTEGER) > timeout
+            "#,
+        )
+        .bind(Status::Running.to_string())
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows.iter().map(Payload::from_row).collect())
+    }
 
-        Ok(payload)
+    /// Payloads not yet cleaned whose deadline from the orchestrator has passed
+    pub async fn list_expired(pool: &SqlitePool) -> Result<Vec<Payload>, sqlx::Error> {
+        let rows = sqlx::query(
+            r#"
+            SELECT * FROM payloads
+            WHERE status != ? AND expires_at IS NOT NULL
+              AND expires_at <= CAST(strftime('%s', 'now') AS INTEGER)
+            ORDER BY id
+            "#,
+        )
+        .bind(Status::Cleaned.to_string())
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows.iter().map(Payload::from_row).collect())
+    }
+
+    /// Ids of the payloads not yet cleaned that were created at least `age` ago
+    pub async fn list_older_than(
+        age: Duration,
+        pool: &SqlitePool,
+    ) -> Result<Vec<u32>, sqlx::Error> {
+        sqlx::query_scalar(
+            "SELECT id FROM payloads WHERE status NOT IN (?, ?) AND created_at <= datetime('now', ?) ORDER BY id",
+        )
+        .bind(Status::Cleaned.to_string())
+        .bind(Status::Cancelled.to_string())
+        .bind(format!("-{} seconds", age.as_secs()))
+        .fetch_all(pool)
+        .await
     }
 
     pub async fn retrieve_by_loc(loc: String, pool: &SqlitePool) -> Result<Payload, sqlx::Error> {
@@ -114,17 +473,7 @@ impl Payload {
             .await?
             .ok_or(sqlx::Error::RowNotFound)?;
 
-        let status: String = row.get("status");
-        let loc: Option<String> = row.get("loc");
-
-        let mut payload = Payload::new();
-        payload.id = row.get("id");
-        payload.status = Status::from_string(&status);
-        payload.loc = loc.map(PathBuf::from).unwrap_or_default();
-        payload.pid = row.get("pid");
-        payload.killed = row.get("killed");
-
-        Ok(payload)
+        Ok(Payload::from_row(&row))
     }
 }
 
@@ -132,6 +481,8 @@ impl Payload {
 mod test {
 
     use super::*;
+    use crate::utils::sandbox::SandboxProfile;
+    use std::time::SystemTime;
     use tempfile::TempDir;
 
     #[tokio::test]
@@ -260,4 +611,279 @@ mod test {
         assert_eq!(retrieved_payload.id, id);
         assert_eq!(retrieved_payload.status, Status::Unknown);
     }
+
+    #[tokio::test]
+    async fn test_retrieve_id_with_tenant() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let pool = crate::datasource::db::init_payload_db(db_path.to_str().unwrap()).await;
+
+        let mut payload = Payload::new();
+        payload.set_tenant("lab".to_string());
+        payload.add_to_db(&pool).await.unwrap();
+
+        let retrieved = Payload::retrieve_id(payload.id, &pool).await.unwrap();
+        assert_eq!(retrieved.tenant, "lab");
+    }
+
+    #[tokio::test]
+    async fn test_retrieve_id_with_sandbox() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let pool = crate::datasource::db::init_payload_db(db_path.to_str().unwrap()).await;
+
+        let mut payload = Payload::new();
+        payload.set_sandbox(SandboxProfile::TrustedInternal);
+        payload.add_to_db(&pool).await.unwrap();
+
+        let retrieved = Payload::retrieve_id(payload.id, &pool).await.unwrap();
+        assert_eq!(retrieved.sandbox, SandboxProfile::TrustedInternal);
+    }
+
+    #[tokio::test]
+    async fn test_retrieve_id_with_result_key() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let pool = crate::datasource::db::init_payload_db(db_path.to_str().unwrap()).await;
+
+        let mut payload = Payload::new();
+        payload.add_to_db(&pool).await.unwrap();
+        let retrieved = Payload::retrieve_id(payload.id, &pool).await.unwrap();
+        assert_eq!(retrieved.result_key, None);
+
+        let mut payload = Payload::new();
+        payload.set_result_key("lab/abc.zip".to_string());
+        payload.add_to_db(&pool).await.unwrap();
+        let retrieved = Payload::retrieve_id(payload.id, &pool).await.unwrap();
+        assert_eq!(retrieved.result_key.as_deref(), Some("lab/abc.zip"));
+    }
+
+    #[tokio::test]
+    async fn test_list_timed_out() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let pool = crate::datasource::db::init_payload_db(db_path.to_str().unwrap()).await;
+
+        let mut ids = Vec::new();
+        for timeout in [None, Some(300), Some(3600)] {
+            let mut payload = Payload::new();
+            payload.timeout = timeout;
+            payload.add_to_db(&pool).await.unwrap();
+            payload.update_status(Status::Running, &pool).await.unwrap();
+            ids.push(payload.id);
+        }
+        // Done before its timeout ran out
+        let mut done = Payload::new();
+        done.timeout = Some(300);
+        done.add_to_db(&pool).await.unwrap();
+        done.update_status(Status::Completed, &pool).await.unwrap();
+        sqlx::query("UPDATE payloads SET status_since = datetime('now', '-10 minutes')")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let timed_out = Payload::list_timed_out(&pool).await.unwrap();
+        let timed_out: Vec<_> = timed_out.iter().map(|p| (p.id, p.timeout)).collect();
+        assert_eq!(timed_out, vec![(ids[1], Some(300))]);
+    }
+
+    #[tokio::test]
+    async fn test_fail() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let pool = crate::datasource::db::init_payload_db(db_path.to_str().unwrap()).await;
+
+        let mut payload = Payload::new();
+        payload.max_output_size = Some(1024);
+        payload.add_to_db(&pool).await.unwrap();
+        assert_eq!(
+            Payload::retrieve_status(payload.id, &pool)
+                .await
+                .unwrap()
+                .failure,
+            None
+        );
+
+        payload.fail("too large".to_string(), &pool).await.unwrap();
+        let retrieved = Payload::retrieve_id(payload.id, &pool).await.unwrap();
+        assert_eq!(retrieved.status, Status::Failed);
+        assert_eq!(retrieved.failure.as_deref(), Some("too large"));
+        assert_eq!(retrieved.max_output_size, Some(1024));
+        let status = Payload::retrieve_status(payload.id, &pool).await.unwrap();
+        assert_eq!(status.failure.as_deref(), Some("too large"));
+    }
+
+    #[tokio::test]
+    async fn test_record_summary() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let pool = crate::datasource::db::init_payload_db(db_path.to_str().unwrap()).await;
+
+        let mut payload = Payload::new();
+        payload.add_to_db(&pool).await.unwrap();
+        let status = Payload::retrieve_status(payload.id, &pool).await.unwrap();
+        assert_eq!(status.summary, None);
+
+        let summary = ResultSummary {
+            status: Some("converged".to_string()),
+            metrics: [("rmsd".to_string(), serde_json::json!(1.2))].into(),
+            warnings: vec!["2 chains skipped".to_string()],
+        };
+        payload
+            .record_summary(summary.clone(), &pool)
+            .await
+            .unwrap();
+        let retrieved = Payload::retrieve_id(payload.id, &pool).await.unwrap();
+        assert_eq!(retrieved.summary.as_ref(), Some(&summary));
+        let status = Payload::retrieve_status(payload.id, &pool).await.unwrap();
+        assert_eq!(status.summary, Some(summary));
+    }
+
+    #[tokio::test]
+    async fn test_queue_estimate() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let pool = crate::datasource::db::init_payload_db(db_path.to_str().unwrap()).await;
+
+        let add = |service: &str| {
+            let mut payload = Payload::new();
+            payload.service = Some(service.to_string());
+            payload
+        };
+        // Three runs of `svc`, the median took 100 seconds, one of `other`
+        for (service, secs) in [("svc", 100), ("svc", 50), ("svc", 200), ("other", 5000)] {
+            let mut payload = add(service);
+            payload.add_to_db(&pool).await.unwrap();
+            payload.update_status(Status::Running, &pool).await.unwrap();
+            sqlx::query("UPDATE payloads SET status_since = datetime('now', ?) WHERE id = ?")
+                .bind(format!("-{secs} seconds"))
+                .bind(payload.id)
+                .execute(&pool)
+                .await
+                .unwrap();
+            payload
+                .update_status(Status::Completed, &pool)
+                .await
+                .unwrap();
+        }
+
+        let mut first = add("svc");
+        first.add_to_db(&pool).await.unwrap();
+        first.update_status(Status::Prepared, &pool).await.unwrap();
+        let mut second = add("svc");
+        second.add_to_db(&pool).await.unwrap();
+        second.update_status(Status::Prepared, &pool).await.unwrap();
+
+        let estimate = second.queue_estimate(&pool).await.unwrap();
+        assert_eq!(estimate.position, 2);
+        assert_eq!(estimate.eta_secs, Some(100));
+        assert_eq!(first.queue_estimate(&pool).await.unwrap().position, 1);
+
+        // Nothing ran yet for this one
+        let mut unknown = add("new");
+        unknown.add_to_db(&pool).await.unwrap();
+        unknown
+            .update_status(Status::Prepared, &pool)
+            .await
+            .unwrap();
+        let estimate = unknown.queue_estimate(&pool).await.unwrap();
+        assert_eq!(estimate.position, 3);
+        assert_eq!(estimate.eta_secs, None);
+    }
+
+    #[tokio::test]
+    async fn test_list_expired() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let pool = crate::datasource::db::init_payload_db(db_path.to_str().unwrap()).await;
+
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        let mut ids = Vec::new();
+        for expires_at in [None, Some(now - 60), Some(now + 3600)] {
+            let mut payload = Payload::new();
+            payload.expires_at = expires_at;
+            payload.add_to_db(&pool).await.unwrap();
+            payload
+                .update_status(Status::Completed, &pool)
+                .await
+                .unwrap();
+            ids.push(payload.id);
+        }
+        // Expired, but gone already
+        let mut cleaned = Payload::new();
+        cleaned.expires_at = Some(now - 60);
+        cleaned.add_to_db(&pool).await.unwrap();
+        cleaned.update_status(Status::Cleaned, &pool).await.unwrap();
+
+        let expired = Payload::list_expired(&pool).await.unwrap();
+        let expired: Vec<_> = expired.iter().map(|p| (p.id, p.expires_at)).collect();
+        assert_eq!(expired, vec![(ids[1], Some(now - 60))]);
+    }
+
+    #[tokio::test]
+    async fn test_retrieve_status() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let pool = crate::datasource::db::init_payload_db(db_path.to_str().unwrap()).await;
+
+        let mut payload = Payload::new();
+        payload.add_to_db(&pool).await.unwrap();
+        payload.update_status(Status::Running, &pool).await.unwrap();
+        sqlx::query("UPDATE payloads SET status_since = datetime('now', '-10 minutes')")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let status = Payload::retrieve_status(payload.id, &pool).await.unwrap();
+        assert_eq!(status.id, payload.id);
+        assert_eq!(status.status, Status::Running);
+        assert!(!status.progress.killed);
+        assert!((600..660).contains(&status.progress.elapsed_secs));
+
+        // Back to zero on the next transition
+        payload
+            .update_status(Status::Completed, &pool)
+            .await
+            .unwrap();
+        let status = Payload::retrieve_status(payload.id, &pool).await.unwrap();
+        assert_eq!(status.status, Status::Completed);
+        assert!(status.progress.elapsed_secs < 60);
+
+        assert!(matches!(
+            Payload::retrieve_status(999, &pool).await,
+            Err(sqlx::Error::RowNotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_list_older_than() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let pool = crate::datasource::db::init_payload_db(db_path.to_str().unwrap()).await;
+
+        let mut ids = Vec::new();
+        for status in [Status::Running, Status::Cleaned, Status::Completed] {
+            let mut payload = Payload::new();
+            payload.add_to_db(&pool).await.unwrap();
+            payload.update_status(status, &pool).await.unwrap();
+            ids.push(payload.id);
+        }
+        sqlx::query("UPDATE payloads SET created_at = datetime('now', '-1 hour') WHERE id != ?")
+            .bind(ids[2])
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let old = Payload::list_older_than(Duration::from_secs(600), &pool)
+            .await
+            .unwrap();
+        assert_eq!(old, vec![ids[0]]);
+        let all = Payload::list_older_than(Duration::ZERO, &pool)
+            .await
+            .unwrap();
+        assert_eq!(all, vec![ids[0], ids[2]]);
+    }
 }
@@ -1,10 +1,18 @@
 use super::job_dao::Job;
+use crate::models::load_dto::LoadReport;
 use crate::{config::loader::Config, models::payload_dao::Payload};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use utoipa::ToSchema;
 
 #[derive(Debug)]
 pub struct Queue<'a> {
     pub jobs: Vec<Job>,
     pub config: &'a Config,
+    /// Services whose jobs `load` leaves queued, e.g. the ones found down
+    pub skip: HashSet<String>,
+    /// Users whose queued jobs `load` left waiting for their per-user quota
+    pub throttled: Vec<Throttled>,
 }
 
 impl Queue<'_> {
@@ -12,10 +20,24 @@ impl Queue<'_> {
         Queue {
             jobs: Vec::new(),
             config,
+            skip: HashSet::new(),
+            throttled: Vec::new(),
         }
     }
 }
 
+/// A user with queued jobs on a service and no slot left under its
+/// `runs_per_user` quota
+#[derive(Debug, PartialEq, Serialize, ToSchema)]
+pub struct Throttled {
+    pub tenant: String,
+    pub user_id: i64,
+    pub service: String,
+    /// Jobs counted against the quota
+    pub active: u16,
+    pub runs_per_user: u16,
+}
+
 #[derive(Debug)]
 pub struct PayloadQueue<'a> {
     pub jobs: Vec<Payload>,
@@ -31,6 +53,35 @@ impl PayloadQueue<'_> {
     }
 }
 
+/// Everything the scheduler bases its decisions on at one point in time,
+/// dumped to reproduce a stuck queue
+#[derive(Debug, Serialize, ToSchema)]
+pub struct QueueSnapshot {
+    /// Seconds since the epoch
+    pub taken_at: u64,
+    /// Jobs not in a final status
+    pub jobs: Vec<Job>,
+    /// Queued jobs the sender would pick next, before the disk space check
+    pub next: Vec<u32>,
+    /// Users whose queued jobs wait for their per-user quota
+    pub throttled: Vec<Throttled>,
+    pub services: BTreeMap<String, ServiceSnapshot>,
+}
+
+/// Scheduler view of one configured service
+#[derive(Debug, PartialEq, Serialize, ToSchema)]
+pub struct ServiceSnapshot {
+    pub queued: usize,
+    /// Jobs counted against `max_runs`
+    pub active: usize,
+    pub max_runs: u16,
+    pub runs_per_user: u16,
+    /// What the client reported under `GET /load`
+    pub load: Option<LoadReport>,
+    /// Why the client could not be asked for its load
+    pub load_error: Option<String>,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -45,6 +96,7 @@ mod tests {
             data_path: "/test/data".to_string(),
             max_age: Duration::from_secs(3600),
             port: 1111,
+            ..Default::default()
         }
     }
 
@@ -1,9 +1,19 @@
 use std::path::{Path, PathBuf};
 
 use super::{queue_dao::Queue, status_dto::Status};
-use crate::models::{job_dao::Job, payload_dao::Payload, queue_dao::PayloadQueue};
-use sqlx::{Row, SqlitePool};
-use std::collections::HashMap;
+use crate::models::{
+    job_dao::Job,
+    payload_dao::Payload,
+    payload_dto::inputs_from_json,
+    queue_dao::{PayloadQueue, Throttled},
+    user_dao::DEFAULT_TIER,
+};
+use sqlx::{Row, SqliteConnection, SqlitePool};
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+/// Longest a job stays claimed by a round that did not send it nor release it
+pub const CLAIM_TTL: Duration = Duration::from_secs(600);
 
 impl Queue<'_> {
     pub async fn list_per_status(
@@ -11,59 +21,128 @@ impl Queue<'_> {
         statuses: Vec<Status>,
         pool: &SqlitePool,
     ) -> Result<(), sqlx::Error> {
+        self.fetch_per_status(statuses, false, pool).await
+    }
+
+    /// Like [`Queue::list_per_status`], leaving out the jobs whose failed
+    /// download is not due for a retry yet
+    pub async fn list_due_per_status(
+        &mut self,
+        statuses: Vec<Status>,
+        pool: &SqlitePool,
+    ) -> Result<(), sqlx::Error> {
+        self.fetch_per_status(statuses, true, pool).await
+    }
+
+    async fn fetch_per_status(
+        &mut self,
+        statuses: Vec<Status>,
+        due_only: bool,
+        pool: &SqlitePool,
+    ) -> Result<(), sqlx::Error> {
+        if let Some(chaos) = &self.config.chaos {
+            chaos.db_fault()?;
+        }
         let mut qb = sqlx::QueryBuilder::new("SELECT * FROM jobs WHERE status IN (");
         let mut sep = qb.separated(", ");
         for s in &statuses {
             sep.push_bind(s.to_string());
         }
         qb.push(")");
+        if due_only {
+            qb.push(" AND (retry_at IS NULL OR retry_at <= datetime('now'))");
+        }
 
         let rows = qb.build().fetch_all(pool).await?;
 
-        let jobs: Vec<Job> = rows
-            .into_iter()
-            .map(|row| {
-                let status: String = row.get("status");
-                let loc: String = row.get("loc");
-                let dest_id: u32 = row.get("dest_id");
-                Job {
-                    id: row.get("id"),
-                    user_id: row.get("user_id"),
-                    service: row.get("service"),
-                    status: Status::from_string(&status),
-                    loc: PathBuf::from(loc),
-                    dest_id,
-                }
-            })
-            .collect();
+        let jobs: Vec<Job> = rows.into_iter().map(|row| Job::from_row(&row)).collect();
         self.jobs = jobs;
         Ok(())
     }
 
+    /// Pick the queued jobs to send this round, at most `SENDER_BATCH_SIZE`,
+    /// and claim them in the same transaction so another round can not pick
+    /// them too. Sending a job or putting it back to `Queued` ends its claim,
+    /// see [`Queue::release`] for the ones left unsent.
     pub async fn load(&mut self, pool: &SqlitePool) -> Result<(), sqlx::Error> {
+        // Taking the write lock up front, a read upgraded to a write fails
+        // outright when another connection wrote in between
+        let mut tx = pool.begin_with("BEGIN IMMEDIATE").await?;
+        self.pick(&mut tx).await?;
+        if !self.jobs.is_empty() {
+            let mut qb = sqlx::QueryBuilder::new(
+                "UPDATE jobs SET claimed_at = CURRENT_TIMESTAMP WHERE status = ",
+            );
+            qb.push_bind(Status::Queued.to_string());
+            qb.push(" AND id IN (");
+            let mut sep = qb.separated(", ");
+            for j in &self.jobs {
+                sep.push_bind(j.id);
+            }
+            qb.push(") RETURNING id");
+            let claimed: HashSet<u32> = qb
+                .build_query_scalar()
+                .fetch_all(&mut *tx)
+                .await?
+                .into_iter()
+                .collect();
+            self.jobs.retain(|j| claimed.contains(&j.id));
+        }
+        tx.commit().await
+    }
+
+    /// The jobs [`Queue::load`] would pick, without claiming them
+    pub async fn preview(&mut self, pool: &SqlitePool) -> Result<(), sqlx::Error> {
+        let mut conn = pool.acquire().await?;
+        self.pick(&mut conn).await
+    }
+
+    /// End the claim on the loaded jobs not sent after all, so the next round
+    /// picks them again
+    pub async fn release(ids: &[u32], pool: &SqlitePool) -> Result<(), sqlx::Error> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        let mut qb = sqlx::QueryBuilder::new("UPDATE jobs SET claimed_at = NULL WHERE id IN (");
+        let mut sep = qb.separated(", ");
+        for id in ids {
+            sep.push_bind(id);
+        }
+        qb.push(")");
+        qb.build().execute(pool).await?;
+        Ok(())
+    }
+
+    async fn pick(&mut self, conn: &mut SqliteConnection) -> Result<(), sqlx::Error> {
         // Clear the job list before adding new ones to make sure there are no stales
         self.jobs = Vec::new();
+        self.throttled = Vec::new();
+        if let Some(chaos) = &self.config.chaos {
+            chaos.db_fault()?;
+        }
+        let batch = self.config.sender_batch;
 
         // ===========================================================================================
         // Step 1a: get how many jobs have been submitted to the service per user
         let submitted_rows = sqlx::query(
-            "SELECT user_id, service, COUNT(*) as count FROM jobs WHERE status IN ('processing', 'submitted', 'running') GROUP BY user_id, service"
+            "SELECT tenant, user_id, service, COUNT(*) as count FROM jobs WHERE status IN ('processing', 'submitted', 'prepared', 'running') GROUP BY tenant, user_id, service"
         )
-        .fetch_all(pool)
+        .fetch_all(&mut *conn)
         .await?;
-        let mut submitted_counts: HashMap<(i64, String), u16> = HashMap::new();
+        let mut submitted_counts: HashMap<(String, i64, String), u16> = HashMap::new();
         for row in submitted_rows {
+            let tenant: String = row.get("tenant");
             let user_id: i64 = row.get("user_id");
             let service: String = row.get("service");
             let count: i64 = row.get("count");
-            submitted_counts.insert((user_id, service), count as u16);
+            submitted_counts.insert((tenant, user_id, service), count as u16);
         }
 
         // Step 1b: get submitted job counts per service (for max_runs limit)
         let submitted_service_rows = sqlx::query(
-            "SELECT service, COUNT(*) as count FROM jobs WHERE status IN ('processing', 'submitted', 'running')  GROUP BY service"
+            "SELECT service, COUNT(*) as count FROM jobs WHERE status IN ('processing', 'submitted', 'prepared', 'running')  GROUP BY service"
         )
-        .fetch_all(pool)
+        .fetch_all(&mut *conn)
         .await?;
         let mut submitted_service_counts: HashMap<String, u16> = HashMap::new();
         for row in submitted_service_rows {
@@ -72,47 +151,109 @@ impl Queue<'_> {
             submitted_service_counts.insert(service, count as u16);
         }
 
-        // ===========================================================================================
-        // Step 2: Get all QUEUED jobs and group them by service, then by user
-        let rows = sqlx::query("SELECT * FROM jobs WHERE status = ?")
-            .bind(Status::Queued.to_string())
-            .fetch_all(pool)
+        // Step 1c: get submitted job counts per tenant (for the tenant max_runs limit)
+        let submitted_tenant_rows = sqlx::query(
+            "SELECT tenant, COUNT(*) as count FROM jobs WHERE status IN ('processing', 'submitted', 'prepared', 'running') GROUP BY tenant"
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+        let mut submitted_tenant_counts: HashMap<String, usize> = HashMap::new();
+        for row in submitted_tenant_rows {
+            let tenant: String = row.get("tenant");
+            let count: i64 = row.get("count");
+            submitted_tenant_counts.insert(tenant, count as usize);
+        }
+
+        // Step 1d: get the tier of every user and those overriding the per-user quota
+        let user_rows = sqlx::query("SELECT id, tier, runs_per_user FROM users")
+            .fetch_all(&mut *conn)
             .await?;
+        let mut user_overrides: HashMap<i64, u16> = HashMap::new();
+        let mut user_tiers: HashMap<i64, String> = HashMap::new();
+        for row in user_rows {
+            let user_id: i64 = row.get("id");
+            if let Some(runs_per_user) = row.get::<Option<u16>, _>("runs_per_user") {
+                user_overrides.insert(user_id, runs_per_user);
+            }
+            user_tiers.insert(user_id, row.get("tier"));
+        }
+
+        // Step 1e: get submitted job counts per tier (for the tier max_runs limit)
+        let submitted_tier_rows = sqlx::query(
+            "SELECT users.tier, COUNT(*) as count FROM jobs JOIN users ON users.id = jobs.user_id WHERE jobs.status IN ('processing', 'submitted', 'prepared', 'running') GROUP BY users.tier"
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+        let mut submitted_tier_counts: HashMap<String, usize> = HashMap::new();
+        for row in submitted_tier_rows {
+            let tier: String = row.get("tier");
+            let count: i64 = row.get("count");
+            submitted_tier_counts.insert(tier, count as usize);
+        }
+
+        // ===========================================================================================
+        // Step 2: Get the QUEUED jobs of the services with free slots, the
+        // first `batch` of each user at most: no more could be sent this round
+        let open: Vec<&String> = self
+            .config
+            .services
+            .iter()
+            .filter(|(name, s)| {
+                !self.skip.contains(*name)
+                    && *submitted_service_counts.get(*name).unwrap_or(&0) < s.max_runs
+            })
+            .map(|(name, _)| name)
+            .collect();
+        if open.is_empty() {
+            return Ok(());
+        }
+        let mut qb = sqlx::QueryBuilder::new(
+            "SELECT * FROM (SELECT *, ROW_NUMBER() OVER (PARTITION BY service, tenant, user_id ORDER BY priority DESC, id) AS turn FROM jobs WHERE status = ",
+        );
+        qb.push_bind(Status::Queued.to_string());
+        // A claim left by a round that never finished, e.g. on a crash, expires
+        qb.push(format!(
+            " AND (claimed_at IS NULL OR claimed_at <= datetime('now', '-{} seconds'))",
+            CLAIM_TTL.as_secs()
+        ));
+        // Uploads that failed wait for their backoff
+        qb.push(" AND (retry_at IS NULL OR retry_at <= datetime('now'))");
+        qb.push(" AND service IN (");
+        let mut sep = qb.separated(", ");
+        for service in open {
+            sep.push_bind(service.as_str());
+        }
+        qb.push(")) WHERE turn <= ");
+        qb.push_bind(batch as i64);
+        qb.push(" ORDER BY priority DESC, id");
+        let rows = qb.build().fetch_all(&mut *conn).await?;
 
-        // Group queued jobs: service -> user_id -> Vec<Job>
-        let mut service_user_jobs: HashMap<String, HashMap<i64, Vec<Job>>> = HashMap::new();
+        // Group queued jobs: service -> (tenant, user_id) -> Vec<Job>
+        let mut service_user_jobs: HashMap<String, HashMap<(String, i64), Vec<Job>>> =
+            HashMap::new();
 
         for row in rows {
             let user_id: i64 = row.get("user_id");
             let service: String = row.get("service");
-            let status: String = row.get("status");
-            let loc: String = row.get("loc");
-            let job = Job {
-                id: row.get("id"),
-                user_id: user_id.try_into().unwrap(),
-                service: service.clone(),
-                status: Status::from_string(&status),
-                loc: PathBuf::from(loc),
-                dest_id: row.get("dest_id"),
-            };
+            let job = Job::from_row(&row);
 
             service_user_jobs
                 .entry(service)
                 .or_default()
-                .entry(user_id)
+                .entry((job.tenant.clone(), user_id))
                 .or_default()
                 .push(job);
         }
 
         // ===========================================================================================
-        // Step 3: For each service, apply round-robin distribution among users
+        // Step 3: For each service, apply round-robin distribution among users,
+        // a user gets as many jobs per turn as the weight of their tier
         for (service, user_jobs_map) in service_user_jobs {
             // Get quotas from config
             let config_service = match self.config.services.get(&service) {
                 Some(s) => s,
                 None => continue, // Skip services not in config
             };
-            let quota_per_user = config_service.runs_per_user;
             let quota_total = config_service.max_runs;
 
             // Get current counts
@@ -124,41 +265,125 @@ impl Queue<'_> {
                 continue; // Service has no available slots
             }
 
-            // Build list of users with their queued jobs and available slots
-            let mut users: Vec<(i64, Vec<Job>, usize)> = user_jobs_map
+            // Build list of users with their tier, queued jobs and available slots
+            let throttled = &mut self.throttled;
+            let mut users: Vec<(String, String, usize, Vec<Job>, usize)> = user_jobs_map
                 .into_iter()
-                .map(|(user_id, jobs)| {
+                .map(|((tenant, user_id), jobs)| {
+                    let tier = user_tiers
+                        .get(&user_id)
+                        .cloned()
+                        .unwrap_or_else(|| DEFAULT_TIER.to_string());
+                    let weight = self.config.tier_weight(&tier);
+                    // A user override wins over the tenant and service quotas
+                    let quota_per_user = match user_overrides.get(&user_id) {
+                        Some(q) => *q,
+                        None => self.config.runs_per_user_for(&tenant, config_service),
+                    };
                     let user_submitted = submitted_counts
-                        .get(&(user_id, service.clone()))
+                        .get(&(tenant.clone(), user_id, service.clone()))
                         .unwrap_or(&0);
                     let available_user_slots =
                         (quota_per_user as usize).saturating_sub(*user_submitted as usize);
-                    (user_id, jobs, available_user_slots)
+                    if available_user_slots == 0 {
+                        throttled.push(Throttled {
+                            tenant: tenant.clone(),
+                            user_id,
+                            service: service.clone(),
+                            active: *user_submitted,
+                            runs_per_user: quota_per_user,
+                        });
+                    }
+                    (tenant, tier, weight, jobs, available_user_slots)
                 })
-                .filter(|(_, _, slots)| *slots > 0)
+                .filter(|(_, _, _, _, slots)| *slots > 0)
                 .collect();
 
+            // Heavier tiers take their turn first
+            users.sort_by_key(|u| std::cmp::Reverse(u.2));
+
+            // Tenants and tiers may cap their active jobs across all services
+            let has_room = |tenant: &String,
+                            tier: &String,
+                            tenant_counts: &HashMap<String, usize>,
+                            tier_counts: &HashMap<String, usize>| {
+                let tenant_submitted = tenant_counts.get(tenant).unwrap_or(&0);
+                let tenant_has_room = match self.config.tenants.get(tenant) {
+                    Some(t) => t
+                        .max_runs
+                        .is_none_or(|max| *tenant_submitted < max as usize),
+                    None => true,
+                };
+                let tier_submitted = tier_counts.get(tier).unwrap_or(&0);
+                let tier_has_room = self
+                    .config
+                    .tier_max_runs(tier)
+                    .is_none_or(|max| *tier_submitted < max as usize);
+                tenant_has_room && tier_has_room
+            };
+
             // never remove users, just skip them - this will ensure all users get a slot
             let mut service_count = 0;
             let mut index = 0;
+            let mut taken_in_turn = 0;
             let users_len = users.len();
 
-            while service_count < available_service_slots {
+            while service_count < available_service_slots && self.jobs.len() < batch {
+                // Users take turns among those whose next job has the highest
+                // priority, the others wait until these are sent
+                let Some(top) = users
+                    .iter()
+                    .filter(|(tenant, tier, _, jobs, slots)| {
+                        !jobs.is_empty()
+                            && *slots > 0
+                            && has_room(
+                                tenant,
+                                tier,
+                                &submitted_tenant_counts,
+                                &submitted_tier_counts,
+                            )
+                    })
+                    .map(|(_, _, _, jobs, _)| jobs[0].priority)
+                    .max()
+                else {
+                    break;
+                };
+
                 let mut found = false;
                 for _ in 0..users_len {
                     let user_index = index % users_len;
-                    let (_, ref mut jobs, ref mut available_slots) = users[user_index];
+                    let (ref tenant, ref tier, weight, ref mut jobs, ref mut available_slots) =
+                        users[user_index];
 
-                    if !jobs.is_empty() && *available_slots > 0 {
+                    if !jobs.is_empty()
+                        && jobs[0].priority == top
+                        && *available_slots > 0
+                        && has_room(
+                            tenant,
+                            tier,
+                            &submitted_tenant_counts,
+                            &submitted_tier_counts,
+                        )
+                    {
                         let job = jobs.remove(0);
+                        *submitted_tenant_counts.entry(tenant.clone()).or_default() += 1;
+                        *submitted_tier_counts.entry(tier.clone()).or_default() += 1;
                         self.jobs.push(job);
                         service_count += 1;
                         *available_slots -= 1;
                         found = true;
-                        index = (user_index + 1) % users_len; // Move to next user
+                        taken_in_turn += 1;
+                        // Move to next user once the tier weight is used up
+                        if taken_in_turn >= weight {
+                            taken_in_turn = 0;
+                            index = (user_index + 1) % users_len;
+                        } else {
+                            index = user_index;
+                        }
                         break;
                     }
 
+                    taken_in_turn = 0;
                     index += 1;
                 }
 
@@ -196,6 +421,18 @@ impl PayloadQueue<'_> {
                 payload.set_status(Status::from_string(&status));
                 payload.pid = row.get("pid");
                 payload.killed = row.get("killed");
+                payload.tenant = row.get("tenant");
+                payload.sandbox = row.get::<String, _>("sandbox").parse().unwrap_or_default();
+                payload.max_output_size = row
+                    .get::<Option<i64>, _>("max_output_size")
+                    .map(|s| s.max(0) as u64);
+                payload.inputs = inputs_from_json(row.get("inputs"));
+                payload.stdout_log = row
+                    .get::<Option<String>, _>("stdout_log")
+                    .map(PathBuf::from);
+                payload.stderr_log = row
+                    .get::<Option<String>, _>("stderr_log")
+                    .map(PathBuf::from);
                 // Use loc from database, or fall back to constructed path for backwards compatibility
                 let loc_path = loc
                     .map(PathBuf::from)
@@ -213,9 +450,11 @@ impl PayloadQueue<'_> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::loader::{Config, Service};
+    use crate::config::loader::{Config, DEFAULT_TENANT, Service, Tenant, Tier};
     use crate::models::job_dto::create_jobs_table;
     use crate::models::payload_dto::create_payload_table;
+    use crate::models::user_dao::User;
+    use crate::utils::chaos::ChaosConfig;
 
     #[tokio::test]
     async fn test_list_per_status_jobs() {
@@ -232,10 +471,14 @@ mod tests {
                 terminate_url: "http://example.com/terminate".to_string(),
                 runs_per_user: 5,
                 max_runs: 1,
+                ..Default::default()
             },
         );
 
         create_jobs_table(&pool).await.unwrap();
+        for id in 1..=5 {
+            User::add_if_missing(id, &pool).await.unwrap();
+        }
 
         sqlx::query("INSERT INTO jobs (user_id, service, status, loc, dest_id) VALUES (1, 'svc', 'submitted', '/tmp/a', NULL)")
             .execute(&pool).await.unwrap();
@@ -254,6 +497,118 @@ mod tests {
         assert!(queue.jobs.iter().any(|j| j.status == Status::Submitted));
         assert!(queue.jobs.iter().any(|j| j.status == Status::Running));
         assert!(queue.jobs.iter().all(|j| j.status != Status::Queued));
+
+        // A failed download waits for its retry
+        sqlx::query("UPDATE jobs SET retry_at = datetime('now', '+1 hour') WHERE id = 2")
+            .execute(&pool)
+            .await
+            .unwrap();
+        queue
+            .list_due_per_status(vec![Status::Submitted, Status::Running], &pool)
+            .await
+            .unwrap();
+        assert_eq!(queue.jobs.len(), 1);
+        assert_eq!(queue.jobs[0].status, Status::Submitted);
+    }
+
+    #[tokio::test]
+    async fn test_queue_chaos_db_failure() {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        create_jobs_table(&pool).await.unwrap();
+        let config = Config {
+            chaos: Some(ChaosConfig {
+                db_failure_rate: 1.0,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let mut queue = Queue::new(&config);
+        assert!(matches!(
+            queue.load(&pool).await,
+            Err(sqlx::Error::PoolTimedOut)
+        ));
+        assert!(matches!(
+            queue.list_per_status(vec![Status::Queued], &pool).await,
+            Err(sqlx::Error::PoolTimedOut)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_load_claims_a_batch() {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        let mut config = Config::new().unwrap();
+        config.sender_batch = 4;
+        config.services.insert(
+            "service".to_string(),
+            Service {
+                name: "service".to_string(),
+                runs_per_user: 10,
+                max_runs: 20,
+                ..Default::default()
+            },
+        );
+        create_jobs_table(&pool).await.unwrap();
+        for user_id in [1, 2] {
+            User::add_if_missing(user_id, &pool).await.unwrap();
+            for i in 0..5 {
+                sqlx::query("INSERT INTO jobs (user_id, service, status, loc) VALUES (?, 'service', 'queued', ?)")
+                    .bind(user_id)
+                    .bind(format!("loc{user_id}-{i}"))
+                    .execute(&pool)
+                    .await
+                    .unwrap();
+            }
+        }
+        let claimed = || async {
+            sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM jobs WHERE claimed_at IS NOT NULL")
+                .fetch_one(&pool)
+                .await
+                .unwrap()
+        };
+        let ids = |queue: &Queue| queue.jobs.iter().map(|j| j.id).collect::<Vec<_>>();
+
+        // A preview claims nothing
+        let mut queue = Queue::new(&config);
+        queue.preview(&pool).await.unwrap();
+        assert_eq!(queue.jobs.len(), 4);
+        assert_eq!(claimed().await, 0);
+
+        // Each user gets half of the batch, and the next round the next jobs
+        queue.load(&pool).await.unwrap();
+        let first = ids(&queue);
+        assert_eq!(first.len(), 4);
+        assert_eq!(queue.jobs.iter().filter(|j| j.user_id == 1).count(), 2);
+        assert_eq!(claimed().await, 4);
+        let mut next = Queue::new(&config);
+        next.load(&pool).await.unwrap();
+        assert_eq!(next.jobs.len(), 4);
+        assert!(ids(&next).iter().all(|id| !first.contains(id)));
+        next.load(&pool).await.unwrap();
+        assert_eq!(next.jobs.len(), 2);
+        next.load(&pool).await.unwrap();
+        assert!(next.jobs.is_empty());
+
+        // Released and requeued jobs are picked again
+        Queue::release(&first[..3], &pool).await.unwrap();
+        queue.jobs[3]
+            .update_status(Status::Queued, &pool)
+            .await
+            .unwrap();
+        next.load(&pool).await.unwrap();
+        let mut again = ids(&next);
+        again.sort();
+        let mut first = first;
+        first.sort();
+        assert_eq!(again, first);
+
+        // Skipped services keep their jobs queued
+        Queue::release(&ids(&next), &pool).await.unwrap();
+        let mut skipping = Queue::new(&config);
+        skipping.skip.insert("service".to_string());
+        skipping.load(&pool).await.unwrap();
+        assert!(skipping.jobs.is_empty());
+        assert_eq!(claimed().await, 6);
     }
 
     #[tokio::test]
@@ -272,10 +627,14 @@ mod tests {
                 terminate_url: "http://example.com/terminate".to_string(),
                 runs_per_user: 5,
                 max_runs: 3, // Only 3 total slots for the service
+                ..Default::default()
             },
         );
 
         create_jobs_table(&pool).await.unwrap();
+        for id in 1..=5 {
+            User::add_if_missing(id, &pool).await.unwrap();
+        }
 
         // User 1 has 5 queued jobs
         for i in 0..5 {
@@ -315,6 +674,107 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_load_priority_goes_first() {
+        let pool = SqlitePool::connect(":memory:")
+            .await
+            .unwrap_or_else(|e| panic!("Database connection failed: {e}"));
+        let mut config = Config::new().unwrap();
+        config.services.insert(
+            "service".to_string(),
+            Service {
+                name: "service".to_string(),
+                upload_url: "http://example.com/upload".to_string(),
+                download_url: "http://example.com/download".to_string(),
+                terminate_url: "http://example.com/terminate".to_string(),
+                runs_per_user: 5,
+                max_runs: 3,
+                ..Default::default()
+            },
+        );
+
+        create_jobs_table(&pool).await.unwrap();
+        for id in 1..=2 {
+            User::add_if_missing(id, &pool).await.unwrap();
+        }
+
+        // User 1 queued early, user 2 later with two urgent jobs
+        for (user_id, priority) in [(1, 0), (1, 0), (1, 0), (2, 0), (2, 5), (2, 5)] {
+            sqlx::query("INSERT INTO jobs (user_id, service, status, loc, priority) VALUES (?, 'service', 'queued', 'loc', ?)")
+                .bind(user_id)
+                .bind(priority)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+
+        let mut queue = Queue::new(&config);
+        queue.load(&pool).await.unwrap();
+
+        // Both urgent jobs jump the queue, then the users take turns by age
+        let picked: Vec<(u32, i32)> = queue.jobs.iter().map(|j| (j.id, j.priority)).collect();
+        assert_eq!(picked, vec![(5, 5), (6, 5), (1, 0)]);
+    }
+
+    #[tokio::test]
+    async fn test_load_throttles_user() {
+        let pool = SqlitePool::connect(":memory:")
+            .await
+            .unwrap_or_else(|e| panic!("Database connection failed: {e}"));
+        let mut config = Config::new().unwrap();
+        config.services.insert(
+            "service".to_string(),
+            Service {
+                name: "service".to_string(),
+                upload_url: "http://example.com/upload".to_string(),
+                download_url: "http://example.com/download".to_string(),
+                terminate_url: "http://example.com/terminate".to_string(),
+                runs_per_user: 2,
+                max_runs: 10,
+                ..Default::default()
+            },
+        );
+
+        create_jobs_table(&pool).await.unwrap();
+        for id in 1..=2 {
+            User::add_if_missing(id, &pool).await.unwrap();
+        }
+
+        // A job prepared on the client takes a slot like a running one
+        for (user_id, status) in [
+            (1, "prepared"),
+            (1, "running"),
+            (1, "queued"),
+            (1, "queued"),
+            (2, "queued"),
+        ] {
+            sqlx::query(
+                "INSERT INTO jobs (user_id, service, status, loc) VALUES (?, 'service', ?, 'loc')",
+            )
+            .bind(user_id)
+            .bind(status)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+
+        let mut queue = Queue::new(&config);
+        queue.load(&pool).await.unwrap();
+
+        let picked: Vec<u32> = queue.jobs.iter().map(|j| j.id).collect();
+        assert_eq!(picked, vec![5]);
+        assert_eq!(
+            queue.throttled,
+            vec![Throttled {
+                tenant: DEFAULT_TENANT.to_string(),
+                user_id: 1,
+                service: "service".to_string(),
+                active: 2,
+                runs_per_user: 2,
+            }]
+        );
+    }
+
     #[tokio::test]
     async fn test_load_respects_max_runs_per_service() {
         // Test that max_runs limits total concurrent jobs per service
@@ -331,10 +791,14 @@ mod tests {
                 terminate_url: "http://example.com/terminate".to_string(),
                 runs_per_user: 10, // High per-user limit
                 max_runs: 2,       // But only 2 total concurrent per service
+                ..Default::default()
             },
         );
 
         create_jobs_table(&pool).await.unwrap();
+        for id in 1..=5 {
+            User::add_if_missing(id, &pool).await.unwrap();
+        }
 
         // Insert 5 submitted jobs for service "test_service" (across different users)
         for user_id in 1..=5 {
@@ -399,10 +863,14 @@ mod tests {
                 terminate_url: "http://example.com/terminate".to_string(),
                 runs_per_user: 2, // Each user can have at most 2
                 max_runs: 10,     // Service can have up to 10
+                ..Default::default()
             },
         );
 
         create_jobs_table(&pool).await.unwrap();
+        for id in 1..=5 {
+            User::add_if_missing(id, &pool).await.unwrap();
+        }
 
         // User 1 already has 2 submitted jobs (at their limit)
         for i in 0..2 {
@@ -438,6 +906,166 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_load_user_quota_override() {
+        let pool = SqlitePool::connect(":memory:")
+            .await
+            .unwrap_or_else(|e| panic!("Database connection failed: {e}"));
+        let mut config = Config::new().unwrap();
+        config.services.insert(
+            "service".to_string(),
+            Service {
+                name: "service".to_string(),
+                upload_url: "http://example.com/upload".to_string(),
+                download_url: "http://example.com/download".to_string(),
+                terminate_url: "http://example.com/terminate".to_string(),
+                runs_per_user: 1,
+                max_runs: 10,
+                ..Default::default()
+            },
+        );
+
+        create_jobs_table(&pool).await.unwrap();
+        // User 1 follows the service quota, user 2 is allowed 3 runs
+        User::add_if_missing(1, &pool).await.unwrap();
+        sqlx::query("INSERT INTO users (id, name, runs_per_user) VALUES (2, 'user2', 3)")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        for user_id in 1..=2 {
+            for i in 0..5 {
+                sqlx::query(&format!("INSERT INTO jobs (user_id, service, status, loc, dest_id) VALUES ({user_id}, 'service', 'queued', 'loc{user_id}{i}', NULL)"))
+                    .execute(&pool).await.unwrap();
+            }
+        }
+
+        let mut queue = Queue::new(&config);
+        queue.load(&pool).await.unwrap();
+
+        let count = |uid| queue.jobs.iter().filter(|j| j.user_id == uid).count();
+        assert_eq!(count(1), 1);
+        assert_eq!(count(2), 3);
+    }
+
+    #[tokio::test]
+    async fn test_load_respects_tenant_quotas() {
+        let pool = SqlitePool::connect(":memory:")
+            .await
+            .unwrap_or_else(|e| panic!("Database connection failed: {e}"));
+        let mut config = Config::new().unwrap();
+        config.services.insert(
+            "service".to_string(),
+            Service {
+                name: "service".to_string(),
+                upload_url: "http://example.com/upload".to_string(),
+                download_url: "http://example.com/download".to_string(),
+                terminate_url: "http://example.com/terminate".to_string(),
+                runs_per_user: 5,
+                max_runs: 10,
+                ..Default::default()
+            },
+        );
+        config.tenants.insert(
+            "lab".to_string(),
+            Tenant {
+                name: "lab".to_string(),
+                runs_per_user: Some(1),
+                max_runs: Some(2),
+                ..Default::default()
+            },
+        );
+
+        create_jobs_table(&pool).await.unwrap();
+        for id in 1..=5 {
+            User::add_if_missing(id, &pool).await.unwrap();
+        }
+
+        // Three users of the `lab` tenant with 3 queued jobs each
+        for user_id in 1..=3 {
+            for i in 0..3 {
+                sqlx::query(&format!("INSERT INTO jobs (user_id, service, status, loc, dest_id, tenant) VALUES ({user_id}, 'service', 'queued', 'lab{user_id}{i}', NULL, 'lab')"))
+                    .execute(&pool).await.unwrap();
+            }
+        }
+        // User 1 of the default tenant is not affected by the `lab` limits
+        for i in 0..3 {
+            sqlx::query(&format!("INSERT INTO jobs (user_id, service, status, loc, dest_id) VALUES (1, 'service', 'queued', 'default{i}', NULL)"))
+                .execute(&pool).await.unwrap();
+        }
+
+        let mut queue = Queue::new(&config);
+        queue.load(&pool).await.unwrap();
+
+        let lab_jobs: Vec<&Job> = queue.jobs.iter().filter(|j| j.tenant == "lab").collect();
+        let default_jobs = queue.jobs.len() - lab_jobs.len();
+
+        // Tenant cap of 2 active jobs, at most 1 per user
+        assert_eq!(lab_jobs.len(), 2);
+        assert_ne!(lab_jobs[0].user_id, lab_jobs[1].user_id);
+        assert_eq!(default_jobs, 3);
+    }
+
+    #[tokio::test]
+    async fn test_load_tier_weights_and_caps() {
+        let pool = SqlitePool::connect(":memory:")
+            .await
+            .unwrap_or_else(|e| panic!("Database connection failed: {e}"));
+        let mut config = Config::new().unwrap();
+        config.services.insert(
+            "service".to_string(),
+            Service {
+                name: "service".to_string(),
+                upload_url: "http://example.com/upload".to_string(),
+                download_url: "http://example.com/download".to_string(),
+                terminate_url: "http://example.com/terminate".to_string(),
+                runs_per_user: 10,
+                max_runs: 4,
+                ..Default::default()
+            },
+        );
+        config.tiers.insert(
+            "priority".to_string(),
+            Tier {
+                name: "priority".to_string(),
+                weight: 3,
+                max_runs: None,
+                ..Default::default()
+            },
+        );
+
+        create_jobs_table(&pool).await.unwrap();
+        // User 1 is a standard user, user 2 a priority one
+        User::add_if_missing(1, &pool).await.unwrap();
+        sqlx::query("INSERT INTO users (id, name, tier) VALUES (2, 'user2', 'priority')")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        for user_id in 1..=2 {
+            for i in 0..5 {
+                sqlx::query(&format!("INSERT INTO jobs (user_id, service, status, loc, dest_id) VALUES ({user_id}, 'service', 'queued', 'loc{user_id}{i}', NULL)"))
+                    .execute(&pool).await.unwrap();
+            }
+        }
+
+        let mut queue = Queue::new(&config);
+        queue.load(&pool).await.unwrap();
+
+        // The priority user gets 3 slots per turn, the standard user is not starved
+        let count = |jobs: &[Job], uid| jobs.iter().filter(|j| j.user_id == uid).count();
+        assert_eq!(count(&queue.jobs, 1), 1);
+        assert_eq!(count(&queue.jobs, 2), 3);
+
+        // Capping the tier leaves the remaining slots to the other users
+        config.tiers.get_mut("priority").unwrap().max_runs = Some(2);
+        let mut queue = Queue::new(&config);
+        queue.load(&pool).await.unwrap();
+
+        assert_eq!(count(&queue.jobs, 1), 2);
+        assert_eq!(count(&queue.jobs, 2), 2);
+    }
+
     #[tokio::test]
     async fn test_list_per_status_payloads() {
         // Setup in-memory SQLite database
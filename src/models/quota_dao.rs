@@ -0,0 +1,80 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// Limits and current usage of one service, as seen by a user
+#[derive(Serialize, Debug, PartialEq, ToSchema)]
+pub struct ServiceQuota {
+    pub service: String,
+    /// Active jobs the user may have on this service
+    pub runs_per_user: u16,
+    /// Active jobs of the user on this service
+    pub active: u16,
+    /// Active jobs the service accepts across all users
+    pub max_runs: u16,
+    /// Active jobs of all users on this service
+    pub service_active: u16,
+    /// Jobs of the user that could be dispatched right now
+    pub remaining: u16,
+}
+
+/// Quotas of a user on every service available to its tenant
+#[derive(Serialize, Debug, ToSchema)]
+pub struct Quota {
+    pub user_id: u32,
+    pub tenant: String,
+    /// Active jobs the whole tenant may have, `None` when uncapped
+    pub tenant_max_runs: Option<u16>,
+    /// Active jobs of the whole tenant
+    pub tenant_active: u16,
+    pub services: Vec<ServiceQuota>,
+}
+
+impl ServiceQuota {
+    /// The remaining slots are bound by the user, service and tenant limits
+    pub fn new(
+        service: &str,
+        (runs_per_user, active): (u16, u16),
+        (max_runs, service_active): (u16, u16),
+        tenant_remaining: u16,
+    ) -> ServiceQuota {
+        let remaining = runs_per_user
+            .saturating_sub(active)
+            .min(max_runs.saturating_sub(service_active))
+            .min(tenant_remaining);
+
+        ServiceQuota {
+            service: service.to_string(),
+            runs_per_user,
+            active,
+            max_runs,
+            service_active,
+            remaining,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_remaining() {
+        // Bound by the user limit
+        assert_eq!(
+            ServiceQuota::new("a", (5, 2), (10, 4), u16::MAX).remaining,
+            3
+        );
+        // Bound by the service limit
+        assert_eq!(
+            ServiceQuota::new("a", (5, 0), (10, 9), u16::MAX).remaining,
+            1
+        );
+        // Bound by the tenant limit
+        assert_eq!(ServiceQuota::new("a", (5, 0), (10, 0), 2).remaining, 2);
+        // Over the limit after a quota change
+        assert_eq!(
+            ServiceQuota::new("a", (2, 4), (10, 4), u16::MAX).remaining,
+            0
+        );
+    }
+}
@@ -0,0 +1,169 @@
+use crate::config::loader::Config;
+use crate::models::quota_dao::{Quota, ServiceQuota};
+use crate::models::user_dao::User;
+use sqlx::{Row, SqlitePool};
+use std::collections::HashMap;
+
+impl Quota {
+    /// Compute the quotas of a user from the active jobs, with the same
+    /// precedence as the sender: user override, then tenant, then service
+    pub async fn load(
+        user: &User,
+        config: &Config,
+        pool: &SqlitePool,
+    ) -> Result<Quota, sqlx::Error> {
+        let user_rows = sqlx::query(
+            "SELECT service, COUNT(*) as count FROM jobs WHERE user_id = ? AND tenant = ? AND status IN ('processing', 'submitted', 'prepared', 'running') GROUP BY service",
+        )
+        .bind(user.id)
+        .bind(&user.tenant)
+        .fetch_all(pool)
+        .await?;
+        let user_counts: HashMap<String, u16> = user_rows
+            .iter()
+            .map(|row| (row.get("service"), row.get::<i64, _>("count") as u16))
+            .collect();
+
+        let service_rows = sqlx::query(
+            "SELECT service, COUNT(*) as count FROM jobs WHERE status IN ('processing', 'submitted', 'prepared', 'running') GROUP BY service",
+        )
+        .fetch_all(pool)
+        .await?;
+        let service_counts: HashMap<String, u16> = service_rows
+            .iter()
+            .map(|row| (row.get("service"), row.get::<i64, _>("count") as u16))
+            .collect();
+
+        let tenant_active: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM jobs WHERE tenant = ? AND status IN ('processing', 'submitted', 'prepared', 'running')",
+        )
+        .bind(&user.tenant)
+        .fetch_one(pool)
+        .await?;
+        let tenant_active = tenant_active as u16;
+
+        let tenant_max_runs = config.tenants.get(&user.tenant).and_then(|t| t.max_runs);
+        let tenant_remaining =
+            tenant_max_runs.map_or(u16::MAX, |max| max.saturating_sub(tenant_active));
+
+        let mut services: Vec<ServiceQuota> = config
+            .services
+            .values()
+            .filter(|s| config.user_allows_service(&user.tenant, &user.tier, &s.name))
+            .map(|s| {
+                let runs_per_user = user
+                    .runs_per_user
+                    .unwrap_or_else(|| config.runs_per_user_for(&user.tenant, s));
+                ServiceQuota::new(
+                    &s.name,
+                    (runs_per_user, *user_counts.get(&s.name).unwrap_or(&0)),
+                    (s.max_runs, *service_counts.get(&s.name).unwrap_or(&0)),
+                    tenant_remaining,
+                )
+            })
+            .collect();
+        services.sort_by(|a, b| a.service.cmp(&b.service));
+
+        Ok(Quota {
+            user_id: user.id,
+            tenant: user.tenant.clone(),
+            tenant_max_runs,
+            tenant_active,
+            services,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::loader::{Service, Tenant};
+    use crate::models::job_dao::Job;
+    use crate::models::job_dto::create_jobs_table;
+    use crate::models::status_dto::Status;
+
+    fn make_config() -> Config {
+        let mut config = Config::default();
+        for (name, runs_per_user, max_runs) in [("alpha", 3, 4), ("beta", 2, 10)] {
+            config.services.insert(
+                name.to_string(),
+                Service {
+                    name: name.to_string(),
+                    runs_per_user,
+                    max_runs,
+                    ..Default::default()
+                },
+            );
+        }
+        config
+    }
+
+    async fn add_job(pool: &SqlitePool, user: &User, service: &str, status: Status) {
+        let mut job = Job::new("");
+        job.set_user_id(user.id as i32);
+        job.set_service(service.to_string());
+        job.tenant = user.tenant.clone();
+        job.add_to_db(pool).await.unwrap();
+        job.update_status(status, pool).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_load() {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        create_jobs_table(&pool).await.unwrap();
+        let mut user = User {
+            name: "user".to_string(),
+            ..Default::default()
+        };
+        user.add_to_db(&pool).await.unwrap();
+        let mut other = User {
+            name: "other".to_string(),
+            ..Default::default()
+        };
+        other.add_to_db(&pool).await.unwrap();
+
+        add_job(&pool, &user, "alpha", Status::Running).await;
+        add_job(&pool, &user, "alpha", Status::Queued).await;
+        add_job(&pool, &other, "alpha", Status::Submitted).await;
+        add_job(&pool, &other, "alpha", Status::Running).await;
+        add_job(&pool, &other, "alpha", Status::Completed).await;
+
+        let quota = Quota::load(&user, &make_config(), &pool).await.unwrap();
+        assert_eq!(quota.tenant_active, 3);
+        assert_eq!(quota.tenant_max_runs, None);
+        assert_eq!(
+            quota.services,
+            vec![
+                ServiceQuota::new("alpha", (3, 1), (4, 3), u16::MAX),
+                ServiceQuota::new("beta", (2, 0), (10, 0), u16::MAX),
+            ]
+        );
+        assert_eq!(quota.services[0].remaining, 1);
+
+        // User overrides win, tenants restrict services and cap active jobs
+        let mut config = make_config();
+        config.tenants.insert(
+            "lab".to_string(),
+            Tenant {
+                name: "lab".to_string(),
+                services: vec!["beta".to_string()],
+                max_runs: Some(1),
+                ..Default::default()
+            },
+        );
+        let mut lab_user = User {
+            name: "lab".to_string(),
+            tenant: "lab".to_string(),
+            runs_per_user: Some(7),
+            ..Default::default()
+        };
+        lab_user.add_to_db(&pool).await.unwrap();
+        add_job(&pool, &lab_user, "beta", Status::Running).await;
+
+        let quota = Quota::load(&lab_user, &config, &pool).await.unwrap();
+        assert_eq!(quota.services.len(), 1);
+        assert_eq!(quota.services[0].runs_per_user, 7);
+        assert_eq!(quota.services[0].active, 1);
+        assert_eq!(quota.services[0].remaining, 0);
+    }
+}
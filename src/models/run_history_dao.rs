@@ -0,0 +1,74 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Finished runs kept per service, the statistics only cover these
+pub const STATS_WINDOW: u32 = 100;
+
+/// How the latest finished runs of a service went, what `GET /stats` answers
+/// for each service
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ServiceRunStats {
+    pub service: String,
+    /// Finished runs counted, at most `STATS_WINDOW`
+    pub runs: u32,
+    /// Seconds taken by its completed runs, unset when none completed
+    pub p50_secs: Option<u64>,
+    pub p95_secs: Option<u64>,
+    /// Share of the runs that failed, from 0 to 1
+    pub failure_rate: f64,
+}
+
+impl ServiceRunStats {
+    /// Statistics of `runs` finished runs, `durations` being the seconds
+    /// taken by the completed ones
+    pub fn new(service: String, runs: u32, failed: u32, mut durations: Vec<u64>) -> Self {
+        durations.sort_unstable();
+        ServiceRunStats {
+            service,
+            runs,
+            p50_secs: percentile(&durations, 50),
+            p95_secs: percentile(&durations, 95),
+            failure_rate: if runs == 0 {
+                0.0
+            } else {
+                f64::from(failed) / f64::from(runs)
+            },
+        }
+    }
+}
+
+/// Nearest-rank percentile of the sorted `values`
+fn percentile(values: &[u64], p: usize) -> Option<u64> {
+    if values.is_empty() {
+        return None;
+    }
+    let rank = (values.len() * p).div_ceil(100).max(1);
+    values.get(rank - 1).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentile() {
+        assert_eq!(percentile(&[], 50), None);
+        assert_eq!(percentile(&[7], 95), Some(7));
+        assert_eq!(percentile(&[100, 200], 50), Some(100));
+        let values: Vec<u64> = (1..=100).collect();
+        assert_eq!(percentile(&values, 50), Some(50));
+        assert_eq!(percentile(&values, 95), Some(95));
+    }
+
+    #[test]
+    fn test_new() {
+        let stats = ServiceRunStats::new("svc".to_string(), 4, 1, vec![30, 10, 20]);
+        assert_eq!(stats.p50_secs, Some(20));
+        assert_eq!(stats.p95_secs, Some(30));
+        assert_eq!(stats.failure_rate, 0.25);
+
+        let failing = ServiceRunStats::new("svc".to_string(), 2, 2, Vec::new());
+        assert_eq!(failing.p50_secs, None);
+        assert_eq!(failing.failure_rate, 1.0);
+    }
+}
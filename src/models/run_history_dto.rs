@@ -0,0 +1,190 @@
+use crate::models::run_history_dao::{STATS_WINDOW, ServiceRunStats};
+use crate::models::status_dto::Status;
+use sqlx::{SqliteConnection, SqlitePool};
+use std::collections::BTreeMap;
+
+pub async fn create_run_history_table(conn: &mut SqliteConnection) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS run_history (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            service TEXT NOT NULL,
+            status TEXT NOT NULL,
+            run_secs INTEGER NOT NULL,
+            finished_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )
+    "#,
+    )
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS run_history_service ON run_history (service, id)")
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+impl ServiceRunStats {
+    /// Record that the run of payload `id` ended with `status`, dropping the
+    /// oldest runs of its service. Called before its status is updated:
+    /// payloads that were not `Running`, or sent without a service, are left out.
+    pub async fn record(id: u32, status: Status, pool: &SqlitePool) -> Result<(), sqlx::Error> {
+        let service: Option<String> = sqlx::query_scalar(
+            r#"
+            INSERT INTO run_history (service, status, run_secs)
+            SELECT service, ?, MAX(0,
+                CAST(strftime('%s', 'now') AS INTEGER)
+                - CAST(strftime('%s', COALESCE(status_since, created_at)) AS INTEGER))
+            FROM payloads WHERE id = ? AND status = ? AND service IS NOT NULL
+            RETURNING service
+            "#,
+        )
+        .bind(status.to_string())
+        .bind(id)
+        .bind(Status::Running.to_string())
+        .fetch_optional(pool)
+        .await?;
+
+        if let Some(service) = service {
+            sqlx::query(
+                r#"
+                DELETE FROM run_history WHERE service = ? AND id NOT IN (
+                    SELECT id FROM run_history WHERE service = ? ORDER BY id DESC LIMIT ?
+                )
+                "#,
+            )
+            .bind(&service)
+            .bind(&service)
+            .bind(STATS_WINDOW)
+            .execute(pool)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Statistics of every service with a finished run, by service
+    pub async fn list(pool: &SqlitePool) -> Result<BTreeMap<String, ServiceRunStats>, sqlx::Error> {
+        let rows: Vec<(String, String, i64)> =
+            sqlx::query_as("SELECT service, status, run_secs FROM run_history ORDER BY id")
+                .fetch_all(pool)
+                .await?;
+        Ok(summarize(rows))
+    }
+
+    /// Statistics of `service`, unset until one of its runs finished
+    pub async fn for_service(
+        service: &str,
+        pool: &SqlitePool,
+    ) -> Result<Option<ServiceRunStats>, sqlx::Error> {
+        let rows: Vec<(String, String, i64)> = sqlx::query_as(
+            "SELECT service, status, run_secs FROM run_history WHERE service = ? ORDER BY id",
+        )
+        .bind(service)
+        .fetch_all(pool)
+        .await?;
+        Ok(summarize(rows).remove(service))
+    }
+}
+
+/// Group the `(service, status, run_secs)` rows by service
+fn summarize(rows: Vec<(String, String, i64)>) -> BTreeMap<String, ServiceRunStats> {
+    let mut runs: BTreeMap<String, (u32, u32, Vec<u64>)> = BTreeMap::new();
+    for (service, status, secs) in rows {
+        let (total, failed, durations) = runs.entry(service).or_default();
+        *total += 1;
+        if Status::from_string(&status) == Status::Completed {
+            durations.push(secs.max(0) as u64);
+        } else {
+            *failed += 1;
+        }
+    }
+    runs.into_iter()
+        .map(|(service, (total, failed, durations))| {
+            let stats = ServiceRunStats::new(service.clone(), total, failed, durations);
+            (service, stats)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::payload_dao::Payload;
+    use crate::models::payload_dto::create_payload_table;
+
+    async fn setup_test_db() -> SqlitePool {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        create_payload_table(&pool).await.unwrap();
+        pool
+    }
+
+    /// A payload of `service` that ran for `secs` and ended with `status`
+    async fn finish(service: Option<&str>, secs: u32, status: Status, pool: &SqlitePool) {
+        let mut payload = Payload::new();
+        payload.service = service.map(str::to_string);
+        payload.add_to_db(pool).await.unwrap();
+        payload.update_status(Status::Running, pool).await.unwrap();
+        sqlx::query("UPDATE payloads SET status_since = datetime('now', ?) WHERE id = ?")
+            .bind(format!("-{secs} seconds"))
+            .bind(payload.id)
+            .execute(pool)
+            .await
+            .unwrap();
+        payload.update_status(status, pool).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_record_and_list() {
+        let pool = setup_test_db().await;
+        assert!(ServiceRunStats::list(&pool).await.unwrap().is_empty());
+
+        finish(Some("svc"), 100, Status::Completed, &pool).await;
+        finish(Some("svc"), 300, Status::Completed, &pool).await;
+        finish(Some("svc"), 5, Status::Failed, &pool).await;
+        finish(Some("svc"), 10, Status::Killed, &pool).await;
+        finish(Some("other"), 20, Status::Failed, &pool).await;
+        finish(None, 20, Status::Completed, &pool).await;
+
+        let stats = ServiceRunStats::list(&pool).await.unwrap();
+        assert_eq!(stats.len(), 2);
+        let svc = &stats["svc"];
+        assert_eq!(svc.runs, 3);
+        assert_eq!(svc.p50_secs, Some(100));
+        assert_eq!(svc.p95_secs, Some(300));
+        assert!((svc.failure_rate - 1.0 / 3.0).abs() < 1e-9);
+        assert_eq!(stats["other"].failure_rate, 1.0);
+
+        let other = ServiceRunStats::for_service("other", &pool).await.unwrap();
+        assert_eq!(other.unwrap().p50_secs, None);
+        assert!(
+            ServiceRunStats::for_service("missing", &pool)
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_record_only_running_payloads() {
+        let pool = setup_test_db().await;
+        let mut payload = Payload::new();
+        payload.service = Some("svc".to_string());
+        payload.add_to_db(&pool).await.unwrap();
+        payload.update_status(Status::Failed, &pool).await.unwrap();
+
+        assert!(ServiceRunStats::list(&pool).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_record_keeps_window() {
+        let pool = setup_test_db().await;
+        for _ in 0..STATS_WINDOW + 5 {
+            finish(Some("svc"), 1, Status::Completed, &pool).await;
+        }
+        finish(Some("other"), 1, Status::Completed, &pool).await;
+
+        let stats = ServiceRunStats::list(&pool).await.unwrap();
+        assert_eq!(stats["svc"].runs, STATS_WINDOW);
+        assert_eq!(stats["other"].runs, 1);
+    }
+}
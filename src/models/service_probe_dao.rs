@@ -0,0 +1,65 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// One check of a service's client, as stored in the `service_probes` table
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct ServiceProbe {
+    pub id: u32,
+    pub service: String,
+    /// UTC time
+    pub probed_at: String,
+    pub up: bool,
+    /// Time to the answer, unset when there was none
+    pub latency_ms: Option<u32>,
+    /// Why the probe failed
+    pub error: Option<String>,
+    /// Version the client reported, unset when it failed or reports none
+    pub version: Option<String>,
+}
+
+/// Availability of one service as seen by the prober
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct ServiceAvailability {
+    /// False once the last `PROBE_DOWN_AFTER` probes failed, the sender then
+    /// leaves its jobs queued
+    pub up: bool,
+    pub probes_last_hour: u32,
+    pub failures_last_hour: u32,
+    /// Client version reported by the last answered probe
+    pub version: Option<String>,
+    /// Latest probes, newest first
+    pub history: Vec<ServiceProbe>,
+}
+
+/// Key of the clients that report no version in the version spread
+pub const UNKNOWN_VERSION: &str = "unknown";
+
+/// How the client version policy treats a client
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum VersionStanding {
+    /// Sent jobs
+    Current,
+    /// Sent jobs, due for an upgrade
+    Deprecated,
+    /// Below the minimum version, sent no jobs
+    Outdated,
+    /// Sent no jobs
+    Blocked,
+    /// Reports no version and no minimum is set, sent jobs
+    Unknown,
+}
+
+impl VersionStanding {
+    /// Whether the sender dispatches jobs to the client
+    pub fn dispatches(self) -> bool {
+        !matches!(self, VersionStanding::Outdated | VersionStanding::Blocked)
+    }
+}
+
+/// Services whose client runs one version
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct VersionSpread {
+    pub standing: VersionStanding,
+    pub services: Vec<String>,
+}
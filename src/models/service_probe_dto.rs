@@ -0,0 +1,364 @@
+use crate::config::loader::ClientVersionPolicy;
+use crate::datasource::db::add_column_if_missing;
+use crate::models::service_probe_dao::{
+    ServiceAvailability, ServiceProbe, UNKNOWN_VERSION, VersionSpread,
+};
+use sqlx::sqlite::SqliteRow;
+use sqlx::{Row, SqliteConnection, SqlitePool};
+use std::collections::{BTreeMap, HashSet};
+use std::time::Duration;
+
+/// Probes kept per service, a day of them at the default interval
+const KEEP_PROBES: u32 = 3_000;
+
+/// Probes listed in the history of each service
+const HISTORY: u32 = 20;
+
+pub async fn create_service_probes_table(conn: &mut SqliteConnection) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS service_probes (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            service TEXT NOT NULL,
+            probed_at TEXT NOT NULL,
+            up BOOLEAN NOT NULL,
+            latency_ms INTEGER,
+            error TEXT
+        )
+    "#,
+    )
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query(
+        "CREATE INDEX IF NOT EXISTS service_probes_service ON service_probes (service, id)",
+    )
+    .execute(&mut *conn)
+    .await?;
+    add_column_if_missing(conn, "service_probes", "version", "TEXT").await?;
+    Ok(())
+}
+
+impl ServiceProbe {
+    fn from_row(row: &SqliteRow) -> ServiceProbe {
+        ServiceProbe {
+            id: row.get("id"),
+            service: row.get("service"),
+            probed_at: row.get("probed_at"),
+            up: row.get("up"),
+            latency_ms: row.get("latency_ms"),
+            error: row.get("error"),
+            version: row.get("version"),
+        }
+    }
+
+    /// Record the outcome of a probe of `service` and the client `version` it
+    /// reported, dropping its oldest ones
+    pub async fn record(
+        service: &str,
+        outcome: Result<Duration, String>,
+        version: Option<&str>,
+        pool: &SqlitePool,
+    ) -> Result<ServiceProbe, sqlx::Error> {
+        let up = outcome.is_ok();
+        let (latency, error) = match outcome {
+            Ok(latency) => (Some(latency.as_millis().min(u32::MAX as u128) as u32), None),
+            Err(e) => (None, Some(e)),
+        };
+        let row = sqlx::query(
+            r#"
+            INSERT INTO service_probes (service, probed_at, up, latency_ms, error, version)
+            VALUES (?, strftime('%Y-%m-%d %H:%M:%f', 'now'), ?, ?, ?, ?)
+            RETURNING *
+            "#,
+        )
+        .bind(service)
+        .bind(up)
+        .bind(latency)
+        .bind(error)
+        .bind(version.filter(|_| up))
+        .fetch_one(pool)
+        .await?;
+        let probe = ServiceProbe::from_row(&row);
+
+        sqlx::query("DELETE FROM service_probes WHERE service = ? AND id <= ?")
+            .bind(service)
+            .bind(probe.id.saturating_sub(KEEP_PROBES))
+            .execute(pool)
+            .await?;
+
+        Ok(probe)
+    }
+
+    /// Services whose last `down_after` probes all failed. A service never
+    /// probed, or probed fewer times, is taken as up.
+    pub async fn down(pool: &SqlitePool, down_after: u32) -> Result<HashSet<String>, sqlx::Error> {
+        sqlx::query_scalar(
+            r#"
+            SELECT service FROM (
+                SELECT service, up,
+                       ROW_NUMBER() OVER (PARTITION BY service ORDER BY id DESC) AS n
+                FROM service_probes
+            )
+            WHERE n <= ?
+            GROUP BY service
+            HAVING COUNT(*) = ? AND SUM(up) = 0
+            "#,
+        )
+        .bind(down_after)
+        .bind(down_after)
+        .fetch_all(pool)
+        .await
+        .map(|services| services.into_iter().collect())
+    }
+
+    /// Client version reported by the last answered probe of each service,
+    /// services that never answered are left out
+    pub async fn versions(
+        pool: &SqlitePool,
+    ) -> Result<BTreeMap<String, Option<String>>, sqlx::Error> {
+        let rows = sqlx::query(
+            r#"
+            SELECT service, version FROM (
+                SELECT service, version,
+                       ROW_NUMBER() OVER (PARTITION BY service ORDER BY id DESC) AS n
+                FROM service_probes
+                WHERE up
+            )
+            WHERE n = 1
+            "#,
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(rows
+            .iter()
+            .map(|row| (row.get("service"), row.get("version")))
+            .collect())
+    }
+
+    /// Services by the client version they run, and how `policy` treats it
+    pub async fn version_spread(
+        pool: &SqlitePool,
+        policy: &ClientVersionPolicy,
+    ) -> Result<BTreeMap<String, VersionSpread>, sqlx::Error> {
+        let mut spread: BTreeMap<String, VersionSpread> = BTreeMap::new();
+        for (service, version) in ServiceProbe::versions(pool).await? {
+            let key = version.as_deref().unwrap_or(UNKNOWN_VERSION).to_string();
+            spread
+                .entry(key)
+                .or_insert_with(|| VersionSpread {
+                    standing: policy.standing(version.as_deref()),
+                    services: Vec::new(),
+                })
+                .services
+                .push(service);
+        }
+        Ok(spread)
+    }
+
+    /// Availability of every service that was probed
+    pub async fn availability(
+        pool: &SqlitePool,
+        down_after: u32,
+    ) -> Result<BTreeMap<String, ServiceAvailability>, sqlx::Error> {
+        let down = ServiceProbe::down(pool, down_after).await?;
+        let mut versions = ServiceProbe::versions(pool).await?;
+
+        let counts = sqlx::query(
+            r#"
+            SELECT service,
+                   SUM(probed_at >= datetime('now', '-1 hour')) AS probes,
+                   SUM(probed_at >= datetime('now', '-1 hour') AND NOT up) AS failures
+            FROM service_probes
+            GROUP BY service
+            "#,
+        )
+        .fetch_all(pool)
+        .await?;
+        let mut availability: BTreeMap<String, ServiceAvailability> = counts
+            .iter()
+            .map(|row| {
+                let service: String = row.get("service");
+                let entry = ServiceAvailability {
+                    up: !down.contains(&service),
+                    probes_last_hour: row.get("probes"),
+                    failures_last_hour: row.get("failures"),
+                    version: versions.remove(&service).flatten(),
+                    history: Vec::new(),
+                };
+                (service, entry)
+            })
+            .collect();
+
+        let recent = sqlx::query(
+            r#"
+            SELECT * FROM (
+                SELECT *, ROW_NUMBER() OVER (PARTITION BY service ORDER BY id DESC) AS n
+                FROM service_probes
+            )
+            WHERE n <= ?
+            ORDER BY service, id DESC
+            "#,
+        )
+        .bind(HISTORY)
+        .fetch_all(pool)
+        .await?;
+        for row in &recent {
+            let probe = ServiceProbe::from_row(row);
+            if let Some(entry) = availability.get_mut(&probe.service) {
+                entry.history.push(probe);
+            }
+        }
+
+        Ok(availability)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::job_dto::create_jobs_table;
+    use crate::models::service_probe_dao::VersionStanding;
+
+    async fn setup_test_db() -> SqlitePool {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        create_jobs_table(&pool).await.unwrap();
+        pool
+    }
+
+    #[tokio::test]
+    async fn test_record() {
+        let pool = setup_test_db().await;
+
+        let up = ServiceProbe::record("a", Ok(Duration::from_millis(12)), None, &pool)
+            .await
+            .unwrap();
+        assert!(up.up);
+        assert_eq!(up.latency_ms, Some(12));
+        assert_eq!(up.error, None);
+
+        let down = ServiceProbe::record("a", Err("refused".to_string()), None, &pool)
+            .await
+            .unwrap();
+        assert!(!down.up);
+        assert_eq!(down.latency_ms, None);
+        assert_eq!(down.error.as_deref(), Some("refused"));
+    }
+
+    #[tokio::test]
+    async fn test_down() {
+        let pool = setup_test_db().await;
+        let failed = || Err("refused".to_string());
+
+        ServiceProbe::record("a", Ok(Duration::ZERO), None, &pool)
+            .await
+            .unwrap();
+        ServiceProbe::record("a", failed(), None, &pool)
+            .await
+            .unwrap();
+        ServiceProbe::record("b", failed(), None, &pool)
+            .await
+            .unwrap();
+        assert!(ServiceProbe::down(&pool, 2).await.unwrap().is_empty());
+        assert_eq!(
+            ServiceProbe::down(&pool, 1).await.unwrap(),
+            HashSet::from(["a".to_string(), "b".to_string()])
+        );
+
+        ServiceProbe::record("a", failed(), None, &pool)
+            .await
+            .unwrap();
+        assert_eq!(
+            ServiceProbe::down(&pool, 2).await.unwrap(),
+            HashSet::from(["a".to_string()])
+        );
+
+        // Back up on the first answer
+        ServiceProbe::record("a", Ok(Duration::ZERO), None, &pool)
+            .await
+            .unwrap();
+        assert!(ServiceProbe::down(&pool, 2).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_availability() {
+        let pool = setup_test_db().await;
+        assert!(
+            ServiceProbe::availability(&pool, 1)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        for _ in 0..HISTORY {
+            ServiceProbe::record("a", Ok(Duration::ZERO), None, &pool)
+                .await
+                .unwrap();
+        }
+        let last = ServiceProbe::record("a", Err("timed out".to_string()), None, &pool)
+            .await
+            .unwrap();
+        ServiceProbe::record("b", Ok(Duration::ZERO), None, &pool)
+            .await
+            .unwrap();
+
+        let availability = ServiceProbe::availability(&pool, 1).await.unwrap();
+        let a = &availability["a"];
+        assert!(!a.up);
+        assert_eq!(a.probes_last_hour, HISTORY + 1);
+        assert_eq!(a.failures_last_hour, 1);
+        assert_eq!(a.history.len(), HISTORY as usize);
+        assert_eq!(a.history[0], last);
+        assert!(availability["b"].up);
+        assert_eq!(availability["b"].history.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_versions() {
+        let pool = setup_test_db().await;
+        let answered = || Ok(Duration::ZERO);
+        ServiceProbe::record("a", answered(), Some("2.1.0"), &pool)
+            .await
+            .unwrap();
+        ServiceProbe::record("a", answered(), Some("2.2.3"), &pool)
+            .await
+            .unwrap();
+        // A failed probe keeps the version of the last answer
+        let failed = ServiceProbe::record("a", Err("refused".to_string()), Some("9.0.0"), &pool)
+            .await
+            .unwrap();
+        assert_eq!(failed.version, None);
+        ServiceProbe::record("b", answered(), Some("2.2.3"), &pool)
+            .await
+            .unwrap();
+        ServiceProbe::record("c", answered(), None, &pool)
+            .await
+            .unwrap();
+        ServiceProbe::record("d", Err("refused".to_string()), None, &pool)
+            .await
+            .unwrap();
+
+        let versions = ServiceProbe::versions(&pool).await.unwrap();
+        assert_eq!(
+            versions,
+            BTreeMap::from([
+                ("a".to_string(), Some("2.2.3".to_string())),
+                ("b".to_string(), Some("2.2.3".to_string())),
+                ("c".to_string(), None),
+            ])
+        );
+        let availability = ServiceProbe::availability(&pool, 3).await.unwrap();
+        assert_eq!(availability["a"].version.as_deref(), Some("2.2.3"));
+        assert_eq!(availability["d"].version, None);
+
+        let policy = ClientVersionPolicy {
+            deprecated: vec![semver::Version::new(2, 2, 3)],
+            ..Default::default()
+        };
+        let spread = ServiceProbe::version_spread(&pool, &policy).await.unwrap();
+        assert_eq!(spread.len(), 2);
+        assert_eq!(spread["2.2.3"].services, ["a", "b"]);
+        assert_eq!(spread["2.2.3"].standing, VersionStanding::Deprecated);
+        assert_eq!(spread[UNKNOWN_VERSION].services, ["c"]);
+        assert_eq!(spread[UNKNOWN_VERSION].standing, VersionStanding::Unknown);
+    }
+}
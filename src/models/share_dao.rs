@@ -0,0 +1,54 @@
+use crate::utils::session::random_token;
+use utoipa::ToSchema;
+
+/// A link to the results of a job handed out by its owner, as stored in the
+/// `shares` table
+#[derive(serde::Serialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct Share {
+    pub id: u32,
+    pub job_id: u32,
+    /// Secret part of the link
+    pub token: String,
+    /// Downloads allowed, unlimited when `None`
+    pub max_downloads: Option<u32>,
+    pub downloads: u32,
+    pub revoked: bool,
+    /// UTC times, set by the database
+    pub created_at: Option<String>,
+    pub expires_at: Option<String>,
+    /// Link to hand out, filled in by the server
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+impl Share {
+    pub fn new(job_id: u32, max_downloads: Option<u32>) -> Share {
+        Share {
+            id: 0,
+            job_id,
+            token: random_token(),
+            max_downloads,
+            downloads: 0,
+            revoked: false,
+            created_at: None,
+            expires_at: None,
+            url: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_new() {
+        let share = Share::new(1, Some(3));
+        assert_eq!(share.job_id, 1);
+        assert_eq!(share.max_downloads, Some(3));
+        assert_eq!(share.downloads, 0);
+        assert!(!share.revoked);
+        assert_eq!(share.token.len(), 64);
+        assert_ne!(share.token, Share::new(1, None).token);
+    }
+}
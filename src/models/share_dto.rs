@@ -0,0 +1,230 @@
+use crate::models::share_dao::Share;
+use sqlx::sqlite::SqliteRow;
+use sqlx::{Row, SqliteConnection, SqlitePool};
+use std::time::Duration;
+
+pub async fn create_shares_table(conn: &mut SqliteConnection) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS shares (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            job_id INTEGER NOT NULL REFERENCES jobs(id),
+            token TEXT NOT NULL UNIQUE,
+            max_downloads INTEGER,
+            downloads INTEGER NOT NULL DEFAULT 0,
+            revoked BOOLEAN NOT NULL DEFAULT 0,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            expires_at DATETIME NOT NULL
+        )
+    "#,
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+/// Not revoked, not expired and downloads left
+const ACTIVE: &str = "revoked = 0 AND expires_at > datetime('now') AND (max_downloads IS NULL OR downloads < max_downloads)";
+
+impl Share {
+    fn from_row(row: &SqliteRow) -> Share {
+        Share {
+            id: row.get("id"),
+            job_id: row.get("job_id"),
+            token: row.get("token"),
+            max_downloads: row.get("max_downloads"),
+            downloads: row.get("downloads"),
+            revoked: row.get("revoked"),
+            created_at: row.get("created_at"),
+            expires_at: row.get("expires_at"),
+            url: None,
+        }
+    }
+
+    /// Store the share, it stops working after `ttl`
+    pub async fn add_to_db(&mut self, ttl: Duration, pool: &SqlitePool) -> Result<(), sqlx::Error> {
+        let row = sqlx::query(
+            "INSERT INTO shares (job_id, token, max_downloads, expires_at) VALUES (?, ?, ?, datetime('now', ?)) RETURNING *",
+        )
+        .bind(self.job_id)
+        .bind(&self.token)
+        .bind(self.max_downloads)
+        .bind(format!("+{} seconds", ttl.as_secs()))
+        .fetch_one(pool)
+        .await?;
+
+        *self = Share::from_row(&row);
+
+        Ok(())
+    }
+
+    /// Shares of a job, oldest first
+    pub async fn list_for_job(job_id: u32, pool: &SqlitePool) -> Result<Vec<Share>, sqlx::Error> {
+        let rows = sqlx::query("SELECT * FROM shares WHERE job_id = ? ORDER BY id")
+            .bind(job_id)
+            .fetch_all(pool)
+            .await?;
+
+        Ok(rows.iter().map(Share::from_row).collect())
+    }
+
+    /// The share of `job_id` with this token, if it can still be used
+    pub async fn find_active(
+        job_id: u32,
+        token: &str,
+        pool: &SqlitePool,
+    ) -> Result<Option<Share>, sqlx::Error> {
+        let row = sqlx::query(&format!(
+            "SELECT * FROM shares WHERE job_id = ? AND token = ? AND {ACTIVE}"
+        ))
+        .bind(job_id)
+        .bind(token)
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(row.as_ref().map(Share::from_row))
+    }
+
+    /// Count one download, `None` when the share can't be used anymore. The
+    /// check and the count are one statement, so concurrent downloads can't
+    /// go over the limit.
+    pub async fn claim(
+        job_id: u32,
+        token: &str,
+        pool: &SqlitePool,
+    ) -> Result<Option<Share>, sqlx::Error> {
+        let row = sqlx::query(&format!(
+            "UPDATE shares SET downloads = downloads + 1 WHERE job_id = ? AND token = ? AND {ACTIVE} RETURNING *"
+        ))
+        .bind(job_id)
+        .bind(token)
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(row.as_ref().map(Share::from_row))
+    }
+
+    /// Stop the share of `job_id` from working, `None` when there is no such share
+    pub async fn revoke(
+        job_id: u32,
+        id: u32,
+        pool: &SqlitePool,
+    ) -> Result<Option<Share>, sqlx::Error> {
+        let row =
+            sqlx::query("UPDATE shares SET revoked = 1 WHERE job_id = ? AND id = ? RETURNING *")
+                .bind(job_id)
+                .bind(id)
+                .fetch_optional(pool)
+                .await?;
+
+        Ok(row.as_ref().map(Share::from_row))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::job_dao::Job;
+    use crate::models::job_dto::create_jobs_table;
+    use crate::models::user_dao::User;
+
+    async fn setup_test_db() -> (SqlitePool, Job) {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        create_jobs_table(&pool).await.unwrap();
+        User::add_if_missing(0, &pool).await.unwrap();
+        let mut job = Job::new("");
+        job.add_to_db(&pool).await.unwrap();
+        (pool, job)
+    }
+
+    #[tokio::test]
+    async fn test_claim() {
+        let (pool, job) = setup_test_db().await;
+        let mut share = Share::new(job.id, Some(2));
+        share
+            .add_to_db(Duration::from_secs(60), &pool)
+            .await
+            .unwrap();
+        assert!(share.id > 0);
+        assert!(share.expires_at.is_some());
+
+        assert!(
+            Share::find_active(job.id, &share.token, &pool)
+                .await
+                .unwrap()
+                .is_some()
+        );
+        assert!(
+            Share::find_active(job.id, "guess", &pool)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            Share::find_active(job.id + 1, &share.token, &pool)
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        for downloads in [1, 2] {
+            let claimed = Share::claim(job.id, &share.token, &pool).await.unwrap();
+            assert_eq!(claimed.unwrap().downloads, downloads);
+        }
+        assert_eq!(
+            Share::claim(job.id, &share.token, &pool).await.unwrap(),
+            None
+        );
+        assert!(
+            Share::find_active(job.id, &share.token, &pool)
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_expired_and_revoked() {
+        let (pool, job) = setup_test_db().await;
+        let mut expired = Share::new(job.id, None);
+        expired
+            .add_to_db(Duration::from_secs(0), &pool)
+            .await
+            .unwrap();
+        assert_eq!(
+            Share::claim(job.id, &expired.token, &pool).await.unwrap(),
+            None
+        );
+
+        let mut share = Share::new(job.id, None);
+        share
+            .add_to_db(Duration::from_secs(60), &pool)
+            .await
+            .unwrap();
+        assert!(
+            Share::claim(job.id, &share.token, &pool)
+                .await
+                .unwrap()
+                .is_some()
+        );
+
+        let revoked = Share::revoke(job.id, share.id, &pool)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(revoked.revoked);
+        assert_eq!(
+            Share::claim(job.id, &share.token, &pool).await.unwrap(),
+            None
+        );
+        assert_eq!(
+            Share::revoke(job.id + 1, share.id, &pool).await.unwrap(),
+            None
+        );
+
+        let shares = Share::list_for_job(job.id, &pool).await.unwrap();
+        assert_eq!(shares.len(), 2);
+        assert!(shares[1].revoked);
+        assert_eq!(shares[1].downloads, 1);
+    }
+}
@@ -7,6 +7,9 @@ pub struct StatusBody {
     pub id: u32,
     pub status: Status,
     pub message: String,
+    /// Receipt of a submission, the same for every job with identical inputs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_hash: Option<String>,
 }
 
 impl Default for StatusBody {
@@ -15,6 +18,7 @@ impl Default for StatusBody {
             id: 0,
             status: Status::Unknown,
             message: String::new(),
+            input_hash: None,
         }
     }
 }
@@ -18,6 +18,8 @@ pub enum Status {
     Unknown,    // Wildcard
     Locked,     // Job is being handled
     Killed,     // Job was manually killed
+    Held,       // Waiting for its suspended user to be restored, or for review
+    Cancelled,  // Cancelled by its owner, its payload discarded
 }
 
 impl fmt::Display for Status {
@@ -35,6 +37,8 @@ impl fmt::Display for Status {
             Status::Running => write!(f, "running"),
             Status::Locked => write!(f, "locked"),
             Status::Killed => write!(f, "killed"),
+            Status::Held => write!(f, "held"),
+            Status::Cancelled => write!(f, "cancelled"),
         }
     }
 }
@@ -53,6 +57,8 @@ impl Status {
             "running" => Status::Running,
             "locked" => Status::Locked,
             "killed" => Status::Killed,
+            "held" => Status::Held,
+            "cancelled" => Status::Cancelled,
             _ => Status::Unknown,
         }
     }
@@ -114,6 +120,16 @@ mod tests {
         assert_eq!(format!("{}", Status::Cleaned), "cleaned");
     }
 
+    #[test]
+    fn test_display_held() {
+        assert_eq!(format!("{}", Status::Held), "held");
+    }
+
+    #[test]
+    fn test_display_cancelled() {
+        assert_eq!(format!("{}", Status::Cancelled), "cancelled");
+    }
+
     #[test]
     fn test_display_running() {
         assert_eq!(format!("{}", Status::Running), "running");
@@ -132,6 +148,8 @@ mod tests {
         assert_eq!(Status::from_string("cleaned"), Status::Cleaned);
         assert_eq!(Status::from_string("prepared"), Status::Prepared);
         assert_eq!(Status::from_string("running"), Status::Running);
+        assert_eq!(Status::from_string("held"), Status::Held);
+        assert_eq!(Status::from_string("cancelled"), Status::Cancelled);
     }
 
     #[test]
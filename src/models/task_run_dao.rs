@@ -0,0 +1,66 @@
+use crate::models::service_probe_dao::{ServiceAvailability, VersionSpread};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use utoipa::ToSchema;
+
+/// Names the scheduled tasks are recorded under
+pub const SENDER_TASK: &str = "sender";
+pub const GETTER_TASK: &str = "getter";
+pub const CLEANER_TASK: &str = "cleaner";
+pub const PROBER_TASK: &str = "prober";
+
+/// What one round of a scheduled task got through
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TaskTally {
+    /// Jobs, or services for the prober, the round handled, successfully or not
+    pub processed: u32,
+    pub errors: u32,
+}
+
+impl TaskTally {
+    /// A round that could not even list its jobs
+    pub fn failed() -> TaskTally {
+        TaskTally {
+            processed: 0,
+            errors: 1,
+        }
+    }
+}
+
+/// One round of a scheduled task, as stored in the `task_runs` table
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct TaskRun {
+    pub id: u32,
+    /// e.g. `getter`
+    pub task: String,
+    /// UTC times, `finished_at` is unset while the round is going on, or
+    /// when it never returned
+    pub started_at: String,
+    pub finished_at: Option<String>,
+    pub processed: u32,
+    pub errors: u32,
+}
+
+/// Recent activity of one scheduled task
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct TaskStats {
+    pub last_run: TaskRun,
+    /// Rounds started over the last hour
+    pub runs_last_hour: u32,
+    /// Errors counted by those rounds
+    pub errors_last_hour: u32,
+    /// Start of the last round with an error, among the ones kept
+    pub last_error_at: Option<String>,
+}
+
+/// What `GET /admin/stats` answers
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SchedulerStats {
+    /// By task name, tasks that never ran are left out
+    pub tasks: BTreeMap<String, TaskStats>,
+    /// By service, services never probed are left out
+    pub services: BTreeMap<String, ServiceAvailability>,
+    /// By client version, over the services that answered a probe. Clients
+    /// reporting none are under `unknown`.
+    pub client_versions: BTreeMap<String, VersionSpread>,
+}
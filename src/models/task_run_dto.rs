@@ -0,0 +1,191 @@
+use crate::models::task_run_dao::{TaskRun, TaskStats, TaskTally};
+use sqlx::sqlite::SqliteRow;
+use sqlx::{Row, SqliteConnection, SqlitePool};
+use std::collections::BTreeMap;
+
+/// Rounds kept per task, older ones are removed as new ones start. The
+/// getter runs twice a second, this covers well over an hour of it.
+const KEEP_RUNS: u32 = 10_000;
+
+/// Timestamps with milliseconds, rounds are often shorter than a second
+const NOW: &str = "strftime('%Y-%m-%d %H:%M:%f', 'now')";
+
+pub async fn create_task_runs_table(conn: &mut SqliteConnection) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS task_runs (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            task TEXT NOT NULL,
+            started_at TEXT NOT NULL,
+            finished_at TEXT,
+            processed INTEGER NOT NULL DEFAULT 0,
+            errors INTEGER NOT NULL DEFAULT 0
+        )
+    "#,
+    )
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS task_runs_task ON task_runs (task, id)")
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+impl TaskRun {
+    fn from_row(row: &SqliteRow) -> TaskRun {
+        TaskRun {
+            id: row.get("id"),
+            task: row.get("task"),
+            started_at: row.get("started_at"),
+            finished_at: row.get("finished_at"),
+            processed: row.get("processed"),
+            errors: row.get("errors"),
+        }
+    }
+
+    /// Record that a round of `task` started, dropping its oldest rounds
+    pub async fn start(task: &str, pool: &SqlitePool) -> Result<TaskRun, sqlx::Error> {
+        let row = sqlx::query(&format!(
+            "INSERT INTO task_runs (task, started_at) VALUES (?, {NOW}) RETURNING *"
+        ))
+        .bind(task)
+        .fetch_one(pool)
+        .await?;
+        let run = TaskRun::from_row(&row);
+
+        sqlx::query("DELETE FROM task_runs WHERE task = ? AND id <= ?")
+            .bind(task)
+            .bind(run.id.saturating_sub(KEEP_RUNS))
+            .execute(pool)
+            .await?;
+
+        Ok(run)
+    }
+
+    pub async fn finish(&mut self, tally: TaskTally, pool: &SqlitePool) -> Result<(), sqlx::Error> {
+        let row = sqlx::query(&format!(
+            "UPDATE task_runs SET finished_at = {NOW}, processed = ?, errors = ? WHERE id = ? RETURNING *"
+        ))
+        .bind(tally.processed)
+        .bind(tally.errors)
+        .bind(self.id)
+        .fetch_one(pool)
+        .await?;
+
+        *self = TaskRun::from_row(&row);
+
+        Ok(())
+    }
+
+    /// Latest round and recent counts of every task that ran
+    pub async fn stats(pool: &SqlitePool) -> Result<BTreeMap<String, TaskStats>, sqlx::Error> {
+        let rows = sqlx::query(
+            r#"
+            SELECT r.*,
+                   (SELECT COUNT(*) FROM task_runs h
+                    WHERE h.task = r.task AND h.started_at >= datetime('now', '-1 hour')) AS runs_last_hour,
+                   (SELECT COALESCE(SUM(h.errors), 0) FROM task_runs h
+                    WHERE h.task = r.task AND h.started_at >= datetime('now', '-1 hour')) AS errors_last_hour,
+                   (SELECT MAX(e.started_at) FROM task_runs e
+                    WHERE e.task = r.task AND e.errors > 0) AS last_error_at
+            FROM task_runs r
+            WHERE r.id IN (SELECT MAX(id) FROM task_runs GROUP BY task)
+            "#,
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| {
+                let last_run = TaskRun::from_row(row);
+                let stats = TaskStats {
+                    runs_last_hour: row.get("runs_last_hour"),
+                    errors_last_hour: row.get("errors_last_hour"),
+                    last_error_at: row.get("last_error_at"),
+                    last_run,
+                };
+                (stats.last_run.task.clone(), stats)
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::job_dto::create_jobs_table;
+
+    async fn setup_test_db() -> SqlitePool {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        create_jobs_table(&pool).await.unwrap();
+        pool
+    }
+
+    #[tokio::test]
+    async fn test_start_and_finish() {
+        let pool = setup_test_db().await;
+
+        let mut run = TaskRun::start("getter", &pool).await.unwrap();
+        assert_eq!(run.task, "getter");
+        assert_eq!(run.finished_at, None);
+
+        let tally = TaskTally {
+            processed: 3,
+            errors: 1,
+        };
+        run.finish(tally, &pool).await.unwrap();
+        assert!(run.finished_at.as_deref() >= Some(run.started_at.as_str()));
+        assert_eq!((run.processed, run.errors), (3, 1));
+    }
+
+    #[tokio::test]
+    async fn test_stats() {
+        let pool = setup_test_db().await;
+        assert!(TaskRun::stats(&pool).await.unwrap().is_empty());
+
+        let mut failed = TaskRun::start("getter", &pool).await.unwrap();
+        failed.finish(TaskTally::failed(), &pool).await.unwrap();
+        let mut ok = TaskRun::start("getter", &pool).await.unwrap();
+        ok.finish(TaskTally::default(), &pool).await.unwrap();
+        // Still going, or hung
+        let hung = TaskRun::start("sender", &pool).await.unwrap();
+
+        let stats = TaskRun::stats(&pool).await.unwrap();
+        assert_eq!(stats.len(), 2);
+        let getter = &stats["getter"];
+        assert_eq!(getter.last_run, ok);
+        assert_eq!(getter.runs_last_hour, 2);
+        assert_eq!(getter.errors_last_hour, 1);
+        assert_eq!(getter.last_error_at, Some(failed.started_at));
+
+        let sender = &stats["sender"];
+        assert_eq!(sender.last_run, hung);
+        assert_eq!(sender.last_run.finished_at, None);
+        assert_eq!(sender.last_error_at, None);
+    }
+
+    #[tokio::test]
+    async fn test_old_runs_are_dropped() {
+        let pool = setup_test_db().await;
+        sqlx::query(
+            "INSERT INTO task_runs (id, task, started_at) VALUES (1, 'getter', '2020-01-01 00:00:00.000'), (2, 'cleaner', '2020-01-01 00:00:00.000')",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query("UPDATE sqlite_sequence SET seq = ? WHERE name = 'task_runs'")
+            .bind(KEEP_RUNS + 1)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        TaskRun::start("getter", &pool).await.unwrap();
+        let ids: Vec<u32> = sqlx::query_scalar("SELECT id FROM task_runs ORDER BY id")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        // Only rounds of the same task are dropped
+        assert_eq!(ids, vec![2, KEEP_RUNS + 2]);
+    }
+}
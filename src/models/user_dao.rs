@@ -0,0 +1,247 @@
+use crate::config::loader::DEFAULT_TENANT;
+use std::time::{Duration, SystemTime};
+use utoipa::ToSchema;
+
+pub const DEFAULT_TIER: &str = "standard";
+
+/// Which notifications about the jobs of a user pass its email on as
+/// `notification_email`, for jobs submitted without one
+#[derive(
+    serde::Serialize, serde::Deserialize, Debug, Clone, Copy, Default, PartialEq, ToSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum EmailPreference {
+    #[default]
+    Never,
+    /// Only `job.failed` and `job.invalid`
+    Failures,
+    Always,
+}
+
+impl EmailPreference {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EmailPreference::Never => "never",
+            EmailPreference::Failures => "failures",
+            EmailPreference::Always => "always",
+        }
+    }
+
+    pub fn from_string(s: &str) -> Self {
+        match s {
+            "failures" => EmailPreference::Failures,
+            "always" => EmailPreference::Always,
+            _ => EmailPreference::Never,
+        }
+    }
+}
+
+/// Defaults for the notifications about the jobs of a user, a job's own
+/// `callback_url` and `notification_email` take precedence
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Default, PartialEq, ToSchema)]
+#[serde(default)]
+pub struct NotificationPreferences {
+    /// Callback of the jobs submitted without a `callback_url`
+    pub callback_url: Option<String>,
+    pub email: EmailPreference,
+    /// `HH:MM-HH:MM` in UTC, the callbacks due in this window are held back
+    /// until it ends. It may span midnight, e.g. `22:00-07:00`.
+    pub quiet_hours: Option<String>,
+}
+
+/// Minutes into the day of `HH:MM`
+fn minute_of_day(s: &str) -> Option<u64> {
+    let (h, m) = s.trim().split_once(':')?;
+    let (h, m) = (h.parse::<u64>().ok()?, m.parse::<u64>().ok()?);
+    (h < 24 && m < 60).then_some(h * 60 + m)
+}
+
+/// Start and end of `HH:MM-HH:MM`, in minutes into the day
+fn quiet_window(s: &str) -> Option<(u64, u64)> {
+    let (start, end) = s.split_once('-')?;
+    let (start, end) = (minute_of_day(start)?, minute_of_day(end)?);
+    (start != end).then_some((start, end))
+}
+
+impl NotificationPreferences {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(url) = &self.callback_url
+            && !reqwest::Url::parse(url).is_ok_and(|u| matches!(u.scheme(), "http" | "https"))
+        {
+            return Err("Invalid callback_url, use an http or https URL".to_string());
+        }
+        if let Some(hours) = &self.quiet_hours
+            && quiet_window(hours).is_none()
+        {
+            return Err("Invalid quiet_hours, use HH:MM-HH:MM in UTC".to_string());
+        }
+        Ok(())
+    }
+
+    /// How long a callback due at `now` is held back, zero outside of the
+    /// quiet hours
+    pub fn quiet_for(&self, now: SystemTime) -> Duration {
+        let Some((start, end)) = self.quiet_hours.as_deref().and_then(quiet_window) else {
+            return Duration::ZERO;
+        };
+        let secs = now
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+            % 86400;
+        let (start, end) = (start * 60, end * 60);
+        let quiet = match start < end {
+            true => (start..end).contains(&secs),
+            // Spans midnight
+            false => secs >= start || secs < end,
+        };
+        match quiet {
+            true => Duration::from_secs((end + 86400 - secs) % 86400),
+            false => Duration::ZERO,
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, ToSchema)]
+#[serde(default)]
+pub struct User {
+    pub id: u32,
+    pub name: String,
+    pub email: Option<String>,
+    pub tier: String,
+    pub tenant: String,
+    /// Overrides the per-user quota of every service for this user
+    pub runs_per_user: Option<u16>,
+    pub enabled: bool,
+    pub notifications: NotificationPreferences,
+}
+
+impl Default for User {
+    fn default() -> Self {
+        User {
+            id: 0,
+            name: String::new(),
+            email: None,
+            tier: DEFAULT_TIER.to_string(),
+            tenant: DEFAULT_TENANT.to_string(),
+            runs_per_user: None,
+            enabled: true,
+            notifications: NotificationPreferences::default(),
+        }
+    }
+}
+
+impl User {
+    /// Check the fields a client is allowed to set
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("Name must not be empty".to_string());
+        }
+        if let Some(email) = &self.email
+            && !email.contains('@')
+        {
+            return Err("Invalid email".to_string());
+        }
+        if self.tier.trim().is_empty() {
+            return Err("Tier must not be empty".to_string());
+        }
+        if self.notifications.email != EmailPreference::Never && self.email.is_none() {
+            return Err("Email notifications need the email of the user".to_string());
+        }
+        self.notifications.validate()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn test_user(name: &str) -> User {
+        User {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_default() {
+        let user = test_user("alice");
+        assert_eq!(user.id, 0);
+        assert_eq!(user.name, "alice");
+        assert_eq!(user.tier, DEFAULT_TIER);
+        assert_eq!(user.tenant, DEFAULT_TENANT);
+        assert!(user.enabled);
+    }
+
+    #[test]
+    fn test_deserialize_defaults() {
+        let user: User = serde_json::from_str(r#"{"name": "alice"}"#).unwrap();
+        assert_eq!(user, test_user("alice"));
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(test_user("alice").validate().is_ok());
+        assert!(test_user(" ").validate().is_err());
+
+        let mut user = test_user("alice");
+        user.email = Some("not-an-email".to_string());
+        assert!(user.validate().is_err());
+
+        user.email = Some("alice@example.com".to_string());
+        assert!(user.validate().is_ok());
+
+        user.tier = String::new();
+        assert!(user.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_notifications() {
+        let mut user = test_user("alice");
+        user.notifications.email = EmailPreference::Failures;
+        assert!(user.validate().is_err());
+        user.email = Some("alice@example.com".to_string());
+        assert!(user.validate().is_ok());
+
+        user.notifications.callback_url = Some("ftp://example.com".to_string());
+        assert!(user.validate().is_err());
+        user.notifications.callback_url = Some("https://example.com/hook".to_string());
+        assert!(user.validate().is_ok());
+
+        for hours in ["22:00", "22:00-22:00", "24:00-07:00", "22h-7h"] {
+            user.notifications.quiet_hours = Some(hours.to_string());
+            assert!(user.validate().is_err(), "{hours}");
+        }
+        user.notifications.quiet_hours = Some("22:00-07:30".to_string());
+        assert!(user.validate().is_ok());
+    }
+
+    #[test]
+    fn test_quiet_for() {
+        let at = |h: u64, m: u64| {
+            SystemTime::UNIX_EPOCH + Duration::from_secs(86400 * 3 + h * 3600 + m * 60)
+        };
+        let mut preferences = NotificationPreferences::default();
+        assert_eq!(preferences.quiet_for(at(23, 0)), Duration::ZERO);
+
+        preferences.quiet_hours = Some("22:00-07:30".to_string());
+        assert_eq!(preferences.quiet_for(at(21, 59)), Duration::ZERO);
+        assert_eq!(
+            preferences.quiet_for(at(22, 0)),
+            Duration::from_secs(570 * 60)
+        );
+        assert_eq!(
+            preferences.quiet_for(at(3, 0)),
+            Duration::from_secs(270 * 60)
+        );
+        assert_eq!(preferences.quiet_for(at(7, 30)), Duration::ZERO);
+
+        preferences.quiet_hours = Some("12:00-13:00".to_string());
+        assert_eq!(
+            preferences.quiet_for(at(12, 15)),
+            Duration::from_secs(45 * 60)
+        );
+        assert_eq!(preferences.quiet_for(at(13, 0)), Duration::ZERO);
+        assert_eq!(preferences.quiet_for(at(11, 0)), Duration::ZERO);
+    }
+}
@@ -0,0 +1,255 @@
+use crate::datasource::db::add_column_if_missing;
+use crate::models::user_dao::{EmailPreference, NotificationPreferences, User};
+use sqlx::sqlite::SqliteRow;
+use sqlx::{Row, SqliteConnection, SqlitePool};
+
+pub async fn create_users_table(conn: &mut SqliteConnection) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS users (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            email TEXT UNIQUE,
+            tier TEXT NOT NULL DEFAULT 'standard',
+            tenant TEXT NOT NULL DEFAULT 'default',
+            runs_per_user INTEGER,
+            enabled BOOLEAN NOT NULL DEFAULT 1,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )
+    "#,
+    )
+    .execute(&mut *conn)
+    .await?;
+    // Notification preferences
+    add_column_if_missing(conn, "users", "callback_url", "TEXT").await?;
+    add_column_if_missing(
+        conn,
+        "users",
+        "email_notifications",
+        "TEXT NOT NULL DEFAULT 'never'",
+    )
+    .await?;
+    add_column_if_missing(conn, "users", "quiet_hours", "TEXT").await?;
+    Ok(())
+}
+
+impl User {
+    /// Build a `User` from a row of the `users` table
+    pub fn from_row(row: &SqliteRow) -> User {
+        let email_notifications: String = row.get("email_notifications");
+        User {
+            id: row.get("id"),
+            name: row.get("name"),
+            email: row.get("email"),
+            tier: row.get("tier"),
+            tenant: row.get("tenant"),
+            runs_per_user: row.get("runs_per_user"),
+            enabled: row.get("enabled"),
+            notifications: NotificationPreferences {
+                callback_url: row.get("callback_url"),
+                email: EmailPreference::from_string(&email_notifications),
+                quiet_hours: row.get("quiet_hours"),
+            },
+        }
+    }
+
+    pub async fn add_to_db(&mut self, pool: &SqlitePool) -> Result<(), sqlx::Error> {
+        let result = sqlx::query(
+            "INSERT INTO users (name, email, tier, tenant, runs_per_user, enabled, callback_url, email_notifications, quiet_hours) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&self.name)
+        .bind(&self.email)
+        .bind(&self.tier)
+        .bind(&self.tenant)
+        .bind(self.runs_per_user)
+        .bind(self.enabled)
+        .bind(&self.notifications.callback_url)
+        .bind(self.notifications.email.as_str())
+        .bind(&self.notifications.quiet_hours)
+        .execute(pool)
+        .await?;
+
+        self.id = result.last_insert_rowid() as u32;
+
+        Ok(())
+    }
+
+    /// Register `id` under a placeholder name, unless it already exists
+    pub async fn add_if_missing(id: u32, pool: &SqlitePool) -> Result<(), sqlx::Error> {
+        sqlx::query("INSERT OR IGNORE INTO users (id, name) VALUES (?, ?)")
+            .bind(id)
+            .bind(format!("user{id}"))
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn update(&self, pool: &SqlitePool) -> Result<(), sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE users SET name = ?, email = ?, tier = ?, tenant = ?, runs_per_user = ?, enabled = ?, callback_url = ?, email_notifications = ?, quiet_hours = ? WHERE id = ?",
+        )
+        .bind(&self.name)
+        .bind(&self.email)
+        .bind(&self.tier)
+        .bind(&self.tenant)
+        .bind(self.runs_per_user)
+        .bind(self.enabled)
+        .bind(&self.notifications.callback_url)
+        .bind(self.notifications.email.as_str())
+        .bind(&self.notifications.quiet_hours)
+        .bind(self.id)
+        .execute(pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(sqlx::Error::RowNotFound);
+        }
+
+        Ok(())
+    }
+
+    pub async fn delete(id: u32, pool: &SqlitePool) -> Result<(), sqlx::Error> {
+        let result = sqlx::query("DELETE FROM users WHERE id = ?")
+            .bind(id)
+            .execute(pool)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(sqlx::Error::RowNotFound);
+        }
+
+        Ok(())
+    }
+
+    pub async fn retrieve_id(id: u32, pool: &SqlitePool) -> Result<User, sqlx::Error> {
+        let row = sqlx::query("SELECT * FROM users WHERE id = ?")
+            .bind(id)
+            .fetch_optional(pool)
+            .await?
+            .ok_or(sqlx::Error::RowNotFound)?;
+
+        Ok(User::from_row(&row))
+    }
+
+    pub async fn list(pool: &SqlitePool) -> Result<Vec<User>, sqlx::Error> {
+        let rows = sqlx::query("SELECT * FROM users ORDER BY id")
+            .fetch_all(pool)
+            .await?;
+
+        Ok(rows.iter().map(User::from_row).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_user(name: &str) -> User {
+        User {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    async fn setup_test_db() -> SqlitePool {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        create_users_table(&mut pool.acquire().await.unwrap())
+            .await
+            .unwrap();
+        pool
+    }
+
+    #[tokio::test]
+    async fn test_add_and_retrieve() {
+        let pool = setup_test_db().await;
+
+        let mut user = test_user("alice");
+        user.email = Some("alice@example.com".to_string());
+        user.runs_per_user = Some(2);
+        user.add_to_db(&pool).await.unwrap();
+        assert!(user.id > 0);
+
+        let retrieved = User::retrieve_id(user.id, &pool).await.unwrap();
+        assert_eq!(retrieved, user);
+    }
+
+    #[tokio::test]
+    async fn test_retrieve_not_found() {
+        let pool = setup_test_db().await;
+        let result = User::retrieve_id(42, &pool).await;
+        assert!(matches!(result, Err(sqlx::Error::RowNotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_email() {
+        let pool = setup_test_db().await;
+
+        let mut alice = test_user("alice");
+        alice.email = Some("same@example.com".to_string());
+        alice.add_to_db(&pool).await.unwrap();
+
+        let mut bob = test_user("bob");
+        bob.email = Some("same@example.com".to_string());
+        assert!(bob.add_to_db(&pool).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_update() {
+        let pool = setup_test_db().await;
+
+        let mut user = test_user("alice");
+        user.add_to_db(&pool).await.unwrap();
+
+        user.tier = "premium".to_string();
+        user.enabled = false;
+        user.update(&pool).await.unwrap();
+
+        let retrieved = User::retrieve_id(user.id, &pool).await.unwrap();
+        assert_eq!(retrieved.tier, "premium");
+        assert!(!retrieved.enabled);
+
+        user.notifications = NotificationPreferences {
+            callback_url: Some("https://example.com/hook".to_string()),
+            email: EmailPreference::Failures,
+            quiet_hours: Some("22:00-07:00".to_string()),
+        };
+        user.update(&pool).await.unwrap();
+        let retrieved = User::retrieve_id(user.id, &pool).await.unwrap();
+        assert_eq!(retrieved.notifications, user.notifications);
+
+        let mut missing = test_user("ghost");
+        missing.id = 42;
+        assert!(matches!(
+            missing.update(&pool).await,
+            Err(sqlx::Error::RowNotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_delete() {
+        let pool = setup_test_db().await;
+
+        let mut user = test_user("alice");
+        user.add_to_db(&pool).await.unwrap();
+
+        User::delete(user.id, &pool).await.unwrap();
+        assert!(User::retrieve_id(user.id, &pool).await.is_err());
+        assert!(matches!(
+            User::delete(user.id, &pool).await,
+            Err(sqlx::Error::RowNotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_list() {
+        let pool = setup_test_db().await;
+
+        for name in ["alice", "bob"] {
+            test_user(name).add_to_db(&pool).await.unwrap();
+        }
+
+        let users = User::list(&pool).await.unwrap();
+        let names: Vec<&str> = users.iter().map(|u| u.name.as_str()).collect();
+        assert_eq!(names, vec!["alice", "bob"]);
+    }
+}
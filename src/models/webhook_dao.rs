@@ -0,0 +1,160 @@
+use serde::Serialize;
+use std::time::Duration;
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, ToSchema)]
+pub enum DeliveryState {
+    /// Not delivered yet, sent again once `next_attempt_at` is reached
+    Pending,
+    Delivered,
+    /// Every attempt failed
+    Failed,
+}
+
+impl DeliveryState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryState::Pending => "pending",
+            DeliveryState::Delivered => "delivered",
+            DeliveryState::Failed => "failed",
+        }
+    }
+
+    pub fn from_string(s: &str) -> Self {
+        match s {
+            "delivered" => DeliveryState::Delivered,
+            "failed" => DeliveryState::Failed,
+            _ => DeliveryState::Pending,
+        }
+    }
+}
+
+/// Kind of target a notification is sent to, see `services::notify`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, serde::Deserialize, ToSchema)]
+pub enum Channel {
+    /// Signed JSON `POST`, as the job callbacks
+    #[default]
+    Webhook,
+    /// Slack incoming webhook, sent a one line summary
+    Slack,
+}
+
+impl Channel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Channel::Webhook => "webhook",
+            Channel::Slack => "slack",
+        }
+    }
+
+    pub fn from_string(s: &str) -> Self {
+        match s {
+            "slack" => Channel::Slack,
+            _ => Channel::Webhook,
+        }
+    }
+}
+
+impl std::str::FromStr for Channel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "webhook" => Ok(Channel::Webhook),
+            "slack" => Ok(Channel::Slack),
+            other => Err(format!("Unknown notification channel: {other}")),
+        }
+    }
+}
+
+/// A notification about a job, as stored in the `webhook_deliveries` table.
+/// Either the callback of the job or a copy for an operator subscription.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct WebhookDelivery {
+    pub id: u32,
+    pub job_id: u32,
+    pub url: String,
+    /// e.g. `job.completed`
+    pub event: String,
+    /// JSON body sent, signed as is
+    pub payload: String,
+    pub state: DeliveryState,
+    pub attempts: u32,
+    /// HTTP status of the last attempt, `None` when no response came back
+    pub last_status: Option<u16>,
+    pub last_error: Option<String>,
+    /// UTC times, set by the database
+    pub created_at: Option<String>,
+    pub next_attempt_at: Option<String>,
+    pub delivered_at: Option<String>,
+    /// How `url` is reached
+    #[serde(skip)]
+    pub channel: Channel,
+    /// Name of the operator subscription, `None` for the callback of the job
+    #[serde(skip)]
+    pub subscriber: Option<String>,
+    /// Wait before the first attempt, see `NotificationPreferences::quiet_hours`
+    #[serde(skip)]
+    pub defer: Duration,
+}
+
+impl WebhookDelivery {
+    pub fn new(job_id: u32, url: &str, event: &str, payload: String) -> WebhookDelivery {
+        WebhookDelivery {
+            id: 0,
+            job_id,
+            url: url.to_string(),
+            event: event.to_string(),
+            payload,
+            state: DeliveryState::Pending,
+            attempts: 0,
+            last_status: None,
+            last_error: None,
+            created_at: None,
+            next_attempt_at: None,
+            delivered_at: None,
+            channel: Channel::Webhook,
+            subscriber: None,
+            defer: Duration::ZERO,
+        }
+    }
+
+    /// Send to the operator subscription `name` over `channel` instead
+    pub fn for_subscriber(mut self, name: &str, channel: Channel) -> WebhookDelivery {
+        self.subscriber = Some(name.to_string());
+        self.channel = channel;
+        self
+    }
+
+    /// Hold the first attempt back for `by`
+    pub fn deferred(mut self, by: Duration) -> WebhookDelivery {
+        self.defer = by;
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_state_round_trip() {
+        for state in [
+            DeliveryState::Pending,
+            DeliveryState::Delivered,
+            DeliveryState::Failed,
+        ] {
+            assert_eq!(DeliveryState::from_string(state.as_str()), state);
+        }
+    }
+
+    #[test]
+    fn test_channel_round_trip() {
+        for channel in [Channel::Webhook, Channel::Slack] {
+            assert_eq!(Channel::from_string(channel.as_str()), channel);
+            assert_eq!(channel.as_str().parse::<Channel>(), Ok(channel));
+        }
+        assert_eq!(" Slack ".parse::<Channel>(), Ok(Channel::Slack));
+        assert!("matrix".parse::<Channel>().is_err());
+    }
+}
@@ -0,0 +1,253 @@
+use crate::datasource::db::add_column_if_missing;
+use crate::models::webhook_dao::{Channel, DeliveryState, WebhookDelivery};
+use sqlx::sqlite::SqliteRow;
+use sqlx::{Row, SqliteConnection, SqlitePool};
+use std::time::Duration;
+
+pub async fn create_webhook_deliveries_table(
+    conn: &mut SqliteConnection,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS webhook_deliveries (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            job_id INTEGER NOT NULL REFERENCES jobs(id),
+            url TEXT NOT NULL,
+            event TEXT NOT NULL,
+            payload TEXT NOT NULL,
+            state TEXT NOT NULL DEFAULT 'pending',
+            attempts INTEGER NOT NULL DEFAULT 0,
+            last_status INTEGER,
+            last_error TEXT,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            next_attempt_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            delivered_at DATETIME
+        )
+    "#,
+    )
+    .execute(&mut *conn)
+    .await?;
+    add_column_if_missing(
+        conn,
+        "webhook_deliveries",
+        "channel",
+        "TEXT NOT NULL DEFAULT 'webhook'",
+    )
+    .await?;
+    add_column_if_missing(conn, "webhook_deliveries", "subscriber", "TEXT").await?;
+    Ok(())
+}
+
+impl WebhookDelivery {
+    fn from_row(row: &SqliteRow) -> WebhookDelivery {
+        let state: String = row.get("state");
+        let channel: String = row.get("channel");
+        WebhookDelivery {
+            id: row.get("id"),
+            job_id: row.get("job_id"),
+            url: row.get("url"),
+            event: row.get("event"),
+            payload: row.get("payload"),
+            state: DeliveryState::from_string(&state),
+            attempts: row.get("attempts"),
+            last_status: row.get("last_status"),
+            last_error: row.get("last_error"),
+            created_at: row.get("created_at"),
+            next_attempt_at: row.get("next_attempt_at"),
+            delivered_at: row.get("delivered_at"),
+            channel: Channel::from_string(&channel),
+            subscriber: row.get("subscriber"),
+            defer: Duration::ZERO,
+        }
+    }
+
+    /// Queue the delivery, it is due once `defer` is over
+    pub async fn add_to_db(&mut self, pool: &SqlitePool) -> Result<(), sqlx::Error> {
+        let row = sqlx::query(
+            "INSERT INTO webhook_deliveries (job_id, url, event, payload, channel, subscriber, next_attempt_at) VALUES (?, ?, ?, ?, ?, ?, datetime('now', ?)) RETURNING *",
+        )
+        .bind(self.job_id)
+        .bind(&self.url)
+        .bind(&self.event)
+        .bind(&self.payload)
+        .bind(self.channel.as_str())
+        .bind(&self.subscriber)
+        .bind(format!("+{} seconds", self.defer.as_secs()))
+        .fetch_one(pool)
+        .await?;
+
+        *self = WebhookDelivery::from_row(&row);
+
+        Ok(())
+    }
+
+    /// Pending deliveries whose next attempt is due, oldest first
+    pub async fn list_due(pool: &SqlitePool) -> Result<Vec<WebhookDelivery>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT * FROM webhook_deliveries WHERE state = ? AND next_attempt_at <= datetime('now') ORDER BY id",
+        )
+        .bind(DeliveryState::Pending.as_str())
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows.iter().map(WebhookDelivery::from_row).collect())
+    }
+
+    /// Callbacks of a job, oldest first. The copies sent to the operators are
+    /// left out, their targets are not for the submitter to see.
+    pub async fn list_for_job(
+        job_id: u32,
+        pool: &SqlitePool,
+    ) -> Result<Vec<WebhookDelivery>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT * FROM webhook_deliveries WHERE job_id = ? AND subscriber IS NULL ORDER BY id",
+        )
+        .bind(job_id)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows.iter().map(WebhookDelivery::from_row).collect())
+    }
+
+    pub async fn record_delivered(
+        &mut self,
+        status: u16,
+        pool: &SqlitePool,
+    ) -> Result<(), sqlx::Error> {
+        let row = sqlx::query(
+            "UPDATE webhook_deliveries SET state = ?, attempts = attempts + 1, last_status = ?, last_error = NULL, delivered_at = datetime('now') WHERE id = ? RETURNING *",
+        )
+        .bind(DeliveryState::Delivered.as_str())
+        .bind(status)
+        .bind(self.id)
+        .fetch_one(pool)
+        .await?;
+
+        *self = WebhookDelivery::from_row(&row);
+
+        Ok(())
+    }
+
+    /// Record a failed attempt, the delivery is tried again after `retry_in`
+    /// or given up when it is `None`
+    pub async fn record_failure(
+        &mut self,
+        status: Option<u16>,
+        error: &str,
+        retry_in: Option<Duration>,
+        pool: &SqlitePool,
+    ) -> Result<(), sqlx::Error> {
+        let (state, delay) = match retry_in {
+            Some(d) => (DeliveryState::Pending, d.as_secs()),
+            None => (DeliveryState::Failed, 0),
+        };
+        let row = sqlx::query(
+            "UPDATE webhook_deliveries SET state = ?, attempts = attempts + 1, last_status = ?, last_error = ?, next_attempt_at = datetime('now', ?) WHERE id = ? RETURNING *",
+        )
+        .bind(state.as_str())
+        .bind(status)
+        .bind(error)
+        .bind(format!("+{delay} seconds"))
+        .bind(self.id)
+        .fetch_one(pool)
+        .await?;
+
+        *self = WebhookDelivery::from_row(&row);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::job_dao::Job;
+    use crate::models::job_dto::create_jobs_table;
+    use crate::models::user_dao::User;
+
+    async fn setup_test_db() -> (SqlitePool, Job) {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        create_jobs_table(&pool).await.unwrap();
+        User::add_if_missing(0, &pool).await.unwrap();
+        let mut job = Job::new("");
+        job.add_to_db(&pool).await.unwrap();
+        (pool, job)
+    }
+
+    #[tokio::test]
+    async fn test_add_and_deliver() {
+        let (pool, job) = setup_test_db().await;
+
+        let mut delivery = WebhookDelivery::new(
+            job.id,
+            "https://example.com/hook",
+            "job.completed",
+            "{}".into(),
+        );
+        delivery.add_to_db(&pool).await.unwrap();
+        assert!(delivery.id > 0);
+        assert_eq!(delivery.state, DeliveryState::Pending);
+        assert!(delivery.created_at.is_some());
+
+        assert_eq!(
+            WebhookDelivery::list_due(&pool).await.unwrap(),
+            vec![delivery.clone()]
+        );
+
+        delivery.record_delivered(204, &pool).await.unwrap();
+        assert_eq!(delivery.state, DeliveryState::Delivered);
+        assert_eq!(delivery.attempts, 1);
+        assert_eq!(delivery.last_status, Some(204));
+        assert!(delivery.delivered_at.is_some());
+        assert!(WebhookDelivery::list_due(&pool).await.unwrap().is_empty());
+
+        let stored = WebhookDelivery::list_for_job(job.id, &pool).await.unwrap();
+        assert_eq!(stored, vec![delivery]);
+    }
+
+    #[tokio::test]
+    async fn test_record_failure() {
+        let (pool, job) = setup_test_db().await;
+
+        let mut delivery = WebhookDelivery::new(
+            job.id,
+            "https://example.com/hook",
+            "job.failed",
+            "{}".into(),
+        );
+        delivery.add_to_db(&pool).await.unwrap();
+
+        // Retried later, so no longer due
+        delivery
+            .record_failure(
+                Some(500),
+                "server error",
+                Some(Duration::from_secs(60)),
+                &pool,
+            )
+            .await
+            .unwrap();
+        assert_eq!(delivery.state, DeliveryState::Pending);
+        assert_eq!(delivery.attempts, 1);
+        assert_eq!(delivery.last_status, Some(500));
+        assert!(WebhookDelivery::list_due(&pool).await.unwrap().is_empty());
+
+        // Due right away
+        delivery
+            .record_failure(None, "connection refused", Some(Duration::ZERO), &pool)
+            .await
+            .unwrap();
+        assert_eq!(delivery.last_status, None);
+        assert_eq!(WebhookDelivery::list_due(&pool).await.unwrap().len(), 1);
+
+        // Given up
+        delivery
+            .record_failure(Some(404), "not found", None, &pool)
+            .await
+            .unwrap();
+        assert_eq!(delivery.state, DeliveryState::Failed);
+        assert_eq!(delivery.attempts, 3);
+        assert_eq!(delivery.last_error.as_deref(), Some("not found"));
+        assert!(WebhookDelivery::list_due(&pool).await.unwrap().is_empty());
+    }
+}
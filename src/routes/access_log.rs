@@ -0,0 +1,178 @@
+use axum::body::{Body, HttpBody};
+use axum::extract::Request;
+use axum::http::{HeaderValue, Method};
+use axum::middleware::Next;
+use axum::response::Response;
+use futures::StreamExt;
+use std::time::Instant;
+
+/// Correlates a request across the access log, the response and the caller
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Set on a response by handlers that learn the user from the body, e.g. the
+/// `user_id` field of an upload
+#[derive(Debug, Clone, Copy)]
+pub struct AccessUser(pub i32);
+
+/// One line of the access log, written when the response body is done
+struct AccessRecord {
+    method: Method,
+    path: String,
+    status: u16,
+    user_id: Option<i32>,
+    request_id: String,
+    started: Instant,
+    bytes: u64,
+}
+
+impl AccessRecord {
+    fn count(&mut self, bytes: usize) {
+        self.bytes += bytes as u64;
+    }
+}
+
+impl Drop for AccessRecord {
+    fn drop(&mut self) {
+        tracing::info!(
+            target: "access_log",
+            method = %self.method,
+            path = %self.path,
+            status = self.status,
+            latency_ms = self.started.elapsed().as_millis() as u64,
+            bytes = self.bytes,
+            user_id = self.user_id,
+            request_id = %self.request_id,
+        );
+    }
+}
+
+/// Log every request with its outcome. The request id is taken from the
+/// `x-request-id` header or generated, and echoed in the response.
+pub async fn access_log(mut request: Request, next: Next) -> Response {
+    let started = Instant::now();
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.is_empty() && v.len() <= 128)
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        request.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let query_user = request.uri().query().and_then(query_user_id);
+
+    let mut response = next.run(request).await;
+
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    let user_id = response
+        .extensions()
+        .get::<AccessUser>()
+        .map(|u| u.0)
+        .or(query_user);
+    let mut record = AccessRecord {
+        method,
+        path,
+        status: response.status().as_u16(),
+        user_id,
+        request_id,
+        started,
+        bytes: 0,
+    };
+
+    // Sized bodies are logged right away, streams once they are sent or dropped
+    if let Some(size) = response.body().size_hint().exact() {
+        record.bytes = size;
+        return response;
+    }
+    let (parts, body) = response.into_parts();
+    let stream = body.into_data_stream().map(move |chunk| {
+        if let Ok(bytes) = &chunk {
+            record.count(bytes.len());
+        }
+        chunk
+    });
+    Response::from_parts(parts, Body::from_stream(stream))
+}
+
+fn query_user_id(query: &str) -> Option<i32> {
+    query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("user_id="))
+        .and_then(|v| v.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::Router;
+    use axum::http::StatusCode;
+    use axum::middleware;
+    use axum::response::IntoResponse;
+    use axum::routing::get;
+    use futures::stream;
+    use tower::ServiceExt;
+
+    fn app() -> Router {
+        Router::new()
+            .route("/", get(|| async { "ok" }))
+            .route(
+                "/upload",
+                get(|| async {
+                    let mut response = StatusCode::CREATED.into_response();
+                    response.extensions_mut().insert(AccessUser(7));
+                    response
+                }),
+            )
+            .route(
+                "/stream",
+                get(|| async {
+                    let chunks = ["ab", "cd"].map(|c| Ok::<_, std::io::Error>(c.to_string()));
+                    Body::from_stream(stream::iter(chunks))
+                }),
+            )
+            .layer(middleware::from_fn(access_log))
+    }
+
+    fn request(uri: &str, request_id: Option<&str>) -> Request {
+        let mut builder = Request::builder().uri(uri);
+        if let Some(id) = request_id {
+            builder = builder.header(REQUEST_ID_HEADER, id);
+        }
+        builder.body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_request_id() {
+        let response = app().oneshot(request("/", Some("abc-123"))).await.unwrap();
+        assert_eq!(response.headers()[REQUEST_ID_HEADER], "abc-123");
+
+        let response = app().oneshot(request("/", None)).await.unwrap();
+        let generated = response.headers()[REQUEST_ID_HEADER].to_str().unwrap();
+        assert!(uuid::Uuid::parse_str(generated).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_access_log_keeps_responses() {
+        let response = app().oneshot(request("/upload", None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let response = app().oneshot(request("/stream", None)).await.unwrap();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(bytes, "abcd");
+    }
+
+    #[test]
+    fn test_query_user_id() {
+        assert_eq!(query_user_id("user_id=3"), Some(3));
+        assert_eq!(query_user_id("redirect=/&user_id=12"), Some(12));
+        assert_eq!(query_user_id("other_user_id=5"), None);
+        assert_eq!(query_user_id("user_id=abc"), None);
+    }
+}
@@ -0,0 +1,188 @@
+use crate::config::loader::IpAllowlist;
+use crate::models::status_body::StatusBody;
+use axum::Json;
+use axum::extract::connect_info::ConnectInfo;
+use axum::extract::{Request, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use ipnet::IpNet;
+use std::net::{IpAddr, SocketAddr};
+
+const FORWARDED_FOR_HEADER: &str = "x-forwarded-for";
+
+fn contains(networks: &[IpNet], ip: IpAddr) -> bool {
+    networks.iter().any(|n| n.contains(&ip))
+}
+
+/// Address of the caller. Behind trusted proxies it is the last address in
+/// `X-Forwarded-For` that was not added by one of them.
+pub fn client_ip(peer: IpAddr, headers: &HeaderMap, trusted_proxies: &[IpNet]) -> IpAddr {
+    let peer = peer.to_canonical();
+    if !contains(trusted_proxies, peer) {
+        return peer;
+    }
+    let forwarded: Vec<IpAddr> = headers
+        .get_all(FORWARDED_FOR_HEADER)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|ip| ip.trim().parse::<IpAddr>().ok())
+        .map(|ip| ip.to_canonical())
+        .collect();
+    forwarded
+        .iter()
+        .rev()
+        .find(|ip| !contains(trusted_proxies, **ip))
+        .or(forwarded.first())
+        .copied()
+        .unwrap_or(peer)
+}
+
+/// Refuse callers outside the allowed networks, requires the server to be
+/// served with `ConnectInfo<SocketAddr>`
+pub async fn restrict_to_allowlist(
+    State(allowlist): State<IpAllowlist>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let ip = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| {
+            client_ip(addr.ip(), request.headers(), &allowlist.trusted_proxies)
+        });
+
+    match ip {
+        Some(ip) if contains(&allowlist.allowed, ip) => next.run(request).await,
+        ip => {
+            let origin = ip.map_or("an unknown address".to_string(), |ip| ip.to_string());
+            tracing::warn!("Refused {} from {origin}", request.uri().path());
+            let body = StatusBody {
+                message: format!("Not allowed from {origin}"),
+                ..Default::default()
+            };
+            (StatusCode::FORBIDDEN, Json(body)).into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::loader::Config;
+    use crate::models::job_dto::create_jobs_table;
+    use crate::routes::router::create_routes;
+    use crate::services::client::Client;
+    use axum::Router;
+    use axum::body::Body;
+    use axum::middleware;
+    use axum::routing::get;
+    use tower::ServiceExt;
+
+    fn nets(list: &[&str]) -> Vec<IpNet> {
+        list.iter().map(|n| n.parse().unwrap()).collect()
+    }
+
+    fn headers(forwarded: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(FORWARDED_FOR_HEADER, forwarded.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_client_ip() {
+        let proxies = nets(&["10.0.0.2/32", "10.0.0.3/32"]);
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+
+        // A direct caller can't claim another address
+        assert_eq!(
+            client_ip(ip("203.0.113.9"), &headers("10.1.1.1"), &proxies),
+            ip("203.0.113.9")
+        );
+        // Entries prepended by the caller are ignored
+        assert_eq!(
+            client_ip(
+                ip("10.0.0.2"),
+                &headers("10.1.1.1, 203.0.113.9, 10.0.0.3"),
+                &proxies
+            ),
+            ip("203.0.113.9")
+        );
+        assert_eq!(
+            client_ip(ip("10.0.0.2"), &HeaderMap::new(), &proxies),
+            ip("10.0.0.2")
+        );
+        assert_eq!(
+            client_ip(ip("::ffff:10.0.0.2"), &headers("10.1.1.1"), &proxies),
+            ip("10.1.1.1")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_restrict_to_allowlist() {
+        let allowlist = IpAllowlist {
+            allowed: nets(&["10.0.0.0/8"]),
+            trusted_proxies: Vec::new(),
+        };
+        let app = Router::new().route("/admin", get(|| async { "ok" })).layer(
+            middleware::from_fn_with_state(allowlist, restrict_to_allowlist),
+        );
+        let request = |peer: Option<&str>| {
+            let mut request = Request::builder()
+                .uri("/admin")
+                .body(Body::empty())
+                .unwrap();
+            if let Some(peer) = peer {
+                let addr: SocketAddr = peer.parse().unwrap();
+                request.extensions_mut().insert(ConnectInfo(addr));
+            }
+            request
+        };
+
+        let response = app
+            .clone()
+            .oneshot(request(Some("10.2.3.4:5000")))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = app
+            .clone()
+            .oneshot(request(Some("203.0.113.9:5000")))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = app.oneshot(request(None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_management_routes_restricted() {
+        let pool = sqlx::SqlitePool::connect(":memory:").await.unwrap();
+        create_jobs_table(&pool).await.unwrap();
+        let config = Config {
+            management_allowlist: Some(IpAllowlist {
+                allowed: nets(&["10.0.0.0/8"]),
+                trusted_proxies: Vec::new(),
+            }),
+            ..Default::default()
+        };
+        let app = create_routes(pool, config, Client::default());
+        let request = |uri: &str| {
+            let mut request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+            let addr: SocketAddr = "203.0.113.9:5000".parse().unwrap();
+            request.extensions_mut().insert(ConnectInfo(addr));
+            request
+        };
+
+        for uri in ["/users", "/users/1"] {
+            let response = app.clone().oneshot(request(uri)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        }
+        // Job submission and inspection stay public
+        let response = app.clone().oneshot(request("/health")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = app.oneshot(request("/jobs/1")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,95 @@
+use crate::models::status_body::StatusBody;
+use axum::Json;
+use axum::extract::{Request, State};
+use axum::http::StatusCode;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+
+/// Marks a 413 that was already turned into JSON, so an outer limit with a
+/// different size leaves it alone
+#[derive(Clone, Copy)]
+struct LimitReported;
+
+/// Replace the plain text 413 of the extractors with a JSON body naming the
+/// limit of the route, used together with `DefaultBodyLimit::max(limit)`
+pub async fn payload_too_large(
+    State(limit): State<usize>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let response = next.run(request).await;
+    if response.status() != StatusCode::PAYLOAD_TOO_LARGE
+        || response.extensions().get::<LimitReported>().is_some()
+    {
+        return response;
+    }
+
+    let body = StatusBody {
+        message: format!("Request body is larger than the limit of {limit} bytes"),
+        ..Default::default()
+    };
+    let mut response = (StatusCode::PAYLOAD_TOO_LARGE, Json(body)).into_response();
+    response.extensions_mut().insert(LimitReported);
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::Router;
+    use axum::body::Body;
+    use axum::extract::DefaultBodyLimit;
+    use axum::middleware;
+    use axum::routing::post;
+    use tower::ServiceExt;
+
+    fn app() -> Router {
+        Router::new()
+            .route(
+                "/upload",
+                post(|body: String| async move { body })
+                    .layer(DefaultBodyLimit::max(100))
+                    .layer(middleware::from_fn_with_state(100, payload_too_large)),
+            )
+            .route("/small", post(|body: String| async move { body }))
+            .layer(DefaultBodyLimit::max(10))
+            .layer(middleware::from_fn_with_state(10, payload_too_large))
+    }
+
+    async fn post_body(uri: &str, size: usize) -> Response {
+        let request = Request::builder()
+            .method("POST")
+            .uri(uri)
+            .body(Body::from("x".repeat(size)))
+            .unwrap();
+        app().oneshot(request).await.unwrap()
+    }
+
+    async fn message(response: Response) -> String {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: StatusBody = serde_json::from_slice(&bytes).unwrap();
+        body.message
+    }
+
+    #[tokio::test]
+    async fn test_payload_too_large() {
+        assert_eq!(post_body("/small", 10).await.status(), StatusCode::OK);
+        let response = post_body("/small", 11).await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(
+            message(response).await,
+            "Request body is larger than the limit of 10 bytes"
+        );
+
+        // The route limit takes precedence over the one of the router
+        assert_eq!(post_body("/upload", 100).await.status(), StatusCode::OK);
+        let response = post_body("/upload", 101).await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(
+            message(response).await,
+            "Request body is larger than the limit of 100 bytes"
+        );
+    }
+}
@@ -0,0 +1,252 @@
+use crate::models::status_body::StatusBody;
+use crate::utils::compression::{self, Encoding};
+use axum::Json;
+use axum::body::{Body, HttpBody};
+use axum::extract::Request;
+use axum::http::{HeaderValue, Method, StatusCode, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+
+/// Bodies smaller than this are not worth compressing
+const MIN_SIZE: u64 = 1024;
+
+/// Content types that are already compressed
+const COMPRESSED_TYPES: [&str; 4] = [
+    "application/zip",
+    "application/gzip",
+    "application/zstd",
+    "image/",
+];
+
+/// Sent as written, a compressor would hold the events back until it filled a block
+const EVENT_STREAM: &str = "text/event-stream";
+
+/// Decompress request bodies sent with a `Content-Encoding`, so the handlers
+/// always see the plain body. The body limit applies to the decompressed size.
+pub async fn decompress_request(request: Request, next: Next) -> Response {
+    let Some(value) = request.headers().get(header::CONTENT_ENCODING) else {
+        return next.run(request).await;
+    };
+    let value = value.to_str().unwrap_or_default().trim().to_string();
+    if value.is_empty() || value.eq_ignore_ascii_case("identity") {
+        return next.run(request).await;
+    }
+
+    let Some(encoding) = Encoding::from_header(&value) else {
+        let body = StatusBody {
+            message: format!("Unsupported Content-Encoding: {value}"),
+            ..Default::default()
+        };
+        return (StatusCode::UNSUPPORTED_MEDIA_TYPE, Json(body)).into_response();
+    };
+
+    let (mut parts, body) = request.into_parts();
+    parts.headers.remove(header::CONTENT_ENCODING);
+    parts.headers.remove(header::CONTENT_LENGTH);
+    let body = Body::from_stream(compression::decode(body.into_data_stream(), encoding));
+
+    next.run(Request::from_parts(parts, body)).await
+}
+
+/// Compress responses for callers that advertise it in `Accept-Encoding`
+pub async fn compress_response(request: Request, next: Next) -> Response {
+    let encoding = request
+        .headers()
+        .get(header::ACCEPT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .and_then(compression::negotiate);
+    let is_head = request.method() == Method::HEAD;
+
+    let response = next.run(request).await;
+
+    let Some(encoding) = encoding else {
+        return response;
+    };
+    if is_head || !should_compress(&response) {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    parts.headers.insert(
+        header::CONTENT_ENCODING,
+        HeaderValue::from_static(encoding.as_str()),
+    );
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts
+        .headers
+        .append(header::VARY, HeaderValue::from_static("accept-encoding"));
+    let body = Body::from_stream(compression::encode(body.into_data_stream(), encoding));
+
+    Response::from_parts(parts, body)
+}
+
+fn should_compress(response: &Response) -> bool {
+    let status = response.status();
+    if status.is_informational()
+        || status == StatusCode::NO_CONTENT
+        || status == StatusCode::NOT_MODIFIED
+    {
+        return false;
+    }
+
+    let headers = response.headers();
+    if headers.contains_key(header::CONTENT_ENCODING) {
+        return false;
+    }
+
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("");
+    if content_type.starts_with(EVENT_STREAM)
+        || COMPRESSED_TYPES.iter().any(|t| content_type.starts_with(t))
+    {
+        return false;
+    }
+
+    // Streams have no exact size, they are usually large enough
+    response
+        .body()
+        .size_hint()
+        .exact()
+        .is_none_or(|size| size >= MIN_SIZE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::Router;
+    use axum::middleware;
+    use axum::routing::{get, post};
+    use tower::ServiceExt;
+
+    const TEXT: &str = "ATOM      1  N   MET A   1      11.104  13.207   2.100\n";
+
+    fn app() -> Router {
+        Router::new()
+            .route("/echo", post(|body: String| async move { body }))
+            .route("/text", get(|| async { TEXT.repeat(100) }))
+            .route("/small", get(|| async { "ok" }))
+            .route(
+                "/zip",
+                get(|| async {
+                    (
+                        [(header::CONTENT_TYPE, "application/zip")],
+                        TEXT.repeat(100),
+                    )
+                }),
+            )
+            .route(
+                "/events",
+                get(|| async { ([(header::CONTENT_TYPE, EVENT_STREAM)], TEXT.repeat(100)) }),
+            )
+            .layer(middleware::from_fn(compress_response))
+            .layer(middleware::from_fn(decompress_request))
+    }
+
+    async fn compress(data: &[u8], encoding: Encoding) -> Vec<u8> {
+        let body = Body::from(data.to_vec());
+        let stream = compression::encode(body.into_data_stream(), encoding);
+        axum::body::to_bytes(Body::from_stream(stream), usize::MAX)
+            .await
+            .unwrap()
+            .to_vec()
+    }
+
+    async fn decompress(body: Body, encoding: Encoding) -> Vec<u8> {
+        let stream = compression::decode(body.into_data_stream(), encoding);
+        axum::body::to_bytes(Body::from_stream(stream), usize::MAX)
+            .await
+            .unwrap()
+            .to_vec()
+    }
+
+    fn get_request(uri: &str, accept: &str) -> Request {
+        Request::builder()
+            .uri(uri)
+            .header(header::ACCEPT_ENCODING, accept)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_decompress_request() {
+        let data = TEXT.repeat(50);
+        for encoding in compression::SUPPORTED {
+            let request = Request::builder()
+                .method("POST")
+                .uri("/echo")
+                .header(header::CONTENT_ENCODING, encoding.as_str())
+                .body(Body::from(compress(data.as_bytes(), encoding).await))
+                .unwrap();
+
+            let response = app().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert_eq!(body, data.as_bytes());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_decompress_request_unsupported() {
+        let request = Request::builder()
+            .method("POST")
+            .uri("/echo")
+            .header(header::CONTENT_ENCODING, "deflate")
+            .body(Body::from("data"))
+            .unwrap();
+
+        let response = app().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    #[tokio::test]
+    async fn test_compress_response() {
+        let response = app()
+            .oneshot(get_request("/text", "gzip, zstd"))
+            .await
+            .unwrap();
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "zstd");
+        assert_eq!(response.headers()[header::VARY], "accept-encoding");
+
+        let plain = decompress(response.into_body(), Encoding::Zstd).await;
+        assert_eq!(plain, TEXT.repeat(100).as_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_compress_response_for_browsers() {
+        // What browsers send without zstd
+        let response = app()
+            .oneshot(get_request("/text", "gzip, deflate, br"))
+            .await
+            .unwrap();
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "br");
+
+        let plain = decompress(response.into_body(), Encoding::Brotli).await;
+        assert_eq!(plain, TEXT.repeat(100).as_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_compress_response_skipped() {
+        // Not requested
+        let response = app()
+            .oneshot(get_request("/text", "deflate"))
+            .await
+            .unwrap();
+        assert!(!response.headers().contains_key(header::CONTENT_ENCODING));
+
+        // Too small to bother
+        let response = app().oneshot(get_request("/small", "gzip")).await.unwrap();
+        assert!(!response.headers().contains_key(header::CONTENT_ENCODING));
+
+        // Already compressed
+        let response = app().oneshot(get_request("/zip", "gzip")).await.unwrap();
+        assert!(!response.headers().contains_key(header::CONTENT_ENCODING));
+
+        // Streamed events
+        let response = app().oneshot(get_request("/events", "gzip")).await.unwrap();
+        assert!(!response.headers().contains_key(header::CONTENT_ENCODING));
+    }
+}
@@ -0,0 +1,115 @@
+use crate::config::loader::CorsConfig;
+use tower_http::cors::{AllowHeaders, AllowMethods, AllowOrigin, Any, CorsLayer};
+
+/// Answers preflight requests and adds the CORS headers for the configured
+/// origins, so browser frontends can call the API without a proxy
+pub fn cors_layer(config: &CorsConfig) -> CorsLayer {
+    let any = |list: &[String]| list.iter().any(|e| e == "*");
+
+    let origins = if any(&config.allowed_origins) {
+        AllowOrigin::from(Any)
+    } else {
+        AllowOrigin::list(config.allowed_origins.iter().filter_map(|o| o.parse().ok()))
+    };
+    let methods = if any(&config.allowed_methods) {
+        AllowMethods::from(Any)
+    } else {
+        AllowMethods::list(config.allowed_methods.iter().filter_map(|m| m.parse().ok()))
+    };
+    let headers = if any(&config.allowed_headers) {
+        AllowHeaders::from(Any)
+    } else {
+        AllowHeaders::list(config.allowed_headers.iter().filter_map(|h| h.parse().ok()))
+    };
+
+    CorsLayer::new()
+        .allow_origin(origins)
+        .allow_methods(methods)
+        .allow_headers(headers)
+        .max_age(config.max_age)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::Router;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode, header};
+    use axum::routing::get;
+    use std::time::Duration;
+    use tower::ServiceExt;
+
+    fn app(origins: &[&str]) -> Router {
+        let config = CorsConfig {
+            allowed_origins: origins.iter().map(|o| o.to_string()).collect(),
+            allowed_methods: vec!["GET".to_string(), "POST".to_string()],
+            allowed_headers: vec!["content-type".to_string()],
+            max_age: Duration::from_secs(600),
+        };
+        Router::new()
+            .route("/", get(|| async { "ok" }))
+            .layer(cors_layer(&config))
+    }
+
+    fn preflight(origin: &str) -> Request<Body> {
+        Request::builder()
+            .method("OPTIONS")
+            .uri("/")
+            .header(header::ORIGIN, origin)
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+            .header(header::ACCESS_CONTROL_REQUEST_HEADERS, "content-type")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_cors_layer() {
+        let app = app(&["https://app.example.org"]);
+
+        let response = app
+            .clone()
+            .oneshot(preflight("https://app.example.org"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let headers = response.headers();
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://app.example.org"
+        );
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_METHODS], "GET,POST");
+        assert_eq!(headers[header::ACCESS_CONTROL_MAX_AGE], "600");
+
+        let response = app
+            .clone()
+            .oneshot(preflight("https://evil.example.org"))
+            .await
+            .unwrap();
+        assert!(
+            !response
+                .headers()
+                .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+        );
+
+        let request = Request::builder()
+            .uri("/")
+            .header(header::ORIGIN, "https://app.example.org")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://app.example.org"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cors_layer_any_origin() {
+        let response = app(&["*"])
+            .oneshot(preflight("https://anywhere.example.org"))
+            .await
+            .unwrap();
+        assert_eq!(response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+    }
+}
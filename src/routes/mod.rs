@@ -1 +1,8 @@
+pub mod access_log;
+pub mod allowlist;
+pub mod body_limit;
+pub mod compression;
+pub mod cors;
 pub mod router;
+pub mod schema;
+pub mod storage;
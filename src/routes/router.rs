@@ -1,91 +1,357 @@
-use crate::config::loader::Config;
-use crate::controllers::client::{kill, load, retrieve, retrieve_partial, submit};
+use crate::config::loader::{Config, CorsConfig, IpAllowlist};
+use crate::controllers::admin::{
+    __path_add_job_note, __path_approve_job, __path_force_status, __path_list_held_jobs,
+    __path_queue_snapshot, __path_reject_job, __path_release_user_jobs, __path_scheduler_stats,
+    AddNote, ForceStatus, RejectJob, add_job_note, approve_job, force_status, list_held_jobs,
+    queue_snapshot, reject_job, release_user_jobs, scheduler_stats,
+};
+use crate::controllers::auth::{
+    __path_callback, __path_login, __path_logout, __path_me, callback, login, logout, me,
+};
+use crate::controllers::client::{
+    cancel_payload, capabilities, delete_payload, kill, list_payloads, load, payload_logs,
+    payload_status, retrieve, retrieve_head, retrieve_partial, stats, stream_logs, submit,
+    upload_progress,
+};
 use crate::controllers::health::__path_health;
-use crate::controllers::health::health;
+use crate::controllers::health::{health, warmup};
 use crate::controllers::ping::ping;
+use crate::controllers::quota::{__path_quota, quota};
+use crate::controllers::server::__path_cancel_job;
+use crate::controllers::server::__path_create_download_link;
 use crate::controllers::server::__path_download;
+use crate::controllers::server::__path_download_group;
 use crate::controllers::server::__path_download_partial;
+use crate::controllers::server::__path_download_shared;
+use crate::controllers::server::__path_get_job;
+use crate::controllers::server::__path_get_job_eta;
+use crate::controllers::server::__path_get_job_events;
+use crate::controllers::server::__path_get_job_history;
+use crate::controllers::server::__path_get_job_inputs;
+use crate::controllers::server::__path_get_job_webhooks;
+use crate::controllers::server::__path_submit_parameters;
+use crate::controllers::server::__path_update_job;
 use crate::controllers::server::__path_upload;
-use crate::controllers::server::{download, download_partial, terminate, upload};
+use crate::controllers::server::{
+    DownloadLink, JobDetail, ParameterSubmission, cancel_job, create_download_link, download,
+    download_group, download_partial, download_shared, get_job, get_job_eta, get_job_events,
+    get_job_history, get_job_inputs, get_job_webhooks, submit_parameters, terminate, update_job,
+    upload,
+};
+use crate::controllers::services::{
+    __path_list_services, AvailableService, ServiceCatalog, list_services,
+};
+use crate::controllers::shares::{
+    __path_create_share, __path_list_shares, __path_revoke_share, create_share, list_shares,
+    revoke_share,
+};
+use crate::controllers::uploads::{
+    __path_begin_upload, __path_complete_upload, __path_upload_outcome, CompleteUpload,
+    Completions, DirectUpload, UploadRequest, UploadUrl, begin_upload, complete_upload,
+    upload_outcome,
+};
+use crate::controllers::users::{
+    __path_create_user, __path_delete_user, __path_get_user, __path_list_users, __path_update_user,
+};
+use crate::controllers::users::{create_user, delete_user, get_user, list_users, update_user};
+use crate::models::download_dao::Download;
+use crate::models::error_body::{ErrorBody, ErrorCode};
 use crate::models::health_dto::Health;
-use crate::models::job_dao::Job;
+use crate::models::job_dao::{Job, JobError, JobEta, JobMetadata, JobMetadataPatch};
+use crate::models::job_event_dao::{JobEvent, StatusChange};
+use crate::models::job_note_dao::JobNote;
+use crate::models::load_dto::LoadReport;
+use crate::models::manifest_dto::ManifestEntry;
+use crate::models::queue_dao::{QueueSnapshot, ServiceSnapshot, Throttled};
+use crate::models::quota_dao::{Quota, ServiceQuota};
+use crate::models::service_probe_dao::{
+    ServiceAvailability, ServiceProbe, VersionSpread, VersionStanding,
+};
+use crate::models::share_dao::Share;
+use crate::models::task_run_dao::{SchedulerStats, TaskRun, TaskStats};
+use crate::models::user_dao::User;
+use crate::models::webhook_dao::{DeliveryState, WebhookDelivery};
+use crate::routes::access_log::access_log;
+use crate::routes::allowlist::restrict_to_allowlist;
+use crate::routes::body_limit::payload_too_large;
+use crate::routes::compression::{compress_response, decompress_request};
+use crate::routes::cors::cors_layer;
+use crate::routes::schema::negotiate_schema;
+use crate::routes::storage::refuse_when_degraded;
+use crate::services::client::Client;
+use crate::utils::progress::UploadTracker;
+use crate::utils::session::{Identity, SessionStore};
 use axum::extract::DefaultBodyLimit;
+use axum::http::{HeaderName, header};
+use axum::middleware;
 use axum::{
     Router,
-    routing::{get, post},
+    routing::{MethodRouter, delete, get, post},
 };
 use sqlx::SqlitePool;
+use tower_http::sensitive_headers::{
+    SetSensitiveRequestHeadersLayer, SetSensitiveResponseHeadersLayer,
+};
 use tower_http::trace::{
     DefaultMakeSpan, DefaultOnFailure, DefaultOnRequest, DefaultOnResponse, TraceLayer,
 };
 use tracing::Level;
-use utoipa::OpenApi;
+use utoipa::openapi::security::{Http, HttpAuthScheme, SecurityScheme};
+use utoipa::{Modify, OpenApi};
 use utoipa_swagger_ui::SwaggerUi;
 
 #[derive(Clone)]
 pub struct AppState {
     pub pool: SqlitePool,
     pub config: Config,
+    /// Shared HTTP client for the requests made to the services
+    pub client: Client,
+    /// Submissions being received, only used by the client
+    pub uploads: UploadTracker,
+    /// Operators logged in with single sign-on, only used by the server
+    pub sessions: SessionStore,
+    /// Direct uploads being fetched from the object store, only used by the server
+    pub completions: Completions,
 }
 
 #[derive(OpenApi)]
 #[openapi(
     paths(
         upload,
+        submit_parameters,
+        begin_upload,
+        complete_upload,
+        upload_outcome,
         download,
         download_partial,
-        health
+        download_group,
+        create_download_link,
+        download_shared,
+        create_share,
+        list_shares,
+        revoke_share,
+        get_job,
+        update_job,
+        cancel_job,
+        get_job_webhooks,
+        get_job_events,
+        get_job_history,
+        get_job_inputs,
+        get_job_eta,
+        health,
+        create_user,
+        list_users,
+        get_user,
+        update_user,
+        delete_user,
+        quota,
+        list_services,
+        force_status,
+        add_job_note,
+        release_user_jobs,
+        queue_snapshot,
+        scheduler_stats,
+        list_held_jobs,
+        approve_job,
+        reject_job,
+        login,
+        callback,
+        me,
+        logout
     ),
     components(
-        schemas(Job, Health)
+        schemas(
+            Job, JobDetail, JobError, JobMetadata, JobMetadataPatch, JobNote, Download, Health, User, Quota,
+            ServiceQuota, ForceStatus, RejectJob, AddNote, JobEvent, StatusChange, WebhookDelivery, DeliveryState, Identity,
+            DownloadLink, Share, UploadRequest, DirectUpload, UploadUrl, CompleteUpload,
+            ParameterSubmission, QueueSnapshot, ServiceSnapshot, Throttled, JobEta, LoadReport, ErrorBody, ErrorCode, ManifestEntry,
+            SchedulerStats, TaskStats, TaskRun, ServiceAvailability, ServiceProbe,
+            VersionSpread, VersionStanding, ServiceCatalog, AvailableService
+        )
     ),
+    modifiers(&AdminSecurity),
     tags(
         (name = "files", description = "File management endpoints"),
+        (name = "jobs", description = "Job inspection endpoints"),
+        (name = "users", description = "User management endpoints"),
+        (name = "admin", description = "Operator endpoints, require ADMIN_TOKEN or a single sign-on session"),
+        (name = "auth", description = "Single sign-on of the operators"),
         (name = "health", description = "Health check endpoints")
     )
 )]
 struct ApiDoc;
 
-pub fn create_routes(pool: SqlitePool, config: Config) -> Router {
-    let state = AppState { pool, config };
-    Router::new()
+/// Registers the bearer token used by the admin endpoints
+struct AdminSecurity;
+
+impl Modify for AdminSecurity {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "admin_token",
+            SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)),
+        );
+    }
+}
+
+pub fn create_routes(pool: SqlitePool, config: Config, client: Client) -> Router {
+    let cors = config.cors.clone();
+    let limits = config.body_limits.clone();
+    let log_requests = config.access_log;
+    let allowlist = config.management_allowlist.clone();
+    let state = AppState {
+        pool,
+        config,
+        client,
+        uploads: UploadTracker::default(),
+        sessions: SessionStore::default(),
+        completions: Completions::default(),
+    };
+    let router = Router::new()
         .route("/", get(ping))
         .route("/health", get(health))
-        .route("/upload", post(upload))
+        .route(
+            "/upload",
+            submission(upload_limit(post(upload), limits.upload)),
+        )
+        .route("/jobs", submission(post(submit_parameters)))
+        .route("/uploads", submission(post(begin_upload)))
+        .route(
+            "/uploads/{upload_id}/complete",
+            submission(post(complete_upload)),
+        )
+        .route("/uploads/{upload_id}", get(upload_outcome))
         .route("/download/{id}", get(download))
         .route("/download_partial/{id}", get(download_partial))
+        .route(
+            "/jobs/{id}",
+            get(get_job).patch(update_job).delete(cancel_job),
+        )
+        .route("/jobs/{id}/webhooks", get(get_job_webhooks))
+        .route("/jobs/{id}/events", get(get_job_events))
+        .route("/jobs/{id}/history", get(get_job_history))
+        .route("/jobs/{id}/inputs", get(get_job_inputs))
+        .route("/jobs/{id}/eta", get(get_job_eta))
+        .route("/jobs/{id}/download_link", post(create_download_link))
+        .route("/jobs/{id}/shares", get(list_shares).post(create_share))
+        .route("/jobs/{id}/shares/{share_id}", delete(revoke_share))
+        .route("/shared/{id}", get(download_shared))
+        .route("/groups/{id}/results", get(download_group))
         .route("/terminate/{id}", post(terminate))
+        .route("/auth/login", get(login))
+        .route("/auth/callback", get(callback))
+        .route("/auth/me", get(me))
+        .route("/auth/logout", post(logout))
+        .route("/quota", get(quota))
+        .route("/services", get(list_services))
+        .merge(management_routes(allowlist))
         .merge(SwaggerUi::new("/swagger").url("/api-docs/openapi.json", ApiDoc::openapi()))
         .with_state(state)
-        .layer(
-            TraceLayer::new_for_http()
-                .make_span_with(
-                    DefaultMakeSpan::new()
-                        .level(Level::INFO)
-                        .include_headers(true), // Log request headers
-                )
-                .on_request(DefaultOnRequest::new().level(Level::INFO))
-                .on_response(
-                    DefaultOnResponse::new()
-                        .level(Level::INFO)
-                        .include_headers(true), // Log response headers
-                )
-                .on_failure(DefaultOnFailure::new().level(Level::ERROR)),
-        )
-        .layer(DefaultBodyLimit::max(400 * 1024 * 1024)) // Set max body size to 400MB
+        .layer(middleware::from_fn(compress_response))
+        .layer(middleware::from_fn(decompress_request));
+    let router = with_trace(router)
+        .layer(DefaultBodyLimit::max(limits.default))
+        .layer(middleware::from_fn_with_state(
+            limits.default,
+            payload_too_large,
+        ));
+    let router = with_access_log(router, log_requests);
+    with_cors(router, cors.as_ref())
 }
 
-pub fn create_client_routes(pool: SqlitePool, config: Config) -> Router {
-    let state = AppState { pool, config };
-    Router::new()
+pub fn create_client_routes(pool: SqlitePool, config: Config, client: Client) -> Router {
+    let cors = config.cors.clone();
+    let limits = config.body_limits.clone();
+    let log_requests = config.access_log;
+    // The HTTP client only reaches the object store shared with the server
+    let state = AppState {
+        pool,
+        config,
+        client,
+        uploads: UploadTracker::default(),
+        sessions: SessionStore::default(),
+        completions: Completions::default(),
+    };
+    let router = Router::new()
         .route("/", get(ping))
         .route("/health", get(health))
         .route("/load", get(load))
-        .route("/submit", post(submit))
-        .route("/retrieve/{id}", get(retrieve))
+        .route("/capabilities", get(capabilities))
+        .route("/warmup", get(warmup))
+        .route("/stats", get(stats))
+        .route("/submit", upload_limit(post(submit), limits.upload))
+        .route("/status/{id}", get(payload_status))
+        .route("/logs/{id}", get(payload_logs))
+        .route("/logs/{id}/stream", get(stream_logs))
+        .route("/retrieve/{id}", get(retrieve).head(retrieve_head))
         .route("/retrieve_partial/{id}", get(retrieve_partial))
         .route("/kill/{id}", post(kill))
+        .route("/cancel/{id}", post(cancel_payload))
+        .route("/uploads/{id}/progress", get(upload_progress))
+        .route("/payloads", get(list_payloads))
+        .route("/payloads/{id}", delete(delete_payload))
         .with_state(state)
+        .layer(middleware::from_fn(negotiate_schema))
+        .layer(middleware::from_fn(compress_response))
+        .layer(middleware::from_fn(decompress_request));
+    let router = with_trace(router)
+        .layer(DefaultBodyLimit::max(limits.default))
+        .layer(middleware::from_fn_with_state(
+            limits.default,
+            payload_too_large,
+        ));
+    let router = with_access_log(router, log_requests);
+    with_cors(router, cors.as_ref())
+}
+
+/// Operator and user management routes, only reachable from the allowed
+/// networks when an allowlist is configured
+fn management_routes(allowlist: Option<IpAllowlist>) -> Router<AppState> {
+    let router = Router::new()
+        .route("/admin/jobs/held", get(list_held_jobs))
+        .route("/admin/jobs/{id}/status", post(force_status))
+        .route("/admin/jobs/{id}/approve", post(approve_job))
+        .route("/admin/jobs/{id}/reject", post(reject_job))
+        .route("/admin/jobs/{id}/notes", post(add_job_note))
+        .route("/admin/users/{id}/release", post(release_user_jobs))
+        .route("/admin/queue", get(queue_snapshot))
+        .route("/admin/stats", get(scheduler_stats))
+        .route("/users", get(list_users).post(create_user))
+        .route(
+            "/users/{id}",
+            get(get_user).put(update_user).delete(delete_user),
+        );
+    match allowlist {
+        Some(allowlist) => router.route_layer(middleware::from_fn_with_state(
+            allowlist,
+            restrict_to_allowlist,
+        )),
+        None => router,
+    }
+}
+
+/// The larger limit of the routes receiving job inputs
+fn upload_limit(route: MethodRouter<AppState>, limit: usize) -> MethodRouter<AppState> {
+    route
+        .layer(DefaultBodyLimit::max(limit))
+        .layer(middleware::from_fn_with_state(limit, payload_too_large))
+}
+
+/// The routes writing new jobs to `DATA_PATH`, refused while it is not writable
+fn submission(route: MethodRouter<AppState>) -> MethodRouter<AppState> {
+    route.layer(middleware::from_fn(refuse_when_degraded))
+}
+
+/// Headers holding credentials or single sign-on sessions, logged as
+/// `Sensitive` by the trace layer
+const SENSITIVE_HEADERS: [HeaderName; 3] =
+    [header::AUTHORIZATION, header::COOKIE, header::SET_COOKIE];
+
+/// Log requests and responses with their headers. The sensitive ones are
+/// marked before the trace layer sees them, on the way in and on the way out
+fn with_trace(router: Router) -> Router {
+    router
+        .layer(SetSensitiveResponseHeadersLayer::new(SENSITIVE_HEADERS))
         .layer(
             TraceLayer::new_for_http()
                 .make_span_with(
@@ -101,5 +367,64 @@ pub fn create_client_routes(pool: SqlitePool, config: Config) -> Router {
                 )
                 .on_failure(DefaultOnFailure::new().level(Level::ERROR)),
         )
-        .layer(DefaultBodyLimit::disable())
+        .layer(SetSensitiveRequestHeadersLayer::new(SENSITIVE_HEADERS))
+}
+
+/// Outside the other middleware, so the status and size are the ones sent
+fn with_access_log(router: Router, enabled: bool) -> Router {
+    if enabled {
+        router.layer(middleware::from_fn(access_log))
+    } else {
+        router
+    }
+}
+
+/// Outermost, so preflight requests are answered before any other middleware
+fn with_cors(router: Router, cors: Option<&CorsConfig>) -> Router {
+    match cors {
+        Some(cors) => router.layer(cors_layer(cors)),
+        None => router,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{HeaderMap, Request};
+    use axum::response::IntoResponse;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_trace_hides_credentials() {
+        // Answers whether the request headers reached it marked as sensitive
+        async fn handler(headers: HeaderMap) -> impl IntoResponse {
+            let seen = SENSITIVE_HEADERS
+                .iter()
+                .all(|name| headers.get(name).is_some_and(|v| v.is_sensitive()));
+            let mut credentials = HeaderMap::new();
+            for name in SENSITIVE_HEADERS {
+                credentials.insert(name, "secret".parse().unwrap());
+            }
+            (credentials, seen.to_string())
+        }
+        let app = with_trace(Router::new().route("/", get(handler)));
+
+        let mut request = Request::builder().uri("/");
+        for name in SENSITIVE_HEADERS {
+            request = request.header(name, "secret");
+        }
+        let response = app
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        for name in SENSITIVE_HEADERS {
+            assert!(response.headers()[&name].is_sensitive());
+        }
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, "true");
+    }
 }
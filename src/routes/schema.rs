@@ -0,0 +1,81 @@
+use crate::models::payload_dao::{
+    MIN_SCHEMA_VERSION, SCHEMA_VERSION, SCHEMA_VERSION_HEADER, is_supported_schema,
+    peer_schema_version,
+};
+use crate::models::status_body::StatusBody;
+use axum::Json;
+use axum::extract::Request;
+use axum::http::{HeaderName, HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+
+/// Client side of the payload schema handshake: refuse orchestrators that are
+/// too old to read our payloads and announce our own version on every response
+pub async fn negotiate_schema(request: Request, next: Next) -> Response {
+    let peer = peer_schema_version(request.headers());
+
+    let mut response = if is_supported_schema(peer) {
+        if peer != SCHEMA_VERSION {
+            tracing::debug!("Orchestrator uses payload schema {peer}, ours is {SCHEMA_VERSION}");
+        }
+        next.run(request).await
+    } else {
+        let body = StatusBody {
+            message: format!(
+                "Payload schema version {peer} is not supported, the minimum is {MIN_SCHEMA_VERSION}"
+            ),
+            ..Default::default()
+        };
+        (StatusCode::BAD_REQUEST, Json(body)).into_response()
+    };
+
+    response.headers_mut().insert(
+        HeaderName::from_static(SCHEMA_VERSION_HEADER),
+        HeaderValue::from(SCHEMA_VERSION),
+    );
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::Router;
+    use axum::body::Body;
+    use axum::middleware;
+    use axum::routing::get;
+    use tower::ServiceExt;
+
+    fn app() -> Router {
+        Router::new()
+            .route("/", get(|| async { "ok" }))
+            .layer(middleware::from_fn(negotiate_schema))
+    }
+
+    fn request(version: Option<&str>) -> Request {
+        let mut builder = Request::builder().uri("/");
+        if let Some(v) = version {
+            builder = builder.header(SCHEMA_VERSION_HEADER, v);
+        }
+        builder.body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_negotiate_schema() {
+        // Legacy orchestrators send no header, newer ones are trusted to stay compatible
+        for version in [None, Some("1"), Some("99")] {
+            let response = app().oneshot(request(version)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(
+                response.headers()[SCHEMA_VERSION_HEADER],
+                SCHEMA_VERSION.to_string()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_negotiate_schema_outdated() {
+        let response = app().oneshot(request(Some("0"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert!(response.headers().contains_key(SCHEMA_VERSION_HEADER));
+    }
+}
@@ -0,0 +1,90 @@
+use crate::models::status_body::StatusBody;
+use crate::utils::storage::StorageHealth;
+use axum::extract::Request;
+use axum::http::StatusCode;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+
+/// Refuse submissions with `507` while `DATA_PATH` is not writable, and
+/// degrade it when a submission could not be written. Without a
+/// [`StorageHealth`] extension every request goes through.
+pub async fn refuse_when_degraded(
+    storage: Option<Extension<StorageHealth>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(Extension(storage)) = storage else {
+        return next.run(request).await;
+    };
+    if let Some(problem) = storage.problem() {
+        let body = StatusBody {
+            message: format!("DATA_PATH is not writable, submissions are refused: {problem}"),
+            ..Default::default()
+        };
+        return (StatusCode::INSUFFICIENT_STORAGE, Json(body)).into_response();
+    }
+
+    let path = request.uri().path().to_string();
+    let response = next.run(request).await;
+    if response.status() == StatusCode::INSUFFICIENT_STORAGE {
+        storage.degrade(&format!("a submission to {path} could not be written"));
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::Router;
+    use axum::body::Body;
+    use axum::middleware;
+    use axum::routing::post;
+    use tower::ServiceExt;
+
+    fn app(storage: &StorageHealth) -> Router {
+        Router::new()
+            .route("/upload", post(|| async { StatusCode::CREATED }))
+            .route("/full", post(|| async { StatusCode::INSUFFICIENT_STORAGE }))
+            .route_layer(middleware::from_fn(refuse_when_degraded))
+            .layer(Extension(storage.clone()))
+    }
+
+    async fn post_to(app: Router, uri: &str) -> StatusCode {
+        let request = Request::builder()
+            .method("POST")
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap();
+        app.oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn test_refuse_when_degraded() {
+        let storage = StorageHealth::default();
+        assert_eq!(post_to(app(&storage), "/upload").await, StatusCode::CREATED);
+
+        // A failed write degrades the node for every submission
+        assert_eq!(
+            post_to(app(&storage), "/full").await,
+            StatusCode::INSUFFICIENT_STORAGE
+        );
+        assert!(storage.problem().unwrap().contains("/full"));
+        assert_eq!(
+            post_to(app(&storage), "/upload").await,
+            StatusCode::INSUFFICIENT_STORAGE
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        storage.check(dir.path().to_str().unwrap()).await;
+        assert_eq!(post_to(app(&storage), "/upload").await, StatusCode::CREATED);
+    }
+
+    #[tokio::test]
+    async fn test_without_storage_health() {
+        let app = Router::new()
+            .route("/upload", post(|| async { StatusCode::CREATED }))
+            .route_layer(middleware::from_fn(refuse_when_degraded));
+        assert_eq!(post_to(app, "/upload").await, StatusCode::CREATED);
+    }
+}
@@ -0,0 +1,215 @@
+use crate::config::loader::AlertConfig;
+use crate::services::notify::Notification;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A watermark crossed, or back under its threshold
+#[derive(Debug, Clone, PartialEq)]
+pub struct Alert {
+    /// e.g. `alert.queue_depth` or `alert.queue_depth.resolved`
+    pub event: String,
+    pub payload: serde_json::Value,
+}
+
+/// Which watermarks are above their threshold, kept between the rounds of
+/// the monitor so each crossing is reported once
+#[derive(Debug, Clone, Default)]
+pub struct AlertState(Arc<Mutex<Watermarks>>);
+
+#[derive(Debug, Default)]
+struct Watermarks {
+    /// When the queue went above `queue_depth`
+    depth_above_since: Option<Instant>,
+    depth_reported: bool,
+    age_reported: bool,
+}
+
+/// Which of the two watermarks an alert is about
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Watermark {
+    QueueDepth,
+    OldestQueued,
+}
+
+impl Alert {
+    fn new(watermark: Watermark, resolved: bool, mut payload: serde_json::Value) -> Alert {
+        let name = match watermark {
+            Watermark::QueueDepth => "alert.queue_depth",
+            Watermark::OldestQueued => "alert.oldest_queued",
+        };
+        let event = if resolved {
+            format!("{name}.resolved")
+        } else {
+            name.to_string()
+        };
+        payload["event"] = serde_json::Value::String(event.clone());
+        Alert { event, payload }
+    }
+
+    pub fn watermark(&self) -> Watermark {
+        if self.event.starts_with("alert.queue_depth") {
+            Watermark::QueueDepth
+        } else {
+            Watermark::OldestQueued
+        }
+    }
+
+    pub fn resolved(&self) -> bool {
+        self.event.ends_with(".resolved")
+    }
+
+    pub fn notification(&self) -> Notification {
+        Notification::new(&self.event, &self.payload)
+    }
+}
+
+impl AlertState {
+    /// Alerts due given the current queue, `oldest` being the wait of the
+    /// oldest queued job. Nothing is marked as reported until [`Self::sent`]
+    /// is called, so an alert that could not be delivered comes back next round.
+    pub fn evaluate(
+        &self,
+        config: &AlertConfig,
+        depth: u32,
+        oldest: Option<Duration>,
+        now: Instant,
+    ) -> Vec<Alert> {
+        let mut marks = self.0.lock().expect("alert state poisoned");
+        let mut alerts = Vec::new();
+
+        if let Some(threshold) = config.queue_depth {
+            let payload = serde_json::json!({
+                "queue_depth": depth,
+                "threshold": threshold,
+                "for_seconds": config.queue_depth_for.as_secs(),
+            });
+            if depth > threshold {
+                let since = *marks.depth_above_since.get_or_insert(now);
+                if !marks.depth_reported && now.duration_since(since) >= config.queue_depth_for {
+                    alerts.push(Alert::new(Watermark::QueueDepth, false, payload));
+                }
+            } else {
+                marks.depth_above_since = None;
+                if marks.depth_reported {
+                    alerts.push(Alert::new(Watermark::QueueDepth, true, payload));
+                }
+            }
+        }
+
+        if let Some(threshold) = config.oldest_queued {
+            let payload = serde_json::json!({
+                "oldest_queued_seconds": oldest.map(|o| o.as_secs()),
+                "threshold_seconds": threshold.as_secs(),
+            });
+            let above = oldest.is_some_and(|o| o > threshold);
+            if above != marks.age_reported {
+                alerts.push(Alert::new(Watermark::OldestQueued, !above, payload));
+            }
+        }
+
+        alerts
+    }
+
+    /// Record that `alert` reached the operators
+    pub fn sent(&self, alert: &Alert) {
+        let mut marks = self.0.lock().expect("alert state poisoned");
+        let reported = !alert.resolved();
+        match alert.watermark() {
+            Watermark::QueueDepth => marks.depth_reported = reported,
+            Watermark::OldestQueued => marks.age_reported = reported,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> AlertConfig {
+        AlertConfig {
+            webhook_url: None,
+            queue_depth: Some(10),
+            queue_depth_for: Duration::from_secs(600),
+            oldest_queued: Some(Duration::from_secs(3600)),
+        }
+    }
+
+    fn events(alerts: &[Alert]) -> Vec<&str> {
+        alerts.iter().map(|a| a.event.as_str()).collect()
+    }
+
+    #[test]
+    fn test_queue_depth_sustained() {
+        let (state, config, start) = (AlertState::default(), config(), Instant::now());
+        assert!(state.evaluate(&config, 11, None, start).is_empty());
+        let later = start + Duration::from_secs(300);
+        assert!(state.evaluate(&config, 20, None, later).is_empty());
+
+        let later = start + Duration::from_secs(600);
+        let alerts = state.evaluate(&config, 20, None, later);
+        assert_eq!(events(&alerts), ["alert.queue_depth"]);
+        assert_eq!(alerts[0].payload["queue_depth"], 20);
+        assert_eq!(alerts[0].payload["event"], "alert.queue_depth");
+
+        // Raised again until delivered, then once only
+        assert_eq!(state.evaluate(&config, 20, None, later).len(), 1);
+        state.sent(&alerts[0]);
+        assert!(state.evaluate(&config, 20, None, later).is_empty());
+
+        let alerts = state.evaluate(&config, 10, None, later);
+        assert_eq!(events(&alerts), ["alert.queue_depth.resolved"]);
+        state.sent(&alerts[0]);
+        assert!(state.evaluate(&config, 10, None, later).is_empty());
+    }
+
+    #[test]
+    fn test_queue_depth_dip_restarts_the_wait() {
+        let (state, config, start) = (AlertState::default(), config(), Instant::now());
+        state.evaluate(&config, 11, None, start);
+        state.evaluate(&config, 5, None, start + Duration::from_secs(300));
+        let later = start + Duration::from_secs(600);
+        assert!(state.evaluate(&config, 11, None, later).is_empty());
+        let later = later + Duration::from_secs(600);
+        assert_eq!(state.evaluate(&config, 11, None, later).len(), 1);
+    }
+
+    #[test]
+    fn test_oldest_queued() {
+        let (state, config, now) = (AlertState::default(), config(), Instant::now());
+        let young = Some(Duration::from_secs(60));
+        let old = Some(Duration::from_secs(7200));
+        assert!(state.evaluate(&config, 1, young, now).is_empty());
+
+        let alerts = state.evaluate(&config, 1, old, now);
+        assert_eq!(events(&alerts), ["alert.oldest_queued"]);
+        assert_eq!(alerts[0].payload["oldest_queued_seconds"], 7200);
+        state.sent(&alerts[0]);
+        assert!(state.evaluate(&config, 1, old, now).is_empty());
+
+        // An empty queue has no old job either
+        let alerts = state.evaluate(&config, 0, None, now);
+        assert_eq!(events(&alerts), ["alert.oldest_queued.resolved"]);
+    }
+
+    #[test]
+    fn test_unset_watermarks_are_ignored() {
+        let config = AlertConfig {
+            queue_depth: None,
+            oldest_queued: None,
+            ..config()
+        };
+        let state = AlertState::default();
+        let later = Instant::now() + Duration::from_secs(86400);
+        state.evaluate(
+            &config,
+            1000,
+            Some(Duration::from_secs(86400)),
+            Instant::now(),
+        );
+        assert!(
+            state
+                .evaluate(&config, 1000, Some(Duration::from_secs(86400)), later)
+                .is_empty()
+        );
+    }
+}
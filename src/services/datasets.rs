@@ -0,0 +1,321 @@
+use crate::config::loader::{Config, Dataset};
+use crate::services::client::{Client, ClientError};
+use crate::utils::io::{extract_archive, file_sha256, sanitize_filename};
+use futures::StreamExt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tracing::{error, info, warn};
+
+/// Directory under `DATA_PATH` the datasets are kept in. Like the quarantine
+/// it is never taken for a payload directory.
+pub const DATASETS_DIR: &str = ".datasets";
+
+/// Name of the link to the datasets in each payload directory
+pub const DATASETS_LINK: &str = "datasets";
+
+/// Wait before fetching again the datasets that failed
+const RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+pub fn cache_dir(config: &Config) -> PathBuf {
+    Path::new(&config.data_path).join(DATASETS_DIR)
+}
+
+/// Every dataset is in the cache, the payloads wait in `Prepared` until then
+pub fn ready(config: &Config) -> bool {
+    let cache = cache_dir(config);
+    config.datasets.keys().all(|name| cache.join(name).is_dir())
+}
+
+/// Fetch the datasets missing from the cache, retrying the ones that failed
+/// until all of them are there
+pub async fn fetcher(config: Config, client: Client) {
+    let cache = cache_dir(&config);
+    let mut names: Vec<&String> = config.datasets.keys().collect();
+    names.sort();
+    loop {
+        for name in &names {
+            if cache.join(name).is_dir() {
+                continue;
+            }
+            info!("Fetching dataset {name}");
+            match fetch(&config.datasets[*name], &cache, &client).await {
+                Ok(()) => info!("Dataset {name} is ready"),
+                Err(e) => error!("Could not fetch dataset {name}: {e}"),
+            }
+        }
+        if ready(&config) {
+            return;
+        }
+        tokio::time::sleep(RETRY_INTERVAL).await;
+    }
+}
+
+/// Name the download is saved under, the last segment of its URL
+fn file_name(dataset: &Dataset) -> String {
+    reqwest::Url::parse(&dataset.url)
+        .ok()
+        .and_then(|url| url.path_segments()?.next_back().map(str::to_string))
+        .filter(|name| !name.is_empty())
+        .map(|name| sanitize_filename(&name))
+        .unwrap_or_else(|| dataset.name.clone())
+}
+
+/// Download `dataset` next to the cache and move it in once verified, so a
+/// dataset in the cache is always complete
+async fn fetch(dataset: &Dataset, cache: &Path, client: &Client) -> io::Result<()> {
+    let partial = cache.join(format!(".{}.partial", dataset.name));
+    // Left behind by a fetch that was interrupted
+    if partial.exists() {
+        tokio::fs::remove_dir_all(&partial).await?;
+    }
+    tokio::fs::create_dir_all(&partial).await?;
+
+    let result = download(dataset, &partial, client).await;
+    if result.is_err() {
+        tokio::fs::remove_dir_all(&partial).await.ok();
+    }
+    result?;
+    tokio::fs::rename(&partial, cache.join(&dataset.name)).await
+}
+
+async fn download(dataset: &Dataset, dir: &Path, client: &Client) -> io::Result<()> {
+    let path = dir.join(file_name(dataset));
+    let response = client
+        .http
+        .get(&dataset.url)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(io::Error::other)?;
+
+    let mut file = tokio::fs::File::create(&path).await?;
+    let mut stream = response.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        file.write_all(&chunk.map_err(io::Error::other)?).await?;
+    }
+    file.flush().await?;
+    drop(file);
+
+    if let Some(expected) = dataset.sha256.clone() {
+        let checked = path.clone();
+        let actual = tokio::task::spawn_blocking(move || file_sha256(&checked))
+            .await
+            .map_err(io::Error::other)??;
+        if actual != expected {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("checksum {actual} does not match {expected}"),
+            ));
+        }
+    }
+
+    if path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("zip"))
+    {
+        let (archive, dst) = (path.clone(), dir.to_path_buf());
+        tokio::task::spawn_blocking(move || extract_archive(&archive, &dst))
+            .await
+            .map_err(io::Error::other)??;
+        tokio::fs::remove_file(&path).await?;
+    }
+    Ok(())
+}
+
+/// Link the datasets into the payload directory as `datasets`, a payload
+/// bringing a file of that name is refused
+pub fn link(dir: &Path, config: &Config) -> Result<(), ClientError> {
+    if config.datasets.is_empty() {
+        return Ok(());
+    }
+    let link = dir.join(DATASETS_LINK);
+    if link.symlink_metadata().is_ok() {
+        return Err(ClientError::UnsafePayload {
+            reason: format!("ships {DATASETS_LINK}, the name is reserved for the reference data"),
+        });
+    }
+    // Absolute, the payload may sit under another storage root
+    let cache = std::fs::canonicalize(cache_dir(config)).map_err(|_| ClientError::Execution)?;
+    std::os::unix::fs::symlink(cache, link).map_err(|_| ClientError::Execution)
+}
+
+/// Remove the link once the payload exited, only its own files are sent back
+pub fn unlink(dir: &Path) {
+    let link = dir.join(DATASETS_LINK);
+    if link
+        .symlink_metadata()
+        .is_ok_and(|m| m.file_type().is_symlink())
+        && let Err(e) = std::fs::remove_file(&link)
+    {
+        warn!("Could not remove {}: {e}", link.display());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sha2::{Digest, Sha256};
+    use std::collections::HashMap;
+    use std::fs;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    fn config_with(data_path: &Path, datasets: &[(&str, String, Option<String>)]) -> Config {
+        Config {
+            data_path: data_path.to_str().unwrap().to_string(),
+            datasets: datasets
+                .iter()
+                .map(|(name, url, sha256)| {
+                    (
+                        name.to_string(),
+                        Dataset {
+                            name: name.to_string(),
+                            url: url.clone(),
+                            sha256: sha256.clone(),
+                        },
+                    )
+                })
+                .collect::<HashMap<_, _>>(),
+            ..Default::default()
+        }
+    }
+
+    fn zipped(name: &str, content: &[u8]) -> Vec<u8> {
+        let mut buffer = io::Cursor::new(Vec::new());
+        let mut zip = zip::ZipWriter::new(&mut buffer);
+        zip.start_file(name, zip::write::SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(content).unwrap();
+        zip.finish().unwrap();
+        buffer.into_inner()
+    }
+
+    #[test]
+    fn test_file_name() {
+        let dataset = |url: &str| Dataset {
+            name: "genome".to_string(),
+            url: url.to_string(),
+            sha256: None,
+        };
+        assert_eq!(
+            file_name(&dataset("https://x.org/ref/hg38.fa.gz")),
+            "hg38.fa.gz"
+        );
+        assert_eq!(file_name(&dataset("https://x.org/")), "genome");
+    }
+
+    #[tokio::test]
+    async fn test_fetcher() {
+        let mut server = mockito::Server::new_async().await;
+        let archive = zipped("params/ff.itp", b"force field");
+        let zip = server
+            .mock("GET", "/ff.zip")
+            .with_body(&archive)
+            .expect(1)
+            .create_async()
+            .await;
+        let genome = server
+            .mock("GET", "/hg38.fa")
+            .with_body("ACGT")
+            .expect(1)
+            .create_async()
+            .await;
+
+        let tempdir = TempDir::new().unwrap();
+        let config = config_with(
+            tempdir.path(),
+            &[
+                ("ff", format!("{}/ff.zip", server.url()), None),
+                (
+                    "genome",
+                    format!("{}/hg38.fa", server.url()),
+                    Some(format!("{:x}", Sha256::digest(b"ACGT"))),
+                ),
+            ],
+        );
+        assert!(!ready(&config));
+
+        fetcher(config.clone(), Client::default()).await;
+        zip.assert_async().await;
+        genome.assert_async().await;
+        assert!(ready(&config));
+
+        let cache = cache_dir(&config);
+        assert_eq!(
+            fs::read(cache.join("ff/params/ff.itp")).unwrap(),
+            b"force field"
+        );
+        assert!(!cache.join("ff/ff.zip").exists());
+        assert_eq!(fs::read(cache.join("genome/hg38.fa")).unwrap(), b"ACGT");
+
+        // Already in the cache, not fetched again
+        fetcher(config, Client::default()).await;
+        zip.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_fetch_checksum_mismatch() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/hg38.fa")
+            .with_body("ACGT")
+            .create_async()
+            .await;
+
+        let tempdir = TempDir::new().unwrap();
+        let config = config_with(
+            tempdir.path(),
+            &[(
+                "genome",
+                format!("{}/hg38.fa", server.url()),
+                Some("00".repeat(32)),
+            )],
+        );
+        let cache = cache_dir(&config);
+        let err = fetch(&config.datasets["genome"], &cache, &Client::default())
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(!ready(&config));
+        assert!(!cache.join(".genome.partial").exists());
+    }
+
+    #[test]
+    fn test_link() {
+        let tempdir = TempDir::new().unwrap();
+        let config = config_with(tempdir.path(), &[("genome", String::new(), None)]);
+        fs::create_dir_all(cache_dir(&config).join("genome")).unwrap();
+        fs::write(cache_dir(&config).join("genome/hg38.fa"), "ACGT").unwrap();
+
+        let payload = tempdir.path().join("payload");
+        fs::create_dir(&payload).unwrap();
+        link(&payload, &config).unwrap();
+        assert_eq!(
+            fs::read(payload.join("datasets/genome/hg38.fa")).unwrap(),
+            b"ACGT"
+        );
+
+        // Removed once run, the datasets stay
+        unlink(&payload);
+        assert!(payload.join(DATASETS_LINK).symlink_metadata().is_err());
+        assert!(cache_dir(&config).join("genome/hg38.fa").exists());
+
+        // A payload can't bring its own
+        fs::create_dir(payload.join(DATASETS_LINK)).unwrap();
+        assert!(matches!(
+            link(&payload, &config),
+            Err(ClientError::UnsafePayload { .. })
+        ));
+        unlink(&payload);
+        assert!(payload.join(DATASETS_LINK).is_dir());
+
+        // Nothing to link without datasets
+        let other = tempdir.path().join("other");
+        fs::create_dir(&other).unwrap();
+        link(&other, &Config::default()).unwrap();
+        assert!(payload.join(DATASETS_LINK).exists());
+        assert!(other.join(DATASETS_LINK).symlink_metadata().is_err());
+    }
+}
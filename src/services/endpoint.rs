@@ -1,8 +1,17 @@
-use crate::config::loader::Config;
-use crate::models::job_dao::Job;
+use crate::config::loader::{Config, ServiceAuth};
+use crate::models::capabilities_dto::Capabilities;
+use crate::models::error_body::ErrorBody;
+use crate::models::job_dao::{Job, JobError};
+use crate::models::job_event_dao::{GETTER_ACTOR, SENDER_ACTOR};
+use crate::models::load_dto::LoadReport;
+use crate::models::payload_dao::{MIN_SCHEMA_VERSION, PayloadStatus};
+use crate::models::run_history_dao::ServiceRunStats;
 use crate::models::status_dto::Status;
+use crate::utils::sandbox::SandboxProfile;
 use anyhow::Result;
 use axum::http::StatusCode;
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime};
 use tracing::info;
 
 #[derive(Debug, thiserror::Error)]
@@ -14,17 +23,81 @@ pub enum UploadError {
     #[error("Request failed: {0}")]
     RequestFailed(#[from] reqwest::Error),
     #[error("Failed to read response: {0}")]
-    ResponseReadFailed(reqwest::Error),
+    ResponseReadFailed(std::io::Error),
     #[error("Failed to deserialize response: {0}")]
     DeserializationFailed(#[from] serde_json::Error),
-    #[error("Server returned error status {status}: {body}")]
-    UnexpectedStatus { status: StatusCode, body: String },
+    #[error("Client rejected the upload with status {status}: {error}")]
+    Rejected {
+        status: StatusCode,
+        error: ErrorBody,
+    },
     #[error("Cannot read file '{path}': {source}")]
     FileRead {
         path: String,
         #[source]
         source: tokio::io::Error,
     },
+    #[error("Service uses payload schema {0}, the oldest supported is {MIN_SCHEMA_VERSION}")]
+    UnsupportedSchema(u32),
+    #[error("Upload failure injected by the chaos mode")]
+    InjectedFault,
+    #[error("Upload cancelled after {0:?}")]
+    TimedOut(Duration),
+}
+
+impl UploadError {
+    /// Whether the failure likely goes away by itself, e.g. a client that
+    /// did not answer in time, a dropped connection or a 5xx, and the job is
+    /// worth sending again
+    pub fn is_transient(&self) -> bool {
+        match self {
+            UploadError::TimedOut(_) | UploadError::InjectedFault => true,
+            UploadError::RequestFailed(e) => {
+                e.is_timeout()
+                    || e.is_connect()
+                    || e.is_body()
+                    || e.status().is_some_and(|s| s.is_server_error())
+            }
+            UploadError::ResponseReadFailed(e) => e.kind() != std::io::ErrorKind::InvalidData,
+            UploadError::Rejected { status, error } => {
+                *status == StatusCode::TOO_MANY_REQUESTS
+                    || (status.is_server_error() && error.code.is_transient())
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Status of the answer of a client and the `ErrorBody` it carried, as JSON
+fn rejection(status: StatusCode, error: &ErrorBody) -> (u16, Option<String>) {
+    (status.as_u16(), serde_json::to_string(error).ok())
+}
+
+impl From<&UploadError> for JobError {
+    fn from(e: &UploadError) -> JobError {
+        let variant = match e {
+            UploadError::InvalidService => "invalid_service",
+            UploadError::EncodingFailed(_) => "encoding_failed",
+            UploadError::RequestFailed(_) => "request_failed",
+            UploadError::ResponseReadFailed(_) => "response_read_failed",
+            UploadError::DeserializationFailed(_) => "deserialization_failed",
+            UploadError::Rejected { .. } => "rejected",
+            UploadError::FileRead { .. } => "file_read",
+            UploadError::UnsupportedSchema(_) => "unsupported_schema",
+            UploadError::InjectedFault => "injected_fault",
+            UploadError::TimedOut(_) => "timed_out",
+        };
+        let error = JobError::new(SENDER_ACTOR, variant, e.to_string());
+        let response = match e {
+            UploadError::Rejected { status, error } => Some(rejection(*status, error)),
+            UploadError::RequestFailed(e) => e.status().map(|s| (s.as_u16(), None)),
+            _ => None,
+        };
+        match response {
+            Some((status, body)) => error.with_response(status, body.as_deref()),
+            None => error,
+        }
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -32,7 +105,7 @@ pub enum DownloadError {
     #[error("Request failed: {0}")]
     RequestFailed(#[from] reqwest::Error),
     #[error("Failed to read response: {0}")]
-    ResponseReadFailed(reqwest::Error),
+    ResponseReadFailed(std::io::Error),
     #[error("Failed to create file '{path}': {source}")]
     FileCreate {
         path: String,
@@ -49,6 +122,93 @@ pub enum DownloadError {
     NotFound,
     #[error("Invalid service")]
     InvalidService,
+    #[error("Invalid archive: {0}")]
+    InvalidArchive(String),
+    #[error("Service uses payload schema {0}, the oldest supported is {MIN_SCHEMA_VERSION}")]
+    UnsupportedSchema(u32),
+    #[error("Download cancelled after {0:?}")]
+    TimedOut(Duration),
+    #[error("Client answered {status}: {error}")]
+    Rejected {
+        status: StatusCode,
+        error: ErrorBody,
+    },
+    #[error("Results of {needed} bytes do not fit in the {free} bytes left")]
+    InsufficientSpace { needed: u64, free: u64 },
+}
+
+impl DownloadError {
+    /// Whether the failure likely goes away by itself, e.g. a timeout, a
+    /// dropped connection or a 5xx, and the download is worth retrying
+    pub fn is_transient(&self) -> bool {
+        match self {
+            DownloadError::TimedOut(_)
+            | DownloadError::InvalidArchive(_)
+            | DownloadError::InsufficientSpace { .. } => true,
+            DownloadError::RequestFailed(e) => {
+                e.is_timeout()
+                    || e.is_connect()
+                    || e.is_body()
+                    || e.status().is_some_and(|s| s.is_server_error())
+            }
+            // A body that does not parse will not parse the next time either
+            DownloadError::ResponseReadFailed(e) => e.kind() != std::io::ErrorKind::InvalidData,
+            DownloadError::Rejected { error, .. } => error.code.is_transient(),
+            _ => false,
+        }
+    }
+}
+
+impl From<&DownloadError> for JobError {
+    fn from(e: &DownloadError) -> JobError {
+        let variant = match e {
+            DownloadError::RequestFailed(_) => "request_failed",
+            DownloadError::ResponseReadFailed(_) => "response_read_failed",
+            DownloadError::FileCreate { .. } => "file_create",
+            DownloadError::FileWrite { .. } => "file_write",
+            DownloadError::NotFound => "not_found",
+            DownloadError::InvalidService => "invalid_service",
+            DownloadError::InvalidArchive(_) => "invalid_archive",
+            DownloadError::UnsupportedSchema(_) => "unsupported_schema",
+            DownloadError::TimedOut(_) => "timed_out",
+            DownloadError::Rejected { .. } => "rejected",
+            DownloadError::InsufficientSpace { .. } => "insufficient_space",
+        };
+        let error = JobError::new(GETTER_ACTOR, variant, e.to_string());
+        let response = match e {
+            DownloadError::Rejected { status, error } => Some(rejection(*status, error)),
+            DownloadError::RequestFailed(e) => e.status().map(|s| (s.as_u16(), None)),
+            DownloadError::NotFound => Some((StatusCode::NOT_FOUND.as_u16(), None)),
+            _ => None,
+        };
+        match response {
+            Some((status, body)) => error.with_response(status, body.as_deref()),
+            None => error,
+        }
+    }
+}
+
+/// What asking a client for the results of a job gave
+#[derive(Debug, PartialEq)]
+pub enum Retrieved {
+    /// No results yet, the client reports the payload in this status
+    Pending(Status),
+    /// `output.zip` was saved into the job directory
+    Downloaded,
+    /// The client put `output.zip` in the shared object store under `key`
+    Shared {
+        key: String,
+        checksum: Option<String>,
+    },
+}
+
+impl Retrieved {
+    pub fn status(&self) -> Status {
+        match self {
+            Retrieved::Pending(status) => *status,
+            _ => Status::Completed,
+        }
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -56,7 +216,7 @@ pub enum DownloadPartialError {
     #[error("Request failed: {0}")]
     RequestFailed(#[from] reqwest::Error),
     #[error("Failed to read response: {0}")]
-    ResponseReadFailed(reqwest::Error),
+    ResponseReadFailed(std::io::Error),
     #[error("Not found")]
     NotFound,
     #[error("Invalid service")]
@@ -65,6 +225,36 @@ pub enum DownloadPartialError {
     UnexpectedStatus(u16),
 }
 
+#[derive(Debug, thiserror::Error)]
+pub enum LoadError {
+    #[error("Invalid service")]
+    InvalidService,
+    #[error("Request failed: {0}")]
+    RequestFailed(#[from] reqwest::Error),
+    #[error("Failed to read response: {0}")]
+    ResponseReadFailed(std::io::Error),
+    #[error("Failed to deserialize response: {0}")]
+    DeserializationFailed(#[from] serde_json::Error),
+    #[error("Unexpected HTTP status: {0}")]
+    UnexpectedStatus(u16),
+    #[error("No answer after {0:?}")]
+    TimedOut(Duration),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReconcileError {
+    #[error("Invalid service")]
+    InvalidService,
+    #[error("Request failed: {0}")]
+    RequestFailed(#[from] reqwest::Error),
+    #[error("Failed to read response: {0}")]
+    ResponseReadFailed(std::io::Error),
+    #[error("Failed to deserialize response: {0}")]
+    DeserializationFailed(#[from] serde_json::Error),
+    #[error("Unexpected HTTP status: {0}")]
+    UnexpectedStatus(u16),
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum TerminateError {
     #[error("generic")]
@@ -73,6 +263,22 @@ pub enum TerminateError {
     HttpError(StatusCode),
 }
 
+/// What the client is told about a payload besides its files
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SubmitOptions {
+    /// Isolation to run it with
+    pub sandbox: SandboxProfile,
+    /// Object store key the results go under, see `shared_results`
+    pub result_key: Option<String>,
+    /// Longest it may run, unbounded when unset
+    pub timeout: Option<Duration>,
+    /// Bytes its directory may hold once run, unbounded when unset
+    pub max_output_size: Option<u64>,
+    /// When the orchestrator stops asking for the results, the client may
+    /// remove them from then on
+    pub expires_at: Option<SystemTime>,
+}
+
 pub async fn send<T>(job: &Job, config: &Config, target: T) -> Result<u32, UploadError>
 where
     T: Endpoint,
@@ -80,12 +286,37 @@ where
     info!("{:?}", job);
 
     match config.get_upload_url(&job.service) {
-        Some(url) => Ok(target.upload(job, url).await?),
+        Some(url) => {
+            // A job sent again needs its packed inputs back
+            job.unpack().map_err(|e| UploadError::FileRead {
+                path: job.loc.display().to_string(),
+                source: e,
+            })?;
+            let service = &config.services[&job.service];
+            let options = SubmitOptions {
+                sandbox: service.sandbox,
+                // Both sides must reach the same store for the results to be shared
+                result_key: (service.shared_results && config.object_store.is_some())
+                    .then(|| job.object_key()),
+                timeout: service
+                    .effective_timeout(job.timeout.map(|t| Duration::from_secs(t.into()))),
+                max_output_size: service.max_output_size,
+                expires_at: job.expires_at(config.max_age_for(&job.tenant)),
+            };
+            let upload = target.upload(job, url, auth(config, &job.service), options);
+            // Dropping the upload cancels the transfer
+            match service.transfer_timeout {
+                Some(timeout) => tokio::time::timeout(timeout, upload)
+                    .await
+                    .map_err(|_| UploadError::TimedOut(timeout))?,
+                None => upload.await,
+            }
+        }
         None => Err(UploadError::InvalidService),
     }
 }
 
-pub async fn retrieve<T>(job: &Job, config: &Config, target: T) -> Result<Status, DownloadError>
+pub async fn retrieve<T>(job: &Job, config: &Config, target: T) -> Result<Retrieved, DownloadError>
 where
     T: Endpoint,
 {
@@ -94,28 +325,282 @@ where
     } else {
         // target.download(job).await
         match config.get_download_url(&job.service) {
-            Some(url) => Ok(target.download(job, url).await?),
+            Some(url) => {
+                let download = target.download(job, url, auth(config, &job.service));
+                match config.services[&job.service].transfer_timeout {
+                    Some(timeout) => match tokio::time::timeout(timeout, download).await {
+                        Ok(retrieved) => retrieved,
+                        Err(_) => {
+                            // Never leave a truncated archive behind
+                            let _ = std::fs::remove_file(job.loc.join("output.zip"));
+                            Err(DownloadError::TimedOut(timeout))
+                        }
+                    },
+                    None => download.await,
+                }
+            }
             None => Err(DownloadError::InvalidService),
         }
     }
 }
 
+/// Ask the client for the status of `job` alone, nothing is zipped or sent
+/// back, so it is cheap to poll
+pub async fn status<T>(
+    job: &Job,
+    config: &Config,
+    target: T,
+) -> Result<PayloadStatus, DownloadError>
+where
+    T: Endpoint,
+{
+    if job.id == 0 {
+        return Err(DownloadError::NotFound);
+    }
+    match config.get_download_url(&job.service) {
+        Some(url) => {
+            let url = sibling_url(url, "status");
+            let status = target.status(job, &url, auth(config, &job.service));
+            match config.services[&job.service].transfer_timeout {
+                Some(timeout) => tokio::time::timeout(timeout, status)
+                    .await
+                    .map_err(|_| DownloadError::TimedOut(timeout))?,
+                None => status.await,
+            }
+        }
+        None => Err(DownloadError::InvalidService),
+    }
+}
+
 pub async fn kill<T>(job: &Job, config: &Config, target: T) -> Result<(), TerminateError>
 where
     T: Endpoint,
 {
     match config.get_terminate_url(&job.service) {
-        Some(url) => Ok(target.terminate(job, url).await?),
+        Some(url) => Ok(target
+            .terminate(job, url, auth(config, &job.service))
+            .await?),
+        None => Err(TerminateError::GenericError),
+    }
+}
+
+/// Ask the client of the job to stop its payload, should it run, and discard it
+pub async fn cancel<T>(job: &Job, config: &Config, target: T) -> Result<(), TerminateError>
+where
+    T: Endpoint,
+{
+    match config.get_upload_url(&job.service) {
+        Some(url) => {
+            let url = sibling_url(url, "cancel");
+            Ok(target
+                .terminate(job, &url, auth(config, &job.service))
+                .await?)
+        }
         None => Err(TerminateError::GenericError),
     }
 }
 
+/// Ask the client of `service` for its load report
+pub async fn load<T>(service: &str, config: &Config, target: T) -> Result<LoadReport, LoadError>
+where
+    T: Endpoint,
+{
+    match config.get_upload_url(service) {
+        Some(url) => {
+            let url = sibling_url(url, "load");
+            let load = target.load(&url, auth(config, service));
+            // A hung client must not hold the sender back
+            match config.services[service].transfer_timeout {
+                Some(timeout) => tokio::time::timeout(timeout, load)
+                    .await
+                    .map_err(|_| LoadError::TimedOut(timeout))?,
+                None => load.await,
+            }
+        }
+        None => Err(LoadError::InvalidService),
+    }
+}
+
+/// Ask the client of `service` what payloads it can run
+pub async fn capabilities<T>(
+    service: &str,
+    config: &Config,
+    target: T,
+) -> Result<Capabilities, LoadError>
+where
+    T: Endpoint,
+{
+    match config.get_upload_url(service) {
+        Some(url) => {
+            let url = sibling_url(url, "capabilities");
+            let capabilities = target.capabilities(&url, auth(config, service));
+            match config.services[service].transfer_timeout {
+                Some(timeout) => tokio::time::timeout(timeout, capabilities)
+                    .await
+                    .map_err(|_| LoadError::TimedOut(timeout))?,
+                None => capabilities.await,
+            }
+        }
+        None => Err(LoadError::InvalidService),
+    }
+}
+
+/// Ask the client of `service` how its latest runs of `service` went, `None`
+/// when it has not finished any yet
+pub async fn run_stats<T>(
+    service: &str,
+    config: &Config,
+    target: T,
+) -> Result<Option<ServiceRunStats>, LoadError>
+where
+    T: Endpoint,
+{
+    match config.get_upload_url(service) {
+        Some(url) => {
+            let url = sibling_url(url, "stats");
+            let stats = target.stats(&url, auth(config, service));
+            let mut stats = match config.services[service].transfer_timeout {
+                Some(timeout) => tokio::time::timeout(timeout, stats)
+                    .await
+                    .map_err(|_| LoadError::TimedOut(timeout))??,
+                None => stats.await?,
+            };
+            Ok(stats.remove(service))
+        }
+        None => Err(LoadError::InvalidService),
+    }
+}
+
+/// Check that the client of `service` answers its `/health`, within the
+/// probe timeout whatever the service's own timeouts. Returns the version the
+/// client reported.
+pub async fn probe<T>(
+    service: &str,
+    config: &Config,
+    target: T,
+) -> Result<Option<String>, LoadError>
+where
+    T: Endpoint,
+{
+    match config.get_upload_url(service) {
+        Some(url) => {
+            let url = sibling_url(url, "health");
+            let timeout = config.probes.timeout;
+            tokio::time::timeout(timeout, target.health(&url, auth(config, service)))
+                .await
+                .map_err(|_| LoadError::TimedOut(timeout))?
+        }
+        None => Err(LoadError::InvalidService),
+    }
+}
+
+/// Where the client of `service` lists its payloads, services sharing a
+/// client share it
+pub fn payloads_url(service: &str, config: &Config) -> Option<String> {
+    config
+        .get_upload_url(service)
+        .map(|url| sibling_url(url, "payloads"))
+}
+
+/// Ask the client of `service` for its payloads created at least `older_than` ago
+pub async fn list_payloads<T>(
+    service: &str,
+    config: &Config,
+    older_than: Duration,
+    target: T,
+) -> Result<Vec<u32>, ReconcileError>
+where
+    T: Endpoint,
+{
+    match payloads_url(service, config) {
+        Some(url) => Ok(target
+            .list_payloads(&url, auth(config, service), older_than)
+            .await?),
+        None => Err(ReconcileError::InvalidService),
+    }
+}
+
+/// Have the client of `service` stop and remove payload `id`
+pub async fn delete_payload<T>(
+    service: &str,
+    id: u32,
+    config: &Config,
+    target: T,
+) -> Result<(), ReconcileError>
+where
+    T: Endpoint,
+{
+    match payloads_url(service, config) {
+        Some(url) => Ok(target
+            .delete_payload(&format!("{url}/{id}"), auth(config, service))
+            .await?),
+        None => Err(ReconcileError::InvalidService),
+    }
+}
+
+/// Replace the last path segment of `url` (e.g., "retrieve" or "submit") with `name`,
+/// handling URLs like "http://client/retrieve" or "http://client/download/"
+fn sibling_url(url: &str, name: &str) -> String {
+    match url.rfind('/') {
+        Some(pos) => format!("{}{name}", &url[..pos + 1]),
+        None => format!("{url}/{name}"),
+    }
+}
+
+/// Credentials for the service, only called once its url has been found
+fn auth<'a>(config: &'a Config, service: &str) -> &'a ServiceAuth {
+    &config.services[service].auth
+}
+
 // These are traits that all Destinations need to have
 pub trait Endpoint {
-    async fn upload(&self, j: &Job, url: &str) -> Result<u32, UploadError>;
-    async fn download(&self, j: &Job, url: &str) -> Result<Status, DownloadError>;
-    async fn download_partial(&self, j: &Job, url: &str) -> Result<Vec<u8>, DownloadPartialError>;
-    async fn terminate(&self, job_id: &Job, url: &str) -> Result<(), TerminateError>;
+    async fn upload(
+        &self,
+        j: &Job,
+        url: &str,
+        auth: &ServiceAuth,
+        options: SubmitOptions,
+    ) -> Result<u32, UploadError>;
+    async fn download(
+        &self,
+        j: &Job,
+        url: &str,
+        auth: &ServiceAuth,
+    ) -> Result<Retrieved, DownloadError>;
+    async fn status(
+        &self,
+        j: &Job,
+        url: &str,
+        auth: &ServiceAuth,
+    ) -> Result<PayloadStatus, DownloadError>;
+    async fn download_partial(
+        &self,
+        j: &Job,
+        url: &str,
+        auth: &ServiceAuth,
+    ) -> Result<Vec<u8>, DownloadPartialError>;
+    async fn terminate(
+        &self,
+        job_id: &Job,
+        url: &str,
+        auth: &ServiceAuth,
+    ) -> Result<(), TerminateError>;
+    async fn load(&self, url: &str, auth: &ServiceAuth) -> Result<LoadReport, LoadError>;
+    async fn capabilities(&self, url: &str, auth: &ServiceAuth) -> Result<Capabilities, LoadError>;
+    async fn stats(
+        &self,
+        url: &str,
+        auth: &ServiceAuth,
+    ) -> Result<BTreeMap<String, ServiceRunStats>, LoadError>;
+    /// The client's version, `None` when it reports none
+    async fn health(&self, url: &str, auth: &ServiceAuth) -> Result<Option<String>, LoadError>;
+    async fn list_payloads(
+        &self,
+        url: &str,
+        auth: &ServiceAuth,
+        older_than: Duration,
+    ) -> Result<Vec<u32>, ReconcileError>;
+    async fn delete_payload(&self, url: &str, auth: &ServiceAuth) -> Result<(), ReconcileError>;
 }
 
 /// Retrieve partial data (current state) from a job on the client
@@ -132,18 +617,10 @@ where
     } else {
         match config.get_download_url(&job.service) {
             Some(url) => {
-                // Replace the last path segment (e.g., "retrieve" or "download") with "retrieve_partial"
-                // This handles URLs like "http://client/retrieve" or "http://client/download"
-                let partial_url = if let Some(pos) = url.rfind('/') {
-                    if pos + 1 < url.len() {
-                        format!("{}retrieve_partial", &url[..pos + 1])
-                    } else {
-                        format!("{}retrieve_partial", url)
-                    }
-                } else {
-                    format!("{}/retrieve_partial", url)
-                };
-                Ok(target.download_partial(job, &partial_url).await?)
+                let partial_url = sibling_url(url, "retrieve_partial");
+                Ok(target
+                    .download_partial(job, &partial_url, auth(config, &job.service))
+                    .await?)
             }
             None => Err(DownloadPartialError::InvalidService),
         }
@@ -154,8 +631,8 @@ where
 mod tests {
     use super::*;
     use crate::config::loader::{Config, Service};
+    use crate::models::error_body::ErrorCode;
     use crate::models::job_dao::Job;
-    use crate::models::status_dto::Status;
     use std::collections::HashMap;
     use tempfile::TempDir;
 
@@ -163,41 +640,186 @@ mod tests {
     struct ErrMockEndpoint;
 
     impl Endpoint for OkMockEndpoint {
-        async fn upload(&self, _j: &Job, _url: &str) -> Result<u32, UploadError> {
+        async fn upload(
+            &self,
+            _j: &Job,
+            _url: &str,
+            _auth: &ServiceAuth,
+            _options: SubmitOptions,
+        ) -> Result<u32, UploadError> {
             Ok(42)
         }
-        async fn download(&self, _j: &Job, _url: &str) -> Result<Status, DownloadError> {
-            Ok(Status::Completed)
+        async fn download(
+            &self,
+            _j: &Job,
+            _url: &str,
+            _auth: &ServiceAuth,
+        ) -> Result<Retrieved, DownloadError> {
+            Ok(Retrieved::Downloaded)
+        }
+        async fn status(
+            &self,
+            _j: &Job,
+            url: &str,
+            _auth: &ServiceAuth,
+        ) -> Result<PayloadStatus, DownloadError> {
+            assert_eq!(url, "http://example.com/status");
+            Ok(PayloadStatus {
+                id: 42,
+                status: Status::Running,
+                progress: Default::default(),
+                failure: None,
+                summary: None,
+            })
         }
         async fn download_partial(
             &self,
             _j: &Job,
             _url: &str,
+            _auth: &ServiceAuth,
         ) -> Result<Vec<u8>, DownloadPartialError> {
             Ok(b"partial data".to_vec())
         }
-        async fn terminate(&self, _j: &Job, _url: &str) -> Result<(), TerminateError> {
+        async fn terminate(
+            &self,
+            _j: &Job,
+            _url: &str,
+            _auth: &ServiceAuth,
+        ) -> Result<(), TerminateError> {
+            Ok(())
+        }
+        async fn load(&self, _url: &str, _auth: &ServiceAuth) -> Result<LoadReport, LoadError> {
+            Ok(LoadReport {
+                cpu: 12.5,
+                disk_free: Some(1024),
+                rejected_submissions: Default::default(),
+            })
+        }
+        async fn capabilities(
+            &self,
+            url: &str,
+            _auth: &ServiceAuth,
+        ) -> Result<Capabilities, LoadError> {
+            assert_eq!(url, "http://example.com/capabilities");
+            Ok(Capabilities::detect(&Config::default()))
+        }
+        async fn stats(
+            &self,
+            url: &str,
+            _auth: &ServiceAuth,
+        ) -> Result<BTreeMap<String, ServiceRunStats>, LoadError> {
+            assert_eq!(url, "http://example.com/stats");
+            let stats = ServiceRunStats::new("test".to_string(), 3, 0, vec![10, 20, 30]);
+            Ok(BTreeMap::from([("test".to_string(), stats)]))
+        }
+        async fn health(
+            &self,
+            url: &str,
+            _auth: &ServiceAuth,
+        ) -> Result<Option<String>, LoadError> {
+            assert_eq!(url, "http://example.com/health");
+            Ok(Some("2.2.3".to_string()))
+        }
+        async fn list_payloads(
+            &self,
+            url: &str,
+            _auth: &ServiceAuth,
+            _older_than: Duration,
+        ) -> Result<Vec<u32>, ReconcileError> {
+            assert_eq!(url, "http://example.com/payloads");
+            Ok(vec![1, 2])
+        }
+        async fn delete_payload(
+            &self,
+            url: &str,
+            _auth: &ServiceAuth,
+        ) -> Result<(), ReconcileError> {
+            assert_eq!(url, "http://example.com/payloads/2");
             Ok(())
         }
     }
 
     impl Endpoint for ErrMockEndpoint {
-        async fn upload(&self, _j: &Job, _url: &str) -> Result<u32, UploadError> {
+        async fn upload(
+            &self,
+            _j: &Job,
+            _url: &str,
+            _auth: &ServiceAuth,
+            _options: SubmitOptions,
+        ) -> Result<u32, UploadError> {
             Err(UploadError::InvalidService)
         }
-        async fn download(&self, _j: &Job, _url: &str) -> Result<Status, DownloadError> {
+        async fn download(
+            &self,
+            _j: &Job,
+            _url: &str,
+            _auth: &ServiceAuth,
+        ) -> Result<Retrieved, DownloadError> {
+            Err(DownloadError::NotFound)
+        }
+        async fn status(
+            &self,
+            _j: &Job,
+            _url: &str,
+            _auth: &ServiceAuth,
+        ) -> Result<PayloadStatus, DownloadError> {
             Err(DownloadError::NotFound)
         }
         async fn download_partial(
             &self,
             _j: &Job,
             _url: &str,
+            _auth: &ServiceAuth,
         ) -> Result<Vec<u8>, DownloadPartialError> {
             Err(DownloadPartialError::NotFound)
         }
-        async fn terminate(&self, _j: &Job, _url: &str) -> Result<(), TerminateError> {
+        async fn terminate(
+            &self,
+            _j: &Job,
+            _url: &str,
+            _auth: &ServiceAuth,
+        ) -> Result<(), TerminateError> {
             Err(TerminateError::GenericError)
         }
+        async fn load(&self, _url: &str, _auth: &ServiceAuth) -> Result<LoadReport, LoadError> {
+            Err(LoadError::UnexpectedStatus(500))
+        }
+        async fn capabilities(
+            &self,
+            _url: &str,
+            _auth: &ServiceAuth,
+        ) -> Result<Capabilities, LoadError> {
+            Err(LoadError::UnexpectedStatus(404))
+        }
+        async fn stats(
+            &self,
+            _url: &str,
+            _auth: &ServiceAuth,
+        ) -> Result<BTreeMap<String, ServiceRunStats>, LoadError> {
+            Err(LoadError::UnexpectedStatus(500))
+        }
+        async fn health(
+            &self,
+            _url: &str,
+            _auth: &ServiceAuth,
+        ) -> Result<Option<String>, LoadError> {
+            Err(LoadError::UnexpectedStatus(503))
+        }
+        async fn list_payloads(
+            &self,
+            _url: &str,
+            _auth: &ServiceAuth,
+            _older_than: Duration,
+        ) -> Result<Vec<u32>, ReconcileError> {
+            Err(ReconcileError::UnexpectedStatus(500))
+        }
+        async fn delete_payload(
+            &self,
+            _url: &str,
+            _auth: &ServiceAuth,
+        ) -> Result<(), ReconcileError> {
+            Err(ReconcileError::UnexpectedStatus(500))
+        }
     }
 
     fn make_config() -> Config {
@@ -211,6 +833,7 @@ mod tests {
                 terminate_url: "http://example.com/terminate".to_string(),
                 runs_per_user: 5,
                 max_runs: 1,
+                ..Default::default()
             },
         );
         Config {
@@ -219,6 +842,7 @@ mod tests {
             data_path: "/tmp".to_string(),
             max_age: std::time::Duration::from_secs(3600),
             port: 5000,
+            ..Default::default()
         }
     }
 
@@ -238,6 +862,20 @@ mod tests {
         assert_eq!(result.unwrap(), 42);
     }
 
+    #[tokio::test]
+    async fn test_send_unpacks_job() {
+        let tempdir = TempDir::new().unwrap();
+        let config = make_config();
+        let job = make_job(tempdir.path().to_str().unwrap(), "test", 1);
+        std::fs::create_dir_all(&job.loc).unwrap();
+        std::fs::write(job.loc.join("run.sh"), b"echo").unwrap();
+        job.pack().unwrap();
+
+        send(&job, &config, OkMockEndpoint).await.unwrap();
+        assert!(!job.is_packed());
+        assert!(job.loc.join("run.sh").exists());
+    }
+
     #[tokio::test]
     async fn test_send_invalid_service() {
         let tempdir = TempDir::new().unwrap();
@@ -262,7 +900,7 @@ mod tests {
         let config = make_config();
         let job = make_job(tempdir.path().to_str().unwrap(), "test", 1);
         let result = retrieve(&job, &config, OkMockEndpoint).await;
-        assert_eq!(result.unwrap(), Status::Completed);
+        assert_eq!(result.unwrap(), Retrieved::Downloaded);
     }
 
     #[tokio::test]
@@ -292,6 +930,86 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[tokio::test]
+    async fn test_status() {
+        let tempdir = TempDir::new().unwrap();
+        let config = make_config();
+        let job = make_job(tempdir.path().to_str().unwrap(), "test", 1);
+        let result = status(&job, &config, OkMockEndpoint).await;
+        assert_eq!(result.unwrap().status, Status::Running);
+
+        let job = make_job(tempdir.path().to_str().unwrap(), "test", 0);
+        let result = status(&job, &config, OkMockEndpoint).await;
+        assert!(matches!(result.unwrap_err(), DownloadError::NotFound));
+
+        let job = make_job(tempdir.path().to_str().unwrap(), "nonexistent", 1);
+        let result = status(&job, &config, OkMockEndpoint).await;
+        assert!(matches!(result.unwrap_err(), DownloadError::InvalidService));
+    }
+
+    #[test]
+    fn test_download_error_is_transient() {
+        assert!(DownloadError::TimedOut(Duration::from_secs(1)).is_transient());
+        assert!(DownloadError::InvalidArchive("truncated".to_string()).is_transient());
+        let reset = std::io::Error::new(std::io::ErrorKind::ConnectionReset, "reset");
+        assert!(DownloadError::ResponseReadFailed(reset).is_transient());
+        let garbage =
+            serde_json::from_str::<crate::models::payload_dao::Payload>("not json").unwrap_err();
+        assert!(!DownloadError::ResponseReadFailed(garbage.into()).is_transient());
+        assert!(!DownloadError::NotFound.is_transient());
+        assert!(!DownloadError::InvalidService.is_transient());
+        assert!(!DownloadError::UnsupportedSchema(0).is_transient());
+        let full = ErrorBody::new(ErrorCode::DiskFull, "No space left on device");
+        assert!(
+            DownloadError::Rejected {
+                status: StatusCode::INSUFFICIENT_STORAGE,
+                error: full,
+            }
+            .is_transient()
+        );
+        let gone = ErrorBody::new(ErrorCode::NotFound, "no such payload");
+        assert!(
+            !DownloadError::Rejected {
+                status: StatusCode::NOT_FOUND,
+                error: gone,
+            }
+            .is_transient()
+        );
+    }
+
+    #[test]
+    fn test_upload_error_is_transient() {
+        assert!(UploadError::TimedOut(Duration::from_secs(1)).is_transient());
+        assert!(UploadError::InjectedFault.is_transient());
+        let full = ErrorBody::new(ErrorCode::DiskFull, "No space left on device");
+        assert!(
+            UploadError::Rejected {
+                status: StatusCode::INSUFFICIENT_STORAGE,
+                error: full,
+            }
+            .is_transient()
+        );
+        let invalid = ErrorBody::new(ErrorCode::InvalidRequest, "invalid timeout");
+        assert!(
+            !UploadError::Rejected {
+                status: StatusCode::BAD_REQUEST,
+                error: invalid,
+            }
+            .is_transient()
+        );
+        // Free text answers of a proxy in front of the client
+        let busy = ErrorBody::new(ErrorCode::Internal, "slow down");
+        assert!(
+            UploadError::Rejected {
+                status: StatusCode::TOO_MANY_REQUESTS,
+                error: busy,
+            }
+            .is_transient()
+        );
+        assert!(!UploadError::InvalidService.is_transient());
+        assert!(!UploadError::UnsupportedSchema(0).is_transient());
+    }
+
     #[tokio::test]
     async fn test_retrieve_partial_valid_job() {
         let tempdir = TempDir::new().unwrap();
@@ -367,6 +1085,7 @@ mod tests {
             data_path: "/tmp".to_string(),
             max_age: std::time::Duration::from_secs(3600),
             port: 5000,
+            ..Default::default()
         };
         let job = make_job("/tmp", "nonexistent", 1);
         let result = kill(&job, &config, OkMockEndpoint).await;
@@ -387,4 +1106,93 @@ mod tests {
         assert!(result.is_ok());
         assert_eq!(result.unwrap(), b"partial data");
     }
+
+    #[test]
+    fn test_sibling_url() {
+        assert_eq!(
+            sibling_url("http://client:9000/submit", "load"),
+            "http://client:9000/load"
+        );
+        assert_eq!(
+            sibling_url("http://client:9000/retrieve/", "load"),
+            "http://client:9000/retrieve/load"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_load() {
+        let config = make_config();
+        let report = load("test", &config, OkMockEndpoint).await.unwrap();
+        assert_eq!(report.disk_free, Some(1024));
+
+        let result = load("nonexistent", &config, OkMockEndpoint).await;
+        assert!(matches!(result.unwrap_err(), LoadError::InvalidService));
+        assert!(load("test", &config, ErrMockEndpoint).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_run_stats() {
+        let config = make_config();
+        let stats = run_stats("test", &config, OkMockEndpoint).await.unwrap();
+        assert_eq!(stats.unwrap().p50_secs, Some(20));
+
+        let result = run_stats("nonexistent", &config, OkMockEndpoint).await;
+        assert!(matches!(result.unwrap_err(), LoadError::InvalidService));
+        assert!(run_stats("test", &config, ErrMockEndpoint).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_capabilities() {
+        let config = make_config();
+        let caps = capabilities("test", &config, OkMockEndpoint).await.unwrap();
+        assert_eq!(caps.executor, "bash");
+
+        let result = capabilities("nonexistent", &config, OkMockEndpoint).await;
+        assert!(matches!(result.unwrap_err(), LoadError::InvalidService));
+        assert!(
+            capabilities("test", &config, ErrMockEndpoint)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_probe() {
+        let config = make_config();
+        let version = probe("test", &config, OkMockEndpoint).await.unwrap();
+        assert_eq!(version.as_deref(), Some("2.2.3"));
+
+        let result = probe("nonexistent", &config, OkMockEndpoint).await;
+        assert!(matches!(result.unwrap_err(), LoadError::InvalidService));
+        let result = probe("test", &config, ErrMockEndpoint).await;
+        assert!(matches!(
+            result.unwrap_err(),
+            LoadError::UnexpectedStatus(503)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_list_and_delete_payloads() {
+        let config = make_config();
+        let ids = list_payloads("test", &config, Duration::ZERO, OkMockEndpoint)
+            .await
+            .unwrap();
+        assert_eq!(ids, vec![1, 2]);
+        assert!(
+            delete_payload("test", 2, &config, OkMockEndpoint)
+                .await
+                .is_ok()
+        );
+
+        let result = list_payloads("nonexistent", &config, Duration::ZERO, OkMockEndpoint).await;
+        assert!(matches!(
+            result.unwrap_err(),
+            ReconcileError::InvalidService
+        ));
+        assert!(
+            delete_payload("test", 2, &config, ErrMockEndpoint)
+                .await
+                .is_err()
+        );
+    }
 }
@@ -0,0 +1,436 @@
+use crate::config::loader::Config;
+use crate::models::job_dao::Job;
+use futures::future::BoxFuture;
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+/// Points of the life of a job where site-specific logic steps in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookPoint {
+    /// A submission passed the checks of the server and is about to be
+    /// queued, the job has no id yet
+    PreSubmit,
+    /// The results of a job are in and it was marked `completed`
+    PostComplete,
+    /// A job aged out and its files are about to be removed or archived
+    PreClean,
+}
+
+impl HookPoint {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HookPoint::PreSubmit => "pre_submit",
+            HookPoint::PostComplete => "post_complete",
+            HookPoint::PreClean => "pre_clean",
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum HookError {
+    /// The hook turned the job down, e.g. a submission over a site budget
+    #[error("{0}")]
+    Rejected(String),
+    /// The hook could not tell, e.g. it is missing or timed out
+    #[error("hook failed: {0}")]
+    Failed(String),
+}
+
+/// Site-specific logic run at the [`HookPoint`]s, e.g. custom accounting or
+/// extra validation. A Rust plugin implements it and is registered in
+/// [`Hooks::new`], next to the programs configured with `HOOK_<POINT>`.
+pub trait Hook: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn handles(&self, point: HookPoint, job: &Job) -> bool;
+
+    /// An error at [`HookPoint::PreSubmit`] rejects the submission and one at
+    /// [`HookPoint::PreClean`] keeps the files until the next round, one at
+    /// [`HookPoint::PostComplete`] is only logged
+    fn run<'a>(&'a self, point: HookPoint, job: &'a Job) -> BoxFuture<'a, Result<(), HookError>>;
+}
+
+/// Run `command` with `job` as JSON on its stdin: exiting with `0` lets the
+/// job through, `1` rejects it with the last line of stderr as the reason,
+/// anything else or running past `timeout` is a failure
+async fn run_program(command: &[OsString], job: &Job, timeout: Duration) -> Result<(), HookError> {
+    let Some((program, args)) = command.split_first() else {
+        return Err(HookError::Failed("empty command".to_string()));
+    };
+    let name = program.to_string_lossy();
+    let input = serde_json::to_vec(job).map_err(|e| HookError::Failed(e.to_string()))?;
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| HookError::Failed(format!("could not start {name}: {e}")))?;
+
+    let run = async {
+        // A program not reading its input still gets to answer
+        if let Some(mut stdin) = child.stdin.take() {
+            let _ = stdin.write_all(&input).await;
+        }
+        child.wait_with_output().await
+    };
+    let output = match tokio::time::timeout(timeout, run).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(e)) => return Err(HookError::Failed(format!("{name}: {e}"))),
+        Err(_) => {
+            return Err(HookError::Failed(format!(
+                "{name} did not finish within {}s",
+                timeout.as_secs()
+            )));
+        }
+    };
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let reason = stderr.lines().rfind(|l| !l.trim().is_empty());
+    match output.status.code() {
+        Some(0) => Ok(()),
+        Some(1) => Err(HookError::Rejected(
+            reason.unwrap_or("rejected by a hook").trim().to_string(),
+        )),
+        _ => Err(HookError::Failed(format!(
+            "{name} exited with {}{}",
+            output.status,
+            reason
+                .map(|r| format!(": {}", r.trim()))
+                .unwrap_or_default()
+        ))),
+    }
+}
+
+/// Runs a program with the job as JSON on its stdin and the hook point as its
+/// only argument, see [`run_program`] for what its exit code means
+pub struct CommandHook {
+    pub point: HookPoint,
+    pub program: PathBuf,
+    pub timeout: Duration,
+}
+
+impl Hook for CommandHook {
+    fn name(&self) -> &str {
+        self.program.to_str().unwrap_or("command")
+    }
+
+    fn handles(&self, point: HookPoint, _job: &Job) -> bool {
+        self.point == point
+    }
+
+    fn run<'a>(&'a self, point: HookPoint, job: &'a Job) -> BoxFuture<'a, Result<(), HookError>> {
+        let command = [self.program.clone().into(), point.as_str().into()];
+        Box::pin(async move { run_program(&command, job, self.timeout).await })
+    }
+}
+
+/// Where the job directory is mapped for the module of a [`WasmHook`]
+pub const WASM_JOB_DIR: &str = "/job";
+
+/// WASI access the module of a [`WasmHook`] is denied, whatever the version of
+/// the runtime defaults to: no network, no name lookups, no environment
+const WASM_DENIED: [&str; 5] = [
+    "inherit-network=n",
+    "allow-ip-name-lookup=n",
+    "tcp=n",
+    "udp=n",
+    "inherit-env=n",
+];
+
+/// Runs the WebAssembly module of a service on its submissions with
+/// wasmtime. The server sets the sandbox of the module: the job directory
+/// under [`WASM_JOB_DIR`] is the only one it can reach, it has no network and
+/// is stopped after running out of fuel, memory or the hook timeout. The
+/// module gets the job like a [`CommandHook`] and may rewrite the files it was
+/// sent. It is read again on every submission, so a new version applies
+/// without restarting the server.
+pub struct WasmHook {
+    pub service: String,
+    pub module: PathBuf,
+    /// The wasmtime executable, see [`WasmHook::command`] for its arguments
+    pub runtime: PathBuf,
+    pub max_memory: u64,
+    pub fuel: u64,
+    pub timeout: Duration,
+}
+
+impl WasmHook {
+    fn command(&self, point: HookPoint, job: &Job) -> Vec<OsString> {
+        let mut dir = job.loc.clone().into_os_string();
+        dir.push(format!("::{WASM_JOB_DIR}"));
+        let mut command: Vec<OsString> = vec![
+            self.runtime.clone().into(),
+            "run".into(),
+            "--dir".into(),
+            dir,
+        ];
+        let limits = [
+            format!("fuel={}", self.fuel),
+            format!("max-memory-size={}", self.max_memory),
+            format!("timeout={}ms", self.timeout.as_millis()),
+        ];
+        for limit in limits {
+            command.extend(["-W".into(), limit.into()]);
+        }
+        for denied in WASM_DENIED {
+            command.extend(["-S".into(), denied.into()]);
+        }
+        command.extend([self.module.clone().into(), point.as_str().into()]);
+        command
+    }
+}
+
+impl Hook for WasmHook {
+    fn name(&self) -> &str {
+        self.module.to_str().unwrap_or("wasm")
+    }
+
+    fn handles(&self, point: HookPoint, job: &Job) -> bool {
+        point == HookPoint::PreSubmit && job.service == self.service
+    }
+
+    fn run<'a>(&'a self, point: HookPoint, job: &'a Job) -> BoxFuture<'a, Result<(), HookError>> {
+        let command = self.command(point, job);
+        Box::pin(async move { run_program(&command, job, self.timeout).await })
+    }
+}
+
+/// Runs the registered hooks at each point
+pub struct Hooks {
+    hooks: Vec<Box<dyn Hook>>,
+}
+
+impl Hooks {
+    pub fn new(config: &Config) -> Hooks {
+        let settings = &config.hooks;
+        let hooks = [
+            (HookPoint::PreSubmit, &settings.pre_submit),
+            (HookPoint::PostComplete, &settings.post_complete),
+            (HookPoint::PreClean, &settings.pre_clean),
+        ]
+        .into_iter()
+        .filter_map(|(point, program)| {
+            let program = program.clone()?;
+            Some(Box::new(CommandHook {
+                point,
+                program,
+                timeout: settings.timeout,
+            }) as Box<dyn Hook>)
+        });
+        let mut validators: Vec<_> = config
+            .services
+            .values()
+            .filter_map(|s| Some((s.name.clone(), s.wasm_validator.clone()?)))
+            .collect();
+        validators.sort();
+        let validators = validators.into_iter().map(|(service, module)| {
+            Box::new(WasmHook {
+                service,
+                module,
+                runtime: settings.wasm_runtime.clone(),
+                max_memory: settings.wasm_max_memory,
+                fuel: settings.wasm_fuel,
+                timeout: settings.timeout,
+            }) as Box<dyn Hook>
+        });
+        // The site hooks see the files as the service module left them
+        Hooks {
+            hooks: validators.chain(hooks).collect(),
+        }
+    }
+
+    /// Run the hooks of `point` on `job` in turn, the first error stops them
+    pub async fn run(&self, point: HookPoint, job: &Job) -> Result<(), HookError> {
+        for hook in self.hooks.iter().filter(|h| h.handles(point, job)) {
+            hook.run(point, job).await.inspect_err(|e| {
+                tracing::warn!(
+                    "Hook {} on job {} at {}: {e}",
+                    hook.name(),
+                    job.id,
+                    point.as_str()
+                )
+            })?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::loader::{HooksConfig, Service};
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::TempDir;
+
+    /// An executable shell script in `dir`
+    fn script(dir: &TempDir, name: &str, body: &str) -> PathBuf {
+        let path = dir.path().join(name);
+        fs::write(&path, format!("#!/bin/sh\n{body}\n")).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    fn hook(program: PathBuf) -> CommandHook {
+        CommandHook {
+            point: HookPoint::PreSubmit,
+            program,
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_command_hook() {
+        let dir = TempDir::new().unwrap();
+        let mut job = Job::new(dir.path().to_str().unwrap());
+        job.set_service("haddock".to_string());
+        let point = HookPoint::PreSubmit;
+
+        // The program reads the job and the point it runs at
+        let seen = dir.path().join("seen");
+        let accept = script(
+            &dir,
+            "accept",
+            &format!("cat > {0}; echo \"$1\" >> {0}", seen.display()),
+        );
+        assert!(hook(accept).run(point, &job).await.is_ok());
+        let seen = fs::read_to_string(seen).unwrap();
+        assert!(seen.contains(r#""service":"haddock""#));
+        assert!(seen.ends_with("pre_submit\n"));
+
+        let reject = script(
+            &dir,
+            "reject",
+            "echo checking >&2; echo over budget >&2; exit 1",
+        );
+        match hook(reject).run(point, &job).await {
+            Err(HookError::Rejected(reason)) => assert_eq!(reason, "over budget"),
+            other => panic!("expected a rejection, got {other:?}"),
+        }
+
+        let broken = script(&dir, "broken", "exit 3");
+        assert!(matches!(
+            hook(broken).run(point, &job).await,
+            Err(HookError::Failed(_))
+        ));
+
+        let missing = dir.path().join("missing");
+        assert!(matches!(
+            hook(missing).run(point, &job).await,
+            Err(HookError::Failed(_))
+        ));
+
+        let slow = CommandHook {
+            timeout: Duration::from_millis(100),
+            ..hook(script(&dir, "slow", "sleep 5"))
+        };
+        match slow.run(point, &job).await {
+            Err(HookError::Failed(reason)) => assert!(reason.contains("did not finish")),
+            other => panic!("expected a timeout, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_wasm_hook() {
+        let dir = TempDir::new().unwrap();
+        // Stands in for wasmtime, the module says what it does
+        let runtime = script(
+            &dir,
+            "runtime",
+            r#"job=${3%%::*}
+echo "$@" > "$job/args"
+eval module=\${$(($# - 1))}
+case $(cat "$module") in
+reject) echo "missing chain A" >&2; exit 1 ;;
+*) echo rewritten > "$job/input.pdb" ;;
+esac"#,
+        );
+        let module = dir.path().join("check.wasm");
+        fs::write(&module, "accept").unwrap();
+
+        let mut config = Config::default();
+        config.hooks.wasm_runtime = runtime;
+        config.hooks.timeout = Duration::from_secs(5);
+        config.services.insert(
+            "haddock".to_string(),
+            Service {
+                name: "haddock".to_string(),
+                wasm_validator: Some(module.clone()),
+                ..Default::default()
+            },
+        );
+        let hooks = Hooks::new(&config);
+
+        let mut job = Job::new(dir.path().to_str().unwrap());
+        fs::create_dir_all(&job.loc).unwrap();
+        fs::write(job.loc.join("input.pdb"), "original").unwrap();
+
+        // Only the jobs of its service go through the module
+        job.set_service("other".to_string());
+        assert!(hooks.run(HookPoint::PreSubmit, &job).await.is_ok());
+        assert!(!job.loc.join("args").exists());
+
+        job.set_service("haddock".to_string());
+        assert!(hooks.run(HookPoint::PostComplete, &job).await.is_ok());
+        assert!(!job.loc.join("args").exists());
+
+        assert!(hooks.run(HookPoint::PreSubmit, &job).await.is_ok());
+        // Confined to the job directory, without network and within limits
+        assert_eq!(
+            fs::read_to_string(job.loc.join("args")).unwrap(),
+            format!(
+                "run --dir {}::{WASM_JOB_DIR} -W fuel=10000000000 -W max-memory-size=268435456 \
+                 -W timeout=5000ms -S inherit-network=n -S allow-ip-name-lookup=n -S tcp=n \
+                 -S udp=n -S inherit-env=n {} pre_submit\n",
+                job.loc.display(),
+                module.display()
+            )
+        );
+        assert_eq!(
+            fs::read_to_string(job.loc.join("input.pdb")).unwrap(),
+            "rewritten\n"
+        );
+
+        // A new module applies to the next submission
+        fs::write(&module, "reject").unwrap();
+        match hooks.run(HookPoint::PreSubmit, &job).await {
+            Err(HookError::Rejected(reason)) => assert_eq!(reason, "missing chain A"),
+            other => panic!("expected a rejection, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hooks_run_at_their_point() {
+        let dir = TempDir::new().unwrap();
+        let job = Job::new(dir.path().to_str().unwrap());
+        let config = Config {
+            hooks: HooksConfig {
+                pre_submit: Some(script(&dir, "reject", "exit 1")),
+                pre_clean: Some(script(&dir, "accept", "exit 0")),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let hooks = Hooks::new(&config);
+        assert!(matches!(
+            hooks.run(HookPoint::PreSubmit, &job).await,
+            Err(HookError::Rejected(_))
+        ));
+        assert!(hooks.run(HookPoint::PreClean, &job).await.is_ok());
+        // Nothing configured runs nothing
+        assert!(hooks.run(HookPoint::PostComplete, &job).await.is_ok());
+        assert!(
+            Hooks::new(&Config::default())
+                .run(HookPoint::PreSubmit, &job)
+                .await
+                .is_ok()
+        );
+    }
+}
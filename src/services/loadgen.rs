@@ -0,0 +1,351 @@
+use crate::models::status_body::StatusBody;
+use crate::models::status_dto::Status;
+use crate::services::client::Client;
+use axum::http::{StatusCode, header};
+use futures::stream::{self, StreamExt};
+use reqwest::multipart::{Form, Part};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Script of the synthetic payloads, cheap to run and producing one file
+const SCRIPT: &str = "#!/bin/bash\nwc -c input.dat > output.txt\n";
+
+/// What `loadgen` sends to the server and how
+#[derive(Debug, Clone)]
+pub struct LoadgenOptions {
+    /// Address of the server, e.g. `http://localhost:5000`
+    pub target: String,
+    pub service: String,
+    pub user_id: u32,
+    pub jobs: usize,
+    /// Jobs in flight at the same time
+    pub concurrency: usize,
+    /// Bytes of the input file sent along the script
+    pub input_size: usize,
+    pub poll_interval: Duration,
+    /// A job not completed by then counts as an error
+    pub timeout: Duration,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LoadgenError {
+    #[error("Request failed: {0}")]
+    RequestFailed(#[from] reqwest::Error),
+    #[error("Unexpected HTTP status on {phase}: {status}")]
+    UnexpectedStatus {
+        phase: &'static str,
+        status: StatusCode,
+    },
+    #[error("Job {id} ended {status}")]
+    JobEnded { id: u32, status: Status },
+    #[error("Job {0} did not complete in time")]
+    TimedOut(u32),
+}
+
+impl LoadgenError {
+    /// Short label the errors are counted under in the report
+    fn kind(&self) -> String {
+        match self {
+            LoadgenError::RequestFailed(_) => "request failed".to_string(),
+            LoadgenError::UnexpectedStatus { phase, status } => {
+                format!("{phase} returned {}", status.as_u16())
+            }
+            LoadgenError::JobEnded { status, .. } => format!("job {status}"),
+            LoadgenError::TimedOut(_) => "timed out".to_string(),
+        }
+    }
+}
+
+/// Timings of one synthetic job that went through
+#[derive(Debug)]
+struct Sample {
+    /// `POST /upload` until the server answered
+    submit: Duration,
+    /// Accepted until the results were available
+    completion: Duration,
+    /// The request that brought the results back
+    download: Duration,
+}
+
+/// Latency distribution of one phase, nearest-rank percentiles
+#[derive(Debug, PartialEq)]
+pub struct Percentiles {
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl Percentiles {
+    /// `None` without samples
+    pub fn of(mut samples: Vec<Duration>) -> Option<Percentiles> {
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort();
+        let rank = |p: usize| samples[(samples.len() * p).div_ceil(100).max(1) - 1];
+        Some(Percentiles {
+            p50: rank(50),
+            p90: rank(90),
+            p99: rank(99),
+            max: rank(100),
+        })
+    }
+}
+
+/// Outcome of a `loadgen` run
+#[derive(Debug)]
+pub struct LoadgenReport {
+    pub jobs: usize,
+    pub completed: usize,
+    /// Failed jobs per kind of error
+    pub errors: BTreeMap<String, usize>,
+    pub elapsed: Duration,
+    pub submit: Option<Percentiles>,
+    pub completion: Option<Percentiles>,
+    pub download: Option<Percentiles>,
+}
+
+impl LoadgenReport {
+    pub fn error_rate(&self) -> f64 {
+        match self.jobs {
+            0 => 0.0,
+            n => (n - self.completed) as f64 / n as f64,
+        }
+    }
+}
+
+impl fmt::Display for LoadgenReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} jobs in {:.1}s: {} completed, error rate {:.1}%",
+            self.jobs,
+            self.elapsed.as_secs_f64(),
+            self.completed,
+            self.error_rate() * 100.0
+        )?;
+        for (phase, percentiles) in [
+            ("submit", &self.submit),
+            ("completion", &self.completion),
+            ("download", &self.download),
+        ] {
+            if let Some(p) = percentiles {
+                writeln!(
+                    f,
+                    "{phase:>10}: p50 {:?}  p90 {:?}  p99 {:?}  max {:?}",
+                    p.p50, p.p90, p.p99, p.max
+                )?;
+            }
+        }
+        for (kind, count) in &self.errors {
+            writeln!(f, "{count:>10} x {kind}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Submit `options.jobs` synthetic payloads to the server, follow each one
+/// until its results are downloaded and report the latencies and errors
+pub async fn run(client: &Client, options: &LoadgenOptions) -> LoadgenReport {
+    let started = Instant::now();
+    let outcomes: Vec<Result<Sample, LoadgenError>> = stream::iter(0..options.jobs)
+        .map(|_| one_job(client, options))
+        .buffer_unordered(options.concurrency.max(1))
+        .collect()
+        .await;
+
+    let mut report = LoadgenReport {
+        jobs: options.jobs,
+        completed: 0,
+        errors: BTreeMap::new(),
+        elapsed: started.elapsed(),
+        submit: None,
+        completion: None,
+        download: None,
+    };
+    let (mut submit, mut completion, mut download) = (Vec::new(), Vec::new(), Vec::new());
+    for outcome in outcomes {
+        match outcome {
+            Ok(sample) => {
+                report.completed += 1;
+                submit.push(sample.submit);
+                completion.push(sample.completion);
+                download.push(sample.download);
+            }
+            Err(e) => {
+                tracing::debug!("loadgen job failed: {e}");
+                *report.errors.entry(e.kind()).or_default() += 1;
+            }
+        }
+    }
+    report.submit = Percentiles::of(submit);
+    report.completion = Percentiles::of(completion);
+    report.download = Percentiles::of(download);
+    report
+}
+
+async fn one_job(client: &Client, options: &LoadgenOptions) -> Result<Sample, LoadgenError> {
+    let target = options.target.trim_end_matches('/');
+    let form = Form::new()
+        .text("user_id", options.user_id.to_string())
+        .text("service", options.service.clone())
+        .part("run.sh", Part::bytes(SCRIPT.as_bytes()).file_name("run.sh"))
+        .part(
+            "input.dat",
+            Part::bytes(vec![b'x'; options.input_size]).file_name("input.dat"),
+        );
+
+    let started = Instant::now();
+    let response = client
+        .http
+        .post(format!("{target}/upload"))
+        .multipart(form)
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(LoadgenError::UnexpectedStatus {
+            phase: "upload",
+            status: response.status(),
+        });
+    }
+    let id = response.json::<StatusBody>().await?.id;
+    let submit = started.elapsed();
+
+    let accepted = Instant::now();
+    loop {
+        let requested = Instant::now();
+        let response = client
+            .http
+            .get(format!("{target}/download/{id}"))
+            .send()
+            .await?;
+        if response.status() != StatusCode::OK {
+            return Err(LoadgenError::UnexpectedStatus {
+                phase: "download",
+                status: response.status(),
+            });
+        }
+
+        let is_archive = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.contains("application/zip"));
+        if is_archive {
+            response.bytes().await?;
+            return Ok(Sample {
+                submit,
+                completion: accepted.elapsed(),
+                download: requested.elapsed(),
+            });
+        }
+
+        let status = response.json::<StatusBody>().await?.status;
+        if matches!(
+            status,
+            Status::Failed | Status::Invalid | Status::Killed | Status::Cancelled | Status::Cleaned
+        ) {
+            return Err(LoadgenError::JobEnded { id, status });
+        }
+        if accepted.elapsed() + options.poll_interval > options.timeout {
+            return Err(LoadgenError::TimedOut(id));
+        }
+        tokio::time::sleep(options.poll_interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::{Matcher, Server};
+
+    fn options(target: String, jobs: usize) -> LoadgenOptions {
+        LoadgenOptions {
+            target,
+            service: "example".to_string(),
+            user_id: 1,
+            jobs,
+            concurrency: 2,
+            input_size: 16,
+            poll_interval: Duration::from_millis(10),
+            timeout: Duration::from_millis(100),
+        }
+    }
+
+    #[test]
+    fn test_percentiles() {
+        assert_eq!(Percentiles::of(Vec::new()), None);
+
+        let samples = (1..=100).rev().map(Duration::from_millis).collect();
+        assert_eq!(
+            Percentiles::of(samples),
+            Some(Percentiles {
+                p50: Duration::from_millis(50),
+                p90: Duration::from_millis(90),
+                p99: Duration::from_millis(99),
+                max: Duration::from_millis(100),
+            })
+        );
+
+        let single = Percentiles::of(vec![Duration::from_millis(7)]).unwrap();
+        assert_eq!(single.p50, Duration::from_millis(7));
+        assert_eq!(single.p99, Duration::from_millis(7));
+    }
+
+    #[tokio::test]
+    async fn test_run() {
+        let mut server = Server::new_async().await;
+        let upload = server
+            .mock("POST", "/upload")
+            .match_body(Matcher::Regex(
+                "name=\"service\"\r\n\r\nexample".to_string(),
+            ))
+            .with_status(201)
+            .with_body(r#"{"id": 1, "status": "Queued", "message": ""}"#)
+            .expect(3)
+            .create_async()
+            .await;
+        let _completed = server
+            .mock("GET", "/download/1")
+            .with_header("content-type", "application/zip")
+            .with_body(b"PK")
+            .create_async()
+            .await;
+
+        let report = run(&Client::default(), &options(server.url(), 3)).await;
+        upload.assert_async().await;
+        assert_eq!(report.completed, 3);
+        assert_eq!(report.error_rate(), 0.0);
+        assert!(report.errors.is_empty());
+        assert!(report.submit.is_some());
+        assert!(report.download.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_run_errors() {
+        let mut server = Server::new_async().await;
+        let _upload = server
+            .mock("POST", "/upload")
+            .with_status(201)
+            .with_body(r#"{"id": 2, "status": "Queued", "message": ""}"#)
+            .create_async()
+            .await;
+        let _queued = server
+            .mock("GET", "/download/2")
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"id": 2, "status": "Queued", "message": ""}"#)
+            .create_async()
+            .await;
+
+        let report = run(&Client::default(), &options(server.url(), 2)).await;
+        assert_eq!(report.completed, 0);
+        assert_eq!(report.error_rate(), 1.0);
+        assert_eq!(report.errors["timed out"], 2);
+        assert_eq!(report.completion, None);
+
+        let unreachable = run(&Client::default(), &options(server.url() + "/nowhere", 1)).await;
+        assert_eq!(unreachable.errors["upload returned 501"], 1);
+    }
+}
@@ -1,3 +1,14 @@
+pub mod alert;
 pub mod client;
+pub mod datasets;
 pub mod endpoint;
+pub mod hooks;
+pub mod loadgen;
+pub mod notify;
+pub mod object_store;
+pub mod oidc;
+pub mod presign;
+pub mod remote_input;
+pub mod result_store;
 pub mod server;
+pub mod webhook;
@@ -0,0 +1,438 @@
+use crate::config::loader::{Config, Secret, Subscription};
+use crate::models::webhook_dao::{Channel, WebhookDelivery};
+use crate::services::client::Client;
+use crate::services::webhook::{Attempt, DELIVERY_HEADER, EVENT_HEADER, SIGNATURE_HEADER, sign};
+use futures::future::BoxFuture;
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Something to tell, an event of a job or an operator alert
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notification {
+    /// e.g. `job.completed` or `alert.queue_depth`
+    pub event: String,
+    /// JSON body, sent as is by the webhooks so its signature holds
+    pub payload: String,
+}
+
+impl Notification {
+    pub fn new(event: &str, payload: &serde_json::Value) -> Notification {
+        Notification {
+            event: event.to_string(),
+            payload: payload.to_string(),
+        }
+    }
+
+    /// One line for the channels read by people
+    pub fn summary(&self) -> String {
+        let payload: serde_json::Value = serde_json::from_str(&self.payload).unwrap_or_default();
+        let Some(id) = payload.get("job_id") else {
+            return format!("{}: {payload}", self.event);
+        };
+        let mut line = format!("{}: job {id}", self.event);
+        if let Some(service) = payload["service"].as_str() {
+            line.push_str(&format!(" of {service}"));
+        }
+        if let Some(reason) = payload["reason"].as_str() {
+            line.push_str(&format!(", {reason}"));
+        }
+        line
+    }
+}
+
+/// A way of reaching people. A new channel implements it and is registered in
+/// [`Dispatcher::new`], the tasks only ever talk to the dispatcher.
+pub trait Notifier: Send + Sync {
+    fn channel(&self) -> Channel;
+
+    /// Send `notification` to `target`. `id` stays the same on every retry of
+    /// a delivery, so receivers can drop duplicates.
+    fn send<'a>(
+        &'a self,
+        target: &'a str,
+        id: &'a str,
+        notification: &'a Notification,
+    ) -> BoxFuture<'a, Attempt>;
+}
+
+/// Any 2xx counts as delivered
+async fn outcome(result: Result<reqwest::Response, reqwest::Error>) -> Attempt {
+    match result {
+        Ok(r) if r.status().is_success() => Attempt::Delivered(r.status().as_u16()),
+        Ok(r) => Attempt::Failed(
+            Some(r.status().as_u16()),
+            format!("Answered {}", r.status()),
+        ),
+        Err(e) => Attempt::Failed(None, e.to_string()),
+    }
+}
+
+/// POSTs the payload, signed with `WEBHOOK_SECRET` when set
+pub struct WebhookNotifier {
+    pub client: Client,
+    pub secret: Option<Secret>,
+}
+
+impl Notifier for WebhookNotifier {
+    fn channel(&self) -> Channel {
+        Channel::Webhook
+    }
+
+    fn send<'a>(
+        &'a self,
+        target: &'a str,
+        id: &'a str,
+        notification: &'a Notification,
+    ) -> BoxFuture<'a, Attempt> {
+        Box::pin(async move {
+            let mut request = self
+                .client
+                .http
+                .post(target)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header(EVENT_HEADER, &notification.event)
+                .header(DELIVERY_HEADER, id);
+            if let Some(secret) = &self.secret {
+                request = request.header(
+                    SIGNATURE_HEADER,
+                    sign(secret, notification.payload.as_bytes()),
+                );
+            }
+            outcome(request.body(notification.payload.clone()).send().await).await
+        })
+    }
+}
+
+/// POSTs the summary to a Slack incoming webhook
+pub struct SlackNotifier {
+    pub client: Client,
+}
+
+impl Notifier for SlackNotifier {
+    fn channel(&self) -> Channel {
+        Channel::Slack
+    }
+
+    fn send<'a>(
+        &'a self,
+        target: &'a str,
+        _id: &'a str,
+        notification: &'a Notification,
+    ) -> BoxFuture<'a, Attempt> {
+        Box::pin(async move {
+            let body = serde_json::json!({ "text": notification.summary() });
+            outcome(self.client.http.post(target).json(&body).send().await).await
+        })
+    }
+}
+
+/// Hands each notification to the notifier of its channel
+pub struct Dispatcher {
+    notifiers: Vec<Box<dyn Notifier>>,
+}
+
+impl Dispatcher {
+    pub fn new(config: &Config, client: &Client) -> Dispatcher {
+        Dispatcher {
+            notifiers: vec![
+                Box::new(WebhookNotifier {
+                    client: client.clone(),
+                    secret: config.webhook.secret.clone(),
+                }),
+                Box::new(SlackNotifier {
+                    client: client.clone(),
+                }),
+            ],
+        }
+    }
+
+    pub async fn send(
+        &self,
+        channel: Channel,
+        target: &str,
+        id: &str,
+        notification: &Notification,
+    ) -> Attempt {
+        match self.notifiers.iter().find(|n| n.channel() == channel) {
+            Some(notifier) => notifier.send(target, id, notification).await,
+            None => Attempt::Failed(None, format!("No notifier for {}", channel.as_str())),
+        }
+    }
+
+    /// Send a queued delivery
+    pub async fn deliver(&self, delivery: &WebhookDelivery) -> Attempt {
+        let notification = Notification {
+            event: delivery.event.clone(),
+            payload: delivery.payload.clone(),
+        };
+        let id = delivery.id.to_string();
+        self.send(delivery.channel, &delivery.url, &id, &notification)
+            .await
+    }
+
+    /// Send `notification` once to every target, without retries. Returns
+    /// how many were reached.
+    pub async fn broadcast(
+        &self,
+        notification: &Notification,
+        targets: &[(Channel, String)],
+    ) -> usize {
+        let id = uuid::Uuid::new_v4().to_string();
+        let mut reached = 0;
+        for (channel, target) in targets {
+            match self.send(*channel, target, &id, notification).await {
+                Attempt::Delivered(_) => reached += 1,
+                Attempt::Failed(_, e) => tracing::error!(
+                    "Failed to send {} over {}: {e}",
+                    notification.event,
+                    channel.as_str()
+                ),
+            }
+        }
+        reached
+    }
+}
+
+/// Operator subscriptions wanting `event`, by name
+pub fn subscribers<'a>(
+    subscriptions: &'a HashMap<String, Subscription>,
+    event: &str,
+) -> Vec<&'a Subscription> {
+    let mut wanted: Vec<_> = subscriptions.values().filter(|s| s.wants(event)).collect();
+    wanted.sort_by(|a, b| a.name.cmp(&b.name));
+    wanted
+}
+
+/// Queue `notification` about a job for its callback, when it has one, and
+/// for every operator subscribed to the event. The notifier task sends them
+/// and retries the failed ones. The callback is held back for `quiet_for`,
+/// the operators are told right away.
+pub async fn enqueue(
+    job_id: u32,
+    callback_url: Option<&str>,
+    quiet_for: Duration,
+    notification: &Notification,
+    subscriptions: &HashMap<String, Subscription>,
+    pool: &SqlitePool,
+) -> Result<Vec<WebhookDelivery>, sqlx::Error> {
+    let event = &notification.event;
+    let payload = &notification.payload;
+    let mut deliveries: Vec<WebhookDelivery> = callback_url
+        .map(|url| WebhookDelivery::new(job_id, url, event, payload.clone()).deferred(quiet_for))
+        .into_iter()
+        .collect();
+    for s in subscribers(subscriptions, event) {
+        deliveries.push(
+            WebhookDelivery::new(job_id, &s.target, event, payload.clone())
+                .for_subscriber(&s.name, s.channel),
+        );
+    }
+    for delivery in deliveries.iter_mut() {
+        delivery.add_to_db(pool).await?;
+    }
+    Ok(deliveries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::job_dao::Job;
+    use crate::models::job_dto::create_jobs_table;
+    use crate::models::user_dao::User;
+    use mockito::Server;
+
+    fn subscription(name: &str, channel: Channel, target: &str, events: &[&str]) -> Subscription {
+        Subscription {
+            name: name.to_string(),
+            channel,
+            target: target.to_string(),
+            events: events.iter().map(|e| e.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_summary() {
+        let payload = serde_json::json!({
+            "event": "job.failed",
+            "job_id": 12,
+            "service": "haddock",
+            "reason": "download failed 8 times",
+        });
+        let notification = Notification::new("job.failed", &payload);
+        assert_eq!(
+            notification.summary(),
+            "job.failed: job 12 of haddock, download failed 8 times"
+        );
+
+        let notification =
+            Notification::new("alert.queue_depth", &serde_json::json!({"queue_depth": 20}));
+        assert_eq!(
+            notification.summary(),
+            r#"alert.queue_depth: {"queue_depth":20}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_webhook_notifier() {
+        let secret = Secret::new("hmac-key");
+        let notification = Notification {
+            event: "job.completed".to_string(),
+            payload: r#"{"job_id":1}"#.to_string(),
+        };
+
+        let mut server = Server::new_async().await;
+        let ok = server
+            .mock("POST", "/ok")
+            .match_header(EVENT_HEADER, "job.completed")
+            .match_header(DELIVERY_HEADER, "7")
+            .match_header(
+                SIGNATURE_HEADER,
+                sign(&secret, notification.payload.as_bytes()).as_str(),
+            )
+            .match_body(r#"{"job_id":1}"#)
+            .with_status(204)
+            .create_async()
+            .await;
+        let _down = server
+            .mock("POST", "/down")
+            .with_status(503)
+            .create_async()
+            .await;
+
+        let notifier = WebhookNotifier {
+            client: Client::default(),
+            secret: Some(secret),
+        };
+        let url = format!("{}/ok", server.url());
+        assert_eq!(
+            notifier.send(&url, "7", &notification).await,
+            Attempt::Delivered(204)
+        );
+        ok.assert_async().await;
+
+        let url = format!("{}/down", server.url());
+        assert!(matches!(
+            notifier.send(&url, "7", &notification).await,
+            Attempt::Failed(Some(503), _)
+        ));
+
+        assert!(matches!(
+            notifier
+                .send("http://127.0.0.1:1/unreachable", "7", &notification)
+                .await,
+            Attempt::Failed(None, _)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_slack_notifier() {
+        let mut server = Server::new_async().await;
+        let hook = server
+            .mock("POST", "/services/T0/B0/x")
+            .match_body(mockito::Matcher::Json(
+                serde_json::json!({"text": "job.failed: job 3 of haddock"}),
+            ))
+            .with_status(200)
+            .create_async()
+            .await;
+
+        let dispatcher = Dispatcher::new(&Config::default(), &Client::default());
+        let payload = serde_json::json!({"job_id": 3, "service": "haddock"});
+        let notification = Notification::new("job.failed", &payload);
+        let target = format!("{}/services/T0/B0/x", server.url());
+        assert_eq!(
+            dispatcher
+                .send(Channel::Slack, &target, "1", &notification)
+                .await,
+            Attempt::Delivered(200)
+        );
+        hook.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_broadcast() {
+        let mut server = Server::new_async().await;
+        let hook = server
+            .mock("POST", "/ops")
+            .with_status(200)
+            .create_async()
+            .await;
+        let _down = server
+            .mock("POST", "/down")
+            .with_status(500)
+            .create_async()
+            .await;
+
+        let dispatcher = Dispatcher::new(&Config::default(), &Client::default());
+        let notification = Notification::new("alert.queue_depth", &serde_json::json!({}));
+        let targets = [
+            (Channel::Webhook, format!("{}/ops", server.url())),
+            (Channel::Slack, format!("{}/down", server.url())),
+        ];
+        assert_eq!(dispatcher.broadcast(&notification, &targets).await, 1);
+        hook.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_enqueue_fans_out() {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        create_jobs_table(&pool).await.unwrap();
+        User::add_if_missing(0, &pool).await.unwrap();
+        let mut job = Job::new("");
+        job.add_to_db(&pool).await.unwrap();
+
+        let subscriptions = HashMap::from([
+            (
+                "ops".to_string(),
+                subscription(
+                    "ops",
+                    Channel::Slack,
+                    "https://hooks.slack.com/x",
+                    &["job.failed"],
+                ),
+            ),
+            (
+                "audit".to_string(),
+                subscription("audit", Channel::Webhook, "https://audit.example.org", &[]),
+            ),
+        ]);
+        let notification = Notification::new("job.completed", &serde_json::json!({}));
+        let deliveries = enqueue(
+            job.id,
+            Some("https://example.com/hook"),
+            Duration::ZERO,
+            &notification,
+            &subscriptions,
+            &pool,
+        )
+        .await
+        .unwrap();
+        assert_eq!(deliveries.len(), 2);
+        assert_eq!(deliveries[0].url, "https://example.com/hook");
+        assert_eq!(deliveries[0].subscriber, None);
+        assert_eq!(deliveries[1].url, "https://audit.example.org");
+        assert_eq!(deliveries[1].subscriber.as_deref(), Some("audit"));
+        assert_eq!(WebhookDelivery::list_due(&pool).await.unwrap(), deliveries);
+
+        // The job only sees its own callback
+        let callbacks = WebhookDelivery::list_for_job(job.id, &pool).await.unwrap();
+        assert_eq!(callbacks, deliveries[..1]);
+
+        // Without a callback only the operators are told
+        let notification = Notification::new("job.failed", &serde_json::json!({}));
+        let deliveries = enqueue(
+            job.id,
+            None,
+            Duration::ZERO,
+            &notification,
+            &subscriptions,
+            &pool,
+        )
+        .await
+        .unwrap();
+        let names: Vec<_> = deliveries.iter().map(|d| d.subscriber.as_deref()).collect();
+        assert_eq!(names, [Some("audit"), Some("ops")]);
+        assert_eq!(deliveries[1].channel, Channel::Slack);
+    }
+}
@@ -0,0 +1,331 @@
+use crate::config::loader::ObjectStoreConfig;
+use crate::services::client::{Client, body_stream, with_auth};
+use bytes::Bytes;
+use futures_util::stream::{BoxStream, StreamExt};
+use reqwest::StatusCode;
+use std::io;
+use std::path::Path;
+use tokio::fs::File;
+use tokio::io::AsyncWriteExt;
+use tokio_util::io::ReaderStream;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ObjectStoreError {
+    #[error("Request failed: {0}")]
+    RequestFailed(#[from] reqwest::Error),
+    #[error("Object store answered {0}")]
+    UnexpectedStatus(StatusCode),
+    #[error("Object not found")]
+    NotFound,
+    #[error("Object is larger than {0} bytes")]
+    TooLarge(u64),
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+}
+
+fn check_status(response: &reqwest::Response) -> Result<(), ObjectStoreError> {
+    match response.status() {
+        s if s.is_success() => Ok(()),
+        StatusCode::NOT_FOUND => Err(ObjectStoreError::NotFound),
+        s => Err(ObjectStoreError::UnexpectedStatus(s)),
+    }
+}
+
+impl Client {
+    /// Stream the file at `path` to the object store under `key`
+    pub async fn put_object(
+        &self,
+        store: &ObjectStoreConfig,
+        key: &str,
+        path: &Path,
+    ) -> Result<(), ObjectStoreError> {
+        let file = File::open(path).await?;
+        let length = file.metadata().await?.len();
+
+        let response = with_auth(self.http.put(store.object_url(key)), &store.auth)
+            .header(reqwest::header::CONTENT_LENGTH, length)
+            .header(reqwest::header::CONTENT_TYPE, "application/zip")
+            .body(reqwest::Body::wrap_stream(ReaderStream::new(file)))
+            .send()
+            .await?;
+        check_status(&response)
+    }
+
+    /// The content of `key`, streamed as it arrives
+    pub async fn get_object(
+        &self,
+        store: &ObjectStoreConfig,
+        key: &str,
+    ) -> Result<BoxStream<'static, io::Result<Bytes>>, ObjectStoreError> {
+        let response = with_auth(self.http.get(store.object_url(key)), &store.auth)
+            .send()
+            .await?;
+        check_status(&response)?;
+        Ok(body_stream(response))
+    }
+
+    /// Save the content of `key` to `path`
+    pub async fn get_object_to_file(
+        &self,
+        store: &ObjectStoreConfig,
+        key: &str,
+        path: &Path,
+    ) -> Result<(), ObjectStoreError> {
+        let mut stream = self.get_object(store, key).await?;
+        let mut file = File::create(path).await?;
+        while let Some(chunk) = stream.next().await {
+            file.write_all(&chunk?).await?;
+        }
+        file.flush().await?;
+        Ok(())
+    }
+
+    /// Save the object behind a pre-signed `url` to `path`, giving up once it
+    /// goes over `limit` bytes. Returns the size of the object.
+    pub async fn get_presigned_to_file(
+        &self,
+        url: &str,
+        path: &Path,
+        limit: u64,
+    ) -> Result<u64, ObjectStoreError> {
+        let response = self.http.get(url).send().await?;
+        check_status(&response)?;
+        if response.content_length().is_some_and(|l| l > limit) {
+            return Err(ObjectStoreError::TooLarge(limit));
+        }
+        let mut stream = body_stream(response);
+        let mut file = File::create(path).await?;
+        let mut size = 0;
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            size += chunk.len() as u64;
+            if size > limit {
+                return Err(ObjectStoreError::TooLarge(limit));
+            }
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+        Ok(size)
+    }
+
+    /// Size of the object behind a pre-signed `url`, when the store reports it,
+    /// without downloading it
+    pub async fn head_presigned(&self, url: &str) -> Result<Option<u64>, ObjectStoreError> {
+        let response = self.http.head(url).send().await?;
+        check_status(&response)?;
+        Ok(response
+            .headers()
+            .get(reqwest::header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok()))
+    }
+
+    /// Remove the object behind a pre-signed `url`, one already gone is not an error
+    pub async fn delete_presigned(&self, url: &str) -> Result<(), ObjectStoreError> {
+        let response = self.http.delete(url).send().await?;
+        match check_status(&response) {
+            Err(ObjectStoreError::NotFound) => Ok(()),
+            result => result,
+        }
+    }
+
+    /// Remove `key`, an object that is already gone is not an error
+    pub async fn delete_object(
+        &self,
+        store: &ObjectStoreConfig,
+        key: &str,
+    ) -> Result<(), ObjectStoreError> {
+        let response = with_auth(self.http.delete(store.object_url(key)), &store.auth)
+            .send()
+            .await?;
+        match check_status(&response) {
+            Err(ObjectStoreError::NotFound) => Ok(()),
+            result => result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::loader::{Secret, ServiceAuth};
+    use mockito::Server;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    fn store(url: String) -> ObjectStoreConfig {
+        ObjectStoreConfig {
+            url,
+            auth: ServiceAuth {
+                bearer_token: Some(Secret::new("tok")),
+                ..Default::default()
+            },
+            tier_after: Duration::from_secs(0),
+            s3: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_put_object() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("PUT", "/default/abc.zip")
+            .match_header("authorization", "Bearer tok")
+            .match_body("archive")
+            .with_status(200)
+            .create_async()
+            .await;
+
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("output.zip");
+        std::fs::write(&path, b"archive").unwrap();
+
+        let client = Client::default();
+        let result = client
+            .put_object(&store(server.url()), "default/abc.zip", &path)
+            .await;
+        assert!(result.is_ok());
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_put_object_error() {
+        let mut server = Server::new_async().await;
+        let _mock = server
+            .mock("PUT", "/default/abc.zip")
+            .with_status(403)
+            .create_async()
+            .await;
+
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("output.zip");
+        std::fs::write(&path, b"archive").unwrap();
+
+        let result = Client::default()
+            .put_object(&store(server.url()), "default/abc.zip", &path)
+            .await;
+        assert!(matches!(
+            result,
+            Err(ObjectStoreError::UnexpectedStatus(StatusCode::FORBIDDEN))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_get_object_to_file() {
+        let mut server = Server::new_async().await;
+        let _found = server
+            .mock("GET", "/default/abc.zip")
+            .with_status(200)
+            .with_body("archive")
+            .create_async()
+            .await;
+        let _missing = server
+            .mock("GET", "/default/missing.zip")
+            .with_status(404)
+            .create_async()
+            .await;
+
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("output.zip");
+        let client = Client::default();
+        let store = store(server.url());
+
+        client
+            .get_object_to_file(&store, "default/abc.zip", &path)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"archive");
+
+        let result = client.get_object(&store, "default/missing.zip").await;
+        assert!(matches!(result, Err(ObjectStoreError::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_delete_object() {
+        let mut server = Server::new_async().await;
+        let deleted = server
+            .mock("DELETE", "/default/abc.zip")
+            .with_status(204)
+            .create_async()
+            .await;
+        let _gone = server
+            .mock("DELETE", "/default/gone.zip")
+            .with_status(404)
+            .create_async()
+            .await;
+
+        let client = Client::default();
+        let store = store(server.url());
+        assert!(
+            client
+                .delete_object(&store, "default/abc.zip")
+                .await
+                .is_ok()
+        );
+        assert!(
+            client
+                .delete_object(&store, "default/gone.zip")
+                .await
+                .is_ok()
+        );
+        deleted.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_get_presigned_to_file() {
+        let mut server = Server::new_async().await;
+        let _found = server
+            .mock("GET", "/uploads/abc/input.pdb?X-Amz-Signature=sig")
+            .with_status(200)
+            .with_body("ATOM")
+            .create_async()
+            .await;
+        let deleted = server
+            .mock("DELETE", "/uploads/abc/input.pdb?X-Amz-Signature=sig")
+            .with_status(204)
+            .create_async()
+            .await;
+
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("input.pdb");
+        let url = format!("{}/uploads/abc/input.pdb?X-Amz-Signature=sig", server.url());
+        let client = Client::default();
+
+        assert_eq!(
+            client.get_presigned_to_file(&url, &path, 4).await.unwrap(),
+            4
+        );
+        assert_eq!(std::fs::read(&path).unwrap(), b"ATOM");
+        assert!(matches!(
+            client.get_presigned_to_file(&url, &path, 3).await,
+            Err(ObjectStoreError::TooLarge(3))
+        ));
+
+        client.delete_presigned(&url).await.unwrap();
+        deleted.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_head_presigned() {
+        let mut server = Server::new_async().await;
+        let _found = server
+            .mock("HEAD", "/uploads/abc/input.pdb?X-Amz-Signature=sig")
+            .with_header("content-length", "4")
+            .create_async()
+            .await;
+        let _missing = server
+            .mock("HEAD", "/uploads/abc/gone.pdb?X-Amz-Signature=sig")
+            .with_status(404)
+            .create_async()
+            .await;
+        let client = Client::default();
+
+        let url = format!("{}/uploads/abc/input.pdb?X-Amz-Signature=sig", server.url());
+        assert_eq!(client.head_presigned(&url).await.unwrap(), Some(4));
+        let url = format!("{}/uploads/abc/gone.pdb?X-Amz-Signature=sig", server.url());
+        assert!(matches!(
+            client.head_presigned(&url).await,
+            Err(ObjectStoreError::NotFound)
+        ));
+    }
+}
@@ -0,0 +1,274 @@
+use crate::config::loader::OidcConfig;
+use crate::services::client::Client;
+use crate::utils::session::Identity;
+use reqwest::StatusCode;
+use serde::Deserialize;
+
+#[derive(Debug, thiserror::Error)]
+pub enum OidcError {
+    #[error("Request failed: {0}")]
+    RequestFailed(#[from] reqwest::Error),
+    #[error("Identity provider answered {0}")]
+    UnexpectedStatus(StatusCode),
+    #[error("Invalid authorization endpoint: {0}")]
+    InvalidUrl(String),
+}
+
+/// The endpoints of the provider's discovery document used by the login
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ProviderMetadata {
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub userinfo_endpoint: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct UserInfo {
+    sub: String,
+    email: Option<String>,
+    #[serde(default)]
+    email_verified: Option<bool>,
+    name: Option<String>,
+}
+
+fn check_status(response: &reqwest::Response) -> Result<(), OidcError> {
+    match response.status() {
+        s if s.is_success() => Ok(()),
+        s => Err(OidcError::UnexpectedStatus(s)),
+    }
+}
+
+/// Where to send the operator to log in, `state` ties the answer to this login
+pub fn authorization_url(
+    config: &OidcConfig,
+    metadata: &ProviderMetadata,
+    state: &str,
+) -> Result<String, OidcError> {
+    let url = reqwest::Url::parse_with_params(
+        &metadata.authorization_endpoint,
+        [
+            ("response_type", "code"),
+            ("client_id", config.client_id.as_str()),
+            ("redirect_uri", config.redirect_url.as_str()),
+            ("scope", "openid email profile"),
+            ("state", state),
+        ],
+    )
+    .map_err(|e| OidcError::InvalidUrl(e.to_string()))?;
+    Ok(url.into())
+}
+
+/// Whether `identity` may use the operator endpoints, nobody without an
+/// allowlist
+pub fn is_allowed(config: &OidcConfig, identity: &Identity) -> bool {
+    let Some(email) = identity.email.as_deref().map(str::to_ascii_lowercase) else {
+        return false;
+    };
+    config.allowed_emails.iter().any(|allowed| {
+        if allowed.starts_with('@') {
+            email.ends_with(allowed.as_str())
+        } else {
+            email == *allowed
+        }
+    })
+}
+
+impl Client {
+    pub async fn oidc_discover(&self, issuer: &str) -> Result<ProviderMetadata, OidcError> {
+        let url = format!(
+            "{}/.well-known/openid-configuration",
+            issuer.trim_end_matches('/')
+        );
+        let response = self.http.get(url).send().await?;
+        check_status(&response)?;
+        Ok(response.json().await?)
+    }
+
+    /// Trade the code the provider sent back for the operator's identity. It
+    /// is read from the userinfo endpoint over TLS, so the ID token does not
+    /// need to be verified here.
+    pub async fn oidc_identity(
+        &self,
+        config: &OidcConfig,
+        metadata: &ProviderMetadata,
+        code: &str,
+    ) -> Result<Identity, OidcError> {
+        let response = self
+            .http
+            .post(&metadata.token_endpoint)
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", config.redirect_url.as_str()),
+                ("client_id", config.client_id.as_str()),
+                ("client_secret", config.client_secret.expose()),
+            ])
+            .send()
+            .await?;
+        check_status(&response)?;
+        let token: TokenResponse = response.json().await?;
+
+        let response = self
+            .http
+            .get(&metadata.userinfo_endpoint)
+            .bearer_auth(token.access_token)
+            .send()
+            .await?;
+        check_status(&response)?;
+        let info: UserInfo = response.json().await?;
+
+        Ok(Identity {
+            subject: info.sub,
+            // An unverified address could be anyone's, so could one the
+            // provider does not say it verified
+            email: info.email.filter(|_| info.email_verified == Some(true)),
+            name: info.name,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::loader::Secret;
+    use mockito::{Matcher, Server};
+    use std::time::Duration;
+
+    fn config(issuer: String) -> OidcConfig {
+        OidcConfig {
+            issuer,
+            client_id: "orchestrator".to_string(),
+            client_secret: Secret::new("client-secret"),
+            redirect_url: "https://orchestrator.example.org/auth/callback".to_string(),
+            allowed_emails: Vec::new(),
+            session_ttl: Duration::from_secs(3600),
+        }
+    }
+
+    fn identity(email: Option<&str>) -> Identity {
+        Identity {
+            subject: "abc".to_string(),
+            email: email.map(str::to_string),
+            name: None,
+        }
+    }
+
+    #[test]
+    fn test_authorization_url() {
+        let metadata = ProviderMetadata {
+            authorization_endpoint: "https://sso.example.org/authorize".to_string(),
+            token_endpoint: String::new(),
+            userinfo_endpoint: String::new(),
+        };
+        let url = authorization_url(&config(String::new()), &metadata, "xyz").unwrap();
+        assert!(url.starts_with("https://sso.example.org/authorize?response_type=code&"));
+        assert!(url.contains("client_id=orchestrator"));
+        assert!(
+            url.contains("redirect_uri=https%3A%2F%2Forchestrator.example.org%2Fauth%2Fcallback")
+        );
+        assert!(url.contains("state=xyz"));
+    }
+
+    #[test]
+    fn test_is_allowed() {
+        let mut config = config(String::new());
+        // Without an allowlist nobody is let in
+        assert!(!is_allowed(&config, &identity(Some("alice@example.org"))));
+        assert!(!is_allowed(&config, &identity(None)));
+
+        config.allowed_emails = vec![
+            "alice@example.org".to_string(),
+            "@ops.example.org".to_string(),
+        ];
+        assert!(is_allowed(&config, &identity(Some("Alice@example.org"))));
+        assert!(is_allowed(&config, &identity(Some("bob@ops.example.org"))));
+        assert!(!is_allowed(&config, &identity(Some("bob@example.org"))));
+        assert!(!is_allowed(
+            &config,
+            &identity(Some("eve@evilops.example.org"))
+        ));
+        assert!(!is_allowed(&config, &identity(None)));
+    }
+
+    #[tokio::test]
+    async fn test_login_flow() {
+        let mut server = Server::new_async().await;
+        let url = server.url();
+        let _discovery = server
+            .mock("GET", "/.well-known/openid-configuration")
+            .with_body(format!(
+                r#"{{"issuer": "{url}", "authorization_endpoint": "{url}/authorize", "token_endpoint": "{url}/token", "userinfo_endpoint": "{url}/userinfo"}}"#
+            ))
+            .create_async()
+            .await;
+        let token = server
+            .mock("POST", "/token")
+            .match_body(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("code".into(), "the-code".into()),
+                Matcher::UrlEncoded("client_secret".into(), "client-secret".into()),
+            ]))
+            .with_body(r#"{"access_token": "at", "token_type": "Bearer"}"#)
+            .create_async()
+            .await;
+        let _userinfo = server
+            .mock("GET", "/userinfo")
+            .match_header("authorization", "Bearer at")
+            .with_body(r#"{"sub": "abc", "email": "alice@example.org", "email_verified": true}"#)
+            .create_async()
+            .await;
+
+        let client = Client::default();
+        let config = config(url.clone());
+        let metadata = client.oidc_discover(&url).await.unwrap();
+        assert_eq!(metadata.token_endpoint, format!("{url}/token"));
+
+        let identity = client
+            .oidc_identity(&config, &metadata, "the-code")
+            .await
+            .unwrap();
+        token.assert_async().await;
+        assert_eq!(identity.subject, "abc");
+        assert_eq!(identity.email.as_deref(), Some("alice@example.org"));
+    }
+
+    #[tokio::test]
+    async fn test_identity_unverified_email() {
+        let mut server = Server::new_async().await;
+        let url = server.url();
+        let _token = server
+            .mock("POST", "/token")
+            .with_body(r#"{"access_token": "at", "token_type": "Bearer"}"#)
+            .create_async()
+            .await;
+        let metadata = ProviderMetadata {
+            authorization_endpoint: format!("{url}/authorize"),
+            token_endpoint: format!("{url}/token"),
+            userinfo_endpoint: format!("{url}/userinfo"),
+        };
+        let client = Client::default();
+        let config = config(url.clone());
+
+        // Unverified, or not said to be verified
+        for info in [
+            r#"{"sub": "abc", "email": "alice@example.org", "email_verified": false}"#,
+            r#"{"sub": "abc", "email": "alice@example.org"}"#,
+        ] {
+            let userinfo = server
+                .mock("GET", "/userinfo")
+                .with_body(info)
+                .create_async()
+                .await;
+            let identity = client
+                .oidc_identity(&config, &metadata, "the-code")
+                .await
+                .unwrap();
+            assert_eq!(identity.email, None);
+            userinfo.remove_async().await;
+        }
+    }
+}
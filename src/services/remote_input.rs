@@ -0,0 +1,323 @@
+use crate::config::loader::RemoteInputConfig;
+use crate::services::client::Client;
+use crate::utils::io::sanitize_filename;
+use axum::http::StatusCode;
+use futures::StreamExt;
+use reqwest::Url;
+use std::collections::HashSet;
+use std::io;
+use std::path::Path;
+use tokio::io::AsyncWriteExt;
+use utoipa::ToSchema;
+
+/// Most files a submission may reference by URL
+pub const MAX_REMOTE_INPUTS: usize = 100;
+
+/// Files written by the server itself, an input can't take their name
+const RESERVED_NAMES: &[&str] = &["run.sh", "params.json"];
+
+/// A file of a `POST /jobs` the server fetches into the job directory
+#[derive(serde::Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct RemoteInput {
+    pub url: String,
+    /// Name in the job directory, the last segment of the URL when unset
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RemoteInputError {
+    #[error("Remote inputs are not enabled")]
+    Disabled,
+    #[error("At most {MAX_REMOTE_INPUTS} remote inputs are allowed")]
+    TooMany,
+    #[error("Invalid input URL {0}")]
+    InvalidUrl(String),
+    #[error("Inputs can't be fetched from {0}")]
+    NotAllowed(String),
+    #[error("Input name {0} is used twice or reserved")]
+    NameTaken(String),
+    #[error("Input {name} is over the limit of {limit} bytes")]
+    TooLarge { name: String, limit: u64 },
+    #[error("Inputs are over the limit of {0} bytes together")]
+    TotalTooLarge(u64),
+    #[error("Could not fetch {url}: {reason}")]
+    FetchFailed { url: String, reason: String },
+    #[error("Could not save input: {0}")]
+    Io(#[from] io::Error),
+}
+
+impl RemoteInputError {
+    /// Status of the answer to the submission
+    pub fn status(&self) -> StatusCode {
+        match self {
+            RemoteInputError::FetchFailed { .. } => StatusCode::BAD_GATEWAY,
+            RemoteInputError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+/// Check `inputs` against `config` before anything is fetched, returns
+/// their URLs with the name they are saved under
+pub fn check(
+    inputs: &[RemoteInput],
+    config: Option<&RemoteInputConfig>,
+) -> Result<Vec<(Url, String)>, RemoteInputError> {
+    if inputs.is_empty() {
+        return Ok(Vec::new());
+    }
+    let config = config.ok_or(RemoteInputError::Disabled)?;
+    if inputs.len() > MAX_REMOTE_INPUTS {
+        return Err(RemoteInputError::TooMany);
+    }
+
+    let mut names: HashSet<String> = RESERVED_NAMES.iter().map(|n| n.to_string()).collect();
+    let mut checked = Vec::with_capacity(inputs.len());
+    for input in inputs {
+        let url =
+            Url::parse(&input.url).map_err(|_| RemoteInputError::InvalidUrl(input.url.clone()))?;
+        if !config.allows(&url) {
+            return Err(RemoteInputError::NotAllowed(
+                url.host_str().unwrap_or(url.scheme()).to_string(),
+            ));
+        }
+        let name = input
+            .name
+            .clone()
+            .or_else(|| url.path_segments()?.next_back().map(str::to_string))
+            .filter(|n| !n.is_empty())
+            .map(|n| sanitize_filename(&n))
+            .ok_or_else(|| RemoteInputError::InvalidUrl(input.url.clone()))?;
+        if !names.insert(name.clone()) {
+            return Err(RemoteInputError::NameTaken(name));
+        }
+        checked.push((url, name));
+    }
+    Ok(checked)
+}
+
+/// Fetch the checked `inputs` into `dir`, within the size limits of
+/// `config`. Returns the number of bytes fetched.
+pub async fn fetch(
+    inputs: &[(Url, String)],
+    dir: &Path,
+    config: &RemoteInputConfig,
+    client: &Client,
+) -> Result<u64, RemoteInputError> {
+    let mut total = 0;
+    for (url, name) in inputs {
+        let failed = |reason: String| RemoteInputError::FetchFailed {
+            url: url.to_string(),
+            reason,
+        };
+        let response = client
+            .remote
+            .get(url.clone())
+            .send()
+            .await
+            .map_err(|e| failed(e.to_string()))?;
+        if response.status().is_redirection() {
+            return Err(failed("redirects are not followed".to_string()));
+        }
+        if !response.status().is_success() {
+            return Err(failed(format!("answered {}", response.status())));
+        }
+        // Refused before downloading when the size is announced
+        let limit = config.max_size.min(config.max_total - total);
+        if response.content_length().is_some_and(|l| l > limit) {
+            return Err(too_large(name, config, total));
+        }
+
+        let path = dir.join(name);
+        let mut file = tokio::fs::File::create(&path).await?;
+        let mut size = 0;
+        let mut stream = response.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|e| failed(e.to_string()))?;
+            size += chunk.len() as u64;
+            if size > limit {
+                return Err(too_large(name, config, total));
+            }
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+        total += size;
+        tracing::debug!("Fetched {url} into {} ({size} bytes)", path.display());
+    }
+    Ok(total)
+}
+
+/// The limit `name` went over, its own or the one of the whole submission
+fn too_large(name: &str, config: &RemoteInputConfig, fetched: u64) -> RemoteInputError {
+    if config.max_total - fetched < config.max_size {
+        RemoteInputError::TotalTooLarge(config.max_total)
+    } else {
+        RemoteInputError::TooLarge {
+            name: name.to_string(),
+            limit: config.max_size,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn config(hosts: &[&str]) -> RemoteInputConfig {
+        RemoteInputConfig {
+            hosts: hosts.iter().map(|h| h.to_string()).collect(),
+            schemes: vec!["http".to_string(), "https".to_string()],
+            max_size: 16,
+            max_total: 20,
+        }
+    }
+
+    fn input(url: &str, name: Option<&str>) -> RemoteInput {
+        RemoteInput {
+            url: url.to_string(),
+            name: name.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_check() {
+        let config = config(&["files.rcsb.org"]);
+        assert!(check(&[], None).unwrap().is_empty());
+        assert!(matches!(
+            check(&[input("https://files.rcsb.org/1abc.pdb", None)], None),
+            Err(RemoteInputError::Disabled)
+        ));
+
+        let checked = check(
+            &[
+                input("https://files.rcsb.org/download/1abc.pdb", None),
+                input(
+                    "https://files.rcsb.org/download/2xyz.cif?v=2",
+                    Some("../model.cif"),
+                ),
+            ],
+            Some(&config),
+        )
+        .unwrap();
+        let names: Vec<_> = checked.iter().map(|(_, n)| n.as_str()).collect();
+        assert_eq!(names, ["1abc.pdb", "model.cif"]);
+
+        for (inputs, expected) in [
+            (
+                vec![input("not a url", None)],
+                "Invalid input URL not a url",
+            ),
+            (
+                vec![input("https://169.254.169.254/latest", None)],
+                "Inputs can't be fetched from 169.254.169.254",
+            ),
+            (
+                vec![input("https://files.rcsb.org/", None)],
+                "Invalid input URL https://files.rcsb.org/",
+            ),
+            (
+                vec![input("https://files.rcsb.org/run.sh", None)],
+                "Input name run.sh is used twice or reserved",
+            ),
+            (
+                vec![
+                    input("https://files.rcsb.org/a/x.pdb", None),
+                    input("https://files.rcsb.org/b/x.pdb", None),
+                ],
+                "Input name x.pdb is used twice or reserved",
+            ),
+        ] {
+            let err = check(&inputs, Some(&config)).unwrap_err();
+            assert_eq!(err.to_string(), expected);
+            assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fetch() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/a.pdb")
+            .with_body("ATOM 1")
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/b.pdb")
+            .with_body("ATOM 2")
+            .create_async()
+            .await;
+        let tempdir = TempDir::new().unwrap();
+        let config = config(&["127.0.0.1"]);
+        let inputs = check(
+            &[
+                input(&format!("{}/a.pdb", server.url()), None),
+                input(&format!("{}/b.pdb", server.url()), Some("ref.pdb")),
+            ],
+            Some(&config),
+        )
+        .unwrap();
+
+        let fetched = fetch(&inputs, tempdir.path(), &config, &Client::default())
+            .await
+            .unwrap();
+        assert_eq!(fetched, 12);
+        assert_eq!(fs::read(tempdir.path().join("a.pdb")).unwrap(), b"ATOM 1");
+        assert_eq!(fs::read(tempdir.path().join("ref.pdb")).unwrap(), b"ATOM 2");
+    }
+
+    #[tokio::test]
+    async fn test_fetch_refused() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/large")
+            .with_body("x".repeat(17))
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/medium")
+            .with_body("x".repeat(12))
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/moved")
+            .with_status(302)
+            .with_header("location", "http://169.254.169.254/")
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/missing")
+            .with_status(404)
+            .create_async()
+            .await;
+        let tempdir = TempDir::new().unwrap();
+        let config = config(&["127.0.0.1"]);
+        let fetch_all = |paths: &[&str]| {
+            let inputs: Vec<_> = paths
+                .iter()
+                .enumerate()
+                .map(|(i, p)| input(&format!("{}{p}", server.url()), Some(&i.to_string())))
+                .collect();
+            let inputs = check(&inputs, Some(&config)).unwrap();
+            let dir = tempdir.path().to_path_buf();
+            let config = config.clone();
+            async move { fetch(&inputs, &dir, &config, &Client::default()).await }
+        };
+
+        let err = fetch_all(&["/large"]).await.unwrap_err();
+        assert_eq!(err.to_string(), "Input 0 is over the limit of 16 bytes");
+        let err = fetch_all(&["/medium", "/medium"]).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Inputs are over the limit of 20 bytes together"
+        );
+
+        let err = fetch_all(&["/moved"]).await.unwrap_err();
+        assert!(err.to_string().ends_with("redirects are not followed"));
+        assert_eq!(err.status(), StatusCode::BAD_GATEWAY);
+        let err = fetch_all(&["/missing"]).await.unwrap_err();
+        assert!(err.to_string().ends_with("answered 404 Not Found"));
+    }
+}
@@ -0,0 +1,136 @@
+use crate::config::loader::ObjectStoreConfig;
+use crate::models::payload_dao::Payload;
+use crate::services::client::Client;
+use crate::services::object_store::ObjectStoreError;
+use crate::utils::io::file_sha256;
+use std::io;
+
+/// Object store key of results that were put in the shared store instead of
+/// being sent, in the `/retrieve` response
+pub const RESULT_KEY_HEADER: &str = "x-result-key";
+
+#[derive(Debug, thiserror::Error)]
+pub enum ResultStoreError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Object store error: {0}")]
+    ObjectStore(#[from] ObjectStoreError),
+}
+
+/// Where the results of a completed payload ended up
+#[derive(Debug, PartialEq)]
+pub enum StoredResult {
+    /// The archive itself, sent in the response
+    Inline(Vec<u8>),
+    /// The archive was put in the shared object store under `key`
+    Shared { key: String, checksum: String },
+}
+
+/// Makes the results of a completed payload available to the server
+pub trait ResultStore {
+    async fn put(&self, payload: &Payload) -> Result<StoredResult, ResultStoreError>;
+}
+
+/// Keeps `output.zip` in the payload directory, the server downloads it
+pub struct LocalResultStore;
+
+impl ResultStore for LocalResultStore {
+    async fn put(&self, payload: &Payload) -> Result<StoredResult, ResultStoreError> {
+        let path = payload.output_archive()?;
+        Ok(StoredResult::Inline(tokio::fs::read(path).await?))
+    }
+}
+
+/// Puts `output.zip` in the object store the server reads from, so the
+/// download on the server becomes a matter of recording the key
+pub struct SharedResultStore<'a> {
+    pub client: &'a Client,
+    pub store: &'a ObjectStoreConfig,
+}
+
+impl ResultStore for SharedResultStore<'_> {
+    async fn put(&self, payload: &Payload) -> Result<StoredResult, ResultStoreError> {
+        // The server names the key, without one the results are sent as before
+        let Some(key) = &payload.result_key else {
+            return LocalResultStore.put(payload).await;
+        };
+        let path = payload.output_archive()?;
+        let checksum = file_sha256(&path)?;
+        self.client.put_object(self.store, key, &path).await?;
+        Ok(StoredResult::Shared {
+            key: key.clone(),
+            checksum,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::loader::ServiceAuth;
+    use mockito::Server;
+    use sha2::{Digest, Sha256};
+    use std::fs;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    fn completed_payload(tempdir: &TempDir) -> Payload {
+        let mut payload = Payload::new();
+        payload.set_loc(tempdir.path().to_path_buf());
+        fs::write(tempdir.path().join("model.pdb"), b"ATOM").unwrap();
+        payload
+    }
+
+    #[tokio::test]
+    async fn test_local_result_store() {
+        let tempdir = TempDir::new().unwrap();
+        let payload = completed_payload(&tempdir);
+
+        let stored = LocalResultStore.put(&payload).await.unwrap();
+        let archive = fs::read(tempdir.path().join("output.zip")).unwrap();
+        assert_eq!(stored, StoredResult::Inline(archive));
+    }
+
+    #[tokio::test]
+    async fn test_shared_result_store() {
+        let tempdir = TempDir::new().unwrap();
+        let mut payload = completed_payload(&tempdir);
+
+        let mut server = Server::new_async().await;
+        let put = server
+            .mock("PUT", "/lab/abc.zip")
+            .with_status(200)
+            .create_async()
+            .await;
+        let store = ObjectStoreConfig {
+            url: server.url(),
+            auth: ServiceAuth::default(),
+            tier_after: Duration::from_secs(0),
+            s3: None,
+        };
+        let client = Client::default();
+        let shared = SharedResultStore {
+            client: &client,
+            store: &store,
+        };
+
+        // Without a key from the server the archive is sent as usual
+        assert!(matches!(
+            shared.put(&payload).await.unwrap(),
+            StoredResult::Inline(_)
+        ));
+
+        payload.set_result_key("lab/abc.zip".to_string());
+        let stored = shared.put(&payload).await.unwrap();
+        put.assert_async().await;
+
+        let archive = fs::read(tempdir.path().join("output.zip")).unwrap();
+        assert_eq!(
+            stored,
+            StoredResult::Shared {
+                key: "lab/abc.zip".to_string(),
+                checksum: format!("{:x}", Sha256::digest(&archive)),
+            }
+        );
+    }
+}
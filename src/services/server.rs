@@ -1,168 +1,822 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
-use std::time::SystemTime;
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime};
 
-use crate::config::loader::Config;
-use crate::models::job_dao::Job;
-use crate::models::{queue_dao::Queue, status_dto::Status};
+use crate::config::loader::{
+    CleanupAction, ClientVersionPolicy, Config, DownloadRetryConfig, StaleAction, SubmitRetryConfig,
+};
+use crate::datasource::backup;
+use crate::models::error_body::ErrorCode;
+use crate::models::job_dao::{Job, JobError, JobEta, RUNNER_SOURCE};
+use crate::models::job_event_dao::{
+    CLEANER_ACTOR, GETTER_ACTOR, RECONCILE_ACTOR, SENDER_ACTOR, WATCHDOG_ACTOR,
+};
+use crate::models::load_dto::LoadReport;
+use crate::models::queue_dao::{Queue, QueueSnapshot, ServiceSnapshot};
+use crate::models::service_probe_dao::{ServiceProbe, UNKNOWN_VERSION, VersionStanding};
+use crate::models::status_dto::Status;
+use crate::models::task_run_dao::{TaskRun, TaskTally};
+use crate::models::webhook_dao::{Channel, WebhookDelivery};
+use crate::services::alert::AlertState;
 use crate::services::client::Client;
-use crate::services::endpoint::{self, TerminateError};
+use crate::services::client::ClientError;
+use crate::services::endpoint::{self, DownloadError, Retrieved, TerminateError, UploadError};
+use crate::services::hooks::{HookPoint, Hooks};
+use crate::services::notify::{self, Dispatcher, Notification};
+use crate::services::webhook::{self, Attempt};
+use crate::utils::chaos::random_fraction;
+use crate::utils::download_link;
+use crate::utils::io::{list_job_dirs, validate_script};
+use crate::utils::storage::StorageHealth;
+use axum::http::StatusCode;
 use futures::stream::{self, StreamExt};
 use sqlx::SqlitePool;
 use tracing::info;
-use tracing::{debug, error};
-
-pub async fn cleaner(pool: SqlitePool, config: Config) {
-    // List all directories inside the config.data_path
-    let elements = match fs::read_dir(&config.data_path) {
-        Ok(e) => e,
-        Err(_) => {
-            error!("could not read directory: {}", config.data_path);
-            return;
+use tracing::{debug, error, warn};
+
+/// Run one round of a scheduled task, recording it in the `task_runs` table.
+/// The bookkeeping is best effort, the round runs even if it can't be recorded.
+pub async fn tracked<F>(task: &str, pool: &SqlitePool, round: F)
+where
+    F: Future<Output = TaskTally>,
+{
+    let run = TaskRun::start(task, pool)
+        .await
+        .inspect_err(|e| error!("could not record the start of a {task} round: {:?}", e))
+        .ok();
+    let tally = round.await;
+    if let Some(mut run) = run
+        && let Err(e) = run.finish(tally, pool).await
+    {
+        error!(
+            "could not record the end of {task} round {}: {:?}",
+            run.id, e
+        );
+    }
+}
+
+pub async fn cleaner(pool: SqlitePool, config: Config, client: Client) -> TaskTally {
+    // The database knows where every job lives, however deep its directory
+    let jobs = match Job::list_cleanable(&pool).await {
+        Ok(jobs) => jobs,
+        Err(e) => {
+            error!("could not list the jobs to clean: {:?}", e);
+            return TaskTally::failed();
         }
     };
 
-    let futures = elements.into_iter().map(|entry| async {
-        let entry = match entry {
-            Ok(d) => d,
-            Err(_) => {
-                error!("could not read subdir");
-                return;
+    let (pool, config, client) = (&pool, &config, &client);
+    let futures = jobs.into_iter().map(|mut job| async move {
+        let max_age = config.max_age_for(&job.tenant);
+        // Gone already, or not created yet
+        let age = dir_age(&job.loc)?;
+        if age < max_age {
+            if let Some(notice) = config.expiry_notice
+                && !job.expiry_notified
+                && age + notice >= max_age
+            {
+                notify_expiry(&mut job, max_age - age, pool, config).await;
             }
-        };
-        let path = entry.path();
-        if !path.is_dir() {
-            return;
+            return None;
         }
-        let metadata = match fs::metadata(&path) {
-            Ok(m) => m,
-            Err(_) => {
-                error!("could not read metadata");
-                return;
-            }
+        debug!(
+            "{:?} - {:?} - {:?}",
+            job.loc.display(),
+            age.as_secs(),
+            max_age
+        );
+
+        // Nothing is lost when the files can't be dealt with, retried on the next round
+        let action = config
+            .services
+            .get(&job.service)
+            .map(|s| s.cleanup)
+            .unwrap_or_default();
+        // A hook may want the files to stay, they are offered again next round
+        if Hooks::new(config)
+            .run(HookPoint::PreClean, &job)
+            .await
+            .is_err()
+        {
+            return Some(false);
+        }
+        let Some(fate) = retire_files(&mut job, action, pool, config, client).await else {
+            return Some(false);
         };
-        if let Ok(mod_time) = metadata.modified() {
-            let current_time = SystemTime::now();
 
-            if let Ok(age) = current_time.duration_since(mod_time)
-                && age >= config.max_age
+        if let Err(e) = job
+            .update_status_by(Status::Cleaned, CLEANER_ACTOR, Some(&fate), pool)
+            .await
+        {
+            error!("Failed to record the cleanup of job {}: {:?}", job.id, e);
+        }
+        if action != CleanupAction::Compress
+            && let Err(e) = job.remove_from_disk()
+        {
+            error!("error: {:?} - could not remove {:?}", e, job.loc);
+            return Some(false);
+        }
+        Some(true)
+    });
+
+    let tally = tally(
+        futures::future::join_all(futures)
+            .await
+            .into_iter()
+            .flatten(),
+    );
+
+    report_orphans(pool, config).await;
+
+    tally
+}
+
+/// Get the files of an aged-out job ready to go the way its service asks,
+/// returning where they went for the job history. `None` when they can't be
+/// dealt with yet, the job directory is then left alone.
+async fn retire_files(
+    job: &mut Job,
+    action: CleanupAction,
+    pool: &SqlitePool,
+    config: &Config,
+    client: &Client,
+) -> Option<String> {
+    match action {
+        CleanupAction::Delete => {
+            // A tiered archive goes with the job
+            if let (Some(key), Some(store)) = (&job.remote_key, &config.object_store)
+                && let Err(e) = client.delete_object(store, key).await
             {
-                debug!(
-                    "{:?} - {:?} - {:?}",
-                    path.display(),
-                    age.as_secs(),
-                    config.max_age
+                error!("{:?} - could not delete {key} from the object store", e);
+                return None;
+            }
+            Some("files deleted".to_string())
+        }
+        CleanupAction::Archive => {
+            let Some(store) = &config.object_store else {
+                error!(
+                    "job {} is to be archived, which needs OBJECT_STORE_URL",
+                    job.id
                 );
-                let mut job = Job::new("");
-                match job.retrieve_by_loc(path.display().to_string(), &pool).await {
-                    Ok(_) => {
-                        let _ = job.update_status(Status::Cleaned, &pool).await;
-                        if let Err(e) = job.remove_from_disk() {
-                            error!("error: {:?} - could not remove {:?}", e, path)
-                        }
-                    }
-                    Err(e) => error!("{:?} - not found: {:?}", e, path),
+                return None;
+            };
+            if let Some(key) = &job.remote_key {
+                return Some(format!("results archived in the object store as {key}"));
+            }
+            let archive = job.loc.join("output.zip");
+            if !archive.exists() {
+                return Some("no results to archive, files deleted".to_string());
+            }
+            let key = job.object_key();
+            if let Err(e) = client.put_object(store, &key, &archive).await {
+                error!("{:?} - could not archive {:?}", e, archive);
+                return None;
+            }
+            if let Err(e) = job.update_remote_key(key.clone(), pool).await {
+                error!(
+                    "{:?} - could not record the object key of job {}",
+                    e, job.id
+                );
+                return None;
+            }
+            Some(format!("results archived in the object store as {key}"))
+        }
+        CleanupAction::Compress => {
+            if !job.is_packed()
+                && let Err(e) = job.pack()
+            {
+                error!("{:?} - could not compress {:?}", e, job.loc);
+                return None;
+            }
+            Some(format!(
+                "files compressed in place in {}",
+                job.loc.display()
+            ))
+        }
+    }
+}
+
+/// Tell the callback of `job` its results are removed in `left`, once. The
+/// link sent along lasts as long as the results, within `max_ttl`.
+async fn notify_expiry(job: &mut Job, left: Duration, pool: &SqlitePool, config: &Config) {
+    let now = download_link::now();
+    let download_url = config
+        .download_links
+        .as_ref()
+        .map(|links| download_link::url(links, job.id, now + left.min(links.max_ttl).as_secs()));
+    let result = async {
+        let expires_at = now + left.as_secs();
+        let subscriptions = &config.subscriptions;
+        let deliveries =
+            webhook::enqueue_expiry(job, expires_at, download_url, subscriptions, pool).await?;
+        if !deliveries.is_empty() {
+            info!(
+                "Results of job {} are removed in {:?}, notice queued",
+                job.id, left
+            );
+            job.mark_expiry_notified(pool).await?;
+        }
+        Ok::<_, sqlx::Error>(())
+    };
+    if let Err(e) = result.await {
+        error!(
+            "Failed to queue the expiry notice of job {}: {:?}",
+            job.id, e
+        );
+    }
+}
+
+/// Count the jobs a round handled given whether each went through
+fn tally(outcomes: impl IntoIterator<Item = bool>) -> TaskTally {
+    outcomes
+        .into_iter()
+        .fold(TaskTally::default(), |mut tally, ok| {
+            tally.processed += 1;
+            tally.errors += u32::from(!ok);
+            tally
+        })
+}
+
+/// Time since the directory was last modified, `None` when it can't be read
+fn dir_age(path: &Path) -> Option<Duration> {
+    let modified = fs::metadata(path).ok()?.modified().ok()?;
+    SystemTime::now().duration_since(modified).ok()
+}
+
+/// Log the aged-out directories no job refers to, they are left in place
+async fn report_orphans(pool: &SqlitePool, config: &Config) {
+    let tenants: Vec<String> = config.tenants.keys().cloned().collect();
+    let mut dirs = Vec::new();
+    for data_path in config.data_paths() {
+        match list_job_dirs(data_path, &tenants, config.job_dir_depth) {
+            Ok(d) => dirs.extend(d),
+            Err(_) => error!("could not read directory: {}", data_path),
+        }
+    }
+
+    for (tenant, path) in dirs {
+        if dir_age(&path).is_none_or(|age| age < config.max_age_for(&tenant)) {
+            continue;
+        }
+        let mut job = Job::new("");
+        match job.retrieve_by_loc(path.display().to_string(), pool).await {
+            Ok(_) => {}
+            Err(sqlx::Error::RowNotFound) => {
+                warn!("{:?} - no job refers to it, not cleaning", path.display())
+            }
+            Err(e) => error!("{:?} - could not look up {:?}", e, path),
+        }
+    }
+}
+
+/// Move the result archives that reached the tiering age to the object store
+pub async fn tierer(pool: SqlitePool, config: Config, client: Client) {
+    let Some(store) = &config.object_store else {
+        return;
+    };
+
+    let jobs = match Job::list_local_completed(&pool).await {
+        Ok(jobs) => jobs,
+        Err(e) => {
+            error!("could not list the completed jobs: {:?}", e);
+            return;
+        }
+    };
+
+    for mut job in jobs {
+        let archive = job.loc.join("output.zip");
+        let age = fs::metadata(&archive)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| SystemTime::now().duration_since(t).ok());
+        if age.is_none_or(|age| age < store.tier_after) {
+            continue;
+        }
+
+        // Downloads are served with an ETag, it can not be computed once the file is gone
+        if job.checksum.is_none() {
+            match job.compute_checksum() {
+                Ok(c) => {
+                    let _ = job.update_checksum(c, &pool).await;
+                }
+                Err(e) => {
+                    error!("{:?} - could not hash {:?}", e, archive);
+                    continue;
+                }
+            }
+        }
+
+        let key = job.object_key();
+        if let Err(e) = client.put_object(store, &key, &archive).await {
+            error!("{:?} - could not move {:?} to the object store", e, archive);
+            continue;
+        }
+        // Point the job at the remote copy before the local one disappears
+        if let Err(e) = job.update_remote_key(key, &pool).await {
+            error!(
+                "{:?} - could not record the object key of job {}",
+                e, job.id
+            );
+            continue;
+        }
+        match job.clear_local_files() {
+            Ok(_) => info!("moved the results of job {} to the object store", job.id),
+            Err(e) => error!("{:?} - could not clear {:?}", e, job.loc),
+        }
+    }
+}
+
+/// Pack the directories of jobs that have been idle for `pack_after`, so jobs
+/// with thousands of small files hold one archive each
+pub async fn packer(pool: SqlitePool, config: Config) {
+    let Some(pack_after) = config.pack_after else {
+        return;
+    };
+
+    let jobs = match Job::list_packable(&pool).await {
+        Ok(jobs) => jobs,
+        Err(e) => {
+            error!("could not list the jobs to pack: {:?}", e);
+            return;
+        }
+    };
+
+    let packed = tokio::task::spawn_blocking(move || {
+        jobs.into_iter()
+            .filter(|job| !job.is_packed())
+            .filter(|job| {
+                fs::metadata(&job.loc)
+                    .and_then(|m| m.modified())
+                    .ok()
+                    .and_then(|t| SystemTime::now().duration_since(t).ok())
+                    .is_some_and(|idle| idle >= pack_after)
+            })
+            .map(|job| match job.pack() {
+                Ok(n) => n,
+                Err(e) => {
+                    error!("{:?} - could not pack {:?}", e, job.loc);
+                    0
                 }
+            })
+            .sum::<usize>()
+    })
+    .await
+    .unwrap_or_default();
+
+    if packed > 0 {
+        info!("packed {packed} files of idle jobs");
+    }
+}
+
+/// Payloads younger than this are left alone, their job may not have recorded
+/// the payload id yet
+const RECONCILE_GRACE: Duration = Duration::from_secs(600);
+
+/// Ask each client to delete the payloads no job refers to anymore, e.g. of jobs
+/// killed or cleaned here before their results were retrieved. Returns how
+/// many were deleted.
+pub async fn reconciler(pool: SqlitePool, config: Config, client: Client) -> usize {
+    if !config.reconcile_payloads {
+        return 0;
+    }
+
+    // Payload ids belong to a client, the services it runs are checked together
+    let mut clients: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for service in config.services.keys() {
+        if let Some(url) = endpoint::payloads_url(service, &config) {
+            clients.entry(url).or_default().push(service.clone());
+        }
+    }
+
+    let mut deleted = 0;
+    for (url, services) in clients {
+        // Any of them reaches the client, its credentials are used
+        let service = &services[0];
+        let payloads = match endpoint::list_payloads(
+            service,
+            &config,
+            RECONCILE_GRACE,
+            client.clone(),
+        )
+        .await
+        {
+            Ok(ids) => ids,
+            Err(e) => {
+                debug!("Could not list the payloads at {url}: {e}");
+                continue;
             }
         };
-    });
+        if payloads.is_empty() {
+            continue;
+        }
+
+        let referenced = match Job::referenced_dest_ids(&services, &pool).await {
+            Ok(ids) => ids,
+            Err(e) => {
+                error!("could not list the payloads in use: {:?}", e);
+                return deleted;
+            }
+        };
+        for id in payloads.into_iter().filter(|id| !referenced.contains(id)) {
+            match endpoint::delete_payload(service, id, &config, client.clone()).await {
+                Ok(_) => {
+                    info!("deleted payload {id} at {url}, no job refers to it");
+                    deleted += 1;
+                }
+                Err(e) => error!("could not delete payload {id} at {url}: {e}"),
+            }
+        }
+    }
+    deleted
+}
+
+/// Why a new submission should wait for an operator to review it, `None`
+/// when it can be queued right away
+pub async fn review_flag(
+    job: &Job,
+    config: &Config,
+    pool: &SqlitePool,
+) -> Result<Option<String>, sqlx::Error> {
+    let Some(service) = config.services.get(&job.service) else {
+        return Ok(None);
+    };
+
+    // A script without the exit trap is not suspicious, the client fails it
+    if service.hold_suspicious
+        && let Err(e @ ClientError::UnsafeScript { .. }) = validate_script(&job.loc.join("run.sh"))
+    {
+        return Ok(Some(e.to_string()));
+    }
+
+    if let Some(limit) = service.hold_queued_over {
+        let waiting = Job::count_waiting(job.user_id, &job.service, pool).await?;
+        if waiting >= limit {
+            return Ok(Some(format!(
+                "User {} already has {waiting} jobs waiting on {}",
+                job.user_id, job.service
+            )));
+        }
+    }
 
-    futures::future::join_all(futures).await;
+    Ok(None)
 }
 
 // Terminate task will send a kill command to the client
 pub async fn terminate_job(
-    mut j: Job,
+    j: &mut Job,
     pool: SqlitePool,
     config: Config,
+    client: Client,
 ) -> Result<(), TerminateError> {
     let original_status = j.get_status();
     // lock the job so no other thread pick it up
     j.update_status(Status::Locked, &pool).await.ok();
+    // Should the client not answer, the getter asks it again instead of
+    // polling the job
+    if let Err(e) = j.request_cancel(&pool).await {
+        error!("Failed to record the cancellation of job {}: {:?}", j.id, e);
+    }
 
-    match endpoint::kill(&j, &config, Client).await {
+    match endpoint::kill(j, &config, client).await {
         Ok(_) => {
             // Job was killed
             j.update_status(Status::Killed, &pool).await.ok();
             Ok(())
         }
-        Err(_) => {
+        Err(e) => {
             // There was an error, do nothing
             j.update_status(original_status, &pool).await.ok();
-            Err(TerminateError::GenericError)
+            Err(e)
+        }
+    }
+}
+
+/// Cancel `j` on its client, which stops the payload should it run and
+/// discards it. A client predating `/cancel` answers it with `404` and is
+/// asked to kill the payload instead, one that no longer has it neither.
+pub async fn cancel_job(
+    j: &mut Job,
+    pool: SqlitePool,
+    config: Config,
+    client: Client,
+) -> Result<(), TerminateError> {
+    let original_status = j.get_status();
+    // lock the job so no other thread pick it up
+    j.update_status(Status::Locked, &pool).await.ok();
+    // Should the client not answer, the getter asks it again
+    if let Err(e) = j.request_discard(&pool).await {
+        error!("Failed to record the cancellation of job {}: {:?}", j.id, e);
+    }
+
+    let cancelled = match endpoint::cancel(j, &config, client.clone()).await {
+        Err(TerminateError::HttpError(StatusCode::NOT_FOUND))
+            if config.get_terminate_url(&j.service).is_some() =>
+        {
+            match endpoint::kill(j, &config, client).await {
+                Err(TerminateError::HttpError(StatusCode::NOT_FOUND)) => Ok(()),
+                killed => killed,
+            }
+        }
+        Err(TerminateError::HttpError(StatusCode::NOT_FOUND)) => Ok(()),
+        cancelled => cancelled,
+    };
+    match cancelled {
+        Ok(_) => {
+            j.update_status(Status::Cancelled, &pool).await.ok();
+            Ok(())
+        }
+        Err(e) => {
+            j.update_status(original_status, &pool).await.ok();
+            Err(e)
+        }
+    }
+}
+
+/// Ask the client of every service what it can run, so the sender holds back
+/// the jobs it could not take. A client that does not answer, e.g. an older
+/// one, gets every job as before.
+pub async fn register(config: &mut Config, client: &Client) {
+    let names: Vec<String> = config.services.keys().cloned().collect();
+    for name in names {
+        let capabilities = match endpoint::capabilities(&name, config, client.clone()).await {
+            Ok(c) => c,
+            Err(e) => {
+                warn!("No capabilities for service {name}, its jobs are sent unchecked: {e}");
+                continue;
+            }
+        };
+        let Some(service) = config.services.get_mut(&name) else {
+            continue;
+        };
+        match capabilities.incompatibility(service, 0) {
+            Some(reason) => error!("Jobs of {name} are held back, {reason}"),
+            None => info!("Service {name} registered: {:?}", capabilities),
+        }
+        service.capabilities = Some(capabilities);
+    }
+}
+
+/// Services whose jobs stay queued this round, whatever their client's load
+async fn held_services(pool: &SqlitePool, config: &Config) -> HashSet<String> {
+    // Jobs of a service the prober finds down wait for it to come back
+    let mut held = match ServiceProbe::down(pool, config.probes.down_after).await {
+        Ok(down) => down,
+        Err(e) => {
+            error!("Failed to read the service probes: {:?}", e);
+            HashSet::new()
+        }
+    };
+    // So do the jobs of a client the version policy refuses, until upgraded
+    match ServiceProbe::versions(pool).await {
+        Ok(versions) => held.extend(
+            versions
+                .into_iter()
+                .filter(|(_, v)| !config.client_versions.standing(v.as_deref()).dispatches())
+                .map(|(service, _)| service),
+        ),
+        Err(e) => error!("Failed to read the client versions: {:?}", e),
+    }
+    // And the jobs of a service outside its execution windows, until one opens
+    let now = SystemTime::now();
+    held.extend(
+        config
+            .services
+            .iter()
+            .filter(|(_, s)| s.windows.as_ref().is_some_and(|w| !w.is_open(now)))
+            .map(|(name, _)| name.clone()),
+    );
+    held
+}
+
+/// Hold back the jobs their client can not take or whose files do not fit on
+/// its disk, they stay queued for a later round. Each client is asked once,
+/// the size of the jobs let through is deducted from the free space it
+/// reported.
+async fn fitting_jobs(jobs: Vec<Job>, config: &Config, client: &Client) -> Vec<Job> {
+    let mut reports: HashMap<String, Option<LoadReport>> = HashMap::new();
+    let mut fitting = Vec::new();
+    for j in jobs {
+        let size = j.input_size();
+        if let Some(reason) = config.services.get(&j.service).and_then(|s| {
+            s.capabilities
+                .as_ref()
+                .and_then(|c| c.incompatibility(s, size))
+        }) {
+            debug!("Keeping job {} queued, {reason}", j.id);
+            continue;
+        }
+
+        if !reports.contains_key(&j.service) {
+            // A client that can not tell, e.g. an older one, still gets its jobs
+            let report = match endpoint::load(&j.service, config, client.clone()).await {
+                Ok(r) => Some(r),
+                Err(e) => {
+                    debug!("No load report for service {}: {e}", j.service);
+                    None
+                }
+            };
+            reports.insert(j.service.clone(), report);
+        }
+
+        match reports.get_mut(&j.service).and_then(Option::as_mut) {
+            Some(report) if !report.fits(size) => info!(
+                "Keeping job {} queued, it needs {size} bytes and its client has {:?} free",
+                j.id, report.disk_free
+            ),
+            Some(report) => {
+                if let Some(free) = report.disk_free.as_mut() {
+                    *free -= size;
+                }
+                fitting.push(j);
+            }
+            None => fitting.push(j),
+        }
+    }
+    fitting
+}
+
+/// Snapshot the database once the newest snapshot is older than the interval,
+/// then drop the snapshots beyond the ones to keep, locally and remotely
+pub async fn backup(pool: SqlitePool, config: Config, client: Client) {
+    let Some(settings) = &config.backup else {
+        return;
+    };
+    match backup::latest_age(&settings.dir) {
+        Ok(Some(age)) if age < settings.interval => return,
+        Ok(_) => {}
+        Err(e) => {
+            error!("could not list the backups in {:?}: {:?}", settings.dir, e);
+            return;
+        }
+    }
+
+    let path = match backup::snapshot(&pool, &settings.dir).await {
+        Ok(path) => path,
+        Err(e) => {
+            error!("database backup failed: {e}");
+            return;
+        }
+    };
+    info!("database backed up to {:?}", path);
+
+    // A copy off the machine survives the loss of its disk
+    if let Some(store) = &config.object_store
+        && let Err(e) = client
+            .put_object(store, &backup::backup_key(&path), &path)
+            .await
+    {
+        error!("could not copy backup {:?} to the object store: {e}", path);
+    }
+
+    let removed = match backup::prune(&settings.dir, settings.keep) {
+        Ok(removed) => removed,
+        Err(e) => {
+            error!("could not prune the backups in {:?}: {:?}", settings.dir, e);
+            return;
+        }
+    };
+    if let Some(store) = &config.object_store {
+        for old in removed {
+            if let Err(e) = client.delete_object(store, &backup::backup_key(&old)).await {
+                error!(
+                    "could not remove backup {:?} from the object store: {e}",
+                    old
+                );
+            }
         }
     }
 }
 
-pub async fn sender(pool: SqlitePool, config: Config) {
+/// Send the job callbacks and their copies for the operators that are due,
+/// each over its channel. Failed ones are retried with an exponential backoff
+/// until `WEBHOOK_MAX_ATTEMPTS` is reached.
+pub async fn notifier(pool: SqlitePool, config: Config, client: Client) {
+    let deliveries = match WebhookDelivery::list_due(&pool).await {
+        Ok(d) => d,
+        Err(e) => {
+            error!("Failed to fetch due webhook deliveries: {:?}", e);
+            return;
+        }
+    };
+
+    let dispatcher = Dispatcher::new(&config, &client);
+    let (pool, config, dispatcher) = (&pool, &config, &dispatcher);
+    stream::iter(deliveries)
+        .for_each_concurrent(10, |mut d| async move {
+            let result = match dispatcher.deliver(&d).await {
+                Attempt::Delivered(status) => d.record_delivered(status, pool).await,
+                Attempt::Failed(status, e) => {
+                    let retry_in = webhook::retry_in(&config.webhook, d.attempts + 1);
+                    match retry_in {
+                        Some(wait) => info!(
+                            "Callback {} of job {} failed ({e}), retrying in {:?}",
+                            d.id, d.job_id, wait
+                        ),
+                        None => error!(
+                            "Callback {} of job {} failed ({e}), giving up after {} attempts",
+                            d.id,
+                            d.job_id,
+                            d.attempts + 1
+                        ),
+                    }
+                    d.record_failure(status, &e, retry_in, pool).await
+                }
+            };
+            if let Err(e) = result {
+                error!("Failed to record webhook delivery {}: {:?}", d.id, e);
+            }
+        })
+        .await;
+}
+
+pub async fn sender(pool: SqlitePool, config: Config, client: Client) -> TaskTally {
     let mut queue = Queue::new(&config);
-    if queue.load(&pool).await.is_ok() {
-        // info!("There are {:?} queued jobs", queue.jobs.len());
-        let futures = queue
-            .jobs
-            .into_iter()
-            .map(|mut j| {
-                // info!("{:?}", j);
-                let pool_clone = pool.clone();
-                let config_clone = config.clone();
-                tokio::spawn(async move {
-                    j.update_status(Status::Processing, &pool_clone).await.ok();
-
-                    match endpoint::send(&j, &config_clone, Client).await {
-                        Ok(upload_id) => {
-                            info!("submitting: {:?}", j);
-                            j.update_status(Status::Submitted, &pool_clone).await.ok();
-                            j.update_dest_id(upload_id, &pool_clone).await.ok();
-                            debug!("{:?}", j);
-                        }
-                        Err(e) => {
-                            error!("Upload error: {:?}", e);
-                            j.update_status(Status::Failed, &pool_clone).await.ok();
-                        }
+    queue.skip = held_services(&pool, &config).await;
+    if let Err(e) = queue.load(&pool).await {
+        error!("Failed to load the queue: {:?}", e);
+        return TaskTally::failed();
+    }
+    for t in &queue.throttled {
+        info!(
+            "Keeping the jobs of user {} ({}) on {} queued, {} of {} runs per user active",
+            t.user_id, t.tenant, t.service, t.active, t.runs_per_user
+        );
+    }
+    // info!("There are {:?} queued jobs", queue.jobs.len());
+    let loaded: Vec<u32> = queue.jobs.iter().map(|j| j.id).collect();
+    let fitting = fitting_jobs(queue.jobs, &config, &client).await;
+    // The jobs held back are picked again on the next round
+    let sent: HashSet<u32> = fitting.iter().map(|j| j.id).collect();
+    let unsent: Vec<u32> = loaded.into_iter().filter(|id| !sent.contains(id)).collect();
+    if let Err(e) = Queue::release(&unsent, &pool).await {
+        error!("Failed to release the jobs not sent: {:?}", e);
+    }
+    let futures = fitting
+        .into_iter()
+        .map(|mut j| {
+            // info!("{:?}", j);
+            let pool_clone = pool.clone();
+            let config_clone = config.clone();
+            let client = client.clone();
+            tokio::spawn(async move {
+                j.update_status(Status::Processing, &pool_clone).await.ok();
+
+                match endpoint::send(&j, &config_clone, client).await {
+                    Ok(upload_id) => {
+                        info!("submitting: {:?}", j);
+                        j.update_status(Status::Submitted, &pool_clone).await.ok();
+                        j.update_dest_id(upload_id, &pool_clone).await.ok();
+                        debug!("{:?}", j);
+                        true
+                    }
+                    Err(e) => {
+                        record_failed_upload(&mut j, e, &pool_clone, &config_clone).await;
+                        false
                     }
-                })
+                }
             })
-            .collect::<Vec<_>>();
+        })
+        .collect::<Vec<_>>();
 
-        futures::future::join_all(futures).await;
-    }
+    // A panicked upload counts as an error too
+    tally(
+        futures::future::join_all(futures)
+            .await
+            .into_iter()
+            .map(|r| r.unwrap_or(false)),
+    )
 }
 
 // The getter task retrieves the jobs from the Client and updates the status on the Server
-pub async fn getter(pool: SqlitePool, config: Config) {
+pub async fn getter(pool: SqlitePool, config: Config, client: Client) -> TaskTally {
     let mut queue = Queue::new(&config);
 
     if let Err(e) = queue
-        .list_per_status(
+        .list_due_per_status(
             vec![Status::Submitted, Status::Prepared, Status::Running],
             &pool,
         )
         .await
     {
         error!("Failed to fetch submitted jobs: {:?}", e);
-        return;
+        return TaskTally::failed();
     }
 
-    let _: Vec<_> = stream::iter(queue.jobs)
+    let outcomes: Vec<_> = stream::iter(queue.jobs)
         .map(|mut j| {
             let pool = pool.clone();
             let config = config.clone();
+            let client = client.clone();
             async move {
-                match  endpoint::retrieve(&j, &config, Client).await {
-                    Ok(s) => {
-                        if let Err(e) = j.update_status(s, &pool).await {
-                            error!("Failed to update status of job {} to {}: {:?}", j.id, s, e);
-                        }
-                    }
-                    Err(e) => {
-                        // Log the error but leave the job status unchanged to avoid
-                        // incorrectly marking transient conditions (e.g., job still
-                        // running) as permanently failed.
-                        error!(
-                            "There was some error while trying to retrieve job {0} from the client: {e}",
-                            j.id
-                        );
-                    }
+                if j.cancel_requested {
+                    return cancel(&mut j, &pool, &config, client).await;
+                }
+                if let Err(e) = collect(&mut j, GETTER_ACTOR, &pool, &config, client).await {
+                    record_failed_download(&mut j, e, &pool, &config).await;
+                    return false;
                 }
+                true
             }
         })
         // NOTE: This will limit how many "retrieves" we are doing at a single time, this might
@@ -170,186 +824,2863 @@ pub async fn getter(pool: SqlitePool, config: Config) {
         .buffer_unordered(10)
         .collect()
         .await;
+
+    tally(outcomes)
 }
 
-#[cfg(test)]
-mod test {
+/// Ask the client of every service for its `/health` and record whether it
+/// answered and its version, the sender skips the services found down and
+/// the clients the version policy refuses
+pub async fn prober(pool: SqlitePool, config: Config, client: Client) -> TaskTally {
+    let down_after = config.probes.down_after;
+    let was_down = ServiceProbe::down(&pool, down_after)
+        .await
+        .unwrap_or_default();
+    let ran = ServiceProbe::versions(&pool).await.unwrap_or_default();
 
-    use super::*;
-    use crate::config::loader::{Config, Service};
-    use crate::models::payload_dao::Payload;
-    use crate::models::{job_dao::Job, job_dto::create_jobs_table};
-    use std::{path::Path, time::Duration};
-    use tempfile::TempDir;
-    use tokio::time::sleep;
+    let (pool, config) = (&pool, &config);
+    let outcomes = config.services.keys().map(|service| {
+        let client = client.clone();
+        async move {
+            let start = Instant::now();
+            let (outcome, version) = match endpoint::probe(service, config, client).await {
+                Ok(version) => (Ok(start.elapsed()), version),
+                Err(e) => (Err(e.to_string()), None),
+            };
+            let up = outcome.is_ok();
+            if let Err(e) = ServiceProbe::record(service, outcome, version.as_deref(), pool).await {
+                error!("Failed to record the probe of service {service}: {:?}", e);
+            }
+            up
+        }
+    });
+    let tally = tally(futures::future::join_all(outcomes).await);
 
-    #[tokio::test]
-    async fn test_cleaner_invalid_path() {
-        let pool = SqlitePool::connect(":memory:")
-            .await
-            .unwrap_or_else(|e| panic!("Database connection failed: {e}"));
-        create_jobs_table(&pool).await.unwrap();
+    match ServiceProbe::down(pool, down_after).await {
+        Ok(down) => {
+            for service in down.difference(&was_down) {
+                warn!("Service {service} failed {down_after} probes in a row, not sending it jobs");
+            }
+            for service in was_down.difference(&down) {
+                info!("Service {service} answers its probes again");
+            }
+        }
+        Err(e) => error!("Failed to read the service probes: {:?}", e),
+    }
+    match ServiceProbe::versions(pool).await {
+        Ok(runs) => {
+            for (service, version) in runs.iter().filter(|(s, v)| ran.get(*s) != Some(v)) {
+                log_version(service, version.as_deref(), &config.client_versions);
+            }
+        }
+        Err(e) => error!("Failed to read the client versions: {:?}", e),
+    }
+
+    tally
+}
+
+/// Tell the operators a service runs a new client version
+fn log_version(service: &str, version: Option<&str>, policy: &ClientVersionPolicy) {
+    let shown = version.unwrap_or(UNKNOWN_VERSION);
+    match policy.standing(version) {
+        VersionStanding::Outdated => warn!(
+            "Service {service} runs client version {shown}, below the minimum, not sending it jobs"
+        ),
+        VersionStanding::Blocked => {
+            warn!("Service {service} runs blocked client version {shown}, not sending it jobs")
+        }
+        VersionStanding::Deprecated => {
+            warn!("Service {service} runs deprecated client version {shown}")
+        }
+        VersionStanding::Current | VersionStanding::Unknown => {
+            info!("Service {service} runs client version {shown}")
+        }
+    }
+}
+
+/// Longest wait between two uploads of the same job
+const MAX_SUBMIT_BACKOFF: Duration = Duration::from_secs(900);
+
+/// Wait before the next upload once `attempts` have failed, `None` when the
+/// job should be given up. Somewhere between half and all of the doubled
+/// backoff, so the jobs that failed together are not all sent again at once.
+fn submit_retry_in(config: &SubmitRetryConfig, attempts: u32) -> Option<Duration> {
+    if attempts >= config.max_attempts {
+        return None;
+    }
+    let factor = 2u32.saturating_pow(attempts.saturating_sub(1));
+    let backoff = config
+        .backoff
+        .saturating_mul(factor)
+        .min(MAX_SUBMIT_BACKOFF);
+    Some(backoff / 2 + backoff.mul_f64(random_fraction() / 2.0))
+}
+
+/// A job whose upload failed for a reason that may go away, e.g. a client
+/// down or timing out, is queued again and sent after a backoff until
+/// `SUBMIT_MAX_ATTEMPTS` uploads failed. It is then, or right away for any
+/// other failure, marked `failed`.
+async fn record_failed_upload(j: &mut Job, e: UploadError, pool: &SqlitePool, config: &Config) {
+    let attempts = j.retry_count + 1;
+    if e.is_transient()
+        && let Some(wait) = submit_retry_in(&config.submit_retry, attempts)
+    {
+        warn!(
+            "Upload of job {} failed ({e}), sending it again in {:?}",
+            j.id, wait
+        );
+        let result = async {
+            j.record_submit_failure(wait, pool).await?;
+            j.update_status(Status::Queued, pool).await
+        };
+        if let Err(e) = result.await {
+            error!(
+                "Failed to record the upload failure of job {}: {:?}",
+                j.id, e
+            );
+        }
+        return;
+    }
+
+    let reason = if e.is_transient() {
+        error!(
+            "Upload of job {} failed ({e}), giving up after {attempts} attempts",
+            j.id
+        );
+        format!("upload failed {attempts} times: {e}")
+    } else {
+        error!("Upload of job {} failed: {e}", j.id);
+        format!("upload failed: {e}")
+    };
+    let result = async {
+        j.update_last_error(JobError::from(&e), pool).await?;
+        j.update_status_by(Status::Failed, SENDER_ACTOR, Some(&reason), pool)
+            .await?;
+        webhook::enqueue(j, &config.subscriptions, pool).await
+    };
+    if let Err(e) = result.await {
+        error!("Failed to give up job {}: {:?}", j.id, e);
+    }
+}
+
+/// Longest wait between two downloads of the same job
+const MAX_DOWNLOAD_BACKOFF: Duration = Duration::from_secs(900);
+
+/// Wait before the next download once `attempts` have failed in a row, `None`
+/// when the job should be given up
+fn download_retry_in(config: &DownloadRetryConfig, attempts: u32) -> Option<Duration> {
+    if attempts >= config.max_attempts {
+        return None;
+    }
+    let factor = 2u32.saturating_pow(attempts.saturating_sub(1));
+    Some(
+        config
+            .backoff
+            .saturating_mul(factor)
+            .min(MAX_DOWNLOAD_BACKOFF),
+    )
+}
+
+/// The job stays where it is and is retried with a backoff until
+/// `DOWNLOAD_MAX_ATTEMPTS` failed in a row. It is then given up as `unknown`
+/// when the client may still hold the results, e.g. it kept timing out, and
+/// as `failed` when it never will.
+async fn record_failed_download(j: &mut Job, e: DownloadError, pool: &SqlitePool, config: &Config) {
+    let attempts = j.download_attempts + 1;
+    if let Some(wait) = download_retry_in(&config.download_retry, attempts) {
+        warn!(
+            "Download of job {} failed ({e}), retrying in {:?}",
+            j.id, wait
+        );
+        if let Err(e) = j.record_download_failure(wait, pool).await {
+            error!(
+                "Failed to record the download failure of job {}: {:?}",
+                j.id, e
+            );
+        }
+        return;
+    }
+
+    let status = if e.is_transient() {
+        Status::Unknown
+    } else {
+        Status::Failed
+    };
+    error!(
+        "Download of job {} failed ({e}), giving up after {attempts} attempts",
+        j.id
+    );
+    let result = async {
+        j.record_download_failure(Duration::ZERO, pool).await?;
+        j.update_last_error(JobError::from(&e), pool).await?;
+        let reason = format!("download failed {attempts} times: {e}");
+        j.update_status_by(status, GETTER_ACTOR, Some(&reason), pool)
+            .await?;
+        webhook::enqueue(j, &config.subscriptions, pool).await
+    };
+    if let Err(e) = result.await {
+        error!("Failed to give up job {}: {:?}", j.id, e);
+    }
+}
+
+/// Ask the client again to stop a job whose termination failed, spaced out
+/// like the downloads but never given up. A client that no longer knows the
+/// payload has nothing left to stop.
+async fn cancel(j: &mut Job, pool: &SqlitePool, config: &Config, client: Client) -> bool {
+    // Cancelled by its owner, or terminated
+    let stopped = match j.discard_requested {
+        true => cancel_job(j, pool.clone(), config.clone(), client).await,
+        false => terminate_job(j, pool.clone(), config.clone(), client).await,
+    };
+    let error = match stopped {
+        Ok(()) => {
+            info!("Job {} {} on its client", j.id, j.status);
+            return true;
+        }
+        Err(TerminateError::HttpError(StatusCode::NOT_FOUND)) => {
+            info!("Client of job {} no longer has it, marking it killed", j.id);
+            if let Err(e) = j.update_status(Status::Killed, pool).await {
+                error!("Failed to update status of job {}: {:?}", j.id, e);
+            }
+            return true;
+        }
+        Err(e) => e,
+    };
+
+    let retry = DownloadRetryConfig {
+        max_attempts: u32::MAX,
+        ..config.download_retry.clone()
+    };
+    let wait = download_retry_in(&retry, j.download_attempts + 1).unwrap_or(MAX_DOWNLOAD_BACKOFF);
+    warn!(
+        "Termination of job {} failed ({error}), retrying in {:?}",
+        j.id, wait
+    );
+    if let Err(e) = j.record_download_failure(wait, pool).await {
+        error!(
+            "Failed to record the termination failure of job {}: {:?}",
+            j.id, e
+        );
+    }
+    false
+}
+
+/// Ask the client of `j` for its results and record what it answered, the
+/// change of status attributed to `actor`. Errors of the database are logged,
+/// `j.status` only changes once stored.
+async fn collect(
+    j: &mut Job,
+    actor: &str,
+    pool: &SqlitePool,
+    config: &Config,
+    client: Client,
+) -> Result<(), DownloadError> {
+    // The results are only asked for once the client reports them ready.
+    // Clients without `/status` are asked for them right away.
+    let (retrieved, mut summary, failure) = match endpoint::status(j, config, client.clone()).await
+    {
+        Ok(p) if p.status != Status::Completed => (Retrieved::Pending(p.status), None, p.failure),
+        Ok(p) => (
+            endpoint::retrieve(j, config, client).await?,
+            p.summary,
+            None,
+        ),
+        Err(e) => {
+            debug!("No status for job {} ({e}), retrieving it", j.id);
+            (endpoint::retrieve(j, config, client).await?, None, None)
+        }
+    };
+    if j.download_attempts > 0
+        && let Err(e) = j.reset_download_attempts(pool).await
+    {
+        error!(
+            "Failed to reset the download attempts of job {}: {:?}",
+            j.id, e
+        );
+    }
+    let s = retrieved.status();
+    // Record the checksum before flipping to Completed so every
+    // completed job can be served with an ETag
+    if let Retrieved::Shared { key, checksum } = &retrieved {
+        // Nothing to download, the archive already sits in the object store
+        if let Some(checksum) = checksum
+            && let Err(e) = j.update_checksum(checksum.clone(), pool).await
+        {
+            error!("Failed to store checksum of job {}: {:?}", j.id, e);
+        }
+        if let Err(e) = j.update_remote_key(key.clone(), pool).await {
+            error!("Failed to store the result key of job {}: {:?}", j.id, e);
+            return Ok(());
+        }
+        if config
+            .services
+            .get(&j.service)
+            .is_some_and(|s| s.extract_results)
+        {
+            info!(
+                "Results of job {} are in the object store, they are not extracted",
+                j.id
+            );
+        }
+    }
+    if retrieved == Retrieved::Downloaded {
+        match j.compute_checksum() {
+            Ok(checksum) => {
+                if let Err(e) = j.update_checksum(checksum, pool).await {
+                    error!("Failed to store checksum of job {}: {:?}", j.id, e);
+                }
+            }
+            Err(e) => error!("Failed to compute checksum of job {}: {:?}", j.id, e),
+        }
+        match j.summarize_output() {
+            Ok(summary) => {
+                if let Err(e) = j.update_output_summary(summary, pool).await {
+                    error!("Failed to store result metadata of job {}: {:?}", j.id, e);
+                }
+            }
+            Err(e) => error!("Failed to inspect results of job {}: {:?}", j.id, e),
+        }
+        // Older clients only ship it in the archive
+        if summary.is_none() {
+            match j.read_result_summary() {
+                Ok(found) => summary = found,
+                Err(e) => error!("Failed to inspect results of job {}: {:?}", j.id, e),
+            }
+        }
+        // The archive stays in place, so a failed extraction still
+        // leaves the results downloadable
+        if config
+            .services
+            .get(&j.service)
+            .is_some_and(|s| s.extract_results)
+        {
+            match j.extract_output() {
+                Ok(n) => info!("Extracted {n} files of job {} into {:?}", j.id, j.loc),
+                Err(e) => error!("Failed to extract results of job {}: {:?}", j.id, e),
+            }
+        }
+    }
+    // Stored before the status, so a failed job shows why right away
+    if matches!(s, Status::Failed | Status::Invalid | Status::Unknown) && s != j.status {
+        let message = failure.unwrap_or_else(|| format!("the client reported the job {s}"));
+        let error = JobError::new(RUNNER_SOURCE, &s.to_string(), message);
+        if let Err(e) = j.update_last_error(error, pool).await {
+            error!("Failed to store the error of job {}: {:?}", j.id, e);
+        }
+    }
+    // Stored before `Completed` so a completed job shows it right away
+    if let Some(summary) = summary
+        && let Err(e) = j.update_result_summary(summary, pool).await
+    {
+        error!(
+            "Failed to store the result summary of job {}: {:?}",
+            j.id, e
+        );
+    }
+    let was = j.status;
+    if let Err(e) = j.update_status_by(s, actor, None, pool).await {
+        error!("Failed to update status of job {} to {}: {:?}", j.id, s, e);
+        return Ok(());
+    }
+    // Failures are logged by the hooks, the job is complete regardless
+    if s == Status::Completed && was != Status::Completed {
+        let _ = Hooks::new(config).run(HookPoint::PostComplete, j).await;
+    }
+    if let Err(e) = webhook::enqueue(j, &config.subscriptions, pool).await {
+        error!("Failed to queue the callback of job {}: {:?}", j.id, e);
+    }
+    Ok(())
+}
+
+/// Report the jobs left `submitted` past the `STALE_AFTER` of their service,
+/// e.g. because the client lost them or never answers. Each job is reported
+/// once, and requeued or cancelled as its service's `ON_STALE` says.
+pub async fn watchdog(pool: SqlitePool, config: Config, client: Client) {
+    for (name, service) in &config.services {
+        let Some(after) = service.stale_after else {
+            continue;
+        };
+        let jobs = match Job::list_stale(name, after, &pool).await {
+            Ok(jobs) => jobs,
+            Err(e) => {
+                error!("Failed to fetch the stale jobs of {name}: {:?}", e);
+                continue;
+            }
+        };
+
+        for mut j in jobs {
+            let reason = format!("submitted for over {}s without progress", after.as_secs());
+            let status = match service.on_stale {
+                StaleAction::Alert => Status::Submitted,
+                StaleAction::Requeue => Status::Queued,
+                StaleAction::Cancel => Status::Failed,
+            };
+            error!("Job {} on {name} was {reason}", j.id);
+            if status != j.status
+                && let Err(e) = endpoint::kill(&j, &config, client.clone()).await
+            {
+                // The payload left behind is removed as a lost one
+                warn!("Could not stop stale job {} on the client: {e}", j.id);
+            }
+
+            let old_status = j.status;
+            let result = async {
+                // Recorded even when the status stays, the job is reported once
+                j.update_status_by(status, WATCHDOG_ACTOR, Some(&reason), &pool)
+                    .await?;
+                if status != old_status {
+                    info!("Stale job {} is now {status}", j.id);
+                }
+                webhook::enqueue(&j, &config.subscriptions, &pool).await
+            };
+            if let Err(e) = result.await {
+                error!("Failed to record stale job {}: {:?}", j.id, e);
+            }
+        }
+    }
+}
+
+/// Check the queue against the watermarks of `ALERT_*` and send the ones
+/// crossed, or cleared, to the operator webhook and the subscriptions to them
+pub async fn monitor(pool: SqlitePool, config: Config, client: Client, state: AlertState) {
+    let Some(alerts) = &config.alerts else {
+        return;
+    };
+    let (depth, oldest) = match Job::queue_watermarks(&pool).await {
+        Ok(w) => w,
+        Err(e) => {
+            error!("Failed to measure the queue: {:?}", e);
+            return;
+        }
+    };
+
+    let dispatcher = Dispatcher::new(&config, &client);
+    for alert in state.evaluate(alerts, depth, oldest, Instant::now()) {
+        if alert.resolved() {
+            info!("Queue back under its watermark: {}", alert.payload);
+        } else {
+            warn!("Queue over its watermark: {}", alert.payload);
+        }
+        let mut targets: Vec<_> = alerts
+            .webhook_url
+            .iter()
+            .map(|url| (Channel::Webhook, url.clone()))
+            .collect();
+        targets.extend(
+            notify::subscribers(&config.subscriptions, &alert.event)
+                .into_iter()
+                .map(|s| (s.channel, s.target.clone())),
+        );
+        // Raised again next round until someone heard of it
+        if dispatcher.broadcast(&alert.notification(), &targets).await > 0 {
+            state.sent(&alert);
+        }
+    }
+}
+
+/// Try a write to `DATA_PATH`, submissions are refused with `507` while it
+/// fails. The operator webhook and the subscriptions to `alert.data_path` are
+/// told when it turns unwritable and when it recovers.
+pub async fn storage_check(config: Config, client: Client, storage: StorageHealth) {
+    storage.check(&config.data_path).await;
+    let Some(degraded) = storage.unreported() else {
+        return;
+    };
+
+    let event = if degraded {
+        "alert.data_path"
+    } else {
+        "alert.data_path.resolved"
+    };
+    let payload = serde_json::json!({
+        "event": event,
+        "data_path": config.data_path,
+        "problem": storage.problem(),
+    });
+    let mut targets: Vec<_> = config
+        .alerts
+        .iter()
+        .filter_map(|a| a.webhook_url.clone())
+        .map(|url| (Channel::Webhook, url))
+        .collect();
+    targets.extend(
+        notify::subscribers(&config.subscriptions, event)
+            .into_iter()
+            .map(|s| (s.channel, s.target.clone())),
+    );
+    if targets.is_empty() {
+        storage.sent(degraded);
+        return;
+    }
+    // Raised again next round until someone heard of it
+    let notification = Notification::new(event, &payload);
+    if Dispatcher::new(&config, &client)
+        .broadcast(&notification, &targets)
+        .await
+        > 0
+    {
+        storage.sent(degraded);
+    }
+}
+
+/// What a run of [`reconcile`] did
+#[derive(Debug, Default, PartialEq)]
+pub struct Reconciliation {
+    /// Jobs whose client was asked
+    pub checked: usize,
+    /// Jobs whose status was corrected
+    pub corrected: usize,
+    /// Jobs whose client could not be asked, left as they were
+    pub unreachable: usize,
+}
+
+/// Ask the clients for the status of every job sent to them and repair the
+/// ones that diverged, e.g. results left uncollected because a getter round
+/// was missed. Each correction is recorded in the job history.
+pub async fn reconcile(
+    pool: &SqlitePool,
+    config: &Config,
+    client: &Client,
+) -> Result<Reconciliation, sqlx::Error> {
+    let mut queue = Queue::new(config);
+    queue
+        .list_per_status(
+            vec![Status::Submitted, Status::Prepared, Status::Running],
+            pool,
+        )
+        .await?;
+
+    let mut report = Reconciliation::default();
+    for mut j in queue.jobs {
+        report.checked += 1;
+        let old_status = j.status;
+        // The correction is recorded in the history by the status update
+        let reason = match collect(&mut j, RECONCILE_ACTOR, pool, config, client.clone()).await {
+            Ok(_) if j.status == old_status => continue,
+            Ok(_) => format!("client reported {}", j.status),
+            // The client does not know the payload, nothing will ever come back
+            Err(DownloadError::Rejected { error, .. }) if error.code == ErrorCode::NotFound => {
+                let reason = "payload not found on the client";
+                j.update_status_by(Status::Failed, RECONCILE_ACTOR, Some(reason), pool)
+                    .await?;
+                reason.to_string()
+            }
+            Err(e) => {
+                warn!("could not reconcile job {}: {e}", j.id);
+                report.unreachable += 1;
+                continue;
+            }
+        };
+
+        info!(
+            "job {} reconciled from {old_status} to {}: {reason}",
+            j.id, j.status
+        );
+        report.corrected += 1;
+    }
+    Ok(report)
+}
+
+/// Dump the jobs the scheduler is still handling, what it would send next and
+/// how each service and its client stand, see [`QueueSnapshot`]
+pub async fn queue_snapshot(
+    pool: &SqlitePool,
+    config: &Config,
+    client: &Client,
+) -> Result<QueueSnapshot, sqlx::Error> {
+    let mut queue = Queue::new(config);
+    queue
+        .list_per_status(
+            vec![
+                Status::Queued,
+                Status::Held,
+                Status::Locked,
+                Status::Processing,
+                Status::Submitted,
+                Status::Prepared,
+                Status::Running,
+            ],
+            pool,
+        )
+        .await?;
+    let jobs = queue.jobs;
+
+    let mut next = Queue::new(config);
+    next.preview(pool).await?;
+    let mut throttled = next.throttled;
+    throttled.sort_by(|a, b| {
+        (&a.service, &a.tenant, a.user_id).cmp(&(&b.service, &b.tenant, b.user_id))
+    });
+    let next = next.jobs.iter().map(|j| j.id).collect();
+
+    let mut services = BTreeMap::new();
+    for (name, service) in &config.services {
+        let count = |statuses: &[Status]| {
+            jobs.iter()
+                .filter(|j| j.service == *name && statuses.contains(&j.status))
+                .count()
+        };
+        let (load, load_error) = match endpoint::load(name, config, client.clone()).await {
+            Ok(report) => (Some(report), None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        services.insert(
+            name.clone(),
+            ServiceSnapshot {
+                queued: count(&[Status::Queued]),
+                // Same statuses `Queue::load` counts against the limit
+                active: count(&[
+                    Status::Processing,
+                    Status::Submitted,
+                    Status::Prepared,
+                    Status::Running,
+                ]),
+                max_runs: service.max_runs,
+                runs_per_user: service.runs_per_user,
+                load,
+                load_error,
+            },
+        );
+    }
+
+    Ok(QueueSnapshot {
+        taken_at: SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+        jobs,
+        next,
+        throttled,
+        services,
+    })
+}
+
+/// Estimate when `job` should be done from its place in the queue of its
+/// service and the runtimes its client reports, see [`JobEta`]. A client that
+/// can't be asked leaves the estimates unset.
+pub async fn job_eta(
+    job: &Job,
+    pool: &SqlitePool,
+    config: &Config,
+    client: &Client,
+) -> Result<JobEta, sqlx::Error> {
+    let (ahead, active) = job.queue_position(pool).await?;
+    let median = match endpoint::run_stats(&job.service, config, client.clone()).await {
+        Ok(stats) => stats.and_then(|s| s.p50_secs),
+        Err(e) => {
+            warn!("Could not get the runtimes of service {}: {e}", job.service);
+            None
+        }
+    };
+    let max_runs = config
+        .services
+        .get(&job.service)
+        .map_or(0, |service| service.max_runs);
+
+    let eta = JobEta::new(job, ahead, active, max_runs, median);
+    Ok(match job.status {
+        Status::Running => eta.running_for(job.status_age(pool).await?),
+        Status::Queued => {
+            let now = SystemTime::now();
+            let opens = config
+                .services
+                .get(&job.service)
+                .and_then(|service| service.windows.as_ref()?.next_open(now));
+            match opens.and_then(|t| t.duration_since(now).ok()) {
+                Some(delay) => eta.delayed_by(delay),
+                None => eta,
+            }
+        }
+        _ => eta,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::models::user_dao::User;
+
+    use super::*;
+    use crate::config::loader::{
+        AlertConfig, BackupConfig, Config, DownloadLinkConfig, ObjectStoreConfig, Secret, Service,
+        Subscription, Tenant,
+    };
+    use crate::datasource::db::init_db;
+    use crate::models::capabilities_dto::Capabilities;
+    use crate::models::job_event_dao::{JobEvent, SERVER_ACTOR};
+    use crate::models::payload_dao::Payload;
+    use crate::models::webhook_dao::DeliveryState;
+    use crate::models::{job_dao::Job, job_dto::create_jobs_table};
+    use crate::services::result_store::RESULT_KEY_HEADER;
+    use crate::utils::io::CHECKSUM_HEADER;
+    use std::{path::Path, time::Duration};
+    use tempfile::TempDir;
+    use tokio::time::sleep;
+
+    #[tokio::test]
+    async fn test_cleaner_invalid_path() {
+        let pool = SqlitePool::connect(":memory:")
+            .await
+            .unwrap_or_else(|e| panic!("Database connection failed: {e}"));
+        create_jobs_table(&pool).await.unwrap();
+        for id in [0, 1] {
+            User::add_if_missing(id, &pool).await.unwrap();
+        }
+
+        let mut config = Config::new().unwrap();
+        config.data_path = "/nonexistent/path/does/not/exist".to_string();
+
+        // Should not panic — cleaner logs the error and returns
+        cleaner(pool, config, Client::default()).await;
+    }
+
+    #[tokio::test]
+    async fn test_cleaner_dir_not_in_db() {
+        let pool = SqlitePool::connect(":memory:")
+            .await
+            .unwrap_or_else(|e| panic!("Database connection failed: {e}"));
+        create_jobs_table(&pool).await.unwrap();
+        for id in [0, 1] {
+            User::add_if_missing(id, &pool).await.unwrap();
+        }
+
+        let tempdir = TempDir::new().unwrap();
+        let orphan_dir = tempdir.path().join("orphan_job");
+        fs::create_dir_all(&orphan_dir).unwrap();
+
+        let mut config = Config::new().unwrap();
+        config.data_path = tempdir.path().to_str().unwrap().to_string();
+        config.max_age = Duration::from_nanos(1);
+
+        sleep(Duration::from_millis(1)).await;
+
+        cleaner(pool, config, Client::default()).await;
+
+        // Directory is still there — retrieve_by_loc failed so nothing was removed
+        assert!(orphan_dir.exists());
+    }
+
+    #[tokio::test]
+    async fn test_sender_success() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = SqlitePool::connect(":memory:")
+            .await
+            .unwrap_or_else(|e| panic!("Database connection failed: {e}"));
+        create_jobs_table(&pool).await.unwrap();
+        for id in [0, 1] {
+            User::add_if_missing(id, &pool).await.unwrap();
+        }
+
+        let mut server = mockito::Server::new_async().await;
+
+        // Build a Payload response that Client::upload expects
+        let mut mock_payload = Payload::new();
+        mock_payload.set_id(42);
+        mock_payload.set_status(crate::models::status_dto::Status::Prepared);
+        let mock_body = serde_json::to_string(&mock_payload).unwrap();
+
+        let mock = server
+            .mock("POST", "/submit")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(mock_body)
+            .create_async()
+            .await;
+
+        let mut config = Config::new().unwrap();
+        config.services.insert(
+            "test".to_string(),
+            Service {
+                name: "test".to_string(),
+                upload_url: format!("{}/submit", server.url()),
+                download_url: format!("{}/retrieve", server.url()),
+                terminate_url: format!("{}/terminate", server.url()),
+                runs_per_user: 5,
+                max_runs: 1,
+                ..Default::default()
+            },
+        );
+
+        let mut job = Job::new(tempdir.path().to_str().unwrap());
+        job.set_service("test".to_string());
+        fs::create_dir_all(&job.loc).unwrap();
+        job.add_to_db(&pool).await.unwrap();
+        job.update_status(Status::Queued, &pool).await.unwrap();
+        let job_id = job.id;
+
+        let tally = sender(pool.clone(), config, Client::default()).await;
+
+        mock.assert_async().await;
+        assert_eq!(
+            tally,
+            TaskTally {
+                processed: 1,
+                errors: 0
+            }
+        );
+
+        let tempdir2 = TempDir::new().unwrap();
+        let mut updated = Job::new(tempdir2.path().to_str().unwrap());
+        updated.retrieve_id(job_id, &pool).await.unwrap();
+        assert_eq!(updated.status, Status::Submitted);
+        assert_eq!(updated.dest_id, 42);
+    }
+
+    #[tokio::test]
+    async fn test_sender_retries_transient_failures() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = SqlitePool::connect(":memory:")
+            .await
+            .unwrap_or_else(|e| panic!("Database connection failed: {e}"));
+        create_jobs_table(&pool).await.unwrap();
+        User::add_if_missing(0, &pool).await.unwrap();
+
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/submit")
+            .with_status(503)
+            .with_body("Service Unavailable")
+            .expect(2)
+            .create_async()
+            .await;
+
+        let mut config = Config::new().unwrap();
+        config.services.insert(
+            "test".to_string(),
+            Service {
+                name: "test".to_string(),
+                upload_url: format!("{}/submit", server.url()),
+                download_url: format!("{}/retrieve", server.url()),
+                runs_per_user: 5,
+                max_runs: 1,
+                ..Default::default()
+            },
+        );
+        config.submit_retry = SubmitRetryConfig {
+            max_attempts: 2,
+            backoff: Duration::from_secs(3600),
+        };
+
+        let mut job = Job::new(tempdir.path().to_str().unwrap());
+        job.set_service("test".to_string());
+        fs::create_dir_all(&job.loc).unwrap();
+        job.add_to_db(&pool).await.unwrap();
+        job.update_status(Status::Queued, &pool).await.unwrap();
+
+        // Queued again, and left alone until the backoff passed
+        for _ in 0..2 {
+            sender(pool.clone(), config.clone(), Client::default()).await;
+        }
+        job.retrieve_id(job.id, &pool).await.unwrap();
+        assert_eq!(job.status, Status::Queued);
+        assert_eq!(job.retry_count, 1);
+
+        sqlx::query("UPDATE jobs SET retry_at = NULL")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sender(pool.clone(), config, Client::default()).await;
+        mock.assert_async().await;
+        job.retrieve_id(job.id, &pool).await.unwrap();
+        assert_eq!(job.status, Status::Failed);
+
+        let events: Vec<_> = JobEvent::history(job.id, &pool)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|c| c.event)
+            .collect();
+        let last = events.last().unwrap();
+        assert_eq!(last.actor, SENDER_ACTOR);
+        assert!(
+            last.reason
+                .as_deref()
+                .unwrap()
+                .starts_with("upload failed 2 times")
+        );
+        let error = job.last_error.unwrap();
+        assert_eq!(
+            (error.source.as_str(), error.variant.as_str()),
+            (SENDER_ACTOR, "rejected")
+        );
+        assert_eq!(error.http_status, Some(503));
+        assert!(error.body.unwrap().contains("Service Unavailable"));
+    }
+
+    #[test]
+    fn test_submit_retry_in() {
+        let config = SubmitRetryConfig {
+            max_attempts: 4,
+            backoff: Duration::from_secs(10),
+        };
+        for _ in 0..20 {
+            let wait = submit_retry_in(&config, 3).unwrap();
+            assert!(wait >= Duration::from_secs(20) && wait <= Duration::from_secs(40));
+        }
+        let wait = submit_retry_in(&config, 1).unwrap();
+        assert!(wait >= Duration::from_secs(5) && wait <= Duration::from_secs(10));
+        assert_eq!(submit_retry_in(&config, 4), None);
+
+        let config = SubmitRetryConfig {
+            max_attempts: 100,
+            ..config
+        };
+        assert!(submit_retry_in(&config, 50).unwrap() <= MAX_SUBMIT_BACKOFF);
+    }
+
+    #[tokio::test]
+    async fn test_transfer_timeout() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = SqlitePool::connect(":memory:")
+            .await
+            .unwrap_or_else(|e| panic!("Database connection failed: {e}"));
+        create_jobs_table(&pool).await.unwrap();
+        User::add_if_missing(0, &pool).await.unwrap();
+
+        // Accepts the connections and never answers
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                held.push(stream);
+            }
+        });
+
+        let mut config = Config::new().unwrap();
+        config.services.insert(
+            "test".to_string(),
+            Service {
+                name: "test".to_string(),
+                upload_url: format!("{url}/submit"),
+                download_url: format!("{url}/retrieve"),
+                runs_per_user: 5,
+                max_runs: 1,
+                transfer_timeout: Some(Duration::from_millis(200)),
+                ..Default::default()
+            },
+        );
+
+        let mut job = Job::new(tempdir.path().to_str().unwrap());
+        job.set_service("test".to_string());
+        fs::create_dir_all(&job.loc).unwrap();
+        fs::write(job.loc.join("run.sh"), "#!/bin/bash\n").unwrap();
+        job.add_to_db(&pool).await.unwrap();
+        job.update_status(Status::Queued, &pool).await.unwrap();
+
+        let started = std::time::Instant::now();
+        sender(pool.clone(), config.clone(), Client::default()).await;
+        assert!(started.elapsed() < Duration::from_secs(5));
+        job.retrieve_id(job.id, &pool).await.unwrap();
+        assert_eq!(job.status, Status::Queued);
+
+        job.update_status(Status::Submitted, &pool).await.unwrap();
+        job.update_dest_id(7, &pool).await.unwrap();
+        getter(pool.clone(), config, Client::default()).await;
+        job.retrieve_id(job.id, &pool).await.unwrap();
+        assert_eq!(job.status, Status::Submitted);
+        assert!(!job.loc.join("output.zip").exists());
+    }
+
+    #[tokio::test]
+    async fn test_prober() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = SqlitePool::connect(":memory:")
+            .await
+            .unwrap_or_else(|e| panic!("Database connection failed: {e}"));
+        create_jobs_table(&pool).await.unwrap();
+        User::add_if_missing(0, &pool).await.unwrap();
+
+        let mut server = mockito::Server::new_async().await;
+        let up = server
+            .mock("GET", "/up/health")
+            .with_status(200)
+            .expect(2)
+            .create_async()
+            .await;
+        let down = server
+            .mock("GET", "/down/health")
+            .with_status(404)
+            .expect(2)
+            .create_async()
+            .await;
+        let submit = server
+            .mock("POST", "/down/submit")
+            .expect(0)
+            .create_async()
+            .await;
+
+        let mut config = Config::new().unwrap();
+        config.probes.down_after = 2;
+        for name in ["up", "down"] {
+            config.services.insert(
+                name.to_string(),
+                Service {
+                    name: name.to_string(),
+                    upload_url: format!("{}/{name}/submit", server.url()),
+                    runs_per_user: 5,
+                    max_runs: 5,
+                    ..Default::default()
+                },
+            );
+        }
+
+        let tally = prober(pool.clone(), config.clone(), Client::default()).await;
+        assert_eq!(
+            tally,
+            TaskTally {
+                processed: 2,
+                errors: 1
+            }
+        );
+        let down_after = config.probes.down_after;
+        assert!(
+            ServiceProbe::down(&pool, down_after)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        prober(pool.clone(), config.clone(), Client::default()).await;
+        up.assert_async().await;
+        down.assert_async().await;
+        let services = ServiceProbe::down(&pool, down_after).await.unwrap();
+        assert_eq!(services.into_iter().collect::<Vec<_>>(), vec!["down"]);
+
+        // The sender leaves the jobs of the service down queued
+        let mut job = Job::new(tempdir.path().to_str().unwrap());
+        job.set_service("down".to_string());
+        fs::create_dir_all(&job.loc).unwrap();
+        job.add_to_db(&pool).await.unwrap();
+        job.update_status(Status::Queued, &pool).await.unwrap();
+
+        let tally = sender(pool.clone(), config, Client::default()).await;
+        assert_eq!(tally, TaskTally::default());
+        submit.assert_async().await;
+        let mut updated = Job::new("");
+        updated.retrieve_id(job.id, &pool).await.unwrap();
+        assert_eq!(updated.status, Status::Queued);
+    }
+
+    #[tokio::test]
+    async fn test_prober_client_versions() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = SqlitePool::connect(":memory:")
+            .await
+            .unwrap_or_else(|e| panic!("Database connection failed: {e}"));
+        create_jobs_table(&pool).await.unwrap();
+        User::add_if_missing(0, &pool).await.unwrap();
+
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/old/health")
+            .with_body(r#"{"status":"ok","database":"ok","version":"2.0.0"}"#)
+            .create_async()
+            .await;
+        // Older clients answer without a version
+        server
+            .mock("GET", "/legacy/health")
+            .with_body(r#"{"status":"ok","database":"ok"}"#)
+            .create_async()
+            .await;
+        let submit = server
+            .mock("POST", mockito::Matcher::Regex("/submit$".to_string()))
+            .expect(0)
+            .create_async()
+            .await;
+
+        let mut config = Config::new().unwrap();
+        config.client_versions.min = Some(semver::Version::new(2, 1, 0));
+        for name in ["old", "legacy"] {
+            config.services.insert(
+                name.to_string(),
+                Service {
+                    name: name.to_string(),
+                    upload_url: format!("{}/{name}/submit", server.url()),
+                    runs_per_user: 5,
+                    max_runs: 5,
+                    ..Default::default()
+                },
+            );
+        }
+
+        let tally = prober(pool.clone(), config.clone(), Client::default()).await;
+        assert_eq!(tally.errors, 0);
+        let versions = ServiceProbe::versions(&pool).await.unwrap();
+        assert_eq!(versions["old"].as_deref(), Some("2.0.0"));
+        assert_eq!(versions["legacy"], None);
+
+        // Both are up, yet below the minimum version
+        for service in ["old", "legacy"] {
+            let mut job = Job::new(tempdir.path().to_str().unwrap());
+            job.set_service(service.to_string());
+            fs::create_dir_all(&job.loc).unwrap();
+            job.add_to_db(&pool).await.unwrap();
+            job.update_status(Status::Queued, &pool).await.unwrap();
+        }
+        let tally = sender(pool.clone(), config, Client::default()).await;
+        assert_eq!(tally, TaskTally::default());
+        submit.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_sender_outside_window() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = SqlitePool::connect(":memory:")
+            .await
+            .unwrap_or_else(|e| panic!("Database connection failed: {e}"));
+        create_jobs_table(&pool).await.unwrap();
+        User::add_if_missing(0, &pool).await.unwrap();
+
+        let mut server = mockito::Server::new_async().await;
+        let submit = server
+            .mock("POST", "/submit")
+            .expect(0)
+            .create_async()
+            .await;
+        // Only open the day after tomorrow
+        let today = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            / 86400;
+        let day = ["thu", "fri", "sat", "sun", "mon", "tue", "wed"][(today as usize + 2) % 7];
+        let mut config = Config::new().unwrap();
+        config.services.insert(
+            "hpc".to_string(),
+            Service {
+                name: "hpc".to_string(),
+                upload_url: format!("{}/submit", server.url()),
+                runs_per_user: 5,
+                max_runs: 5,
+                windows: Some(day.parse().unwrap()),
+                ..Default::default()
+            },
+        );
+
+        let mut job = Job::new(tempdir.path().to_str().unwrap());
+        job.set_service("hpc".to_string());
+        fs::create_dir_all(&job.loc).unwrap();
+        job.add_to_db(&pool).await.unwrap();
+        job.update_status(Status::Queued, &pool).await.unwrap();
+
+        let tally = sender(pool.clone(), config, Client::default()).await;
+        assert_eq!(tally, TaskTally::default());
+        submit.assert_async().await;
+        let mut updated = Job::new("");
+        updated.retrieve_id(job.id, &pool).await.unwrap();
+        assert_eq!(updated.status, Status::Queued);
+    }
+
+    #[tokio::test]
+    async fn test_sender_skips_full_client() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = SqlitePool::connect(":memory:")
+            .await
+            .unwrap_or_else(|e| panic!("Database connection failed: {e}"));
+        create_jobs_table(&pool).await.unwrap();
+        User::add_if_missing(0, &pool).await.unwrap();
+
+        let mut server = mockito::Server::new_async().await;
+        let load = server
+            .mock("GET", "/load")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"cpu": 3.0, "disk_free": 5}"#)
+            .create_async()
+            .await;
+        let submit = server
+            .mock("POST", "/submit")
+            .expect(0)
+            .create_async()
+            .await;
+
+        let mut config = Config::new().unwrap();
+        config.services.insert(
+            "test".to_string(),
+            Service {
+                name: "test".to_string(),
+                upload_url: format!("{}/submit", server.url()),
+                runs_per_user: 5,
+                max_runs: 1,
+                ..Default::default()
+            },
+        );
+
+        let mut job = Job::new(tempdir.path().to_str().unwrap());
+        job.set_service("test".to_string());
+        fs::create_dir_all(&job.loc).unwrap();
+        fs::write(job.loc.join("input.pdb"), b"ATOM ATOM ATOM").unwrap();
+        job.add_to_db(&pool).await.unwrap();
+        job.update_status(Status::Queued, &pool).await.unwrap();
+
+        sender(pool.clone(), config, Client::default()).await;
+
+        load.assert_async().await;
+        submit.assert_async().await;
+        let mut updated = Job::new(tempdir.path().to_str().unwrap());
+        updated.retrieve_id(job.id, &pool).await.unwrap();
+        assert_eq!(updated.status, Status::Queued);
+    }
+
+    #[tokio::test]
+    async fn test_register_holds_back_incompatible_jobs() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = SqlitePool::connect(":memory:")
+            .await
+            .unwrap_or_else(|e| panic!("Database connection failed: {e}"));
+        create_jobs_table(&pool).await.unwrap();
+        User::add_if_missing(0, &pool).await.unwrap();
+
+        // The client takes uploads of 5 bytes at most
+        let mut caps = Capabilities::detect(&Config::default());
+        caps.max_upload_size = 5;
+        let mut server = mockito::Server::new_async().await;
+        let capabilities = server
+            .mock("GET", "/capabilities")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(serde_json::to_string(&caps).unwrap())
+            .create_async()
+            .await;
+        let submit = server
+            .mock("POST", "/submit")
+            .expect(0)
+            .create_async()
+            .await;
+
+        let mut config = Config::new().unwrap();
+        for name in ["test", "unreachable"] {
+            config.services.insert(
+                name.to_string(),
+                Service {
+                    name: name.to_string(),
+                    upload_url: match name {
+                        "test" => format!("{}/submit", server.url()),
+                        _ => "http://127.0.0.1:1/submit".to_string(),
+                    },
+                    runs_per_user: 5,
+                    max_runs: 1,
+                    ..Default::default()
+                },
+            );
+        }
+
+        register(&mut config, &Client::default()).await;
+        capabilities.assert_async().await;
+        assert_eq!(config.services["test"].capabilities, Some(caps));
+        assert_eq!(config.services["unreachable"].capabilities, None);
+
+        let mut job = Job::new(tempdir.path().to_str().unwrap());
+        job.set_service("test".to_string());
+        fs::create_dir_all(&job.loc).unwrap();
+        fs::write(job.loc.join("input.pdb"), b"ATOM ATOM ATOM").unwrap();
+        job.add_to_db(&pool).await.unwrap();
+        job.update_status(Status::Queued, &pool).await.unwrap();
+
+        sender(pool.clone(), config, Client::default()).await;
+
+        submit.assert_async().await;
+        let mut updated = Job::new(tempdir.path().to_str().unwrap());
+        updated.retrieve_id(job.id, &pool).await.unwrap();
+        assert_eq!(updated.status, Status::Queued);
+    }
+
+    #[tokio::test]
+    async fn test_getter_extracts_results() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = SqlitePool::connect(":memory:")
+            .await
+            .unwrap_or_else(|e| panic!("Database connection failed: {e}"));
+        create_jobs_table(&pool).await.unwrap();
+        User::add_if_missing(0, &pool).await.unwrap();
+
+        let src = tempdir.path().join("results");
+        fs::create_dir_all(&src).unwrap();
+        fs::write(src.join("model.pdb"), b"ATOM").unwrap();
+        let archive = crate::utils::io::zip_directory_to_bytes(&src).unwrap();
+
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/retrieve/42")
+            .with_status(200)
+            .with_header("content-type", "application/zip")
+            .with_body(archive)
+            .expect(2)
+            .create_async()
+            .await;
+
+        let mut config = Config::new().unwrap();
+        for (name, extract_results) in [("plain", false), ("unpacked", true)] {
+            config.services.insert(
+                name.to_string(),
+                Service {
+                    name: name.to_string(),
+                    download_url: format!("{}/retrieve", server.url()),
+                    extract_results,
+                    ..Default::default()
+                },
+            );
+        }
+
+        let mut jobs = Vec::new();
+        for name in ["plain", "unpacked"] {
+            let mut job = Job::new(tempdir.path().to_str().unwrap());
+            job.set_service(name.to_string());
+            if name == "plain" {
+                job.metadata.callback_url = Some("https://example.com/hook".to_string());
+            }
+            fs::create_dir_all(&job.loc).unwrap();
+            job.add_to_db(&pool).await.unwrap();
+            job.update_dest_id(42, &pool).await.unwrap();
+            job.update_status(Status::Submitted, &pool).await.unwrap();
+            jobs.push(job);
+        }
+
+        getter(pool.clone(), config, Client::default()).await;
+
+        mock.assert_async().await;
+        for job in &jobs {
+            let mut stored = Job::new("");
+            stored.retrieve_id(job.id, &pool).await.unwrap();
+            assert_eq!(stored.status, Status::Completed);
+            assert!(job.loc.join("output.zip").exists());
+        }
+        let mut stored = Job::new("");
+        stored.retrieve_id(jobs[0].id, &pool).await.unwrap();
+        assert_eq!(stored.file_count, Some(1));
+        assert!(stored.archive_size.is_some_and(|s| s > 0));
+        assert!(!jobs[0].loc.join("model.pdb").exists());
+        assert_eq!(fs::read(jobs[1].loc.join("model.pdb")).unwrap(), b"ATOM");
+
+        // Only the job with a callback URL gets a delivery queued
+        let deliveries = WebhookDelivery::list_due(&pool).await.unwrap();
+        assert_eq!(deliveries.len(), 1);
+        assert_eq!(deliveries[0].job_id, jobs[0].id);
+        assert_eq!(deliveries[0].event, "job.completed");
+    }
+
+    #[tokio::test]
+    async fn test_notifier() {
+        let pool = SqlitePool::connect(":memory:")
+            .await
+            .unwrap_or_else(|e| panic!("Database connection failed: {e}"));
+        create_jobs_table(&pool).await.unwrap();
+        User::add_if_missing(0, &pool).await.unwrap();
+
+        let mut server = mockito::Server::new_async().await;
+        let ok = server
+            .mock("POST", "/ok")
+            .match_header(
+                webhook::SIGNATURE_HEADER,
+                mockito::Matcher::Regex("^sha256=".into()),
+            )
+            .with_status(200)
+            .create_async()
+            .await;
+        let _down = server
+            .mock("POST", "/down")
+            .with_status(500)
+            .create_async()
+            .await;
+
+        let mut config = Config::new().unwrap();
+        config.webhook.secret = Some(Secret::new("hmac-key"));
+        config.webhook.max_attempts = 2;
+        config.webhook.backoff = Duration::ZERO;
+
+        let mut jobs = Vec::new();
+        for path in ["ok", "down"] {
+            let mut job = Job::new("");
+            job.metadata.callback_url = Some(format!("{}/{path}", server.url()));
+            job.add_to_db(&pool).await.unwrap();
+            job.update_status(Status::Completed, &pool).await.unwrap();
+            webhook::enqueue(&job, &HashMap::new(), &pool)
+                .await
+                .unwrap();
+            jobs.push(job);
+        }
+
+        // The failing one is retried once more, then given up
+        for _ in 0..3 {
+            notifier(pool.clone(), config.clone(), Client::default()).await;
+        }
+        ok.assert_async().await;
+
+        let delivered = WebhookDelivery::list_for_job(jobs[0].id, &pool)
+            .await
+            .unwrap();
+        assert_eq!(delivered[0].state, DeliveryState::Delivered);
+        assert_eq!(delivered[0].attempts, 1);
+
+        let failed = WebhookDelivery::list_for_job(jobs[1].id, &pool)
+            .await
+            .unwrap();
+        assert_eq!(failed[0].state, DeliveryState::Failed);
+        assert_eq!(failed[0].attempts, 2);
+        assert_eq!(failed[0].last_status, Some(500));
+    }
+
+    #[tokio::test]
+    async fn test_tracked() {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        create_jobs_table(&pool).await.unwrap();
+
+        let config = Config::new().unwrap();
+        tracked(
+            "sender",
+            &pool,
+            sender(pool.clone(), config, Client::default()),
+        )
+        .await;
+
+        let stats = TaskRun::stats(&pool).await.unwrap();
+        let run = &stats["sender"].last_run;
+        assert!(run.finished_at.is_some());
+        assert_eq!((run.processed, run.errors), (0, 0));
+
+        // The round still runs when it can't be recorded
+        sqlx::query("DROP TABLE task_runs")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let ran = std::sync::atomic::AtomicBool::new(false);
+        tracked("sender", &pool, async {
+            ran.store(true, std::sync::atomic::Ordering::SeqCst);
+            TaskTally::default()
+        })
+        .await;
+        assert!(ran.into_inner());
+    }
+
+    #[tokio::test]
+    async fn test_storage_check() {
+        let mut server = mockito::Server::new_async().await;
+        let raised = server
+            .mock("POST", "/ops")
+            .match_header(webhook::EVENT_HEADER, "alert.data_path")
+            .match_body(mockito::Matcher::Regex("No such file".into()))
+            .with_status(204)
+            .expect(1)
+            .create_async()
+            .await;
+        let resolved = server
+            .mock("POST", "/ops")
+            .match_header(webhook::EVENT_HEADER, "alert.data_path.resolved")
+            .with_status(204)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let dir = tempfile::tempdir().unwrap();
+        let data_path = dir.path().join("data");
+        let mut config = Config::new().unwrap();
+        config.data_path = data_path.to_str().unwrap().to_string();
+        config.alerts = Some(AlertConfig {
+            webhook_url: Some(format!("{}/ops", server.url())),
+            queue_depth: None,
+            queue_depth_for: Duration::ZERO,
+            oldest_queued: None,
+        });
+
+        // Reported once while DATA_PATH stays unwritable
+        let storage = StorageHealth::default();
+        for _ in 0..2 {
+            storage_check(config.clone(), Client::default(), storage.clone()).await;
+        }
+        assert!(storage.problem().is_some());
+        raised.assert_async().await;
+
+        std::fs::create_dir(&data_path).unwrap();
+        storage_check(config, Client::default(), storage.clone()).await;
+        assert_eq!(storage.problem(), None);
+        resolved.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_monitor() {
+        let pool = SqlitePool::connect(":memory:")
+            .await
+            .unwrap_or_else(|e| panic!("Database connection failed: {e}"));
+        create_jobs_table(&pool).await.unwrap();
+        User::add_if_missing(0, &pool).await.unwrap();
+
+        let mut server = mockito::Server::new_async().await;
+        let raised = server
+            .mock("POST", "/ops")
+            .match_header(webhook::EVENT_HEADER, "alert.oldest_queued")
+            .match_header(
+                webhook::SIGNATURE_HEADER,
+                mockito::Matcher::Regex("^sha256=".into()),
+            )
+            .match_body(mockito::Matcher::PartialJsonString(
+                r#"{"threshold_seconds": 3600}"#.to_string(),
+            ))
+            .with_status(204)
+            .expect(1)
+            .create_async()
+            .await;
+        let resolved = server
+            .mock("POST", "/ops")
+            .match_header(webhook::EVENT_HEADER, "alert.oldest_queued.resolved")
+            .with_status(204)
+            .expect(1)
+            .create_async()
+            .await;
+        let slack = server
+            .mock("POST", "/slack")
+            .match_body(mockito::Matcher::Regex("alert.oldest_queued".into()))
+            .with_status(200)
+            .expect(2)
+            .create_async()
+            .await;
+        let unsubscribed = server
+            .mock("POST", "/jobs-only")
+            .expect(0)
+            .create_async()
+            .await;
+
+        let mut config = Config::new().unwrap();
+        config.webhook.secret = Some(Secret::new("hmac-key"));
+        for (name, target, events) in [("ops", "slack", "alert.*"), ("dev", "jobs-only", "job.*")] {
+            config.subscriptions.insert(
+                name.to_string(),
+                Subscription {
+                    name: name.to_string(),
+                    channel: Channel::Slack,
+                    target: format!("{}/{target}", server.url()),
+                    events: vec![events.to_string()],
+                },
+            );
+        }
+        config.alerts = Some(AlertConfig {
+            webhook_url: Some(format!("{}/ops", server.url())),
+            queue_depth: Some(5),
+            queue_depth_for: Duration::ZERO,
+            oldest_queued: Some(Duration::from_secs(3600)),
+        });
+
+        let mut job = Job::new("");
+        job.add_to_db(&pool).await.unwrap();
+        job.update_status(Status::Queued, &pool).await.unwrap();
+        let state = AlertState::default();
+        monitor(
+            pool.clone(),
+            config.clone(),
+            Client::default(),
+            state.clone(),
+        )
+        .await;
+
+        sqlx::query("UPDATE jobs SET status_since = datetime('now', '-2 hours')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        // Reported once while the job keeps waiting
+        for _ in 0..2 {
+            monitor(
+                pool.clone(),
+                config.clone(),
+                Client::default(),
+                state.clone(),
+            )
+            .await;
+        }
+        raised.assert_async().await;
+
+        job.update_status(Status::Submitted, &pool).await.unwrap();
+        monitor(
+            pool.clone(),
+            config.clone(),
+            Client::default(),
+            state.clone(),
+        )
+        .await;
+        resolved.assert_async().await;
+        slack.assert_async().await;
+        unsubscribed.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_getter_polls_status_first() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = SqlitePool::connect(":memory:")
+            .await
+            .unwrap_or_else(|e| panic!("Database connection failed: {e}"));
+        create_jobs_table(&pool).await.unwrap();
+        User::add_if_missing(0, &pool).await.unwrap();
+
+        let mut server = mockito::Server::new_async().await;
+        let status = |id: u32, status: &str| {
+            format!(
+                r#"{{"id":{id},"status":"{status}","progress":{{"elapsed_secs":5,"killed":false}}}}"#
+            )
+        };
+        let running = server
+            .mock("GET", "/status/1")
+            .with_status(200)
+            .with_body(status(1, "Running"))
+            .create_async()
+            .await;
+        let completed = server
+            .mock("GET", "/status/2")
+            .with_status(200)
+            .with_body(status(2, "Completed").replacen(
+                "}}",
+                r#"},"summary":{"status":"converged","metrics":{"rmsd":1.2}}}"#,
+                1,
+            ))
+            .create_async()
+            .await;
+        let not_retrieved = server
+            .mock("GET", "/retrieve/1")
+            .expect(0)
+            .create_async()
+            .await;
+        let src = tempdir.path().join("results");
+        fs::create_dir_all(&src).unwrap();
+        fs::write(src.join("model.pdb"), b"ATOM").unwrap();
+        // The summary reported with the status is the one kept
+        fs::write(src.join("result.json"), r#"{"status":"archived"}"#).unwrap();
+        let retrieved = server
+            .mock("GET", "/retrieve/2")
+            .with_status(200)
+            .with_header("content-type", "application/zip")
+            .with_body(crate::utils::io::zip_directory_to_bytes(&src).unwrap())
+            .create_async()
+            .await;
+
+        let mut config = Config::new().unwrap();
+        config.services.insert(
+            "test".to_string(),
+            Service {
+                name: "test".to_string(),
+                download_url: format!("{}/retrieve", server.url()),
+                ..Default::default()
+            },
+        );
+
+        let mut jobs = Vec::new();
+        for dest_id in [1, 2] {
+            let mut job = Job::new(tempdir.path().to_str().unwrap());
+            job.set_service("test".to_string());
+            fs::create_dir_all(&job.loc).unwrap();
+            job.add_to_db(&pool).await.unwrap();
+            job.update_dest_id(dest_id, &pool).await.unwrap();
+            job.update_status(Status::Submitted, &pool).await.unwrap();
+            jobs.push(job);
+        }
+
+        getter(pool.clone(), config, Client::default()).await;
+
+        for mock in [running, completed, not_retrieved, retrieved] {
+            mock.assert_async().await;
+        }
+        let mut stored = Job::new("");
+        stored.retrieve_id(jobs[0].id, &pool).await.unwrap();
+        assert_eq!(stored.status, Status::Running);
+        assert_eq!(stored.result_summary, None);
+        stored.retrieve_id(jobs[1].id, &pool).await.unwrap();
+        assert_eq!(stored.status, Status::Completed);
+        assert!(jobs[1].loc.join("output.zip").exists());
+        let summary = stored.result_summary.unwrap();
+        assert_eq!(summary.status.as_deref(), Some("converged"));
+        assert_eq!(summary.metrics["rmsd"], serde_json::json!(1.2));
+    }
+
+    #[tokio::test]
+    async fn test_getter_keeps_failure_of_the_client() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        create_jobs_table(&pool).await.unwrap();
+        User::add_if_missing(0, &pool).await.unwrap();
+
+        let mut server = mockito::Server::new_async().await;
+        let failed = server
+            .mock("GET", "/status/1")
+            .with_status(200)
+            .with_body(
+                r#"{"id":1,"status":"Invalid","progress":{"elapsed_secs":5,"killed":false},"failure":"run.sh is missing"}"#,
+            )
+            .create_async()
+            .await;
+
+        let mut config = Config::new().unwrap();
+        config.services.insert(
+            "test".to_string(),
+            Service {
+                name: "test".to_string(),
+                download_url: format!("{}/retrieve", server.url()),
+                ..Default::default()
+            },
+        );
+        let mut job = Job::new(tempdir.path().to_str().unwrap());
+        job.set_service("test".to_string());
+        job.add_to_db(&pool).await.unwrap();
+        job.update_dest_id(1, &pool).await.unwrap();
+        job.update_status(Status::Submitted, &pool).await.unwrap();
+
+        getter(pool.clone(), config, Client::default()).await;
+        failed.assert_async().await;
+        job.retrieve_id(job.id, &pool).await.unwrap();
+        assert_eq!(job.status, Status::Invalid);
+        assert_eq!(
+            job.last_error,
+            Some(JobError::new(RUNNER_SOURCE, "invalid", "run.sh is missing"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_getter_reads_archived_summary() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = SqlitePool::connect(":memory:")
+            .await
+            .unwrap_or_else(|e| panic!("Database connection failed: {e}"));
+        create_jobs_table(&pool).await.unwrap();
+        User::add_if_missing(0, &pool).await.unwrap();
+
+        // A client without `/status`, the summary is only in the archive
+        let src = tempdir.path().join("results");
+        fs::create_dir_all(&src).unwrap();
+        fs::write(src.join("result.json"), r#"{"warnings":["low pLDDT"]}"#).unwrap();
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/retrieve/42")
+            .with_status(200)
+            .with_header("content-type", "application/zip")
+            .with_body(crate::utils::io::zip_directory_to_bytes(&src).unwrap())
+            .create_async()
+            .await;
+
+        let mut config = Config::new().unwrap();
+        config.services.insert(
+            "test".to_string(),
+            Service {
+                name: "test".to_string(),
+                download_url: format!("{}/retrieve", server.url()),
+                ..Default::default()
+            },
+        );
+        let mut job = Job::new(tempdir.path().to_str().unwrap());
+        job.set_service("test".to_string());
+        fs::create_dir_all(&job.loc).unwrap();
+        job.add_to_db(&pool).await.unwrap();
+        job.update_dest_id(42, &pool).await.unwrap();
+        job.update_status(Status::Submitted, &pool).await.unwrap();
+
+        getter(pool.clone(), config, Client::default()).await;
+
+        mock.assert_async().await;
+        let mut stored = Job::new("");
+        stored.retrieve_id(job.id, &pool).await.unwrap();
+        assert_eq!(stored.status, Status::Completed);
+        assert_eq!(
+            stored.result_summary.unwrap().warnings,
+            vec!["low pLDDT".to_string()]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_getter_records_shared_results() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = SqlitePool::connect(":memory:")
+            .await
+            .unwrap_or_else(|e| panic!("Database connection failed: {e}"));
+        create_jobs_table(&pool).await.unwrap();
+        User::add_if_missing(0, &pool).await.unwrap();
+
+        let mut job = Job::new(tempdir.path().to_str().unwrap());
+        job.set_service("shared".to_string());
+        fs::create_dir_all(&job.loc).unwrap();
+        job.add_to_db(&pool).await.unwrap();
+        job.update_dest_id(42, &pool).await.unwrap();
+        job.update_status(Status::Submitted, &pool).await.unwrap();
+
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/retrieve/42")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_header(RESULT_KEY_HEADER, &job.object_key())
+            .with_header(CHECKSUM_HEADER, "abc123")
+            .with_body(r#"{"id":42,"status":"Completed","loc":""}"#)
+            .create_async()
+            .await;
+
+        let mut config = Config::new().unwrap();
+        config.services.insert(
+            "shared".to_string(),
+            Service {
+                name: "shared".to_string(),
+                download_url: format!("{}/retrieve", server.url()),
+                shared_results: true,
+                ..Default::default()
+            },
+        );
+
+        getter(pool.clone(), config, Client::default()).await;
+
+        mock.assert_async().await;
+        let mut stored = Job::new("");
+        stored.retrieve_id(job.id, &pool).await.unwrap();
+        assert_eq!(stored.status, Status::Completed);
+        assert_eq!(stored.remote_key, Some(job.object_key()));
+        assert_eq!(stored.checksum.as_deref(), Some("abc123"));
+        assert!(!job.loc.join("output.zip").exists());
+    }
+
+    #[tokio::test]
+    async fn test_getter_retries_cancellation() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        create_jobs_table(&pool).await.unwrap();
+        User::add_if_missing(0, &pool).await.unwrap();
+
+        let mut server = mockito::Server::new_async().await;
+        // Neither the status nor the results are asked for a cancelled job
+        let polled = server
+            .mock("GET", mockito::Matcher::Any)
+            .expect(0)
+            .create_async()
+            .await;
+        let down = server
+            .mock("POST", "/terminate/42")
+            .with_status(503)
+            .create_async()
+            .await;
+
+        let mut config = Config::new().unwrap();
+        config.download_retry = DownloadRetryConfig {
+            max_attempts: 1,
+            backoff: Duration::ZERO,
+        };
+        config.services.insert(
+            "svc".to_string(),
+            Service {
+                name: "svc".to_string(),
+                download_url: format!("{}/download", server.url()),
+                terminate_url: format!("{}/terminate", server.url()),
+                ..Default::default()
+            },
+        );
+        let mut job = Job::new(tempdir.path().to_str().unwrap());
+        job.set_service("svc".to_string());
+        job.add_to_db(&pool).await.unwrap();
+        job.update_dest_id(42, &pool).await.unwrap();
+        job.update_status(Status::Submitted, &pool).await.unwrap();
+
+        // The first attempt fails, the job is left as it was
+        let result = terminate_job(&mut job, pool.clone(), config.clone(), Client::default()).await;
+        assert!(result.is_err());
+        let mut stored = Job::new("");
+        stored.retrieve_id(job.id, &pool).await.unwrap();
+        assert_eq!(stored.status, Status::Submitted);
+        assert!(stored.cancel_requested);
+
+        // The getter asks again, past `max_attempts` too
+        for _ in 0..2 {
+            let tally = getter(pool.clone(), config.clone(), Client::default()).await;
+            assert_eq!(tally.errors, 1);
+        }
+        stored.retrieve_id(job.id, &pool).await.unwrap();
+        assert_eq!(stored.status, Status::Submitted);
+        down.remove_async().await;
+
+        // Once it goes through the job is left alone
+        let killed = server
+            .mock("POST", "/terminate/42")
+            .with_status(200)
+            .expect(1)
+            .create_async()
+            .await;
+        for _ in 0..2 {
+            getter(pool.clone(), config.clone(), Client::default()).await;
+        }
+        stored.retrieve_id(job.id, &pool).await.unwrap();
+        assert_eq!(stored.status, Status::Killed);
+        killed.assert_async().await;
+        polled.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_cancel_job() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        create_jobs_table(&pool).await.unwrap();
+        User::add_if_missing(0, &pool).await.unwrap();
+
+        let mut server = mockito::Server::new_async().await;
+        let down = server
+            .mock("POST", "/cancel/42")
+            .with_status(503)
+            .expect(1)
+            .create_async()
+            .await;
+        let mut config = Config::new().unwrap();
+        config.services.insert(
+            "svc".to_string(),
+            Service {
+                name: "svc".to_string(),
+                upload_url: format!("{}/submit", server.url()),
+                terminate_url: format!("{}/kill", server.url()),
+                ..Default::default()
+            },
+        );
+        let mut job = Job::new(tempdir.path().to_str().unwrap());
+        job.set_service("svc".to_string());
+        job.add_to_db(&pool).await.unwrap();
+        job.update_dest_id(42, &pool).await.unwrap();
+        job.update_status(Status::Running, &pool).await.unwrap();
+        let recorded = JobEvent::history(job.id, &pool).await.unwrap().len();
+
+        // The client is down, the job is left as it was for the getter, with
+        // nothing added to its history
+        let result = cancel_job(&mut job, pool.clone(), config.clone(), Client::default()).await;
+        assert!(result.is_err());
+        let mut stored = Job::new("");
+        stored.retrieve_id(job.id, &pool).await.unwrap();
+        assert_eq!(stored.status, Status::Running);
+        assert!(stored.cancel_requested && stored.discard_requested);
+        assert_eq!(
+            JobEvent::history(job.id, &pool).await.unwrap().len(),
+            recorded
+        );
+        down.assert_async().await;
+        down.remove_async().await;
+
+        let cancelled = server
+            .mock("POST", "/cancel/42")
+            .with_status(204)
+            .expect(1)
+            .create_async()
+            .await;
+        let tally = getter(pool.clone(), config, Client::default()).await;
+        assert_eq!(tally.errors, 0);
+        stored.retrieve_id(job.id, &pool).await.unwrap();
+        assert_eq!(stored.status, Status::Cancelled);
+        cancelled.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_cancel_job_on_older_client() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        create_jobs_table(&pool).await.unwrap();
+        User::add_if_missing(0, &pool).await.unwrap();
+
+        // No `/cancel` route, the payload is killed instead
+        let mut server = mockito::Server::new_async().await;
+        let unknown = server
+            .mock("POST", "/cancel/42")
+            .with_status(404)
+            .expect(1)
+            .create_async()
+            .await;
+        let killed = server
+            .mock("POST", "/kill/42")
+            .with_status(200)
+            .expect(1)
+            .create_async()
+            .await;
+        let mut config = Config::new().unwrap();
+        config.services.insert(
+            "svc".to_string(),
+            Service {
+                name: "svc".to_string(),
+                upload_url: format!("{}/submit", server.url()),
+                terminate_url: format!("{}/kill", server.url()),
+                ..Default::default()
+            },
+        );
+        let mut job = Job::new(tempdir.path().to_str().unwrap());
+        job.set_service("svc".to_string());
+        job.add_to_db(&pool).await.unwrap();
+        job.update_dest_id(42, &pool).await.unwrap();
+        job.update_status(Status::Submitted, &pool).await.unwrap();
+
+        cancel_job(&mut job, pool.clone(), config, Client::default())
+            .await
+            .unwrap();
+        let mut stored = Job::new("");
+        stored.retrieve_id(job.id, &pool).await.unwrap();
+        assert_eq!(stored.status, Status::Cancelled);
+        unknown.assert_async().await;
+        killed.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_getter_cancels_lost_payload() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        create_jobs_table(&pool).await.unwrap();
+        User::add_if_missing(0, &pool).await.unwrap();
+
+        let mut server = mockito::Server::new_async().await;
+        let missing = server
+            .mock("POST", "/terminate/42")
+            .with_status(404)
+            .expect(1)
+            .create_async()
+            .await;
+        let mut config = Config::new().unwrap();
+        config.services.insert(
+            "svc".to_string(),
+            Service {
+                name: "svc".to_string(),
+                terminate_url: format!("{}/terminate", server.url()),
+                ..Default::default()
+            },
+        );
+        let mut job = Job::new(tempdir.path().to_str().unwrap());
+        job.set_service("svc".to_string());
+        job.add_to_db(&pool).await.unwrap();
+        job.update_dest_id(42, &pool).await.unwrap();
+        job.update_status(Status::Running, &pool).await.unwrap();
+        job.request_cancel(&pool).await.unwrap();
+
+        let tally = getter(pool.clone(), config, Client::default()).await;
+        assert_eq!(tally.errors, 0);
+        let mut stored = Job::new("");
+        stored.retrieve_id(job.id, &pool).await.unwrap();
+        assert_eq!(stored.status, Status::Killed);
+        missing.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_getter_retries_failed_downloads() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = SqlitePool::connect(":memory:")
+            .await
+            .unwrap_or_else(|e| panic!("Database connection failed: {e}"));
+        create_jobs_table(&pool).await.unwrap();
+        User::add_if_missing(0, &pool).await.unwrap();
+
+        // A client that is down for now, and one that lost the payload
+        let mut server = mockito::Server::new_async().await;
+        let unavailable = server
+            .mock("GET", "/flaky/42")
+            .with_status(503)
+            // The HTTP client retries a 5xx by itself first
+            .expect_at_least(2)
+            .create_async()
+            .await;
+        let missing = server
+            .mock("GET", "/gone/42")
+            .with_status(404)
+            .expect(2)
+            .create_async()
+            .await;
+
+        let mut config = Config::new().unwrap();
+        config.download_retry = DownloadRetryConfig {
+            max_attempts: 2,
+            backoff: Duration::ZERO,
+        };
+        let mut ids = Vec::new();
+        for name in ["flaky", "gone"] {
+            config.services.insert(
+                name.to_string(),
+                Service {
+                    name: name.to_string(),
+                    download_url: format!("{}/{name}", server.url()),
+                    ..Default::default()
+                },
+            );
+            let mut job = Job::new(tempdir.path().to_str().unwrap());
+            job.set_service(name.to_string());
+            fs::create_dir_all(&job.loc).unwrap();
+            job.add_to_db(&pool).await.unwrap();
+            job.update_dest_id(42, &pool).await.unwrap();
+            job.update_status(Status::Submitted, &pool).await.unwrap();
+            ids.push(job.id);
+        }
+
+        // The first failure is retried
+        let tally = getter(pool.clone(), config.clone(), Client::default()).await;
+        assert_eq!(
+            tally,
+            TaskTally {
+                processed: 2,
+                errors: 2
+            }
+        );
+        for id in &ids {
+            let mut stored = Job::new("");
+            stored.retrieve_id(*id, &pool).await.unwrap();
+            assert_eq!(stored.status, Status::Submitted);
+            assert_eq!(stored.download_attempts, 1);
+        }
+
+        // The second one gives the jobs up
+        getter(pool.clone(), config, Client::default()).await;
+        unavailable.assert_async().await;
+        missing.assert_async().await;
+        let mut stored = Job::new("");
+        stored.retrieve_id(ids[0], &pool).await.unwrap();
+        assert_eq!(stored.status, Status::Unknown);
+        assert_eq!(stored.download_attempts, 2);
+        assert_eq!(stored.last_error.as_ref().unwrap().http_status, Some(503));
+        stored.retrieve_id(ids[1], &pool).await.unwrap();
+        assert_eq!(stored.status, Status::Failed);
+        let error = stored.last_error.unwrap();
+        assert_eq!(error.source, GETTER_ACTOR);
+        assert_eq!(error.variant, "rejected");
+        assert_eq!(error.http_status, Some(404));
+
+        let events: Vec<(u32, String, String)> = sqlx::query_as(
+            "SELECT job_id, new_status, actor FROM job_events WHERE actor != ? ORDER BY job_id, id",
+        )
+        .bind(SERVER_ACTOR)
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        assert_eq!(
+            events,
+            vec![
+                (ids[0], "unknown".to_string(), GETTER_ACTOR.to_string()),
+                (ids[1], "failed".to_string(), GETTER_ACTOR.to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_download_retry_in() {
+        let config = DownloadRetryConfig {
+            max_attempts: 4,
+            backoff: Duration::from_secs(10),
+        };
+        assert_eq!(download_retry_in(&config, 1), Some(Duration::from_secs(10)));
+        assert_eq!(download_retry_in(&config, 3), Some(Duration::from_secs(40)));
+        assert_eq!(download_retry_in(&config, 4), None);
+    }
+
+    #[tokio::test]
+    async fn test_sender() {
+        let pool = SqlitePool::connect(":memory:")
+            .await
+            .unwrap_or_else(|e| panic!("Database connection failed: {e}"));
+        let mut config = Config::new().unwrap();
+        config.services.insert(
+            "A".to_string(),
+            Service {
+                name: "A".to_string(),
+                upload_url: "http://example.com/upload_a".to_string(),
+                download_url: "http://example.com/download_a".to_string(),
+                terminate_url: "http://example.com/terminate_a".to_string(),
+                runs_per_user: 5,
+                max_runs: 1,
+                ..Default::default()
+            },
+        );
+
+        create_jobs_table(&pool).await.unwrap();
+        for id in [0, 1] {
+            User::add_if_missing(id, &pool).await.unwrap();
+        }
+
+        // add a job
+        let tempdir = TempDir::new().unwrap();
+        let mut job = Job::new(tempdir.path().to_str().unwrap());
+        job.set_service("A".to_string());
+        job.add_to_db(&pool).await.unwrap();
+        job.update_status(Status::Queued, &pool).await.unwrap();
+        let id = job.id;
+
+        // Given up on the first failure
+        config.submit_retry.max_attempts = 1;
+        sender(pool.clone(), config, Client::default()).await;
+
+        let tempdir = TempDir::new().unwrap();
+        let mut _job = Job::new(tempdir.path().to_str().unwrap());
+        _job.retrieve_id(id, &pool).await.unwrap();
+
+        // Since nothing is configured, it will fail
+        //  the only thing we need to test here is if
+        //  the status is being updated
+        assert_eq!(_job.status, Status::Failed);
+
+        // TODO: Add mock the `send` function to test the match arm
+    }
+
+    #[tokio::test]
+    async fn test_cleaner() {
+        let pool = SqlitePool::connect(":memory:")
+            .await
+            .unwrap_or_else(|e| panic!("Database connection failed: {e}"));
+        let mut config = Config::new().unwrap();
+
+        create_jobs_table(&pool).await.unwrap();
+        for id in [0, 1] {
+            User::add_if_missing(id, &pool).await.unwrap();
+        }
+
+        // add a job
+        let tempdir = TempDir::new().unwrap();
+        let mut job = Job::new(tempdir.path().to_str().unwrap());
+        fs::create_dir_all(&job.loc).unwrap();
+
+        job.add_to_db(&pool).await.unwrap();
+
+        config.max_age = Duration::from_nanos(1);
+        config.data_path = tempdir.path().to_str().unwrap().to_string();
+
+        // Sleep to allow the file to age
+        // NOTE: This is simpler than editing the mtime
+        sleep(Duration::from_nanos(1)).await;
+
+        assert!(Path::new(&job.loc).exists());
+
+        let tally = cleaner(pool.clone(), config, Client::default()).await;
+
+        assert!(!Path::new(&job.loc).exists());
+        assert_eq!(
+            tally,
+            TaskTally {
+                processed: 1,
+                errors: 0
+            }
+        );
+
+        let mut _job = Job::new("");
+        let _ = _job.retrieve_id(job.id, &pool).await;
+
+        assert_eq!(_job.status, Status::Cleaned);
+
+        // The history tells where the files went
+        let events: Vec<_> = JobEvent::history(job.id, &pool)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|c| c.event)
+            .collect();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].new_status, Status::Cleaned);
+        assert_eq!(events[0].actor, CLEANER_ACTOR);
+        assert_eq!(events[0].reason.as_deref(), Some("files deleted"));
+    }
+
+    #[tokio::test]
+    async fn test_cleaner_pre_clean_hook() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let pool = SqlitePool::connect(":memory:")
+            .await
+            .unwrap_or_else(|e| panic!("Database connection failed: {e}"));
+        create_jobs_table(&pool).await.unwrap();
+        User::add_if_missing(0, &pool).await.unwrap();
+
+        let tempdir = TempDir::new().unwrap();
+        let mut job = Job::new(tempdir.path().to_str().unwrap());
+        fs::create_dir_all(&job.loc).unwrap();
+        job.add_to_db(&pool).await.unwrap();
+
+        // The files stay as long as the site still needs them
+        let hooks = TempDir::new().unwrap();
+        let keep = hooks.path().join("keep");
+        let hook = hooks.path().join("pre-clean");
+        fs::write(
+            &hook,
+            format!(
+                "#!/bin/sh\ntest -e {} && {{ echo 'not billed yet' >&2; exit 1; }}\nexit 0\n",
+                keep.display()
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&hook, fs::Permissions::from_mode(0o755)).unwrap();
+        fs::write(&keep, "").unwrap();
+
+        let mut config = Config::new().unwrap();
+        config.max_age = Duration::from_nanos(1);
+        config.data_path = tempdir.path().to_str().unwrap().to_string();
+        config.hooks.pre_clean = Some(hook);
+        sleep(Duration::from_nanos(1)).await;
+
+        let tally = cleaner(pool.clone(), config.clone(), Client::default()).await;
+        assert_eq!(tally.errors, 1);
+        assert!(job.loc.exists());
+        let mut kept = Job::new("");
+        kept.retrieve_id(job.id, &pool).await.unwrap();
+        assert_ne!(kept.status, Status::Cleaned);
+
+        fs::remove_file(&keep).unwrap();
+        cleaner(pool.clone(), config, Client::default()).await;
+        assert!(!job.loc.exists());
+        kept.retrieve_id(job.id, &pool).await.unwrap();
+        assert_eq!(kept.status, Status::Cleaned);
+    }
+
+    #[tokio::test]
+    async fn test_cleaner_notifies_expiry() {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        create_jobs_table(&pool).await.unwrap();
+        User::add_if_missing(0, &pool).await.unwrap();
+
+        let tempdir = TempDir::new().unwrap();
+        let mut config = Config::new().unwrap();
+        config.data_path = tempdir.path().to_str().unwrap().to_string();
+        config.max_age = Duration::from_secs(3600);
+        config.expiry_notice = Some(Duration::from_secs(1800));
+        config.download_links = Some(DownloadLinkConfig {
+            secret: Secret::new("link-key"),
+            max_ttl: Duration::from_secs(600),
+            base_url: Some("https://orchestrator.example.com".to_string()),
+        });
+
+        // One job halfway through its notice, one not there yet
+        let mut ids = Vec::new();
+        for age in [2400, 600] {
+            let mut job = Job::new(tempdir.path().to_str().unwrap());
+            fs::create_dir_all(&job.loc).unwrap();
+            let mtime = SystemTime::now() - Duration::from_secs(age);
+            fs::File::open(&job.loc)
+                .unwrap()
+                .set_modified(mtime)
+                .unwrap();
+            job.metadata.callback_url = Some("https://example.com/hook".to_string());
+            job.add_to_db(&pool).await.unwrap();
+            job.update_status(Status::Completed, &pool).await.unwrap();
+            ids.push(job.id);
+        }
+
+        // Sent once, however many rounds see the job
+        for _ in 0..2 {
+            let tally = cleaner(pool.clone(), config.clone(), Client::default()).await;
+            assert_eq!(tally.processed, 0);
+        }
+        let deliveries = WebhookDelivery::list_for_job(ids[0], &pool).await.unwrap();
+        assert_eq!(deliveries.len(), 1);
+        assert_eq!(deliveries[0].event, webhook::EXPIRING_EVENT);
+        let payload: serde_json::Value = serde_json::from_str(&deliveries[0].payload).unwrap();
+        let expires_at = payload["expires_at"].as_u64().unwrap();
+        let left = expires_at - download_link::now();
+        assert!((1100..=1200).contains(&left), "{left}");
+        // The link is capped at `max_ttl`
+        let url = payload["download_url"].as_str().unwrap();
+        assert!(url.starts_with(&format!(
+            "https://orchestrator.example.com/shared/{}?expires=",
+            ids[0]
+        )));
+        let mut stored = Job::new("");
+        stored.retrieve_id(ids[0], &pool).await.unwrap();
+        assert!(stored.expiry_notified);
+
+        assert!(
+            WebhookDelivery::list_for_job(ids[1], &pool)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cleaner_nested_layout() {
+        let pool = SqlitePool::connect(":memory:")
+            .await
+            .unwrap_or_else(|e| panic!("Database connection failed: {e}"));
+        create_jobs_table(&pool).await.unwrap();
+        User::add_if_missing(0, &pool).await.unwrap();
+
+        let tempdir = TempDir::new().unwrap();
+        let mut config = Config::new().unwrap();
+        config.data_path = tempdir.path().to_str().unwrap().to_string();
+        config.max_age = Duration::from_nanos(1);
+        config.job_dir_depth = 2;
+
+        // `<tenant>/<service>/<job>`, out of reach of a flat listing
+        let nested = tempdir.path().join("lab").join("haddock");
+        let mut job = Job::new(nested.to_str().unwrap());
+        fs::create_dir_all(&job.loc).unwrap();
+        job.add_to_db(&pool).await.unwrap();
+
+        let mut held = Job::new(nested.to_str().unwrap());
+        fs::create_dir_all(&held.loc).unwrap();
+        held.add_to_db(&pool).await.unwrap();
+        held.update_status(Status::Held, &pool).await.unwrap();
+
+        sleep(Duration::from_millis(1)).await;
+
+        cleaner(pool.clone(), config, Client::default()).await;
+
+        assert!(!job.loc.exists());
+        job.retrieve_id(job.id, &pool).await.unwrap();
+        assert_eq!(job.status, Status::Cleaned);
+        assert!(held.loc.exists());
+    }
+
+    #[tokio::test]
+    async fn test_cleaner_tenant_max_age() {
+        let pool = SqlitePool::connect(":memory:")
+            .await
+            .unwrap_or_else(|e| panic!("Database connection failed: {e}"));
+        let mut config = Config::new().unwrap();
+
+        create_jobs_table(&pool).await.unwrap();
+        for id in [0, 1] {
+            User::add_if_missing(id, &pool).await.unwrap();
+        }
+
+        let tempdir = TempDir::new().unwrap();
+        let data_path = tempdir.path().to_str().unwrap();
+
+        // `short` keeps jobs for a moment, `long` inherits the global retention
+        config.data_path = data_path.to_string();
+        config.max_age = Duration::from_secs(3600);
+        for (name, max_age) in [("short", Some(Duration::from_nanos(1))), ("long", None)] {
+            config.tenants.insert(
+                name.to_string(),
+                Tenant {
+                    name: name.to_string(),
+                    max_age,
+                    ..Default::default()
+                },
+            );
+        }
+
+        let mut short_job = Job::new(data_path);
+        short_job
+            .set_tenant("short".to_string(), data_path)
+            .unwrap();
+        fs::create_dir_all(&short_job.loc).unwrap();
+        short_job.add_to_db(&pool).await.unwrap();
+
+        let mut long_job = Job::new(data_path);
+        long_job.set_tenant("long".to_string(), data_path).unwrap();
+        fs::create_dir_all(&long_job.loc).unwrap();
+        long_job.add_to_db(&pool).await.unwrap();
+
+        sleep(Duration::from_millis(1)).await;
+
+        cleaner(pool.clone(), config, Client::default()).await;
+
+        assert!(!short_job.loc.exists());
+        assert!(long_job.loc.exists());
+    }
+
+    async fn setup_tiering_db() -> SqlitePool {
+        let pool = SqlitePool::connect(":memory:")
+            .await
+            .unwrap_or_else(|e| panic!("Database connection failed: {e}"));
+        create_jobs_table(&pool).await.unwrap();
+        User::add_if_missing(0, &pool).await.unwrap();
+        pool
+    }
+
+    fn object_store(url: String, tier_after: Duration) -> ObjectStoreConfig {
+        ObjectStoreConfig {
+            url,
+            auth: Default::default(),
+            tier_after,
+            s3: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tierer() {
+        let pool = setup_tiering_db().await;
+        let tempdir = TempDir::new().unwrap();
+
+        let mut old = Job::new(tempdir.path().to_str().unwrap());
+        fs::create_dir_all(&old.loc).unwrap();
+        fs::write(old.loc.join("output.zip"), b"abc").unwrap();
+        fs::write(old.loc.join("model.pdb"), b"ATOM").unwrap();
+        old.add_to_db(&pool).await.unwrap();
+        old.update_status(Status::Completed, &pool).await.unwrap();
+
+        let mut running = Job::new(tempdir.path().to_str().unwrap());
+        fs::create_dir_all(&running.loc).unwrap();
+        fs::write(running.loc.join("output.zip"), b"abc").unwrap();
+        running.add_to_db(&pool).await.unwrap();
+        running.update_status(Status::Running, &pool).await.unwrap();
+
+        let mut server = mockito::Server::new_async().await;
+        let put = server
+            .mock("PUT", format!("/{}", old.object_key()).as_str())
+            .match_body("abc")
+            .with_status(200)
+            .expect(1)
+            .create_async()
+            .await;
+
+        // Not old enough yet
+        let mut config = Config::new().unwrap();
+        config.object_store = Some(object_store(server.url(), Duration::from_secs(3600)));
+        tierer(pool.clone(), config.clone(), Client::default()).await;
+        assert!(old.loc.join("output.zip").exists());
+
+        sleep(Duration::from_millis(1)).await;
+        config.object_store = Some(object_store(server.url(), Duration::from_nanos(1)));
+        tierer(pool.clone(), config, Client::default()).await;
+        put.assert_async().await;
+
+        let mut tiered = Job::new("");
+        tiered.retrieve_id(old.id, &pool).await.unwrap();
+        assert_eq!(tiered.remote_key, Some(old.object_key()));
+        assert_eq!(
+            tiered.checksum.as_deref(),
+            Some("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+        );
+        assert!(old.loc.exists());
+        assert_eq!(fs::read_dir(&old.loc).unwrap().count(), 0);
+        assert!(running.loc.join("output.zip").exists());
+    }
+
+    #[tokio::test]
+    async fn test_packer() {
+        let pool = setup_tiering_db().await;
+        let tempdir = TempDir::new().unwrap();
+
+        let mut jobs = Vec::new();
+        for status in [Status::Completed, Status::Running] {
+            let mut job = Job::new(tempdir.path().to_str().unwrap());
+            fs::create_dir_all(&job.loc).unwrap();
+            fs::write(job.loc.join("input.pdb"), b"ATOM").unwrap();
+            job.add_to_db(&pool).await.unwrap();
+            job.update_status(status, &pool).await.unwrap();
+            jobs.push(job);
+        }
+
+        // Disabled unless configured
+        let mut config = Config::new().unwrap();
+        config.pack_after = None;
+        packer(pool.clone(), config.clone()).await;
+        assert!(!jobs[0].is_packed());
+
+        // Not idle long enough
+        config.pack_after = Some(Duration::from_secs(3600));
+        packer(pool.clone(), config.clone()).await;
+        assert!(!jobs[0].is_packed());
+
+        sleep(Duration::from_millis(1)).await;
+        config.pack_after = Some(Duration::from_nanos(1));
+        packer(pool.clone(), config).await;
+        assert!(jobs[0].is_packed());
+        assert!(!jobs[0].loc.join("input.pdb").exists());
+        // Running jobs are left alone
+        assert!(!jobs[1].is_packed());
+    }
+
+    #[tokio::test]
+    async fn test_tierer_upload_failure_keeps_local() {
+        let pool = setup_tiering_db().await;
+        let tempdir = TempDir::new().unwrap();
+
+        let mut job = Job::new(tempdir.path().to_str().unwrap());
+        fs::create_dir_all(&job.loc).unwrap();
+        fs::write(job.loc.join("output.zip"), b"abc").unwrap();
+        job.add_to_db(&pool).await.unwrap();
+        job.update_status(Status::Completed, &pool).await.unwrap();
+
+        let mut server = mockito::Server::new_async().await;
+        let _put = server
+            .mock("PUT", mockito::Matcher::Any)
+            .with_status(500)
+            .create_async()
+            .await;
+
+        let mut config = Config::new().unwrap();
+        config.object_store = Some(object_store(server.url(), Duration::from_nanos(1)));
+        sleep(Duration::from_millis(1)).await;
+        tierer(pool.clone(), config, Client::default()).await;
+
+        let mut retrieved = Job::new("");
+        retrieved.retrieve_id(job.id, &pool).await.unwrap();
+        assert_eq!(retrieved.remote_key, None);
+        assert!(job.loc.join("output.zip").exists());
+    }
+
+    #[tokio::test]
+    async fn test_backup() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = init_db(tempdir.path().join("db.sqlite").to_str().unwrap()).await;
+        let dir = tempdir.path().join("backups");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("orchestrator-1000.sqlite"), b"").unwrap();
+
+        let mut server = mockito::Server::new_async().await;
+        let put = server
+            .mock(
+                "PUT",
+                mockito::Matcher::Regex(r"^/backups/orchestrator-\d+\.sqlite$".to_string()),
+            )
+            .with_status(200)
+            .create_async()
+            .await;
+        let delete = server
+            .mock("DELETE", "/backups/orchestrator-1000.sqlite")
+            .with_status(204)
+            .create_async()
+            .await;
 
         let mut config = Config::new().unwrap();
-        config.data_path = "/nonexistent/path/does/not/exist".to_string();
+        config.object_store = Some(object_store(server.url(), Duration::from_secs(3600)));
+        config.backup = Some(BackupConfig {
+            dir: dir.clone(),
+            interval: Duration::from_nanos(1),
+            keep: 1,
+        });
+        backup(pool.clone(), config.clone(), Client::default()).await;
 
-        // Should not panic — cleaner logs the error and returns
-        cleaner(pool, config).await;
+        put.assert_async().await;
+        delete.assert_async().await;
+        let snapshots = backup::list(&dir).unwrap();
+        assert_eq!(snapshots.len(), 1);
+        assert_ne!(snapshots[0], dir.join("orchestrator-1000.sqlite"));
+
+        // The latest snapshot is recent enough, nothing is written
+        config.backup.as_mut().unwrap().interval = Duration::from_secs(3600);
+        backup(pool, config, Client::default()).await;
+        assert_eq!(backup::list(&dir).unwrap(), snapshots);
     }
 
     #[tokio::test]
-    async fn test_cleaner_dir_not_in_db() {
-        let pool = SqlitePool::connect(":memory:")
+    async fn test_cleaner_deletes_remote_object() {
+        let pool = setup_tiering_db().await;
+        let tempdir = TempDir::new().unwrap();
+
+        let mut job = Job::new(tempdir.path().to_str().unwrap());
+        fs::create_dir_all(&job.loc).unwrap();
+        job.add_to_db(&pool).await.unwrap();
+        job.update_remote_key(job.object_key(), &pool)
             .await
-            .unwrap_or_else(|e| panic!("Database connection failed: {e}"));
-        create_jobs_table(&pool).await.unwrap();
+            .unwrap();
 
-        let tempdir = TempDir::new().unwrap();
-        let orphan_dir = tempdir.path().join("orphan_job");
-        fs::create_dir_all(&orphan_dir).unwrap();
+        let mut server = mockito::Server::new_async().await;
+        let delete = server
+            .mock("DELETE", format!("/{}", job.object_key()).as_str())
+            .with_status(204)
+            .create_async()
+            .await;
 
         let mut config = Config::new().unwrap();
         config.data_path = tempdir.path().to_str().unwrap().to_string();
         config.max_age = Duration::from_nanos(1);
-
+        config.object_store = Some(object_store(server.url(), Duration::from_secs(3600)));
         sleep(Duration::from_millis(1)).await;
 
-        cleaner(pool, config).await;
+        cleaner(pool.clone(), config, Client::default()).await;
 
-        // Directory is still there — retrieve_by_loc failed so nothing was removed
-        assert!(orphan_dir.exists());
+        delete.assert_async().await;
+        assert!(!job.loc.exists());
+        let mut retrieved = Job::new("");
+        retrieved.retrieve_id(job.id, &pool).await.unwrap();
+        assert_eq!(retrieved.status, Status::Cleaned);
     }
 
     #[tokio::test]
-    async fn test_sender_success() {
+    async fn test_cleaner_archives() {
+        let pool = setup_tiering_db().await;
         let tempdir = TempDir::new().unwrap();
-        let pool = SqlitePool::connect(":memory:")
-            .await
-            .unwrap_or_else(|e| panic!("Database connection failed: {e}"));
-        create_jobs_table(&pool).await.unwrap();
-
-        let mut server = mockito::Server::new_async().await;
 
-        // Build a Payload response that Client::upload expects
-        let mut mock_payload = Payload::new();
-        mock_payload.set_id(42);
-        mock_payload.set_status(crate::models::status_dto::Status::Prepared);
-        let mock_body = serde_json::to_string(&mock_payload).unwrap();
+        let mut job = Job::new(tempdir.path().to_str().unwrap());
+        job.set_service("archived".to_string());
+        fs::create_dir_all(&job.loc).unwrap();
+        fs::write(job.loc.join("output.zip"), b"abc").unwrap();
+        job.add_to_db(&pool).await.unwrap();
+        job.update_status(Status::Completed, &pool).await.unwrap();
 
-        let mock = server
-            .mock("POST", "/submit")
-            .with_status(200)
-            .with_header("content-type", "application/json")
-            .with_body(mock_body)
+        let mut server = mockito::Server::new_async().await;
+        let put = server
+            .mock("PUT", format!("/{}", job.object_key()).as_str())
+            .with_status(500)
+            .create_async()
+            .await;
+        let delete = server
+            .mock("DELETE", mockito::Matcher::Any)
+            .expect(0)
             .create_async()
             .await;
 
         let mut config = Config::new().unwrap();
+        config.data_path = tempdir.path().to_str().unwrap().to_string();
+        config.max_age = Duration::from_nanos(1);
         config.services.insert(
-            "test".to_string(),
+            "archived".to_string(),
             Service {
-                name: "test".to_string(),
-                upload_url: format!("{}/submit", server.url()),
-                download_url: format!("{}/retrieve", server.url()),
-                terminate_url: format!("{}/terminate", server.url()),
-                runs_per_user: 5,
-                max_runs: 1,
+                name: "archived".to_string(),
+                cleanup: CleanupAction::Archive,
+                ..Default::default()
             },
         );
 
-        let mut job = Job::new(tempdir.path().to_str().unwrap());
-        job.set_service("test".to_string());
-        fs::create_dir_all(&job.loc).unwrap();
-        job.add_to_db(&pool).await.unwrap();
-        job.update_status(Status::Queued, &pool).await.unwrap();
-        let job_id = job.id;
+        // Without the object store the results are left in place
+        sleep(Duration::from_millis(1)).await;
+        let tally = cleaner(pool.clone(), config.clone(), Client::default()).await;
+        assert_eq!(tally.errors, 1);
+        assert!(job.loc.join("output.zip").exists());
 
-        sender(pool.clone(), config).await;
+        // So they are when the archive can't be stored
+        config.object_store = Some(object_store(server.url(), Duration::from_secs(3600)));
+        let tally = cleaner(pool.clone(), config.clone(), Client::default()).await;
+        assert_eq!(tally.errors, 1);
+        put.assert_async().await;
+        assert!(job.loc.join("output.zip").exists());
+        let mut retrieved = Job::new("");
+        retrieved.retrieve_id(job.id, &pool).await.unwrap();
+        assert_eq!(retrieved.status, Status::Completed);
 
-        mock.assert_async().await;
+        put.remove_async().await;
+        let put = server
+            .mock("PUT", format!("/{}", job.object_key()).as_str())
+            .with_status(200)
+            .create_async()
+            .await;
+        let tally = cleaner(pool.clone(), config, Client::default()).await;
+        assert_eq!(tally.errors, 0);
+        put.assert_async().await;
+        delete.assert_async().await;
+        assert!(!job.loc.exists());
 
-        let tempdir2 = TempDir::new().unwrap();
-        let mut updated = Job::new(tempdir2.path().to_str().unwrap());
-        updated.retrieve_id(job_id, &pool).await.unwrap();
-        assert_eq!(updated.status, Status::Submitted);
-        assert_eq!(updated.dest_id, 42);
+        let mut retrieved = Job::new("");
+        retrieved.retrieve_id(job.id, &pool).await.unwrap();
+        assert_eq!(retrieved.status, Status::Cleaned);
+        assert_eq!(retrieved.remote_key, Some(job.object_key()));
+        let events: Vec<_> = JobEvent::history(job.id, &pool)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|c| c.event)
+            .collect();
+        assert_eq!(
+            events.last().unwrap().reason,
+            Some(format!(
+                "results archived in the object store as {}",
+                job.object_key()
+            ))
+        );
     }
 
     #[tokio::test]
-    async fn test_sender() {
-        let pool = SqlitePool::connect(":memory:")
-            .await
-            .unwrap_or_else(|e| panic!("Database connection failed: {e}"));
+    async fn test_cleaner_compresses() {
+        let pool = setup_tiering_db().await;
+        let tempdir = TempDir::new().unwrap();
+
+        let mut job = Job::new(tempdir.path().to_str().unwrap());
+        job.set_service("compressed".to_string());
+        fs::create_dir_all(job.loc.join("run")).unwrap();
+        fs::write(job.loc.join("run").join("model.pdb"), b"ATOM").unwrap();
+        fs::write(job.loc.join("output.zip"), b"abc").unwrap();
+        job.add_to_db(&pool).await.unwrap();
+        job.update_status(Status::Completed, &pool).await.unwrap();
+
         let mut config = Config::new().unwrap();
+        config.data_path = tempdir.path().to_str().unwrap().to_string();
+        config.max_age = Duration::from_nanos(1);
         config.services.insert(
-            "A".to_string(),
+            "compressed".to_string(),
             Service {
-                name: "A".to_string(),
-                upload_url: "http://example.com/upload_a".to_string(),
-                download_url: "http://example.com/download_a".to_string(),
-                terminate_url: "http://example.com/terminate_a".to_string(),
-                runs_per_user: 5,
-                max_runs: 1,
+                name: "compressed".to_string(),
+                cleanup: CleanupAction::Compress,
+                ..Default::default()
             },
         );
+        sleep(Duration::from_millis(1)).await;
 
-        create_jobs_table(&pool).await.unwrap();
-
-        // add a job
-        let tempdir = TempDir::new().unwrap();
-        let mut job = Job::new(tempdir.path().to_str().unwrap());
-        job.set_service("A".to_string());
-        job.add_to_db(&pool).await.unwrap();
-        job.update_status(Status::Queued, &pool).await.unwrap();
-        let id = job.id;
-
-        sender(pool.clone(), config).await;
-
-        let tempdir = TempDir::new().unwrap();
-        let mut _job = Job::new(tempdir.path().to_str().unwrap());
-        _job.retrieve_id(id, &pool).await.unwrap();
-
-        // Since nothing is configured, it will fail
-        //  the only thing we need to test here is if
-        //  the status is being updated
-        assert_eq!(_job.status, Status::Failed);
+        let tally = cleaner(pool.clone(), config, Client::default()).await;
+        assert_eq!(tally.errors, 0);
 
-        // TODO: Add mock the `send` function to test the match arm
+        assert!(job.is_packed());
+        assert!(job.loc.join("output.zip").exists());
+        assert!(!job.loc.join("run").join("model.pdb").exists());
+        let mut retrieved = Job::new("");
+        retrieved.retrieve_id(job.id, &pool).await.unwrap();
+        assert_eq!(retrieved.status, Status::Cleaned);
+        let events: Vec<_> = JobEvent::history(job.id, &pool)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|c| c.event)
+            .collect();
+        assert_eq!(
+            events.last().unwrap().reason,
+            Some(format!(
+                "files compressed in place in {}",
+                job.loc.display()
+            ))
+        );
     }
 
     #[tokio::test]
-    async fn test_cleaner() {
+    async fn test_watchdog() {
+        let tempdir = TempDir::new().unwrap();
         let pool = SqlitePool::connect(":memory:")
             .await
             .unwrap_or_else(|e| panic!("Database connection failed: {e}"));
-        let mut config = Config::new().unwrap();
-
         create_jobs_table(&pool).await.unwrap();
+        User::add_if_missing(0, &pool).await.unwrap();
 
-        // add a job
-        let tempdir = TempDir::new().unwrap();
-        let mut job = Job::new(tempdir.path().to_str().unwrap());
-        fs::create_dir_all(&job.loc).unwrap();
-
-        job.add_to_db(&pool).await.unwrap();
-
-        config.max_age = Duration::from_nanos(1);
-        config.data_path = tempdir.path().to_str().unwrap().to_string();
-
-        // Sleep to allow the file to age
-        // NOTE: This is simpler than editing the mtime
-        sleep(Duration::from_nanos(1)).await;
-
-        assert!(Path::new(&job.loc).exists());
+        let mut server = mockito::Server::new_async().await;
+        let kill = server
+            .mock("POST", "/terminate/42")
+            .with_status(200)
+            .expect(2)
+            .create_async()
+            .await;
 
-        cleaner(pool.clone(), config).await;
+        let mut config = Config::new().unwrap();
+        let mut ids = Vec::new();
+        for (name, on_stale) in [
+            ("alert", StaleAction::Alert),
+            ("requeue", StaleAction::Requeue),
+            ("cancel", StaleAction::Cancel),
+            ("unwatched", StaleAction::Alert),
+        ] {
+            config.services.insert(
+                name.to_string(),
+                Service {
+                    name: name.to_string(),
+                    terminate_url: format!("{}/terminate", server.url()),
+                    stale_after: (name != "unwatched").then(|| Duration::from_secs(60)),
+                    on_stale,
+                    ..Default::default()
+                },
+            );
+            let mut job = Job::new(tempdir.path().to_str().unwrap());
+            job.set_service(name.to_string());
+            job.add_to_db(&pool).await.unwrap();
+            job.update_dest_id(42, &pool).await.unwrap();
+            job.update_status(Status::Submitted, &pool).await.unwrap();
+            ids.push(job.id);
+        }
+        sqlx::query("UPDATE jobs SET status_since = datetime('now', '-1 hour')")
+            .execute(&pool)
+            .await
+            .unwrap();
 
-        assert!(!Path::new(&job.loc).exists());
+        // A second round reports nothing new
+        watchdog(pool.clone(), config.clone(), Client::default()).await;
+        watchdog(pool.clone(), config, Client::default()).await;
 
-        let mut _job = Job::new("");
-        let _ = _job.retrieve_id(job.id, &pool).await;
+        kill.assert_async().await;
+        let mut statuses = Vec::new();
+        for id in &ids {
+            let mut stored = Job::new("");
+            stored.retrieve_id(*id, &pool).await.unwrap();
+            statuses.push(stored.status);
+        }
+        assert_eq!(
+            statuses,
+            vec![
+                Status::Submitted,
+                Status::Queued,
+                Status::Failed,
+                Status::Submitted
+            ]
+        );
 
-        assert_eq!(_job.status, Status::Cleaned);
+        let events: Vec<(u32, String, String)> = sqlx::query_as(
+            "SELECT job_id, new_status, actor FROM job_events WHERE actor != ? ORDER BY job_id, id",
+        )
+        .bind(SERVER_ACTOR)
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        assert_eq!(
+            events,
+            vec![
+                (ids[0], "submitted".to_string(), WATCHDOG_ACTOR.to_string()),
+                (ids[1], "queued".to_string(), WATCHDOG_ACTOR.to_string()),
+                (ids[2], "failed".to_string(), WATCHDOG_ACTOR.to_string()),
+            ]
+        );
     }
 
     #[tokio::test]
@@ -359,6 +3690,9 @@ mod test {
             .await
             .unwrap_or_else(|e| panic!("Database connection failed: {e}"));
         create_jobs_table(&pool).await.unwrap();
+        for id in [0, 1] {
+            User::add_if_missing(id, &pool).await.unwrap();
+        }
 
         let mut config = Config::new().unwrap();
         config.services.insert(
@@ -370,6 +3704,7 @@ mod test {
                 terminate_url: "http://example.com/terminate".to_string(),
                 runs_per_user: 5,
                 max_runs: 1,
+                ..Default::default()
             },
         );
 
@@ -394,7 +3729,7 @@ mod test {
             service.terminate_url = format!("{}/terminate", server.url());
         }
 
-        let result = terminate_job(job, pool.clone(), config).await;
+        let result = terminate_job(&mut job, pool.clone(), config, Client::default()).await;
         assert!(result.is_ok());
 
         // Verify the job status was updated to Killed
@@ -402,6 +3737,15 @@ mod test {
         updated_job.retrieve_id(job_id, &pool).await.unwrap();
         assert_eq!(updated_job.status, Status::Killed);
 
+        // The lock is not part of the history
+        let history = JobEvent::history(job_id, &pool).await.unwrap();
+        let last = &history.last().unwrap().event;
+        assert_eq!(
+            (last.old_status, last.new_status),
+            (Status::Running, Status::Killed)
+        );
+        assert!(history.iter().all(|c| c.event.new_status != Status::Locked));
+
         mock.assert_async().await;
     }
 
@@ -412,6 +3756,9 @@ mod test {
             .await
             .unwrap_or_else(|e| panic!("Database connection failed: {e}"));
         create_jobs_table(&pool).await.unwrap();
+        for id in [0, 1] {
+            User::add_if_missing(id, &pool).await.unwrap();
+        }
 
         let mut config = Config::new().unwrap();
         config.services.insert(
@@ -423,6 +3770,7 @@ mod test {
                 terminate_url: "http://example.com/terminate".to_string(),
                 runs_per_user: 5,
                 max_runs: 1,
+                ..Default::default()
             },
         );
 
@@ -448,7 +3796,7 @@ mod test {
             service.terminate_url = format!("{}/terminate", server.url());
         }
 
-        let result = terminate_job(job, pool.clone(), config).await;
+        let result = terminate_job(&mut job, pool.clone(), config, Client::default()).await;
         assert!(result.is_err());
 
         // Verify the job status was restored to original
@@ -458,4 +3806,333 @@ mod test {
 
         mock.assert_async().await;
     }
+
+    #[tokio::test]
+    async fn test_reconciler() {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        create_jobs_table(&pool).await.unwrap();
+        User::add_if_missing(1, &pool).await.unwrap();
+
+        let mut server = mockito::Server::new_async().await;
+        let listed = server
+            .mock("GET", "/payloads?older_than=600")
+            .with_header("content-type", "application/json")
+            .with_body("[1, 2, 3]")
+            .expect(1)
+            .create_async()
+            .await;
+        let kept = server
+            .mock("DELETE", "/payloads/1")
+            .expect(0)
+            .create_async()
+            .await;
+        let deleted = server
+            .mock(
+                "DELETE",
+                mockito::Matcher::Regex("^/payloads/[23]$".to_string()),
+            )
+            .with_status(204)
+            .expect(2)
+            .create_async()
+            .await;
+
+        // Two services run on the same client, it is asked once
+        let mut config = Config::default();
+        for name in ["a", "b"] {
+            config.services.insert(
+                name.to_string(),
+                Service {
+                    name: name.to_string(),
+                    upload_url: format!("{}/submit", server.url()),
+                    ..Default::default()
+                },
+            );
+        }
+
+        for (service, dest_id, status) in [("b", 1, Status::Running), ("a", 2, Status::Killed)] {
+            let mut job = Job::new("");
+            job.set_user_id(1);
+            job.set_service(service.to_string());
+            job.add_to_db(&pool).await.unwrap();
+            job.update_dest_id(dest_id, &pool).await.unwrap();
+            job.update_status(status, &pool).await.unwrap();
+        }
+
+        let count = reconciler(pool.clone(), config.clone(), Client::default()).await;
+        assert_eq!(count, 2);
+        listed.assert_async().await;
+        kept.assert_async().await;
+        deleted.assert_async().await;
+
+        config.reconcile_payloads = false;
+        assert_eq!(reconciler(pool, config, Client::default()).await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_reconcile() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        create_jobs_table(&pool).await.unwrap();
+        User::add_if_missing(0, &pool).await.unwrap();
+
+        let src = tempdir.path().join("results");
+        fs::create_dir_all(&src).unwrap();
+        fs::write(src.join("model.pdb"), b"ATOM").unwrap();
+        let archive = crate::utils::io::zip_directory_to_bytes(&src).unwrap();
+        let mut running = Payload::new();
+        running.set_status(Status::Running);
+
+        let mut server = mockito::Server::new_async().await;
+        let _completed = server
+            .mock("GET", "/retrieve/1")
+            .with_header("content-type", "application/zip")
+            .with_body(archive)
+            .create_async()
+            .await;
+        let _lost = server
+            .mock("GET", "/retrieve/2")
+            .with_status(404)
+            .create_async()
+            .await;
+        let _running = server
+            .mock("GET", "/retrieve/3")
+            .with_header("content-type", "application/json")
+            .with_body(serde_json::to_string(&running).unwrap())
+            .create_async()
+            .await;
+        let _broken = server
+            .mock("GET", "/retrieve/4")
+            .with_status(500)
+            .create_async()
+            .await;
+
+        let mut config = Config::default();
+        config.services.insert(
+            "test".to_string(),
+            Service {
+                name: "test".to_string(),
+                download_url: format!("{}/retrieve", server.url()),
+                ..Default::default()
+            },
+        );
+
+        let mut ids = Vec::new();
+        for (dest_id, status) in [
+            (1, Status::Submitted),
+            (2, Status::Running),
+            (3, Status::Running),
+            (4, Status::Submitted),
+        ] {
+            let mut job = Job::new(tempdir.path().to_str().unwrap());
+            job.set_service("test".to_string());
+            fs::create_dir_all(&job.loc).unwrap();
+            job.add_to_db(&pool).await.unwrap();
+            job.update_dest_id(dest_id, &pool).await.unwrap();
+            job.update_status(status, &pool).await.unwrap();
+            ids.push(job.id);
+        }
+
+        let report = reconcile(&pool, &config, &Client::default()).await.unwrap();
+        assert_eq!(
+            report,
+            Reconciliation {
+                checked: 4,
+                corrected: 2,
+                unreachable: 1,
+            }
+        );
+
+        let mut statuses = Vec::new();
+        for id in &ids {
+            let mut job = Job::new("");
+            job.retrieve_id(*id, &pool).await.unwrap();
+            statuses.push(job.status);
+        }
+        assert_eq!(
+            statuses,
+            vec![
+                Status::Completed,
+                Status::Failed,
+                Status::Running,
+                Status::Submitted
+            ]
+        );
+
+        let events: Vec<(u32, String, Option<String>, String)> = sqlx::query_as(
+            "SELECT job_id, new_status, reason, actor FROM job_events WHERE actor != ? ORDER BY job_id, id",
+        )
+        .bind(SERVER_ACTOR)
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        assert_eq!(
+            events,
+            vec![
+                (
+                    ids[0],
+                    "completed".to_string(),
+                    None,
+                    RECONCILE_ACTOR.to_string()
+                ),
+                (
+                    ids[1],
+                    "failed".to_string(),
+                    Some("payload not found on the client".to_string()),
+                    RECONCILE_ACTOR.to_string()
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_job_eta() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        create_jobs_table(&pool).await.unwrap();
+        User::add_if_missing(0, &pool).await.unwrap();
+
+        let mut server = mockito::Server::new_async().await;
+        let _stats = server
+            .mock("GET", "/stats")
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"test": {"service": "test", "runs": 3, "p50_secs": 60, "p95_secs": 90, "failure_rate": 0.0}}"#,
+            )
+            .create_async()
+            .await;
+
+        let mut config = Config::default();
+        for (name, upload_url) in [
+            ("test", format!("{}/upload", server.url())),
+            ("down", "http://127.0.0.1:1/upload".to_string()),
+        ] {
+            config.services.insert(
+                name.to_string(),
+                Service {
+                    name: name.to_string(),
+                    upload_url,
+                    max_runs: 1,
+                    ..Default::default()
+                },
+            );
+        }
+
+        let mut jobs = Vec::new();
+        for (service, status) in [
+            ("test", Status::Running),
+            ("test", Status::Queued),
+            ("test", Status::Queued),
+            ("down", Status::Queued),
+        ] {
+            let mut job = Job::new(tempdir.path().to_str().unwrap());
+            job.set_service(service.to_string());
+            job.add_to_db(&pool).await.unwrap();
+            job.update_status(status, &pool).await.unwrap();
+            jobs.push(job);
+        }
+        sqlx::query("UPDATE jobs SET status_since = datetime('now', '-20 seconds') WHERE id = ?")
+            .bind(jobs[0].id)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let client = Client::default();
+        let running = job_eta(&jobs[0], &pool, &config, &client).await.unwrap();
+        let run_secs = running.run_secs.unwrap();
+        assert!((38..=40).contains(&run_secs), "{run_secs}");
+
+        // Second in line behind the running job, on a single slot
+        let queued = job_eta(&jobs[2], &pool, &config, &client).await.unwrap();
+        assert_eq!(queued.queue_position, Some(2));
+        assert_eq!(queued.active, 1);
+        assert_eq!(queued.wait_secs, Some(120));
+        assert_eq!(queued.eta_secs, Some(180));
+
+        // The client can't be asked, only the queue position is known
+        let unknown = job_eta(&jobs[3], &pool, &config, &client).await.unwrap();
+        assert_eq!(unknown.queue_position, Some(1));
+        assert_eq!((unknown.median_run_secs, unknown.eta_secs), (None, None));
+    }
+
+    #[tokio::test]
+    async fn test_queue_snapshot() {
+        let tempdir = TempDir::new().unwrap();
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        create_jobs_table(&pool).await.unwrap();
+        User::add_if_missing(0, &pool).await.unwrap();
+
+        let mut server = mockito::Server::new_async().await;
+        let _load = server
+            .mock("GET", "/load")
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"cpu": 12.5, "disk_free": 1000}"#)
+            .create_async()
+            .await;
+
+        let mut config = Config::default();
+        for (name, upload_url) in [
+            ("test", format!("{}/upload", server.url())),
+            ("down", "http://127.0.0.1:1/upload".to_string()),
+        ] {
+            config.services.insert(
+                name.to_string(),
+                Service {
+                    name: name.to_string(),
+                    upload_url,
+                    max_runs: 2,
+                    runs_per_user: 1,
+                    ..Default::default()
+                },
+            );
+        }
+
+        let mut ids = Vec::new();
+        for status in [
+            Status::Queued,
+            Status::Queued,
+            Status::Running,
+            Status::Completed,
+        ] {
+            let mut job = Job::new(tempdir.path().to_str().unwrap());
+            job.set_service("test".to_string());
+            job.add_to_db(&pool).await.unwrap();
+            job.update_status(status, &pool).await.unwrap();
+            ids.push(job.id);
+        }
+
+        let snapshot = queue_snapshot(&pool, &config, &Client::default())
+            .await
+            .unwrap();
+        let mut listed: Vec<u32> = snapshot.jobs.iter().map(|j| j.id).collect();
+        listed.sort();
+        assert_eq!(listed, ids[..3]);
+        // The user already runs a job, its queued ones have to wait
+        assert!(snapshot.next.is_empty());
+        let throttled: Vec<_> = snapshot
+            .throttled
+            .iter()
+            .map(|t| (t.service.as_str(), t.active, t.runs_per_user))
+            .collect();
+        assert_eq!(throttled, [("test", 1, 1)]);
+
+        assert_eq!(
+            snapshot.services["test"],
+            ServiceSnapshot {
+                queued: 2,
+                active: 1,
+                max_runs: 2,
+                runs_per_user: 1,
+                load: Some(LoadReport {
+                    cpu: 12.5,
+                    disk_free: Some(1000),
+                    rejected_submissions: Default::default(),
+                }),
+                load_error: None,
+            }
+        );
+        let down = &snapshot.services["down"];
+        assert_eq!((down.queued, down.active), (0, 0));
+        assert!(down.load.is_none());
+        assert!(down.load_error.is_some());
+    }
 }
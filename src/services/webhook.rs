@@ -0,0 +1,320 @@
+use crate::config::loader::{Secret, Subscription, WebhookConfig};
+use crate::models::job_dao::Job;
+use crate::models::status_dto::Status;
+use crate::models::user_dao::{EmailPreference, User};
+use crate::models::webhook_dao::WebhookDelivery;
+use crate::services::notify::{self, Notification};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+/// `sha256=<hex HMAC of the body>`, keyed with `WEBHOOK_SECRET`
+pub const SIGNATURE_HEADER: &str = "x-orchestrator-signature";
+
+/// Name of the event, e.g. `job.completed`
+pub const EVENT_HEADER: &str = "x-orchestrator-event";
+
+/// Id of the delivery, the same on every retry so receivers can drop duplicates
+pub const DELIVERY_HEADER: &str = "x-orchestrator-delivery";
+
+/// No retry waits longer than this, however many attempts failed
+const MAX_BACKOFF: Duration = Duration::from_secs(86400);
+
+/// Outcome of one attempt, the status is `None` when no response came back
+#[derive(Debug, PartialEq)]
+pub enum Attempt {
+    Delivered(u16),
+    Failed(Option<u16>, String),
+}
+
+/// Signature of `body`, as sent in the [`SIGNATURE_HEADER`]
+pub fn sign(secret: &Secret, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.expose().as_bytes())
+        .expect("HMAC takes keys of any size");
+    mac.update(body);
+    format!("sha256={:x}", mac.finalize().into_bytes())
+}
+
+/// Wait before the next attempt once `attempts` have failed, `None` when the
+/// delivery should be given up
+pub fn retry_in(config: &WebhookConfig, attempts: u32) -> Option<Duration> {
+    if attempts >= config.max_attempts {
+        return None;
+    }
+    let factor = 2u32.saturating_pow(attempts.saturating_sub(1));
+    Some(config.backoff.saturating_mul(factor).min(MAX_BACKOFF))
+}
+
+/// Where the notifications about a job go: the settings of the job, and the
+/// preferences of its user for those it was submitted without
+struct Recipient {
+    callback_url: Option<String>,
+    email: Option<String>,
+    quiet_for: Duration,
+}
+
+impl Recipient {
+    /// Preferences are read when the notification is queued, so a change
+    /// applies to the jobs already submitted. `failure` is whether the
+    /// notification is about a failed job.
+    async fn of(job: &Job, failure: bool, pool: &SqlitePool) -> Result<Recipient, sqlx::Error> {
+        let user = match User::retrieve_id(job.user_id as u32, pool).await {
+            Ok(user) => user,
+            Err(sqlx::Error::RowNotFound) => User::default(),
+            Err(e) => return Err(e),
+        };
+        let preferences = &user.notifications;
+        let email = match preferences.email {
+            EmailPreference::Always => user.email.clone(),
+            EmailPreference::Failures if failure => user.email.clone(),
+            _ => None,
+        };
+        Ok(Recipient {
+            callback_url: job
+                .metadata
+                .callback_url
+                .clone()
+                .or_else(|| preferences.callback_url.clone()),
+            email: job.metadata.notification_email.clone().or(email),
+            quiet_for: preferences.quiet_for(SystemTime::now()),
+        })
+    }
+}
+
+/// Queue the callback of a job that reached a final status, and its copies
+/// for the operators subscribed to the event
+pub async fn enqueue(
+    job: &Job,
+    subscriptions: &HashMap<String, Subscription>,
+    pool: &SqlitePool,
+) -> Result<Vec<WebhookDelivery>, sqlx::Error> {
+    if !matches!(
+        job.status,
+        Status::Completed | Status::Failed | Status::Invalid
+    ) {
+        return Ok(Vec::new());
+    }
+
+    let failure = job.status != Status::Completed;
+    let recipient = Recipient::of(job, failure, pool).await?;
+    let event = format!("job.{}", job.status);
+    let payload = serde_json::json!({
+        "event": event,
+        "job_id": job.id,
+        "user_id": job.user_id,
+        "service": job.service,
+        "status": job.status,
+        "tags": job.metadata.tags,
+        "checksum": job.checksum,
+        "exit_code": job.exit_code,
+        "reason": job.review_reason,
+        "notification_email": recipient.email,
+    });
+    let notification = Notification::new(&event, &payload);
+    notify::enqueue(
+        job.id,
+        recipient.callback_url.as_deref(),
+        recipient.quiet_for,
+        &notification,
+        subscriptions,
+        pool,
+    )
+    .await
+}
+
+/// Event of the notice sent before the cleaner removes the results
+pub const EXPIRING_EVENT: &str = "job.expiring";
+
+/// Queue the notice that the results of a completed job are removed at
+/// `expires_at`, with a link to fetch them when links are configured. The
+/// orchestrator sends no mail, `notification_email` is passed on for the
+/// receiver to use.
+pub async fn enqueue_expiry(
+    job: &Job,
+    expires_at: u64,
+    download_url: Option<String>,
+    subscriptions: &HashMap<String, Subscription>,
+    pool: &SqlitePool,
+) -> Result<Vec<WebhookDelivery>, sqlx::Error> {
+    if job.status != Status::Completed {
+        return Ok(Vec::new());
+    }
+
+    let recipient = Recipient::of(job, false, pool).await?;
+    let payload = serde_json::json!({
+        "event": EXPIRING_EVENT,
+        "job_id": job.id,
+        "user_id": job.user_id,
+        "service": job.service,
+        "tags": job.metadata.tags,
+        "expires_at": expires_at,
+        "download_url": download_url,
+        "notification_email": recipient.email,
+    });
+    let notification = Notification::new(EXPIRING_EVENT, &payload);
+    notify::enqueue(
+        job.id,
+        recipient.callback_url.as_deref(),
+        recipient.quiet_for,
+        &notification,
+        subscriptions,
+        pool,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::job_dto::create_jobs_table;
+
+    #[test]
+    fn test_sign() {
+        // RFC 4231 test case 2
+        let secret = Secret::new("Jefe");
+        assert_eq!(
+            sign(&secret, b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn test_retry_in() {
+        let config = WebhookConfig {
+            secret: None,
+            max_attempts: 4,
+            backoff: Duration::from_secs(10),
+        };
+        assert_eq!(retry_in(&config, 1), Some(Duration::from_secs(10)));
+        assert_eq!(retry_in(&config, 2), Some(Duration::from_secs(20)));
+        assert_eq!(retry_in(&config, 3), Some(Duration::from_secs(40)));
+        assert_eq!(retry_in(&config, 4), None);
+
+        let config = WebhookConfig {
+            max_attempts: u32::MAX,
+            ..config
+        };
+        assert_eq!(retry_in(&config, 100), Some(MAX_BACKOFF));
+    }
+
+    #[tokio::test]
+    async fn test_enqueue() {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        create_jobs_table(&pool).await.unwrap();
+        User::add_if_missing(0, &pool).await.unwrap();
+
+        let mut job = Job::new("");
+        job.add_to_db(&pool).await.unwrap();
+        job.update_status(Status::Completed, &pool).await.unwrap();
+        let none = HashMap::new();
+        assert!(enqueue(&job, &none, &pool).await.unwrap().is_empty());
+
+        job.metadata.callback_url = Some("https://example.com/hook".to_string());
+        job.update_status(Status::Running, &pool).await.unwrap();
+        assert!(enqueue(&job, &none, &pool).await.unwrap().is_empty());
+
+        job.update_status(Status::Completed, &pool).await.unwrap();
+        let deliveries = enqueue(&job, &none, &pool).await.unwrap();
+        assert_eq!(deliveries.len(), 1);
+        let delivery = &deliveries[0];
+        assert_eq!(delivery.event, "job.completed");
+        assert_eq!(delivery.url, "https://example.com/hook");
+        let payload: serde_json::Value = serde_json::from_str(&delivery.payload).unwrap();
+        assert_eq!(payload["job_id"], job.id);
+        assert_eq!(payload["status"], "Completed");
+    }
+
+    #[tokio::test]
+    async fn test_enqueue_expiry() {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        create_jobs_table(&pool).await.unwrap();
+        User::add_if_missing(0, &pool).await.unwrap();
+
+        let mut job = Job::new("");
+        job.add_to_db(&pool).await.unwrap();
+        job.update_status(Status::Completed, &pool).await.unwrap();
+        let none = HashMap::new();
+        let deliveries = enqueue_expiry(&job, 100, None, &none, &pool).await;
+        assert!(deliveries.unwrap().is_empty());
+
+        job.metadata.callback_url = Some("https://example.com/hook".to_string());
+        job.metadata.notification_email = Some("user@example.com".to_string());
+        job.update_status(Status::Failed, &pool).await.unwrap();
+        let deliveries = enqueue_expiry(&job, 100, None, &none, &pool).await;
+        assert!(deliveries.unwrap().is_empty());
+
+        job.update_status(Status::Completed, &pool).await.unwrap();
+        let link = Some("https://example.com/shared/1".to_string());
+        let deliveries = enqueue_expiry(&job, 100, link, &none, &pool).await.unwrap();
+        assert_eq!(deliveries.len(), 1);
+        let delivery = &deliveries[0];
+        assert_eq!(delivery.event, EXPIRING_EVENT);
+        let payload: serde_json::Value = serde_json::from_str(&delivery.payload).unwrap();
+        assert_eq!(payload["expires_at"], 100);
+        assert_eq!(payload["download_url"], "https://example.com/shared/1");
+        assert_eq!(payload["notification_email"], "user@example.com");
+    }
+
+    #[tokio::test]
+    async fn test_enqueue_user_preferences() {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        create_jobs_table(&pool).await.unwrap();
+        let mut user = User {
+            name: "alice".to_string(),
+            email: Some("alice@example.com".to_string()),
+            ..Default::default()
+        };
+        user.notifications.callback_url = Some("https://alice.example.com/hook".to_string());
+        user.notifications.email = EmailPreference::Failures;
+        user.add_to_db(&pool).await.unwrap();
+
+        let mut job = Job::new("");
+        job.set_user_id(user.id as i32);
+        job.add_to_db(&pool).await.unwrap();
+        let none = HashMap::new();
+        let payload = |d: &WebhookDelivery| -> serde_json::Value {
+            serde_json::from_str(&d.payload).unwrap()
+        };
+
+        // The callback of the user, the email only on failures
+        job.update_status(Status::Completed, &pool).await.unwrap();
+        let deliveries = enqueue(&job, &none, &pool).await.unwrap();
+        assert_eq!(deliveries[0].url, "https://alice.example.com/hook");
+        assert_eq!(
+            payload(&deliveries[0])["notification_email"],
+            serde_json::Value::Null
+        );
+        job.update_status(Status::Failed, &pool).await.unwrap();
+        let deliveries = enqueue(&job, &none, &pool).await.unwrap();
+        assert_eq!(
+            payload(&deliveries[0])["notification_email"],
+            "alice@example.com"
+        );
+
+        // The settings of the job come first
+        job.metadata.callback_url = Some("https://example.com/hook".to_string());
+        job.metadata.notification_email = Some("lab@example.com".to_string());
+        let deliveries = enqueue(&job, &none, &pool).await.unwrap();
+        assert_eq!(deliveries[0].url, "https://example.com/hook");
+        assert_eq!(
+            payload(&deliveries[0])["notification_email"],
+            "lab@example.com"
+        );
+        assert_eq!(WebhookDelivery::list_due(&pool).await.unwrap().len(), 3);
+
+        // Quiet all day but for the minute before midnight, held back
+        user.notifications.quiet_hours = Some("00:00-23:59".to_string());
+        user.update(&pool).await.unwrap();
+        let deliveries = enqueue(&job, &none, &pool).await.unwrap();
+        assert_eq!(deliveries.len(), 1);
+        let due = WebhookDelivery::list_due(&pool).await.unwrap();
+        let held = !due.iter().any(|d| d.id == deliveries[0].id);
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        assert_eq!(held, now % 86400 < 23 * 3600 + 59 * 60);
+    }
+}
@@ -0,0 +1,100 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Faults injected on purpose so the retry and recovery paths can be
+/// exercised in integration tests and staging. Rates go from 0, never, to 1,
+/// every time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChaosConfig {
+    /// Uploads to the clients failing before anything is sent
+    pub upload_failure_rate: f64,
+    /// Downloads from the clients held back by `slow_download_delay`
+    pub slow_download_rate: f64,
+    pub slow_download_delay: Duration,
+    /// Scheduler queries failing as if the database had timed out
+    pub db_failure_rate: f64,
+}
+
+impl Default for ChaosConfig {
+    fn default() -> Self {
+        ChaosConfig {
+            upload_failure_rate: 0.0,
+            slow_download_rate: 0.0,
+            slow_download_delay: Duration::from_secs(5),
+            db_failure_rate: 0.0,
+        }
+    }
+}
+
+impl ChaosConfig {
+    /// Whether any fault can happen at all
+    pub fn is_active(&self) -> bool {
+        self.upload_failure_rate > 0.0
+            || self.slow_download_rate > 0.0
+            || self.db_failure_rate > 0.0
+    }
+
+    pub fn upload_fails(&self) -> bool {
+        strikes(self.upload_failure_rate)
+    }
+
+    /// How long to hold the next download back, if at all
+    pub fn download_delay(&self) -> Option<Duration> {
+        strikes(self.slow_download_rate).then_some(self.slow_download_delay)
+    }
+
+    /// The error a scheduler query returns when a database fault strikes
+    pub fn db_fault(&self) -> Result<(), sqlx::Error> {
+        match strikes(self.db_failure_rate) {
+            true => Err(sqlx::Error::PoolTimedOut),
+            false => Ok(()),
+        }
+    }
+}
+
+/// A number in `[0, 1)`. The randomness comes from a v4 UUID, whose low 53
+/// bits are all random.
+pub fn random_fraction() -> f64 {
+    let bits = Uuid::new_v4().as_u128() as u64 & ((1 << 53) - 1);
+    bits as f64 / (1u64 << 53) as f64
+}
+
+/// Whether a fault happening with probability `rate` happens this time
+fn strikes(rate: f64) -> bool {
+    rate > 0.0 && random_fraction() < rate
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strikes() {
+        assert!((0..1000).all(|_| !strikes(0.0)));
+        assert!((0..1000).all(|_| strikes(1.0)));
+
+        let hits = (0..10_000).filter(|_| strikes(0.5)).count();
+        assert!((4000..6000).contains(&hits), "{hits} hits out of 10000");
+    }
+
+    #[test]
+    fn test_chaos_config() {
+        let off = ChaosConfig::default();
+        assert!(!off.is_active());
+        assert!(!off.upload_fails());
+        assert_eq!(off.download_delay(), None);
+        assert!(off.db_fault().is_ok());
+
+        let always = ChaosConfig {
+            upload_failure_rate: 1.0,
+            slow_download_rate: 1.0,
+            slow_download_delay: Duration::from_millis(10),
+            db_failure_rate: 1.0,
+        };
+        assert!(always.is_active());
+        assert!(always.upload_fails());
+        assert_eq!(always.download_delay(), Some(Duration::from_millis(10)));
+        assert!(matches!(always.db_fault(), Err(sqlx::Error::PoolTimedOut)));
+    }
+}
@@ -0,0 +1,305 @@
+use bytes::Bytes;
+use flate2::Compression;
+use flate2::write::{GzDecoder, GzEncoder};
+use futures::stream::{self, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::io::{self, Write};
+use std::str::FromStr;
+
+/// Encodings understood on both ends, in order of preference
+pub const SUPPORTED: [Encoding; 3] = [Encoding::Zstd, Encoding::Brotli, Encoding::Gzip];
+
+/// `Accept-Encoding` value sent with every outbound request
+pub const ACCEPT_ENCODING: &str = "zstd, br, gzip";
+
+/// zstd level, favours speed since most transfers are large
+const ZSTD_LEVEL: i32 = 3;
+
+/// Brotli quality, 11 is far too slow to compress on the fly
+const BROTLI_QUALITY: u32 = 5;
+
+/// Brotli window, as a power of two
+const BROTLI_WINDOW: u32 = 22;
+
+/// Size of the internal buffer of the brotli coders
+const BROTLI_BUFFER: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    Gzip,
+    Zstd,
+    #[serde(rename = "br")]
+    Brotli,
+}
+
+impl Encoding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Zstd => "zstd",
+            Encoding::Brotli => "br",
+        }
+    }
+
+    /// Parse a `Content-Encoding` header, `None` when it is not one we support
+    pub fn from_header(value: &str) -> Option<Encoding> {
+        value.trim().parse().ok()
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "gzip" | "x-gzip" => Ok(Encoding::Gzip),
+            "zstd" => Ok(Encoding::Zstd),
+            "br" => Ok(Encoding::Brotli),
+            other => Err(format!("Unsupported encoding: {other}")),
+        }
+    }
+}
+
+impl std::fmt::Display for Encoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Pick the preferred supported encoding offered in an `Accept-Encoding` header
+pub fn negotiate(accept_encoding: &str) -> Option<Encoding> {
+    let offered: Vec<Encoding> = accept_encoding
+        .split(',')
+        .filter_map(|item| {
+            let mut parts = item.split(';');
+            let encoding = Encoding::from_header(parts.next()?)?;
+            // `q=0` explicitly refuses the encoding
+            let refused = parts.any(|p| {
+                p.trim()
+                    .strip_prefix("q=")
+                    .and_then(|q| q.parse::<f32>().ok())
+                    == Some(0.0)
+            });
+            (!refused).then_some(encoding)
+        })
+        .collect();
+
+    SUPPORTED.into_iter().find(|e| offered.contains(e))
+}
+
+/// Streaming (de)compressor, fed chunk by chunk
+enum Coder {
+    GzipEncoder(GzEncoder<Vec<u8>>),
+    ZstdEncoder(zstd::stream::write::Encoder<'static, Vec<u8>>),
+    BrotliEncoder(Box<brotli::CompressorWriter<Vec<u8>>>),
+    GzipDecoder(GzDecoder<Vec<u8>>),
+    ZstdDecoder(zstd::stream::write::Decoder<'static, Vec<u8>>),
+    BrotliDecoder(Box<brotli::DecompressorWriter<Vec<u8>>>),
+}
+
+impl Coder {
+    fn encoder(encoding: Encoding) -> io::Result<Coder> {
+        Ok(match encoding {
+            Encoding::Gzip => {
+                Coder::GzipEncoder(GzEncoder::new(Vec::new(), Compression::default()))
+            }
+            Encoding::Zstd => {
+                Coder::ZstdEncoder(zstd::stream::write::Encoder::new(Vec::new(), ZSTD_LEVEL)?)
+            }
+            Encoding::Brotli => Coder::BrotliEncoder(Box::new(brotli::CompressorWriter::new(
+                Vec::new(),
+                BROTLI_BUFFER,
+                BROTLI_QUALITY,
+                BROTLI_WINDOW,
+            ))),
+        })
+    }
+
+    fn decoder(encoding: Encoding) -> io::Result<Coder> {
+        Ok(match encoding {
+            Encoding::Gzip => Coder::GzipDecoder(GzDecoder::new(Vec::new())),
+            Encoding::Zstd => Coder::ZstdDecoder(zstd::stream::write::Decoder::new(Vec::new())?),
+            Encoding::Brotli => Coder::BrotliDecoder(Box::new(brotli::DecompressorWriter::new(
+                Vec::new(),
+                BROTLI_BUFFER,
+            ))),
+        })
+    }
+
+    /// Process a chunk and return whatever output is ready so far
+    fn push(&mut self, chunk: &[u8]) -> io::Result<Bytes> {
+        let output = match self {
+            Coder::GzipEncoder(c) => {
+                c.write_all(chunk)?;
+                c.get_mut()
+            }
+            Coder::ZstdEncoder(c) => {
+                c.write_all(chunk)?;
+                c.get_mut()
+            }
+            Coder::GzipDecoder(c) => {
+                c.write_all(chunk)?;
+                c.get_mut()
+            }
+            Coder::ZstdDecoder(c) => {
+                c.write_all(chunk)?;
+                c.get_mut()
+            }
+            Coder::BrotliEncoder(c) => {
+                c.write_all(chunk)?;
+                c.get_mut()
+            }
+            Coder::BrotliDecoder(c) => {
+                c.write_all(chunk)?;
+                c.get_mut()
+            }
+        };
+        Ok(Bytes::from(std::mem::take(output)))
+    }
+
+    /// Flush the remaining output once the input is exhausted
+    fn finish(self) -> io::Result<Bytes> {
+        let output = match self {
+            Coder::GzipEncoder(c) => c.finish()?,
+            Coder::ZstdEncoder(c) => c.finish()?,
+            Coder::GzipDecoder(c) => c.finish()?,
+            Coder::ZstdDecoder(mut c) => {
+                c.flush()?;
+                c.into_inner()
+            }
+            Coder::BrotliEncoder(c) => c.into_inner(),
+            // A stream cut short is handed back as an error
+            Coder::BrotliDecoder(c) => c.into_inner().map_err(|_| {
+                io::Error::new(io::ErrorKind::UnexpectedEof, "truncated brotli stream")
+            })?,
+        };
+        Ok(Bytes::from(output))
+    }
+}
+
+/// Run a byte stream through a coder, without holding the whole body in memory
+fn transcode<S, E>(input: S, coder: io::Result<Coder>) -> impl Stream<Item = io::Result<Bytes>>
+where
+    S: Stream<Item = Result<Bytes, E>> + Send + 'static,
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    // The state is `None` once the stream is over or failed
+    stream::unfold(
+        (input.boxed(), Some(coder)),
+        |(mut input, state)| async move {
+            let mut coder = match state? {
+                Ok(c) => c,
+                Err(e) => return Some((Err(e), (input, None))),
+            };
+            loop {
+                match input.next().await {
+                    Some(Ok(chunk)) => match coder.push(&chunk) {
+                        Ok(out) if out.is_empty() => continue,
+                        Ok(out) => return Some((Ok(out), (input, Some(Ok(coder))))),
+                        Err(e) => return Some((Err(e), (input, None))),
+                    },
+                    Some(Err(e)) => return Some((Err(io::Error::other(e)), (input, None))),
+                    None => return Some((coder.finish(), (input, None))),
+                }
+            }
+        },
+    )
+}
+
+/// Compress a byte stream
+pub fn encode<S, E>(input: S, encoding: Encoding) -> impl Stream<Item = io::Result<Bytes>>
+where
+    S: Stream<Item = Result<Bytes, E>> + Send + 'static,
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    transcode(input, Coder::encoder(encoding))
+}
+
+/// Decompress a byte stream
+pub fn decode<S, E>(input: S, encoding: Encoding) -> impl Stream<Item = io::Result<Bytes>>
+where
+    S: Stream<Item = Result<Bytes, E>> + Send + 'static,
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    transcode(input, Coder::decoder(encoding))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    async fn collect(s: impl Stream<Item = io::Result<Bytes>>) -> io::Result<Vec<u8>> {
+        let chunks: Vec<io::Result<Bytes>> = s.collect().await;
+        let mut out = Vec::new();
+        for chunk in chunks {
+            out.extend_from_slice(&chunk?);
+        }
+        Ok(out)
+    }
+
+    fn chunks(data: &[u8]) -> impl Stream<Item = Result<Bytes, io::Error>> + Send + 'static {
+        let parts: Vec<Result<Bytes, io::Error>> = data
+            .chunks(7)
+            .map(|c| Ok(Bytes::copy_from_slice(c)))
+            .collect();
+        stream::iter(parts)
+    }
+
+    #[test]
+    fn test_parse_encoding() {
+        assert_eq!("gzip".parse::<Encoding>(), Ok(Encoding::Gzip));
+        assert_eq!("ZSTD".parse::<Encoding>(), Ok(Encoding::Zstd));
+        assert_eq!("br".parse::<Encoding>(), Ok(Encoding::Brotli));
+        assert!("deflate".parse::<Encoding>().is_err());
+        assert_eq!(Encoding::from_header(" x-gzip "), Some(Encoding::Gzip));
+    }
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(negotiate("gzip, deflate, zstd"), Some(Encoding::Zstd));
+        assert_eq!(negotiate("gzip;q=0.8, br"), Some(Encoding::Brotli));
+        assert_eq!(negotiate("gzip, deflate, br, zstd"), Some(Encoding::Zstd));
+        assert_eq!(negotiate("zstd;q=0, gzip"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("br;q=0, gzip"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("deflate, identity"), None);
+        assert_eq!(negotiate(""), None);
+    }
+
+    #[tokio::test]
+    async fn test_roundtrip() {
+        let data = "ATOM      1  N   MET A   1\n".repeat(500).into_bytes();
+        for encoding in SUPPORTED {
+            let compressed = collect(encode(chunks(&data), encoding)).await.unwrap();
+            assert!(
+                compressed.len() < data.len() / 10,
+                "{encoding} did not shrink"
+            );
+
+            let decompressed = collect(decode(chunks(&compressed), encoding))
+                .await
+                .unwrap();
+            assert_eq!(decompressed, data);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_decode_invalid() {
+        let garbage = b"definitely not compressed".to_vec();
+        for encoding in SUPPORTED {
+            assert!(collect(decode(chunks(&garbage), encoding)).await.is_err());
+        }
+
+        // Cut short
+        let data = "ATOM      1  N   MET A   1\n".repeat(500).into_bytes();
+        let compressed = collect(encode(chunks(&data), Encoding::Brotli))
+            .await
+            .unwrap();
+        let truncated = &compressed[..compressed.len() / 2];
+        assert!(
+            collect(decode(chunks(truncated), Encoding::Brotli))
+                .await
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,76 @@
+use crate::config::loader::{DownloadLinkConfig, Secret};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Seconds since the epoch, the unit of the link expiry
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn mac(secret: &Secret, job_id: u32, expires: u64) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.expose().as_bytes())
+        .expect("HMAC takes keys of any size");
+    // Prefixed so the signature can't be mistaken for one made for another purpose
+    mac.update(format!("download:{job_id}:{expires}").as_bytes());
+    mac
+}
+
+/// Hex signature of the link to the results of `job_id`, valid until `expires`
+pub fn sign(secret: &Secret, job_id: u32, expires: u64) -> String {
+    format!("{:x}", mac(secret, job_id, expires).finalize().into_bytes())
+}
+
+/// Signed link to the results of `job_id` under `/shared`, valid until `expires`
+pub fn url(links: &DownloadLinkConfig, job_id: u32, expires: u64) -> String {
+    let signature = sign(&links.secret, job_id, expires);
+    let base = links.base_url.as_deref().unwrap_or_default();
+    format!("{base}/shared/{job_id}?expires={expires}&signature={signature}")
+}
+
+/// The signature matches and `expires` has not passed, compared in constant time
+pub fn verify(secret: &Secret, job_id: u32, expires: u64, signature: &str) -> bool {
+    if expires < now() {
+        return false;
+    }
+    let Some(bytes) = decode_hex(signature) else {
+        return false;
+    };
+    mac(secret, job_id, expires).verify_slice(&bytes).is_ok()
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign_and_verify() {
+        let secret = Secret::new("key");
+        let expires = now() + 60;
+        let signature = sign(&secret, 1, expires);
+        assert_eq!(signature.len(), 64);
+
+        assert!(verify(&secret, 1, expires, &signature));
+        assert!(!verify(&secret, 2, expires, &signature));
+        assert!(!verify(&secret, 1, expires + 1, &signature));
+        assert!(!verify(&Secret::new("other"), 1, expires, &signature));
+        assert!(!verify(&secret, 1, expires, "zz"));
+        assert!(!verify(&secret, 1, expires, ""));
+
+        let expired = now() - 1;
+        assert!(!verify(&secret, 1, expired, &sign(&secret, 1, expired)));
+    }
+}
@@ -0,0 +1,121 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use sysinfo::System;
+
+/// Written next to the payload files and shipped inside the result archive
+pub const ENVIRONMENT_FILE: &str = "environment.json";
+
+/// A probe that hangs must not stall the runner
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Where and with what a payload ran, recorded so results can be reproduced
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnvironmentSnapshot {
+    pub hostname: String,
+    pub kernel: String,
+    pub client_version: String,
+    pub image_digest: Option<String>,
+    /// Probe command and the first line it printed, `None` when it failed
+    pub tools: BTreeMap<String, Option<String>>,
+}
+
+impl EnvironmentSnapshot {
+    pub fn capture(image_digest: Option<&str>, probes: &[String]) -> EnvironmentSnapshot {
+        EnvironmentSnapshot {
+            hostname: System::host_name().unwrap_or_else(|| "unknown".to_string()),
+            kernel: System::kernel_version().unwrap_or_else(|| "unknown".to_string()),
+            client_version: env!("CARGO_PKG_VERSION").to_string(),
+            image_digest: image_digest.map(str::to_string),
+            tools: probes
+                .iter()
+                .map(|probe| (probe.clone(), run_probe(probe)))
+                .collect(),
+        }
+    }
+
+    pub fn write(&self, dir: &Path) -> std::io::Result<()> {
+        let json = serde_json::to_vec_pretty(self).map_err(std::io::Error::other)?;
+        std::fs::write(dir.join(ENVIRONMENT_FILE), json)
+    }
+}
+
+/// Run `probe` (e.g. `gmx --version`) and keep the first non-empty line,
+/// some tools print their version on stderr
+fn run_probe(probe: &str) -> Option<String> {
+    let mut args = probe.split_whitespace();
+    let mut child = Command::new(args.next()?)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .ok()?;
+
+    let deadline = Instant::now() + PROBE_TIMEOUT;
+    while child.try_wait().ok()?.is_none() {
+        if Instant::now() > deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return None;
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+
+    let output = child.wait_with_output().ok()?;
+    [output.stdout, output.stderr]
+        .iter()
+        .flat_map(|out| {
+            String::from_utf8_lossy(out)
+                .lines()
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        })
+        .map(|line| line.trim().to_string())
+        .find(|line| !line.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capture() {
+        let probes = vec![
+            "echo tool 1.2.3".to_string(),
+            "does-not-exist --version".to_string(),
+        ];
+        let snapshot = EnvironmentSnapshot::capture(Some("sha256:abc"), &probes);
+
+        assert!(!snapshot.hostname.is_empty());
+        assert_eq!(snapshot.client_version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(snapshot.image_digest.as_deref(), Some("sha256:abc"));
+        assert_eq!(
+            snapshot.tools["echo tool 1.2.3"].as_deref(),
+            Some("tool 1.2.3")
+        );
+        assert_eq!(snapshot.tools["does-not-exist --version"], None);
+    }
+
+    #[test]
+    fn test_run_probe() {
+        // Version printed on stderr
+        assert!(run_probe("ls /does-not-exist").is_some());
+        // Nothing printed
+        assert_eq!(run_probe("true"), None);
+        assert_eq!(run_probe(""), None);
+    }
+
+    #[test]
+    fn test_write() {
+        let tempdir = tempfile::TempDir::new().unwrap();
+        let snapshot = EnvironmentSnapshot::capture(None, &[]);
+        snapshot.write(tempdir.path()).unwrap();
+
+        let json = std::fs::read(tempdir.path().join(ENVIRONMENT_FILE)).unwrap();
+        let read: EnvironmentSnapshot = serde_json::from_slice(&json).unwrap();
+        assert_eq!(read, snapshot);
+    }
+}
@@ -1,16 +1,100 @@
 use crate::client::ClientError;
+use crate::config::loader::DEFAULT_TENANT;
+use crate::utils::storage::write_error_status;
 use axum::http::StatusCode;
 use std::fs::File;
 use std::io;
-use std::io::{Read, Write};
-use std::path::PathBuf;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::{FileExt, FileTypeExt};
+use std::path::{Path, PathBuf};
 use std::sync::LazyLock;
+use std::time::SystemTime;
 use tokio::io::AsyncWriteExt;
 use walkdir::WalkDir;
 use zip::ZipWriter;
 use zip::write::FileOptions;
 
 use regex::Regex;
+use sha2::{Digest, Sha256};
+
+/// List the job directories inside `data_path` together with their tenant.
+/// Directories named after one of `tenants` are namespaces, the others belong
+/// to the default tenant. Jobs sit `depth` levels below their namespace: 1 for
+/// `<namespace>/<job>`, 2 for e.g. `<namespace>/<service>/<job>`.
+pub fn list_job_dirs(
+    data_path: &str,
+    tenants: &[String],
+    depth: usize,
+) -> io::Result<Vec<(String, PathBuf)>> {
+    let depth = depth.max(1);
+    let mut dirs = Vec::new();
+    for entry in std::fs::read_dir(data_path)? {
+        let path = entry?.path();
+        if !path.is_dir() {
+            continue;
+        }
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        // e.g. the quarantine, never a job directory
+        if name.starts_with('.') {
+            continue;
+        }
+        let (tenant, below) = match tenants.contains(&name) {
+            true => (name, depth),
+            false => (DEFAULT_TENANT.to_string(), depth - 1),
+        };
+        for dir in dirs_below(&path, below)? {
+            dirs.push((tenant.clone(), dir));
+        }
+    }
+    Ok(dirs)
+}
+
+/// Move a directory to `to`, copying it over when `to` is on another
+/// filesystem, e.g. another storage root
+pub fn move_dir(from: &Path, to: &Path) -> io::Result<()> {
+    match std::fs::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            copy_dir(from, to)?;
+            std::fs::remove_dir_all(from)
+        }
+        result => result,
+    }
+}
+
+fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    for entry in WalkDir::new(from) {
+        let entry = entry?;
+        let target = to.join(entry.path().strip_prefix(from).unwrap_or(entry.path()));
+        if entry.file_type().is_dir() {
+            std::fs::create_dir_all(&target)?;
+        } else {
+            std::fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Directories exactly `depth` levels below `dir`, `dir` itself for 0
+fn dirs_below(dir: &Path, depth: usize) -> io::Result<Vec<PathBuf>> {
+    let mut dirs = Vec::new();
+    let hidden =
+        |e: &walkdir::DirEntry| e.depth() > 0 && e.file_name().to_string_lossy().starts_with('.');
+    for entry in WalkDir::new(dir)
+        .min_depth(depth)
+        .max_depth(depth)
+        .into_iter()
+        .filter_entry(|e| !hidden(e))
+    {
+        let entry = entry?;
+        if entry.file_type().is_dir() {
+            dirs.push(entry.into_path());
+        }
+    }
+    Ok(dirs)
+}
 
 /// Sanitize filename to prevent path traversal attacks
 pub fn sanitize_filename(filename: &str) -> String {
@@ -26,55 +110,67 @@ pub async fn save_file(
     mut field: axum::extract::multipart::Field<'_>,
     path: &std::path::Path,
 ) -> Result<(), (StatusCode, String)> {
-    let mut file = tokio::fs::File::create(path).await.map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("File creation failed: {e}"),
-        )
-    })?;
+    let mut file = tokio::fs::File::create(path)
+        .await
+        .map_err(|e| (write_error_status(&e), format!("File creation failed: {e}")))?;
 
     let mut buffer = Vec::with_capacity(1024 * 1024); // 1MB buffer
 
     while let Some(chunk) = field
         .chunk()
         .await
-        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Chunk read failed: {e}")))?
+        .map_err(|e| (e.status(), format!("Chunk read failed: {e}")))?
     {
         buffer.extend_from_slice(&chunk);
 
         // Write in chunks to balance memory and performance
         if buffer.len() >= 1024 * 1024 {
-            file.write_all(&buffer).await.map_err(|e| {
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    format!("Write failed: {e}"),
-                )
-            })?;
+            file.write_all(&buffer)
+                .await
+                .map_err(|e| (write_error_status(&e), format!("Write failed: {e}")))?;
             buffer.clear();
         }
     }
 
     // Write remaining data
     if !buffer.is_empty() {
-        file.write_all(&buffer).await.map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Final write failed: {e}"),
-            )
-        })?;
+        file.write_all(&buffer)
+            .await
+            .map_err(|e| (write_error_status(&e), format!("Final write failed: {e}")))?;
     }
 
-    file.flush().await.map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Flush failed: {e}"),
-        )
-    })?;
+    file.flush()
+        .await
+        .map_err(|e| (write_error_status(&e), format!("Flush failed: {e}")))?;
 
     Ok(())
 }
 
 /// Internal helper function to write directory contents to a ZipWriter
+/// Whether a walked entry is a regular file or a directory, the only kinds
+/// shipped to a client or put in an archive. Symlinks are not followed,
+/// wherever they point, and devices, FIFOs and sockets are never read. Each
+/// entry left out is logged.
+pub fn is_shippable(entry: &walkdir::DirEntry) -> bool {
+    let file_type = entry.file_type();
+    if file_type.is_file() || file_type.is_dir() {
+        return true;
+    }
+    let kind = if file_type.is_symlink() {
+        "symlink"
+    } else if file_type.is_block_device() || file_type.is_char_device() {
+        "device file"
+    } else if file_type.is_fifo() {
+        "FIFO"
+    } else if file_type.is_socket() {
+        "socket"
+    } else {
+        "special file"
+    };
+    tracing::warn!("Skipping {}: {kind}", entry.path().display());
+    false
+}
+
 /// Returns the writer after finishing the zip
 pub(crate) fn write_directory_to_zip<W: std::io::Write + std::io::Seek>(
     src_dir: &PathBuf,
@@ -96,7 +192,7 @@ pub(crate) fn write_directory_to_zip<W: std::io::Write + std::io::Seek>(
         ))
     })?;
 
-    for entry in it.filter_map(|e| e.ok()) {
+    for entry in it.filter_map(|e| e.ok()).filter(is_shippable) {
         let path = entry.path();
 
         // Check for path traversal: canonicalize the path and ensure it's within src_dir
@@ -137,7 +233,7 @@ pub(crate) fn write_directory_to_zip<W: std::io::Write + std::io::Seek>(
                 ))
             })?;
 
-            if path.is_dir() {
+            if entry.file_type().is_dir() {
                 // Add directory entry
                 zip.add_directory(name_str, options)?;
             } else {
@@ -159,6 +255,8 @@ pub(crate) fn write_directory_to_zip<W: std::io::Write + std::io::Seek>(
     zip.finish()
 }
 
+/// Zip a directory into `dst_file`, which must lie outside of it
+#[cfg(test)]
 pub fn zip_directory(src_dir: &PathBuf, dst_file: &PathBuf) -> zip::result::ZipResult<()> {
     // Create the output file
     let file = File::create(dst_file)?;
@@ -179,6 +277,379 @@ pub fn zip_directory_to_bytes(src_dir: &PathBuf) -> zip::result::ZipResult<Vec<u
     Ok(cursor.into_inner())
 }
 
+/// Bytes taken by the regular files in `dir`, links are not followed
+pub fn dir_size(dir: &Path) -> u64 {
+    WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| e.metadata().ok())
+        .map(|m| m.len())
+        .sum()
+}
+
+/// Latest modification of `dir` or anything inside it, leaving out `skip`
+pub fn newest_mtime(dir: &Path, skip: &Path) -> io::Result<SystemTime> {
+    let mut newest = SystemTime::UNIX_EPOCH;
+    for entry in WalkDir::new(dir) {
+        let entry = entry?;
+        if entry.path() == skip {
+            continue;
+        }
+        newest = newest.max(entry.metadata()?.modified()?);
+    }
+    Ok(newest)
+}
+
+/// The last `max` bytes of a file, and whether the ones before were left out
+pub fn read_tail(path: &Path, max: u64) -> io::Result<(Vec<u8>, bool)> {
+    let mut file = File::open(path)?;
+    let skipped = file.metadata()?.len().saturating_sub(max);
+    file.seek(SeekFrom::Start(skipped))?;
+    let mut tail = Vec::new();
+    file.take(max).read_to_end(&mut tail)?;
+    Ok((tail, skipped > 0))
+}
+
+/// Drop the start of a file longer than `max` bytes, returning how many
+/// bytes went. The tail replaces the file in one rename, so a reader never
+/// finds it empty. Only for files no longer written, see [`shrink_to_tail`].
+pub fn keep_tail(path: &Path, max: u64) -> io::Result<u64> {
+    let len = std::fs::metadata(path)?.len();
+    let (tail, trimmed) = read_tail(path, max)?;
+    if trimmed {
+        let partial = path.with_extension("partial");
+        std::fs::write(&partial, &tail)?;
+        std::fs::rename(&partial, path)?;
+        return Ok(len.saturating_sub(tail.len() as u64));
+    }
+    Ok(0)
+}
+
+/// Like [`keep_tail`] for a file another process appends to, which has to
+/// have opened it in append mode. The tail is moved to the start of the file
+/// in place, so the writer carries on right after it, and what it writes
+/// while the file is shrunk may be lost.
+pub fn shrink_to_tail(path: &Path, max: u64) -> io::Result<u64> {
+    let len = std::fs::metadata(path)?.len();
+    let (tail, trimmed) = read_tail(path, max)?;
+    if trimmed {
+        let file = std::fs::OpenOptions::new().write(true).open(path)?;
+        file.write_all_at(&tail, 0)?;
+        file.set_len(tail.len() as u64)?;
+        return Ok(len.saturating_sub(tail.len() as u64));
+    }
+    Ok(0)
+}
+
+/// The complete lines appended to a file since `offset`, reading at most
+/// `max` bytes, and whether its end was reached. `offset` moves past the
+/// lines returned, a partial last line waits for the next read unless `flush`
+/// is set or it fills `max` alone. A file shorter than `offset` was
+/// rewritten, it is followed from its end. Only the text after the last `\r`
+/// of a line is kept, as a terminal would show a progress bar.
+pub fn read_new_lines(
+    path: &Path,
+    offset: &mut u64,
+    max: u64,
+    flush: bool,
+) -> io::Result<(Vec<String>, bool)> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    *offset = (*offset).min(len);
+    file.seek(SeekFrom::Start(*offset))?;
+    let mut chunk = Vec::new();
+    file.take(max).read_to_end(&mut chunk)?;
+    let at_end = *offset + chunk.len() as u64 >= len;
+
+    let complete = match chunk.iter().rposition(|&b| b == b'\n') {
+        Some(last) if !(flush && at_end) => last + 1,
+        _ if flush && at_end || chunk.len() as u64 >= max => chunk.len(),
+        _ => 0,
+    };
+    *offset += complete as u64;
+
+    let lines = chunk[..complete]
+        .strip_suffix(b"\n")
+        .unwrap_or(&chunk[..complete])
+        .split(|&b| b == b'\n')
+        .filter(|_| complete > 0)
+        .map(|line| {
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            let shown = line.rsplit(|&b| b == b'\r').next().unwrap_or(line);
+            String::from_utf8_lossy(shown).into_owned()
+        })
+        .collect();
+    Ok((lines, at_end && complete == chunk.len()))
+}
+
+/// Header with the hex SHA-256 of the archive sent by the client on retrieve
+pub const CHECKSUM_HEADER: &str = "x-checksum-sha256";
+
+/// Hex SHA-256 of a file, read in chunks
+pub fn file_sha256(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Check that a downloaded archive is a readable zip with at least one entry,
+/// returning the number of entries
+pub fn validate_archive(path: &std::path::Path) -> io::Result<usize> {
+    let archive = zip::ZipArchive::new(File::open(path)?)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    if archive.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Archive has no entries",
+        ));
+    }
+    Ok(archive.len())
+}
+
+/// Most bytes [`extract_archive`] writes, archives unpacking to more are
+/// rejected as zip bombs
+pub const MAX_EXTRACTED_BYTES: u64 = 20 * 1024 * 1024 * 1024;
+
+/// Most entries [`extract_archive`] accepts in an archive
+pub const MAX_EXTRACTED_ENTRIES: usize = 100_000;
+
+/// Unpack an archive into `dst`, returning the number of files written.
+/// Entries that would land outside `dst` (zip-slip), symbolic links, entries
+/// going through a link already in `dst` and archives unpacking to more than
+/// [`MAX_EXTRACTED_BYTES`] or holding more than [`MAX_EXTRACTED_ENTRIES`]
+/// abort the extraction. An entry shadowing the archive itself is skipped.
+pub fn extract_archive(archive: &Path, dst: &Path) -> io::Result<usize> {
+    extract_archive_within(archive, dst, MAX_EXTRACTED_BYTES, MAX_EXTRACTED_ENTRIES)
+}
+
+fn extract_archive_within(
+    archive: &Path,
+    dst: &Path,
+    max_bytes: u64,
+    max_entries: usize,
+) -> io::Result<usize> {
+    let mut zip = zip::ZipArchive::new(File::open(archive)?)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    if zip.len() > max_entries {
+        return Err(io::Error::new(
+            io::ErrorKind::FileTooLarge,
+            format!(
+                "Archive has {} entries, at most {max_entries} are extracted",
+                zip.len()
+            ),
+        ));
+    }
+
+    let mut extracted = 0;
+    let mut written = 0;
+    for i in 0..zip.len() {
+        let mut entry = zip
+            .by_index(i)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        // `enclosed_name` rejects absolute paths and `..` components
+        let Some(relative) = entry.enclosed_name() else {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("Path traversal detected in entry {}", entry.name()),
+            ));
+        };
+        // A link could point anywhere, later entries would be written through it
+        if entry.is_symlink() {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("Symbolic link in entry {}", entry.name()),
+            ));
+        }
+        let target = dst.join(&relative);
+        if target == archive {
+            continue;
+        }
+        reject_links(dst, &relative)?;
+
+        if entry.is_dir() {
+            std::fs::create_dir_all(&target)?;
+            continue;
+        }
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // The sizes in the headers can lie, count what is actually written
+        let remaining = max_bytes - written;
+        let mut file = File::create(&target)?;
+        let copied = io::copy(&mut (&mut entry).take(remaining + 1), &mut file)?;
+        if copied > remaining {
+            drop(file);
+            std::fs::remove_file(&target)?;
+            return Err(io::Error::new(
+                io::ErrorKind::FileTooLarge,
+                format!("Archive unpacks to more than {max_bytes} bytes"),
+            ));
+        }
+        written += copied;
+        extracted += 1;
+    }
+
+    Ok(extracted)
+}
+
+/// Fail when `relative` goes through, or ends on, a symbolic link inside `dst`
+fn reject_links(dst: &Path, relative: &Path) -> io::Result<()> {
+    let mut path = dst.to_path_buf();
+    for component in relative.components() {
+        path.push(component);
+        match std::fs::symlink_metadata(&path) {
+            Ok(m) if m.file_type().is_symlink() => {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    format!("{} is a symbolic link", path.display()),
+                ));
+            }
+            Ok(_) => {}
+            // Nothing further down exists yet
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Move the files of `dir` into `archive`, a file inside `dir`, leaving the
+/// top-level entries named in `keep` and symlinks in place. Returns the number
+/// of files packed. The archive is written under a temporary name first, so
+/// an interrupted run never loses a file.
+pub fn pack_directory(dir: &Path, archive: &Path, keep: &[&str]) -> io::Result<usize> {
+    let partial = archive.with_extension("partial");
+    let skipped = |name: &std::ffi::OsStr| {
+        keep.iter().any(|k| name == *k)
+            || Some(name) == archive.file_name()
+            || Some(name) == partial.file_name()
+    };
+
+    let options: FileOptions<()> =
+        FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    let mut zip = ZipWriter::new(File::create(&partial)?);
+    let (mut files, mut dirs) = (Vec::new(), Vec::new());
+
+    let walk = WalkDir::new(dir)
+        .min_depth(1)
+        .into_iter()
+        .filter_entry(|e| e.depth() > 1 || !skipped(e.file_name()));
+    let result = (|| {
+        for entry in walk {
+            let entry = entry.map_err(io::Error::other)?;
+            let name = entry
+                .path()
+                .strip_prefix(dir)
+                .ok()
+                .and_then(|p| p.to_str())
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid file name"))?
+                .to_string();
+
+            if entry.file_type().is_dir() {
+                zip.add_directory(name, options).map_err(io::Error::other)?;
+                dirs.push(entry.into_path());
+            } else if entry.file_type().is_file() {
+                zip.start_file(name, options).map_err(io::Error::other)?;
+                io::copy(&mut File::open(entry.path())?, &mut zip)?;
+                files.push(entry.into_path());
+            }
+        }
+        zip.finish().map_err(io::Error::other)?;
+        std::fs::rename(&partial, archive)
+    })();
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&partial);
+        return Err(e);
+    }
+
+    for file in &files {
+        std::fs::remove_file(file)?;
+    }
+    // Deepest first, a directory still holding a symlink is left alone
+    for dir in dirs.iter().rev() {
+        let _ = std::fs::remove_dir(dir);
+    }
+    Ok(files.len())
+}
+
+/// Restore the files moved into `archive` by [`pack_directory`] and remove it
+pub fn unpack_directory(dir: &Path, archive: &Path) -> io::Result<usize> {
+    let unpacked = extract_archive(archive, dir)?;
+    std::fs::remove_file(archive)?;
+    Ok(unpacked)
+}
+
+/// Write one archive holding the entries of several `output.zip` files, each
+/// under its own directory. Entries are copied without recompressing and the
+/// writer needs no `Seek`, so the result can be streamed as it is built.
+pub fn write_combined_archive<W: Write>(
+    members: &[(String, PathBuf)],
+    writer: W,
+) -> zip::result::ZipResult<W> {
+    let mut zip = ZipWriter::new_stream(writer);
+
+    for (dir, archive) in members {
+        let mut source = zip::ZipArchive::new(File::open(archive)?)?;
+        for i in 0..source.len() {
+            let entry = source.by_index_raw(i)?;
+            let name = format!("{dir}/{}", entry.name());
+            zip.raw_copy_file_rename(entry, name)?;
+        }
+    }
+
+    Ok(zip.finish()?.into_inner())
+}
+
+/// Blocking writer feeding a channel in chunks, to turn synchronous output
+/// into a response body. Fails once the receiving side is gone.
+pub struct ChannelWriter {
+    tx: tokio::sync::mpsc::Sender<io::Result<bytes::Bytes>>,
+    buffer: Vec<u8>,
+}
+
+impl ChannelWriter {
+    const CHUNK_SIZE: usize = 64 * 1024;
+
+    pub fn new(tx: tokio::sync::mpsc::Sender<io::Result<bytes::Bytes>>) -> ChannelWriter {
+        ChannelWriter {
+            tx,
+            buffer: Vec::with_capacity(Self::CHUNK_SIZE),
+        }
+    }
+
+    /// Pass an error on to the receiver so it does not mistake a partial
+    /// output for a complete one
+    pub fn fail(self, error: io::Error) {
+        let _ = self.tx.blocking_send(Err(error));
+    }
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        if self.buffer.len() >= Self::CHUNK_SIZE {
+            self.flush()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let chunk = bytes::Bytes::from(std::mem::replace(
+            &mut self.buffer,
+            Vec::with_capacity(Self::CHUNK_SIZE),
+        ));
+        self.tx
+            .blocking_send(Ok(chunk))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "Receiver dropped"))
+    }
+}
+
 /// Validate a script for dangerous patterns before execution.
 ///
 /// NOTE: This is NOT a full security solution. It is a basic sanity check
@@ -283,6 +754,25 @@ pub fn validate_script(path: &std::path::PathBuf) -> Result<(), ClientError> {
         .collect()
     });
 
+    let content = read_script(path)?;
+
+    for (re, description) in &*DANGEROUS_PATTERNS {
+        if re.is_match(&content) {
+            return Err(ClientError::UnsafeScript {
+                reason: description.to_string(),
+            });
+        }
+    }
+
+    require_exit_trap(&content)
+}
+
+/// Check only the exit trap, for sandbox profiles that trust their scripts
+pub fn validate_exit_trap(path: &std::path::PathBuf) -> Result<(), ClientError> {
+    require_exit_trap(&read_script(path)?)
+}
+
+fn read_script(path: &std::path::PathBuf) -> Result<String, ClientError> {
     // Since the script will be loaded fully to memory, check its size!
     const MAX_SCRIPT_SIZE: u64 = 1024 * 1024 * 20; // 20 MiB
     let metadata = std::fs::metadata(path).map_err(|_| ClientError::NoExecScript)?;
@@ -292,18 +782,12 @@ pub fn validate_script(path: &std::path::PathBuf) -> Result<(), ClientError> {
         });
     }
     let bytes = std::fs::read(path).map_err(|_| ClientError::NoExecScript)?;
-    let content = String::from_utf8(bytes).map_err(|_| ClientError::UnsafeScript {
+    String::from_utf8(bytes).map_err(|_| ClientError::UnsafeScript {
         reason: "script is not valid UTF-8".to_string(),
-    })?;
-
-    for (re, description) in &*DANGEROUS_PATTERNS {
-        if re.is_match(&content) {
-            return Err(ClientError::UnsafeScript {
-                reason: description.to_string(),
-            });
-        }
-    }
+    })
+}
 
+fn require_exit_trap(content: &str) -> Result<(), ClientError> {
     // Ensure script has the required trap for exit code capture
     if !content.contains("trap")
         || !content.contains(".orchestrator.exit")
@@ -324,7 +808,287 @@ mod tests {
 
     use std::fs;
 
+    #[test]
+    fn test_validate_archive() {
+        let tempdir = TempDir::new().unwrap();
+        let src = tempdir.path().join("src");
+        fs::create_dir_all(&src).unwrap();
+        fs::write(src.join("result.pdb"), b"ATOM").unwrap();
+
+        let archive = tempdir.path().join("output.zip");
+        zip_directory(&src, &archive).unwrap();
+        assert_eq!(validate_archive(&archive).unwrap(), 1);
+
+        // Truncated, the central directory at the end is gone
+        let bytes = fs::read(&archive).unwrap();
+        fs::write(&archive, &bytes[..bytes.len() / 2]).unwrap();
+        assert!(validate_archive(&archive).is_err());
+
+        // Valid but empty
+        let empty = tempdir.path().join("empty");
+        fs::create_dir_all(&empty).unwrap();
+        zip_directory(&empty, &archive).unwrap();
+        assert!(validate_archive(&archive).is_err());
+
+        assert!(validate_archive(&tempdir.path().join("missing.zip")).is_err());
+    }
+
+    #[test]
+    fn test_extract_archive() {
+        let tempdir = TempDir::new().unwrap();
+        let src = tempdir.path().join("src");
+        fs::create_dir_all(src.join("nested")).unwrap();
+        fs::write(src.join("result.pdb"), b"ATOM").unwrap();
+        fs::write(src.join("nested").join("log.txt"), b"done").unwrap();
+
+        let dst = tempdir.path().join("job");
+        fs::create_dir_all(&dst).unwrap();
+        let archive = dst.join("output.zip");
+        zip_directory(&src, &archive).unwrap();
+
+        assert_eq!(extract_archive(&archive, &dst).unwrap(), 2);
+        assert_eq!(fs::read(dst.join("result.pdb")).unwrap(), b"ATOM");
+        assert_eq!(
+            fs::read(dst.join("nested").join("log.txt")).unwrap(),
+            b"done"
+        );
+        assert!(archive.exists());
+    }
+
+    #[test]
+    fn test_pack_and_unpack_directory() {
+        let tempdir = TempDir::new().unwrap();
+        let dir = tempdir.path().join("job");
+        fs::create_dir_all(dir.join("inputs").join("deep")).unwrap();
+        fs::write(dir.join("run.sh"), b"echo").unwrap();
+        fs::write(dir.join("inputs").join("deep").join("a.pdb"), b"ATOM").unwrap();
+        fs::write(dir.join("output.zip"), b"results").unwrap();
+        let archive = dir.join(".packed.zip");
+
+        assert_eq!(pack_directory(&dir, &archive, &["output.zip"]).unwrap(), 2);
+        let mut left: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        left.sort();
+        assert_eq!(left, vec![".packed.zip", "output.zip"]);
+
+        assert_eq!(unpack_directory(&dir, &archive).unwrap(), 2);
+        assert!(!archive.exists());
+        assert_eq!(fs::read(dir.join("run.sh")).unwrap(), b"echo");
+        assert_eq!(
+            fs::read(dir.join("inputs").join("deep").join("a.pdb")).unwrap(),
+            b"ATOM"
+        );
+        assert_eq!(fs::read(dir.join("output.zip")).unwrap(), b"results");
+    }
+
+    #[test]
+    fn test_extract_archive_zip_slip() {
+        let tempdir = TempDir::new().unwrap();
+        let dst = tempdir.path().join("job");
+        fs::create_dir_all(&dst).unwrap();
+        let archive = dst.join("output.zip");
+
+        let mut zip = ZipWriter::new(File::create(&archive).unwrap());
+        zip.start_file("../escaped.txt", FileOptions::<()>::default())
+            .unwrap();
+        zip.write_all(b"gotcha").unwrap();
+        zip.finish().unwrap();
+
+        let err = extract_archive(&archive, &dst).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        assert!(!tempdir.path().join("escaped.txt").exists());
+    }
+
+    #[test]
+    fn test_extract_archive_symlinks() {
+        let tempdir = TempDir::new().unwrap();
+        let outside = tempdir.path().join("outside");
+        fs::create_dir_all(&outside).unwrap();
+        let dst = tempdir.path().join("job");
+        fs::create_dir_all(&dst).unwrap();
+
+        // A link entry in the archive
+        let archive = tempdir.path().join("link.zip");
+        let mut zip = ZipWriter::new(File::create(&archive).unwrap());
+        zip.add_symlink(
+            "escape",
+            outside.to_str().unwrap(),
+            FileOptions::<()>::default(),
+        )
+        .unwrap();
+        zip.start_file("escape/passwd", FileOptions::<()>::default())
+            .unwrap();
+        zip.write_all(b"gotcha").unwrap();
+        zip.finish().unwrap();
+
+        let err = extract_archive(&archive, &dst).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        assert!(!dst.join("escape").exists());
+
+        // A link already in the destination
+        std::os::unix::fs::symlink(&outside, dst.join("escape")).unwrap();
+        let archive = tempdir.path().join("through.zip");
+        let mut zip = ZipWriter::new(File::create(&archive).unwrap());
+        zip.start_file("escape/passwd", FileOptions::<()>::default())
+            .unwrap();
+        zip.write_all(b"gotcha").unwrap();
+        zip.finish().unwrap();
+
+        let err = extract_archive(&archive, &dst).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        assert!(!outside.join("passwd").exists());
+    }
+
+    #[test]
+    fn test_extract_archive_bomb() {
+        let tempdir = TempDir::new().unwrap();
+        let dst = tempdir.path().join("job");
+        fs::create_dir_all(&dst).unwrap();
+        let archive = tempdir.path().join("bomb.zip");
+
+        let options =
+            FileOptions::<()>::default().compression_method(zip::CompressionMethod::Deflated);
+        let mut zip = ZipWriter::new(File::create(&archive).unwrap());
+        for name in ["a.txt", "b.txt"] {
+            zip.start_file(name, options).unwrap();
+            zip.write_all(&[0; 600]).unwrap();
+        }
+        zip.finish().unwrap();
+
+        assert_eq!(extract_archive_within(&archive, &dst, 1200, 2).unwrap(), 2);
+
+        let err = extract_archive_within(&archive, &dst, 1000, 2).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::FileTooLarge);
+        assert!(!dst.join("b.txt").exists());
+
+        let err = extract_archive_within(&archive, &dst, 1200, 1).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::FileTooLarge);
+    }
+
+    #[test]
+    fn test_write_combined_archive() {
+        let tempdir = TempDir::new().unwrap();
+        let mut members = Vec::new();
+        for id in [1, 2] {
+            let src = tempdir.path().join(format!("src{id}"));
+            fs::create_dir_all(src.join("run")).unwrap();
+            fs::write(src.join("run").join("model.pdb"), format!("ATOM {id}")).unwrap();
+            let archive = tempdir.path().join(format!("output{id}.zip"));
+            zip_directory(&src, &archive).unwrap();
+            members.push((format!("job-{id}"), archive));
+        }
+
+        let bytes = write_combined_archive(&members, Vec::new()).unwrap();
+
+        let mut combined = zip::ZipArchive::new(std::io::Cursor::new(bytes)).unwrap();
+        for id in [1, 2] {
+            let mut content = String::new();
+            combined
+                .by_name(&format!("job-{id}/run/model.pdb"))
+                .unwrap()
+                .read_to_string(&mut content)
+                .unwrap();
+            assert_eq!(content, format!("ATOM {id}"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_channel_writer() {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(4);
+        let data = vec![7u8; ChannelWriter::CHUNK_SIZE + 10];
+
+        let expected = data.clone();
+        let writer = tokio::task::spawn_blocking(move || {
+            let mut writer = ChannelWriter::new(tx);
+            writer.write_all(&data).unwrap();
+            writer.flush().unwrap();
+        });
+
+        let mut received = Vec::new();
+        while let Some(chunk) = rx.recv().await {
+            received.extend_from_slice(&chunk.unwrap());
+        }
+        writer.await.unwrap();
+        assert_eq!(received, expected);
+    }
+
     // ===== validate_script tests =====
+    #[test]
+    fn test_list_job_dirs_with_tenants() {
+        let tempdir = tempfile::TempDir::new().unwrap();
+        std::fs::create_dir_all(tempdir.path().join("job1")).unwrap();
+        std::fs::create_dir_all(tempdir.path().join("lab").join("job2")).unwrap();
+        std::fs::write(tempdir.path().join("stray.txt"), b"x").unwrap();
+        // Quarantined submissions are no jobs
+        std::fs::create_dir_all(tempdir.path().join(".quarantine").join("3")).unwrap();
+        std::fs::create_dir_all(tempdir.path().join("lab").join(".quarantine")).unwrap();
+
+        let mut dirs =
+            list_job_dirs(tempdir.path().to_str().unwrap(), &["lab".to_string()], 1).unwrap();
+        dirs.sort();
+
+        assert_eq!(
+            dirs,
+            vec![
+                (DEFAULT_TENANT.to_string(), tempdir.path().join("job1")),
+                ("lab".to_string(), tempdir.path().join("lab").join("job2")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_copy_dir() {
+        let tempdir = TempDir::new().unwrap();
+        let from = tempdir.path().join("from");
+        fs::create_dir_all(from.join("nested")).unwrap();
+        fs::write(from.join("run.sh"), b"echo").unwrap();
+        fs::write(from.join("nested").join("a.pdb"), b"ATOM").unwrap();
+
+        let to = tempdir.path().join("to");
+        copy_dir(&from, &to).unwrap();
+        assert_eq!(fs::read(to.join("run.sh")).unwrap(), b"echo");
+        assert_eq!(fs::read(to.join("nested").join("a.pdb")).unwrap(), b"ATOM");
+
+        let moved = tempdir.path().join("moved");
+        move_dir(&from, &moved).unwrap();
+        assert!(!from.exists());
+        assert!(moved.join("nested").join("a.pdb").exists());
+    }
+
+    #[test]
+    fn test_list_job_dirs_nested() {
+        let tempdir = tempfile::TempDir::new().unwrap();
+        let root = tempdir.path();
+        std::fs::create_dir_all(root.join("haddock").join("job1")).unwrap();
+        std::fs::create_dir_all(root.join("lab").join("haddock").join("job2")).unwrap();
+        std::fs::create_dir_all(root.join("lab").join("empty")).unwrap();
+        std::fs::write(root.join("haddock").join("stray.txt"), b"x").unwrap();
+
+        let mut dirs = list_job_dirs(root.to_str().unwrap(), &["lab".to_string()], 2).unwrap();
+        dirs.sort();
+
+        assert_eq!(
+            dirs,
+            vec![
+                (
+                    DEFAULT_TENANT.to_string(),
+                    root.join("haddock").join("job1")
+                ),
+                (
+                    "lab".to_string(),
+                    root.join("lab").join("haddock").join("job2")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_list_job_dirs_missing_path() {
+        assert!(list_job_dirs("/nonexistent/path/does/not/exist", &[], 1).is_err());
+    }
+
     #[test]
     fn test_validate_script_non_utf8() {
         let temp_dir = tempfile::tempdir().unwrap();
@@ -359,6 +1123,27 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_validate_exit_trap() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let script_path = temp_dir.path().join("run.sh");
+
+        // Patterns are not scanned, only the trap is required
+        fs::write(
+            &script_path,
+            b"#!/bin/bash\ntrap 'echo $? > .orchestrator.exit' EXIT\ncurl http://example.com\n",
+        )
+        .unwrap();
+        assert!(validate_exit_trap(&script_path).is_ok());
+        assert!(validate_script(&script_path).is_err());
+
+        fs::write(&script_path, b"#!/bin/bash\nexit 0\n").unwrap();
+        assert!(matches!(
+            validate_exit_trap(&script_path),
+            Err(ClientError::MissingRequirement { .. })
+        ));
+    }
+
     #[test]
     fn test_validate_script_rm_rf() {
         let temp_dir = tempfile::tempdir().unwrap();
@@ -613,6 +1398,108 @@ mod tests {
         assert_eq!(sanitize_filename("Ñoño.pdf"), "Ñoño.pdf");
     }
 
+    #[test]
+    fn test_read_tail() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("stdout.log");
+        fs::write(&path, b"first\nsecond\n").unwrap();
+
+        assert_eq!(
+            read_tail(&path, 64).unwrap(),
+            (b"first\nsecond\n".to_vec(), false)
+        );
+        assert_eq!(read_tail(&path, 7).unwrap(), (b"second\n".to_vec(), true));
+        assert!(read_tail(&dir.path().join("missing.log"), 7).is_err());
+
+        assert_eq!(keep_tail(&path, 64).unwrap(), 0);
+        assert_eq!(keep_tail(&path, 7).unwrap(), 6);
+        assert_eq!(fs::read(&path).unwrap(), b"second\n");
+        assert!(!dir.path().join("stdout.partial").exists());
+    }
+
+    #[test]
+    fn test_shrink_to_tail() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("stdout.log");
+        let mut writer = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .unwrap();
+        writer.write_all(b"first\nsecond\n").unwrap();
+
+        assert_eq!(shrink_to_tail(&path, 64).unwrap(), 0);
+        assert_eq!(shrink_to_tail(&path, 7).unwrap(), 6);
+        assert_eq!(fs::read(&path).unwrap(), b"second\n");
+
+        // The writer goes on at the new end of the file
+        writer.write_all(b"third\n").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"second\nthird\n");
+    }
+
+    #[test]
+    fn test_read_new_lines() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("stdout.log");
+        fs::write(&path, b"first\nsec").unwrap();
+
+        let mut offset = 0;
+        let (lines, at_end) = read_new_lines(&path, &mut offset, 64, false).unwrap();
+        assert_eq!(lines, vec!["first"]);
+        assert!(!at_end);
+        assert_eq!(offset, 6);
+
+        // The partial line waits until it is complete
+        let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"ond\r\n10%\r50%\n\nlast").unwrap();
+        let (lines, _) = read_new_lines(&path, &mut offset, 64, false).unwrap();
+        assert_eq!(lines, vec!["second", "50%", ""]);
+        let (lines, at_end) = read_new_lines(&path, &mut offset, 64, true).unwrap();
+        assert_eq!(lines, vec!["last"]);
+        assert!(at_end);
+        let (lines, at_end) = read_new_lines(&path, &mut offset, 64, true).unwrap();
+        assert!(lines.is_empty());
+        assert!(at_end);
+
+        // A line longer than what is read at once comes in pieces
+        fs::write(&path, b"abcdef").unwrap();
+        let mut offset = 0;
+        let (lines, at_end) = read_new_lines(&path, &mut offset, 4, false).unwrap();
+        assert_eq!(lines, vec!["abcd"]);
+        assert!(!at_end);
+
+        // Rewritten shorter, followed from its end
+        let mut offset = 100;
+        let (lines, at_end) = read_new_lines(&path, &mut offset, 64, false).unwrap();
+        assert!(lines.is_empty());
+        assert!(at_end);
+        assert_eq!(offset, 6);
+    }
+
+    #[test]
+    fn test_newest_mtime() {
+        let dir = TempDir::new().unwrap();
+        let nested = dir.path().join("nested");
+        fs::create_dir(&nested).unwrap();
+        let skipped = dir.path().join("skipped.txt");
+        fs::write(&skipped, b"skipped").unwrap();
+        fs::write(nested.join("file.txt"), b"data").unwrap();
+
+        let later = SystemTime::now() + std::time::Duration::from_secs(60);
+        let set = |path: &Path, time: SystemTime| {
+            File::options()
+                .write(true)
+                .open(path)
+                .unwrap()
+                .set_modified(time)
+                .unwrap()
+        };
+        set(&skipped, later + std::time::Duration::from_secs(60));
+        set(&nested.join("file.txt"), later);
+
+        assert_eq!(newest_mtime(dir.path(), &skipped).unwrap(), later);
+    }
+
     // ===== zip_directory tests =====
 
     #[test]
@@ -842,6 +1729,34 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_zip_directory_skips_special_files() -> zip::result::ZipResult<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let outside = temp_dir.path().join("outside");
+        std::fs::create_dir(&outside).unwrap();
+        std::fs::write(outside.join("secret.txt"), b"secret").unwrap();
+        let src_dir = temp_dir.path().join("source");
+        std::fs::create_dir(&src_dir).unwrap();
+        std::fs::write(src_dir.join("real.txt"), b"real").unwrap();
+
+        std::os::unix::fs::symlink(src_dir.join("real.txt"), src_dir.join("inside")).unwrap();
+        std::os::unix::fs::symlink(outside.join("secret.txt"), src_dir.join("escape")).unwrap();
+        std::os::unix::fs::symlink(&outside, src_dir.join("escape_dir")).unwrap();
+        // Reading a FIFO nobody writes to would never return
+        let status = std::process::Command::new("mkfifo")
+            .arg(src_dir.join("pipe"))
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let bytes = zip_directory_to_bytes(&src_dir)?;
+        let archive = zip::ZipArchive::new(std::io::Cursor::new(bytes))?;
+        let names: Vec<_> = archive.file_names().collect();
+        assert_eq!(names, vec!["real.txt"]);
+
+        Ok(())
+    }
+
     #[test]
     fn test_zip_directory_to_bytes_nonexistent_source() {
         let temp_dir = TempDir::new().unwrap();
@@ -1,2 +1,16 @@
+pub mod chaos;
+pub mod compression;
+pub mod download_link;
+pub mod environment;
 pub mod io;
+pub mod progress;
+pub mod quarantine;
+pub mod result_summary;
+pub mod sandbox;
+pub mod scratch;
+pub mod session;
+pub mod storage;
 pub mod sys;
+pub mod template;
+pub mod warmup;
+pub mod window;
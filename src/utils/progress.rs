@@ -0,0 +1,166 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use utoipa::ToSchema;
+
+/// Names the upload session of a submission, sent by the orchestrator
+pub const UPLOAD_ID_HEADER: &str = "x-upload-id";
+
+/// Total size in bytes of the files in a submission, sent by the orchestrator
+pub const UPLOAD_SIZE_HEADER: &str = "x-upload-size";
+
+/// How long a finished session can still be polled
+const FINISHED_RETENTION: Duration = Duration::from_secs(300);
+
+/// Bytes received so far for one upload session
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct UploadProgress {
+    /// File bytes received over all files
+    pub received: u64,
+    /// Total file bytes announced by the sender, if any
+    pub expected: Option<u64>,
+    /// Bytes received per file
+    pub files: BTreeMap<String, u64>,
+    /// The request ended, successfully or not
+    pub finished: bool,
+    /// Seconds since the last bytes arrived, tells a slow transfer from a hung one
+    pub idle_secs: u64,
+}
+
+#[derive(Debug)]
+struct Session {
+    received: u64,
+    expected: Option<u64>,
+    files: BTreeMap<String, u64>,
+    finished: bool,
+    last_activity: Instant,
+}
+
+/// In-memory record of the submissions being received by the client
+#[derive(Debug, Clone, Default)]
+pub struct UploadTracker {
+    sessions: Arc<Mutex<HashMap<String, Session>>>,
+    /// Submissions refused since the client started, by reason
+    rejections: Arc<Mutex<BTreeMap<String, u64>>>,
+}
+
+impl UploadTracker {
+    /// Open a session, it is marked finished when the returned handle is dropped
+    pub fn start(&self, id: String, expected: Option<u64>) -> UploadSession {
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|_, s| !s.finished || s.last_activity.elapsed() < FINISHED_RETENTION);
+        sessions.insert(
+            id.clone(),
+            Session {
+                received: 0,
+                expected,
+                files: BTreeMap::new(),
+                finished: false,
+                last_activity: Instant::now(),
+            },
+        );
+        UploadSession {
+            tracker: self.clone(),
+            id,
+        }
+    }
+
+    pub fn progress(&self, id: &str) -> Option<UploadProgress> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .get(id)
+            .map(|s| UploadProgress {
+                received: s.received,
+                expected: s.expected,
+                files: s.files.clone(),
+                finished: s.finished,
+                idle_secs: s.last_activity.elapsed().as_secs(),
+            })
+    }
+
+    /// Count a submission refused for `reason`, e.g. `malformed`
+    pub fn reject(&self, reason: &str) {
+        *self
+            .rejections
+            .lock()
+            .unwrap()
+            .entry(reason.to_string())
+            .or_default() += 1;
+    }
+
+    pub fn rejections(&self) -> BTreeMap<String, u64> {
+        self.rejections.lock().unwrap().clone()
+    }
+}
+
+/// Handle to a session opened with [`UploadTracker::start`]
+pub struct UploadSession {
+    tracker: UploadTracker,
+    id: String,
+}
+
+impl UploadSession {
+    pub fn record(&self, file: &str, bytes: usize) {
+        if let Some(s) = self.tracker.sessions.lock().unwrap().get_mut(&self.id) {
+            s.received += bytes as u64;
+            *s.files.entry(file.to_string()).or_default() += bytes as u64;
+            s.last_activity = Instant::now();
+        }
+    }
+}
+
+impl Drop for UploadSession {
+    fn drop(&mut self) {
+        if let Ok(mut sessions) = self.tracker.sessions.lock()
+            && let Some(s) = sessions.get_mut(&self.id)
+        {
+            s.finished = true;
+            s.last_activity = Instant::now();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upload_tracker() {
+        let tracker = UploadTracker::default();
+        assert_eq!(tracker.progress("abc"), None);
+
+        let session = tracker.start("abc".to_string(), Some(30));
+        session.record("a.txt", 10);
+        session.record("b.txt", 5);
+        session.record("a.txt", 10);
+
+        let progress = tracker.progress("abc").unwrap();
+        assert_eq!(progress.received, 25);
+        assert_eq!(progress.expected, Some(30));
+        assert_eq!(progress.files["a.txt"], 20);
+        assert_eq!(progress.files["b.txt"], 5);
+        assert!(!progress.finished);
+
+        drop(session);
+        assert!(tracker.progress("abc").unwrap().finished);
+    }
+
+    #[test]
+    fn test_upload_tracker_rejections() {
+        let tracker = UploadTracker::default();
+        assert!(tracker.rejections().is_empty());
+
+        tracker.reject("malformed");
+        tracker.clone().reject("malformed");
+        tracker.reject("too_many_fields");
+        assert_eq!(
+            tracker.rejections(),
+            BTreeMap::from([
+                ("malformed".to_string(), 2),
+                ("too_many_fields".to_string(), 1)
+            ])
+        );
+    }
+}
@@ -0,0 +1,268 @@
+use crate::config::loader::UploadChecks;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// Directory, next to the payload directories, the submitted files are
+/// written to until they pass the checks. Being on the same filesystem, the
+/// files reach the payload directory in one rename.
+pub const QUARANTINE_DIR: &str = ".quarantine";
+
+/// A scanner that hangs must not hold the submission forever
+const SCAN_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Read in full to look for the denied text, whatever their first line
+const SCRIPT_EXTENSIONS: [&str; 3] = ["sh", "bash", "py"];
+
+/// Bytes of a script read at once when looking for the denied text
+const SCAN_CHUNK: usize = 64 * 1024;
+
+#[derive(Debug, thiserror::Error)]
+pub enum QuarantineError {
+    /// A file failed one of the checks, sending it again will not help
+    #[error("{0}")]
+    Rejected(String),
+    /// The scanner could not tell, e.g. it is missing or timed out
+    #[error("could not scan the files: {0}")]
+    ScanFailed(String),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// Check the files in `dir`, the first one failing a check is reported
+pub fn validate(dir: &Path, checks: &UploadChecks) -> Result<(), QuarantineError> {
+    let mut entries = fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|e| e.file_name());
+
+    for entry in entries {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        if let Some(max) = checks.max_file_size
+            && entry.metadata()?.len() > max
+        {
+            return Err(QuarantineError::Rejected(format!(
+                "{name} is larger than {max} bytes"
+            )));
+        }
+        if !checks.allowed_extensions.is_empty() && !checks.allowed_extensions.contains(&extension)
+        {
+            return Err(QuarantineError::Rejected(format!(
+                "{name} does not have an accepted extension"
+            )));
+        }
+        if !checks.script_denylist.is_empty()
+            && is_script(&path, &extension)?
+            && let Some(denied) = find_denied(&path, &checks.script_denylist)?
+        {
+            return Err(QuarantineError::Rejected(format!(
+                "{name} contains the denied '{denied}'"
+            )));
+        }
+    }
+
+    match &checks.av_command {
+        Some(command) => scan(command, dir),
+        None => Ok(()),
+    }
+}
+
+/// Whether `path` is a script, from its extension or else its `#!` line. Only
+/// the first two bytes of the other files are read.
+fn is_script(path: &Path, extension: &str) -> io::Result<bool> {
+    if SCRIPT_EXTENSIONS.contains(&extension) {
+        return Ok(true);
+    }
+    let mut start = Vec::with_capacity(2);
+    File::open(path)?.take(2).read_to_end(&mut start)?;
+    Ok(start == b"#!")
+}
+
+/// The first entry of `denylist` in the file at `path`, read a chunk at a
+/// time. The end of each chunk is kept in front of the next one, so text
+/// spanning two chunks is found too.
+fn find_denied<'a>(path: &Path, denylist: &'a [String]) -> io::Result<Option<&'a str>> {
+    let keep = denylist.iter().map(String::len).max().unwrap_or(0);
+    let mut file = File::open(path)?;
+    let mut chunk = vec![0; SCAN_CHUNK];
+    let mut window = Vec::with_capacity(keep + SCAN_CHUNK);
+    loop {
+        let read = match file.read(&mut chunk) {
+            Ok(0) => return Ok(None),
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        window.extend_from_slice(&chunk[..read]);
+        if let Some(denied) = denylist.iter().find(|d| contains(&window, d.as_bytes())) {
+            return Ok(Some(denied));
+        }
+        window.drain(..window.len().saturating_sub(keep));
+    }
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    needle.is_empty() || haystack.windows(needle.len()).any(|w| w == needle)
+}
+
+/// Run the scanner on `dir`: 0 is clean, 1 found something, anything else
+/// is taken as a failure of the scanner
+fn scan(command: &str, dir: &Path) -> Result<(), QuarantineError> {
+    let mut args = command.split_whitespace();
+    let program = args
+        .next()
+        .ok_or_else(|| QuarantineError::ScanFailed("empty command".to_string()))?;
+    let mut child = Command::new(program)
+        .args(args)
+        .arg(dir)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| QuarantineError::ScanFailed(format!("{program}: {e}")))?;
+
+    let deadline = Instant::now() + SCAN_TIMEOUT;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() > deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(QuarantineError::ScanFailed(format!(
+                "{program} did not finish within {SCAN_TIMEOUT:?}"
+            )));
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    };
+
+    match status.code() {
+        Some(0) => Ok(()),
+        Some(1) => Err(QuarantineError::Rejected(format!(
+            "{program} flagged the files"
+        ))),
+        _ => Err(QuarantineError::ScanFailed(format!(
+            "{program} exited with {status}"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn quarantine(files: &[(&str, &str)]) -> TempDir {
+        let dir = TempDir::new().unwrap();
+        for (name, content) in files {
+            fs::write(dir.path().join(name), content).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn test_validate_without_checks() {
+        let dir = quarantine(&[("run.sh", "curl example.org"), ("data", "")]);
+        assert!(validate(dir.path(), &UploadChecks::default()).is_ok());
+    }
+
+    #[test]
+    fn test_validate_size() {
+        let dir = quarantine(&[("small.txt", "abc"), ("large.txt", "abcdef")]);
+        let checks = UploadChecks {
+            max_file_size: Some(5),
+            ..Default::default()
+        };
+        match validate(dir.path(), &checks) {
+            Err(QuarantineError::Rejected(reason)) => assert!(reason.starts_with("large.txt")),
+            other => panic!("expected a rejection, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_validate_extension() {
+        let checks = UploadChecks {
+            allowed_extensions: vec!["sh".to_string(), "pdb".to_string()],
+            ..Default::default()
+        };
+        let dir = quarantine(&[("run.sh", ""), ("INPUT.PDB", "")]);
+        assert!(validate(dir.path(), &checks).is_ok());
+
+        let dir = quarantine(&[("run.sh", ""), ("tool.exe", "")]);
+        assert!(matches!(
+            validate(dir.path(), &checks),
+            Err(QuarantineError::Rejected(_))
+        ));
+        let dir = quarantine(&[("Makefile", "")]);
+        assert!(matches!(
+            validate(dir.path(), &checks),
+            Err(QuarantineError::Rejected(_))
+        ));
+    }
+
+    #[test]
+    fn test_validate_script_denylist() {
+        let checks = UploadChecks {
+            script_denylist: vec!["curl".to_string()],
+            ..Default::default()
+        };
+        let dir = quarantine(&[("run.sh", "echo hello"), ("notes.txt", "curl")]);
+        assert!(validate(dir.path(), &checks).is_ok());
+
+        for (name, content) in [("run.sh", "curl example.org"), ("run", "#!/bin/sh\ncurl x")] {
+            let dir = quarantine(&[(name, content)]);
+            match validate(dir.path(), &checks) {
+                Err(QuarantineError::Rejected(reason)) => assert!(reason.contains("'curl'")),
+                other => panic!("expected a rejection, got {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_validate_script_denylist_across_chunks() {
+        let checks = UploadChecks {
+            script_denylist: vec!["curl".to_string()],
+            ..Default::default()
+        };
+        // The denied text starts in the first chunk and ends in the second
+        let script = format!("{}curl x", " ".repeat(SCAN_CHUNK - 2));
+        let dir = quarantine(&[("run.sh", &script)]);
+        assert!(matches!(
+            validate(dir.path(), &checks),
+            Err(QuarantineError::Rejected(_))
+        ));
+
+        // The same text outside a script is not looked at
+        let dir = quarantine(&[("data.bin", &script)]);
+        assert!(validate(dir.path(), &checks).is_ok());
+    }
+
+    #[test]
+    fn test_validate_av_command() {
+        let dir = quarantine(&[("run.sh", "echo")]);
+        let with = |command: &str| UploadChecks {
+            av_command: Some(command.to_string()),
+            ..Default::default()
+        };
+
+        assert!(validate(dir.path(), &with("test -d")).is_ok());
+        assert!(matches!(
+            validate(dir.path(), &with("false")),
+            Err(QuarantineError::Rejected(_))
+        ));
+        assert!(matches!(
+            validate(dir.path(), &with("ls /does-not-exist")),
+            Err(QuarantineError::ScanFailed(_))
+        ));
+        assert!(matches!(
+            validate(dir.path(), &with("does-not-exist")),
+            Err(QuarantineError::ScanFailed(_))
+        ));
+    }
+}
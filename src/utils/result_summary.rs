@@ -0,0 +1,180 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::Path;
+use utoipa::ToSchema;
+
+/// File `run.sh` may write in its directory to report its headline numbers
+pub const RESULT_FILE: &str = "result.json";
+
+/// Larger files are ignored, the summary is stored in the database and
+/// served with every job
+pub const MAX_RESULT_SIZE: u64 = 64 * 1024;
+
+const MAX_STATUS_LEN: usize = 256;
+const MAX_METRICS: usize = 100;
+const MAX_WARNINGS: usize = 100;
+const MAX_WARNING_LEN: usize = 1024;
+
+/// Contents of `result.json`, every field is optional and other fields are
+/// ignored
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ResultSummary {
+    /// Outcome in the words of the script, e.g. `converged`
+    #[serde(default)]
+    pub status: Option<String>,
+    /// Key metrics by name, each a string, a number or a boolean
+    #[serde(default)]
+    #[schema(value_type = Object)]
+    pub metrics: BTreeMap<String, Value>,
+    #[serde(default)]
+    pub warnings: Vec<String>,
+}
+
+impl ResultSummary {
+    /// Read and check the `result.json` content in `bytes`
+    pub fn parse(bytes: &[u8]) -> Result<ResultSummary, String> {
+        if bytes.len() as u64 > MAX_RESULT_SIZE {
+            return Err(format!("larger than {MAX_RESULT_SIZE} bytes"));
+        }
+        let summary: ResultSummary =
+            serde_json::from_slice(bytes).map_err(|e| format!("not valid: {e}"))?;
+        summary.validate()?;
+        Ok(summary)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self
+            .status
+            .as_ref()
+            .is_some_and(|s| s.chars().count() > MAX_STATUS_LEN)
+        {
+            return Err(format!("status exceeds {MAX_STATUS_LEN} characters"));
+        }
+        if self.metrics.len() > MAX_METRICS {
+            return Err(format!("more than {MAX_METRICS} metrics"));
+        }
+        if let Some((name, _)) = self
+            .metrics
+            .iter()
+            .find(|(_, v)| !matches!(v, Value::String(_) | Value::Number(_) | Value::Bool(_)))
+        {
+            return Err(format!(
+                "metric {name} must be a string, a number or a boolean"
+            ));
+        }
+        if self.warnings.len() > MAX_WARNINGS {
+            return Err(format!("more than {MAX_WARNINGS} warnings"));
+        }
+        if self
+            .warnings
+            .iter()
+            .any(|w| w.chars().count() > MAX_WARNING_LEN)
+        {
+            return Err(format!("a warning exceeds {MAX_WARNING_LEN} characters"));
+        }
+        Ok(())
+    }
+
+    /// The summary `run.sh` left in `dir`, `None` when it wrote none or an
+    /// invalid one, which is logged
+    pub fn read(dir: &Path) -> Option<ResultSummary> {
+        let path = dir.join(RESULT_FILE);
+        // Not followed, a link could point anywhere on the client
+        let metadata = std::fs::symlink_metadata(&path).ok()?;
+        if !metadata.is_file() {
+            tracing::warn!("Ignoring {}, not a regular file", path.display());
+            return None;
+        }
+        if metadata.len() > MAX_RESULT_SIZE {
+            tracing::warn!(
+                "Ignoring {}, larger than {MAX_RESULT_SIZE} bytes",
+                path.display()
+            );
+            return None;
+        }
+        let parsed = std::fs::read(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| ResultSummary::parse(&bytes));
+        match parsed {
+            Ok(summary) => Some(summary),
+            Err(e) => {
+                tracing::warn!("Ignoring {}: {e}", path.display());
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse() {
+        let summary = ResultSummary::parse(
+            br#"{"status": "converged", "metrics": {"rmsd": 1.2, "model": "m1", "clashes": 0},
+                 "warnings": ["2 chains skipped"], "version": 3}"#,
+        )
+        .unwrap();
+        assert_eq!(summary.status.as_deref(), Some("converged"));
+        assert_eq!(summary.metrics["rmsd"], json!(1.2));
+        assert_eq!(summary.metrics["model"], json!("m1"));
+        assert_eq!(summary.warnings, vec!["2 chains skipped"]);
+
+        // Every field is optional
+        assert_eq!(
+            ResultSummary::parse(b"{}").unwrap(),
+            ResultSummary::default()
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(ResultSummary::parse(b"not json").is_err());
+        assert!(ResultSummary::parse(b"[1, 2]").is_err());
+        assert!(ResultSummary::parse(br#"{"warnings": "one"}"#).is_err());
+        assert_eq!(
+            ResultSummary::parse(br#"{"metrics": {"chains": ["A", "B"]}}"#),
+            Err("metric chains must be a string, a number or a boolean".to_string())
+        );
+        let many: BTreeMap<String, Value> = (0..=MAX_METRICS)
+            .map(|i| (i.to_string(), json!(i)))
+            .collect();
+        let content = serde_json::to_vec(&json!({ "metrics": many })).unwrap();
+        assert!(ResultSummary::parse(&content).is_err());
+        let large = vec![b' '; MAX_RESULT_SIZE as usize + 1];
+        assert!(ResultSummary::parse(&large).is_err());
+    }
+
+    #[test]
+    fn test_read() {
+        let tempdir = TempDir::new().unwrap();
+        assert_eq!(ResultSummary::read(tempdir.path()), None);
+
+        fs::write(tempdir.path().join(RESULT_FILE), r#"{"status": "ok"}"#).unwrap();
+        assert_eq!(
+            ResultSummary::read(tempdir.path())
+                .unwrap()
+                .status
+                .as_deref(),
+            Some("ok")
+        );
+
+        fs::write(tempdir.path().join(RESULT_FILE), "{").unwrap();
+        assert_eq!(ResultSummary::read(tempdir.path()), None);
+
+        // Links are not followed
+        fs::remove_file(tempdir.path().join(RESULT_FILE)).unwrap();
+        fs::write(tempdir.path().join("elsewhere.json"), "{}").unwrap();
+        std::os::unix::fs::symlink(
+            tempdir.path().join("elsewhere.json"),
+            tempdir.path().join(RESULT_FILE),
+        )
+        .unwrap();
+        assert_eq!(ResultSummary::read(tempdir.path()), None);
+    }
+}
@@ -0,0 +1,507 @@
+use crate::utils::scratch::SCRATCH_VAR;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+use utoipa::ToSchema;
+use walkdir::WalkDir;
+
+/// Wrapper used to isolate the network and the filesystem of a payload
+const BWRAP: &str = "bwrap";
+
+/// Hands the restricted `PATH` to the shell that starts the script
+const PINNED_PATH_VAR: &str = "ORCHESTRATOR_PINNED_PATH";
+
+/// Named bundle of isolation settings, selected per service so operators
+/// choose one profile instead of tuning every setting
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum SandboxProfile {
+    /// Scripts from outside parties: no network, read-only root, limits and validation
+    Untrusted,
+    /// Scripts written in-house: resource limits only
+    TrustedInternal,
+    /// Script validation only, how payloads ran before profiles existed
+    #[default]
+    Legacy,
+}
+
+/// Limits applied with `ulimit` before the script starts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceLimits {
+    pub cpu_seconds: u64,
+    pub memory_kib: u64,
+}
+
+/// Isolation settings a profile expands to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SandboxPolicy {
+    pub network: bool,
+    pub read_only_root: bool,
+    /// Scan `run.sh` for dangerous patterns, the exit trap is always required
+    pub validate_script: bool,
+    pub limits: Option<ResourceLimits>,
+}
+
+impl SandboxProfile {
+    pub const ALL: [SandboxProfile; 3] = [
+        SandboxProfile::Untrusted,
+        SandboxProfile::TrustedInternal,
+        SandboxProfile::Legacy,
+    ];
+
+    /// Profiles this host can apply, the ones needing `bwrap` only when it
+    /// is installed
+    pub fn available() -> Vec<SandboxProfile> {
+        let path = std::env::var_os("PATH").unwrap_or_default();
+        let wrapper = find_executable(BWRAP, &path).is_some();
+        SandboxProfile::ALL
+            .into_iter()
+            .filter(|p| wrapper || !p.policy().needs_wrapper())
+            .collect()
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SandboxProfile::Untrusted => "untrusted",
+            SandboxProfile::TrustedInternal => "trusted-internal",
+            SandboxProfile::Legacy => "legacy",
+        }
+    }
+
+    pub fn policy(&self) -> SandboxPolicy {
+        let limits = ResourceLimits {
+            cpu_seconds: 24 * 3600,
+            memory_kib: 16 * 1024 * 1024,
+        };
+        match self {
+            SandboxProfile::Untrusted => SandboxPolicy {
+                network: false,
+                read_only_root: true,
+                validate_script: true,
+                limits: Some(limits),
+            },
+            SandboxProfile::TrustedInternal => SandboxPolicy {
+                network: true,
+                read_only_root: false,
+                validate_script: false,
+                limits: Some(limits),
+            },
+            SandboxProfile::Legacy => SandboxPolicy {
+                network: true,
+                read_only_root: false,
+                validate_script: true,
+                limits: None,
+            },
+        }
+    }
+}
+
+impl FromStr for SandboxProfile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "untrusted" => Ok(SandboxProfile::Untrusted),
+            "trusted-internal" | "trusted_internal" => Ok(SandboxProfile::TrustedInternal),
+            "legacy" => Ok(SandboxProfile::Legacy),
+            other => Err(format!("Unknown sandbox profile: {other}")),
+        }
+    }
+}
+
+impl std::fmt::Display for SandboxProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl SandboxPolicy {
+    /// Whether the payload has to run inside `bwrap`
+    pub fn needs_wrapper(&self) -> bool {
+        !self.network || self.read_only_root
+    }
+
+    /// Command running `script` from `dir` with the policy applied, `path`
+    /// replaces the `PATH` the script sees and `scratch` is handed to it as
+    /// `SCRATCH_DIR`
+    pub fn command(
+        &self,
+        dir: &Path,
+        script: &Path,
+        path: Option<&OsStr>,
+        scratch: Option<&Path>,
+    ) -> Command {
+        // The limits and `PATH` are set by the shell itself right before it becomes the script
+        let mut prelude = Vec::new();
+        if let Some(limits) = self.limits {
+            prelude.push(format!(
+                "ulimit -t {} -v {}",
+                limits.cpu_seconds, limits.memory_kib
+            ));
+        }
+        if path.is_some() {
+            prelude.push(format!("export PATH=\"${PINNED_PATH_VAR}\""));
+        }
+
+        let mut shell: Vec<OsString> = vec!["bash".into()];
+        if !prelude.is_empty() {
+            prelude.push("exec \"$BASH\" \"$0\"".to_string());
+            shell.push("-c".into());
+            shell.push(prelude.join(" && ").into());
+        }
+        shell.push(script.into());
+
+        let mut command = if self.needs_wrapper() {
+            let mut command = Command::new(BWRAP);
+            let root = if self.read_only_root {
+                "--ro-bind"
+            } else {
+                "--bind"
+            };
+            command
+                .args([root, "/", "/"])
+                .args(["--dev", "/dev", "--proc", "/proc"]);
+            if self.read_only_root {
+                // Only the payload and scratch directories and a private /tmp stay writable
+                command.args(["--tmpfs", "/tmp"]);
+                command.arg("--bind").arg(dir).arg(dir);
+                if let Some(scratch) = scratch {
+                    command.arg("--bind").arg(scratch).arg(scratch);
+                }
+            }
+            if !self.network {
+                command.arg("--unshare-net");
+            }
+            command.arg("--die-with-parent").arg("--chdir").arg(dir);
+            command.arg("--").args(&shell);
+            command
+        } else {
+            let mut command = Command::new(&shell[0]);
+            command.args(&shell[1..]);
+            command
+        };
+        command.current_dir(dir);
+        if let Some(path) = path {
+            command.env(PINNED_PATH_VAR, path);
+        }
+        if let Some(scratch) = scratch {
+            command.env(SCRATCH_VAR, scratch);
+        }
+        command
+    }
+}
+
+/// A binary payloads may invoke, pinned to the SHA-256 of its content
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PinnedBinary {
+    pub path: PathBuf,
+    pub sha256: String,
+}
+
+impl PinnedBinary {
+    /// Check that the binary on disk still has the pinned hash
+    pub fn verify(&self) -> Result<(), String> {
+        let mut file =
+            File::open(&self.path).map_err(|e| format!("{}: {e}", self.path.display()))?;
+        let mut hasher = Sha256::new();
+        io::copy(&mut file, &mut hasher).map_err(|e| format!("{}: {e}", self.path.display()))?;
+
+        let digest = format!("{:x}", hasher.finalize());
+        if digest != self.sha256 {
+            return Err(format!(
+                "{} has hash {digest}, expected {}",
+                self.path.display(),
+                self.sha256
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for PinnedBinary {
+    type Err = String;
+
+    /// Parse a `<absolute path>=<sha256>` entry
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (path, sha256) = s
+            .trim()
+            .rsplit_once('=')
+            .ok_or_else(|| format!("Expected <path>=<sha256>, got: {s}"))?;
+        let path = PathBuf::from(path.trim());
+        let sha256 = sha256.trim().to_ascii_lowercase();
+
+        if !path.is_absolute() {
+            return Err(format!(
+                "Pinned binary path must be absolute: {}",
+                path.display()
+            ));
+        }
+        if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!("Invalid SHA-256 for {}: {sha256}", path.display()));
+        }
+        Ok(PinnedBinary { path, sha256 })
+    }
+}
+
+/// `PATH` for payloads when binaries are pinned: only the directories holding them
+pub fn pinned_path(pinned: &[PinnedBinary]) -> OsString {
+    let mut dirs: Vec<&Path> = Vec::new();
+    for dir in pinned.iter().filter_map(|p| p.path.parent()) {
+        if !dirs.contains(&dir) {
+            dirs.push(dir);
+        }
+    }
+    std::env::join_paths(dirs).unwrap_or_default()
+}
+
+/// First executable file called `name` in the directories of `path`
+pub fn find_executable(name: &str, path: &OsStr) -> Option<PathBuf> {
+    std::env::split_paths(path)
+        .map(|dir| dir.join(name))
+        .find(|candidate| {
+            fs::metadata(candidate)
+                .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        })
+}
+
+/// Files of a payload that could run as a tool of their own: native
+/// executables or anything with an executable bit, `run.sh` aside
+pub fn shipped_executables(dir: &Path, script: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut found = Vec::new();
+    for entry in WalkDir::new(dir).into_iter().filter_map(|e| e.ok()) {
+        if !entry.file_type().is_file() || entry.path() == script {
+            continue;
+        }
+
+        let executable = entry.metadata()?.permissions().mode() & 0o111 != 0;
+        let mut magic = [0u8; 4];
+        let native = File::open(entry.path())?.read(&mut magic)? == 4 && magic == *b"\x7fELF";
+        if executable || native {
+            found.push(entry.path().to_path_buf());
+        }
+    }
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(command: &Command) -> Vec<String> {
+        command
+            .get_args()
+            .map(|a| a.to_string_lossy().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_profile() {
+        assert_eq!(
+            "untrusted".parse::<SandboxProfile>(),
+            Ok(SandboxProfile::Untrusted)
+        );
+        assert_eq!(
+            "Trusted-Internal".parse::<SandboxProfile>(),
+            Ok(SandboxProfile::TrustedInternal)
+        );
+        assert_eq!(
+            "legacy".parse::<SandboxProfile>(),
+            Ok(SandboxProfile::Legacy)
+        );
+        assert!("open".parse::<SandboxProfile>().is_err());
+        assert_eq!(SandboxProfile::default(), SandboxProfile::Legacy);
+        assert_eq!(
+            SandboxProfile::TrustedInternal.to_string(),
+            "trusted-internal"
+        );
+    }
+
+    #[test]
+    fn test_legacy_command() {
+        let command = SandboxProfile::Legacy.policy().command(
+            Path::new("/data/1"),
+            Path::new("/data/1/run.sh"),
+            None,
+            None,
+        );
+
+        assert_eq!(command.get_program(), "bash");
+        assert_eq!(args(&command), vec!["/data/1/run.sh"]);
+        assert_eq!(command.get_current_dir(), Some(Path::new("/data/1")));
+    }
+
+    #[test]
+    fn test_trusted_internal_command() {
+        let policy = SandboxProfile::TrustedInternal.policy();
+        assert!(!policy.validate_script);
+        assert!(!policy.needs_wrapper());
+
+        let command = policy.command(
+            Path::new("/data/1"),
+            Path::new("/data/1/run.sh"),
+            None,
+            None,
+        );
+        let args = args(&command);
+        assert_eq!(command.get_program(), "bash");
+        assert_eq!(args[0], "-c");
+        assert!(args[1].starts_with("ulimit -t 86400 -v "));
+        assert_eq!(args[2], "/data/1/run.sh");
+    }
+
+    #[test]
+    fn test_untrusted_command() {
+        let policy = SandboxProfile::Untrusted.policy();
+        assert!(policy.validate_script);
+
+        let command = policy.command(
+            Path::new("/data/1"),
+            Path::new("/data/1/run.sh"),
+            None,
+            None,
+        );
+        let args = args(&command);
+        assert_eq!(command.get_program(), BWRAP);
+        assert_eq!(&args[..3], ["--ro-bind", "/", "/"]);
+        assert!(args.contains(&"--unshare-net".to_string()));
+        assert!(
+            args.windows(3)
+                .any(|w| w == ["--bind", "/data/1", "/data/1"])
+        );
+
+        // The script comes after the separator, wrapped by the limits
+        let separator = args.iter().position(|a| a == "--").unwrap();
+        assert_eq!(args[separator + 1], "bash");
+        assert_eq!(args.last().unwrap(), "/data/1/run.sh");
+    }
+
+    #[test]
+    fn test_scratch_command() {
+        let scratch = Path::new("/nvme/scratch/1");
+        for profile in [SandboxProfile::Untrusted, SandboxProfile::Legacy] {
+            let command = profile.policy().command(
+                Path::new("/data/1"),
+                Path::new("/data/1/run.sh"),
+                None,
+                Some(scratch),
+            );
+            assert!(
+                command
+                    .get_envs()
+                    .any(|(k, v)| k == SCRATCH_VAR && v == Some(scratch.as_os_str()))
+            );
+            // Writable under the read-only root
+            let bound = args(&command)
+                .windows(3)
+                .any(|w| w == ["--bind", "/nvme/scratch/1", "/nvme/scratch/1"]);
+            assert_eq!(bound, profile == SandboxProfile::Untrusted);
+        }
+    }
+
+    #[test]
+    fn test_pinned_path_command() {
+        let command = SandboxProfile::Legacy.policy().command(
+            Path::new("/data/1"),
+            Path::new("/data/1/run.sh"),
+            Some(OsStr::new("/opt/tools")),
+            None,
+        );
+        let args = args(&command);
+        assert_eq!(args[0], "-c");
+        assert!(args[1].starts_with(&format!("export PATH=\"${PINNED_PATH_VAR}\"")));
+        assert!(
+            command
+                .get_envs()
+                .any(|(k, v)| k == PINNED_PATH_VAR && v == Some(OsStr::new("/opt/tools")))
+        );
+    }
+
+    #[test]
+    fn test_parse_pinned_binary() {
+        let hash = "AB".repeat(32);
+        let pinned: PinnedBinary = format!("/usr/bin/tool={hash}").parse().unwrap();
+        assert_eq!(pinned.path, PathBuf::from("/usr/bin/tool"));
+        assert_eq!(pinned.sha256, hash.to_ascii_lowercase());
+
+        assert!("/usr/bin/tool".parse::<PinnedBinary>().is_err());
+        assert!(format!("tool={hash}").parse::<PinnedBinary>().is_err());
+        assert!("/usr/bin/tool=1234".parse::<PinnedBinary>().is_err());
+    }
+
+    #[test]
+    fn test_verify_pinned_binary() {
+        let tempdir = tempfile::TempDir::new().unwrap();
+        let tool = tempdir.path().join("tool");
+        std::fs::write(&tool, b"genuine").unwrap();
+
+        let mut pinned = PinnedBinary {
+            path: tool.clone(),
+            sha256: format!("{:x}", Sha256::digest(b"genuine")),
+        };
+        assert!(pinned.verify().is_ok());
+
+        std::fs::write(&tool, b"trojan").unwrap();
+        assert!(pinned.verify().is_err());
+
+        pinned.path = tempdir.path().join("missing");
+        assert!(pinned.verify().is_err());
+    }
+
+    #[test]
+    fn test_pinned_path() {
+        let pinned: Vec<PinnedBinary> = ["/opt/a/x", "/usr/bin/y", "/opt/a/z"]
+            .iter()
+            .map(|p| PinnedBinary {
+                path: PathBuf::from(p),
+                sha256: String::new(),
+            })
+            .collect();
+        assert_eq!(pinned_path(&pinned), OsString::from("/opt/a:/usr/bin"));
+    }
+
+    #[test]
+    fn test_find_executable() {
+        let tempdir = tempfile::TempDir::new().unwrap();
+        let (plain, tools) = (tempdir.path().join("plain"), tempdir.path().join("tools"));
+        fs::create_dir_all(&plain).unwrap();
+        fs::create_dir_all(&tools).unwrap();
+        fs::write(plain.join("python3"), b"").unwrap();
+        fs::write(tools.join("python3"), b"").unwrap();
+        fs::set_permissions(tools.join("python3"), fs::Permissions::from_mode(0o755)).unwrap();
+
+        let path = std::env::join_paths([&plain, &tools]).unwrap();
+        assert_eq!(
+            find_executable("python3", &path),
+            Some(tools.join("python3"))
+        );
+        assert_eq!(find_executable("perl", &path), None);
+    }
+
+    #[test]
+    fn test_shipped_executables() {
+        let tempdir = tempfile::TempDir::new().unwrap();
+        let dir = tempdir.path();
+        let script = dir.join("run.sh");
+        std::fs::write(&script, b"#!/bin/bash\n").unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        std::fs::write(dir.join("input.pdb"), b"ATOM").unwrap();
+        std::fs::create_dir(dir.join("bin")).unwrap();
+        std::fs::write(dir.join("bin").join("tool"), b"\x7fELF\x02\x01").unwrap();
+        std::fs::write(dir.join("helper"), b"echo hi").unwrap();
+        std::fs::set_permissions(dir.join("helper"), std::fs::Permissions::from_mode(0o700))
+            .unwrap();
+
+        let mut found = shipped_executables(dir, &script).unwrap();
+        found.sort();
+        assert_eq!(
+            found,
+            vec![dir.join("bin").join("tool"), dir.join("helper")]
+        );
+    }
+}
@@ -0,0 +1,131 @@
+use crate::config::loader::Config;
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Directory under `DATA_PATH` holding the scratch directories when
+/// `SCRATCH_PATH` is unset. Like the quarantine it is never taken for a
+/// payload directory.
+pub const SCRATCH_DIR: &str = ".scratch";
+
+/// Variable `run.sh` finds its scratch directory in
+pub const SCRATCH_VAR: &str = "SCRATCH_DIR";
+
+pub fn root(config: &Config) -> PathBuf {
+    match &config.scratch_path {
+        Some(path) => PathBuf::from(path),
+        None => Path::new(&config.data_path).join(SCRATCH_DIR),
+    }
+}
+
+/// Scratch directory of the payload in `loc`, named after its directory
+pub fn dir(config: &Config, loc: &Path) -> PathBuf {
+    root(config).join(loc.file_name().unwrap_or(loc.as_os_str()))
+}
+
+/// Create an empty scratch directory for the payload in `loc`, whatever an
+/// earlier run left in it
+pub fn create(config: &Config, loc: &Path) -> io::Result<PathBuf> {
+    let dir = dir(config, loc);
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir)?;
+    }
+    std::fs::create_dir_all(&dir)?;
+    // Absolute, it is handed to a script running in another directory
+    std::fs::canonicalize(dir)
+}
+
+/// Wipe the scratch directory of the payload in `loc` once it ran
+pub async fn remove(config: &Config, loc: &Path) {
+    let dir = dir(config, loc);
+    match tokio::fs::remove_dir_all(&dir).await {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => {
+            tracing::warn!("Could not remove {}: {e}", dir.display())
+        }
+        _ => {}
+    }
+}
+
+/// Remove the scratch directories not named in `active`, left behind by
+/// runs that ended while the client was down. Returns how many were removed.
+pub fn sweep(config: &Config, active: &HashSet<String>) -> io::Result<usize> {
+    let entries = match std::fs::read_dir(root(config)) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+    let mut removed = 0;
+    for entry in entries {
+        let entry = entry?;
+        if active.contains(&entry.file_name().to_string_lossy().to_string()) {
+            continue;
+        }
+        match entry.file_type()?.is_dir() {
+            true => std::fs::remove_dir_all(entry.path())?,
+            false => std::fs::remove_file(entry.path())?,
+        }
+        removed += 1;
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn config(data_path: &Path) -> Config {
+        Config {
+            data_path: data_path.to_str().unwrap().to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_root() {
+        let mut config = config(Path::new("/data"));
+        assert_eq!(root(&config), PathBuf::from("/data/.scratch"));
+        config.scratch_path = Some("/nvme/scratch".to_string());
+        assert_eq!(root(&config), PathBuf::from("/nvme/scratch"));
+        assert_eq!(
+            dir(&config, Path::new("/data/tenant/abc")),
+            PathBuf::from("/nvme/scratch/abc")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_and_remove() {
+        let tempdir = TempDir::new().unwrap();
+        let config = config(tempdir.path());
+        let loc = tempdir.path().join("abc");
+
+        let scratch = create(&config, &loc).unwrap();
+        assert!(scratch.is_absolute());
+        fs::write(scratch.join("tmp.dat"), "x").unwrap();
+
+        // A new run starts empty
+        let scratch = create(&config, &loc).unwrap();
+        assert!(!scratch.join("tmp.dat").exists());
+
+        remove(&config, &loc).await;
+        assert!(!scratch.exists());
+        // Already gone
+        remove(&config, &loc).await;
+    }
+
+    #[test]
+    fn test_sweep() {
+        let tempdir = TempDir::new().unwrap();
+        let config = config(tempdir.path());
+        assert_eq!(sweep(&config, &HashSet::new()).unwrap(), 0);
+
+        for name in ["running", "done"] {
+            create(&config, &tempdir.path().join(name)).unwrap();
+        }
+        let active = HashSet::from(["running".to_string()]);
+        assert_eq!(sweep(&config, &active).unwrap(), 1);
+        assert!(root(&config).join("running").exists());
+        assert!(!root(&config).join("done").exists());
+    }
+}
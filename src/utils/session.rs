@@ -0,0 +1,174 @@
+use axum::http::{HeaderMap, header};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// Cookie carrying the session of a logged in operator
+pub const SESSION_COOKIE: &str = "orchestrator_session";
+
+/// How long the provider may take to send the operator back
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Who a session belongs to, as reported by the identity provider
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct Identity {
+    pub subject: String,
+    pub email: Option<String>,
+    pub name: Option<String>,
+}
+
+#[derive(Debug)]
+struct Session {
+    identity: Identity,
+    expires: Instant,
+}
+
+#[derive(Debug)]
+struct PendingLogin {
+    redirect: String,
+    started: Instant,
+}
+
+/// In-memory sessions of the operators, a restart logs everybody out
+#[derive(Debug, Clone, Default)]
+pub struct SessionStore {
+    sessions: Arc<Mutex<HashMap<String, Session>>>,
+    logins: Arc<Mutex<HashMap<String, PendingLogin>>>,
+}
+
+impl SessionStore {
+    /// Remember a login sent to the provider, returns its `state` parameter
+    pub fn begin_login(&self, redirect: String) -> String {
+        let mut logins = self.logins.lock().unwrap();
+        logins.retain(|_, l| l.started.elapsed() < LOGIN_TIMEOUT);
+        let state = random_token();
+        logins.insert(
+            state.clone(),
+            PendingLogin {
+                redirect,
+                started: Instant::now(),
+            },
+        );
+        state
+    }
+
+    /// Where to send the operator once the login with `state` completes, each
+    /// state can only be used once
+    pub fn finish_login(&self, state: &str) -> Option<String> {
+        self.logins
+            .lock()
+            .unwrap()
+            .remove(state)
+            .filter(|l| l.started.elapsed() < LOGIN_TIMEOUT)
+            .map(|l| l.redirect)
+    }
+
+    /// Open a session, returns the token to set in the cookie
+    pub fn create(&self, identity: Identity, ttl: Duration) -> String {
+        let mut sessions = self.sessions.lock().unwrap();
+        let now = Instant::now();
+        sessions.retain(|_, s| s.expires > now);
+        let token = random_token();
+        sessions.insert(
+            token.clone(),
+            Session {
+                identity,
+                expires: now + ttl,
+            },
+        );
+        token
+    }
+
+    pub fn get(&self, token: &str) -> Option<Identity> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .get(token)
+            .filter(|s| s.expires > Instant::now())
+            .map(|s| s.identity.clone())
+    }
+
+    pub fn remove(&self, token: &str) {
+        self.sessions.lock().unwrap().remove(token);
+    }
+
+    /// Identity behind the session cookie of a request, if it is still valid
+    pub fn identify(&self, headers: &HeaderMap) -> Option<Identity> {
+        session_token(headers).and_then(|t| self.get(t))
+    }
+}
+
+/// Value of the session cookie in the `Cookie` headers
+pub fn session_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .filter_map(|c| c.trim().split_once('='))
+        .find(|(name, _)| *name == SESSION_COOKIE)
+        .map(|(_, value)| value)
+}
+
+/// 244 random bits, hex encoded
+pub fn random_token() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn identity() -> Identity {
+        Identity {
+            subject: "abc".to_string(),
+            email: Some("alice@example.org".to_string()),
+            name: None,
+        }
+    }
+
+    #[test]
+    fn test_sessions() {
+        let store = SessionStore::default();
+        let token = store.create(identity(), Duration::from_secs(60));
+        assert_eq!(token.len(), 64);
+        assert_eq!(store.get(&token), Some(identity()));
+        assert_eq!(store.get("other"), None);
+
+        store.remove(&token);
+        assert_eq!(store.get(&token), None);
+
+        let expired = store.create(identity(), Duration::ZERO);
+        assert_eq!(store.get(&expired), None);
+    }
+
+    #[test]
+    fn test_logins() {
+        let store = SessionStore::default();
+        let state = store.begin_login("/swagger".to_string());
+        assert_eq!(store.finish_login("unknown"), None);
+        assert_eq!(store.finish_login(&state).as_deref(), Some("/swagger"));
+        // Not replayable
+        assert_eq!(store.finish_login(&state), None);
+    }
+
+    #[test]
+    fn test_session_token() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(session_token(&headers), None);
+
+        headers.insert(
+            header::COOKIE,
+            format!("theme=dark; {SESSION_COOKIE}=tok123; other=1")
+                .parse()
+                .unwrap(),
+        );
+        assert_eq!(session_token(&headers), Some("tok123"));
+
+        let store = SessionStore::default();
+        assert_eq!(store.identify(&headers), None);
+    }
+}
@@ -0,0 +1,150 @@
+use axum::http::StatusCode;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tokio::io::AsyncWriteExt;
+
+/// Written and removed again in `DATA_PATH` by [`StorageHealth::check`]
+const PROBE_FILE: &str = ".write-probe";
+
+/// Whether `DATA_PATH` takes writes, shared by the routes and the storage
+/// check. A submission that could not be written degrades it right away, only
+/// the check brings it back.
+#[derive(Debug, Clone, Default)]
+pub struct StorageHealth(Arc<Mutex<Storage>>);
+
+#[derive(Debug, Default)]
+struct Storage {
+    /// Why `DATA_PATH` is not writable, `None` while it is
+    problem: Option<String>,
+    /// Whether the operators were told it is degraded
+    reported: bool,
+}
+
+/// Errors of a full or read-only `DATA_PATH`, answered with `507`; anything
+/// else is a plain `500`
+pub fn write_error_status(e: &io::Error) -> StatusCode {
+    match e.kind() {
+        io::ErrorKind::StorageFull
+        | io::ErrorKind::QuotaExceeded
+        | io::ErrorKind::ReadOnlyFilesystem => StatusCode::INSUFFICIENT_STORAGE,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// Create, sync and remove a small file in `data_path`
+async fn probe(data_path: &str) -> io::Result<()> {
+    let path = Path::new(data_path).join(PROBE_FILE);
+    let mut file = tokio::fs::File::create(&path).await?;
+    file.write_all(b"ok").await?;
+    file.sync_all().await?;
+    tokio::fs::remove_file(&path).await
+}
+
+impl StorageHealth {
+    /// Why `DATA_PATH` is not writable, `None` while it is
+    pub fn problem(&self) -> Option<String> {
+        self.0.lock().unwrap().problem.clone()
+    }
+
+    pub fn degrade(&self, problem: &str) {
+        let mut storage = self.0.lock().unwrap();
+        if storage.problem.is_none() {
+            tracing::error!("DATA_PATH is not writable, refusing submissions: {problem}");
+        }
+        storage.problem = Some(problem.to_string());
+    }
+
+    /// Try a write to `data_path` and degrade or recover accordingly
+    pub async fn check(&self, data_path: &str) {
+        match probe(data_path).await {
+            Ok(()) => {
+                let mut storage = self.0.lock().unwrap();
+                if storage.problem.take().is_some() {
+                    tracing::info!("DATA_PATH is writable again, accepting submissions");
+                }
+            }
+            Err(e) => self.degrade(&e.to_string()),
+        }
+    }
+
+    /// `Some(true)` when the operators are still to be told `DATA_PATH` is
+    /// degraded, `Some(false)` when it recovered since they were
+    pub fn unreported(&self) -> Option<bool> {
+        let storage = self.0.lock().unwrap();
+        let degraded = storage.problem.is_some();
+        (degraded != storage.reported).then_some(degraded)
+    }
+
+    /// Record that the operators were told `DATA_PATH` is `degraded`, or not
+    pub fn sent(&self, degraded: bool) {
+        self.0.lock().unwrap().reported = degraded;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn test_write_error_status() {
+        let full = io::Error::from(io::ErrorKind::StorageFull);
+        assert_eq!(write_error_status(&full), StatusCode::INSUFFICIENT_STORAGE);
+        let read_only = io::Error::from(io::ErrorKind::ReadOnlyFilesystem);
+        assert_eq!(
+            write_error_status(&read_only),
+            StatusCode::INSUFFICIENT_STORAGE
+        );
+        let missing = io::Error::from(io::ErrorKind::NotFound);
+        assert_eq!(
+            write_error_status(&missing),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+
+    #[tokio::test]
+    async fn test_check_degrades_and_recovers() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_path = dir.path().to_str().unwrap();
+        let storage = StorageHealth::default();
+
+        storage.check(data_path).await;
+        assert_eq!(storage.problem(), None);
+        assert_eq!(storage.unreported(), None);
+        assert!(!dir.path().join(PROBE_FILE).exists());
+
+        std::fs::set_permissions(dir.path(), std::fs::Permissions::from_mode(0o500)).unwrap();
+        storage.check(data_path).await;
+        // Root writes anyway
+        if std::fs::File::create(dir.path().join("root")).is_err() {
+            assert!(storage.problem().is_some());
+            assert_eq!(storage.unreported(), Some(true));
+            storage.sent(true);
+            assert_eq!(storage.unreported(), None);
+        }
+
+        std::fs::set_permissions(dir.path(), std::fs::Permissions::from_mode(0o700)).unwrap();
+        storage.check(data_path).await;
+        assert_eq!(storage.problem(), None);
+    }
+
+    #[test]
+    fn test_degrade_is_reported_once() {
+        let storage = StorageHealth::default();
+        storage.degrade("No space left on device");
+        storage.degrade("No space left on device");
+        assert_eq!(
+            storage.problem().as_deref(),
+            Some("No space left on device")
+        );
+        assert_eq!(storage.unreported(), Some(true));
+        storage.sent(true);
+        assert_eq!(storage.unreported(), None);
+
+        storage.0.lock().unwrap().problem = None;
+        assert_eq!(storage.unreported(), Some(false));
+        storage.sent(false);
+        assert_eq!(storage.unreported(), None);
+    }
+}
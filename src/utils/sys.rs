@@ -1,4 +1,6 @@
+use std::path::Path;
 use std::process::Command;
+use sysinfo::Disks;
 
 pub fn is_pid_running(pid: u32) -> bool {
     Command::new("kill")
@@ -11,10 +13,28 @@ pub fn is_pid_running(pid: u32) -> bool {
         .unwrap_or(false)
 }
 
+/// Bytes available on the filesystem holding `path`, found through the
+/// deepest mount point containing it
+pub fn free_space(path: &Path) -> Option<u64> {
+    let path = path.canonicalize().ok()?;
+    Disks::new_with_refreshed_list()
+        .iter()
+        .filter(|disk| path.starts_with(disk.mount_point()))
+        .max_by_key(|disk| disk.mount_point().as_os_str().len())
+        .map(|disk| disk.available_space())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_free_space() {
+        assert!(free_space(Path::new("/does/not/exist")).is_none());
+        // Some sandboxes expose no mount table, only check it does not panic
+        let _ = free_space(&std::env::temp_dir());
+    }
+
     #[test]
     fn test_is_pid_running_nonexistent_pid() {
         // Use a very high PID that is unlikely to exist
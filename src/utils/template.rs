@@ -0,0 +1,97 @@
+use regex::Regex;
+use serde_json::{Map, Value};
+use std::sync::LazyLock;
+
+/// `{{name}}`, spaces allowed inside the braces
+static PLACEHOLDER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{\{\s*([A-Za-z_][A-Za-z0-9_]*)\s*\}\}").unwrap());
+
+/// Quote `value` so that the shell reads it as a single word, whatever it holds
+pub fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// The text a parameter is replaced with, only strings, numbers and booleans
+/// can be used in a script
+fn scalar(name: &str, value: &Value) -> Result<String, String> {
+    match value {
+        Value::String(s) => Ok(s.clone()),
+        Value::Number(n) => Ok(n.to_string()),
+        Value::Bool(b) => Ok(b.to_string()),
+        _ => Err(format!(
+            "Parameter {name} must be a string, a number or a boolean"
+        )),
+    }
+}
+
+/// Replace every `{{name}}` of `template` with the shell quoted value of
+/// parameter `name`. Parameters the template does not use are ignored, a
+/// placeholder without a parameter is an error.
+pub fn render(template: &str, parameters: &Map<String, Value>) -> Result<String, String> {
+    let mut rendered = String::with_capacity(template.len());
+    let mut last = 0;
+    for caps in PLACEHOLDER.captures_iter(template) {
+        let whole = caps.get(0).unwrap();
+        let name = &caps[1];
+        let value = parameters
+            .get(name)
+            .ok_or_else(|| format!("Missing parameter {name}"))?;
+        rendered.push_str(&template[last..whole.start()]);
+        rendered.push_str(&shell_quote(&scalar(name, value)?));
+        last = whole.end();
+    }
+    rendered.push_str(&template[last..]);
+    Ok(rendered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn params(value: Value) -> Map<String, Value> {
+        value.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("A,B"), "'A,B'");
+        assert_eq!(shell_quote(""), "''");
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+        assert_eq!(shell_quote("$(rm -rf /)"), "'$(rm -rf /)'");
+    }
+
+    #[test]
+    fn test_render() {
+        let template = "run --chains {{chains}} --steps {{ steps }} --fast={{fast}}\n";
+        let rendered = render(
+            template,
+            &params(json!({"chains": "A,B", "steps": 100, "fast": true, "unused": "x"})),
+        )
+        .unwrap();
+        assert_eq!(rendered, "run --chains 'A,B' --steps '100' --fast='true'\n");
+    }
+
+    #[test]
+    fn test_render_injection() {
+        let rendered = render("echo {{name}}", &params(json!({"name": "x'; rm -rf / #"}))).unwrap();
+        assert_eq!(rendered, r"echo 'x'\''; rm -rf / #'");
+    }
+
+    #[test]
+    fn test_render_errors() {
+        assert_eq!(
+            render("echo {{name}}", &Map::new()),
+            Err("Missing parameter name".to_string())
+        );
+        assert_eq!(
+            render("echo {{name}}", &params(json!({"name": ["a", "b"]}))),
+            Err("Parameter name must be a string, a number or a boolean".to_string())
+        );
+        // Braces that are not a placeholder are kept as they are
+        assert_eq!(
+            render("echo ${HOME} {{ }}", &Map::new()).unwrap(),
+            "echo ${HOME} {{ }}"
+        );
+    }
+}
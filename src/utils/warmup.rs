@@ -0,0 +1,189 @@
+use crate::config::loader::WarmupConfig;
+use serde::{Deserialize, Serialize};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::process::Command;
+use utoipa::ToSchema;
+
+/// Lines of output kept in the report, the end is where the error usually is
+const OUTPUT_LINES: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum WarmupState {
+    Running,
+    Ready,
+    Failed,
+}
+
+/// How the warm-up command of the client went, served under `GET /warmup`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct WarmupReport {
+    pub command: String,
+    pub state: WarmupState,
+    /// `None` while running, or when the command timed out or did not start
+    pub exit_code: Option<i32>,
+    /// Seconds the command took, `None` while running
+    pub seconds: Option<u64>,
+    /// Last lines the command printed, stdout then stderr
+    pub output: Vec<String>,
+}
+
+/// Outcome of the warm-up shared with the routes, a client without a
+/// warm-up command is ready from the start
+#[derive(Debug, Clone, Default)]
+pub struct Warmup {
+    report: Arc<Mutex<Option<WarmupReport>>>,
+}
+
+impl Warmup {
+    pub fn report(&self) -> Option<WarmupReport> {
+        self.report.lock().unwrap().clone()
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.report
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_none_or(|r| r.state == WarmupState::Ready)
+    }
+
+    /// Run the command of `config`, the client is not ready until it exited with `0`
+    pub async fn run(&self, config: &WarmupConfig) {
+        let running = WarmupReport {
+            command: config.command.clone(),
+            state: WarmupState::Running,
+            exit_code: None,
+            seconds: None,
+            output: Vec::new(),
+        };
+        *self.report.lock().unwrap() = Some(running.clone());
+
+        tracing::info!("Warming up with `{}`", config.command);
+        let started = Instant::now();
+        let child = Command::new("sh")
+            .arg("-c")
+            .arg(&config.command)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn();
+
+        let mut report = running;
+        match child {
+            Ok(child) => {
+                match tokio::time::timeout(config.timeout, child.wait_with_output()).await {
+                    Ok(Ok(output)) => {
+                        report.exit_code = output.status.code();
+                        report.output = tail(&[&output.stdout, &output.stderr]);
+                    }
+                    Ok(Err(e)) => report.output = vec![e.to_string()],
+                    Err(_) => {
+                        report.output =
+                            vec![format!("timed out after {}s", config.timeout.as_secs())]
+                    }
+                }
+            }
+            Err(e) => report.output = vec![format!("could not start: {e}")],
+        }
+        report.seconds = Some(started.elapsed().as_secs());
+        report.state = match report.exit_code {
+            Some(0) => WarmupState::Ready,
+            _ => WarmupState::Failed,
+        };
+
+        match report.state {
+            WarmupState::Ready => {
+                tracing::info!("Warm-up done in {}s", started.elapsed().as_secs())
+            }
+            _ => tracing::error!(
+                "Warm-up failed, the client stays unhealthy: {}",
+                report
+                    .output
+                    .last()
+                    .map(String::as_str)
+                    .unwrap_or("no output")
+            ),
+        }
+        *self.report.lock().unwrap() = Some(report);
+    }
+}
+
+/// The last non-empty lines of `outputs`
+fn tail(outputs: &[&[u8]]) -> Vec<String> {
+    let lines: Vec<String> = outputs
+        .iter()
+        .flat_map(|out| {
+            String::from_utf8_lossy(out)
+                .lines()
+                .map(|l| l.trim_end().to_string())
+                .collect::<Vec<_>>()
+        })
+        .filter(|l| !l.is_empty())
+        .collect();
+    lines[lines.len().saturating_sub(OUTPUT_LINES)..].to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn config(command: &str) -> WarmupConfig {
+        WarmupConfig {
+            command: command.to_string(),
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_warmup_ready() {
+        let warmup = Warmup::default();
+        assert!(warmup.is_ready());
+        assert_eq!(warmup.report(), None);
+
+        warmup.run(&config("echo pulled; echo verified >&2")).await;
+        assert!(warmup.is_ready());
+        let report = warmup.report().unwrap();
+        assert_eq!(report.state, WarmupState::Ready);
+        assert_eq!(report.exit_code, Some(0));
+        assert_eq!(report.output, vec!["pulled", "verified"]);
+    }
+
+    #[tokio::test]
+    async fn test_warmup_failed() {
+        let warmup = Warmup::default();
+        warmup
+            .run(&config("echo 'gmx: not found' >&2; exit 127"))
+            .await;
+        assert!(!warmup.is_ready());
+        let report = warmup.report().unwrap();
+        assert_eq!(report.state, WarmupState::Failed);
+        assert_eq!(report.exit_code, Some(127));
+        assert_eq!(report.output, vec!["gmx: not found"]);
+    }
+
+    #[tokio::test]
+    async fn test_warmup_timeout() {
+        let warmup = Warmup::default();
+        let mut config = config("sleep 10");
+        config.timeout = Duration::from_millis(100);
+        warmup.run(&config).await;
+        let report = warmup.report().unwrap();
+        assert_eq!(report.state, WarmupState::Failed);
+        assert_eq!(report.exit_code, None);
+        assert_eq!(report.output, vec!["timed out after 0s"]);
+    }
+
+    #[test]
+    fn test_tail() {
+        let out = (0..30).map(|i| format!("{i}\n")).collect::<String>();
+        let lines = tail(&[out.as_bytes(), b"\n  \nlast\n"]);
+        assert_eq!(lines.len(), OUTPUT_LINES);
+        assert_eq!(lines.first().unwrap(), "11");
+        assert_eq!(lines.last().unwrap(), "last");
+    }
+}
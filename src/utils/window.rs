@@ -0,0 +1,249 @@
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime};
+
+const DAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+const DAY: u64 = 86400;
+
+/// When the sender may dispatch the jobs of a service, e.g.
+/// `mon-fri 08:00-18:00; sat,sun`. Times are UTC, a window ending before it
+/// starts runs past midnight into the next day.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ExecutionWindows(Vec<Window>);
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+struct Window {
+    /// Days the window starts on, Monday first
+    days: [bool; 7],
+    /// Seconds into the day
+    start: u64,
+    end: u64,
+}
+
+impl Window {
+    fn is_open(&self, weekday: usize, secs: u64) -> bool {
+        if self.start < self.end {
+            return self.days[weekday] && (self.start..self.end).contains(&secs);
+        }
+        // Spans midnight, the early hours belong to the day before
+        (self.days[weekday] && secs >= self.start)
+            || (self.days[(weekday + 6) % 7] && secs < self.end)
+    }
+}
+
+/// Day of the week of `days` since the epoch, Monday first. The epoch was a
+/// Thursday.
+fn weekday(days: u64) -> usize {
+    ((days + 3) % 7) as usize
+}
+
+fn epoch_secs(t: SystemTime) -> u64 {
+    t.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Index of `mon`, `tue`, ...
+fn day_index(s: &str) -> Option<usize> {
+    DAYS.iter().position(|d| d.eq_ignore_ascii_case(s.trim()))
+}
+
+/// `mon-fri`, `sat,sun` or `mon,wed-fri`, ranges may wrap around the week
+fn parse_days(s: &str) -> Option<[bool; 7]> {
+    let mut days = [false; 7];
+    for part in s.split(',') {
+        match part.split_once('-') {
+            Some((first, last)) => {
+                let (mut day, last) = (day_index(first)?, day_index(last)?);
+                days[day] = true;
+                while day != last {
+                    day = (day + 1) % 7;
+                    days[day] = true;
+                }
+            }
+            None => days[day_index(part)?] = true,
+        }
+    }
+    Some(days)
+}
+
+/// Seconds into the day of `HH:MM`, `24:00` included
+fn second_of_day(s: &str) -> Option<u64> {
+    let (h, m) = s.trim().split_once(':')?;
+    let (h, m) = (h.parse::<u64>().ok()?, m.parse::<u64>().ok()?);
+    (m < 60 && (h < 24 || (h, m) == (24, 0))).then_some(h * 3600 + m * 60)
+}
+
+impl std::str::FromStr for Window {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid execution window: {}", s.trim());
+        let mut window = Window {
+            days: [true; 7],
+            start: 0,
+            end: DAY,
+        };
+        let (mut days, mut hours) = (None, None);
+        for part in s.split_whitespace() {
+            match part.contains(':') {
+                true if hours.is_none() => hours = Some(part),
+                false if days.is_none() => days = Some(part),
+                _ => return Err(invalid()),
+            }
+        }
+        if days.is_none() && hours.is_none() {
+            return Err(invalid());
+        }
+        if let Some(days) = days {
+            window.days = parse_days(days).ok_or_else(invalid)?;
+        }
+        if let Some(hours) = hours {
+            let (start, end) = hours.split_once('-').ok_or_else(invalid)?;
+            window.start = second_of_day(start)
+                .filter(|s| *s < DAY)
+                .ok_or_else(invalid)?;
+            window.end = second_of_day(end).ok_or_else(invalid)?;
+            if window.start == window.end {
+                return Err(invalid());
+            }
+        }
+        Ok(window)
+    }
+}
+
+impl std::str::FromStr for ExecutionWindows {
+    type Err = String;
+
+    /// Windows separated by `;`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let windows = s
+            .split(';')
+            .filter(|w| !w.trim().is_empty())
+            .map(str::parse)
+            .collect::<Result<Vec<Window>, _>>()?;
+        if windows.is_empty() {
+            return Err("No execution window given".to_string());
+        }
+        Ok(ExecutionWindows(windows))
+    }
+}
+
+impl ExecutionWindows {
+    pub fn is_open(&self, now: SystemTime) -> bool {
+        let secs = epoch_secs(now);
+        let (day, secs) = (weekday(secs / DAY), secs % DAY);
+        self.0.iter().any(|w| w.is_open(day, secs))
+    }
+
+    /// When the next window opens, `None` while one is open
+    pub fn next_open(&self, now: SystemTime) -> Option<SystemTime> {
+        if self.is_open(now) {
+            return None;
+        }
+        let secs = epoch_secs(now);
+        let today = secs / DAY;
+        // Every window starts at least once a week
+        (today..=today + 7)
+            .flat_map(|day| {
+                self.0
+                    .iter()
+                    .filter(move |w| w.days[weekday(day)])
+                    .map(move |w| day * DAY + w.start)
+            })
+            .filter(|start| *start > secs)
+            .min()
+            .map(|start| SystemTime::UNIX_EPOCH + Duration::from_secs(start))
+    }
+}
+
+/// `Mon 22:00 UTC`
+pub fn weekday_time(t: SystemTime) -> String {
+    let secs = epoch_secs(t);
+    let day = DAYS[weekday(secs / DAY)];
+    let secs = secs % DAY;
+    format!(
+        "{}{} {:02}:{:02} UTC",
+        day[..1].to_ascii_uppercase(),
+        &day[1..],
+        secs / 3600,
+        secs % 3600 / 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `h:m` on the day `days` after Monday 1970-01-05
+    fn at(days: u64, h: u64, m: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs((4 + days) * DAY + h * 3600 + m * 60)
+    }
+
+    #[test]
+    fn test_parse() {
+        let windows: ExecutionWindows = "mon-fri 08:00-18:00; sat,sun".parse().unwrap();
+        assert_eq!(windows.0.len(), 2);
+        assert_eq!(
+            windows.0[0].days,
+            [true, true, true, true, true, false, false]
+        );
+        assert_eq!(
+            (windows.0[0].start, windows.0[0].end),
+            (8 * 3600, 18 * 3600)
+        );
+        assert_eq!((windows.0[1].start, windows.0[1].end), (0, DAY));
+
+        let windows: ExecutionWindows = "22:00-06:00".parse().unwrap();
+        assert_eq!(windows.0[0].days, [true; 7]);
+        let windows: ExecutionWindows = "Fri-Mon 18:00-24:00".parse().unwrap();
+        assert_eq!(
+            windows.0[0].days,
+            [true, false, false, false, true, true, true]
+        );
+
+        for invalid in [
+            "",
+            ";",
+            "weekdays",
+            "mon-fri 8-18",
+            "mon 08:00-08:00",
+            "mon 24:00-06:00",
+            "mon 08:00-18:60",
+            "mon tue",
+            "08:00-10:00 12:00-14:00",
+        ] {
+            assert!(invalid.parse::<ExecutionWindows>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_is_open() {
+        let weekdays: ExecutionWindows = "mon-fri 08:00-18:00".parse().unwrap();
+        assert!(weekdays.is_open(at(0, 8, 0)));
+        assert!(weekdays.is_open(at(4, 17, 59)));
+        assert!(!weekdays.is_open(at(4, 18, 0)));
+        assert!(!weekdays.is_open(at(0, 7, 59)));
+        assert!(!weekdays.is_open(at(5, 12, 0)));
+
+        // The night of Friday runs into Saturday, the one of Sunday is closed
+        let nights: ExecutionWindows = "mon-fri 22:00-06:00".parse().unwrap();
+        assert!(nights.is_open(at(4, 23, 0)));
+        assert!(nights.is_open(at(5, 5, 59)));
+        assert!(!nights.is_open(at(5, 6, 0)));
+        assert!(!nights.is_open(at(0, 3, 0)));
+        assert!(nights.is_open(at(1, 3, 0)));
+    }
+
+    #[test]
+    fn test_next_open() {
+        let windows: ExecutionWindows = "mon-fri 22:00-06:00; sun 12:00-14:00".parse().unwrap();
+        assert_eq!(windows.next_open(at(2, 23, 0)), None);
+        assert_eq!(windows.next_open(at(2, 12, 0)), Some(at(2, 22, 0)));
+        assert_eq!(windows.next_open(at(5, 6, 0)), Some(at(6, 12, 0)));
+        // Over the weekend, to the next Monday night
+        assert_eq!(windows.next_open(at(6, 14, 0)), Some(at(7, 22, 0)));
+
+        assert_eq!(weekday_time(at(6, 12, 0)), "Sun 12:00 UTC");
+        assert_eq!(weekday_time(at(7, 22, 5)), "Mon 22:05 UTC");
+    }
+}